@@ -0,0 +1,276 @@
+use anyhow::{anyhow, bail, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{disks, network};
+
+const BUNDLED_BOARDS: &[u8] = include_bytes!("../res/boards.json");
+const EXTRA_BOARDS_PATH: &str = "/etc/deploykit/boards.json";
+
+/// A bootloader image to be written to a raw offset of the target device
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BoardImage {
+    /// Path to the image in the installed system
+    pub path: String,
+    /// Offset in bytes from the start of the device
+    pub offset: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Board {
+    pub name: String,
+    pub description: String,
+    pub arch: Vec<String>,
+    #[serde(default)]
+    pub images: Vec<BoardImage>,
+    /// Files (or directories) in the installed system to be copied to the boot partition
+    #[serde(default)]
+    pub firmware: Vec<String>,
+    /// Where the first partition may start at the earliest in bytes, for the images to fit
+    /// before it, `disks::DEFAULT_PARTITION_START` if not given
+    #[serde(default)]
+    pub partition_start: Option<u64>,
+}
+
+/// Whether the architecture needs board-specific bootloaders
+pub fn is_board_arch() -> bool {
+    matches!(network::get_arch_name(), Some("arm64") | Some("riscv64"))
+}
+
+/// List the boards supported on this architecture, boards from `/etc/deploykit/boards.json`
+/// are added to (or replace) the bundled ones
+pub fn list_boards() -> Result<Vec<Board>> {
+    if !is_board_arch() {
+        return Ok(vec![]);
+    }
+
+    let mut boards: Vec<Board> = serde_json::from_slice(BUNDLED_BOARDS)?;
+    if let Ok(data) = std::fs::read(EXTRA_BOARDS_PATH) {
+        let extra: Vec<Board> = serde_json::from_slice(&data)?;
+        boards = merge_boards(boards, extra);
+    }
+
+    let arch = network::get_arch_name().unwrap_or_default();
+
+    Ok(boards
+        .into_iter()
+        .filter(|x| x.arch.iter().any(|x| x == arch))
+        .collect())
+}
+
+fn merge_boards(mut boards: Vec<Board>, extra: Vec<Board>) -> Vec<Board> {
+    for board in extra {
+        match boards.iter_mut().find(|x| x.name == board.name) {
+            Some(old) => *old = board,
+            None => boards.push(board),
+        }
+    }
+
+    boards
+}
+
+/// Find the board by its name
+pub fn find_board(name: &str) -> Result<Board> {
+    list_boards()?
+        .into_iter()
+        .find(|x| x.name == name)
+        .ok_or_else(|| anyhow!("Board {} is not supported on this architecture.", name))
+}
+
+/// Describe what will be written where, for the user to confirm
+pub fn describe_actions(board: &Board, device: &Path) -> Vec<String> {
+    let mut actions = board
+        .images
+        .iter()
+        .map(|x| {
+            format!(
+                "/{} will be written to {} at offset {} (sector {})",
+                x.path,
+                device.display(),
+                x.offset,
+                x.offset / 512
+            )
+        })
+        .collect::<Vec<_>>();
+    actions.extend(
+        board
+            .firmware
+            .iter()
+            .map(|x| format!("/{x} will be copied to the boot partition")),
+    );
+
+    actions
+}
+
+/// Where the first partition of the board may start at the earliest, in bytes
+pub fn partition_start(board: &Board) -> u64 {
+    board
+        .partition_start
+        .unwrap_or(disks::DEFAULT_PARTITION_START)
+}
+
+/// Check the image offsets of the board against the raw area between the partition table
+/// and the first partition as planned, before anything is written: the area has to hold
+/// all the room reserved for the images, their sizes are only checked when flashing
+pub fn check_layout(board: &Board, area: (u64, u64)) -> Result<()> {
+    let start = partition_start(board);
+    if area.1 < start {
+        bail!(
+            "{} needs the first partition to start at {} bytes or later to hold its bootloader, but it starts at {} bytes. Please leave enough unpartitioned space at the start of the device.",
+            board.description,
+            start,
+            area.1
+        );
+    }
+    for image in &board.images {
+        if image.offset < area.0 || image.offset >= start {
+            bail!(
+                "Bootloader image /{} at offset {} is outside of the area reserved for it ({}..{}).",
+                image.path,
+                image.offset,
+                area.0,
+                start
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the images fit in the raw area between the partition table and the first partition
+fn check_bounds(images: &[(u64, u64)], area: (u64, u64)) -> Result<()> {
+    for (offset, len) in images {
+        if *offset < area.0 || offset + len > area.1 {
+            bail!(
+                "Bootloader image at offset {} ({} bytes) does not fit between the partition table and the first partition ({}..{}).",
+                offset,
+                len,
+                area.0,
+                area.1
+            );
+        }
+    }
+
+    let mut sorted = images.to_vec();
+    sorted.sort();
+    if sorted.windows(2).any(|x| x[0].0 + x[0].1 > x[1].0) {
+        bail!("Bootloader images overlap with each other.");
+    }
+
+    Ok(())
+}
+
+/// Write the bootloader images of the board to `device` and copy its firmware to `boot`
+/// Must be used outside of the chroot context
+pub fn flash_board(board: &Board, root: &Path, device: &Path, boot: Option<&Path>) -> Result<()> {
+    let mut images = vec![];
+    for image in &board.images {
+        let data = std::fs::read(root.join(&image.path))?;
+        images.push((image, data));
+    }
+
+    let bounds = images
+        .iter()
+        .map(|(image, data)| (image.offset, data.len() as u64))
+        .collect::<Vec<_>>();
+    check_bounds(&bounds, disks::raw_area_before_partitions(device)?)?;
+
+    if !images.is_empty() {
+        let mut f = OpenOptions::new().write(true).open(device)?;
+        for (image, data) in images {
+            info!(
+                "Writing /{} to {} at offset {}",
+                image.path,
+                device.display(),
+                image.offset
+            );
+            f.seek(SeekFrom::Start(image.offset))?;
+            f.write_all(&data)?;
+        }
+        f.sync_all()?;
+    }
+
+    if board.firmware.is_empty() {
+        return Ok(());
+    }
+
+    let boot = boot.ok_or_else(|| {
+        anyhow!(
+            "Board {} needs a FAT boot partition for its firmware.",
+            board.name
+        )
+    })?;
+    for firmware in &board.firmware {
+        info!("Copying /{} to {}", firmware, boot.display());
+        copy_recursively(&root.join(firmware), boot)?;
+    }
+
+    Ok(())
+}
+
+/// Copy the file, or the contents of the directory, into `dst`
+fn copy_recursively(src: &Path, dst: &Path) -> Result<()> {
+    if !src.is_dir() {
+        let name = src
+            .file_name()
+            .ok_or_else(|| anyhow!("Can not get filename of {}", src.display()))?;
+        std::fs::copy(src, dst.join(name))?;
+
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(src)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            let dst = dst.join(path.file_name().unwrap_or_default());
+            std::fs::create_dir_all(&dst)?;
+            copy_recursively(&path, &dst)?;
+        } else {
+            copy_recursively(&path, dst)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bundled_boards() {
+    let boards: Vec<Board> = serde_json::from_slice(BUNDLED_BOARDS).unwrap();
+    assert!(boards
+        .iter()
+        .all(|x| !x.arch.is_empty() && (!x.images.is_empty() || !x.firmware.is_empty())));
+
+    let extra = vec![Board {
+        name: "rockpro64".to_string(),
+        description: "Custom ROCKPro64".to_string(),
+        arch: vec!["arm64".to_string()],
+        images: vec![],
+        firmware: vec![],
+        partition_start: None,
+    }];
+    let merged = merge_boards(boards.clone(), extra);
+    assert_eq!(merged.len(), boards.len());
+    assert_eq!(merged[0].description, "Custom ROCKPro64");
+
+    // the bundled images fit between an MBR and the first partition
+    for board in &boards {
+        assert!(check_layout(board, (512, partition_start(board))).is_ok());
+    }
+    let rockpro64 = &boards[0];
+    assert!(check_layout(rockpro64, (34 * 512, disks::DEFAULT_PARTITION_START)).is_err());
+    assert!(check_layout(rockpro64, (34 * 512, 32 * 1024 * 1024)).is_ok());
+}
+
+#[test]
+fn test_check_bounds() {
+    let area = (34 * 512, 16 * 1024 * 1024);
+    assert!(check_bounds(&[(32768, 4096), (8388608, 1024)], area).is_ok());
+    assert!(check_bounds(&[(8192, 4096)], area).is_err());
+    assert!(check_bounds(&[(8388608, 16 * 1024 * 1024)], area).is_err());
+    assert!(check_bounds(&[(32768, 8388608), (8388608, 1024)], area).is_err());
+}
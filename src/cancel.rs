@@ -0,0 +1,140 @@
+use std::{
+    fmt,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// The installation has been cancelled by the user
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The installation has been cancelled.")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Shared between the frontend, which requests the cancellation, and the installation,
+/// which checks it at safe points and stops there
+#[derive(Clone)]
+pub struct CancelToken {
+    requested: Arc<AtomicBool>,
+    /// Wipe the incomplete system from the target after stopping
+    wipe: Arc<AtomicBool>,
+    /// The system release is being (or has been) unpacked to the target
+    target_modified: Arc<AtomicBool>,
+    /// There are no more safe points, the installation has to run to the end
+    finishing: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken {
+            requested: Arc::new(AtomicBool::new(false)),
+            wipe: Arc::new(AtomicBool::new(false)),
+            target_modified: Arc::new(AtomicBool::new(false)),
+            finishing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Ask the installation to stop at the next safe point, and whether to wipe the target then
+    pub fn cancel(&self, wipe: bool) {
+        self.wipe.store(wipe, Ordering::SeqCst);
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// A safe point, fails with `Cancelled` if the cancellation has been requested
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+
+        Ok(())
+    }
+
+    pub fn wants_wipe(&self) -> bool {
+        self.wipe.load(Ordering::SeqCst)
+    }
+
+    pub fn set_target_modified(&self) {
+        self.target_modified.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_target_modified(&self) -> bool {
+        self.target_modified.load(Ordering::SeqCst)
+    }
+
+    /// Past the last safe point, cancelling is no longer possible
+    pub fn set_finishing(&self) {
+        self.finishing.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancellable(&self) -> bool {
+        !self.finishing.load(Ordering::SeqCst)
+    }
+
+    /// The raw flag, for code taking an `Arc<AtomicBool>` to stop (e.g., unsquashfs)
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.requested.clone()
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fails the reads once the cancellation has been requested, so that whatever consumes
+/// the stream (e.g., the tar unpacker) stops after the current block
+pub struct CancellableReader<R> {
+    inner: R,
+    token: CancelToken,
+}
+
+impl<R> CancellableReader<R> {
+    pub fn new(inner: R, token: CancelToken) -> Self {
+        CancellableReader { inner, token }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Other, Cancelled));
+        }
+
+        self.inner.read(buf)
+    }
+}
+
+/// Whether the error is (or has been caused by) a cancellation
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.chain().any(|x| x.is::<Cancelled>())
+}
+
+#[test]
+fn test_cancel_token() {
+    let token = CancelToken::new();
+    let copy = token.clone();
+    assert!(token.check().is_ok());
+
+    let mut reader = CancellableReader::new(&b"hello"[..], token.clone());
+    let mut buf = [0u8; 2];
+    assert_eq!(reader.read(&mut buf).unwrap(), 2);
+
+    copy.cancel(true);
+    assert!(token.wants_wipe());
+    assert!(is_cancelled(&token.check().unwrap_err()));
+    assert!(reader.read(&mut buf).is_err());
+    assert!(token.flag().load(Ordering::SeqCst));
+}
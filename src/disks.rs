@@ -16,6 +16,7 @@ use libparted::IsZero;
 use libparted::Partition as PedPartition;
 use libparted_sys::PedPartitionFlag;
 use libparted_sys::PedPartitionType;
+use log::debug;
 use log::error;
 use log::info;
 use serde::{Deserialize, Serialize};
@@ -26,11 +27,26 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 
+use crate::log::LIVE_MEDIUM_PATH;
+use crate::network;
+
 const EFI_DETECT_PATH: &str = "/sys/firmware/efi";
+const LABELS_PATH: &str = "/dev/disk/by-label";
+const UUIDS_PATH: &str = "/dev/disk/by-uuid";
+const IDS_PATH: &str = "/dev/disk/by-id";
+const UDEV_DATA_PATH: &str = "/run/udev/data";
+const KERNEL_CMDLINE_PATH: &str = "/proc/cmdline";
+/// The partitioning programs that may be run on a disk from the installer
+pub const PARTITIONERS: &[&str] = &["cfdisk", "parted"];
 pub const ALLOWED_FS_TYPE: &[&str] = &["ext4", "xfs"];
 const DEFAULT_FS_TYPE: &str = "ext4";
+/// Where `auto_create_partitions` starts the first partition unless a board needs more room
+/// for its bootloader, in bytes
+pub const DEFAULT_PARTITION_START: u64 = 1024 * 1024;
 
 const SUPPORT_PARTITION_TYPE: &[&str] = &["primary", "logical"];
+// the PReP boot partition only holds the raw GRUB core image
+const PREP_SIZE: u64 = 8 * 1024 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Partition {
@@ -63,37 +79,69 @@ pub fn get_recommended_fs_type(type_: &str) -> &str {
 }
 
 pub fn format_partition(partition: &Partition) -> Result<()> {
+    format_partition_with_label(partition, None)
+}
+
+/// Format the partition, giving the new filesystem the label if there is one
+pub fn format_partition_with_label(partition: &Partition, label: Option<&str>) -> Result<()> {
+    crate::assert_not_ui_thread("Formatting a partition");
+
+    let (command, args) = mkfs_command(partition, label)?;
+    crate::install::run_command_logged(&command, args).map_err(|e| {
+        anyhow!(
+            "Installer failed to format the specified partition: \n{}",
+            e
+        )
+    })
+}
+
+/// The mkfs program and its arguments to format the partition with
+pub fn mkfs_command(partition: &Partition, label: Option<&str>) -> Result<(String, Vec<OsString>)> {
     let default_fs = DEFAULT_FS_TYPE.to_owned();
     let fs_type = partition.fs_type.as_ref().unwrap_or(&default_fs);
-    let mut command = Command::new(format!("mkfs.{fs_type}"));
-    let cmd;
+    let mut args: Vec<OsString> = vec![];
 
+    // ext4 is not quiet, its progress (e.g., writing the inode tables) is shown as it formats
     if fs_type == "ext4" {
-        cmd = command.arg("-Fq");
+        args.push("-F".into());
     } else if fs_type == "vfat" {
-        cmd = command.arg("-F32");
+        args.push("-F32".into());
     } else {
-        cmd = command.arg("-f");
+        args.push("-f".into());
+    }
+    if let Some(label) = label {
+        args.push(label_option(fs_type).into());
+        args.push(label.into());
     }
+    args.push(
+        partition
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Installer could not find the specified partition.\nDid you partition your target disk?"))?
+            .into(),
+    );
 
-    info!("{cmd:?}");
-    let output = cmd
-        .arg(
-            partition
-                .path
-                .as_ref()
-                .ok_or_else(|| anyhow!("Installer could not find the specified partition.\nDid you partition your target disk?"))?,
-        )
-        .output()?;
-    if !output.status.success() {
-        return Err(anyhow!(
-            "Installer failed to format the specified partition: \n{}\n{}",
-            String::from_utf8_lossy(&output.stderr),
-            String::from_utf8_lossy(&output.stdout)
-        ));
+    Ok((format!("mkfs.{fs_type}"), args))
+}
+
+/// The option of mkfs for `fs_type` to set the label with
+fn label_option(fs_type: &str) -> &'static str {
+    match fs_type {
+        "vfat" => "-n",
+        "f2fs" => "-l",
+        _ => "-L",
     }
+}
 
-    Ok(())
+/// The longest label `fs_type` can have
+pub fn label_limit(fs_type: &str) -> usize {
+    match fs_type {
+        "vfat" | "fat16" | "fat32" => 11,
+        "xfs" => 12,
+        "ext4" => 16,
+        "f2fs" => 512,
+        _ => 255,
+    }
 }
 
 pub fn fill_fs_type(part: &Partition, use_ext4: bool) -> Partition {
@@ -114,34 +162,69 @@ pub fn fill_fs_type(part: &Partition, use_ext4: bool) -> Partition {
 }
 
 pub fn find_esp_partition(device_path: &Path) -> Result<Partition> {
+    find_partition_by_flag(device_path, libparted::PartitionFlag::PED_PARTITION_ESP)?
+        .ok_or_else(|| anyhow!("Installer could not detect the EFI system partition."))
+}
+
+/// Whether the firmware loads GRUB from a PReP boot partition (IBM CHRP/PowerVM),
+/// OPAL (PowerNV) systems boot through petitboot instead
+pub fn needs_prep_partition() -> bool {
+    matches!(
+        network::get_arch_name(),
+        Some("ppc64el") | Some("ppc64") | Some("powerpc")
+    ) && !is_opal_firmware()
+}
+
+fn is_opal_firmware() -> bool {
+    std::fs::read_to_string("/proc/cpuinfo")
+        .unwrap_or_default()
+        .lines()
+        .find(|x| x.starts_with("firmware"))
+        .and_then(|x| x.split(':').nth(1))
+        .map(|x| x.trim() == "OPAL")
+        .unwrap_or(false)
+}
+
+pub fn find_prep_partition(device_path: &Path) -> Result<Partition> {
+    find_partition_by_flag(device_path, libparted::PartitionFlag::PED_PARTITION_PREP)?
+        .ok_or_else(|| {
+            anyhow!(
+                "Installer could not find a PReP boot partition on {}. On PowerPC systems with IBM (CHRP/PowerVM) firmware, the firmware loads the bootloader from a small (4 - 8MiB) unformatted partition with the \"prep\" flag set (type 0x41 on DOS/MBR partition maps). Please create one at the start of the disk, or let the installer partition the disk automatically.",
+                device_path.display()
+            )
+        })
+}
+
+fn find_partition_by_flag(
+    device_path: &Path,
+    flag: libparted::PartitionFlag,
+) -> Result<Option<Partition>> {
     let mut device = libparted::Device::get(device_path)?;
     if let Ok(disk) = libparted::Disk::new(&mut device) {
         for mut part in disk.parts() {
             if part.num() < 0 {
                 continue;
             }
-            if part.get_flag(libparted::PartitionFlag::PED_PARTITION_ESP) {
+            if part.get_flag(flag) {
                 let fs_type = if let Ok(type_) = part.get_geom().probe_fs() {
                     Some(type_.name().to_owned())
                 } else {
                     None
                 };
-                let path = part.get_path().ok_or_else(|| {
-                    anyhow!("Installer could not detect the EFI system partition.")
-                })?;
-                return Ok(Partition {
+                let path = part
+                    .get_path()
+                    .ok_or_else(|| anyhow!("Installer could not detect the partition path."))?;
+                return Ok(Some(Partition {
                     path: Some(path.to_owned()),
                     parent_path: None,
                     size: 0,
                     fs_type,
-                });
+                }));
             }
         }
     }
 
-    Err(anyhow!(
-        "Installer could not detect the EFI system partition."
-    ))
+    Ok(None)
 }
 
 pub fn list_devices() -> Vec<Device<'static>> {
@@ -212,6 +295,465 @@ pub fn device_is_empty(dev: &Path) -> Result<bool> {
     Ok(parts.all(|x| x.get_path().is_none()))
 }
 
+/// Why a disk or partition can not be installed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unusable {
+    /// The installer is running from it
+    LiveMedium,
+    /// Not a disk AOSC OS can boot from, e.g., an RPMB or boot area of eMMC storage
+    Unsupported,
+    /// Smaller than the selected variant needs
+    TooSmall,
+    /// Mounted (at the path) or used as swap
+    InUse(String),
+    ReadOnly,
+}
+
+/// A disk with what is known about it, for choosing where to install
+#[derive(Debug, Clone)]
+pub struct DiskInfo {
+    pub device: DkDerive,
+    pub serial: Option<String>,
+    /// The link in /dev/disk/by-id, which stays the same across reboots
+    pub by_id: Option<PathBuf>,
+    /// Whether it is a spinning disk, if the kernel can tell
+    pub rotational: Option<bool>,
+    /// How the disk is connected, e.g., "SATA", "NVMe" or "USB"
+    pub transport: Option<String>,
+    /// The partition table type, e.g., "gpt" or "msdos"
+    pub table: Option<String>,
+    /// Whether SMART reports the disk as healthy, if smartctl could tell
+    pub healthy: Option<bool>,
+    /// Space not taken by any partition
+    pub unallocated: u64,
+    pub partitions: Vec<PartitionInfo>,
+    pub unusable: Option<Unusable>,
+}
+
+/// A partition with what is known about it, for choosing where to install
+#[derive(Debug, Clone)]
+pub struct PartitionInfo {
+    pub partition: Partition,
+    pub label: Option<String>,
+    /// Where it is mounted, or "[SWAP]" if it is used as swap
+    pub mount_point: Option<String>,
+    /// The operating system (or boot loaders) found on it
+    pub os: Option<String>,
+    /// Space left in its filesystem
+    pub free: Option<u64>,
+    /// Space taken in its filesystem
+    pub used: Option<u64>,
+    /// Whether it is flagged as an EFI system partition
+    pub esp: bool,
+    pub unusable: Option<Unusable>,
+}
+
+/// All disks with their partitions, `required_size` is the space the selected variant
+/// needs, disks and partitions smaller than it are marked as unusable
+pub fn list_disks(required_size: u64) -> Vec<DiskInfo> {
+    crate::assert_not_ui_thread("Listing the disks");
+
+    let mounts = read_mounts();
+    let labels = read_labels();
+    let live_sources = mounts
+        .iter()
+        .filter(|(_, mount_point)| mount_point == LIVE_MEDIUM_PATH)
+        .map(|(source, _)| source.clone())
+        .collect::<Vec<_>>();
+
+    let mut disks = vec![];
+    for device in libparted::Device::devices(true) {
+        let path = device.path().to_path_buf();
+        let sector_size = device.sector_size();
+        let size = sector_size * device.length();
+        let dk = DkDerive {
+            path: path.clone(),
+            model: device.model().to_string(),
+            size,
+        };
+        let sysfs = sysfs_path(&path);
+        let transport = std::fs::canonicalize(&sysfs)
+            .ok()
+            .and_then(|x| transport_from_sysfs(&x.to_string_lossy()))
+            .map(|x| x.to_string());
+        let read_only = std::fs::read_to_string(sysfs.join("ro")).is_ok_and(|x| x.trim() == "1");
+        let rotational = std::fs::read_to_string(sysfs.join("queue/rotational"))
+            .ok()
+            .map(|x| x.trim() == "1");
+        let unallocated = unallocated_space(device, size);
+        let esp = find_partition_by_flag(&path, libparted::PartitionFlag::PED_PARTITION_ESP)
+            .ok()
+            .flatten()
+            .and_then(|x| x.path);
+
+        let partitions = list_partitions(Some(path.clone()))
+            .into_iter()
+            .map(|partition| {
+                let mut info = partition_info(partition, &mounts, &labels, required_size);
+                info.esp = esp.is_some() && info.partition.path == esp;
+                info
+            })
+            .collect::<Vec<_>>();
+        let is_live = live_sources.iter().any(|source| {
+            *source == path
+                || partitions
+                    .iter()
+                    .any(|x| x.partition.path.as_ref() == Some(source))
+        });
+        let unusable = if is_live {
+            Some(Unusable::LiveMedium)
+        } else if !(device_is_sata(&path) || device_is_sdcard(&path) || device_is_nvme(&path)) {
+            Some(Unusable::Unsupported)
+        } else if read_only {
+            Some(Unusable::ReadOnly)
+        } else if size < required_size {
+            Some(Unusable::TooSmall)
+        } else {
+            None
+        };
+
+        disks.push(DiskInfo {
+            serial: disk_serial(&path),
+            by_id: by_id_link(&path),
+            rotational,
+            transport,
+            table: get_partition_table_type(Some(&path)).ok(),
+            healthy: disk_health(&path),
+            unallocated,
+            partitions,
+            unusable,
+            device: dk,
+        });
+    }
+
+    disks
+}
+
+fn partition_info(
+    partition: Partition,
+    mounts: &[(PathBuf, String)],
+    labels: &[(PathBuf, String)],
+    required_size: u64,
+) -> PartitionInfo {
+    let path = partition.path.clone().unwrap_or_default();
+    let mount_point = mounts
+        .iter()
+        .find(|(source, _)| *source == path)
+        .map(|(_, mount_point)| mount_point.clone())
+        .or_else(|| is_swap(&path).then(|| "[SWAP]".to_string()));
+    let label = labels
+        .iter()
+        .find(|(target, _)| *target == path)
+        .map(|(_, label)| label.clone());
+    let contents = match partition.fs_type.as_deref() {
+        Some(fs_type) if !fs_type.starts_with("linux-swap") => {
+            crate::install::probe_partition(&partition, mount_point.as_deref().map(Path::new)).ok()
+        }
+        _ => None,
+    };
+    let unusable = if let Some(mount_point) = &mount_point {
+        Some(Unusable::InUse(mount_point.clone()))
+    } else if partition.size < required_size {
+        Some(Unusable::TooSmall)
+    } else {
+        None
+    };
+
+    PartitionInfo {
+        partition,
+        label,
+        mount_point,
+        os: contents.as_ref().and_then(|x| x.os.clone()),
+        free: contents.as_ref().map(|x| x.free),
+        used: contents.as_ref().map(|x| x.used),
+        esp: false,
+        unusable,
+    }
+}
+
+/// The sources (resolved to the device nodes) and mount points in /proc/mounts
+fn read_mounts() -> Vec<(PathBuf, String)> {
+    let buf = std::fs::read("/proc/mounts").unwrap_or_default();
+    let Ok((_, mounts)) = crate::parser::list_mounts(&buf) else {
+        return vec![];
+    };
+
+    mounts
+        .into_iter()
+        .filter(|(source, _)| source.starts_with("/dev/"))
+        .map(|(source, mount_point)| {
+            let source = std::fs::canonicalize(source).unwrap_or_else(|_| PathBuf::from(source));
+            (source, mount_point.replace("\\040", " "))
+        })
+        .collect()
+}
+
+fn is_swap(path: &Path) -> bool {
+    std::fs::read_to_string("/proc/swaps")
+        .unwrap_or_default()
+        .lines()
+        .skip(1)
+        .filter_map(|x| x.split_whitespace().next())
+        .any(|x| Path::new(x) == path)
+}
+
+/// The device nodes and their filesystem labels, from the links udev creates
+/// The filesystem label of the partition at `path`
+pub fn partition_label(path: &Path) -> Option<String> {
+    read_labels()
+        .into_iter()
+        .find(|(target, _)| target == path)
+        .map(|(_, label)| label)
+}
+
+fn read_labels() -> Vec<(PathBuf, String)> {
+    let Ok(dir) = std::fs::read_dir(LABELS_PATH) else {
+        return vec![];
+    };
+
+    dir.flatten()
+        .filter_map(|entry| {
+            let target = std::fs::canonicalize(entry.path()).ok()?;
+            Some((target, unescape_udev(&entry.file_name().to_string_lossy())))
+        })
+        .collect()
+}
+
+/// udev escapes the characters not allowed in the link names as `\xNN`
+fn unescape_udev(name: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = name.as_bytes();
+    while !rest.is_empty() {
+        if rest.len() >= 4 && rest.starts_with(b"\\x") {
+            if let Some(byte) = std::str::from_utf8(&rest[2..4])
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+            {
+                bytes.push(byte);
+                rest = &rest[4..];
+                continue;
+            }
+        }
+        bytes.push(rest[0]);
+        rest = &rest[1..];
+    }
+
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn sysfs_path(device_path: &Path) -> PathBuf {
+    Path::new("/sys/class/block").join(device_path.file_name().unwrap_or_default())
+}
+
+/// The disk a partition is on, or the device itself if it is not a partition
+fn parent_disk(device_path: &Path) -> PathBuf {
+    let sysfs = sysfs_path(device_path);
+    if !sysfs.join("partition").exists() {
+        return device_path.to_path_buf();
+    }
+
+    std::fs::canonicalize(&sysfs)
+        .ok()
+        .and_then(|x| x.parent().and_then(|x| x.file_name()).map(|x| x.to_owned()))
+        .map(|x| Path::new("/dev").join(x))
+        .unwrap_or_else(|| device_path.to_path_buf())
+}
+
+/// The serial number of the disk, as udev has read it or from sysfs
+fn disk_serial(device_path: &Path) -> Option<String> {
+    let sysfs = sysfs_path(device_path);
+    let udev_data = std::fs::read_to_string(sysfs.join("dev"))
+        .ok()
+        .and_then(|x| {
+            std::fs::read_to_string(Path::new(UDEV_DATA_PATH).join(format!("b{}", x.trim()))).ok()
+        })
+        .unwrap_or_default();
+
+    udev_property(&udev_data, "ID_SERIAL_SHORT")
+        .or_else(|| {
+            std::fs::read_to_string(sysfs.join("device/serial"))
+                .ok()
+                .map(|x| x.trim().to_string())
+        })
+        .filter(|x| !x.is_empty())
+}
+
+/// The value of a property (`E:KEY=value`) in a udev database entry
+fn udev_property(data: &str, key: &str) -> Option<String> {
+    data.lines()
+        .filter_map(|x| x.strip_prefix("E:"))
+        .find_map(|x| x.strip_prefix(key)?.strip_prefix('='))
+        .map(|x| x.to_string())
+}
+
+/// The link in /dev/disk/by-id to the disk or partition, which stays the same across boots
+/// and machines
+pub fn by_id_link(device_path: &Path) -> Option<PathBuf> {
+    let names = std::fs::read_dir(IDS_PATH)
+        .ok()?
+        .flatten()
+        .filter(|entry| {
+            std::fs::canonicalize(entry.path()).is_ok_and(|target| target == device_path)
+        })
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+
+    preferred_by_id(names).map(|x| Path::new(IDS_PATH).join(x))
+}
+
+/// The device at `path`, following the links in /dev/disk/by-id and the like
+pub fn resolve_device_link(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The most readable of the by-id names of a disk: one with the model and serial number
+/// rather than the WWN or EUI
+fn preferred_by_id(mut names: Vec<String>) -> Option<String> {
+    names.sort();
+    names.sort_by_key(|x| {
+        x.starts_with("wwn-") || x.starts_with("nvme-eui.") || x.starts_with("nvme-nvme.")
+    });
+
+    names.into_iter().next()
+}
+
+/// Where the live medium is found according to dracut's `root=live:` argument, only if the
+/// live system has been copied to the RAM (`rd.live.ram`), as it runs from the medium otherwise
+fn live_medium_link(cmdline: &str) -> Option<PathBuf> {
+    let args = cmdline.split_whitespace().collect::<Vec<_>>();
+    if !args
+        .iter()
+        .any(|x| *x == "rd.live.ram" || *x == "rd.live.ram=1")
+    {
+        return None;
+    }
+    let spec = args.iter().find_map(|x| x.strip_prefix("root=live:"))?;
+    // spaces in the label are written as `\x20`, as in the names of the udev links
+    if let Some(label) = spec
+        .strip_prefix("CDLABEL=")
+        .or_else(|| spec.strip_prefix("LABEL="))
+    {
+        Some(Path::new(LABELS_PATH).join(label))
+    } else if let Some(uuid) = spec.strip_prefix("UUID=") {
+        Some(Path::new(UUIDS_PATH).join(uuid))
+    } else {
+        spec.starts_with("/dev/").then(|| PathBuf::from(spec))
+    }
+}
+
+/// The disk the installer has been booted from, if it may be powered off before rebooting:
+/// the live system has to be running from the RAM, with nothing on the disk mounted
+pub fn ejectable_live_medium() -> Option<PathBuf> {
+    let cmdline = std::fs::read_to_string(KERNEL_CMDLINE_PATH).ok()?;
+    let partition = std::fs::canonicalize(live_medium_link(&cmdline)?).ok()?;
+    let disk = parent_disk(&partition);
+    if read_mounts()
+        .iter()
+        .any(|(source, _)| *source == disk || parent_disk(source) == disk)
+    {
+        return None;
+    }
+
+    Some(disk)
+}
+
+/// How the disk is connected, judging from the device path in sysfs
+fn transport_from_sysfs(path: &str) -> Option<&'static str> {
+    [
+        ("/usb", "USB"),
+        ("/nvme/", "NVMe"),
+        ("/mmc_host/", "MMC"),
+        ("/virtio", "VirtIO"),
+        ("/ata", "SATA"),
+        ("/host", "SCSI"),
+    ]
+    .iter()
+    .find(|(pattern, _)| path.contains(pattern))
+    .map(|(_, name)| *name)
+}
+
+fn unallocated_space(mut device: Device<'_>, size: u64) -> u64 {
+    let sector_size = device.sector_size();
+    match libparted::Disk::new(&mut device) {
+        Ok(disk) => disk
+            .parts()
+            .filter(|x| x.type_get_name() == "free")
+            .map(|x| x.geom_length().max(0) as u64 * sector_size)
+            .sum(),
+        // no partition table
+        Err(_) => size,
+    }
+}
+
+/// Ask smartctl (if installed) whether the disk is healthy
+fn disk_health(path: &Path) -> Option<bool> {
+    debug!("Running smartctl -H -j {}", path.display());
+    let output = Command::new("smartctl")
+        .args(["-H", "-j"])
+        .arg(path)
+        .output()
+        .ok()?;
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+
+    report["smart_status"]["passed"].as_bool()
+}
+
+/// Find a program in `PATH`
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Run a partitioning program on the disk, and wait for udev to pick up the changes
+pub fn run_partitioner(program: &str, device_path: &Path) -> Result<std::process::ExitStatus> {
+    info!("Running {} on {}", program, device_path.display());
+    let status = Command::new(program).arg(device_path).status()?;
+    info!("{} exited with {}", program, status);
+    Command::new("udevadm").arg("settle").status().ok();
+
+    Ok(status)
+}
+
+/// Whether the partition is still on its disk, at the same path and of the same size
+pub fn partition_unchanged(partition: &Partition) -> bool {
+    list_partitions(partition.parent_path.clone())
+        .iter()
+        .any(|x| x.path == partition.path && x.size == partition.size)
+}
+
+/// The raw area between the partition table `auto_create_partitions` creates and its first
+/// partition starting at `first_start`, returns the (start, end) offsets in bytes
+pub fn planned_raw_area(first_start: u64) -> (u64, u64) {
+    // protective MBR + GPT header + 128 entries, of 512-byte sectors
+    let table_end = if is_efi_booted() { 34 } else { 1 };
+
+    (table_end * 512, first_start)
+}
+
+/// Find the raw area between the partition table and the first partition of the device,
+/// returns the (start, end) offsets in bytes
+pub fn raw_area_before_partitions(dev: &Path) -> Result<(u64, u64)> {
+    let table_end = match get_partition_table_type(Some(dev))?.as_str() {
+        // protective MBR + GPT header + 128 entries
+        "gpt" => 34,
+        _ => 1,
+    };
+
+    let mut device = libparted::Device::new(dev)?;
+    let sector_size = device.sector_size();
+    let length = device.length();
+    let disk = libparted::Disk::new(&mut device)?;
+    let first_start = disk
+        .parts()
+        .filter(|x| x.num() > 0)
+        .map(|x| x.geom_start().max(0) as u64)
+        .min()
+        .unwrap_or(length);
+
+    Ok((table_end * sector_size, first_start * sector_size))
+}
+
 fn loop_device_get_parts(
     mut device: Device<'_>,
     partitions: &mut Vec<Partition>,
@@ -306,6 +848,13 @@ pub fn right_combine(device_path: Option<&Path>) -> Result<()> {
 
 #[cfg(target_arch = "powerpc64")]
 pub fn right_combine(device_path: Option<&Path>) -> Result<()> {
+    if needs_prep_partition() {
+        let device_path = device_path.ok_or_else(|| {
+            anyhow!("Installer could not detect the corresponding block device node for the specified partition!")
+        })?;
+        find_prep_partition(device_path)?;
+    }
+
     Ok(())
 }
 
@@ -313,34 +862,40 @@ pub fn fstab_entries(
     device_path: Option<&PathBuf>,
     fs_type: &str,
     mount_path: Option<&Path>,
+    options: Option<&str>,
 ) -> Result<OsString> {
     let target = device_path.ok_or_else(|| {
         anyhow!(
             "Installer could not detect the corresponding device file for the specified partition!"
         )
     })?;
-    let (fs_type, option) = match fs_type {
-        "vfat" | "fat16" | "fat32" => (FileSystem::Fat32, "defaults,nofail"),
-        "ext4" => (FileSystem::Ext4, "defaults"),
-        "btrfs" => (FileSystem::Btrfs, "defaults"),
-        "xfs" => (FileSystem::Xfs, "defaults"),
-        "f2fs" => (FileSystem::F2fs, "defaults"),
-        "swap" => (FileSystem::Swap, "sw"),
-        _ => return Err(anyhow!("Unsupported filesystem type!")),
-    };
+    let (fs_type, option) = fstab_options(fs_type)?;
     let root_id = BlockInfo::get_partition_id(target, fs_type).ok_or_else(|| {
         anyhow!(
             "Installer could not obtain partition UUID for {}!",
             target.display()
         )
     })?;
-    let root = BlockInfo::new(root_id, fs_type, mount_path, option);
+    let root = BlockInfo::new(root_id, fs_type, mount_path, options.unwrap_or(option));
     let fstab = &mut OsString::new();
     root.write_entry(fstab);
 
     Ok(fstab.to_owned())
 }
 
+/// The filesystem and the mount options of fstab entries for `fs_type`
+pub fn fstab_options(fs_type: &str) -> Result<(FileSystem, &'static str)> {
+    Ok(match fs_type {
+        "vfat" | "fat16" | "fat32" => (FileSystem::Fat32, "defaults,nofail"),
+        "ext4" => (FileSystem::Ext4, "defaults"),
+        "btrfs" => (FileSystem::Btrfs, "defaults"),
+        "xfs" => (FileSystem::Xfs, "defaults"),
+        "f2fs" => (FileSystem::F2fs, "defaults"),
+        "swap" => (FileSystem::Swap, "sw"),
+        _ => return Err(anyhow!("Unsupported filesystem type!")),
+    })
+}
+
 pub fn get_recommend_swap_size(mem: u64) -> Result<f64> {
     // 1073741824 is 1 * 1024 * 1024 * 1024 (1GiB => 1iB)
     let swap_size = match mem {
@@ -354,6 +909,33 @@ pub fn get_recommend_swap_size(mem: u64) -> Result<f64> {
     Ok(swap_size)
 }
 
+/// The swap size recommended for `mem` bytes of RAM: about the size of the RAM (twice of
+/// it with 1GiB or less), or RAM + √RAM (counted in GiB) to be able to hibernate
+pub fn recommend_swap_size(mem: u64, hibernation: bool) -> u64 {
+    const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+    if hibernation {
+        let gib = mem as f64 / GIB;
+        return ((gib + gib.sqrt()) * GIB).round() as u64;
+    }
+
+    match mem {
+        x @ ..=1073741824 => x * 2,
+        x => x,
+    }
+}
+
+/// The partitions formatted as swap on all the disks
+pub fn list_swap_partitions() -> Vec<Partition> {
+    list_partitions(None)
+        .into_iter()
+        .filter(|x| {
+            x.fs_type
+                .as_deref()
+                .is_some_and(|x| x.starts_with("linux-swap"))
+        })
+        .collect()
+}
+
 pub fn is_enable_hibernation(custom_size: f64) -> Result<bool> {
     // Get men (iB)
     let mem = sysinfo::System::new_all().total_memory();
@@ -372,7 +954,12 @@ pub fn is_enable_hibernation(custom_size: f64) -> Result<bool> {
 }
 
 #[cfg(debug_assertions)]
-pub fn auto_create_partitions(dev: &Path) -> Result<Partition> {
+pub fn auto_create_partitions(
+    dev: &Path,
+    keep_free: u64,
+    fs_type: &str,
+    first_start: u64,
+) -> Result<Partition> {
     let mut device = libparted::Device::new(dev)?;
     // let t: DeviceType = device.
     // if ["PED_DEVICE_LOOP", "PED_DEVICE_NVME", "PED_DEVICE_SDMMC", "PED_DEVICE_IDE", ]
@@ -437,13 +1024,13 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
     let device = &mut device as *mut Device;
     let device = unsafe { &mut (*device) };
 
-    let start_sector = 1024 * 1024 / sector_size;
+    let start_sector = first_start / sector_size;
 
     let system_end_sector = if is_efi {
         length - efi_size / sector_size + start_sector
     } else {
         length + start_sector
-    };
+    } - keep_free / sector_size;
 
     let mut flags = vec![];
 
@@ -456,7 +1043,7 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
         start_sector,
         end_sector: system_end_sector,
         format: true,
-        file_system: Some(FileSystem::Ext4),
+        file_system: Some(fstab_options(fs_type)?.0),
         kind: PartitionType::Primary,
         flags,
         label: None,
@@ -467,7 +1054,7 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
     let p = Partition {
         path: Some(PathBuf::from("/dev/loop30p1")),
         parent_path: Some(dev.to_path_buf()),
-        fs_type: Some("ext4".to_string()),
+        fs_type: Some(fs_type.to_string()),
         size: system_end_sector * device.sector_size(),
     };
 
@@ -516,8 +1103,15 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
     Ok(p)
 }
 
+/// Partition the whole disk `dev` for AOSC OS, leaving `keep_free` bytes at its end
+/// unpartitioned, returns the system partition
 #[cfg(not(debug_assertions))]
-pub fn auto_create_partitions(dev: &Path) -> Result<Partition> {
+pub fn auto_create_partitions(
+    dev: &Path,
+    keep_free: u64,
+    fs_type: &str,
+    first_start: u64,
+) -> Result<Partition> {
     let mut device = libparted::Device::new(dev)?;
     let device = &mut device as *mut Device;
     let device = unsafe { &mut (*device) };
@@ -609,7 +1203,7 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
     let device = &mut device as *mut Device;
     let mut device = unsafe { &mut (*device) };
 
-    let start_sector = 1024 * 1024 / sector_size;
+    let start_sector = first_start / sector_size;
     let end_sector = start_sector + (512 * 1024 * 1024 / device.sector_size());
 
     if is_efi {
@@ -630,7 +1224,24 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
         create_partition(&mut device, efi)?;
     }
 
-    let system_start_sector = if is_efi { end_sector } else { start_sector };
+    let mut system_start_sector = if is_efi { end_sector } else { start_sector };
+
+    if needs_prep_partition() {
+        let prep_end_sector = system_start_sector + PREP_SIZE / sector_size;
+        let prep = &PartitionCreate {
+            path: dev.to_path_buf(),
+            start_sector: system_start_sector,
+            end_sector: prep_end_sector,
+            format: false,
+            file_system: None,
+            kind: PartitionType::Primary,
+            flags: vec![PedPartitionFlag::PED_PARTITION_PREP],
+            label: None,
+        };
+
+        create_partition(&mut device, prep)?;
+        system_start_sector = prep_end_sector;
+    }
 
     let mut flags = vec![];
 
@@ -638,18 +1249,17 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
         flags.push(PedPartitionFlag::PED_PARTITION_BOOT);
     }
 
-    let length = device.length();
-
     // Ref: https://en.wikipedia.org/wiki/GUID_Partition_Table#Partition_entries_(LBA_2%E2%80%9333)
-    let last_usable_sector = device.length() - 34;
+    let last_usable_sector = device.length() - 34 - keep_free / sector_size;
     let mmod = (last_usable_sector - system_start_sector) % (1024 * 1024 / sector_size);
+    let system_end_sector = last_usable_sector - mmod;
 
     let system = &PartitionCreate {
         path: dev.to_path_buf(),
         start_sector: system_start_sector,
-        end_sector: last_usable_sector - mmod,
+        end_sector: system_end_sector,
         format: true,
-        file_system: Some(FileSystem::Ext4),
+        file_system: Some(fstab_options(fs_type)?.0),
         kind: PartitionType::Primary,
         flags,
         label: None,
@@ -692,8 +1302,8 @@ If you want to do this, change your computer's boot mode to UEFI mode."#
     let p = Partition {
         path: Some(p),
         parent_path: Some(dev.to_path_buf()),
-        fs_type: Some("ext4".to_owned()),
-        size: (length - system_start_sector) * sector_size,
+        fs_type: Some(fs_type.to_owned()),
+        size: (system_end_sector - system_start_sector) * sector_size,
     };
 
     format_partition(&p)?;
@@ -925,6 +1535,15 @@ fn test_recommend_swap_size() {
     assert_eq!(recommand_size, 1073741824.0);
 }
 
+#[test]
+fn test_recommend_swap_size_hibernation() {
+    let gib = 1024 * 1024 * 1024;
+    assert_eq!(recommend_swap_size(gib / 2, false), gib);
+    assert_eq!(recommend_swap_size(4 * gib, false), 4 * gib);
+    assert_eq!(recommend_swap_size(4 * gib, true), 6 * gib);
+    assert_eq!(recommend_swap_size(16 * gib, true), 20 * gib);
+}
+
 #[test]
 fn test_device_is_match() {
     assert!(device_is_nvme(Path::new("/dev/nvme0n1")));
@@ -935,3 +1554,72 @@ fn test_device_is_match() {
     assert!(!device_is_sata(Path::new("/dev/sr0")));
     assert!(!device_is_sdcard(Path::new("/dev/sr0")));
 }
+
+#[test]
+fn test_disk_info_helpers() {
+    assert_eq!(unescape_udev("AOSC\\x20OS"), "AOSC OS");
+    assert_eq!(unescape_udev("EFI\\x2fboot\\x"), "EFI/boot\\x");
+    assert_eq!(
+        transport_from_sysfs("/sys/devices/pci0000:00/0000:00:14.0/usb2/2-1/2-1:1.0/host6/target6:0:0/6:0:0:0/block/sdb"),
+        Some("USB")
+    );
+    assert_eq!(
+        transport_from_sysfs(
+            "/sys/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda"
+        ),
+        Some("SATA")
+    );
+    assert_eq!(
+        transport_from_sysfs(
+            "/sys/devices/pci0000:00/0000:00:1d.0/0000:3d:00.0/nvme/nvme0/nvme0n1"
+        ),
+        Some("NVMe")
+    );
+    assert_eq!(
+        transport_from_sysfs("/sys/devices/virtual/block/loop0"),
+        None
+    );
+}
+
+#[test]
+fn test_disk_ids() {
+    let data = "S:disk/by-id/ata-Samsung_SSD_870_S6PNNS0T\nE:ID_SERIAL=Samsung_SSD_870_S6PNNS0T\nE:ID_SERIAL_SHORT=S6PNNS0T\n";
+    assert_eq!(
+        udev_property(data, "ID_SERIAL_SHORT").as_deref(),
+        Some("S6PNNS0T")
+    );
+    assert_eq!(
+        udev_property(data, "ID_SERIAL").as_deref(),
+        Some("Samsung_SSD_870_S6PNNS0T")
+    );
+    assert_eq!(udev_property(data, "ID_MODEL"), None);
+    assert_eq!(
+        preferred_by_id(vec![
+            "wwn-0x5002538f4250a1b2".to_string(),
+            "ata-Samsung_SSD_870_S6PNNS0T".to_string(),
+        ])
+        .as_deref(),
+        Some("ata-Samsung_SSD_870_S6PNNS0T")
+    );
+    assert_eq!(
+        preferred_by_id(vec!["nvme-eui.0025385b71b0a1b2".to_string()]).as_deref(),
+        Some("nvme-eui.0025385b71b0a1b2")
+    );
+    assert_eq!(preferred_by_id(vec![]), None);
+}
+
+#[test]
+fn test_live_medium_link() {
+    assert_eq!(
+        live_medium_link(
+            "BOOT_IMAGE=/boot/vmlinuz root=live:CDLABEL=AOSC\\x20OS rd.live.ram quiet"
+        ),
+        Some(PathBuf::from("/dev/disk/by-label/AOSC\\x20OS"))
+    );
+    assert_eq!(
+        live_medium_link("root=live:UUID=1234-ABCD rd.live.ram=1"),
+        Some(PathBuf::from("/dev/disk/by-uuid/1234-ABCD"))
+    );
+    // the live system still runs from the medium
+    assert_eq!(live_medium_link("root=live:CDLABEL=AOSC quiet"), None);
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+
+use crate::{cancel::Cancelled, tr};
+
+/// Why the installer has exited, scripts depend on these codes (listed by
+/// `aoscdk-rs --help-exit-codes`), so they are never renumbered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// Anything not covered below, e.g., a bug
+    Internal = 1,
+    /// Also used by clap for invalid command line arguments
+    InvalidConfig = 2,
+    BulletinNotAcknowledged = 3,
+    LockConflict = 4,
+    UnsupportedArch = 5,
+    Network = 6,
+    InsufficientSpace = 7,
+    UserAbort = 8,
+    Partitioning = 9,
+    Extraction = 10,
+    Bootloader = 11,
+}
+
+impl ExitCode {
+    pub const ALL: &'static [ExitCode] = &[
+        ExitCode::Success,
+        ExitCode::Internal,
+        ExitCode::InvalidConfig,
+        ExitCode::BulletinNotAcknowledged,
+        ExitCode::LockConflict,
+        ExitCode::UnsupportedArch,
+        ExitCode::Network,
+        ExitCode::InsufficientSpace,
+        ExitCode::UserAbort,
+        ExitCode::Partitioning,
+        ExitCode::Extraction,
+        ExitCode::Bootloader,
+    ];
+
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// The symbolic name printed with the error message
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Success => "SUCCESS",
+            ExitCode::Internal => "INTERNAL_ERROR",
+            ExitCode::InvalidConfig => "INVALID_CONFIG",
+            ExitCode::BulletinNotAcknowledged => "BULLETIN_NOT_ACKNOWLEDGED",
+            ExitCode::LockConflict => "LOCK_CONFLICT",
+            ExitCode::UnsupportedArch => "UNSUPPORTED_ARCH",
+            ExitCode::Network => "NETWORK_FAILURE",
+            ExitCode::InsufficientSpace => "INSUFFICIENT_SPACE",
+            ExitCode::UserAbort => "USER_ABORT",
+            ExitCode::Partitioning => "PARTITIONING_FAILURE",
+            ExitCode::Extraction => "EXTRACTION_FAILURE",
+            ExitCode::Bootloader => "BOOTLOADER_FAILURE",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ExitCode::Success => "The command has succeeded.",
+            ExitCode::Internal => "Installer has encountered an unexpected error.",
+            ExitCode::InvalidConfig => {
+                "The command line arguments or the configuration file are invalid."
+            }
+            ExitCode::BulletinNotAcknowledged => {
+                "A critical release bulletin has not been acknowledged."
+            }
+            ExitCode::LockConflict => "Another instance of Installer is running.",
+            ExitCode::UnsupportedArch => "The architecture of this device is not supported.",
+            ExitCode::Network => "Installer could not download what it needs.",
+            ExitCode::InsufficientSpace => "The target is too small for the selected variant.",
+            ExitCode::UserAbort => "The installation has been cancelled.",
+            ExitCode::Partitioning => "Installer failed to partition or format the target.",
+            ExitCode::Extraction => "Installer failed to unpack the system release.",
+            ExitCode::Bootloader => "Installer failed to install the bootloader.",
+        }
+    }
+
+    /// The table printed by `--help-exit-codes`
+    pub fn table() -> String {
+        ExitCode::ALL
+            .iter()
+            .map(|x| {
+                format!(
+                    "{:>4}  {:<26}{}\n",
+                    x.code(),
+                    x.name(),
+                    tr!(x.description())
+                )
+            })
+            .collect()
+    }
+
+    /// Make `e` exit with this code, unless it has been given one already
+    pub fn wrap(self, e: anyhow::Error) -> anyhow::Error {
+        if e.chain().any(|x| x.is::<Failure>()) {
+            return e;
+        }
+
+        Failure {
+            code: self,
+            error: e,
+        }
+        .into()
+    }
+
+    /// Make `e` exit with this code, unless it has been given one already or the kind of
+    /// error decides one
+    pub fn fallback(self, e: anyhow::Error) -> anyhow::Error {
+        if ExitCode::of_error(&e) != ExitCode::Internal {
+            return e;
+        }
+
+        self.wrap(e)
+    }
+
+    /// The code to exit with for the error: the one it has been given, or the one of the
+    /// kind of error it comes from
+    pub fn of_error(e: &anyhow::Error) -> ExitCode {
+        if let Some(failure) = e.chain().find_map(|x| x.downcast_ref::<Failure>()) {
+            return failure.code;
+        }
+        if e.chain().any(|x| x.is::<Cancelled>()) {
+            return ExitCode::UserAbort;
+        }
+        if e.chain().any(|x| x.is::<reqwest::Error>()) {
+            return ExitCode::Network;
+        }
+        if e.chain()
+            .any(|x| x.is::<toml::de::Error>() || x.is::<serde_json::Error>())
+        {
+            return ExitCode::InvalidConfig;
+        }
+
+        ExitCode::Internal
+    }
+}
+
+/// An error with the code the installer should exit with, see `ExitCode::wrap`
+#[derive(Debug)]
+struct Failure {
+    code: ExitCode,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for Failure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+#[test]
+fn test_exit_code_of_error() {
+    use anyhow::anyhow;
+
+    assert_eq!(
+        ExitCode::of_error(&anyhow!("Something went wrong.")),
+        ExitCode::Internal
+    );
+    assert_eq!(
+        ExitCode::of_error(&anyhow::Error::from(Cancelled).context("Unpacking")),
+        ExitCode::UserAbort
+    );
+    let e = toml::from_str::<toml::Table>("user = ").unwrap_err();
+    assert_eq!(
+        ExitCode::of_error(&anyhow::Error::from(e)),
+        ExitCode::InvalidConfig
+    );
+
+    // the code given first stays, and goes through the context added later
+    let e = ExitCode::InsufficientSpace.wrap(anyhow!("The partition is too small."));
+    assert_eq!(e.to_string(), "The partition is too small.");
+    let e = ExitCode::Network.wrap(e).context("Checking the target");
+    assert_eq!(ExitCode::of_error(&e), ExitCode::InsufficientSpace);
+    let e = ExitCode::InvalidConfig.fallback(e);
+    assert_eq!(ExitCode::of_error(&e), ExitCode::InsufficientSpace);
+    // the kind of error decides unless a code is given
+    let e = ExitCode::Extraction.fallback(Cancelled.into());
+    assert_eq!(ExitCode::of_error(&e), ExitCode::UserAbort);
+    let e = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+    let e = ExitCode::Network.wrap(e.into());
+    assert_eq!(ExitCode::of_error(&e), ExitCode::Network);
+    assert_eq!(
+        ExitCode::of_error(&ExitCode::Bootloader.fallback(anyhow!("grub-install failed"))),
+        ExitCode::Bootloader
+    );
+
+    let codes = ExitCode::ALL.iter().map(|x| x.code()).collect::<Vec<_>>();
+    assert_eq!(codes, (0..ExitCode::ALL.len() as i32).collect::<Vec<_>>());
+}
@@ -0,0 +1,46 @@
+use cursive::{
+    view::SizeConstraint,
+    views::{ResizedView, SelectView},
+    View,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Terminals without cursor addressing worth the name, on which only the accessible mode works
+const DUMB_TERMS: &[&str] = &["dumb"];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the accessible mode is asked for, or the terminal needs it
+pub(super) fn detect(asked: bool) -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+
+    asked || DUMB_TERMS.contains(&term.as_str())
+}
+
+/// Show every screen and message on its own: dialogs fill the screen instead of being
+/// stacked over each other, and lists are shown inline instead of in popups
+pub(super) fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub(super) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Size the content of a dialog, at most `width` columns wide, or the whole screen in
+/// accessible mode so that nothing beneath it is shown
+pub(super) fn dialog_content<V: View>(view: V, width: usize) -> ResizedView<V> {
+    if is_enabled() {
+        ResizedView::with_full_screen(view)
+    } else {
+        ResizedView::new(SizeConstraint::AtMost(width), SizeConstraint::Free, view)
+    }
+}
+
+/// A select view opening a popup to choose from, or showing all the choices in place in
+/// accessible mode
+pub(super) fn dropdown<T: 'static>(mut view: SelectView<T>) -> SelectView<T> {
+    view.set_popup(!is_enabled());
+
+    view
+}
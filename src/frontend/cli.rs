@@ -1,30 +1,164 @@
 use std::{
+    ffi::OsString,
+    io::IsTerminal,
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     thread,
     time::Duration,
 };
 
 use crate::{
-    disks::{self, Partition},
-    install::{self, is_acceptable_username, is_valid_hostname, umount_all},
-    network::{self, fetch_mirrors, Mirror, VariantEntry},
+    boards,
+    cancel::{self, CancelToken},
+    disks::{self, DkDerive, Partition},
+    exit_code::ExitCode,
+    i18n::{self, Language},
+    install::{self, check_username, is_valid_hostname, umount_all, UnmountStatus},
+    log::{is_quiet, save_log, StepJournal, StepOutcome, Verbosity},
+    network::{
+        self, fetch_mirrors, BulletinLevel, Mirror, MirrorTest, SpeedtestLimits, VariantEntry,
+    },
+    report, tr,
 };
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use indicatif::ProgressBar;
-use log::{error, info};
+use log::{error, info, warn};
+use rustix::fd::OwnedFd;
+use serde::Serialize;
+
+use super::{
+    begin_install, check_config_file_passwords, check_root_account,
+    cmdline::{self, BootOptions},
+    config_file::{self, ConfigFile, ConfigSource, TargetMode},
+    data_loss_warning,
+    disk_list::{DiskListing, PartitionListing},
+    dry_run::InstallPlan,
+    human_size,
+    json_progress::{self, Event, ProgressEvents},
+    mirror_bench::{self, BenchReport},
+    mounts::{self, MountAssignment},
+    not_enough_space_msg, overall_percent, package_repo_summary, password_hash, plain_description,
+    reinstall_summary, resolve_ssh_keys, resume,
+    settings::{self, Setting},
+    step_exit_code,
+    theme::ThemeName,
+    tui::{self, auto_partition_system_size, make_device_list},
+    tui_main,
+    validate::{self, Diagnostic, Report},
+    AtomicBoolWrapper, BootMenu, InstallClock, InstallConfig, PostInstallAction, RootPassword,
+    StorageMode, SwapKind, DEFAULT_EMPTY_SIZE, LAZY_UNMOUNT_INFO,
+};
 
-use super::{begin_install, tui_main, AtomicBoolWrapper, InstallConfig, DEFAULT_EMPTY_SIZE};
+/// What has to be given for an unattended installation: the key of the answer file and the
+/// options giving it instead
+const REQUIRED_ANSWERS: &[(&str, &str)] = &[
+    ("variant", "--variant"),
+    ("target", "--target or --target-dir"),
+    ("user", "--user or --oobe"),
+    ("user.password", "--password or --password-hash"),
+];
+/// The mirror used unless another one is given
+const DEFAULT_MIRROR: &str = "https://repo.aosc.io/aosc-os";
+/// Set for the installation started by `dk.auto` on the kernel command line, which asks
+/// nothing even on the console
+static UNATTENDED: AtomicBool = AtomicBool::new(false);
+/// The options answering the wizard, they override the answer file given with `--config`
+const ANSWER_OPTIONS: &[&str] = &[
+    "tarball",
+    "mirror",
+    "path",
+    "target_dir",
+    "fs",
+    "user",
+    "password",
+    "password_hash",
+    "password_cost",
+    "full_name",
+    "uid",
+    "groups",
+    "sudo_nopasswd",
+    "no_admin",
+    "root_password",
+    "enable_sshd",
+    "ssh_key",
+    "ssh_disable_password_auth",
+    "no_copy_network",
+    "no_verify_files",
+    "extra_package",
+    "enable_service",
+    "disable_service",
+    "kernel_cmdline",
+    "grub_timeout",
+    "os_prober",
+    "grub_hide_menu",
+    "boot_splash",
+    "no_boot_splash",
+    "hidpi",
+    "no_hidpi",
+    "console_font",
+    "grub_gfxmode",
+    "generate_machine_id",
+    "keymap",
+    "default_target",
+    "disable_display_manager",
+    "oobe",
+    "autologin",
+    "shell",
+    "nvidia_driver",
+    "no_mirror_repo",
+    "board",
+    "bootloader",
+    "preserve_home",
+    "no_restore_users",
+    "hostname",
+    "timezone",
+    "locale",
+    "extra_locale",
+    "use_rtc",
+    "no_ntp",
+    "ntp_server",
+    "no_swap",
+    "swap_size",
+];
 
 #[derive(Parser, Debug)]
 #[clap(about, version, author)]
 pub struct Args {
     #[clap(subcommand)]
-    subcommand: DeployKitCliCommand,
+    subcommand: Option<DeployKitCliCommand>,
+    /// List the exit codes of the installer and what they mean
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    help_exit_codes: bool,
+    /// Language of the messages (en or zh), detected from LANG/LC_ALL by default
+    #[clap(long, global = true, value_parser = parse_language)]
+    language: Option<Language>,
+    /// Print nothing but the errors and the result
+    #[clap(short, long, global = true, conflicts_with = "verbose", action = clap::ArgAction::SetTrue)]
+    quiet: bool,
+    /// Print what is going on, twice (-vv) for the commands run with their whole output
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Write the log to this file (default: /var/log/deploykit/cli-<timestamp>.log, or
+    /// tui-<timestamp>.log for the TUI, when running as root)
+    #[clap(long, global = true, env = settings::LOG_FILE_ENV)]
+    log_file: Option<PathBuf>,
+    /// Fetch the release manifest from this URL instead, e.g., from a local mirror
+    #[clap(long, global = true, env = settings::MANIFEST_ENV, value_parser = parse_url)]
+    manifest: Option<String>,
+    /// Connect through this proxy, e.g., http://proxy.example.com:8080
+    #[clap(long, global = true, env = settings::PROXY_ENV, value_parser = parse_proxy)]
+    proxy: Option<String>,
+    /// Print the settings with where each one comes from (an option, the environment, the
+    /// configuration file or the default), and exit
+    #[clap(long, global = true, action = clap::ArgAction::SetTrue)]
+    print_effective_config: bool,
+    /// The settings taken from the options and the environment, see `settings::collect`
+    #[clap(skip)]
+    settings: Vec<Setting>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -35,20 +169,109 @@ enum DeployKitCliCommand {
     Install(Box<InstallCommand>),
     /// List of mirror
     ListMirror(ListMirror),
+    /// List the mirrors with their locations, optionally ranked by a speed test
+    ListMirrors(ListMirrors),
+    /// Benchmark the mirrors one after another, repeating each test a few times
+    BenchMirrors(BenchMirrors),
     /// List of locale
     ListLocale(ListLocale),
     /// List of timezone
     ListTimezone(ListTimezone),
     /// List of tarball
     ListTarball(ListTarball),
+    /// List the variants with their releases, sizes and checksums
+    ListVariants(ListVariants),
+    /// List of supported boards
+    ListBoards(ListBoards),
+    /// List the disks with their models, serial numbers and sizes
+    ListDisks(ListDisks),
+    /// List the partitions with their filesystems, labels and mount points
+    ListPartitions(ListPartitions),
+    /// Check a configuration file for `install --config`, listing every problem at once
+    ValidateConfig(ValidateConfig),
+    /// Download and verify the system release only, e.g., to install from a USB drive later
+    Download(DownloadCommand),
+    /// Open a shell in an installed system
+    Chroot(ChrootCommand),
 }
 
 #[derive(Parser, Debug)]
-struct Tui;
+struct Tui {
+    /// Colours of the interface, `plain` has none for serial consoles (detected from TERM by default)
+    #[clap(long, value_enum)]
+    theme: Option<ThemeName>,
+    /// Show one screen at a time without colours or overlapping dialogs, for screen readers
+    /// (always on with TERM=dumb)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    accessible: bool,
+    /// Write the plan to an answer file (for `install --config`) once the summary is
+    /// reached, even if the installation is not started then
+    #[clap(long)]
+    export_config: Option<PathBuf>,
+}
 
 #[derive(Parser, Debug)]
 struct ListMirror;
 
+#[derive(Parser, Debug)]
+struct ListMirrors {
+    /// Test the download speed of each mirror and list them from the fastest
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    speedtest: bool,
+    /// Print the mirrors as JSON (an array of objects, with the raw results of --speedtest)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    json: bool,
+    /// Stop the speed test after this many seconds, the mirrors not tested by then are
+    /// listed as timed out
+    #[clap(long, requires = "speedtest")]
+    timeout: Option<u64>,
+    /// Also list (and test) the mirror at this URL, can be given more than once
+    #[clap(long = "add", value_name = "URL")]
+    extra: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct BenchMirrors {
+    /// Stop each test after downloading for this long, e.g., `10s` or `500ms`
+    #[clap(long, default_value = "10s", value_parser = mirror_bench::parse_duration)]
+    duration: Duration,
+    /// Stop each test after downloading this much, e.g., `16M` (the whole test file by
+    /// default)
+    #[clap(long, value_parser = mirror_bench::parse_size)]
+    size: Option<u64>,
+    /// How many times to test each mirror
+    #[clap(long, default_value_t = 3, value_parser = clap::value_parser!(u16).range(1..))]
+    repeat: u16,
+    /// Download no faster than this many bytes per second, e.g., `1M`, for metered
+    /// connections
+    #[clap(long, env = settings::LIMIT_RATE_ENV, value_parser = mirror_bench::parse_size)]
+    limit_rate: Option<u64>,
+    /// Print the results as JSON
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    json: bool,
+    /// Only test the mirror at this URL (from the manifest or not), can be given more than
+    /// once
+    #[clap(long = "mirror", value_name = "URL")]
+    mirrors: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+struct DownloadCommand {
+    /// The AOSC OS variant to download (e.g., Workstation, Server, Base)
+    #[clap(long, alias = "tarball", env = settings::VARIANT_ENV)]
+    variant: String,
+    /// Download from this mirror (e.g., one listed by `list-mirrors`)
+    #[clap(long, alias = "mirror-url", env = settings::MIRROR_ENV, default_value = DEFAULT_MIRROR)]
+    mirror: String,
+    /// Save the system release into this directory, with a `.json` file describing it next to
+    /// it
+    #[clap(long)]
+    output: PathBuf,
+    /// Only verify the system release already downloaded into the output directory
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    check_only: bool,
+}
+
 #[derive(Parser, Debug)]
 struct ListLocale;
 
@@ -58,56 +281,504 @@ struct ListTimezone;
 #[derive(Parser, Debug)]
 struct ListTarball;
 
+#[derive(Parser, Debug)]
+struct ListVariants {
+    /// Print the variants as JSON (an array of objects, one per release)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    json: bool,
+    /// List every release of each variant (the latest first), not only the latest one
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    all_dates: bool,
+    /// List the releases for this architecture (e.g., amd64, arm64, riscv64) instead of
+    /// the one of this device
+    #[clap(long)]
+    arch: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+struct ListBoards;
+
+#[derive(Parser, Debug)]
+struct ListDisks {
+    /// Print the disks (with their partitions) as JSON
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    json: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ListPartitions {
+    /// Print the partitions as JSON
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    json: bool,
+    /// List the partitions of this disk only (e.g., /dev/nvme0n1)
+    #[clap(long)]
+    disk: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+struct ValidateConfig {
+    /// The answer file, or a configuration saved by the TUI
+    path: PathBuf,
+    /// Look up the variant and the mirror, and check that the disks and partitions of the
+    /// answer file are on this machine and large enough, as `install` does (once there are
+    /// no other problems)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    check_hardware: bool,
+    /// Print the problems as JSON (with the key, line and column of each), for editors
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    json: bool,
+}
+
+#[derive(Parser, Debug)]
+struct ChrootCommand {
+    /// Root partition of the installed system (e.g., /dev/sda1)
+    partition: String,
+}
+
 #[derive(Parser, Debug)]
 struct InstallCommand {
+    /// Install as set in a configuration file without asking anything, either a TOML answer
+    /// file (see res/config-example.toml), whose answers the other options override, or a
+    /// configuration saved by the TUI
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Check everything and print what the installation would do (the partitions, the
+    /// fstab, the release and the commands run), without changing anything
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    dry_run: bool,
+    /// Print the plan of --dry-run as JSON
+    #[clap(long, requires = "dry_run", action = clap::ArgAction::SetTrue)]
+    json: bool,
+    /// Write the installation set by the other options to an answer file (for --config)
+    /// instead of installing, the passwords are stored as hashes
+    #[clap(long, conflicts_with_all = ["dry_run", "reboot", "wipe_on_cancel"])]
+    export_config: Option<PathBuf>,
+    /// Print the progress to stdout as one JSON object per line (see
+    /// res/json-progress.schema.json) and never ask anything, for wrapping the installer
+    #[clap(long, conflicts_with = "json", action = clap::ArgAction::SetTrue)]
+    json_progress: bool,
+    /// Continue the interrupted installation from its first step not completed, once its
+    /// partitions and release have been checked against the recorded ones
+    #[clap(long, conflicts_with_all = ["config", "dry_run", "export_config", "path", "target_dir"], action = clap::ArgAction::SetTrue)]
+    resume: bool,
+    /// Keep the state of the installation for --resume in this file (default:
+    /// /run/deploykit/state.json)
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+    /// Run the steps again from this one with --resume, even if it has completed (e.g.,
+    /// bootloader to repair the bootloader)
+    #[clap(long, requires = "resume", value_parser = parse_step)]
+    from_step: Option<&'static str>,
     /// Select AOSC OS variant to install (e.g., Workstation, Server, Base)
-    #[clap(long, default_value = "Base")]
+    #[clap(long, alias = "variant", env = settings::VARIANT_ENV, default_value = "Base")]
     tarball: String,
-    /// Set URL for download source
-    #[clap(long, default_value = "https://repo.aosc.io/aosc-os")]
+    /// Set URL for download source (e.g., one listed by `list-mirrors`)
+    #[clap(long, alias = "mirror-url", env = settings::MIRROR_ENV, default_value = DEFAULT_MIRROR)]
     mirror: String,
-    /// Set target partition to install AOSC OS to (e.g., /dev/sda1)
-    #[clap(long)]
-    path: String,
+    /// Set target partition to install AOSC OS to (e.g., /dev/sda1), or a whole disk to wipe
+    /// and partition automatically (e.g., /dev/vda)
+    #[clap(long, alias = "target")]
+    path: Option<String>,
+    /// Install into an existing (empty) directory instead, e.g., a container root, skipping
+    /// the partitioning, fstab and bootloader steps
+    #[clap(long, conflicts_with_all = ["path", "fs", "preserve_home", "board", "bootloader", "swap_size", "kernel_cmdline", "grub_timeout", "os_prober", "grub_hide_menu", "grub_gfxmode"])]
+    target_dir: Option<PathBuf>,
+    /// Format the target with this filesystem (default: ext4 on a whole disk, the current one on a partition)
+    #[clap(long, alias = "filesystem", conflicts_with = "preserve_home", value_parser = clap::builder::PossibleValuesParser::new(disks::ALLOWED_FS_TYPE))]
+    fs: Option<String>,
     /// Set name of the default user
     #[clap(long)]
-    user: String,
-    /// Set password for default user
+    user: Option<String>,
+    /// Set password for default user, either in plain text or as a yescrypt ($y$) or SHA-512 ($6$) hash
+    #[clap(long, env = "AOSCDK_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// Set password for default user as a yescrypt ($y$) or SHA-512 ($6$) hash
+    #[clap(long, env = "AOSCDK_PASSWORD_HASH", hide_env_values = true, conflicts_with = "password", value_parser = parse_password_hash)]
+    password_hash: Option<String>,
+    /// Set the yescrypt cost factor (1 - 11) for hashing the passwords
+    #[clap(long)]
+    password_cost: Option<u32>,
+    /// Set full name of the default user
+    #[clap(long)]
+    full_name: Option<String>,
+    /// Set UID of the default user (must be 1000 or above)
+    #[clap(long)]
+    uid: Option<u32>,
+    /// Set supplementary groups of the default user (e.g., wheel,audio,video)
+    #[clap(long, value_delimiter = ',')]
+    groups: Option<Vec<String>>,
+    /// Allow the default user to use sudo (or doas) without a password
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    sudo_nopasswd: bool,
+    /// Do not make the default user an administrator (no wheel group, no sudo)
+    #[clap(long, action = clap::ArgAction::SetTrue, conflicts_with = "sudo_nopasswd")]
+    no_admin: bool,
+    /// Set up the root account: `none` (leave as is), `locked` or a crypt(3) password hash
+    #[clap(long, env = "AOSCDK_ROOT_PASSWORD", hide_env_values = true, default_value = "none", value_parser = parse_root_password)]
+    root_password: RootPassword,
+    /// Enable the SSH server in the installed system
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    enable_sshd: bool,
+    /// Import SSH authorized keys for the default user from a key, file or URL (may be specified multiple times)
+    #[clap(long)]
+    ssh_key: Vec<String>,
+    /// Disable SSH password authentication (requires --ssh-key)
+    #[clap(long, requires = "ssh_key", action = clap::ArgAction::SetTrue)]
+    ssh_disable_password_auth: bool,
+    /// Do not copy the network configuration of the live session (including Wi-Fi passwords)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_copy_network: bool,
+    /// Do not re-hash the unpacked files against the file manifest of the release (faster)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_verify_files: bool,
+    /// Wipe the incomplete system from the target if the installation is cancelled (Ctrl-C) while unpacking
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    wipe_on_cancel: bool,
+    /// Reboot into the installed system once the installation is done, powering off the
+    /// installer medium first if the live system runs from the RAM
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    reboot: bool,
+    /// Install even if the release bulletin warns against it (for unattended installs), a
+    /// critical bulletin has to be acknowledged with `--acknowledge-bulletin` instead
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    ignore_bulletin: bool,
+    /// Acknowledge the critical release bulletin with this ID, printed along with it (for unattended installs)
+    #[clap(long)]
+    acknowledge_bulletin: Option<String>,
+    /// Format the target partition without asking, even if there is data on it (for unattended installs)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    allow_data_loss: bool,
+    /// Install additional packages in the installed system (e.g., vim,zsh)
+    #[clap(long, value_delimiter = ',')]
+    extra_package: Vec<String>,
+    /// Enable systemd units in the installed system (e.g., zramswap,fstrim.timer)
+    #[clap(long, value_delimiter = ',')]
+    enable_service: Vec<String>,
+    /// Disable systemd units in the installed system (e.g., bluetooth)
+    #[clap(long, value_delimiter = ',')]
+    disable_service: Vec<String>,
+    /// Append parameters to the kernel command line (e.g., "quiet splash")
+    #[clap(long)]
+    kernel_cmdline: Option<String>,
+    /// Set the GRUB menu timeout in seconds
+    #[clap(long)]
+    grub_timeout: Option<u32>,
+    /// Detect other operating systems with os-prober and add them to the GRUB menu
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    os_prober: bool,
+    /// Hide the GRUB menu unless a key is pressed
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    grub_hide_menu: bool,
+    /// Show a boot splash (default for desktop variants, requires Plymouth in the variant)
+    #[clap(long, action = clap::ArgAction::SetTrue, conflicts_with = "no_boot_splash")]
+    boot_splash: bool,
+    /// Show boot messages as plain text instead of a boot splash
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_boot_splash: bool,
+    /// Set up a larger console font and boot menu for a HiDPI display (detected from the display resolution by default)
+    #[clap(long, action = clap::ArgAction::SetTrue, conflicts_with = "no_hidpi")]
+    hidpi: bool,
+    /// Keep the default console font and boot menu resolution, even on a HiDPI display
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_hidpi: bool,
+    /// Set the console font used on HiDPI displays (default: ter-132n)
+    #[clap(long, conflicts_with = "no_hidpi")]
+    console_font: Option<String>,
+    /// Set the GRUB graphics mode (e.g., 1280x720,auto), HiDPI displays get a lower one by default
+    #[clap(long)]
+    grub_gfxmode: Option<String>,
+    /// Generate the machine ID during installation instead of on first boot
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    generate_machine_id: bool,
+    /// Set the keyboard layout, `<layout>` or `<layout>-<variant>` (e.g., de-nodeadkeys)
+    #[clap(long)]
+    keymap: Option<String>,
+    /// Set the default systemd target (default: graphical.target if a display manager is shipped)
+    #[clap(long, value_parser = clap::builder::PossibleValuesParser::new(install::DEFAULT_TARGETS))]
+    default_target: Option<String>,
+    /// Disable the display manager for headless use
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    disable_display_manager: bool,
+    /// Create no user account nor set the root password, the end user creates their account on first boot
+    #[clap(long, action = clap::ArgAction::SetTrue, conflicts_with_all = ["user", "password", "password_hash", "full_name", "uid", "groups", "no_admin", "sudo_nopasswd", "autologin", "shell", "ssh_key"])]
+    oobe: bool,
+    /// Log the default user in automatically on boot (anyone with physical access gets their session)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    autologin: bool,
+    /// Set the login shell of the default user (default: /bin/bash)
+    #[clap(long)]
+    shell: Option<String>,
+    /// Install the proprietary NVIDIA driver (requires network access)
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    nvidia_driver: bool,
+    /// Keep the package sources of the installed system as shipped, instead of pointing them at the mirror
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_mirror_repo: bool,
+    /// Write the bootloader for the board (arm64 and riscv64 only, see `list-boards`)
+    #[clap(long)]
+    board: Option<String>,
+    /// Set the bootloader: `grub`, or a board to write its bootloader for along with GRUB (as --board)
+    #[clap(long, conflicts_with = "board")]
+    bootloader: Option<String>,
+    /// Reinstall over the AOSC OS installation on the target partition, keeping /home
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    preserve_home: bool,
+    /// Do not re-create the users of the previous installation (with --preserve-home)
+    #[clap(long, action = clap::ArgAction::SetTrue, requires = "preserve_home")]
+    no_restore_users: bool,
+    /// Set device hostname (default: aosc-<variant>-<random>)
     #[clap(long)]
-    password: String,
-    /// Set device hostname
-    #[clap(long, default_value = "aosc")]
-    hostname: String,
-    /// Set default timezone
+    hostname: Option<String>,
+    /// Set default timezone (e.g., Asia/Shanghai)
     #[clap(long, default_value = "UTC")]
     timezone: String,
     /// Set default locale (affects display language, units, time/date format etc.)
     #[clap(long, default_value = "C.UTF-8")]
     locale: String,
+    /// Generate an additional locale besides the default one (may be specified multiple times)
+    #[clap(long)]
+    extra_locale: Vec<String>,
     /// Toggle using RTC (real time clock) time as local time
     #[clap(long, action = clap::ArgAction::SetTrue)]
     use_rtc: bool,
+    /// Do not enable network time synchronisation
+    #[clap(long, action = clap::ArgAction::SetTrue)]
+    no_ntp: bool,
+    /// Use a custom NTP server (may be specified multiple times)
+    #[clap(long, conflicts_with = "no_ntp")]
+    ntp_server: Vec<String>,
     /// Disable swapfile
     #[clap(long, conflicts_with = "swap_size", action = clap::ArgAction::SetTrue)]
     no_swap: bool,
     /// Set custom swapfile size
     #[clap(long, conflicts_with = "no_swap")]
     swap_size: Option<f64>,
+    /// The options of `ANSWER_OPTIONS` given on the command line or by the environment
+    #[clap(skip)]
+    given: Vec<String>,
+}
+
+impl InstallCommand {
+    fn is_given(&self, id: &str) -> bool {
+        self.given.iter().any(|x| x == id)
+    }
+
+    /// The answers in `missing` which have to be given, the user password may be left out
+    /// of an exported answer file
+    fn required<'a>(&self, missing: Vec<&'a str>) -> Vec<&'a str> {
+        missing
+            .into_iter()
+            .filter(|x| self.export_config.is_none() || *x != "user.password")
+            .collect()
+    }
+
+    /// The board given by --board, or by --bootloader unless it is `grub`
+    fn board(&self) -> Option<&str> {
+        self.board
+            .as_deref()
+            .or(self.bootloader.as_deref().filter(|x| *x != "grub"))
+    }
+}
+
+impl Args {
+    /// Parse the command line, noting which options of `install` are given, to override
+    /// the answer file with them only
+    pub fn parse_with_sources() -> Args {
+        let matches = Args::command().get_matches();
+        let mut args = Args::from_arg_matches(&matches)
+            .map_err(|e| e.format(&mut Args::command()))
+            .unwrap_or_else(|e| e.exit());
+        if let (Some(DeployKitCliCommand::Install(ic)), Some(("install", sub))) =
+            (args.subcommand.as_mut(), matches.subcommand())
+        {
+            ic.given = given_answers(sub);
+        }
+        args.settings = settings::collect(&matches);
+        // the TUI is started without arguments, with the settings of the environment
+        if std::env::args_os().len() < 2 {
+            args.subcommand = Some(DeployKitCliCommand::Tui(Tui {
+                theme: None,
+                accessible: false,
+                export_config: None,
+            }));
+        }
+
+        args
+    }
+
+    /// Whether the TUI is to be started, rather than a command run
+    pub fn is_tui(&self) -> bool {
+        matches!(self.subcommand, Some(DeployKitCliCommand::Tui(_)))
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        Verbosity::new(self.quiet, self.verbose)
+    }
+
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log_file.as_deref()
+    }
+}
+
+/// The options of `ANSWER_OPTIONS` in the matches of `install` which are not left at their
+/// defaults
+fn given_answers(matches: &ArgMatches) -> Vec<String> {
+    ANSWER_OPTIONS
+        .iter()
+        .filter(|x| {
+            matches!(
+                matches.value_source(x),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .map(|x| x.to_string())
+        .collect()
 }
 
 pub fn execute(args: Args) -> Result<()> {
-    match args.subcommand {
-        DeployKitCliCommand::Tui(Tui) => tui_main(),
+    if let Some(language) = args.language {
+        i18n::set_language(language);
+    }
+    if args.help_exit_codes {
+        print!("{}", ExitCode::table());
+        return Ok(());
+    }
+    let mut effective = args.settings;
+    if let Some(log_file) = effective
+        .iter_mut()
+        .find(|x| x.name == "log-file" && x.value.is_none())
+    {
+        log_file.value = crate::LOG_FILE.get().map(|x| x.display().to_string());
+    }
+    if args.print_effective_config {
+        print!("{}", settings::effective_config(&effective));
+        return Ok(());
+    }
+    for setting in effective.iter() {
+        info!("Effective setting: {}", setting);
+    }
+    if let Some(url) = args.manifest.as_deref() {
+        network::set_manifest_url(url);
+    }
+    if let Some(proxy) = args.proxy.as_deref() {
+        network::set_proxy(Some(proxy))?;
+    }
+    let Some(subcommand) = args.subcommand else {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required",
+            )
+            .exit();
+    };
+    match subcommand {
+        DeployKitCliCommand::Tui(Tui {
+            theme,
+            accessible,
+            export_config,
+        }) => start_tui(theme, accessible, export_config)?,
         DeployKitCliCommand::Install(ic) => start_install(*ic)?,
         DeployKitCliCommand::ListMirror(ListMirror) => list_mirror()?,
+        DeployKitCliCommand::ListMirrors(lm) => list_mirrors(lm)?,
+        DeployKitCliCommand::BenchMirrors(bm) => bench_mirrors(bm)?,
         DeployKitCliCommand::ListLocale(ListLocale) => list_locale()?,
         DeployKitCliCommand::ListTimezone(ListTimezone) => list_timezone()?,
         DeployKitCliCommand::ListTarball(ListTarball) => list_tarball()?,
+        DeployKitCliCommand::ListVariants(lv) => list_variants(lv)?,
+        DeployKitCliCommand::ListBoards(ListBoards) => list_boards()?,
+        DeployKitCliCommand::ListDisks(ld) => list_disks(ld)?,
+        DeployKitCliCommand::ListPartitions(lp) => list_partitions(lp)?,
+        DeployKitCliCommand::ValidateConfig(vc) => validate_config(vc)?,
+        DeployKitCliCommand::Download(dc) => download_release(dc)?,
+        DeployKitCliCommand::Chroot(cc) => chroot_into(cc)?,
+    }
+
+    Ok(())
+}
+
+/// Start the TUI, unless the kernel command line asks for an automatic installation with
+/// `dk.config=URL dk.auto`. Without `dk.auto`, the answer file is only what the wizard
+/// starts with. Whatever goes wrong is shown in the TUI, which never reboots by itself
+fn start_tui(
+    theme: Option<ThemeName>,
+    accessible: bool,
+    export_config: Option<PathBuf>,
+) -> Result<()> {
+    let boot = BootOptions::read();
+    if let Some(url) = boot.config.as_deref() {
+        info!(
+            "Configuration file given on the kernel command line: {}",
+            url
+        );
+        match boot_install(&boot) {
+            Ok(true) => return Ok(()),
+            Ok(false) => (),
+            Err(e) => {
+                error!("Failed to install by the kernel command line: {}", e);
+                tui::show_at_start(tr!(
+                    "Installer could not install AOSC OS as set on the kernel command line (dk.config={}):\n\n{}",
+                    url,
+                    e
+                ));
+            }
+        }
     }
+    tui_main(theme, accessible, export_config);
 
     Ok(())
 }
 
+/// Install with the answer file given on the kernel command line if `dk.auto` is set,
+/// returns whether it has, or preload the wizard with it
+fn boot_install(boot: &BootOptions) -> Result<bool> {
+    let path = boot.fetch_config()?;
+    let text = std::fs::read_to_string(&path)?;
+    let auto = boot.auto && cmdline::claim_auto_install();
+    if boot.auto && !auto {
+        info!("The automatic installation has been tried since boot, showing the TUI");
+    }
+    if !auto {
+        let (file, diagnostics) = validate::parse(&text);
+        if !diagnostics.is_empty() {
+            let report = Report::new(&path, &diagnostics).text();
+            warn!("Problems in the configuration file given on the kernel command line:\n{report}");
+            tui::show_at_start(tr!(
+                "Installer found problems in the configuration file given on the kernel command line (dk.config={}), the answers affected are left for the wizard to ask:\n\n{}",
+                boot.config.as_deref().unwrap_or_default(),
+                report.trim_end()
+            ));
+        }
+        if let Some(file) = file {
+            tui::preload(answers_of_file(&file));
+        }
+        return Ok(false);
+    }
+
+    boot.check_auto()?;
+    let diagnostics = answer_file_diagnostics(&text, true);
+    if !diagnostics.is_empty() {
+        return Err(anyhow!(
+            "{}",
+            Report::new(&path, &diagnostics).text().trim_end()
+        ));
+    }
+    let mut args = vec![
+        OsString::from("install"),
+        OsString::from("--config"),
+        path.into(),
+    ];
+    if boot.reboot {
+        args.push(OsString::from("--reboot"));
+    }
+    let matches = InstallCommand::command().try_get_matches_from(args)?;
+    let mut ic = InstallCommand::from_arg_matches(&matches)?;
+    ic.given = given_answers(&matches);
+    UNATTENDED.store(true, Ordering::SeqCst);
+    start_install(ic)?;
+
+    Ok(true)
+}
+
 fn list_mirror() -> Result<()> {
     let recipe = network::fetch_recipe()?;
     let mirrors = fetch_mirrors(&recipe);
@@ -118,6 +789,236 @@ fn list_mirror() -> Result<()> {
     Ok(())
 }
 
+/// A mirror as printed by `list-mirrors --json`, scripts depend on these fields
+#[derive(Serialize)]
+struct ListedMirror<'a> {
+    name: &'a str,
+    name_tr: &'a str,
+    location: &'a str,
+    location_tr: &'a str,
+    /// To be passed to `install --mirror-url`
+    url: &'a str,
+    /// Given with --add rather than listed in the manifest
+    local: bool,
+    /// `ok`, `failed`, `timed_out`, or none without --speedtest
+    status: Option<&'static str>,
+    /// Bytes per second
+    throughput: Option<f64>,
+    latency_ms: Option<u128>,
+}
+
+fn list_mirrors(lm: ListMirrors) -> Result<()> {
+    let recipe = network::fetch_recipe().map_err(|e| {
+        ExitCode::Network.wrap(anyhow!(tr!(
+            "Installer could not fetch the release manifest, please check your network connection: {}",
+            e
+        )))
+    })?;
+    let mut mirrors = fetch_mirrors(&recipe);
+    let mut local_urls = vec![];
+    for url in lm.extra.iter() {
+        let mirror = local_mirror(url)?;
+        local_urls.push(mirror.url.clone());
+        mirrors.push(mirror);
+    }
+
+    let results = if lm.speedtest {
+        let timeout = lm.timeout.map(Duration::from_secs);
+        let tests = test_mirror_speeds(&mirrors, timeout);
+        network::rank_mirrors(mirrors.iter().cloned().zip(tests).collect())
+    } else {
+        mirrors
+            .iter()
+            .cloned()
+            .map(|x| (x, MirrorTest::Queued))
+            .collect()
+    };
+    let listed = results
+        .iter()
+        .map(|(mirror, test)| {
+            let speed = match test {
+                MirrorTest::Done(speed) => Some(speed),
+                _ => None,
+            };
+            ListedMirror {
+                name: &mirror.name,
+                name_tr: &mirror.name_tr,
+                location: &mirror.loc,
+                location_tr: &mirror.loc_tr,
+                url: &mirror.url,
+                local: local_urls.contains(&mirror.url),
+                status: match test {
+                    MirrorTest::Queued | MirrorTest::Testing => None,
+                    MirrorTest::Done(_) => Some("ok"),
+                    MirrorTest::Failed => Some("failed"),
+                    MirrorTest::TimedOut => Some("timed_out"),
+                },
+                throughput: speed.map(|x| x.throughput),
+                latency_ms: speed.map(|x| x.latency.as_millis()),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if lm.json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+    for mirror in listed.iter() {
+        let name = format!(
+            "{} ({})",
+            i18n::localized(mirror.name, mirror.name_tr),
+            i18n::localized(mirror.location, mirror.location_tr)
+        );
+        if !lm.speedtest {
+            println!("{:<40}{}", name, mirror.url);
+            continue;
+        }
+        let speed = match (mirror.status, mirror.throughput, mirror.latency_ms) {
+            (Some("ok"), Some(throughput), Some(latency)) => {
+                format!("{:>9.2} MB/s {:>6} ms", throughput / 1_000_000.0, latency)
+            }
+            (Some("timed_out"), ..) => format!("{:>22}", tr!("timed out")),
+            _ => format!("{:>22}", tr!("unreachable")),
+        };
+        println!("{:<40}{}  {}", name, speed, mirror.url);
+    }
+
+    Ok(())
+}
+
+/// A mirror given by URL on the command line, named after its host
+fn local_mirror(url: &str) -> Result<Mirror> {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x.to_string()))
+        .ok_or_else(|| anyhow!(tr!("{} is not a valid mirror URL.", url)))?;
+    let loc = tr!("Local").to_string();
+
+    Ok(Mirror {
+        name: host.clone(),
+        name_tr: host,
+        loc: loc.clone(),
+        loc_tr: loc,
+        ..get_mirror(url)
+    })
+}
+
+/// Test the speed of the mirrors, giving up on the ones not tested after `timeout`
+fn test_mirror_speeds(mirrors: &[Mirror], timeout: Option<Duration>) -> Vec<MirrorTest> {
+    let tests = Arc::new(Mutex::new(vec![MirrorTest::Queued; mirrors.len()]));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let bar = if is_quiet() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    bar.enable_steady_tick(Duration::from_millis(50));
+    bar.set_message(tr!("{} of {} mirrors tested", 0, mirrors.len()));
+
+    thread::spawn({
+        let mirrors = mirrors.to_vec();
+        let tests = tests.clone();
+        let stop = stop.clone();
+        let bar = bar.clone();
+        move || {
+            network::speedtest_mirrors(&mirrors, &stop, |index, test| {
+                let mut tests = tests.lock().unwrap();
+                tests[index] = test;
+                bar.set_message(tr!(
+                    "{} of {} mirrors tested",
+                    tests.iter().filter(|x| x.is_finished()).count(),
+                    tests.len()
+                ));
+            });
+            tx.send(()).ok();
+        }
+    });
+    // the tests still running at the deadline are left behind, they end with the process
+    match timeout {
+        Some(timeout) => rx.recv_timeout(timeout).ok(),
+        None => rx.recv().ok(),
+    };
+    stop.store(true, Ordering::SeqCst);
+    bar.finish_and_clear();
+
+    let tests = tests.lock().unwrap();
+    tests
+        .iter()
+        .map(|x| {
+            if x.is_finished() {
+                *x
+            } else {
+                MirrorTest::TimedOut
+            }
+        })
+        .collect()
+}
+
+fn bench_mirrors(bm: BenchMirrors) -> Result<()> {
+    let recipe = network::fetch_recipe().map_err(|e| {
+        ExitCode::Network.wrap(anyhow!(tr!(
+            "Installer could not fetch the release manifest, please check your network connection: {}",
+            e
+        )))
+    })?;
+    let listed = fetch_mirrors(&recipe);
+    let mut mirrors = vec![];
+    let mut local_urls = vec![];
+    for url in bm.mirrors.iter() {
+        let known = listed
+            .iter()
+            .find(|x| x.url.trim_end_matches('/') == url.trim_end_matches('/'));
+        match known {
+            Some(mirror) => mirrors.push(mirror.clone()),
+            None => {
+                let mirror = local_mirror(url)?;
+                local_urls.push(mirror.url.clone());
+                mirrors.push(mirror);
+            }
+        }
+    }
+    if bm.mirrors.is_empty() {
+        mirrors = listed;
+    }
+
+    let repeat = bm.repeat as usize;
+    let limits = SpeedtestLimits {
+        size: bm.size,
+        duration: Some(bm.duration),
+        rate: bm.limit_rate,
+    };
+    let mut tests = vec![vec![]; mirrors.len()];
+    let bar = if is_quiet() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    bar.enable_steady_tick(Duration::from_millis(50));
+    let total = mirrors.len() * repeat;
+    let mut done = 0;
+    bar.set_message(tr!("{} of {} tests done", done, total));
+    // Ctrl-C stops after the test running, reporting the ones done so far
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_copy = stop.clone();
+    ctrlc::set_handler(move || stop_copy.store(true, Ordering::SeqCst))?;
+    network::bench_mirrors(&mirrors, repeat, &limits, &stop, |index, test| {
+        tests[index].push(test);
+        done += 1;
+        bar.set_message(tr!("{} of {} tests done", done, total));
+    });
+    bar.finish_and_clear();
+
+    let report = BenchReport::new(&mirrors, &local_urls, &tests, repeat, &limits);
+    if bm.json {
+        println!("{}", report.to_json()?);
+    } else {
+        print!("{}", report.table());
+    }
+
+    Ok(())
+}
+
 fn list_locale() -> Result<()> {
     let locale_list = install::get_locale_list()?;
     for (lang, locale, _) in locale_list {
@@ -145,93 +1046,693 @@ fn list_tarball() -> Result<()> {
     Ok(())
 }
 
-fn get_variant(tarball: &str) -> Result<VariantEntry> {
-    let variants = network::get_variants()?;
+/// A release as printed by `list-variants --json`, scripts depend on these fields
+#[derive(Serialize)]
+struct ListedVariant<'a> {
+    name: &'a str,
+    description: &'a str,
+    description_tr: &'a str,
+    arch: &'a str,
+    date: &'a str,
+    download_size: u64,
+    install_size: u64,
+    sha256sum: &'a str,
+    /// Relative to the mirror
+    path: &'a str,
+    /// On the default mirror
+    url: String,
+    manifest: Option<&'a str>,
+}
 
-    let index = variants
+fn list_variants(lv: ListVariants) -> Result<()> {
+    let arch = match lv.arch.as_deref() {
+        Some(arch) => arch,
+        None => network::get_arch_name()
+            .ok_or_else(|| ExitCode::UnsupportedArch.wrap(anyhow!("Unsupported architecture.")))?,
+    };
+    let recipe = network::fetch_recipe().map_err(|e| {
+        ExitCode::Network.wrap(anyhow!(tr!(
+            "Installer could not fetch the release manifest, please check your network connection: {}",
+            e
+        )))
+    })?;
+    let variants = network::find_variants(recipe, arch, lv.all_dates)?;
+    let mirror = get_mirror(DEFAULT_MIRROR);
+    let listed = variants
         .iter()
-        .position(|x| x.name.to_lowercase() == tarball.to_lowercase());
-    if let Some(index) = index {
-        return Ok(variants[index].to_owned());
+        .map(|x| ListedVariant {
+            name: &x.name,
+            description: &x.description,
+            description_tr: &x.description_tr,
+            arch,
+            date: &x.date,
+            download_size: x.size,
+            install_size: x.install_size,
+            sha256sum: &x.sha256sum,
+            path: &x.url,
+            url: format!("{}{}", mirror.url, x.url),
+            manifest: x.manifest.as_deref(),
+        })
+        .collect::<Vec<_>>();
+
+    if lv.json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+    for (i, variant) in listed.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{} ({})", variant.name, variant.date);
+        let description =
+            plain_description(i18n::localized(variant.description, variant.description_tr));
+        for line in description.lines().filter(|x| !x.is_empty()) {
+            println!("    {line}");
+        }
+        println!(
+            "    {}",
+            tr!(
+                "Download size: {}, installed size: {}",
+                human_size(variant.download_size),
+                human_size(variant.install_size)
+            )
+        );
+        println!("    SHA-256: {}", variant.sha256sum);
+        println!("    {}", variant.url);
     }
 
-    Err(anyhow!(
-        "Installer could not find tarball for specified variant {}.\nPlease refer to the `aoscdk-rs list-tarball` output for a list of available tarballs.",
-        tarball
-    ))
+    Ok(())
 }
 
-fn get_partition(path: &str, variant: &VariantEntry) -> Result<Partition> {
-    let required_size = variant.install_size + variant.size;
-    if cfg!(debug_assertions) {
-        disks::right_combine(Some(&PathBuf::from("/dev/loop30")))?;
+fn download_release(dc: DownloadCommand) -> Result<()> {
+    let recipe = network::fetch_recipe().map_err(|e| {
+        ExitCode::Network.wrap(anyhow!(tr!(
+            "Installer could not fetch the release manifest, please check your network connection: {}",
+            e
+        )))
+    })?;
+    let variant =
+        find_variant(recipe, &dc.variant).map_err(|e| ExitCode::InvalidConfig.fallback(e))?;
+    let mirror = get_mirror(&dc.mirror);
+    let url = format!("{}{}", mirror.url, variant.url);
+    let name = Path::new(&variant.url).file_name().ok_or_else(|| {
+        anyhow!(
+            "The release of {} has no file name: {}",
+            variant.name,
+            variant.url
+        )
+    })?;
+    let path = dc.output.join(name);
 
-        return Ok(Partition {
-            fs_type: Some("ext4".to_string()),
-            path: Some(PathBuf::from("/dev/loop30p1")),
-            parent_path: Some(PathBuf::from("/dev/loop30")),
-            size: 53687091200,
+    if !dc.check_only {
+        std::fs::create_dir_all(&dc.output)?;
+        let bar = if is_quiet() {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new_spinner()
+        };
+        bar.enable_steady_tick(Duration::from_millis(50));
+        let total = human_size(variant.size);
+        info!("Downloading {} to {}", url, path.display());
+        let result = network::download_release(&url, &path, variant.size, |received| {
+            bar.set_message(tr!(
+                "Downloading AOSC OS {}: {} of {}",
+                variant.name,
+                human_size(received),
+                total
+            ))
         });
+        bar.finish_and_clear();
+        result.map_err(|e| ExitCode::Network.wrap(e))?;
+    } else if !path.is_file() {
+        return Err(ExitCode::InvalidConfig.wrap(anyhow!(tr!("{} is not a file.", path.display()))));
     }
-    let path = Path::new(path);
-    let list_part = disks::list_partitions(None);
-    let index = list_part
-        .iter()
-        .position(|x| x.path == Some(path.to_path_buf()));
-    if let Some(index) = index {
-        let partition = list_part[index].to_owned();
-        if partition.size < required_size + variant.size {
-            let s = format!(
-                "The specified partition does not contain enough space to install AOSC OS release!\n\nAvailable space: {:.3}GiB\nRequired space: {:.3}GiB", 
-                partition.size as f32 / 1024.0 / 1024.0 / 1024.0,
-                required_size as f32 / 1024.0 / 1024.0 / 1024.0
-            );
-            return Err(anyhow!(s));
-        }
-        let partition = disks::fill_fs_type(&partition, false);
-        disks::right_combine(partition.parent_path.as_deref())?;
 
-        return Ok(partition);
+    print_summary(&format!("{}\n", tr!("Verifying the system release ...")));
+    let sha256 = network::file_sha256(&path)?;
+    if sha256 != variant.sha256sum {
+        if !dc.check_only {
+            // downloaded again from scratch next time
+            std::fs::remove_file(&path).ok();
+        }
+        return Err(ExitCode::Network.wrap(anyhow!(tr!(
+            "The checksum of {} is {}, instead of {} for AOSC OS {} ({}).",
+            path.display(),
+            sha256,
+            variant.sha256sum,
+            variant.name,
+            variant.date
+        ))));
     }
+    if !dc.check_only {
+        network::ReleaseSidecar::new(&variant, &url).write(&path)?;
+    }
+    print_summary(&format!(
+        "{}\n",
+        tr!(
+            "{} is AOSC OS {} ({}), verified.",
+            path.display(),
+            variant.name,
+            variant.date
+        )
+    ));
+    println!("{}", path.display());
 
-    Err(anyhow!(
-        "Installer could not find the specified partition: {}\nDid you partition your target disk?",
-        path.display()
-    ))
+    Ok(())
 }
 
-fn get_mirror(mirror: &str) -> Mirror {
-    let s = "cli_usage";
-    let mirror = if mirror.ends_with('/') {
-        mirror.to_string()
+fn list_disks(ld: ListDisks) -> Result<()> {
+    let disks = disks::list_disks(0);
+    let listing = DiskListing::new(&disks);
+    if ld.json {
+        println!("{}", listing.to_json()?);
     } else {
-        format!("{mirror}/")
-    };
+        print!("{}", listing.table());
+    }
 
-    Mirror {
-        name: s.to_string(),
-        name_tr: s.to_string(),
-        loc: s.to_string(),
-        loc_tr: s.to_string(),
-        url: mirror,
+    Ok(())
+}
+
+fn list_partitions(lp: ListPartitions) -> Result<()> {
+    let disks = disks::list_disks(0);
+    let disk = lp.disk.as_deref().map(disks::resolve_device_link);
+    if let Some(disk) = &disk {
+        if !disks.iter().any(|x| x.device.path == *disk) {
+            return Err(anyhow!(tr!(
+                "Installer could not find disk {}.",
+                disk.display()
+            )));
+        }
+    }
+    let listing = PartitionListing::new(&disks, disk.as_deref());
+    if lp.json {
+        println!("{}", listing.to_json()?);
+    } else {
+        print!("{}", listing.table());
     }
+
+    Ok(())
 }
 
-fn get_swap(
-    swap_size: Option<f64>,
-    partition: &Partition,
-    variant: &VariantEntry,
-) -> Result<(bool, f64, bool)> {
-    let result = if let Some(swap_size) = swap_size {
-        let size = swap_size * 1024.0 * 1024.0 * 1024.0;
-        let is_hibernation = disks::is_enable_hibernation(size)?;
+fn list_boards() -> Result<()> {
+    for i in boards::list_boards()? {
+        println!("{:<20}{}", i.name, i.description);
+    }
 
-        (true, size, is_hibernation)
+    Ok(())
+}
+
+fn validate_config(vc: ValidateConfig) -> Result<()> {
+    let path = vc.path.as_path();
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        ExitCode::InvalidConfig.wrap(anyhow!(
+            "Installer failed to read the configuration file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let diagnostics = if config_file::is_saved_config(&text) {
+        saved_config_diagnostics(&text)
     } else {
-        let mem = sysinfo::System::new_all().total_memory();
-        let size = disks::get_recommend_swap_size(mem)?;
+        answer_file_diagnostics(&text, vc.check_hardware)
+    };
 
-        if partition.size as f64
+    let report = Report::new(path, &diagnostics);
+    if vc.json {
+        println!("{}", report.to_json()?);
+    } else {
+        eprint!("{}", report.text());
+    }
+    if !diagnostics.is_empty() {
+        return Err(ExitCode::InvalidConfig.wrap(anyhow!(tr!(
+            "{} problems were found in {}.",
+            diagnostics.len(),
+            path.display()
+        ))));
+    }
+    if !vc.json {
+        println!("{}", tr!("{} is valid.", path.display()));
+    }
+
+    Ok(())
+}
+
+/// Everything `install --config` would refuse in the answer file `text`, not only the first
+fn answer_file_diagnostics(text: &str, check_hardware: bool) -> Vec<Diagnostic> {
+    let (file, mut diagnostics) = validate::parse(text);
+    let Some(file) = file else {
+        return diagnostics;
+    };
+
+    let missing = file.missing();
+    for key in missing.iter() {
+        diagnostics.push(Diagnostic::new(
+            text,
+            Some(key),
+            format!("`{key}` is required."),
+        ));
+    }
+    for e in file.problems() {
+        let message = e.to_string();
+        let key = validate::key_of(&message);
+        // the target left out is refused as not set once, already
+        if key == Some("target") && missing.contains(&"target") {
+            continue;
+        }
+        diagnostics.push(Diagnostic::new(text, key, &message));
+    }
+    // the lookups take the answers to be complete and consistent, as `install` does
+    let consistent = diagnostics.is_empty();
+
+    let config = answers_of_file(&file);
+    let mut problems = answer_problems(&config);
+    if let Err(e) = check_config_file_passwords(&config) {
+        problems.push(("user.password", e));
+    }
+    if let Err(e) = check_timezone(file.region.timezone.as_deref().unwrap_or("UTC")) {
+        problems.push(("region.timezone", e));
+    }
+    if let Err(e) = check_root_account(&config) {
+        problems.push(("root_password", e));
+    }
+    if check_hardware && consistent {
+        problems.extend(lookup_problems(&file));
+    }
+    for (key, e) in problems {
+        diagnostics.push(Diagnostic::new(text, Some(key), e));
+    }
+
+    diagnostics
+}
+
+/// What `install --config` would refuse in the configuration saved by the TUI `text`,
+/// before looking anything up
+fn saved_config_diagnostics(text: &str) -> Vec<Diagnostic> {
+    let config = match serde_json::from_str::<InstallConfig>(text) {
+        Ok(config) => config,
+        Err(e) => return vec![Diagnostic::unplaced(e)],
+    };
+    let mut diagnostics = answer_problems(&config)
+        .into_iter()
+        .map(|(_, e)| Diagnostic::unplaced(e))
+        .collect::<Vec<_>>();
+    diagnostics.extend(
+        check_config_file_passwords(&config)
+            .err()
+            .map(Diagnostic::unplaced),
+    );
+    diagnostics.extend(check_root_account(&config).err().map(Diagnostic::unplaced));
+
+    diagnostics
+}
+
+/// What `install --config` would refuse once it has looked up the variant, the mirror and
+/// the target of `file` on this machine
+fn lookup_problems(file: &ConfigFile) -> Vec<(&'static str, anyhow::Error)> {
+    let variant = file.variant.as_deref().unwrap_or_default();
+    // the bulletin is shown by `install`, whose options acknowledge it
+    let variant = match get_variant(variant, true, None) {
+        Ok(variant) => variant,
+        Err(e) => return vec![("variant", e)],
+    };
+    let mut problems = vec![];
+    if let Err(e) = find_mirror(file.mirror.as_deref().unwrap_or(DEFAULT_MIRROR)) {
+        problems.push(("mirror", e));
+    }
+
+    let target = &file.target;
+    let partition = match target.mode() {
+        Ok(TargetMode::Disk(path)) => {
+            let keep_free =
+                (target.keep_free_space.unwrap_or(0.0) * 1024.0 * 1024.0 * 1024.0) as u64;
+            get_disk(path, true, false)
+                .and_then(|x| {
+                    guided_partition(&x, keep_free, target.filesystem.as_deref(), &variant)
+                })
+                .map(Some)
+                .map_err(|e| ("target.disk", e))
+        }
+        Ok(TargetMode::Partition(path)) => {
+            get_partition(&path.to_string_lossy(), &variant, true, false)
+                .map(Some)
+                .map_err(|e| ("target.partition", e))
+        }
+        Ok(TargetMode::Directory(dir)) => check_target_dir(dir, &variant)
+            .map(|_| None)
+            .map_err(|e| ("target.directory", e)),
+        // refused by `ConfigFile::problems`, before looking anything up
+        Err(_) => Ok(None),
+    };
+    let partition = partition.unwrap_or_else(|problem| {
+        problems.push(problem);
+        None
+    });
+    if let Some(partition) = partition.as_ref() {
+        let format = !target.preserve_home.unwrap_or(false);
+        if !target.mounts.is_empty() {
+            if let Err(e) = get_mounts(partition, format, &target.mounts) {
+                problems.push(("target.mounts", e));
+            }
+        }
+        if let Err(e) = get_swap_from_file(&file.swap, Some(partition), &variant) {
+            problems.push(("swap", e));
+        }
+    }
+
+    problems
+}
+
+fn chroot_into(cc: ChrootCommand) -> Result<()> {
+    let path = PathBuf::from(&cc.partition);
+    let partition = disks::list_partitions(None)
+        .into_iter()
+        .find(|x| x.path.as_ref() == Some(&path))
+        .ok_or_else(|| anyhow!("Installer could not find partition {}.", cc.partition))?;
+
+    let tempdir = tempfile::Builder::new()
+        .prefix(".dkmount")
+        .tempdir()?
+        .into_path();
+    install::mount_root_path(&partition, &tempdir)?;
+
+    if disks::is_efi_booted() {
+        if let Some(esp) = partition
+            .parent_path
+            .as_ref()
+            .and_then(|x| disks::find_esp_partition(x).ok())
+        {
+            install::mount_root_path(&esp, &tempdir.join("efi")).ok();
+        }
+    }
+
+    let result = install::setup_bind_mounts(&tempdir).and_then(|_| {
+        println!(
+            "{}",
+            tr!(
+                "Exit the shell (command prompt) to unmount {}.",
+                cc.partition
+            )
+        );
+        install::open_guest_shell(&tempdir)
+    });
+
+    if install::unmount_recursively(&tempdir)? == UnmountStatus::Lazy {
+        println!("{}", tr!(LAZY_UNMOUNT_INFO));
+    }
+
+    result
+}
+
+fn parse_language(s: &str) -> Result<Language> {
+    Language::from_code(s).ok_or_else(|| anyhow!("expected `en` or `zh`"))
+}
+
+fn parse_step(s: &str) -> Result<&'static str> {
+    resume::step_by_name(s)
+        .ok_or_else(|| anyhow!("expected one of {}", resume::step_names().join(", ")))
+}
+
+fn parse_url(s: &str) -> Result<String> {
+    reqwest::Url::parse(s).map_err(|_| {
+        anyhow!("expected a URL such as `https://releases.aosc.io/manifest/recipe.json`")
+    })?;
+
+    Ok(s.to_string())
+}
+
+fn parse_proxy(s: &str) -> Result<String> {
+    reqwest::Proxy::all(s)
+        .map_err(|_| anyhow!("expected a proxy URL such as `http://proxy.example.com:8080`"))?;
+
+    Ok(s.to_string())
+}
+
+fn parse_password_hash(s: &str) -> Result<String> {
+    if !install::is_password_hash(s) {
+        return Err(anyhow!("expected a yescrypt ($y$) or SHA-512 ($6$) hash"));
+    }
+
+    Ok(s.to_string())
+}
+
+fn parse_root_password(s: &str) -> Result<RootPassword> {
+    match RootPassword::try_from(s.to_string()) {
+        Ok(RootPassword::Password(_)) | Err(_) => Err(anyhow!(
+            "expected `none`, `locked` or a yescrypt ($y$) or SHA-512 ($6$) hash"
+        )),
+        Ok(p) => Ok(p),
+    }
+}
+
+fn get_variant(
+    tarball: &str,
+    ignore_bulletin: bool,
+    acknowledged: Option<&str>,
+) -> Result<VariantEntry> {
+    let recipe = network::fetch_recipe()?;
+    check_bulletin(&recipe.bulletin, ignore_bulletin, acknowledged)?;
+
+    find_variant(recipe, tarball)
+}
+
+/// The latest release of the variant named `tarball` (in any case) for this device
+fn find_variant(recipe: network::Recipe, tarball: &str) -> Result<VariantEntry> {
+    let variants = network::find_variant_candidates(recipe)?;
+
+    let index = variants
+        .iter()
+        .position(|x| x.name.to_lowercase() == tarball.to_lowercase());
+    if let Some(index) = index {
+        return Ok(variants[index].to_owned());
+    }
+
+    Err(anyhow!(tr!(
+        "Installer could not find tarball for specified variant {}.\nPlease refer to the `aoscdk-rs list-tarball` output for a list of available tarballs.",
+        tarball
+    )))
+}
+
+/// A critical release bulletin stopped an unattended installation
+#[derive(Debug)]
+pub struct BulletinNotAcknowledged(String);
+
+impl std::fmt::Display for BulletinNotAcknowledged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            tr!(
+                "The release bulletin above is critical, use --acknowledge-bulletin {} to install anyway.",
+                self.0
+            )
+        )
+    }
+}
+
+impl std::error::Error for BulletinNotAcknowledged {}
+
+/// Print the release bulletin, a warning has to be confirmed before installing (or ignored
+/// with `--ignore-bulletin`), a critical one acknowledged by its ID when not interactive
+fn check_bulletin(
+    bulletin: &network::Bulletin,
+    ignore_bulletin: bool,
+    acknowledged: Option<&str>,
+) -> Result<()> {
+    if bulletin.is_empty() {
+        return Ok(());
+    }
+    let level = bulletin.level();
+    let id = bulletin.id();
+    eprintln!(
+        "{}\n\n{}\n",
+        i18n::localized(&bulletin.title, &bulletin.title_tr),
+        i18n::localized(&bulletin.body, &bulletin.body_tr)
+    );
+    if level == BulletinLevel::Critical {
+        eprintln!("{}\n", tr!("Bulletin ID: {}", id));
+    }
+    if !bulletin.needs_acknowledgement() {
+        return Ok(());
+    }
+    let acknowledge = || {
+        info!(
+            "Release bulletin {} ({}) acknowledged: {}",
+            id, bulletin.type_, bulletin.title
+        );
+    };
+    if level == BulletinLevel::Critical && acknowledged.map(str::trim) == Some(id.as_str()) {
+        acknowledge();
+        return Ok(());
+    }
+    if level != BulletinLevel::Critical && ignore_bulletin {
+        return Ok(());
+    }
+    if !is_interactive() {
+        json_progress::emit(&Event::Prompt {
+            prompt: "bulletin",
+            question: tr!("Install anyway? [y/N] ").trim_end(),
+            answer: "no",
+        });
+        if level == BulletinLevel::Critical {
+            return Err(ExitCode::BulletinNotAcknowledged.wrap(BulletinNotAcknowledged(id).into()));
+        }
+        return Err(anyhow!(tr!(
+            "The release bulletin above warns against installing now, use --ignore-bulletin to install anyway."
+        )));
+    }
+
+    eprint!("{}", tr!("Install anyway? [y/N] "));
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return Err(ExitCode::UserAbort.wrap(anyhow!(tr!("The installation has been cancelled."))));
+    }
+    acknowledge();
+
+    Ok(())
+}
+
+/// Print what is on the target partition, erasing it has to be confirmed (or allowed with
+/// `--allow-data-loss`)
+fn check_data_loss(partition: &Partition, allow_data_loss: bool) -> Result<()> {
+    let Some(warning) = data_loss_warning(partition, true) else {
+        return Ok(());
+    };
+    eprintln!("{warning}\n");
+    if allow_data_loss {
+        return Ok(());
+    }
+    if !is_interactive() {
+        json_progress::emit(&Event::Prompt {
+            prompt: "data_loss",
+            question: tr!("Erase it and install? [y/N] ").trim_end(),
+            answer: "no",
+        });
+        return Err(anyhow!(tr!(
+            "The target partition holds the data above, use --allow-data-loss to erase it anyway."
+        )));
+    }
+
+    eprint!("{}", tr!("Erase it and install? [y/N] "));
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    if !answer.trim().eq_ignore_ascii_case("y") {
+        return Err(ExitCode::UserAbort.wrap(anyhow!(tr!("The installation has been cancelled."))));
+    }
+
+    Ok(())
+}
+
+/// The partition at `path` to install to, erasing the data on it is confirmed unless
+/// `allow_data_loss`, or left to the plan in a `dry_run`
+fn get_partition(
+    path: &str,
+    variant: &VariantEntry,
+    allow_data_loss: bool,
+    dry_run: bool,
+) -> Result<Partition> {
+    let required_size = variant.install_size + variant.size;
+    if cfg!(debug_assertions) {
+        disks::right_combine(Some(&PathBuf::from("/dev/loop30")))?;
+
+        return Ok(Partition {
+            fs_type: Some("ext4".to_string()),
+            path: Some(PathBuf::from("/dev/loop30p1")),
+            parent_path: Some(PathBuf::from("/dev/loop30")),
+            size: 53687091200,
+        });
+    }
+    let path = Path::new(path);
+    let list_part = disks::list_partitions(None);
+    let index = list_part
+        .iter()
+        .position(|x| x.path == Some(path.to_path_buf()));
+    if let Some(index) = index {
+        let partition = list_part[index].to_owned();
+        if partition.size < required_size + variant.size {
+            return Err(ExitCode::InsufficientSpace
+                .wrap(anyhow!(not_enough_space_msg(partition.size, required_size))));
+        }
+        if !dry_run {
+            check_data_loss(&partition, allow_data_loss)?;
+        }
+        let partition = disks::fill_fs_type(&partition, false);
+        disks::right_combine(partition.parent_path.as_deref())?;
+
+        return Ok(partition);
+    }
+
+    Err(anyhow!(tr!(
+        "Installer could not find the specified partition: {}\nDid you partition your target disk?",
+        path.display()
+    )))
+}
+
+fn check_target_dir(path: &Path, variant: &VariantEntry) -> Result<()> {
+    if !path.is_dir() {
+        return Err(anyhow!(tr!(
+            "The specified target directory {} does not exist.",
+            path.display()
+        )));
+    }
+    if std::fs::read_dir(path)?
+        .flatten()
+        .any(|x| x.file_name() != "lost+found")
+    {
+        return Err(anyhow!(tr!(
+            "The specified target directory {} is not empty.",
+            path.display()
+        )));
+    }
+
+    // the tarball is downloaded into the target directory as well
+    let required_size = variant.install_size + variant.size;
+    let stat = rustix::fs::statvfs(path)?;
+    let available = stat.f_bavail * stat.f_frsize;
+    if available < required_size {
+        return Err(ExitCode::InsufficientSpace.wrap(anyhow!(tr!(
+            "The specified target directory does not contain enough space to install AOSC OS release!\n\nAvailable space: {}GiB\nRequired space: {}GiB",
+            format!("{:.3}", available as f32 / 1024.0 / 1024.0 / 1024.0),
+            format!("{:.3}", required_size as f32 / 1024.0 / 1024.0 / 1024.0)
+        ))));
+    }
+
+    Ok(())
+}
+
+fn get_mirror(mirror: &str) -> Mirror {
+    let s = "cli_usage";
+    let mirror = if mirror.ends_with('/') {
+        mirror.to_string()
+    } else {
+        format!("{mirror}/")
+    };
+
+    Mirror {
+        name: s.to_string(),
+        name_tr: s.to_string(),
+        loc: s.to_string(),
+        loc_tr: s.to_string(),
+        url: mirror,
+        repo_url: None,
+    }
+}
+
+fn get_swap(
+    swap_size: Option<f64>,
+    partition: &Partition,
+    variant: &VariantEntry,
+) -> Result<(bool, f64, bool)> {
+    let result = if let Some(swap_size) = swap_size {
+        let size = swap_size * 1024.0 * 1024.0 * 1024.0;
+        let is_hibernation = disks::is_enable_hibernation(size)?;
+
+        (true, size, is_hibernation)
+    } else {
+        let mem = sysinfo::System::new_all().total_memory();
+        let size = disks::get_recommend_swap_size(mem)?;
+
+        if partition.size as f64
             > size + variant.install_size as f64 + variant.size as f64 - DEFAULT_EMPTY_SIZE as f64
         {
             (true, size, true)
@@ -243,92 +1744,1523 @@ fn get_swap(
     Ok(result)
 }
 
-fn start_install(ic: InstallCommand) -> Result<()> {
-    let running = Arc::new(AtomicBool::new(true));
-    let r = running.clone();
-    let variant = get_variant(&ic.tarball)?;
-    let partition = get_partition(&ic.path, &variant)?;
-    let mirror = get_mirror(&ic.mirror);
-    let tc = if ic.use_rtc { "RTC" } else { "UTC" };
-    let (use_swap, swap_size, is_hibernation) = get_swap(ic.swap_size, &partition, &variant)?;
+fn start_install(ic: InstallCommand) -> Result<()> {
+    if !ic.json_progress {
+        return install_as_commanded(ic);
+    }
+
+    json_progress::enable();
+    json_progress::emit(&Event::Started {
+        version: json_progress::SCHEMA_VERSION,
+        dry_run: ic.dry_run,
+    });
+    let result = install_as_commanded(ic);
+    json_progress::emit(&Event::Result {
+        success: result.is_ok(),
+        exit_code: result
+            .as_ref()
+            .map_or(ExitCode::Success, ExitCode::of_error)
+            .code(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        log: crate::LOG_FILE.get().map(|x| x.as_path()),
+    });
+
+    result
+}
+
+fn install_as_commanded(ic: InstallCommand) -> Result<()> {
+    let (dry_run, json, wipe_on_cancel, reboot) =
+        (ic.dry_run, ic.json, ic.wipe_on_cancel, ic.reboot);
+    if let Some(path) = ic.state_file.as_deref() {
+        resume::set_state_file(path);
+    }
+    if ic.resume {
+        return resume_install(ic.from_step, wipe_on_cancel, reboot);
+    }
+    let export_path = ic.export_config.clone();
+    // what stops the installation from starting is otherwise taken as a mistake in the
+    // options or the configuration file
+    let install_config = command_config(ic).map_err(|e| ExitCode::InvalidConfig.fallback(e))?;
+    if let Some(path) = export_path {
+        return export_config(&install_config, &path);
+    }
+    if dry_run {
+        return print_plan(&install_config, json);
+    }
+
+    run_install(install_config, wipe_on_cancel, reboot, None)
+}
+
+/// Continue the interrupted installation, from `from_step` if set
+fn resume_install(
+    from_step: Option<&'static str>,
+    wipe_on_cancel: bool,
+    reboot: bool,
+) -> Result<()> {
+    let resumption = resume::load(from_step).map_err(|e| ExitCode::InvalidConfig.fallback(e))?;
+    for step in resumption.steps.iter().filter(|x| !resumption.runs(x)) {
+        let line = if resumption.completed.contains(step) {
+            tr!("Completed: {}", tr!(step))
+        } else {
+            tr!("Skipped: {}", tr!(step))
+        };
+        print_summary(&format!("{line}\n"));
+    }
+    print_summary(&format!(
+        "{}\n",
+        tr!("Resuming the installation from: {}", tr!(resumption.from))
+    ));
+
+    run_install(
+        resumption.config.clone(),
+        wipe_on_cancel,
+        reboot,
+        Some(resumption),
+    )
+}
+
+/// The installation set by the options, or by the configuration file they name
+fn command_config(ic: InstallCommand) -> Result<InstallConfig> {
+    if let Some(path) = ic.config.as_ref() {
+        return match config_file::read(path)? {
+            ConfigSource::File(mut file) => {
+                override_config_file(&mut file, &ic)?;
+                file.resolve_device_links();
+                check_missing(&ic.required(file.missing()))?;
+                file.check()
+                    .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+                config_from_file(*file, &ic)
+            }
+            ConfigSource::Saved(config) => {
+                let given = ANSWER_OPTIONS
+                    .iter()
+                    .filter(|x| ic.is_given(x))
+                    .map(|x| option_name(x))
+                    .collect::<Vec<_>>();
+                if !given.is_empty() {
+                    return Err(anyhow!(
+                        "{}: only an answer file can be overridden by options, not a configuration saved by the TUI.",
+                        given.join(", ")
+                    ));
+                }
+                check_saved_config(*config, &ic)
+            }
+        };
+    }
+
+    let path = ic.path.as_ref().map(|x| {
+        disks::resolve_device_link(Path::new(x))
+            .to_string_lossy()
+            .to_string()
+    });
+    let mut missing = vec![];
+    if path.is_none() && ic.target_dir.is_none() {
+        missing.push("target");
+    }
+    if !ic.oobe {
+        if ic.user.is_none() {
+            missing.push("user");
+        }
+        if ic.password.is_none() && ic.password_hash.is_none() {
+            missing.push("user.password");
+        }
+    }
+    check_missing(&ic.required(missing))?;
+
+    let variant = get_variant(
+        &ic.tarball,
+        ic.ignore_bulletin,
+        ic.acknowledge_bulletin.as_deref(),
+    )?;
+    // a whole disk is wiped and partitioned as in the guided mode
+    let disk = match path.as_deref() {
+        Some(path) if is_disk(Path::new(path)) => {
+            if ic.preserve_home {
+                return Err(anyhow!(
+                    "--preserve-home needs a partition as the target, not a whole disk."
+                ));
+            }
+            Some(get_disk(Path::new(path), ic.allow_data_loss, ic.dry_run)?)
+        }
+        _ => None,
+    };
+    let partition = match (ic.target_dir.as_ref(), disk.as_ref()) {
+        (Some(dir), _) => {
+            check_target_dir(dir, &variant)?;
+            None
+        }
+        (None, Some(device)) => Some(guided_partition(device, 0, ic.fs.as_deref(), &variant)?),
+        (None, None) => {
+            let mut partition = get_partition(
+                path.as_ref().unwrap(),
+                &variant,
+                ic.allow_data_loss || ic.preserve_home,
+                ic.dry_run,
+            )?;
+            if let Some(fs_type) = ic.fs.as_ref() {
+                partition.fs_type = Some(fs_type.clone());
+            }
+            Some(partition)
+        }
+    };
+
+    if let Some(partition) = partition.as_ref().filter(|_| ic.preserve_home) {
+        print_reinstall_summary(path.as_ref().unwrap(), partition, !ic.no_restore_users)?;
+    }
+
+    let board = ic.board().map(|x| x.to_string());
+    if let (Some(board), Some(partition)) = (board.as_ref(), partition.as_ref()) {
+        print_board_actions(board, partition)?;
+    }
+    let mirror = get_mirror(&ic.mirror);
+    print_summary(&package_repo_summary(&mirror, !ic.no_mirror_repo));
+    let tc = if ic.use_rtc { "RTC" } else { "UTC" };
+    let (use_swap, swap_size, is_hibernation) = match partition.as_ref() {
+        Some(partition) => get_swap(ic.swap_size, partition, &variant)?,
+        // a swapfile makes no sense in a container
+        None => (false, 0.0, false),
+    };
+
+    let hostname = ic
+        .hostname
+        .unwrap_or_else(|| install::gen_default_hostname(&variant.name));
+    let ssh_keys = resolve_all_ssh_keys(&ic.ssh_key)?;
+    let timezone = check_timezone(&ic.timezone)?;
+
+    let storage_mode = if disk.is_some() {
+        StorageMode::Guided
+    } else {
+        StorageMode::Advanced
+    };
+
+    let install_config = InstallConfig {
+        variant: Some(Arc::new(variant)),
+        partition: partition.map(Arc::new),
+        keep_free_space: disk.is_some().then_some(0),
+        wipe_disk: disk.map(Arc::new),
+        mirror: Some(Arc::new(mirror)),
+        user: ic.user.map(Arc::new),
+        full_name: ic.full_name.map(Arc::new),
+        uid: ic.uid,
+        groups: ic.groups.map(Arc::new),
+        admin: Some(!ic.no_admin),
+        sudo_nopasswd: Some(ic.sudo_nopasswd),
+        password: ic.password_hash.or(ic.password).map(Arc::new),
+        hostname: Some(hostname),
+        locale: Some(Arc::new(ic.locale)),
+        extra_locales: Some(Arc::new(ic.extra_locale)),
+        timezone: Some(Arc::new(timezone)),
+        tc: Some(Arc::new(tc.to_string())),
+        enable_ntp: Some(!ic.no_ntp),
+        enable_sshd: Some(ic.enable_sshd),
+        enable_services: Some(Arc::new(ic.enable_service)),
+        disable_services: Some(Arc::new(ic.disable_service)),
+        ssh_keys: Some(Arc::new(ssh_keys)),
+        ssh_disable_password_auth: Some(ic.ssh_disable_password_auth),
+        copy_network_config: Some(!ic.no_copy_network),
+        extra_packages: Some(Arc::new(ic.extra_package)),
+        kernel_cmdline: ic.kernel_cmdline.map(Arc::new),
+        grub_timeout: ic.grub_timeout,
+        grub_os_prober: ic.os_prober.then_some(true),
+        grub_hidden_menu: ic.grub_hide_menu.then_some(true),
+        boot_splash: if ic.boot_splash {
+            Some(true)
+        } else {
+            ic.no_boot_splash.then_some(false)
+        },
+        hidpi: if ic.hidpi {
+            Some(true)
+        } else {
+            ic.no_hidpi.then_some(false)
+        },
+        console_font: ic.console_font.map(Arc::new),
+        grub_gfxmode: ic.grub_gfxmode.map(Arc::new),
+        generate_machine_id: Some(ic.generate_machine_id),
+        keymap: ic.keymap.map(Arc::new),
+        default_target: ic.default_target.map(Arc::new),
+        disable_display_manager: Some(ic.disable_display_manager),
+        autologin: Some(ic.autologin),
+        oobe: Some(ic.oobe),
+        shell: ic.shell.map(Arc::new),
+        nvidia_driver: Some(ic.nvidia_driver),
+        use_mirror_repo: Some(!ic.no_mirror_repo),
+        board: board.map(Arc::new),
+        preserve_home: Some(ic.preserve_home),
+        restore_users: Some(ic.preserve_home && !ic.no_restore_users),
+        verify_files: Some(!ic.no_verify_files),
+        target_dir: ic.target_dir.clone(),
+        ntp_servers: Some(Arc::new(ic.ntp_server)),
+        swap: Some(if !ic.no_swap && use_swap {
+            SwapKind::File
+        } else {
+            SwapKind::None
+        }),
+        swap_partition: None,
+        use_swap: Arc::new(AtomicBoolWrapper {
+            v: AtomicBool::new(!ic.no_swap && use_swap),
+        }),
+        swap_size: Arc::new(Some(swap_size)),
+        is_hibernation: Arc::new(AtomicBoolWrapper {
+            v: AtomicBool::new(is_hibernation),
+        }),
+        root_password: Some(Arc::new(ic.root_password)),
+        password_cost: ic.password_cost,
+        allow_plaintext_password: None,
+        allow_data_loss: Some(ic.allow_data_loss),
+        storage_mode: Some(storage_mode),
+        mounts: None,
+        encrypt: None,
+        encryption_passphrase: None,
+        encrypt_discard: None,
+        local_release: None,
+        hooks: None,
+        bulletin: None,
+        back_to_summary: false,
+    };
+
+    check_answers(&install_config)?;
+    check_root_account(&install_config)?;
+
+    Ok(install_config)
+}
+
+/// Write `install_config` to `path` as an answer file instead of installing it
+fn export_config(install_config: &InstallConfig, path: &Path) -> Result<()> {
+    let text = config_file::export(install_config, false)?;
+    std::fs::write(path, text).map_err(|e| {
+        anyhow!(
+            "Installer failed to write the configuration file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    print_note(&tr!(
+        "Installer has successfully saved your installation configuration: {}.",
+        path.display()
+    ));
+
+    Ok(())
+}
+
+/// Print what installing `install_config` would do instead of installing it
+fn print_plan(install_config: &InstallConfig, json: bool) -> Result<()> {
+    info!("Installation plan: {}", install_config.redacted_json()?);
+    let plan = InstallPlan::new(install_config)?;
+    if json_progress::is_enabled() {
+        json_progress::emit(&Event::Plan { plan: &plan });
+    } else if json {
+        println!("{}", plan.to_json()?);
+    } else {
+        println!("{}", plan.describe());
+    }
+
+    Ok(())
+}
+
+/// Print what will become of the installation on `partition` when reinstalling over it, the
+/// notes before installing go to stderr, leaving stdout to the plan of `--dry-run`
+fn print_reinstall_summary(path: &str, partition: &Partition, restore_users: bool) -> Result<()> {
+    let previous = install::probe_previous_install(partition)?.ok_or_else(|| {
+        anyhow!(
+            "Installer could not find an existing AOSC OS installation on {} to preserve /home from.",
+            path
+        )
+    })?;
+    print_summary(&reinstall_summary(
+        path,
+        partition.fs_type.as_deref().unwrap_or_default(),
+        &previous,
+        restore_users,
+    ));
+
+    Ok(())
+}
+
+fn print_board_actions(board: &str, partition: &Partition) -> Result<()> {
+    let board = boards::find_board(board)?;
+    for action in boards::describe_actions(&board, partition.parent_path.as_ref().unwrap()) {
+        print_summary(&format!("{action}\n"));
+    }
+
+    Ok(())
+}
+
+fn resolve_all_ssh_keys(sources: &[String]) -> Result<Vec<String>> {
+    let mut ssh_keys = vec![];
+    for source in sources {
+        ssh_keys.extend(resolve_ssh_keys(source)?);
+    }
+
+    Ok(ssh_keys)
+}
+
+fn check_timezone(timezone: &str) -> Result<String> {
+    let mut timezone = install::check_timezone(timezone, &install::get_zoneinfo_list()?)?;
+    // tzdata 的北京时间是 Asia/Shanghai
+    if timezone == "Asia/Beijing" {
+        timezone = "Asia/Shanghai".to_string();
+    }
+
+    Ok(timezone)
+}
+
+/// Check the answers given by the options or a configuration file, before anything is
+/// written to the disks
+fn check_answers(config: &InstallConfig) -> Result<()> {
+    match answer_problems(config).into_iter().next() {
+        Some((_, e)) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Everything `check_answers` refuses, not only the first, with the key of the answer file
+/// each is about
+fn answer_problems(config: &InstallConfig) -> Vec<(&'static str, anyhow::Error)> {
+    let mut problems = vec![];
+    if let Some(hostname) = config.hostname.as_ref() {
+        if !is_valid_hostname(hostname) {
+            let e = match install::suggest_hostname(hostname) {
+                Some(lower) => anyhow!(
+                    "hostname {} is not valid! Hostname may not contain uppercase letters, did you mean {}?",
+                    hostname,
+                    lower
+                ),
+                None => anyhow!("hostname {} is not valid!", hostname),
+            };
+            problems.push(("hostname", e));
+        }
+    }
+
+    if let Some(user) = config.user.as_ref() {
+        if let Err(e) = check_username(user) {
+            problems.push((
+                "user.name",
+                anyhow!("username {} is not valid! {}", user, e),
+            ));
+        }
+    }
+
+    if let Some(full_name) = config.full_name.as_ref() {
+        if full_name.contains('\n') || full_name.contains(':') {
+            problems.push((
+                "user.full_name",
+                anyhow!("full name {} is not valid!", full_name),
+            ));
+        }
+    }
+
+    if let Some(uid) = config.uid {
+        if uid < 1000 {
+            problems.push((
+                "user.uid",
+                anyhow!("UID {} is reserved for system users!", uid),
+            ));
+        }
+    }
+
+    for group in config
+        .groups
+        .iter()
+        .flat_map(|x| x.iter())
+        .filter(|x| !install::is_valid_group_name(x))
+    {
+        problems.push(("user.groups", anyhow!("group {} is not valid!", group)));
+    }
+
+    for package in config
+        .extra_packages
+        .iter()
+        .flat_map(|x| x.iter())
+        .filter(|x| !install::is_valid_package_name(x))
+    {
+        problems.push((
+            "extra_packages",
+            anyhow!("package name {} is not valid!", package),
+        ));
+    }
+
+    if let Some(cmdline) = config.kernel_cmdline.as_ref() {
+        if !install::is_valid_kernel_cmdline(cmdline) {
+            problems.push((
+                "bootloader.kernel_cmdline",
+                anyhow!("kernel command line {} is not valid!", cmdline),
+            ));
+        }
+    }
+
+    if let Some(font) = config.console_font.as_ref() {
+        if !install::is_valid_console_font(font) {
+            problems.push((
+                "bootloader.console_font",
+                anyhow!("console font {} is not valid!", font),
+            ));
+        }
+    }
+
+    if let Some(gfxmode) = config.grub_gfxmode.as_ref() {
+        if !install::is_valid_grub_gfxmode(gfxmode) {
+            problems.push((
+                "bootloader.gfxmode",
+                anyhow!("GRUB graphics mode {} is not valid!", gfxmode),
+            ));
+        }
+    }
+
+    if let Some(shell) = config.shell.as_ref() {
+        if !shell.starts_with('/') {
+            problems.push((
+                "user.shell",
+                anyhow!("shell {} is not an absolute path!", shell),
+            ));
+        }
+    }
+
+    let supported_locales = install::get_supported_locales(Path::new("/")).unwrap_or_else(|_| {
+        install::get_locale_list()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| x.1.to_string())
+            .collect()
+    });
+    let locales = config
+        .locale
+        .iter()
+        .map(|x| ("region.locale", x.as_str()))
+        .chain(
+            config
+                .extra_locales
+                .iter()
+                .flat_map(|x| x.iter())
+                .map(|x| ("region.extra_locales", x.as_str())),
+        );
+    for (key, locale) in locales {
+        if let Err(e) = install::check_locale(locale, &supported_locales) {
+            problems.push((key, e));
+        }
+    }
+
+    if let Some(keymap) = config.keymap.as_ref() {
+        // the live environment may not ship the XKB data, leave it to the installed system
+        if let Ok(layouts) = install::get_keyboard_layouts(Path::new("/")) {
+            if let Err(e) = install::check_keymap(keymap, &layouts) {
+                problems.push(("region.keymap", e));
+            }
+        }
+    }
+
+    if let Some(cost) = config.password_cost {
+        if let Err(e) = install::check_password_cost(cost) {
+            problems.push(("password_cost", e));
+        }
+    }
+
+    if let Some(default_target) = config.default_target.as_deref() {
+        if !install::DEFAULT_TARGETS.contains(&default_target.as_str()) {
+            problems.push((
+                "default_target",
+                anyhow!(
+                    "`default_target`: {} is not supported, expected one of {}.",
+                    default_target,
+                    install::DEFAULT_TARGETS.join(", ")
+                ),
+            ));
+        }
+    }
+
+    problems
+}
+
+/// The mirror named `name` in the manifest (as listed by `list-mirror`), or at the URL
+fn find_mirror(name: &str) -> Result<Mirror> {
+    if name.contains("://") {
+        return Ok(get_mirror(name));
+    }
+    let recipe = network::fetch_recipe()?;
+    fetch_mirrors(&recipe)
+        .into_iter()
+        .find(|x| x.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            anyhow!(tr!(
+                "Installer could not find mirror {}.\nPlease refer to the `aoscdk-rs list-mirror` output for a list of available mirrors.",
+                name
+            ))
+        })
+}
+
+/// Whether `path` is a whole disk rather than a partition
+fn is_disk(path: &Path) -> bool {
+    make_device_list(disks::list_devices())
+        .iter()
+        .any(|x| x.path == path)
+}
+
+/// The system partition to be created on `device` in the guided mode, leaving `keep_free`
+/// bytes unpartitioned
+fn guided_partition(
+    device: &DkDerive,
+    keep_free: u64,
+    fs_type: Option<&str>,
+    variant: &VariantEntry,
+) -> Result<Partition> {
+    let size = auto_partition_system_size(device.size, keep_free);
+    let required_size = variant.install_size + variant.size;
+    if size < required_size {
+        return Err(
+            ExitCode::InsufficientSpace.wrap(anyhow!(not_enough_space_msg(size, required_size)))
+        );
+    }
+
+    Ok(Partition {
+        path: None,
+        parent_path: Some(device.path.clone()),
+        fs_type: Some(fs_type.unwrap_or("ext4").to_string()),
+        size,
+    })
+}
+
+/// The disk at `path`, erasing the data on its partitions is confirmed unless
+/// `allow_data_loss`, or left to the plan in a `dry_run`
+fn get_disk(path: &Path, allow_data_loss: bool, dry_run: bool) -> Result<DkDerive> {
+    let device = make_device_list(disks::list_devices())
+        .into_iter()
+        .find(|x| x.path == path)
+        .ok_or_else(|| {
+            anyhow!(tr!(
+                "Installer could not find the specified disk: {}",
+                path.display()
+            ))
+        })?;
+    if !dry_run {
+        for partition in disks::list_partitions(Some(device.path.clone())) {
+            check_data_loss(&partition, allow_data_loss)?;
+        }
+    }
+
+    Ok(device)
+}
+
+/// The partitions mounted into the installed system, checked the same way as in the TUI
+fn get_mounts(
+    system: &Partition,
+    format: bool,
+    entries: &[config_file::Mount],
+) -> Result<Vec<MountAssignment>> {
+    let partitions = disks::list_partitions(None);
+    let mut assigned = vec![];
+    for (i, mount) in entries.iter().enumerate() {
+        let partition = partitions
+            .iter()
+            .find(|x| x.path.as_ref() == Some(&mount.partition))
+            .ok_or_else(|| {
+                anyhow!(
+                    "`target.mounts[{}]`: Installer could not find partition {}.",
+                    i,
+                    mount.partition.display()
+                )
+            })?;
+        assigned.push(MountAssignment {
+            partition: partition.clone(),
+            mount_point: mount.mount_point.clone(),
+            fs_type: mount.filesystem.clone(),
+            format: mount.format,
+            label: mount.label.clone(),
+            options: mount.options.clone(),
+            encrypt: false,
+        });
+    }
+    let esp = system
+        .parent_path
+        .as_ref()
+        .filter(|_| disks::is_efi_booted())
+        .and_then(|x| disks::find_esp_partition(x).ok())
+        .and_then(|x| x.path);
+    mounts::check_assignments(system, format, &assigned, esp.as_deref())
+        .map_err(|e| anyhow!("`target.mounts`: {}", e))?;
+
+    Ok(assigned)
+}
+
+/// Where the installed system swaps to as set in `[swap]`: the kind, the swap partition,
+/// the swapfile size and whether to hibernate
+fn get_swap_from_file(
+    swap: &config_file::Swap,
+    partition: Option<&Partition>,
+    variant: &VariantEntry,
+) -> Result<(SwapKind, Option<Partition>, Option<f64>, bool)> {
+    let Some(partition) = partition else {
+        return Ok((swap.kind.unwrap_or(SwapKind::None), None, None, false));
+    };
+    let result = match swap.kind {
+        None => {
+            let (use_swap, size, is_hibernation) = get_swap(None, partition, variant)?;
+            if use_swap {
+                (SwapKind::File, None, Some(size), is_hibernation)
+            } else {
+                (SwapKind::None, None, None, false)
+            }
+        }
+        Some(SwapKind::File) => {
+            let size = match swap.size {
+                Some(size) => size * 1024.0 * 1024.0 * 1024.0,
+                None => disks::get_recommend_swap_size(sysinfo::System::new_all().total_memory())?,
+            };
+            let is_hibernation = match swap.hibernation {
+                Some(x) => x,
+                None => disks::is_enable_hibernation(size)?,
+            };
+            (SwapKind::File, None, Some(size), is_hibernation)
+        }
+        Some(SwapKind::Partition) => {
+            let path = swap.partition.as_ref().unwrap();
+            let swap_partition = disks::list_swap_partitions()
+                .into_iter()
+                .find(|x| x.path.as_ref() == Some(path))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "`swap.partition`: Installer could not find swap partition {}.",
+                        path.display()
+                    )
+                })?;
+            (
+                SwapKind::Partition,
+                Some(swap_partition),
+                None,
+                swap.hibernation.unwrap_or(false),
+            )
+        }
+        Some(kind) => (kind, None, None, false),
+    };
+
+    Ok(result)
+}
+
+/// Refuse to install without the answers in `missing` (keys of the answer file), all of
+/// them are listed at once
+fn check_missing(missing: &[&str]) -> Result<()> {
+    if missing.is_empty() {
+        return Ok(());
+    }
 
-    if !is_valid_hostname(&ic.hostname) {
-        return Err(anyhow!("hostname {} is not valid!", ic.hostname));
+    let mut message = tr!("Installer needs the following answers to install without asking, please give them as options or in the configuration file:").to_string();
+    for (key, options) in REQUIRED_ANSWERS.iter().filter(|(x, _)| missing.contains(x)) {
+        message.push_str(&format!("\n  {options} (`{key}`)"));
     }
 
-    if !is_acceptable_username(&ic.user) {
-        return Err(anyhow!("username {} is not valid!", ic.user));
+    Err(anyhow!(message))
+}
+
+/// The option `id` as it is written on the command line, e.g., `--target-dir`
+fn option_name(id: &str) -> String {
+    InstallCommand::command()
+        .get_arguments()
+        .find(|x| x.get_id() == id)
+        .and_then(|x| x.get_long())
+        .map(|x| format!("--{x}"))
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// Override the answer file with the options given along with it
+fn override_config_file(file: &mut ConfigFile, ic: &InstallCommand) -> Result<()> {
+    if ic.is_given("tarball") {
+        file.variant = Some(ic.tarball.clone());
+    }
+    if ic.is_given("mirror") {
+        file.mirror = Some(ic.mirror.clone());
+    }
+    if ic.no_mirror_repo {
+        file.use_mirror_repo = Some(false);
     }
 
-    let install_config = InstallConfig {
-        variant: Some(Arc::new(variant)),
-        partition: Some(Arc::new(partition)),
-        mirror: Some(Arc::new(mirror)),
-        user: Some(Arc::new(ic.user)),
-        full_name: None,
-        password: Some(Arc::new(ic.password)),
-        hostname: Some(ic.hostname),
-        locale: Some(Arc::new(ic.locale)),
-        timezone: Some(Arc::new(ic.timezone)),
-        tc: Some(Arc::new(tc.to_string())),
-        use_swap: Arc::new(AtomicBoolWrapper {
-            v: AtomicBool::new(!ic.no_swap && use_swap),
-        }),
-        swap_size: Arc::new(Some(swap_size)),
-        is_hibernation: Arc::new(AtomicBoolWrapper {
-            v: AtomicBool::new(is_hibernation),
-        }),
-        root_password: None,
+    let target = &mut file.target;
+    if let Some(path) = ic.path.as_ref().map(PathBuf::from) {
+        let is_disk = is_disk(&path);
+        target.disk = is_disk.then(|| path.clone());
+        target.partition = (!is_disk).then_some(path);
+        target.directory = None;
+    }
+    if let Some(dir) = ic.target_dir.as_ref() {
+        target.disk = None;
+        target.partition = None;
+        target.directory = Some(dir.clone());
+    }
+    if ic.fs.is_some() {
+        target.filesystem = ic.fs.clone();
+    }
+    if ic.preserve_home {
+        target.preserve_home = Some(true);
+    }
+    if ic.no_restore_users {
+        target.restore_users = Some(false);
+    }
+
+    let swap = &mut file.swap;
+    if ic.no_swap {
+        swap.kind = Some(SwapKind::None);
+        swap.size = None;
+        swap.partition = None;
+        swap.hibernation = None;
+    }
+    if let Some(size) = ic.swap_size {
+        swap.kind = Some(SwapKind::File);
+        swap.size = Some(size);
+        swap.partition = None;
+    }
+
+    if ic.password_cost.is_some() {
+        file.password_cost = ic.password_cost;
+    }
+    if ic.oobe {
+        file.oobe = Some(true);
+        file.user = None;
+    }
+    if let Some(name) = ic.user.as_ref() {
+        file.oobe = None;
+        file.user.get_or_insert_with(Default::default).name = name.clone();
+    }
+    if let Some(user) = file.user.as_mut() {
+        if let Some(hash) = ic.password_hash.as_ref() {
+            user.password = Some(hash.clone());
+        } else if let Some(password) = ic.password.as_ref() {
+            // given on the command line, a plain text password is fine
+            user.password = Some(password_hash(password, file.password_cost)?);
+        }
+        if ic.full_name.is_some() {
+            user.full_name = ic.full_name.clone();
+        }
+        if ic.uid.is_some() {
+            user.uid = ic.uid;
+        }
+        if ic.groups.is_some() {
+            user.groups = ic.groups.clone();
+        }
+        if ic.no_admin {
+            user.admin = Some(false);
+        }
+        if ic.sudo_nopasswd {
+            user.sudo_nopasswd = Some(true);
+        }
+        if ic.shell.is_some() {
+            user.shell = ic.shell.clone();
+        }
+        if ic.autologin {
+            user.autologin = Some(true);
+        }
+        if !ic.ssh_key.is_empty() {
+            user.ssh_keys = ic.ssh_key.clone();
+        }
+    }
+    if ic.is_given("root_password") {
+        file.root_password = Some(ic.root_password.clone());
+    }
+    if ic.hostname.is_some() {
+        file.hostname = ic.hostname.clone();
+    }
+
+    let region = &mut file.region;
+    if ic.is_given("locale") {
+        region.locale = Some(ic.locale.clone());
+    }
+    if !ic.extra_locale.is_empty() {
+        region.extra_locales = ic.extra_locale.clone();
+    }
+    if ic.is_given("timezone") {
+        region.timezone = Some(ic.timezone.clone());
+    }
+    if ic.use_rtc {
+        region.rtc_local_time = Some(true);
+    }
+    if ic.no_ntp {
+        region.ntp = Some(false);
+        region.ntp_servers.clear();
+    }
+    if !ic.ntp_server.is_empty() {
+        region.ntp = Some(true);
+        region.ntp_servers = ic.ntp_server.clone();
+    }
+    if ic.keymap.is_some() {
+        region.keymap = ic.keymap.clone();
+    }
+
+    let bootloader = &mut file.bootloader;
+    if ic.kernel_cmdline.is_some() {
+        bootloader.kernel_cmdline = ic.kernel_cmdline.clone();
+    }
+    if ic.grub_timeout.is_some() {
+        bootloader.timeout = ic.grub_timeout;
+    }
+    if ic.os_prober {
+        bootloader.os_prober = Some(true);
+    }
+    if ic.grub_hide_menu {
+        bootloader.hide_menu = Some(true);
+    }
+    if ic.grub_gfxmode.is_some() {
+        bootloader.gfxmode = ic.grub_gfxmode.clone();
+    }
+    if ic.boot_splash || ic.no_boot_splash {
+        bootloader.boot_splash = Some(ic.boot_splash);
+    }
+    if ic.hidpi || ic.no_hidpi {
+        bootloader.hidpi = Some(ic.hidpi);
+    }
+    if ic.console_font.is_some() {
+        bootloader.console_font = ic.console_font.clone();
+    }
+    if ic.board.is_some() || ic.bootloader.is_some() {
+        bootloader.board = ic.board().map(|x| x.to_string());
+    }
+
+    let network = &mut file.network;
+    if ic.no_copy_network {
+        network.copy_config = Some(false);
+    }
+    if ic.enable_sshd {
+        network.enable_sshd = Some(true);
+    }
+    if ic.ssh_disable_password_auth {
+        network.ssh_disable_password_auth = Some(true);
+    }
+
+    if !ic.enable_service.is_empty() {
+        file.services.enable = ic.enable_service.clone();
+    }
+    if !ic.disable_service.is_empty() {
+        file.services.disable = ic.disable_service.clone();
+    }
+    if !ic.extra_package.is_empty() {
+        file.extra_packages = ic.extra_package.clone();
+    }
+    if ic.nvidia_driver {
+        file.nvidia_driver = Some(true);
+    }
+    if ic.default_target.is_some() {
+        file.default_target = ic.default_target.clone();
+    }
+    if ic.disable_display_manager {
+        file.disable_display_manager = Some(true);
+    }
+    if ic.generate_machine_id {
+        file.generate_machine_id = Some(true);
+    }
+    if ic.no_verify_files {
+        file.verify_files = Some(false);
+    }
+
+    Ok(())
+}
+
+/// The plan of an answer file, looking up the variant, the mirror and the target, and
+/// checking everything before anything is written to the disks
+fn config_from_file(file: ConfigFile, ic: &InstallCommand) -> Result<InstallConfig> {
+    let variant = get_variant(
+        file.variant.as_deref().unwrap_or_default(),
+        ic.ignore_bulletin,
+        ic.acknowledge_bulletin.as_deref(),
+    )?;
+    let mirror = find_mirror(file.mirror.as_deref().unwrap_or(DEFAULT_MIRROR))?;
+    let allow_data_loss = ic.allow_data_loss || file.allow_data_loss.unwrap_or(false);
+    let target = &file.target;
+    let preserve_home = target.preserve_home.unwrap_or(false);
+
+    let mut config = answers_of_file(&file);
+    match target.mode()? {
+        TargetMode::Disk(path) => {
+            let device = get_disk(path, allow_data_loss, ic.dry_run)?;
+            let keep_free =
+                (target.keep_free_space.unwrap_or(0.0) * 1024.0 * 1024.0 * 1024.0) as u64;
+            let partition =
+                guided_partition(&device, keep_free, target.filesystem.as_deref(), &variant)?;
+            config.partition = Some(Arc::new(partition));
+            config.wipe_disk = Some(Arc::new(device));
+            config.storage_mode = Some(StorageMode::Guided);
+            config.keep_free_space = Some(keep_free);
+        }
+        TargetMode::Partition(path) => {
+            let path_str = path.to_string_lossy();
+            let mut partition = get_partition(
+                &path_str,
+                &variant,
+                allow_data_loss || preserve_home,
+                ic.dry_run,
+            )?;
+            if let Some(fs_type) = target.filesystem.as_ref() {
+                partition.fs_type = Some(fs_type.clone());
+            }
+            let restore_users = preserve_home && target.restore_users.unwrap_or(true);
+            if preserve_home {
+                print_reinstall_summary(&path_str, &partition, restore_users)?;
+            }
+            let assigned = get_mounts(&partition, !preserve_home, &target.mounts)?;
+            config.mounts = (!assigned.is_empty()).then(|| Arc::new(assigned));
+            config.partition = Some(Arc::new(partition));
+            config.storage_mode = Some(StorageMode::Advanced);
+            config.preserve_home = Some(preserve_home);
+            config.restore_users = Some(restore_users);
+        }
+        TargetMode::Directory(dir) => {
+            check_target_dir(dir, &variant)?;
+            config.target_dir = Some(dir.to_path_buf());
+        }
+    }
+
+    if let (Some(board), Some(partition)) =
+        (file.bootloader.board.as_ref(), config.partition.as_ref())
+    {
+        print_board_actions(board, partition)?;
+    }
+    print_summary(&package_repo_summary(
+        &mirror,
+        config.use_mirror_repo.unwrap_or(true),
+    ));
+
+    let (swap, swap_partition, swap_size, is_hibernation) =
+        get_swap_from_file(&file.swap, config.partition.as_deref(), &variant)?;
+    config.swap = Some(swap);
+    config.swap_partition = swap_partition.map(Arc::new);
+    config.swap_size = Arc::new(swap_size);
+    config.use_swap = Arc::new(AtomicBoolWrapper {
+        v: AtomicBool::new(swap == SwapKind::File),
+    });
+    config.is_hibernation = Arc::new(AtomicBoolWrapper {
+        v: AtomicBool::new(is_hibernation),
+    });
+
+    if let Some(user) = file.user.as_ref() {
+        config.ssh_keys = Some(Arc::new(resolve_all_ssh_keys(&user.ssh_keys)?));
+    }
+    if config.hostname.is_none() {
+        config.hostname = Some(install::gen_default_hostname(&variant.name));
+    }
+    config.allow_data_loss = Some(allow_data_loss);
+    config.timezone = Some(Arc::new(check_timezone(
+        file.region.timezone.as_deref().unwrap_or("UTC"),
+    )?));
+    config.variant = Some(Arc::new(variant));
+    config.mirror = Some(Arc::new(mirror));
+
+    check_config_file_passwords(&config)?;
+    check_answers(&config)?;
+    check_root_account(&config)?;
+
+    Ok(config)
+}
+
+/// The answers of the answer file as they are, without looking anything up: the target,
+/// the swap, the variant and the mirror are left out, the SSH keys are not resolved
+fn answers_of_file(file: &ConfigFile) -> InstallConfig {
+    let mut config = InstallConfig::default();
+    if let Some(user) = file.user.as_ref() {
+        config.ssh_keys = Some(Arc::new(user.ssh_keys.clone()));
+        config.user = Some(Arc::new(user.name.clone()));
+        config.password = user.password.clone().map(Arc::new);
+        config.full_name = user.full_name.clone().map(Arc::new);
+        config.uid = user.uid;
+        config.groups = user.groups.clone().map(Arc::new);
+        config.admin = user.admin;
+        config.sudo_nopasswd = user.sudo_nopasswd;
+        config.shell = user.shell.clone().map(Arc::new);
+        config.autologin = user.autologin;
+    }
+    config.hostname = file.hostname.clone();
+    config.root_password = Some(Arc::new(
+        file.root_password.clone().unwrap_or(RootPassword::None),
+    ));
+    config.password_cost = file.password_cost;
+    config.allow_plaintext_password = file.allow_plaintext_password;
+    config.allow_data_loss = file.allow_data_loss;
+    config.oobe = file.oobe;
+
+    let region = &file.region;
+    config.locale = Some(Arc::new(
+        region
+            .locale
+            .clone()
+            .unwrap_or_else(|| "C.UTF-8".to_string()),
+    ));
+    config.extra_locales = Some(Arc::new(region.extra_locales.clone()));
+    config.timezone = Some(Arc::new(
+        region.timezone.clone().unwrap_or_else(|| "UTC".to_string()),
+    ));
+    let tc = if region.rtc_local_time.unwrap_or(false) {
+        "RTC"
+    } else {
+        "UTC"
     };
+    config.tc = Some(Arc::new(tc.to_string()));
+    config.enable_ntp = region.ntp;
+    config.ntp_servers = Some(Arc::new(region.ntp_servers.clone()));
+    config.keymap = region.keymap.clone().map(Arc::new);
+
+    let bootloader = &file.bootloader;
+    config.kernel_cmdline = bootloader.kernel_cmdline.clone().map(Arc::new);
+    config.grub_timeout = bootloader.timeout;
+    config.grub_os_prober = bootloader.os_prober;
+    config.grub_hidden_menu = bootloader.hide_menu;
+    config.grub_gfxmode = bootloader.gfxmode.clone().map(Arc::new);
+    config.boot_splash = bootloader.boot_splash;
+    config.hidpi = bootloader.hidpi;
+    config.console_font = bootloader.console_font.clone().map(Arc::new);
+    config.board = bootloader.board.clone().map(Arc::new);
+
+    config.copy_network_config = file.network.copy_config;
+    config.enable_sshd = file.network.enable_sshd;
+    config.ssh_disable_password_auth = file.network.ssh_disable_password_auth;
+    config.enable_services = Some(Arc::new(file.services.enable.clone()));
+    config.disable_services = Some(Arc::new(file.services.disable.clone()));
+    config.extra_packages = Some(Arc::new(file.extra_packages.clone()));
+    config.nvidia_driver = file.nvidia_driver;
+    config.default_target = file.default_target.clone().map(Arc::new);
+    config.disable_display_manager = file.disable_display_manager;
+    config.generate_machine_id = file.generate_machine_id;
+    config.verify_files = file.verify_files;
+    config.use_mirror_repo = Some(file.use_mirror_repo.unwrap_or(true));
+    config.hooks = Some(Arc::new(file.hooks.clone()));
+
+    config
+}
+
+/// Check a configuration saved by the TUI against this machine: the variant is looked up
+/// again for the current release, and the disks and partitions have to be there still
+fn check_saved_config(mut config: InstallConfig, ic: &InstallCommand) -> Result<InstallConfig> {
+    let missing = |key: &str| anyhow!("The configuration file does not set `{}`.", key);
+    check_config_file_passwords(&config)?;
+    let name = config
+        .variant
+        .as_ref()
+        .map(|x| x.name.clone())
+        .ok_or_else(|| missing("variant"))?;
+    let variant = get_variant(
+        &name,
+        ic.ignore_bulletin,
+        ic.acknowledge_bulletin.as_deref(),
+    )?;
+    if config.mirror.is_none() {
+        return Err(missing("mirror"));
+    }
+    for (key, set) in [
+        ("hostname", config.hostname.is_some()),
+        ("locale", config.locale.is_some()),
+        ("timezone", config.timezone.is_some()),
+        ("tc", config.tc.is_some()),
+    ] {
+        if !set {
+            return Err(missing(key));
+        }
+    }
+    if config.encrypt.unwrap_or(false) {
+        return Err(anyhow!("The passphrase of the encrypted system partition is never saved, please install with the TUI to encrypt it."));
+    }
+
+    let allow_data_loss = ic.allow_data_loss || config.allow_data_loss.unwrap_or(false);
+    let preserve_home = config.preserve_home.unwrap_or(false);
+    if let Some(dir) = config.target_dir.as_ref() {
+        check_target_dir(dir, &variant)?;
+    } else if let Some(device) = config.wipe_disk.clone() {
+        let device = get_disk(&device.path, allow_data_loss, ic.dry_run)?;
+        let partition = guided_partition(
+            &device,
+            config.keep_free_space.unwrap_or(0),
+            config.partition.as_ref().and_then(|x| x.fs_type.as_deref()),
+            &variant,
+        )?;
+        config.partition = Some(Arc::new(partition));
+        config.wipe_disk = Some(Arc::new(device));
+    } else if let Some(saved) = config.partition.clone() {
+        let path = saved
+            .path
+            .as_ref()
+            .ok_or_else(|| missing("partition.path"))?;
+        let path_str = path.to_string_lossy();
+        let mut partition = get_partition(
+            &path_str,
+            &variant,
+            allow_data_loss || preserve_home,
+            ic.dry_run,
+        )?;
+        partition.fs_type = saved.fs_type.clone().or(partition.fs_type);
+        if preserve_home {
+            print_reinstall_summary(&path_str, &partition, config.restore_users.unwrap_or(false))?;
+        }
+        let partitions = disks::list_partitions(None);
+        if let Some(mount) = config
+            .mounts
+            .iter()
+            .flat_map(|x| x.iter())
+            .find(|x| !partitions.iter().any(|p| p.path == x.partition.path))
+        {
+            return Err(anyhow!(tr!(
+                "Installer could not find the specified partition: {}\nDid you partition your target disk?",
+                mount.partition.path.as_deref().unwrap_or(Path::new("")).display()
+            )));
+        }
+        config.partition = Some(Arc::new(partition));
+    } else {
+        return Err(missing("partition"));
+    }
+
+    if let (Some(board), Some(partition)) = (config.board.as_deref(), config.partition.as_ref()) {
+        print_board_actions(board, partition)?;
+    }
+    if let Some(mirror) = config.mirror.as_ref() {
+        print_summary(&package_repo_summary(
+            mirror,
+            config.use_mirror_repo.unwrap_or(true),
+        ));
+    }
+    let timezone = check_timezone(config.timezone.as_ref().unwrap())?;
+    config.timezone = Some(Arc::new(timezone));
+    config.variant = Some(Arc::new(variant));
+    config.allow_data_loss = Some(allow_data_loss);
+    check_answers(&config)?;
+    check_root_account(&config)?;
+
+    Ok(config)
+}
 
-    let root_fd = install::get_dir_fd(Path::new("/"))?;
-    let rfc = root_fd.try_clone().unwrap();
+/// Install as planned by `install_config`, or continue the interrupted installation
+/// `resume` (planned by `install_config` as well), showing the progress until it is done
+fn run_install(
+    install_config: InstallConfig,
+    wipe_on_cancel: bool,
+    reboot: bool,
+    resume: Option<resume::Resumption>,
+) -> Result<()> {
+    let rfc = install::get_dir_fd(Path::new("/"))?;
 
     let (tx, rx) = std::sync::mpsc::channel();
 
-    let tempdir = tempfile::Builder::new()
-        .prefix(".dkmount")
-        .tempdir()
-        .expect("Installer failed to create temporary file for the download process.")
-        .into_path();
+    let is_target_dir = install_config.target_dir.is_some();
+    let tempdir = match install_config.target_dir.clone() {
+        Some(dir) => dir,
+        None => tempfile::Builder::new()
+            .prefix(".dkmount")
+            .tempdir()
+            .expect("Installer failed to create temporary file for the download process.")
+            .into_path(),
+    };
+    let cleanup = move |root: &Path, root_fd: OwnedFd| {
+        if is_target_dir {
+            install::umount_guest(root, root_fd);
+        } else {
+            umount_all(root, root_fd);
+        }
+    };
 
     let tempdir_clone = tempdir.clone();
-    let tempdir_clone_2 = tempdir.clone();
+    let cancel = CancelToken::new();
+    let cancel_copy = cancel.clone();
+    let cancel_copy_2 = cancel.clone();
     ctrlc::set_handler(move || {
-        info!("User request to exit the installer");
-        umount_all(&tempdir, root_fd.try_clone().unwrap());
-        r.store(false, Ordering::SeqCst);
+        if !cancel_copy.is_cancellable() {
+            eprintln!("{}", tr!("Installer is configuring the installed system, the installation can no longer be cancelled safely. Please wait for it to finish."));
+            return;
+        }
+        info!("User requested to cancel the installation");
+        cancel_copy.cancel(wipe_on_cancel);
     }).expect("Installer could not initialize SIGINT handler.\n\nPlease restart your installation environment.");
 
-    let install_thread = thread::spawn(move || begin_install(tx, install_config, tempdir_clone));
-    let bar = ProgressBar::new_spinner();
+    let config_json = install_config.redacted_json().ok();
+    let install_thread = thread::spawn(move || {
+        begin_install(tx, install_config, tempdir_clone, cancel_copy_2, resume)
+    });
+    // stdout is left to the events with --json-progress
+    let json = json_progress::is_enabled();
+    let bar = if json || is_quiet() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
     bar.enable_steady_tick(Duration::from_millis(50));
+    let mut events = ProgressEvents::default();
+    // the status of the step running, which tells the exit code if the installation fails
+    let mut status = String::new();
+    let mut clock = InstallClock::new();
+    let mut plan = vec![];
+    let mut overall = 0;
+    let mut transfer = None;
+    let mut boot_menu = BootMenu::default();
+    let mut warnings = vec![];
+    let mut journal = StepJournal::default();
 
     loop {
-        if !running.load(Ordering::SeqCst) {
-            return Err(anyhow!("AOSC OS installation has been aborted."));
-        }
         if let Ok(progress) = rx.recv() {
             match progress {
                 super::InstallProgress::Pending(msg, pct) => {
-                    bar.set_message(format!("{msg} ({pct}/100)"));
+                    if let Some(x) = overall_percent(&plan, &msg, pct) {
+                        overall = overall.max(x);
+                    }
+                    if json {
+                        events.pending(&plan, &msg, pct, overall);
+                    }
+                    status.clone_from(&msg);
+                    let msg = tr!(&msg);
+                    let time = clock.summary();
+                    match &transfer {
+                        Some(transfer) => bar.set_message(format!(
+                            "{msg} ({}) [{overall}%] - {time}",
+                            transfer.summary
+                        )),
+                        None => bar.set_message(format!("{msg} ({pct}/100) [{overall}%] - {time}")),
+                    }
+                }
+                super::InstallProgress::Steps(steps) => {
+                    if json {
+                        events.steps(&steps);
+                    }
+                    plan = steps;
+                }
+                super::InstallProgress::Remaining(estimate) => {
+                    clock.set_remaining(estimate);
+                }
+                super::InstallProgress::Transfer(stats) => {
+                    if let Some(stats) = stats.as_ref().filter(|_| json) {
+                        events.transfer(stats);
+                    }
+                    transfer = stats;
+                }
+                super::InstallProgress::Retry(msg, retry_tx) => {
+                    bar.suspend(|| {
+                        error!("{}", msg);
+                        retry_tx.send(ask_retry()).ok();
+                    });
+                }
+                super::InstallProgress::Warning(warning) => {
+                    json_progress::emit(&Event::Warning { message: &warning });
+                    warnings.push(warning);
                 }
-                super::InstallProgress::Finished => {
-                    bar.finish_with_message("AOSC OS installation has successfully completed! Good luck to you, Dungeon Master :)");
+                // already in the log
+                super::InstallProgress::Output(_) => {}
+                super::InstallProgress::PostInstall(_, menu, steps, action_tx) => {
+                    boot_menu = menu;
+                    journal = steps;
+                    action_tx.send(PostInstallAction::Finish).ok();
+                }
+                super::InstallProgress::Finished(unmount) if journal.is_incomplete() => {
+                    events.finish_step();
+                    finish_progress(
+                        &bar,
+                        tr!("AOSC OS has been installed, but some steps have not completed:"),
+                    );
+                    for (step, outcome) in journal.steps() {
+                        let message = match outcome {
+                            StepOutcome::Succeeded => continue,
+                            StepOutcome::Failed(e) => tr!("{}: failed: {}", tr!(step), e),
+                            StepOutcome::NotRun => tr!("{}: not run", tr!(step)),
+                        };
+                        report_line(&message);
+                    }
+                    if unmount == UnmountStatus::Lazy {
+                        report_line(tr!(LAZY_UNMOUNT_INFO));
+                    }
+                    let code = journal
+                        .steps()
+                        .iter()
+                        .find(|(_, x)| matches!(x, StepOutcome::Failed(_)))
+                        .map_or(ExitCode::Internal, |(step, _)| step_exit_code(step));
+                    return Err(code.wrap(anyhow!(tr!("The installed system may not boot, please run the steps left with `aoscdk-rs install --resume` once the problem is fixed, see the log for details."))));
+                }
+                super::InstallProgress::Finished(unmount) => {
+                    events.finish_step();
+                    finish_progress(&bar, tr!("AOSC OS installation has successfully completed! Good luck to you, Dungeon Master :)"));
+                    for os in &boot_menu.foreign_os {
+                        print_note(&tr!("Detected and added to the boot menu: {}", os));
+                    }
+                    // the warnings of the installation have been sent as they came
+                    for warning in boot_menu.warning.iter().filter(|_| !is_quiet()) {
+                        report_line(&tr!("Warning: {}", warning));
+                    }
+                    if !json && !is_quiet() {
+                        for warning in warnings.iter() {
+                            println!("{}", tr!("Warning: {}", warning));
+                        }
+                    }
+                    if unmount == UnmountStatus::Lazy {
+                        report_line(tr!(LAZY_UNMOUNT_INFO));
+                    }
+                    if reboot {
+                        print_note(tr!("Rebooting into the installed system ..."));
+                        install::sync_and_reboot(disks::ejectable_live_medium().as_deref())?;
+                    }
                     return Ok(());
                 }
             }
         } else {
             let err = install_thread.join().map_err(|_| anyhow!("Installer has encountered an unexpected error. Please restart your installation environment."))?.unwrap_err();
 
+            if cancel::is_cancelled(&err) {
+                finish_progress(&bar, tr!("AOSC OS installation has been cancelled."));
+                cleanup(&tempdir, rfc);
+                if cancel.is_target_modified() && !cancel.wants_wipe() {
+                    eprintln!("{}", tr!("The target contains an incomplete system, use --wipe-on-cancel to wipe it on cancellation."));
+                }
+                return Err(err);
+            }
+            let err = step_exit_code(&status).fallback(err);
             error!("{}", err);
-            umount_all(&tempdir_clone_2, rfc);
+            cleanup(&tempdir, rfc);
+            for path in save_log(None) {
+                eprintln!("{}", tr!("Installation log is saved to {}", path.display()));
+            }
+            if resume::exists() {
+                eprintln!("{}", tr!("Once the problem is fixed, the installation may be continued with `aoscdk-rs install --resume`."));
+            }
+            match report::save_report(&format!("{err:?}"), config_json.as_deref()) {
+                Ok(path) => eprintln!(
+                    "{}",
+                    tr!(
+                        "Error details are saved to {}, please attach this file when reporting the problem.",
+                        path.display()
+                    )
+                ),
+                Err(e) => warn!("{}", e),
+            }
             return Err(err);
         }
     }
 }
 
+/// Print a line of the summary of the installation, sent as a warning with --json-progress
+fn report_line(line: &str) {
+    if json_progress::is_enabled() {
+        json_progress::emit(&Event::Warning { message: line });
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Print a note on the installation, to stderr with --json-progress, left out with -q
+fn print_note(line: &str) {
+    if is_quiet() {
+        return;
+    }
+    if json_progress::is_enabled() {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Print what the installation is going to do before it starts, left out with -q
+fn print_summary(text: &str) {
+    if !is_quiet() {
+        eprint!("{text}");
+    }
+}
+
+/// Finish the progress bar with the result, which is printed as it is if the bar is hidden
+/// with -q
+fn finish_progress(bar: &ProgressBar, message: &str) {
+    if bar.is_hidden() && !json_progress::is_enabled() {
+        println!("{message}");
+    } else {
+        bar.finish_with_message(message.to_string());
+    }
+}
+
+/// Whether the user may be asked, not with --json-progress, nor when installing by the
+/// kernel command line or without a terminal
+fn is_interactive() -> bool {
+    !json_progress::is_enabled()
+        && !UNATTENDED.load(Ordering::SeqCst)
+        && std::io::stdin().is_terminal()
+}
+
+/// Ask the user whether to retry the failed step, never retries when not running interactively
+fn ask_retry() -> bool {
+    if !is_interactive() {
+        json_progress::emit(&Event::Prompt {
+            prompt: "retry",
+            question: "Retry? [y/N]",
+            answer: "no",
+        });
+        info!("Not running interactively, skipping the failed step");
+        return false;
+    }
+
+    eprint!("Retry? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+
+    answer.trim().eq_ignore_ascii_case("y")
+}
+
 #[test]
 fn test() {
     dbg!(list_tarball().unwrap());
 }
+
+#[test]
+fn test_override_config_file() {
+    let file = "variant = \"Workstation\"\nhostname = \"aosc-pc\"\n[target]\ndirectory = \"/mnt\"\n[region]\nlocale = \"zh_CN.UTF-8\"\ntimezone = \"Asia/Shanghai\"\n";
+    let mut file: ConfigFile = toml::from_str(file).unwrap();
+    let matches = InstallCommand::command()
+        .try_get_matches_from([
+            "install",
+            "--variant",
+            "Base",
+            "--hostname",
+            "test",
+            "--user",
+            "me",
+            "--password-hash",
+            "$6$salt$hash",
+            "--locale",
+            "en_US.UTF-8",
+            "--bootloader",
+            "grub",
+        ])
+        .unwrap();
+    let mut ic = InstallCommand::from_arg_matches(&matches).unwrap();
+    ic.given = given_answers(&matches);
+    assert!(ic.is_given("tarball") && !ic.is_given("timezone"));
+    assert_eq!(ic.board(), None);
+
+    // the options given win, the rest of the file stays
+    override_config_file(&mut file, &ic).unwrap();
+    assert!(file.missing().is_empty());
+    file.check().unwrap();
+    assert_eq!(file.variant.as_deref(), Some("Base"));
+    assert_eq!(file.hostname.as_deref(), Some("test"));
+    assert_eq!(file.region.locale.as_deref(), Some("en_US.UTF-8"));
+    assert_eq!(file.region.timezone.as_deref(), Some("Asia/Shanghai"));
+    let user = file.user.as_ref().unwrap();
+    assert_eq!(user.name, "me");
+    assert_eq!(user.password.as_deref(), Some("$6$salt$hash"));
+
+    // all of the answers missing are listed at once
+    let e = check_missing(&["target", "user.password"])
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("--target or --target-dir (`target`)"), "{e}");
+    assert!(
+        e.contains("--password or --password-hash (`user.password`)"),
+        "{e}"
+    );
+    assert!(!e.contains("`user`)"), "{e}");
+    assert_eq!(option_name("target_dir"), "--target-dir");
+}
+
+#[test]
+fn test_answer_file_diagnostics() {
+    let text = "variant = \"Base\"\nhostname = \"AOSC\"\nroot_password = \"locked\"\n\n[target]\ndisk = \"/dev/sda\"\n\n[swap]\nkind = \"zram\"\nhibernation = true\n\n[user]\nname = \"aosc\"\nadmin = false\n";
+    let diagnostics = answer_file_diagnostics(text, false);
+    let report = Report::new(Path::new("site.toml"), &diagnostics)
+        .to_json()
+        .unwrap();
+    let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(report["valid"], false);
+    let found = |key: &str| {
+        report["diagnostics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|x| x["key"] == key)
+            .unwrap_or_else(|| panic!("{key} in {report}"))
+            .clone()
+    };
+    // everything is reported at once, with the line of the key
+    assert_eq!(found("user.password")["line"], 12);
+    assert_eq!(found("swap.hibernation")["line"], 10);
+    assert_eq!(found("hostname")["line"], 2);
+    assert_eq!(found("root_password")["line"], 3);
+}
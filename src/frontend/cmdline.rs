@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use sha2::{Digest, Sha256};
+
+use crate::{network, tr};
+
+const CMDLINE: &str = "/proc/cmdline";
+/// Where the answer file given on the kernel command line is kept for `install --config`
+const CONFIG_PATH: &str = "/run/deploykit/cmdline.toml";
+/// Left once the automatic installation has been tried, so that it is only tried once per
+/// boot: the installer started again after a failure shows the TUI instead
+const AUTO_MARKER: &str = "/run/deploykit/auto-install";
+/// The network may come up after the installer has started at boot
+const FETCH_ATTEMPTS: u32 = 5;
+const FETCH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The options of the installer on the kernel command line, e.g., for installing from PXE
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct BootOptions {
+    /// `dk.config=URL`, an answer file (`http://`, `https://` or `file://`)
+    pub config: Option<String>,
+    /// `dk.config_sha256=`, the checksum the answer file has to have
+    pub sha256: Option<String>,
+    /// `dk.auto`, install with the answer file without showing the TUI
+    pub auto: bool,
+    /// `dk.reboot=1`, reboot once the automatic installation is done
+    pub reboot: bool,
+}
+
+impl BootOptions {
+    /// The options on the command line the live system has been booted with
+    pub fn read() -> Self {
+        fs::read_to_string(CMDLINE)
+            .map(|x| Self::parse(&x))
+            .unwrap_or_default()
+    }
+
+    fn parse(cmdline: &str) -> Self {
+        let mut options = BootOptions::default();
+        for word in cmdline.split_whitespace() {
+            let (key, value) = match word.split_once('=') {
+                Some((key, value)) => (key, Some(value.trim_matches('"'))),
+                None => (word, None),
+            };
+            // a flag given without a value is on
+            let on = !matches!(value, Some("0" | "no" | "false" | "off"));
+            match key {
+                "dk.config" => options.config = value.filter(|x| !x.is_empty()).map(String::from),
+                "dk.config_sha256" => options.sha256 = value.map(|x| x.to_lowercase()),
+                "dk.auto" => options.auto = on,
+                "dk.reboot" => options.reboot = on,
+                _ => (),
+            }
+        }
+
+        options
+    }
+
+    /// Refuse to install automatically with an answer file fetched over plain HTTP without
+    /// `dk.config_sha256`, as anyone on the network could replace it with one wiping
+    /// another disk
+    pub fn check_auto(&self) -> Result<()> {
+        let plain_http = self
+            .config
+            .as_deref()
+            .and_then(|x| x.get(..7))
+            .is_some_and(|x| x.eq_ignore_ascii_case("http://"));
+        if self.auto && plain_http && self.sha256.is_none() {
+            return Err(anyhow!(tr!(
+                "Installer will not install automatically with a configuration file fetched over plain HTTP, unless its checksum is given by dk.config_sha256."
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the answer file, checking it against `dk.config_sha256` if given, and keep it
+    /// for `install --config`
+    pub fn fetch_config(&self) -> Result<PathBuf> {
+        let url = self
+            .config
+            .as_deref()
+            .ok_or_else(|| anyhow!("No configuration file is given by dk.config."))?;
+        let mut attempt = 1;
+        let text = loop {
+            match network::fetch_config(url) {
+                Ok(text) => break text,
+                Err(e) if attempt < FETCH_ATTEMPTS => {
+                    warn!("Failed to fetch {} (attempt {}): {}", url, attempt, e);
+                    thread::sleep(FETCH_INTERVAL);
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(anyhow!(tr!(
+                        "Installer could not fetch the configuration file {}: {}",
+                        url,
+                        e
+                    )))
+                }
+            }
+        };
+        if let Some(expected) = self.sha256.as_deref() {
+            let sha256 = hex::encode(Sha256::digest(text.as_bytes()));
+            if sha256 != expected {
+                return Err(anyhow!(tr!(
+                    "The checksum of the configuration file {} is {}, instead of {} given by dk.config_sha256.",
+                    url,
+                    sha256,
+                    expected
+                )));
+            }
+        }
+
+        let path = Path::new(CONFIG_PATH);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // it may hold password hashes, only readable by root from the start
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(text.as_bytes())?;
+
+        Ok(path.to_path_buf())
+    }
+}
+
+/// Whether the automatic installation is to be tried, it is only tried once per boot
+pub(super) fn claim_auto_install() -> bool {
+    if let Some(dir) = Path::new(AUTO_MARKER).parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(AUTO_MARKER)
+    {
+        Ok(_) => true,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => false,
+        Err(e) => {
+            warn!("Failed to mark the automatic installation as tried: {}", e);
+            true
+        }
+    }
+}
+
+#[test]
+fn test_parse_boot_options() {
+    let options = BootOptions::parse(
+        "BOOT_IMAGE=/vmlinuz root=live:CDLABEL=AOSC quiet dk.config=http://server/machine.toml dk.config_sha256=ABC123 dk.auto=1 dk.reboot=1\n",
+    );
+    assert_eq!(
+        options,
+        BootOptions {
+            config: Some("http://server/machine.toml".to_string()),
+            sha256: Some("abc123".to_string()),
+            auto: true,
+            reboot: true,
+        }
+    );
+
+    let options = BootOptions::parse(
+        "quiet dk.config=\"file:///run/media/machine.toml\" dk.auto dk.reboot=0",
+    );
+    assert_eq!(
+        options.config.as_deref(),
+        Some("file:///run/media/machine.toml")
+    );
+    assert!(options.auto && !options.reboot);
+    assert_eq!(
+        BootOptions::parse("quiet splash dk.config="),
+        BootOptions::default()
+    );
+}
+
+#[test]
+fn test_check_auto() {
+    let options = BootOptions::parse("dk.config=http://server/machine.toml dk.auto");
+    assert!(options.check_auto().is_err());
+    let options = BootOptions::parse("dk.config=HTTP://server/machine.toml dk.auto");
+    assert!(options.check_auto().is_err());
+    let options =
+        BootOptions::parse("dk.config=http://server/machine.toml dk.config_sha256=abc123 dk.auto");
+    assert!(options.check_auto().is_ok());
+    let options = BootOptions::parse("dk.config=https://server/machine.toml dk.auto");
+    assert!(options.check_auto().is_ok());
+    let options = BootOptions::parse("dk.config=http://server/machine.toml");
+    assert!(options.check_auto().is_ok());
+}
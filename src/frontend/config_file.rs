@@ -0,0 +1,875 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use crate::{
+    disks::{self, ALLOWED_FS_TYPE},
+    install,
+};
+
+use super::{password_hash, InstallConfig, RootPassword, SwapKind};
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// What a configuration file given to `install --config` holds
+pub(super) enum ConfigSource {
+    /// An answer file written by hand, in TOML
+    File(Box<ConfigFile>),
+    /// A configuration saved by earlier versions of the TUI ("Save Configuration"), in JSON
+    Saved(Box<InstallConfig>),
+}
+
+/// The answers of the wizard for an unattended installation, see res/config-example.toml
+///
+/// Keys left out take the same defaults as in the wizard, unknown keys are refused. The
+/// options of `install` override the keys they answer.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct ConfigFile {
+    /// The variant to install, as listed by `list-tarball`
+    pub(super) variant: Option<String>,
+    /// A mirror name as listed by `list-mirror`, or the URL of a mirror
+    pub(super) mirror: Option<String>,
+    /// Point the package sources of the installed system at the mirror
+    pub(super) use_mirror_repo: Option<bool>,
+    #[serde(default)]
+    pub(super) target: Target,
+    #[serde(default)]
+    pub(super) swap: Swap,
+    /// Defaults to aosc-<variant>-<random>
+    pub(super) hostname: Option<String>,
+    /// Left out when `oobe` is set
+    pub(super) user: Option<User>,
+    /// `none`, `locked` or a crypt(3) hash
+    pub(super) root_password: Option<RootPassword>,
+    pub(super) password_cost: Option<u32>,
+    /// Accept plain text passwords instead of hashes
+    pub(super) allow_plaintext_password: Option<bool>,
+    /// Format the target without asking, even if there is data on it
+    pub(super) allow_data_loss: Option<bool>,
+    /// Create no user, the end user creates their account on first boot
+    pub(super) oobe: Option<bool>,
+    #[serde(default)]
+    pub(super) region: Region,
+    #[serde(default)]
+    pub(super) bootloader: Bootloader,
+    #[serde(default)]
+    pub(super) network: Network,
+    #[serde(default)]
+    pub(super) services: Services,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) extra_packages: Vec<String>,
+    pub(super) nvidia_driver: Option<bool>,
+    pub(super) default_target: Option<String>,
+    pub(super) disable_display_manager: Option<bool>,
+    pub(super) generate_machine_id: Option<bool>,
+    pub(super) verify_files: Option<bool>,
+    #[serde(default)]
+    pub(super) hooks: Hooks,
+}
+
+/// Where to install to, exactly one of `disk`, `partition` and `directory` is set
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Target {
+    /// Guided mode: wipe the whole disk and partition it automatically
+    pub(super) disk: Option<PathBuf>,
+    /// GiB left unpartitioned at the end of `disk`
+    pub(super) keep_free_space: Option<f64>,
+    /// Advanced mode: the system partition
+    pub(super) partition: Option<PathBuf>,
+    /// The filesystem to format `partition` with, the current one is kept by default, or the
+    /// one of the partition created on `disk` (ext4 by default)
+    pub(super) filesystem: Option<String>,
+    /// Reinstall over the AOSC OS installation on `partition`, keeping /home
+    pub(super) preserve_home: Option<bool>,
+    /// Re-create the users of the previous installation (with `preserve_home`)
+    pub(super) restore_users: Option<bool>,
+    /// Other partitions mounted into the installed system (with `partition`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) mounts: Vec<Mount>,
+    /// Install into this existing, empty directory, without partitions nor a bootloader
+    pub(super) directory: Option<PathBuf>,
+}
+
+/// The way the target is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TargetMode<'a> {
+    Disk(&'a Path),
+    Partition(&'a Path),
+    Directory(&'a Path),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Mount {
+    pub(super) partition: PathBuf,
+    pub(super) mount_point: String,
+    #[serde(default)]
+    pub(super) format: bool,
+    /// The filesystem to format the partition with (with `format`)
+    pub(super) filesystem: Option<String>,
+    pub(super) label: Option<String>,
+    /// Mount options replacing the defaults of the filesystem
+    pub(super) options: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Swap {
+    /// `none`, `zram`, `partition` or `file`, recommended from the RAM size by default
+    pub(super) kind: Option<SwapKind>,
+    /// GiB of the swapfile, recommended from the RAM size by default
+    pub(super) size: Option<f64>,
+    /// The existing swap partition for `kind = "partition"`
+    pub(super) partition: Option<PathBuf>,
+    /// Set up resuming from the swap, to be able to hibernate
+    pub(super) hibernation: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct User {
+    pub(super) name: String,
+    /// A yescrypt ($y$) or SHA-512 ($6$) hash
+    pub(super) password: Option<String>,
+    pub(super) full_name: Option<String>,
+    pub(super) uid: Option<u32>,
+    pub(super) groups: Option<Vec<String>>,
+    /// Make the user an administrator (in the wheel group and allowed to use sudo)
+    pub(super) admin: Option<bool>,
+    pub(super) sudo_nopasswd: Option<bool>,
+    pub(super) shell: Option<String>,
+    pub(super) autologin: Option<bool>,
+    /// SSH authorized keys, each a key, file or URL
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) ssh_keys: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Region {
+    /// Defaults to C.UTF-8
+    pub(super) locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) extra_locales: Vec<String>,
+    /// Defaults to UTC
+    pub(super) timezone: Option<String>,
+    /// Keep the RTC (real time clock) in local time instead of UTC
+    pub(super) rtc_local_time: Option<bool>,
+    pub(super) ntp: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) ntp_servers: Vec<String>,
+    /// `<layout>` or `<layout>-<variant>`
+    pub(super) keymap: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Bootloader {
+    pub(super) kernel_cmdline: Option<String>,
+    pub(super) timeout: Option<u32>,
+    pub(super) os_prober: Option<bool>,
+    pub(super) hide_menu: Option<bool>,
+    pub(super) gfxmode: Option<String>,
+    pub(super) boot_splash: Option<bool>,
+    pub(super) hidpi: Option<bool>,
+    pub(super) console_font: Option<String>,
+    /// The board to write the bootloader for, as listed by `list-boards`
+    pub(super) board: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Network {
+    /// Copy the network configuration of the live session (including Wi-Fi passwords)
+    pub(super) copy_config: Option<bool>,
+    pub(super) enable_sshd: Option<bool>,
+    pub(super) ssh_disable_password_auth: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Services {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) enable: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) disable: Vec<String>,
+}
+
+/// Shell commands run during the installation, each with `sh -c`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Hooks {
+    /// Run in the live system before the target is partitioned
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) pre_install: Vec<String>,
+    /// Run in the installed system (chrooted) once it has been configured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) post_install: Vec<String>,
+}
+
+impl Target {
+    pub(super) fn mode(&self) -> Result<TargetMode<'_>> {
+        match (&self.disk, &self.partition, &self.directory) {
+            (Some(disk), None, None) => Ok(TargetMode::Disk(disk)),
+            (None, Some(partition), None) => Ok(TargetMode::Partition(partition)),
+            (None, None, Some(directory)) => Ok(TargetMode::Directory(directory)),
+            _ => Err(anyhow!(
+                "`target`: exactly one of `disk`, `partition` and `directory` must be set."
+            )),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// The keys without which nothing can be installed, left out of the file
+    pub(super) fn missing(&self) -> Vec<&'static str> {
+        let target = &self.target;
+        let mut missing = vec![];
+        if self.variant.is_none() {
+            missing.push("variant");
+        }
+        if target.disk.is_none() && target.partition.is_none() && target.directory.is_none() {
+            missing.push("target");
+        }
+        if !self.oobe.unwrap_or(false) {
+            match &self.user {
+                None => missing.push("user"),
+                Some(user) if user.password.is_none() => missing.push("user.password"),
+                Some(_) => (),
+            }
+        }
+
+        missing
+    }
+
+    /// Check the answers that depend on each other, before anything is looked up, the
+    /// answers `missing` are reported beforehand
+    pub(super) fn check(&self) -> Result<()> {
+        match self.problems().into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Everything `check` refuses, not only the first
+    pub(super) fn problems(&self) -> Vec<anyhow::Error> {
+        let target = &self.target;
+        let mode = match target.mode() {
+            Ok(mode) => mode,
+            Err(e) => return vec![e],
+        };
+        let mut problems = vec![];
+        let only_with = |set: bool, key: &str, with: &str| {
+            set.then(|| anyhow!("`target.{}` is only used with `target.{}`.", key, with))
+        };
+        let is_disk = matches!(mode, TargetMode::Disk(_));
+        let is_partition = matches!(mode, TargetMode::Partition(_));
+        problems.extend(only_with(
+            !is_disk && target.keep_free_space.is_some(),
+            "keep_free_space",
+            "disk",
+        ));
+        if matches!(mode, TargetMode::Directory(_)) && target.filesystem.is_some() {
+            problems.push(anyhow!(
+                "`target.filesystem` is not used with `target.directory`."
+            ));
+        }
+        problems.extend(only_with(
+            !is_partition && target.preserve_home.is_some(),
+            "preserve_home",
+            "partition",
+        ));
+        problems.extend(only_with(
+            !is_partition && !target.mounts.is_empty(),
+            "mounts",
+            "partition",
+        ));
+        if target.restore_users.is_some() && target.preserve_home != Some(true) {
+            problems.push(anyhow!(
+                "`target.restore_users` is only used with `target.preserve_home = true`."
+            ));
+        }
+        if target.keep_free_space.is_some_and(|x| x < 0.0) {
+            problems.push(anyhow!("`target.keep_free_space` may not be negative."));
+        }
+        if let Some(fs_type) = target.filesystem.as_deref() {
+            if !ALLOWED_FS_TYPE.contains(&fs_type) {
+                problems.push(anyhow!(
+                    "`target.filesystem`: {} is not supported, expected one of {}.",
+                    fs_type,
+                    ALLOWED_FS_TYPE.join(", ")
+                ));
+            }
+            if target.preserve_home == Some(true) {
+                problems.push(anyhow!(
+                    "`target.filesystem` can not be changed with `target.preserve_home = true`."
+                ));
+            }
+        }
+        for (i, mount) in target.mounts.iter().enumerate() {
+            if mount.filesystem.is_some() && !mount.format {
+                problems.push(anyhow!(
+                    "`target.mounts[{}].filesystem` is only used with `format = true`.",
+                    i
+                ));
+            }
+        }
+
+        problems.extend(self.swap_problems(mode));
+
+        if self.oobe.unwrap_or(false) && self.user.is_some() {
+            problems.push(anyhow!(
+                "`user` may not be set with `oobe = true`, the account is created on first boot."
+            ));
+        }
+        let has_ssh_keys = self.user.as_ref().is_some_and(|x| !x.ssh_keys.is_empty());
+        if self.network.ssh_disable_password_auth == Some(true) && !has_ssh_keys {
+            problems.push(anyhow!(
+                "`network.ssh_disable_password_auth` requires `user.ssh_keys`."
+            ));
+        }
+        if self.region.ntp == Some(false) && !self.region.ntp_servers.is_empty() {
+            problems.push(anyhow!(
+                "`region.ntp_servers` is not used with `region.ntp = false`."
+            ));
+        }
+        if matches!(mode, TargetMode::Directory(_)) && self.bootloader.is_set() {
+            problems.push(anyhow!(
+                "`bootloader` is not used with `target.directory`, no bootloader is installed into a directory."
+            ));
+        }
+
+        problems
+    }
+
+    fn swap_problems(&self, mode: TargetMode) -> Vec<anyhow::Error> {
+        let swap = &self.swap;
+        let mut problems = vec![];
+        let kind = swap.kind;
+        if swap.size.is_some() && kind != Some(SwapKind::File) {
+            problems.push(anyhow!(
+                "`swap.size` is only used with `swap.kind = \"file\"`."
+            ));
+        }
+        if swap.size.is_some_and(|x| x <= 0.0) {
+            problems.push(anyhow!("`swap.size` must be positive."));
+        }
+        match (kind, swap.partition.is_some()) {
+            (Some(SwapKind::Partition), false) => {
+                problems.push(anyhow!(
+                    "`swap.partition` is missing for `swap.kind = \"partition\"`."
+                ));
+            }
+            (Some(SwapKind::Partition), true) => (),
+            (_, true) => {
+                problems.push(anyhow!(
+                    "`swap.partition` is only used with `swap.kind = \"partition\"`."
+                ));
+            }
+            _ => (),
+        }
+        if swap.hibernation == Some(true) && kind.is_some_and(|x| !x.is_on_disk()) {
+            problems.push(anyhow!(
+                "`swap.hibernation` needs the swap on the disk, a swapfile or a swap partition."
+            ));
+        }
+        if matches!(mode, TargetMode::Directory(_)) && kind.is_some_and(|x| x.is_on_disk()) {
+            problems.push(anyhow!(
+                "`swap.kind`: there is no swap on the disk when installing into `target.directory`."
+            ));
+        }
+
+        problems
+    }
+}
+
+impl Bootloader {
+    fn is_set(&self) -> bool {
+        self.kernel_cmdline.is_some()
+            || self.timeout.is_some()
+            || self.os_prober.is_some()
+            || self.hide_menu.is_some()
+            || self.gfxmode.is_some()
+            || self.board.is_some()
+    }
+}
+
+impl ConfigFile {
+    /// The answer file installing as planned by `config`: the passwords are hashed unless
+    /// `plaintext_passwords` is set, and the devices are referred to by their links in
+    /// /dev/disk/by-id where they have one
+    pub(super) fn from_config(
+        config: &InstallConfig,
+        plaintext_passwords: bool,
+    ) -> Result<ConfigFile> {
+        let stable = |path: &Path| disks::by_id_link(path).unwrap_or_else(|| path.to_path_buf());
+        let cost = config.password_cost;
+        let preserve_home = config.preserve_home.unwrap_or(false);
+
+        let mut target = Target::default();
+        if let Some(dir) = config.target_dir.as_ref() {
+            target.directory = Some(dir.clone());
+        } else if let Some(device) = config.wipe_disk.as_ref() {
+            target.disk = Some(stable(&device.path));
+            target.keep_free_space = config
+                .keep_free_space
+                .filter(|x| *x > 0)
+                .map(|x| x as f64 / GIB);
+            target.filesystem = config.partition.as_ref().and_then(|x| x.fs_type.clone());
+        } else {
+            let partition = config
+                .partition
+                .as_ref()
+                .ok_or_else(|| anyhow!("The target has not been chosen."))?;
+            let path = partition
+                .path
+                .as_deref()
+                .ok_or_else(|| anyhow!("The system partition has not been chosen."))?;
+            target.partition = Some(stable(path));
+            // the filesystem of a preserved /home is kept
+            target.filesystem = partition
+                .fs_type
+                .clone()
+                .filter(|x| !preserve_home && ALLOWED_FS_TYPE.contains(&x.as_str()));
+            target.preserve_home = preserve_home.then_some(true);
+            target.restore_users = config.restore_users.filter(|_| preserve_home);
+            target.mounts = config
+                .mounts
+                .iter()
+                .flat_map(|x| x.iter())
+                .filter(|x| !x.mount_point.is_empty())
+                .filter_map(|x| {
+                    Some(Mount {
+                        partition: stable(x.partition.path.as_deref()?),
+                        mount_point: x.mount_point.clone(),
+                        format: x.format,
+                        filesystem: x.fs_type.clone().filter(|_| x.format),
+                        label: x.label.clone().filter(|_| x.format),
+                        options: x.options.clone(),
+                    })
+                })
+                .collect();
+        }
+
+        let kind = match config.swap {
+            Some(SwapKind::File) if !config.use_swap.v.load(Ordering::SeqCst) => {
+                Some(SwapKind::None)
+            }
+            x => x,
+        };
+        let swap = Swap {
+            kind,
+            size: (*config.swap_size)
+                .filter(|_| kind == Some(SwapKind::File))
+                .map(|x| x / GIB),
+            partition: config
+                .swap_partition
+                .as_ref()
+                .and_then(|x| x.path.as_deref())
+                .filter(|_| kind == Some(SwapKind::Partition))
+                .map(stable),
+            hibernation: kind
+                .filter(|x| x.is_on_disk())
+                .map(|_| config.is_hibernation.v.load(Ordering::SeqCst)),
+        };
+
+        let oobe = config.oobe.unwrap_or(false);
+        let user = match config.user.as_ref().filter(|_| !oobe) {
+            Some(name) => Some(User {
+                name: name.to_string(),
+                password: config
+                    .password
+                    .as_deref()
+                    .map(|x| match plaintext_passwords {
+                        true => Ok(x.to_string()),
+                        false => password_hash(x, cost),
+                    })
+                    .transpose()?,
+                full_name: config.full_name.as_deref().cloned(),
+                uid: config.uid,
+                groups: config.groups.as_deref().cloned(),
+                admin: config.admin,
+                sudo_nopasswd: config.sudo_nopasswd,
+                shell: config.shell.as_deref().cloned(),
+                autologin: config.autologin,
+                ssh_keys: config.ssh_keys.as_deref().cloned().unwrap_or_default(),
+            }),
+            None => None,
+        };
+        let root_password = match config.root_password.as_deref() {
+            // "none" or "locked" would be read back as another mode
+            Some(RootPassword::Password(password))
+                if !plaintext_passwords || RootPassword::is_ambiguous_password(password) =>
+            {
+                Some(RootPassword::Hash(password_hash(password, cost)?))
+            }
+            x => x.cloned(),
+        };
+        let has_plaintext = user
+            .as_ref()
+            .and_then(|x| x.password.as_deref())
+            .is_some_and(|x| !install::is_password_hash(x))
+            || matches!(root_password, Some(RootPassword::Password(_)));
+
+        let ntp = config.enable_ntp;
+        let region = Region {
+            locale: config.locale.as_deref().cloned(),
+            extra_locales: config.extra_locales.as_deref().cloned().unwrap_or_default(),
+            timezone: config.timezone.as_deref().cloned(),
+            rtc_local_time: config.tc.as_deref().map(|x| x == "RTC"),
+            ntp,
+            ntp_servers: config
+                .ntp_servers
+                .as_deref()
+                .cloned()
+                .filter(|_| ntp != Some(false))
+                .unwrap_or_default(),
+            keymap: config.keymap.as_deref().cloned(),
+        };
+        // nothing is installed into a directory to boot it
+        let bootloader = match config.target_dir {
+            Some(_) => Bootloader::default(),
+            None => Bootloader {
+                kernel_cmdline: config.kernel_cmdline.as_deref().cloned(),
+                timeout: config.grub_timeout,
+                os_prober: config.grub_os_prober,
+                hide_menu: config.grub_hidden_menu,
+                gfxmode: config.grub_gfxmode.as_deref().cloned(),
+                boot_splash: config.boot_splash,
+                hidpi: config.hidpi,
+                console_font: config.console_font.as_deref().cloned(),
+                board: config.board.as_deref().cloned(),
+            },
+        };
+
+        Ok(ConfigFile {
+            variant: config.variant.as_ref().map(|x| x.name.clone()),
+            mirror: config.mirror.as_ref().map(|x| x.url.clone()),
+            use_mirror_repo: config.use_mirror_repo,
+            target,
+            swap,
+            hostname: config.hostname.clone(),
+            user,
+            root_password,
+            password_cost: cost,
+            allow_plaintext_password: has_plaintext.then_some(true),
+            allow_data_loss: config.allow_data_loss.filter(|x| *x),
+            oobe: oobe.then_some(true),
+            region,
+            bootloader,
+            network: Network {
+                copy_config: config.copy_network_config,
+                enable_sshd: config.enable_sshd,
+                ssh_disable_password_auth: config.ssh_disable_password_auth,
+            },
+            services: Services {
+                enable: config
+                    .enable_services
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default(),
+                disable: config
+                    .disable_services
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default(),
+            },
+            extra_packages: config
+                .extra_packages
+                .as_deref()
+                .cloned()
+                .unwrap_or_default(),
+            nvidia_driver: config.nvidia_driver,
+            default_target: config.default_target.as_deref().cloned(),
+            disable_display_manager: config.disable_display_manager,
+            generate_machine_id: config.generate_machine_id,
+            verify_files: config.verify_files,
+            hooks: config.hooks.as_deref().cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Follow the links to the devices (e.g., in /dev/disk/by-id), which are looked up by
+    /// their paths in /dev
+    pub(super) fn resolve_device_links(&mut self) {
+        let target = &mut self.target;
+        let paths = target
+            .disk
+            .iter_mut()
+            .chain(target.partition.iter_mut())
+            .chain(target.mounts.iter_mut().map(|x| &mut x.partition))
+            .chain(self.swap.partition.iter_mut());
+        for path in paths {
+            *path = disks::resolve_device_link(path);
+        }
+    }
+}
+
+/// `config` as an answer file for `install --config`, see `ConfigFile::from_config`. What an
+/// answer file can not hold is noted in comments at the top, a user password not known yet
+/// is left for the reader to fill in
+pub(super) fn export(config: &InstallConfig, plaintext_passwords: bool) -> Result<String> {
+    let file = ConfigFile::from_config(config, plaintext_passwords)?;
+    let mut text = String::from(
+        "# Written by `aoscdk-rs --export-config`, see res/config-example.toml for every key\n",
+    );
+    if config.encrypt.unwrap_or(false) {
+        text.push_str("# The system partition is encrypted in the plan, which an answer file can not\n# set up. The passphrase is never exported.\n");
+    }
+    if config.local_release.is_some() {
+        text.push_str("# The plan installs a copy of the system release on this device, the release is\n# downloaded from the mirror instead.\n");
+    }
+    text.push('\n');
+    text.push_str(&toml::to_string_pretty(&file)?);
+    if file.user.as_ref().is_some_and(|x| x.password.is_none()) {
+        text = text.replacen(
+            "[user]\n",
+            "[user]\n# required, a yescrypt ($y$) or SHA-512 ($6$) hash, e.g., from `mkpasswd`\n# password = \"\"\n",
+            1,
+        );
+    }
+
+    Ok(text)
+}
+
+/// Parse and check an answer file as `install --config` does without options
+#[cfg(test)]
+fn parse(text: &str) -> Result<ConfigFile> {
+    let file: ConfigFile = toml::from_str(text)?;
+    let missing = file.missing();
+    if !missing.is_empty() {
+        return Err(anyhow!("missing {}", missing.join(", ")));
+    }
+    file.check()?;
+
+    Ok(file)
+}
+
+/// Whether the configuration file `text` has been saved by an earlier version of the TUI
+/// (JSON), rather than being an answer file
+pub(super) fn is_saved_config(text: &str) -> bool {
+    text.trim_start().starts_with('{')
+}
+
+/// Read the configuration file at `path`, either an answer file or a configuration saved by
+/// an earlier version of the TUI, which is JSON
+///
+/// The errors of an answer file point at the line and the key. It is checked by
+/// `ConfigFile::check` once the options have overridden it.
+pub(super) fn read(path: &Path) -> Result<ConfigSource> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        anyhow!(
+            "Installer failed to read the configuration file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+    let source = if is_saved_config(&text) {
+        serde_json::from_str(&text)
+            .map(|x| ConfigSource::Saved(Box::new(x)))
+            .map_err(anyhow::Error::from)
+    } else {
+        toml::from_str(&text)
+            .map(|x| ConfigSource::File(Box::new(x)))
+            .map_err(anyhow::Error::from)
+    };
+
+    source.map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn test_config_example() {
+    // the example documents every key
+    let file = parse(include_str!("../../res/config-example.toml")).unwrap();
+    assert_eq!(file.variant.as_deref(), Some("Workstation"));
+    assert_eq!(
+        file.target.mode().unwrap(),
+        TargetMode::Partition(Path::new("/dev/sda2"))
+    );
+    assert_eq!(file.target.mounts.len(), 2);
+    assert_eq!(file.swap.kind, Some(SwapKind::File));
+    assert_eq!(file.root_password, Some(RootPassword::Locked));
+    assert_eq!(file.hooks.post_install.len(), 1);
+    assert!(file.user.unwrap().password.unwrap().starts_with("$y$"));
+}
+
+#[test]
+fn test_config_file_errors() {
+    let minimal = "variant = \"Base\"\n[target]\ndisk = \"/dev/sda\"\n[user]\nname = \"aosc\"\npassword = \"$6$salt$hash\"\n";
+    assert!(parse(minimal).is_ok());
+
+    // unknown keys are refused with the line they are on
+    let e = parse(&format!("{minimal}shel = \"/bin/zsh\"\n"))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("line 7") && e.contains("shel"), "{e}");
+    let e = parse("target = { disk = \"/dev/sda\" }\n")
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("variant"), "{e}");
+
+    let e = parse(&minimal.replace("disk = \"/dev/sda\"", ""))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("missing target"), "{e}");
+    let e = parse(&minimal.replace("[target]\n", "[target]\npartition = \"/dev/sda1\"\n"))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("exactly one of"), "{e}");
+    // the partition created on the disk may be formatted otherwise
+    assert!(parse(&minimal.replace("[target]\n", "[target]\nfilesystem = \"xfs\"\n")).is_ok());
+    let directory = minimal.replace("disk = \"/dev/sda\"", "directory = \"/mnt\"");
+    let e = parse(&directory.replace("[target]\n", "[target]\nfilesystem = \"xfs\"\n"))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("`target.filesystem`"), "{e}");
+
+    let e = parse(&format!("{minimal}[swap]\nsize = 4.0\n"))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("`swap.size`"), "{e}");
+    assert!(parse(&format!("{minimal}[swap]\nkind = \"file\"\nsize = 4.0\n")).is_ok());
+    let e = parse(&format!("{minimal}[swap]\nkind = \"partition\"\n"))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("`swap.partition`"), "{e}");
+
+    let oobe = minimal.replace("[user]\nname = \"aosc\"\npassword = \"$6$salt$hash\"\n", "");
+    assert!(parse(&oobe)
+        .unwrap_err()
+        .to_string()
+        .contains("missing user"));
+    // everything missing is reported at once
+    let e = parse(
+        &oobe
+            .replace("variant = \"Base\"\n", "")
+            .replace("disk = \"/dev/sda\"", ""),
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(e.contains("missing variant, target, user"), "{e}");
+    let e = parse(&minimal.replace("password = \"$6$salt$hash\"\n", ""))
+        .unwrap_err()
+        .to_string();
+    assert!(e.contains("missing user.password"), "{e}");
+    assert!(parse(&format!("oobe = true\n{oobe}")).is_ok());
+    assert!(parse(&format!("oobe = true\n{minimal}")).is_err());
+}
+
+#[test]
+fn test_read_saved_config() {
+    let config = InstallConfig {
+        user: Some(std::sync::Arc::new("aosc".to_string())),
+        password: Some(std::sync::Arc::new("$y$j9T$salt$hash".to_string())),
+        hostname: Some("aosc-pc".to_string()),
+        ..Default::default()
+    };
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(file.path(), config.exported_json(false).unwrap()).unwrap();
+    // saved by "Save Configuration" and read back as is
+    match read(file.path()).unwrap() {
+        ConfigSource::Saved(loaded) => assert_eq!(loaded.hostname.as_deref(), Some("aosc-pc")),
+        ConfigSource::File(_) => panic!("a saved configuration is read as an answer file"),
+    }
+
+    std::fs::write(
+        file.path(),
+        "{\n  \"hostname\": \"aosc-pc\",\n  \"hostnme\": 1\n}",
+    )
+    .unwrap();
+    let e = read(file.path()).unwrap_err().to_string();
+    assert!(e.contains("hostnme") && e.contains("line 3"), "{e}");
+}
+
+#[test]
+fn test_export_round_trip() {
+    use super::dry_run::sample_directory_config;
+    use crate::disks::{DkDerive, Partition};
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    let mut config = sample_directory_config();
+    config.password = Some(Arc::new("secret".to_string()));
+    let text = export(&config, false).unwrap();
+    // what is exported passes the checks of `install --config` as it is
+    let file = parse(&text).unwrap();
+    assert_eq!(file.variant.as_deref(), Some("Base"));
+    assert_eq!(
+        file.target.mode().unwrap(),
+        TargetMode::Directory(Path::new("/var/lib/machines/aosc"))
+    );
+    let user = file.user.unwrap();
+    assert_eq!(user.uid, Some(1001));
+    assert!(user.password.unwrap().starts_with("$y$"));
+    assert!(!text.contains("secret"));
+    assert_eq!(file.region.timezone.as_deref(), Some("UTC"));
+    assert_eq!(file.region.rtc_local_time, Some(false));
+    assert_eq!(file.services.enable, ["fstrim.timer"]);
+    assert_eq!(file.hooks.post_install.len(), 1);
+
+    // the guided mode, with a swapfile
+    let gib = 1024 * 1024 * 1024;
+    config.target_dir = None;
+    config.wipe_disk = Some(Arc::new(DkDerive {
+        path: PathBuf::from("/dev/aoscdk-test"),
+        model: "Test Disk".to_string(),
+        size: 64 * gib,
+    }));
+    config.partition = Some(Arc::new(Partition {
+        path: None,
+        parent_path: Some(PathBuf::from("/dev/aoscdk-test")),
+        fs_type: Some("xfs".to_string()),
+        size: 62 * gib,
+    }));
+    config.keep_free_space = Some(2 * gib);
+    config.swap = Some(SwapKind::File);
+    config.use_swap.v.store(true, Ordering::SeqCst);
+    config.swap_size = Arc::new(Some(4.0 * GIB));
+    config.is_hibernation = Arc::new(super::AtomicBoolWrapper {
+        v: AtomicBool::new(true),
+    });
+    let file = parse(&export(&config, false).unwrap()).unwrap();
+    assert_eq!(
+        file.target.mode().unwrap(),
+        TargetMode::Disk(Path::new("/dev/aoscdk-test"))
+    );
+    assert_eq!(file.target.keep_free_space, Some(2.0));
+    assert_eq!(file.target.filesystem.as_deref(), Some("xfs"));
+    assert_eq!(file.swap.kind, Some(SwapKind::File));
+    assert_eq!(file.swap.size, Some(4.0));
+    assert_eq!(file.swap.hibernation, Some(true));
+
+    // a user password not known yet is left to be filled in
+    config.password = None;
+    let text = export(&config, false).unwrap();
+    assert!(text.contains("[user]\n# required"), "{text}");
+    let file: ConfigFile = toml::from_str(&text).unwrap();
+    assert_eq!(file.missing(), ["user.password"]);
+}
+
+#[test]
+fn test_export_plaintext_passwords() {
+    use super::dry_run::sample_directory_config;
+    use std::sync::Arc;
+
+    let mut config = sample_directory_config();
+    config.password = Some(Arc::new("secret".to_string()));
+    config.root_password = Some(Arc::new(RootPassword::Password("hunter2".to_string())));
+    let file = parse(&export(&config, true).unwrap()).unwrap();
+    assert_eq!(file.user.unwrap().password.as_deref(), Some("secret"));
+    assert_eq!(
+        file.root_password,
+        Some(RootPassword::Password("hunter2".to_string()))
+    );
+    assert_eq!(file.allow_plaintext_password, Some(true));
+
+    // "locked" would lock the account instead
+    config.root_password = Some(Arc::new(RootPassword::Password("locked".to_string())));
+    config.password = Some(Arc::new("$y$j9T$salt$hash".to_string()));
+    let file = parse(&export(&config, true).unwrap()).unwrap();
+    assert!(matches!(file.root_password, Some(RootPassword::Hash(_))));
+    assert_eq!(file.allow_plaintext_password, None);
+}
@@ -0,0 +1,309 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{disks::DiskInfo, tr};
+
+use super::human_size;
+
+/// Bumped whenever a field of `list-disks --json` or `list-partitions --json` is renamed,
+/// removed or changes its meaning, adding fields does not bump it
+const SCHEMA_VERSION: u32 = 1;
+
+/// The disks as printed by `list-disks --json`, scripts depend on these fields
+#[derive(Debug, Serialize)]
+pub(super) struct DiskListing<'a> {
+    version: u32,
+    disks: Vec<ListedDisk<'a>>,
+}
+
+/// The partitions as printed by `list-partitions --json`
+#[derive(Debug, Serialize)]
+pub(super) struct PartitionListing<'a> {
+    version: u32,
+    partitions: Vec<ListedPartition<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListedDisk<'a> {
+    path: &'a Path,
+    by_id: Option<&'a Path>,
+    model: &'a str,
+    serial: Option<&'a str>,
+    /// In bytes
+    size: u64,
+    transport: Option<&'a str>,
+    rotational: Option<bool>,
+    /// `gpt` or `msdos`, none without a partition table
+    table: Option<&'a str>,
+    healthy: Option<bool>,
+    /// In bytes
+    unallocated: u64,
+    partitions: Vec<ListedPartition<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListedPartition<'a> {
+    path: Option<&'a Path>,
+    disk: &'a Path,
+    /// In bytes
+    size: u64,
+    fs_type: Option<&'a str>,
+    label: Option<&'a str>,
+    /// The operating system (or boot loaders) found on it
+    os: Option<&'a str>,
+    mounted: bool,
+    /// `[SWAP]` if it is used as swap
+    mount_point: Option<&'a str>,
+    esp: bool,
+    /// Free and used space of its filesystem in bytes, if it could be mounted
+    free: Option<u64>,
+    used: Option<u64>,
+}
+
+impl<'a> DiskListing<'a> {
+    pub fn new(disks: &'a [DiskInfo]) -> Self {
+        DiskListing {
+            version: SCHEMA_VERSION,
+            disks: disks.iter().map(listed_disk).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// An aligned table of the disks
+    pub fn table(&self) -> String {
+        let mut rows = vec![vec![
+            tr!("Disk").to_string(),
+            tr!("Size").to_string(),
+            tr!("Transport").to_string(),
+            tr!("Type").to_string(),
+            tr!("Table").to_string(),
+            tr!("Model").to_string(),
+            tr!("Serial").to_string(),
+            tr!("By ID").to_string(),
+        ]];
+        for disk in self.disks.iter() {
+            rows.push(vec![
+                disk.path.display().to_string(),
+                human_size(disk.size),
+                disk.transport.unwrap_or("-").to_string(),
+                match disk.rotational {
+                    Some(true) => tr!("HDD").to_string(),
+                    Some(false) => tr!("SSD").to_string(),
+                    None => "-".to_string(),
+                },
+                disk.table.unwrap_or("-").to_string(),
+                disk.model.to_string(),
+                disk.serial.unwrap_or("-").to_string(),
+                disk.by_id
+                    .map(|x| x.display().to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ]);
+        }
+
+        table(&rows)
+    }
+}
+
+impl<'a> PartitionListing<'a> {
+    /// The partitions of the disks, or of the disk at `disk` only
+    pub fn new(disks: &'a [DiskInfo], disk: Option<&Path>) -> Self {
+        PartitionListing {
+            version: SCHEMA_VERSION,
+            partitions: disks
+                .iter()
+                .filter(|x| disk.is_none_or(|disk| x.device.path == disk))
+                .flat_map(|x| listed_disk(x).partitions)
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// An aligned table of the partitions
+    pub fn table(&self) -> String {
+        let mut rows = vec![vec![
+            tr!("Partition").to_string(),
+            tr!("Size").to_string(),
+            tr!("Filesystem").to_string(),
+            tr!("Label").to_string(),
+            tr!("Mounted at").to_string(),
+            tr!("ESP").to_string(),
+            tr!("Operating system").to_string(),
+        ]];
+        for partition in self.partitions.iter() {
+            rows.push(vec![
+                partition
+                    .path
+                    .map(|x| x.display().to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                human_size(partition.size),
+                partition.fs_type.unwrap_or("-").to_string(),
+                partition.label.unwrap_or("-").to_string(),
+                partition.mount_point.unwrap_or("-").to_string(),
+                if partition.esp {
+                    tr!("yes").to_string()
+                } else {
+                    "-".to_string()
+                },
+                partition.os.unwrap_or("-").to_string(),
+            ]);
+        }
+
+        table(&rows)
+    }
+}
+
+fn listed_disk(disk: &DiskInfo) -> ListedDisk<'_> {
+    let path = disk.device.path.as_path();
+    let partitions = disk
+        .partitions
+        .iter()
+        .map(|x| ListedPartition {
+            path: x.partition.path.as_deref(),
+            disk: path,
+            size: x.partition.size,
+            fs_type: x.partition.fs_type.as_deref(),
+            label: x.label.as_deref(),
+            os: x.os.as_deref(),
+            mounted: x.mount_point.is_some(),
+            mount_point: x.mount_point.as_deref(),
+            esp: x.esp,
+            free: x.free,
+            used: x.used,
+        })
+        .collect();
+
+    ListedDisk {
+        path,
+        by_id: disk.by_id.as_deref(),
+        model: &disk.device.model,
+        serial: disk.serial.as_deref(),
+        size: disk.device.size,
+        transport: disk.transport.as_deref(),
+        rotational: disk.rotational,
+        table: disk.table.as_deref(),
+        healthy: disk.healthy,
+        unallocated: disk.unallocated,
+        partitions,
+    }
+}
+
+/// The rows with their columns padded to the same width, the first row is the header
+pub(super) fn table(rows: &[Vec<String>]) -> String {
+    let columns = rows.first().map(|x| x.len()).unwrap_or(0);
+    let widths = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let mut text = String::new();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+fn sample_disks() -> Vec<DiskInfo> {
+    use crate::disks::{DkDerive, Partition, PartitionInfo};
+    use std::path::PathBuf;
+
+    let gib = 1024 * 1024 * 1024;
+    let partition = |path: &str, fs_type: &str, size: u64| Partition {
+        path: Some(PathBuf::from(path)),
+        parent_path: Some(PathBuf::from("/dev/nvme0n1")),
+        fs_type: Some(fs_type.to_string()),
+        size,
+    };
+
+    vec![DiskInfo {
+        device: DkDerive {
+            path: PathBuf::from("/dev/nvme0n1"),
+            model: "Samsung SSD 980".to_string(),
+            size: 500 * gib,
+        },
+        serial: Some("S64DNX0R123456".to_string()),
+        by_id: Some(PathBuf::from(
+            "/dev/disk/by-id/nvme-Samsung_SSD_980_S64DNX0R123456",
+        )),
+        rotational: Some(false),
+        transport: Some("NVMe".to_string()),
+        table: Some("gpt".to_string()),
+        healthy: Some(true),
+        unallocated: gib,
+        partitions: vec![
+            PartitionInfo {
+                partition: partition("/dev/nvme0n1p1", "fat32", gib / 2),
+                label: Some("EFI".to_string()),
+                mount_point: None,
+                os: Some("Windows Boot Manager".to_string()),
+                free: Some(gib / 4),
+                used: Some(gib / 4),
+                esp: true,
+                unusable: None,
+            },
+            PartitionInfo {
+                partition: partition("/dev/nvme0n1p2", "ext4", 498 * gib),
+                label: None,
+                mount_point: Some("/mnt".to_string()),
+                os: None,
+                free: None,
+                used: None,
+                esp: false,
+                unusable: None,
+            },
+        ],
+        unusable: None,
+    }]
+}
+
+#[test]
+fn test_disk_listing_snapshot() {
+    let disks = sample_disks();
+    assert_eq!(
+        DiskListing::new(&disks).to_json().unwrap(),
+        include_str!("snapshots/list-disks.json").trim_end()
+    );
+    assert_eq!(
+        PartitionListing::new(&disks, None).to_json().unwrap(),
+        include_str!("snapshots/list-partitions.json").trim_end()
+    );
+    assert!(PartitionListing::new(&disks, Some(Path::new("/dev/sda")))
+        .partitions
+        .is_empty());
+}
+
+#[test]
+fn test_table() {
+    let rows = [
+        vec!["Disk".to_string(), "Size".to_string(), "Model".to_string()],
+        vec![
+            "/dev/sda".to_string(),
+            "1.0 TiB".to_string(),
+            "-".to_string(),
+        ],
+    ];
+    assert_eq!(
+        table(&rows),
+        "Disk      Size     Model\n/dev/sda  1.0 TiB  -\n"
+    );
+}
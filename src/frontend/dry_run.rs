@@ -0,0 +1,1124 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    disks::{self, Partition},
+    install, tr,
+};
+
+use super::{
+    assigned_mounts, boot_splash, data_loss_warning, default_groups, hidpi, human_size, mount_plan,
+    mounts::{self, MountAssignment},
+    planned_kernel_cmdline, swap_partition, InstallConfig, RootPassword, SwapKind, STEP1, STEP5,
+    STEP6, STEP7, STEP8, ZRAM_UNIT,
+};
+
+/// Where the target is mounted while installing, the directory is only created then
+const MOUNT_ROOT: &str = "/tmp/.dkmountXXXXXX";
+const ESP_SIZE: u64 = 512 * 1024 * 1024;
+const PREP_SIZE: u64 = 8 * 1024 * 1024;
+
+/// What an installation would do, as printed by `install --dry-run`
+#[derive(Debug, Serialize)]
+pub(super) struct InstallPlan {
+    variant: String,
+    release: Release,
+    target: Target,
+    /// The warnings about the data on the target erased by formatting it, the partitions are
+    /// not looked into
+    data_loss: Vec<String>,
+    /// The partitions created, formatted or used as they are, in this order
+    partitions: Vec<PlannedPartition>,
+    /// With the device paths, the UUIDs are not known until the partitions are formatted,
+    /// `None` when installing into a directory
+    fstab: Option<String>,
+    swap: Swap,
+    system: System,
+    accounts: Accounts,
+    /// `None` when installing into a directory
+    bootloader: Option<Bootloader>,
+    software: Software,
+    /// The external commands run, those depending on the unpacked system (e.g., whether a
+    /// unit is shipped) may be skipped
+    commands: Vec<PlannedCommand>,
+}
+
+#[derive(Debug, Serialize)]
+struct Release {
+    mirror: String,
+    url: String,
+    /// Installed from this copy on the device instead of downloading it
+    local_release: Option<PathBuf>,
+    sha256sum: String,
+    date: String,
+    download_size: u64,
+    install_size: u64,
+    verify_files: bool,
+    /// The package repository the installed system is pointed at
+    package_repo: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+enum Target {
+    /// The whole disk is repartitioned
+    Disk {
+        device: PathBuf,
+        model: String,
+        size: u64,
+        partition_table: &'static str,
+        keep_free_space: u64,
+    },
+    Partition {
+        path: PathBuf,
+        preserve_home: bool,
+        restore_users: bool,
+    },
+    Directory {
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PartitionAction {
+    /// Created (and formatted) on the repartitioned disk
+    Create,
+    Format,
+    /// Used with the filesystem already on it
+    Reuse,
+}
+
+#[derive(Debug, Serialize)]
+struct PlannedPartition {
+    path: PathBuf,
+    action: PartitionAction,
+    filesystem: Option<String>,
+    size: u64,
+    label: Option<String>,
+    /// `None` if the partition is not mounted, e.g., the PReP boot partition
+    mount_point: Option<String>,
+    options: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Swap {
+    kind: SwapKind,
+    /// The size of the swapfile
+    size: Option<u64>,
+    partition: Option<PathBuf>,
+    hibernation: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct System {
+    hostname: String,
+    locale: String,
+    extra_locales: Vec<String>,
+    timezone: String,
+    rtc_local_time: bool,
+    ntp: bool,
+    ntp_servers: Vec<String>,
+    keymap: Option<String>,
+    /// `None` leaves it to whether the variant ships a display manager
+    default_target: Option<String>,
+    disable_display_manager: bool,
+    generate_machine_id: bool,
+    copy_network_config: bool,
+    enable_sshd: bool,
+    ssh_disable_password_auth: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Accounts {
+    oobe: bool,
+    user: Option<User>,
+    /// `none`, `locked` or `password`
+    root_account: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct User {
+    name: String,
+    full_name: Option<String>,
+    uid: Option<u32>,
+    groups: Vec<String>,
+    admin: bool,
+    sudo_nopasswd: bool,
+    shell: String,
+    autologin: bool,
+    ssh_keys: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Bootloader {
+    /// `uefi`, `prep` or `bios`
+    firmware: &'static str,
+    /// The parameters added to the distribution defaults
+    kernel_cmdline: Option<String>,
+    timeout: Option<u32>,
+    os_prober: bool,
+    hide_menu: bool,
+    gfxmode: Option<String>,
+    boot_splash: bool,
+    hidpi: bool,
+    console_font: Option<String>,
+    board: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Software {
+    extra_packages: Vec<String>,
+    nvidia_driver: bool,
+    enable_services: Vec<String>,
+    disable_services: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PlannedCommand {
+    /// The installation step running the command
+    step: &'static str,
+    /// Run in the installed system
+    chroot: bool,
+    command: String,
+}
+
+impl InstallPlan {
+    /// Work out what installing `config` would do, without changing anything
+    pub(super) fn new(config: &InstallConfig) -> Result<Self> {
+        let variant = config.variant.as_deref().unwrap();
+        let mirror = config.mirror.as_deref().unwrap();
+        let (target, partitions, fstab) = plan_storage(config)?;
+        let data_loss = planned_data_loss(config);
+
+        let swap = Swap {
+            kind: config.swap.unwrap_or(SwapKind::None),
+            size: (*config.swap_size)
+                .filter(|_| config.use_swap.v.load(Ordering::SeqCst))
+                .map(|x| x as u64),
+            partition: swap_partition(config).and_then(|x| x.path.clone()),
+            hibernation: config.is_hibernation.v.load(Ordering::SeqCst),
+        };
+        let locale = config.locale.as_deref().unwrap().clone();
+        let system = System {
+            hostname: config.hostname.clone().unwrap(),
+            extra_locales: config
+                .extra_locales
+                .iter()
+                .flat_map(|x| x.iter())
+                .filter(|x| **x != locale)
+                .cloned()
+                .collect(),
+            locale,
+            timezone: config.timezone.as_deref().unwrap().clone(),
+            rtc_local_time: config.tc.as_deref().map(|x| x.as_str()) == Some("RTC"),
+            ntp: config.enable_ntp.unwrap_or(true),
+            ntp_servers: config.ntp_servers.as_deref().cloned().unwrap_or_default(),
+            keymap: config.keymap.as_deref().cloned(),
+            default_target: config.default_target.as_deref().cloned(),
+            disable_display_manager: config.disable_display_manager.unwrap_or(false),
+            generate_machine_id: config.generate_machine_id.unwrap_or(false),
+            copy_network_config: config.copy_network_config.unwrap_or(true),
+            enable_sshd: config.enable_sshd.unwrap_or(false),
+            ssh_disable_password_auth: config.ssh_disable_password_auth.unwrap_or(false),
+        };
+        let oobe = config.oobe.unwrap_or(false);
+        let user = config.user.as_deref().filter(|_| !oobe).map(|name| {
+            let admin = config.admin.unwrap_or(true);
+            let mut groups = config
+                .groups
+                .as_deref()
+                .cloned()
+                .unwrap_or_else(default_groups);
+            if !admin {
+                groups.retain(|x| x != "wheel");
+            }
+            User {
+                name: name.clone(),
+                full_name: config
+                    .full_name
+                    .as_deref()
+                    .filter(|x| !x.is_empty())
+                    .cloned(),
+                uid: config.uid,
+                groups,
+                admin,
+                sudo_nopasswd: config.sudo_nopasswd.unwrap_or(false),
+                shell: config
+                    .shell
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_else(|| install::DEFAULT_SHELL.to_string()),
+                autologin: config.autologin.unwrap_or(false),
+                ssh_keys: config.ssh_keys.as_ref().map(|x| x.len()).unwrap_or(0),
+            }
+        });
+        let accounts = Accounts {
+            oobe,
+            user,
+            root_account: match config.root_password.as_deref() {
+                _ if oobe => "none",
+                None | Some(RootPassword::None) => "none",
+                Some(RootPassword::Locked) => "locked",
+                Some(RootPassword::Hash(_) | RootPassword::Password(_)) => "password",
+            },
+        };
+        let bootloader = config.target_dir.is_none().then(|| Bootloader {
+            firmware: firmware(),
+            kernel_cmdline: planned_kernel_cmdline(config),
+            timeout: config.grub_timeout,
+            os_prober: config.grub_os_prober.unwrap_or(false),
+            hide_menu: config.grub_hidden_menu.unwrap_or(false),
+            gfxmode: config
+                .grub_gfxmode
+                .as_deref()
+                .cloned()
+                .or_else(|| hidpi(config).then(|| install::HIDPI_GRUB_GFXMODE.to_string())),
+            boot_splash: boot_splash(config),
+            hidpi: hidpi(config),
+            console_font: hidpi(config).then(|| {
+                config
+                    .console_font
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_else(|| install::HIDPI_CONSOLE_FONT.to_string())
+            }),
+            board: config.board.as_deref().cloned(),
+        });
+        let software = Software {
+            extra_packages: config
+                .extra_packages
+                .as_deref()
+                .cloned()
+                .unwrap_or_default(),
+            nvidia_driver: config.nvidia_driver.unwrap_or(false),
+            enable_services: config
+                .enable_services
+                .as_deref()
+                .cloned()
+                .unwrap_or_default(),
+            disable_services: config
+                .disable_services
+                .as_deref()
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        let mut plan = InstallPlan {
+            variant: variant.name.clone(),
+            release: Release {
+                mirror: mirror.url.clone(),
+                url: format!("{}{}", mirror.url, variant.url),
+                local_release: config.local_release.as_deref().cloned(),
+                sha256sum: variant.sha256sum.clone(),
+                date: variant.date.clone(),
+                download_size: variant.size,
+                install_size: variant.install_size,
+                verify_files: config.verify_files.unwrap_or(true),
+                package_repo: mirror
+                    .package_repo()
+                    .filter(|_| config.use_mirror_repo.unwrap_or(true)),
+            },
+            target,
+            data_loss,
+            partitions,
+            fstab,
+            swap,
+            system,
+            accounts,
+            bootloader,
+            software,
+            commands: vec![],
+        };
+        plan.commands = plan.list_commands(config)?;
+
+        Ok(plan)
+    }
+
+    /// The external commands run by the installation, in this order
+    fn list_commands(&self, config: &InstallConfig) -> Result<Vec<PlannedCommand>> {
+        let mut commands = vec![];
+        let mut push = |step: &'static str, chroot: bool, program: &str, args: &[&str]| {
+            commands.push(PlannedCommand {
+                step,
+                chroot,
+                command: command_line(program, args),
+            })
+        };
+        let root = match &self.target {
+            Target::Directory { path } => path.clone(),
+            _ => PathBuf::from(MOUNT_ROOT),
+        };
+        let root_arg = format!("--root={}", root.display());
+        let hooks = config.hooks.as_deref().cloned().unwrap_or_default();
+
+        for hook in &hooks.pre_install {
+            push(STEP1, false, "sh", &["-c", hook]);
+        }
+        for partition in &self.partitions {
+            if partition.action == PartitionAction::Reuse || partition.filesystem.is_none() {
+                continue;
+            }
+            let (program, args) =
+                disks::mkfs_command(&partition.to_partition(), partition.label.as_deref())?;
+            let args = args
+                .iter()
+                .map(|x| x.to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            push(
+                STEP1,
+                false,
+                &program,
+                &args.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+            );
+        }
+        if self.swap.size.is_some() {
+            let swapfile = root.join("swapfile");
+            let swapfile = swapfile.to_string_lossy();
+            push(STEP1, false, "mkswap", &[&swapfile]);
+            push(STEP1, false, "swapon", &[&swapfile]);
+        }
+
+        if !cfg!(feature = "is_retro") {
+            push(STEP5, true, "/usr/bin/update-initramfs", &[]);
+        }
+        if self.bootloader.is_some() {
+            let mbr_dev = config
+                .partition
+                .as_ref()
+                .and_then(|x| x.parent_path.clone())
+                .filter(|_| !disks::is_efi_booted());
+            // the PReP boot partition is not there before the disk is repartitioned
+            let grub_commands = match &self.target {
+                Target::Disk { .. } => install::grub_commands(mbr_dev.as_ref()).unwrap_or_default(),
+                _ => install::grub_commands(mbr_dev.as_ref())?,
+            };
+            for (program, args) in grub_commands {
+                push(
+                    STEP6,
+                    true,
+                    program,
+                    &args.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+                );
+            }
+        }
+        if cfg!(feature = "is_retro") {
+            push(STEP7, true, "ssh-keygen", &["-A"]);
+        }
+
+        if self.system.generate_machine_id {
+            push(STEP8, true, "systemd-machine-id-setup", &[]);
+        }
+        if let Some(user) = self.accounts.user.as_ref() {
+            let uid = user.uid.map(|x| x.to_string());
+            let mut args = vec!["-m", "-s", install::DEFAULT_SHELL];
+            if let Some(uid) = uid.as_deref() {
+                args.extend(["-u", uid]);
+            }
+            args.push(&user.name);
+            push(STEP8, true, "useradd", &args);
+            if !user.groups.is_empty() {
+                push(
+                    STEP8,
+                    true,
+                    "usermod",
+                    &["-aG", &user.groups.join(","), &user.name],
+                );
+            }
+            push(STEP8, true, "chpasswd", &["-e"]);
+        }
+        if self.accounts.root_account == "password" {
+            push(STEP8, true, "chpasswd", &["-e"]);
+        }
+        push(STEP8, true, "locale-gen", &[]);
+        let mut install_packages = |packages: &[&str]| {
+            let mut args = vec!["install", "-y"];
+            args.extend(packages);
+            push(STEP8, true, "oma", &args);
+        };
+        if !self.software.extra_packages.is_empty() {
+            install_packages(
+                &self
+                    .software
+                    .extra_packages
+                    .iter()
+                    .map(|x| x.as_str())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        // only if the font is missing from the installed system
+        if self.bootloader.as_ref().is_some_and(|x| x.hidpi) {
+            install_packages(&[install::CONSOLE_FONT_PACKAGE]);
+        }
+        if self.software.nvidia_driver {
+            install_packages(install::NVIDIA_DRIVER_PACKAGES);
+            push(STEP8, true, "/usr/bin/update-initramfs", &[]);
+        }
+        if let Some(user) = self
+            .accounts
+            .user
+            .as_ref()
+            .filter(|x| x.shell != install::DEFAULT_SHELL)
+        {
+            push(STEP8, true, "chsh", &["-s", &user.shell, &user.name]);
+        }
+
+        if self.system.enable_sshd {
+            push(
+                STEP8,
+                false,
+                "systemctl",
+                &["enable", &root_arg, "sshd.service"],
+            );
+        }
+        if let Some(target) = self.system.default_target.as_deref() {
+            push(
+                STEP8,
+                false,
+                "systemctl",
+                &["set-default", &root_arg, target],
+            );
+        }
+        if self.swap.kind == SwapKind::Zram {
+            push(STEP8, false, "systemctl", &["enable", &root_arg, ZRAM_UNIT]);
+        }
+        for (action, units) in [
+            ("enable", &self.software.enable_services),
+            ("disable", &self.software.disable_services),
+        ] {
+            for unit in units {
+                push(
+                    STEP8,
+                    false,
+                    "systemctl",
+                    &[action, &root_arg, &install::unit_name(unit)],
+                );
+            }
+        }
+        for hook in &hooks.post_install {
+            push(STEP8, true, "sh", &["-c", hook]);
+        }
+
+        Ok(commands)
+    }
+
+    /// The plan as JSON, for scripts checking it
+    pub(super) fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The plan as text, section by section
+    pub(super) fn describe(&self) -> String {
+        let mut lines = vec![tr!("Installer would perform the following operations:").to_string()];
+        let yes_no = |x: bool| if x { tr!("yes") } else { tr!("no") };
+
+        lines.push(String::new());
+        lines.push(tr!("Target:").to_string());
+        lines.push(match &self.target {
+            Target::Disk {
+                device,
+                model,
+                size,
+                partition_table,
+                keep_free_space,
+            } => {
+                let mut s = tr!(
+                    "- {} ({}, {}) will be wiped and repartitioned ({}).",
+                    device.display(),
+                    model,
+                    human_size(*size),
+                    partition_table
+                );
+                if *keep_free_space > 0 {
+                    s.push_str(&tr!(
+                        " {} will be left unpartitioned at its end.",
+                        human_size(*keep_free_space)
+                    ));
+                }
+                s
+            }
+            Target::Partition {
+                path,
+                preserve_home: true,
+                restore_users,
+            } => tr!(
+                "- AOSC OS will be reinstalled on {}, keeping /home (users restored: {}).",
+                path.display(),
+                yes_no(*restore_users)
+            ),
+            Target::Partition { path, .. } => {
+                tr!("- AOSC OS will be installed on {}.", path.display())
+            }
+            Target::Directory { path } => tr!(
+                "- AOSC OS will be installed into {}, without partitions nor a bootloader.",
+                path.display()
+            ),
+        });
+        for partition in &self.partitions {
+            let action = match partition.action {
+                PartitionAction::Create => tr!("create"),
+                PartitionAction::Format => tr!("format"),
+                PartitionAction::Reuse => tr!("keep as is"),
+            };
+            let mut s = format!(
+                "  {} [{}] {} {}",
+                partition.path.display(),
+                action,
+                partition.filesystem.as_deref().unwrap_or("-"),
+                human_size(partition.size)
+            );
+            if let Some(mount_point) = partition.mount_point.as_deref() {
+                s.push_str(&format!(" -> {mount_point}"));
+            }
+            if let Some(label) = partition.label.as_deref() {
+                s.push_str(&tr!(", label: {}", label));
+            }
+            if let Some(options) = partition.options.as_deref() {
+                s.push_str(&tr!(", options: {}", options));
+            }
+            lines.push(s);
+        }
+        for warning in &self.data_loss {
+            lines.push(String::new());
+            lines.push(warning.clone());
+        }
+        if let Some(fstab) = self.fstab.as_deref() {
+            lines.push(String::new());
+            lines.push(tr!(
+                "/etc/fstab (with the UUIDs of the partitions once formatted):\n{}",
+                fstab.trim_end()
+            ));
+        }
+
+        lines.push(String::new());
+        lines.push(tr!("System release:").to_string());
+        let release = &self.release;
+        lines.push(tr!("- Variant: {} ({})", self.variant, release.date));
+        match release.local_release.as_ref() {
+            Some(path) => lines.push(tr!("- Installed from: {}", path.display())),
+            None => lines.push(tr!("- Downloaded from: {}", release.url)),
+        }
+        lines.push(tr!(
+            "- Download size: {}, installed size: {}",
+            human_size(release.download_size),
+            human_size(release.install_size)
+        ));
+        lines.push(tr!("- SHA-256: {}", release.sha256sum));
+        lines.push(tr!(
+            "- Verify unpacked files: {}",
+            yes_no(release.verify_files)
+        ));
+        lines.push(match release.package_repo.as_deref() {
+            Some(repo) => tr!("- Package repository: {}", repo),
+            None => tr!("- Package repository: unchanged").to_string(),
+        });
+
+        lines.push(String::new());
+        lines.push(tr!("Swap:").to_string());
+        lines.push(
+            match (self.swap.kind, self.swap.size, &self.swap.partition) {
+                (SwapKind::Zram, _, _) => tr!("- Compressed swap in the RAM (zram)").to_string(),
+                (SwapKind::Partition, _, Some(path)) => {
+                    tr!("- Swap partition: {}", path.display())
+                }
+                (_, Some(size), _) => tr!("- Swapfile: {}", human_size(size)),
+                _ => tr!("- None").to_string(),
+            },
+        );
+        lines.push(tr!("- Hibernation: {}", yes_no(self.swap.hibernation)));
+
+        lines.push(String::new());
+        lines.push(tr!("System:").to_string());
+        let system = &self.system;
+        lines.push(tr!("- Hostname: {}", system.hostname));
+        let mut locales = vec![system.locale.as_str()];
+        locales.extend(system.extra_locales.iter().map(|x| x.as_str()));
+        lines.push(tr!("- Locales: {}", locales.join(", ")));
+        lines.push(tr!(
+            "- Timezone: {} (RTC in {})",
+            system.timezone,
+            if system.rtc_local_time {
+                tr!("local time")
+            } else {
+                "UTC"
+            }
+        ));
+        lines.push(match (system.ntp, system.ntp_servers.is_empty()) {
+            (false, _) => tr!("- Network time synchronisation: {}", tr!("no")),
+            (true, true) => tr!("- Network time synchronisation: {}", tr!("yes")),
+            (true, false) => tr!(
+                "- Network time synchronisation: {}",
+                system.ntp_servers.join(", ")
+            ),
+        });
+        if let Some(keymap) = system.keymap.as_deref() {
+            lines.push(tr!("- Keyboard layout: {}", keymap));
+        }
+        lines.push(tr!(
+            "- Default target: {}",
+            system
+                .default_target
+                .as_deref()
+                .unwrap_or(tr!("graphical.target if a display manager is shipped"))
+        ));
+        if system.disable_display_manager {
+            lines.push(tr!("- The display manager will be disabled.").to_string());
+        }
+        lines.push(tr!(
+            "- Copy network configuration: {}",
+            yes_no(system.copy_network_config)
+        ));
+        lines.push(tr!(
+            "- SSH server: {}{}",
+            yes_no(system.enable_sshd),
+            if system.ssh_disable_password_auth {
+                tr!(" (password authentication disabled)")
+            } else {
+                ""
+            }
+        ));
+
+        lines.push(String::new());
+        lines.push(tr!("Accounts:").to_string());
+        match self.accounts.user.as_ref() {
+            Some(user) => {
+                lines.push(tr!(
+                    "- User: {}{} (administrator: {}, shell: {})",
+                    user.name,
+                    user.full_name
+                        .as_deref()
+                        .map(|x| format!(" ({x})"))
+                        .unwrap_or_default(),
+                    yes_no(user.admin),
+                    user.shell
+                ));
+                lines.push(tr!(
+                    "- UID: {}, groups: {}",
+                    user.uid
+                        .map(|x| x.to_string())
+                        .unwrap_or_else(|| tr!("automatic").to_string()),
+                    user.groups.join(",")
+                ));
+                if user.sudo_nopasswd {
+                    lines.push(tr!("- sudo without a password").to_string());
+                }
+                if user.autologin {
+                    lines.push(tr!("- Logged in automatically on boot").to_string());
+                }
+                if user.ssh_keys > 0 {
+                    lines.push(tr!("- SSH authorized keys: {}", user.ssh_keys));
+                }
+            }
+            None => {
+                lines.push(tr!("- No user, the account will be created on first boot.").to_string())
+            }
+        }
+        lines.push(tr!(
+            "- Root account: {}",
+            match self.accounts.root_account {
+                "locked" => tr!("locked"),
+                "password" => tr!("password set"),
+                _ => tr!("unchanged"),
+            }
+        ));
+
+        if let Some(bootloader) = self.bootloader.as_ref() {
+            lines.push(String::new());
+            lines.push(tr!("Bootloader:").to_string());
+            lines.push(tr!("- GRUB ({})", bootloader.firmware));
+            if let Some(cmdline) = bootloader.kernel_cmdline.as_deref() {
+                lines.push(tr!("- Kernel command line: {}", cmdline));
+            }
+            if let Some(timeout) = bootloader.timeout {
+                lines.push(tr!("- Menu timeout: {}s", timeout));
+            }
+            lines.push(tr!(
+                "- Detect other operating systems: {}, hide menu: {}",
+                yes_no(bootloader.os_prober),
+                yes_no(bootloader.hide_menu)
+            ));
+            if let Some(gfxmode) = bootloader.gfxmode.as_deref() {
+                lines.push(tr!("- Graphics mode: {}", gfxmode));
+            }
+            lines.push(tr!("- Boot splash: {}", yes_no(bootloader.boot_splash)));
+            if let Some(font) = bootloader.console_font.as_deref() {
+                lines.push(tr!("- HiDPI console font: {}", font));
+            }
+            if let Some(board) = bootloader.board.as_deref() {
+                lines.push(tr!("- Board: {}", board));
+            }
+        }
+
+        let software = &self.software;
+        if !software.extra_packages.is_empty()
+            || software.nvidia_driver
+            || !software.enable_services.is_empty()
+            || !software.disable_services.is_empty()
+        {
+            lines.push(String::new());
+            lines.push(tr!("Software:").to_string());
+            if !software.extra_packages.is_empty() {
+                lines.push(tr!(
+                    "- Additional packages: {}",
+                    software.extra_packages.join(", ")
+                ));
+            }
+            if software.nvidia_driver {
+                lines.push(tr!("- The proprietary NVIDIA driver will be installed.").to_string());
+            }
+            if !software.enable_services.is_empty() {
+                lines.push(tr!(
+                    "- Enabled units: {}",
+                    software.enable_services.join(", ")
+                ));
+            }
+            if !software.disable_services.is_empty() {
+                lines.push(tr!(
+                    "- Disabled units: {}",
+                    software.disable_services.join(", ")
+                ));
+            }
+        }
+
+        lines.push(String::new());
+        lines.push(tr!("Commands:").to_string());
+        let mut step = "";
+        for command in &self.commands {
+            if command.step != step {
+                step = command.step;
+                lines.push(format!("{}:", tr!(step)));
+            }
+            if command.chroot {
+                lines.push(format!("  (chroot) {}", command.command));
+            } else {
+                lines.push(format!("  {}", command.command));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// The warnings about the data erased on the target, without mounting anything: every
+/// partition of a wiped disk, or the system partition unless /home is kept on it
+fn planned_data_loss(config: &InstallConfig) -> Vec<String> {
+    if config.target_dir.is_some() {
+        return vec![];
+    }
+    let partitions = match config.wipe_disk.as_deref() {
+        Some(device) => disks::list_partitions(Some(device.path.clone())),
+        None if config.preserve_home.unwrap_or(false) => vec![],
+        None => config.partition.as_deref().cloned().into_iter().collect(),
+    };
+
+    partitions
+        .iter()
+        .filter_map(|x| data_loss_warning(x, false))
+        .collect()
+}
+
+/// The target of `config`, the partitions it uses and the fstab of the installed system
+fn plan_storage(config: &InstallConfig) -> Result<(Target, Vec<PlannedPartition>, Option<String>)> {
+    if let Some(dir) = config.target_dir.as_ref() {
+        return Ok((Target::Directory { path: dir.clone() }, vec![], None));
+    }
+
+    let partition = config.partition.as_deref().unwrap();
+    let swapfile = config.use_swap.v.load(Ordering::SeqCst);
+    let mut partitions = vec![];
+    let (target, fstab) = match config.wipe_disk.as_deref() {
+        Some(device) => {
+            let is_efi = disks::is_efi_booted();
+            let mut number = 0;
+            let mut next_path = || {
+                number += 1;
+                new_partition_path(&device.path, number)
+            };
+            let mut plan = install::MountPlan {
+                partitions: vec![],
+                swapfile,
+                extra: vec![],
+                options: HashMap::new(),
+            };
+            if is_efi {
+                let esp = PlannedPartition {
+                    path: next_path(),
+                    action: PartitionAction::Create,
+                    filesystem: Some("vfat".to_string()),
+                    size: ESP_SIZE,
+                    label: None,
+                    mount_point: Some(mounts::ESP_MOUNT_POINT.to_string()),
+                    options: None,
+                };
+                plan.partitions
+                    .push((esp.to_partition(), PathBuf::from(mounts::ESP_MOUNT_POINT)));
+                partitions.push(esp);
+            }
+            if disks::needs_prep_partition() {
+                partitions.push(PlannedPartition {
+                    path: next_path(),
+                    action: PartitionAction::Create,
+                    filesystem: None,
+                    size: PREP_SIZE,
+                    label: None,
+                    mount_point: None,
+                    options: None,
+                });
+            }
+            let system = PlannedPartition {
+                path: next_path(),
+                action: PartitionAction::Create,
+                filesystem: Some("ext4".to_string()),
+                size: partition.size,
+                label: None,
+                mount_point: Some("/".to_string()),
+                options: None,
+            };
+            plan.partitions
+                .insert(0, (system.to_partition(), PathBuf::from("/")));
+            partitions.push(system);
+            let target = Target::Disk {
+                device: device.path.clone(),
+                model: device.model.clone(),
+                size: device.size,
+                partition_table: if is_efi { "gpt" } else { "msdos" },
+                keep_free_space: config.keep_free_space.unwrap_or(0),
+            };
+
+            (target, plan.preview()?)
+        }
+        None => {
+            let path = partition.path.clone().unwrap();
+            let preserve_home = config.preserve_home.unwrap_or(false);
+            partitions.push(PlannedPartition {
+                path: path.clone(),
+                action: if preserve_home {
+                    PartitionAction::Reuse
+                } else {
+                    PartitionAction::Format
+                },
+                filesystem: partition.fs_type.clone(),
+                size: partition.size,
+                label: None,
+                mount_point: Some("/".to_string()),
+                options: None,
+            });
+            let assigned = assigned_mounts(config);
+            if disks::is_efi_booted() && !mounts::has_esp(assigned) {
+                let esp = disks::find_esp_partition(partition.parent_path.as_ref().unwrap())?;
+                partitions.push(PlannedPartition {
+                    path: esp.path.clone().unwrap_or_default(),
+                    action: if esp.fs_type.is_none() {
+                        PartitionAction::Format
+                    } else {
+                        PartitionAction::Reuse
+                    },
+                    filesystem: Some(esp.fs_type.unwrap_or_else(|| "vfat".to_string())),
+                    size: esp.size,
+                    label: None,
+                    mount_point: Some(mounts::ESP_MOUNT_POINT.to_string()),
+                    options: None,
+                });
+            }
+            partitions.extend(assigned.iter().map(PlannedPartition::assigned));
+            if let Some(swap) = swap_partition(config) {
+                partitions.push(PlannedPartition {
+                    path: swap.path.clone().unwrap_or_default(),
+                    action: PartitionAction::Reuse,
+                    filesystem: swap.fs_type.clone(),
+                    size: swap.size,
+                    label: None,
+                    mount_point: Some("swap".to_string()),
+                    options: None,
+                });
+            }
+            // the /home of the previous installation is added as it is, once it is found
+            let fstab = mount_plan(partition, None, swapfile, swap_partition(config), assigned)?
+                .preview()?;
+            let target = Target::Partition {
+                path,
+                preserve_home,
+                restore_users: config.restore_users.unwrap_or(false),
+            };
+
+            (target, fstab)
+        }
+    };
+
+    Ok((target, partitions, Some(fstab)))
+}
+
+impl PlannedPartition {
+    fn assigned(mount: &MountAssignment) -> Self {
+        let partition = mount.target_partition();
+        PlannedPartition {
+            path: partition.path.clone().unwrap_or_default(),
+            action: if mount.format {
+                PartitionAction::Format
+            } else {
+                PartitionAction::Reuse
+            },
+            filesystem: partition.fs_type,
+            size: partition.size,
+            label: mount.label.clone().filter(|_| mount.format),
+            mount_point: Some(mount.mount_point.clone()).filter(|x| !x.is_empty()),
+            options: mount.options.clone(),
+        }
+    }
+
+    fn to_partition(&self) -> Partition {
+        Partition {
+            path: Some(self.path.clone()),
+            parent_path: None,
+            fs_type: self.filesystem.clone(),
+            size: self.size,
+        }
+    }
+}
+
+/// The firmware GRUB is installed for
+fn firmware() -> &'static str {
+    if disks::is_efi_booted() {
+        "uefi"
+    } else if disks::needs_prep_partition() {
+        "prep"
+    } else {
+        "bios"
+    }
+}
+
+/// The path of partition `number` on `disk`, e.g., /dev/sda1 or /dev/nvme0n1p1
+fn new_partition_path(disk: &Path, number: usize) -> PathBuf {
+    let disk = disk.to_string_lossy();
+    if disk.ends_with(|x: char| x.is_ascii_digit()) {
+        PathBuf::from(format!("{disk}p{number}"))
+    } else {
+        PathBuf::from(format!("{disk}{number}"))
+    }
+}
+
+/// `program` with `args`, quoted where a shell would split them
+fn command_line(program: &str, args: &[&str]) -> String {
+    let mut words = vec![program.to_string()];
+    for arg in args {
+        if arg.is_empty()
+            || arg.contains(|x: char| x.is_whitespace() || "'\"$`\\;&|<>*?()".contains(x))
+        {
+            words.push(format!("'{}'", arg.replace('\'', "'\\''")));
+        } else {
+            words.push(arg.to_string());
+        }
+    }
+
+    words.join(" ")
+}
+
+#[test]
+fn test_new_partition_path() {
+    assert_eq!(
+        new_partition_path(Path::new("/dev/sda"), 2),
+        PathBuf::from("/dev/sda2")
+    );
+    assert_eq!(
+        new_partition_path(Path::new("/dev/nvme0n1"), 1),
+        PathBuf::from("/dev/nvme0n1p1")
+    );
+    assert_eq!(
+        new_partition_path(Path::new("/dev/mmcblk0"), 3),
+        PathBuf::from("/dev/mmcblk0p3")
+    );
+}
+
+#[test]
+fn test_command_line() {
+    assert_eq!(
+        command_line("mkfs.ext4", &["-F", "/dev/sda2"]),
+        "mkfs.ext4 -F /dev/sda2"
+    );
+    assert_eq!(
+        command_line("grub-install", &["--bootloader-id=AOSC OS", "--removable"]),
+        "grub-install '--bootloader-id=AOSC OS' --removable"
+    );
+    assert_eq!(
+        command_line("sh", &["-c", "echo 'hi' > /etc/motd"]),
+        "sh -c 'echo '\\''hi'\\'' > /etc/motd'"
+    );
+}
+
+/// An installation into a directory, which can be planned without any disk
+#[cfg(test)]
+pub(super) fn sample_directory_config() -> InstallConfig {
+    use super::config_file::Hooks;
+    use crate::network::{Mirror, VariantEntry};
+    use std::sync::Arc;
+
+    InstallConfig {
+        variant: Some(Arc::new(VariantEntry {
+            name: "Base".to_string(),
+            size: 1024,
+            install_size: 4096,
+            date: "20240101".to_string(),
+            sha256sum: "abc".to_string(),
+            url: "os-amd64/base/aosc-os_base_20240101_amd64.tar.xz".to_string(),
+            manifest: None,
+            description: String::new(),
+            description_tr: String::new(),
+        })),
+        mirror: Some(Arc::new(Mirror {
+            name: "origin".to_string(),
+            name_tr: String::new(),
+            loc: String::new(),
+            loc_tr: String::new(),
+            url: "https://repo.aosc.io/aosc-os/".to_string(),
+            repo_url: None,
+        })),
+        target_dir: Some(PathBuf::from("/var/lib/machines/aosc")),
+        user: Some(Arc::new("aosc".to_string())),
+        password: Some(Arc::new("$y$j9T$salt$hash".to_string())),
+        uid: Some(1001),
+        hostname: Some("aosc-pc".to_string()),
+        locale: Some(Arc::new("en_US.UTF-8".to_string())),
+        extra_locales: Some(Arc::new(vec!["en_US.UTF-8".to_string()])),
+        timezone: Some(Arc::new("UTC".to_string())),
+        tc: Some(Arc::new("UTC".to_string())),
+        enable_services: Some(Arc::new(vec!["fstrim.timer".to_string()])),
+        hooks: Some(Arc::new(Hooks {
+            pre_install: vec![],
+            post_install: vec!["echo done > /etc/motd".to_string()],
+        })),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_directory_plan() {
+    let plan = InstallPlan::new(&sample_directory_config()).unwrap();
+    assert!(plan.partitions.is_empty());
+    assert!(plan.fstab.is_none());
+    assert!(plan.data_loss.is_empty());
+    assert!(plan.bootloader.is_none());
+    assert!(plan.system.extra_locales.is_empty());
+    assert_eq!(
+        plan.release.url,
+        "https://repo.aosc.io/aosc-os/os-amd64/base/aosc-os_base_20240101_amd64.tar.xz"
+    );
+    let commands = plan
+        .commands
+        .iter()
+        .map(|x| x.command.as_str())
+        .collect::<Vec<_>>();
+    assert!(commands.contains(&"useradd -m -s /bin/bash -u 1001 aosc"));
+    assert!(commands.contains(&"systemctl enable --root=/var/lib/machines/aosc fstrim.timer"));
+    assert_eq!(commands.last(), Some(&"sh -c 'echo done > /etc/motd'"));
+    assert!(!commands.iter().any(|x| x.starts_with("mkfs.")));
+
+    let json: serde_json::Value = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+    assert_eq!(json["target"]["mode"], "directory");
+    assert_eq!(json["accounts"]["root_account"], "none");
+}
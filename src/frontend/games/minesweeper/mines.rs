@@ -2,12 +2,15 @@ use cursive::{
     direction::Direction,
     event::{Event, EventResult, MouseButton, MouseEvent},
     theme::{BaseColor, Color, ColorStyle},
-    view::CannotFocus,
+    view::{CannotFocus, Nameable},
     views::{Button, Dialog, LinearLayout, Panel, SelectView},
     Cursive, Printer, Vec2,
 };
 
-use crate::frontend::games::add_main_callback;
+use crate::frontend::{
+    games::{add_main_callback, GAME_LAYER},
+    theme::{self, ThemeName},
+};
 
 use super::game;
 
@@ -30,7 +33,8 @@ pub fn _start_mines_inner(siv: &mut Cursive) {
                             }))
                             .unwrap();
                     })),
-            ),
+            )
+            .with_name(GAME_LAYER),
     );
 }
 
@@ -194,6 +198,12 @@ impl cursive::view::View for BoardView {
                 Cell::Visible(n) => ["  ", " 1", " 2", " 3", " 4", " 5", " 6", " 7", " 8"][n],
             };
 
+            if theme::current() == ThemeName::Plain {
+                // the cells are told apart by their text already
+                printer.print((x, y), text);
+                continue;
+            }
+
             let color = match *cell {
                 Cell::Unknown => Color::RgbLowRes(3, 3, 3),
                 Cell::Flag => Color::RgbLowRes(4, 4, 2),
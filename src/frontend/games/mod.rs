@@ -1,14 +1,18 @@
 use cursive::{
-    view::SizeConstraint,
+    view::{Nameable, SizeConstraint},
     views::{Dialog, LinearLayout, ResizedView, ScrollView, SelectView},
     Cursive,
 };
 
 use self::{minesweeper::start_mines_inner, sudoku::start_sudoku_inner};
+use super::shortcuts::{add_global_shortcut, clear_global_shortcut};
 
 mod minesweeper;
 mod sudoku;
 
+/// Names the bottom layer of a game, so that the installer can close it
+const GAME_LAYER: &str = "game";
+
 fn start_mines(siv: &mut Cursive) {
     clear_callback(siv);
     start_mines_inner(siv);
@@ -46,26 +50,43 @@ fn start_game(siv: &mut Cursive) {
                     s.pop_layer();
                 }))
                 .unwrap()
-        }),
+        })
+        .with_name(GAME_LAYER),
     );
 }
 
-fn clear_callback(siv: &mut Cursive) {
-    siv.clear_global_callbacks('m');
-    siv.clear_global_callbacks('s');
-    siv.clear_global_callbacks('g');
+pub fn clear_callback(siv: &mut Cursive) {
+    clear_global_shortcut(siv, 'm');
+    clear_global_shortcut(siv, 's');
+    clear_global_shortcut(siv, 'g');
 }
 
 pub fn add_main_callback(siv: &mut Cursive) {
+    add_global_shortcut(siv, 'g', "Games", |s| {
+        start_game(s);
+    });
+    add_global_shortcut(siv, 's', "Sudoku", |s| {
+        start_sudoku(s);
+    });
     if std::env::var("DISPLAY").is_ok() {
-        siv.add_global_callback('m', |s| {
+        add_global_shortcut(siv, 'm', "Minesweeper", |s| {
             start_mines(s);
         });
     }
-    siv.add_global_callback('s', |s| {
-        start_sudoku(s);
-    });
-    siv.add_global_callback('g', |s| {
-        start_game(s);
-    });
+}
+
+pub fn is_playing(siv: &mut Cursive) -> bool {
+    siv.screen_mut().find_layer_from_name(GAME_LAYER).is_some()
+}
+
+/// Close the game being played and everything opened over it
+pub fn close_game(siv: &mut Cursive) {
+    if !is_playing(siv) {
+        return;
+    }
+    while is_playing(siv) {
+        siv.pop_layer();
+    }
+    sudoku::clear_game_callback(siv);
+    add_main_callback(siv);
 }
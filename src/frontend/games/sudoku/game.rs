@@ -1,5 +1,6 @@
 use crate::frontend::{
-    games::{add_main_callback, sudoku::board::SudokuBoard},
+    games::{add_main_callback, sudoku::board::SudokuBoard, GAME_LAYER},
+    shortcuts::{add_global_shortcut, clear_global_shortcut},
     tui::wrap_in_dialog,
 };
 use cursive::{
@@ -21,21 +22,21 @@ pub fn run(siv: &mut Cursive) {
     )
     .title("SUDOKU");
 
-    siv.add_layer(view);
+    siv.add_layer(view.with_name(GAME_LAYER));
 }
 
 fn add_game_callbacks(siv: &mut Cursive) {
-    siv.add_global_callback('r', restart);
-    siv.add_global_callback('h', hint);
-    siv.add_global_callback('q', quit);
-    siv.add_global_callback('m', help);
-    siv.add_global_callback('z', redo);
-    siv.add_global_callback('u', undo);
+    add_global_shortcut(siv, 'h', "Hint", hint);
+    add_global_shortcut(siv, 'u', "Undo", undo);
+    add_global_shortcut(siv, 'z', "Redo", redo);
+    add_global_shortcut(siv, 'r', "Restart", restart);
+    add_global_shortcut(siv, 'm', "Controls", help);
+    add_global_shortcut(siv, 'q', "Quit Game", quit);
 }
 
-fn clear_game_callback(siv: &mut Cursive) {
+pub fn clear_game_callback(siv: &mut Cursive) {
     for i in ['r', 'h', 'q', 'm', 'z', 'u'] {
-        siv.clear_global_callbacks(i);
+        clear_global_shortcut(siv, i);
     }
 }
 
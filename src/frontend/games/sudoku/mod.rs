@@ -1,5 +1,6 @@
 use cursive::Cursive;
 
+pub use self::game::clear_game_callback;
 use self::game::run;
 
 mod board;
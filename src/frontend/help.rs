@@ -0,0 +1,184 @@
+use cursive::{
+    event::Key,
+    traits::*,
+    views::{Dialog, TextView},
+    Cursive, View,
+};
+
+use crate::tr;
+
+use super::{accessible::dialog_content, shortcuts::Shortcuts};
+
+/// The screens of the installer with help
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Screen {
+    Variant,
+    Mirrors,
+    Disk,
+    Partition,
+    Mounts,
+    User,
+    Hostname,
+    Timezone,
+    Swap,
+    Summary,
+    Installing,
+}
+
+/// The help of each screen, as the title and the text to be translated
+const HELP: &[(Screen, &str, &str)] = &[
+    (
+        Screen::Variant,
+        "About Variants",
+        r#"AOSC OS comes in several variants, which differ in the software installed out of the box. The Desktop variant (with the KDE Plasma desktop) suits most users, the Server variant comes without a graphical desktop, and the Base variant contains only the essentials to be built upon.
+
+The download size is how much will be downloaded, and the installed size is how much space the installed system takes. The system partition needs room for both, as the download is kept on it during the installation. Select "More Info" (or press <i>) to read the whole description of the highlighted variant, along with the date, sizes and checksum of its release file.
+
+Additional software may be installed after the installation with the package manager, whichever variant you choose."#,
+    ),
+    (
+        Screen::Mirrors,
+        "About Mirrors",
+        r#"Mirrors are servers keeping copies of AOSC OS for download. A mirror close to you usually provides the fastest download speed. Mirrors are grouped by their location, select a group to expand or collapse it.
+
+Select "Benchmark Mirrors" to measure the speed of all mirrors and rank them from the fastest to the slowest. The result of each mirror is shown as soon as it has been tested, and "Skip" stops the test, keeping the results so far. "Specify URL" is for using a mirror not listed, e.g., one in your local network, and its URL should end with "/aosc-os/"."#,
+    ),
+    (
+        Screen::Disk,
+        "About Disks",
+        r#"Choose how to install AOSC OS on the drives first.
+
+In guided mode, select a drive (hard disk, SSD, or USB drive) to be used as a whole: Installer partitions it automatically, which erases everything on it. Check "Encrypt the system partition" to protect the system with a passphrase (LUKS), which is asked for on every boot; in advanced mode, the passphrase is entered with the mount points. To keep room for another operating system, enter the space to be left unpartitioned at the end of the drive.
+
+In advanced mode, select a partition for AOSC OS on one of the drives, and the other partitions are left untouched. Press <Enter> on a drive to show or hide its partitions. Selecting a drive that is empty or has no valid partition still offers to partition it automatically. To create, remove or resize partitions, select "Open cfdisk on This Disk" or "Open parted on This Disk" (a button is disabled if the program is not installed): the installer is suspended while the program runs on the selected drive, and the drives are listed again once it exits.
+
+The drives are listed with their device names, models, sizes, connections and partition tables, and their partitions under them with the filesystems, sizes, labels and operating systems found on them. Drives and partitions that can not be installed to (e.g., the installer medium itself, or a mounted partition) are greyed out, the reason is shown below the list.
+
+Nothing is written to the drive until you confirm the installation on the final summary."#,
+    ),
+    (
+        Screen::Partition,
+        "About Partitions",
+        r#"AOSC OS is installed on a single system partition, which has to be large enough for both the download and the installed system of the selected variant. The partition will be formatted (erasing everything on it), unless an existing AOSC OS installation is found on it and you choose to preserve /home. Other partitions may be mounted into the installed system on the next screen, e.g. a separate /home.
+
+On UEFI systems, an EFI System Partition (ESP) is also required for booting. It is a FAT32 partition (usually 300MiB to 1GiB) on a GPT partition table, often shared with other operating systems, and will not be formatted. Automatic partitioning creates a 512MiB ESP.
+
+To create or resize partitions, use GParted (in a graphical session) or a shell with tools like cfdisk or gdisk, and come back to this screen afterwards."#,
+    ),
+    (
+        Screen::Mounts,
+        "About Mount Points",
+        r#"Besides the system partition mounted at /, other partitions may be used by the installed system, e.g., a separate /home to keep your files across reinstallations. Enter the directory a partition is to be mounted at, or leave it empty to leave the partition alone. Common mount points are completed as you type, press <Right> at the end of the mount point to take the completion.
+
+Partitions are only formatted if "Format" is checked, which erases everything on them. Choose a filesystem to format a partition with another one, or keep its current filesystem. A label may be given to the new filesystem, and mount options (separated by commas, e.g. "noatime") replace the defaults in /etc/fstab. If the system partition is encrypted, check "Encrypt" to encrypt a partition as well, it is unlocked on boot with a keyfile kept on the system partition; /boot and the EFI System Partition can not be encrypted.
+
+On UEFI systems, the EFI System Partition is mounted at /efi, it has to be a FAT filesystem. /usr, /etc and other essential parts of the system can not be on a separate partition. Problems are shown under each partition, and the installation can only continue once there is none.
+
+"Auto-assign" proposes mount points for the partitions left alone: the EFI System Partition, and /home on a partition labelled "home" or the largest Linux partition without an operating system on it. The existing filesystems are kept, please review the proposal before continuing."#,
+    ),
+    (
+        Screen::User,
+        "About the User Account",
+        r#"The user account is the one you will log in to the installed system with. By default it is an administrator, which is in the wheel group and may run commands as root with sudo and its own password. An account that is not an administrator needs root login to administrate the system, so it may only be created with root login enabled.
+
+The username must start with a lower-cased letter (a-z), contain only lower-cased letters a-z, numbers 0-9, dash ("-") and underscore ("_"), and be no longer than 32 characters. The full name is optional and shown on the login screen.
+
+The supplementary groups give access to virtual machines (libvirt), containers (docker), serial ports (dialout) and removable devices (plugdev). They are only joined if the installed system has them. Other groups may be entered separated by commas, they are created if missing.
+
+The login shells listed are those the selected variant is known to ship. Choose "Other..." to enter the path of another shell, e.g., one installed with the additional packages. If the shell is missing from the installed system, bash is used instead, with a warning when the installation is done.
+
+A password of 12 or more characters, mixing letters, numbers and symbols, is recommended. Passwords have to be entered twice, to make sure they have been typed correctly."#,
+    ),
+    (
+        Screen::Hostname,
+        "About the Hostname and Options",
+        r#"The hostname is the name of this device on the network, e.g., "aosc-laptop". It may only consist of letters a-z, numbers 0-9, and dash ("-"), and may not start or end with a dash.
+
+Enabling the SSH server allows logging in to this device remotely. SSH keys may be imported (from a URL, a file, or pasted) to log in without a password, in which case password authentication may be disabled for better security.
+
+Additional packages are installed along with the system, and the boot options adjust the bootloader and the kernel command line. The defaults work for most devices."#,
+    ),
+    (
+        Screen::Timezone,
+        "About Locale and Timezone",
+        r#"The locale decides the language of the installed system, as well as the formats of dates, times and numbers. Several locales may be checked in the list to be generated for the users of the system, and the one chosen with the round button is the system default. Search the list by the language, the territory or the locale code (e.g., zh_CN). If no locale is checked, C.UTF-8 (no localization) is used.
+
+The timezone is used for showing the local time. Select the region and then the city of the timezone, or type in the name of a city or a country (former names like Peking work too) to search for it; the current time in the highlighted timezone is shown below the list. The system time may be kept in UTC (the default for Linux systems) or in local time. Choose local time only if you also use Windows on this device, which keeps the hardware clock in local time, to avoid the clock being off in one of them.
+
+The keyboard layout is used on the console and in the graphical desktop. The layout highlighted in the list is loaded to the console for testing: type in the test field to check the @ sign and the dead keys (accents), the former layout is put back if the selection is cancelled. The passphrase of an encrypted system partition is typed with this layout on every boot."#,
+    ),
+    (
+        Screen::Swap,
+        "About Swap",
+        r#"Swap is used as additional memory when the RAM is full. Compressed swap in the RAM (zram) needs no disk space and is recommended for devices with 8GiB of RAM or more. A swapfile is created on the system partition and takes space from it, while an existing swap partition (which may be created in the advanced mode of the disk step) is used as-is.
+
+A swapfile or swap partition also allows hibernation (saving the memory to the drive before powering off), which is set up if "Enable hibernation" is checked. The recommended swapfile size is about the size of the RAM (twice of it with 1GiB of RAM or less), or RAM + √RAM (in GiB) for hibernation. Hibernation is not possible with a swap smaller than the RAM.
+
+The space of the system partition is shown below: the installed system and the swapfile take from it, and the download is also kept on it until it has been unpacked. The installation can only continue if everything fits, and a warning is shown if less than 2GiB would be left free."#,
+    ),
+    (
+        Screen::Summary,
+        "About the Summary",
+        r#"This is the last chance to review the installation before anything is written to the drive. Operations shown in red erase data, e.g., formatting a partition or wiping the whole drive, so please make sure everything on them has been backed up.
+
+Select "Change" next to an item to change just that, the installer comes back here afterwards. The storage can only be changed by going through the following steps again. Select "Save Configuration" to save the settings to a file, for installing more devices the same way."#,
+    ),
+    (
+        Screen::Installing,
+        "About the Installation",
+        r#"Installer is now downloading, verifying and unpacking AOSC OS, and configuring the installed system. This may take minutes, or longer on slow devices or networks. The time left is estimated from the installations before on this installer medium, only the elapsed time is shown until there have been any.
+
+The last lines printed by the running command (e.g., formatting or generating the initramfs) are shown under the progress bar, so that it can be seen moving. Press <F12> to see the installer log. The installation may be cancelled until the installed system is being configured, and a partially downloaded system release is resumed the next time. Quitting the installer (<Ctrl-C>) tells what is running, and cleans up the target before exiting.
+
+While playing a game or reading the log, the terminal beeps and a banner is shown as soon as the system release has been downloaded, a step needs your decision, or the installation has finished or failed. Press any key to close the game or the log, and to return to the installation."#,
+    ),
+];
+
+/// Show the help of `screen` in a scrollable dialog
+pub(super) fn show_help(siv: &mut Cursive, screen: Screen) {
+    let Some((_, title, text)) = HELP.iter().find(|(x, _, _)| *x == screen) else {
+        return;
+    };
+    siv.add_layer(
+        Dialog::around(dialog_content(TextView::new(tr!(text)).scrollable(), 80))
+            .title(tr!(title))
+            .button(tr!("OK"), |s| {
+                s.pop_layer();
+            })
+            .padding_lrtb(2, 2, 1, 1),
+    );
+}
+
+/// Add the Help button of `screen` to the dialog
+pub(super) fn help_button(dialog: Dialog, screen: Screen) -> Dialog {
+    dialog.button(tr!("Help"), move |s| show_help(s, screen))
+}
+
+/// Show the help of `screen` on <F1> too, wherever the focus is in the view
+pub(super) fn help_on_f1<V: View>(view: V, screen: Screen) -> Shortcuts<V> {
+    Shortcuts::new(view).shortcut(Key::F1, "Help", move |s| show_help(s, screen))
+}
+
+/// Add the Help button of `screen` to the dialog, and show the help on <F1> as well
+pub(super) fn with_help(dialog: Dialog, screen: Screen) -> Shortcuts<Dialog> {
+    help_on_f1(help_button(dialog, screen), screen)
+}
+
+#[test]
+fn test_help() {
+    for screen in [
+        Screen::Variant,
+        Screen::Mirrors,
+        Screen::Disk,
+        Screen::Partition,
+        Screen::Mounts,
+        Screen::User,
+        Screen::Hostname,
+        Screen::Timezone,
+        Screen::Swap,
+        Screen::Summary,
+        Screen::Installing,
+    ] {
+        assert_eq!(HELP.iter().filter(|(x, _, _)| *x == screen).count(), 1);
+    }
+}
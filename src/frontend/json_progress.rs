@@ -0,0 +1,320 @@
+use std::{
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+
+use crate::tr;
+
+use super::{dry_run::InstallPlan, step_of, TransferStatus, MEASURED_STEPS};
+
+/// Bumped whenever a field of an event is renamed, removed or changes its meaning, adding
+/// events or fields does not bump it. The schema is in res/json-progress.schema.json
+pub(super) const SCHEMA_VERSION: u32 = 1;
+
+/// Whether `install --json-progress` is on, the events are printed to stdout then and
+/// nothing else is
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// An event of `install --json-progress`, printed as one JSON object per line
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(super) enum Event<'a> {
+    /// Always the first event
+    Started {
+        version: u32,
+        dry_run: bool,
+    },
+    /// The steps planned, in order, once the installation has started
+    Steps {
+        steps: Vec<&'a str>,
+    },
+    StepStarted {
+        step: &'a str,
+        /// Translated, to be shown as it is
+        description: &'a str,
+        /// Of the steps planned, `None` for a step not planned
+        index: Option<usize>,
+        total: usize,
+    },
+    StepFinished {
+        step: &'a str,
+    },
+    /// How far the current step and the whole installation are, only sent when they change
+    Progress {
+        step: &'a str,
+        /// `None` for the steps which can not tell
+        percent: Option<usize>,
+        overall_percent: usize,
+    },
+    /// Bytes downloaded or unpacked
+    Transfer {
+        step: &'a str,
+        done: u64,
+        total: Option<u64>,
+        /// Bytes per second
+        rate: Option<f64>,
+        eta_seconds: Option<u64>,
+    },
+    Warning {
+        message: &'a str,
+    },
+    /// A question which would have been asked interactively, answered as when not running
+    /// interactively, a wrapper may fail fast on it
+    Prompt {
+        /// `bulletin`, `data_loss` or `retry`
+        prompt: &'a str,
+        question: &'a str,
+        answer: &'a str,
+    },
+    /// What the installation would do, instead of installing with --dry-run
+    Plan {
+        plan: &'a InstallPlan,
+    },
+    /// Always the last event
+    Result {
+        success: bool,
+        exit_code: i32,
+        error: Option<String>,
+        log: Option<&'a Path>,
+    },
+}
+
+impl Event<'_> {
+    pub fn transfer<'a>(step: &'a str, status: &TransferStatus) -> Event<'a> {
+        Event::Transfer {
+            step,
+            done: status.done,
+            total: status.total,
+            rate: status.rate,
+            eta_seconds: status.eta.map(|x| x.as_secs()),
+        }
+    }
+}
+
+/// Turns the progress reported by the installation into events, sending only what has
+/// changed
+#[derive(Default)]
+pub(super) struct ProgressEvents {
+    step: Option<String>,
+    /// The step and overall percentages last sent
+    last: Option<(Option<usize>, usize)>,
+}
+
+impl ProgressEvents {
+    pub fn steps(&self, plan: &[(&'static str, f64)]) {
+        emit(&Event::Steps {
+            steps: plan.iter().map(|(x, _)| *x).collect(),
+        });
+    }
+
+    pub fn pending(&mut self, plan: &[(&str, f64)], status: &str, percent: usize, overall: usize) {
+        let step = step_of(status).unwrap_or(status);
+        if self.step.as_deref() != Some(step) {
+            self.finish_step();
+            emit(&Event::StepStarted {
+                step,
+                description: tr!(step),
+                index: plan.iter().position(|(x, _)| *x == step),
+                total: plan.len(),
+            });
+            self.step = Some(step.to_string());
+        }
+        // the others report made-up percentages to show they are alive
+        let percent = MEASURED_STEPS
+            .iter()
+            .any(|x| *x == step)
+            .then_some(percent.min(100));
+        if self.last != Some((percent, overall)) {
+            emit(&Event::Progress {
+                step,
+                percent,
+                overall_percent: overall,
+            });
+            self.last = Some((percent, overall));
+        }
+    }
+
+    pub fn transfer(&self, status: &TransferStatus) {
+        if let Some(step) = &self.step {
+            emit(&Event::transfer(step, status));
+        }
+    }
+
+    pub fn finish_step(&mut self) {
+        if let Some(step) = self.step.take() {
+            emit(&Event::StepFinished { step: &step });
+        }
+    }
+}
+
+pub(super) fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub(super) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Print the event if --json-progress is on
+pub(super) fn emit(event: &Event) {
+    if !is_enabled() {
+        return;
+    }
+    let mut stdout = std::io::stdout().lock();
+    write_event(&mut stdout, event).ok();
+    stdout.flush().ok();
+}
+
+fn write_event(out: &mut impl Write, event: &Event) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *out, event)?;
+    writeln!(out)
+}
+
+/// Whether `value` is valid against `schema`, for the subset of JSON Schema used by
+/// res/json-progress.schema.json
+#[cfg(test)]
+fn validate(schema: &serde_json::Value, value: &serde_json::Value) -> bool {
+    use serde_json::Value;
+
+    if let Some(variants) = schema.get("oneOf").and_then(|x| x.as_array()) {
+        if variants.iter().filter(|x| validate(x, value)).count() != 1 {
+            return false;
+        }
+    }
+    if schema.get("const").is_some_and(|x| x != value) {
+        return false;
+    }
+    if let Some(options) = schema.get("enum").and_then(|x| x.as_array()) {
+        if !options.contains(value) {
+            return false;
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let types = match types {
+            Value::Array(x) => x.iter().filter_map(|x| x.as_str()).collect(),
+            x => vec![x.as_str().unwrap_or_default()],
+        };
+        let matches = |name: &str| match name {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_u64() || value.is_i64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => false,
+        };
+        if !types.into_iter().any(matches) {
+            return false;
+        }
+    }
+    if let (Some(minimum), Some(x)) = (
+        schema.get("minimum").and_then(|x| x.as_f64()),
+        value.as_f64(),
+    ) {
+        if x < minimum {
+            return false;
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        if !array.iter().all(|x| validate(items, x)) {
+            return false;
+        }
+    }
+    if let Some(object) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(|x| x.as_array()) {
+            if !required
+                .iter()
+                .filter_map(|x| x.as_str())
+                .all(|x| object.contains_key(x))
+            {
+                return false;
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(|x| x.as_object()) {
+            let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+            for (key, value) in object {
+                match properties.get(key) {
+                    Some(schema) if !validate(schema, value) => return false,
+                    None if closed => return false,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    true
+}
+
+#[test]
+fn test_dry_run_events() {
+    use super::dry_run::sample_directory_config;
+    use std::time::Duration;
+
+    let schema: serde_json::Value =
+        serde_json::from_str(include_str!("../../res/json-progress.schema.json")).unwrap();
+    let plan = InstallPlan::new(&sample_directory_config()).unwrap();
+    let transfer = TransferStatus {
+        done: 1024,
+        total: None,
+        rate: Some(512.0),
+        eta: Some(Duration::from_secs(2)),
+        summary: String::new(),
+    };
+    let events = [
+        Event::Started {
+            version: SCHEMA_VERSION,
+            dry_run: true,
+        },
+        Event::Prompt {
+            prompt: "bulletin",
+            question: "Install anyway?",
+            answer: "no",
+        },
+        Event::Steps {
+            steps: vec!["Step 2 of 8: Downloading system release"],
+        },
+        Event::StepStarted {
+            step: "Step 2 of 8: Downloading system release",
+            description: "Step 2 of 8: Downloading system release",
+            index: Some(0),
+            total: 1,
+        },
+        Event::Progress {
+            step: "Step 2 of 8: Downloading system release",
+            percent: None,
+            overall_percent: 10,
+        },
+        Event::transfer("Step 2 of 8: Downloading system release", &transfer),
+        Event::StepFinished {
+            step: "Step 2 of 8: Downloading system release",
+        },
+        Event::Warning { message: "oops" },
+        Event::Plan { plan: &plan },
+        Event::Result {
+            success: true,
+            exit_code: 0,
+            error: None,
+            log: Some(Path::new("/var/log/dklog.log")),
+        },
+    ];
+    let mut out = vec![];
+    for event in events.iter() {
+        write_event(&mut out, event).unwrap();
+    }
+
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), events.len());
+    for line in out.lines() {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(validate(&schema, &value), "{line}");
+    }
+    // the schema does reject what it does not describe
+    let unknown = serde_json::json!({ "event": "started", "version": 1 });
+    assert!(!validate(&schema, &unknown));
+    let unknown = serde_json::json!({ "event": "finished" });
+    assert!(!validate(&schema, &unknown));
+}
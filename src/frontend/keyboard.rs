@@ -0,0 +1,257 @@
+use anyhow::Result;
+use cursive::{
+    traits::*,
+    views::{Dialog, DummyView, EditView, LinearLayout, SelectView, TextContent, TextView},
+    Cursive,
+};
+use log::warn;
+use std::{cell::RefCell, rc::Rc, sync::Mutex};
+
+use crate::{
+    install::{self, KeyboardLayout},
+    tr,
+};
+
+use super::tui::wrap_in_dialog;
+
+const KEYBOARD_LAYOUTS: &str = "keyboard_layouts";
+const KEYBOARD_TEST: &str = "keyboard_test";
+/// The layouts used the most, put at the top of the list with their variants
+const COMMON_LAYOUTS: &[&str] = &[
+    "us", "gb", "de", "fr", "es", "it", "pt", "br", "latam", "ru", "jp", "ch",
+];
+
+/// The keymap loaded to the live console by the installer, `None` if it is still the one
+/// the live system was booted with
+static CONSOLE_KEYMAP: Mutex<Option<String>> = Mutex::new(None);
+
+/// The common layouts first, the variants stay right after their layouts
+pub(super) fn sort_layouts(mut layouts: Vec<KeyboardLayout>) -> Vec<KeyboardLayout> {
+    layouts.sort_by_key(|x| {
+        COMMON_LAYOUTS
+            .iter()
+            .position(|y| *y == x.layout)
+            .unwrap_or(COMMON_LAYOUTS.len())
+    });
+
+    layouts
+}
+
+/// The description of the layout with its keymap name, e.g. "German (no dead keys) (de-nodeadkeys)"
+pub(super) fn describe_layout(layout: &KeyboardLayout) -> String {
+    format!("{} ({})", layout.description, layout.keymap())
+}
+
+fn search<'a>(layouts: &'a [KeyboardLayout], query: &str) -> Vec<&'a KeyboardLayout> {
+    let query = query.trim().to_lowercase();
+    layouts
+        .iter()
+        .filter(|x| {
+            x.keymap().to_lowercase().contains(&query)
+                || x.description.to_lowercase().contains(&query)
+        })
+        .collect()
+}
+
+fn fill_layouts(siv: &mut Cursive, layouts: &[&KeyboardLayout], indent: bool) {
+    siv.call_on_name(KEYBOARD_LAYOUTS, |view: &mut SelectView<String>| {
+        view.clear();
+        for layout in layouts {
+            let label = match layout.variant {
+                Some(_) if indent => format!("  {}", describe_layout(layout)),
+                _ => describe_layout(layout),
+            };
+            view.add_item(label, layout.keymap());
+        }
+    });
+}
+
+fn load_keymap(keymap: Option<&str>) -> Result<()> {
+    let mut loaded = CONSOLE_KEYMAP.lock().unwrap();
+    if loaded.as_deref() != keymap {
+        install::load_console_keymap(keymap)?;
+        *loaded = keymap.map(|x| x.to_string());
+    }
+
+    Ok(())
+}
+
+/// Load the layout to the live console for the test field, and tell whether it worked
+fn try_layout(layout: &KeyboardLayout, hint: &TextContent) {
+    if !install::has_console_keymap(layout) {
+        hint.set_content(tr!(
+            "There is no console keymap for {}, it can not be tested here and will only be used in the graphical desktop.",
+            layout.description
+        ));
+        return;
+    }
+
+    match load_keymap(Some(&layout.keymap())) {
+        Ok(()) => hint.set_content(tr!(
+            "Type here to test the layout, e.g., the @ sign and the dead keys:"
+        )),
+        Err(e) => {
+            warn!("Failed to load keymap {}: {}", layout.keymap(), e);
+            hint.set_content(tr!(
+                "The layout can only be tested when the installer runs on the console."
+            ));
+        }
+    }
+}
+
+/// Select the keyboard layout, which is loaded to the live console while it is
+/// highlighted so that it may be tested, and put back on cancel
+pub(super) fn keyboard_picker(
+    layouts: Rc<Vec<KeyboardLayout>>,
+    keymap: Rc<RefCell<String>>,
+    status_text: TextContent,
+    encrypt: bool,
+) -> Dialog {
+    let before = CONSOLE_KEYMAP.lock().unwrap().clone();
+    let hint = TextContent::new("");
+    let current = keymap.borrow().clone();
+
+    let mut layout_view = SelectView::new();
+    for layout in layouts.iter() {
+        let label = match layout.variant {
+            Some(_) => format!("  {}", describe_layout(layout)),
+            None => describe_layout(layout),
+        };
+        layout_view.add_item(label, layout.keymap());
+    }
+    if let Some(index) = layouts.iter().position(|x| x.keymap() == current) {
+        layout_view.set_selection(index);
+        try_layout(&layouts[index], &hint);
+    }
+
+    let find = {
+        let layouts = layouts.clone();
+        move |keymap: &str| layouts.iter().find(|x| x.keymap() == keymap).cloned()
+    };
+    let confirm = {
+        let find = find.clone();
+        Rc::new(move |s: &mut Cursive, selected: &str| {
+            if let Some(layout) = find(selected) {
+                s.pop_layer();
+                keymap.replace(selected.to_string());
+                status_text.set_content(describe_layout(&layout));
+            }
+        })
+    };
+
+    let hint_copy = hint.clone();
+    let find_copy = find.clone();
+    let confirm_copy = confirm.clone();
+    let layout_view = layout_view
+        .on_select(move |s, selected: &String| {
+            if let Some(layout) = find_copy(selected) {
+                try_layout(&layout, &hint_copy);
+            }
+            // typed with the former layout
+            s.call_on_name(KEYBOARD_TEST, |view: &mut EditView| {
+                view.set_content("");
+            });
+        })
+        .on_submit(move |s, selected: &String| confirm_copy(s, selected));
+
+    let layouts_copy = layouts.clone();
+    let hint_copy = hint.clone();
+    let query_view = EditView::new()
+        .on_edit(move |s, query, _| {
+            if query.trim().is_empty() {
+                fill_layouts(s, &layouts_copy.iter().collect::<Vec<_>>(), true);
+            } else {
+                fill_layouts(s, &search(&layouts_copy, query), false);
+            }
+            let selected = s
+                .call_on_name(KEYBOARD_LAYOUTS, |view: &mut SelectView<String>| {
+                    view.selection()
+                })
+                .flatten()
+                .and_then(|x| find(&x));
+            match selected {
+                Some(layout) => try_layout(&layout, &hint_copy),
+                None => hint_copy.set_content(tr!("No keyboard layout found.")),
+            }
+        })
+        .on_submit({
+            let confirm = confirm.clone();
+            move |s, _| {
+                // the best match is taken without moving into the list
+                let selected = s
+                    .call_on_name(KEYBOARD_LAYOUTS, |view: &mut SelectView<String>| {
+                        view.selection()
+                    })
+                    .flatten();
+                if let Some(selected) = selected {
+                    confirm(s, &selected);
+                }
+            }
+        });
+
+    let mut view = LinearLayout::vertical()
+        .child(TextView::new(tr!(
+            "Search by the name of the layout or its keymap, or select it from the list:"
+        )))
+        .child(query_view)
+        .child(
+            layout_view
+                .with_name(KEYBOARD_LAYOUTS)
+                .scrollable()
+                .fixed_height(12),
+        )
+        .child(DummyView {})
+        .child(TextView::new_with_content(hint))
+        .child(EditView::new().with_name(KEYBOARD_TEST));
+    if encrypt {
+        view.add_child(DummyView {});
+        view.add_child(TextView::new(tr!(
+            "The passphrase of the encrypted system partition will be typed with this layout on every boot, please make sure that it can be typed here."
+        )));
+    }
+
+    wrap_in_dialog(view, tr!("Select Keyboard Layout"), Some(72))
+        .button(tr!("OK"), move |s| {
+            let selected = s
+                .call_on_name(KEYBOARD_LAYOUTS, |view: &mut SelectView<String>| {
+                    view.selection()
+                })
+                .flatten();
+            if let Some(selected) = selected {
+                confirm(s, &selected);
+            }
+        })
+        .button(tr!("Cancel"), move |s| {
+            if let Err(e) = load_keymap(before.as_deref()) {
+                warn!("Failed to restore the console keymap: {}", e);
+            }
+            s.pop_layer();
+        })
+}
+
+#[test]
+fn test_sort_layouts() {
+    let layout = |layout: &str, variant: Option<&str>| KeyboardLayout {
+        layout: layout.to_string(),
+        variant: variant.map(|x| x.to_string()),
+        description: layout.to_string(),
+    };
+    let layouts = sort_layouts(vec![
+        layout("al", None),
+        layout("de", None),
+        layout("de", Some("nodeadkeys")),
+        layout("us", None),
+        layout("us", Some("dvorak")),
+    ]);
+    assert_eq!(
+        layouts.iter().map(|x| x.keymap()).collect::<Vec<_>>(),
+        vec!["us", "us-dvorak", "de", "de-nodeadkeys", "al"]
+    );
+    assert_eq!(
+        search(&layouts, "DVORAK")
+            .iter()
+            .map(|x| x.keymap())
+            .collect::<Vec<_>>(),
+        vec!["us-dvorak"]
+    );
+}
@@ -0,0 +1,273 @@
+use cursive::{
+    traits::*,
+    views::{
+        Checkbox, Dialog, DummyView, EditView, LinearLayout, RadioButton, RadioGroup, TextView,
+    },
+    Cursive,
+};
+use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc};
+
+use crate::{install, tr};
+
+use super::tui::{show_msg, wrap_in_dialog};
+
+const LOCALE_ROWS: &str = "locale_rows";
+/// The locale used if none is selected
+pub(super) const FALLBACK_LOCALE: &str = "C.UTF-8";
+
+/// A locale to choose from, with its language and territory in words
+#[derive(Debug, Clone)]
+pub(super) struct LocaleEntry {
+    pub code: String,
+    pub description: String,
+}
+
+/// The locales to be generated, one of which is the system default
+#[derive(Debug, Clone, Default)]
+pub(super) struct LocaleChoice {
+    pub default: Option<String>,
+    pub extra: Vec<String>,
+}
+
+impl LocaleChoice {
+    fn is_checked(&self, code: &str) -> bool {
+        self.default.as_deref() == Some(code) || self.extra.iter().any(|x| x == code)
+    }
+
+    /// Add or remove the locale, the first locale added becomes the default, and the next
+    /// one takes over if the default is removed
+    fn set_checked(&mut self, code: &str, checked: bool) {
+        if checked {
+            if self.default.is_none() {
+                self.default = Some(code.to_string());
+            } else if !self.is_checked(code) {
+                self.extra.push(code.to_string());
+            }
+            return;
+        }
+
+        self.extra.retain(|x| x != code);
+        if self.default.as_deref() == Some(code) {
+            self.default = (!self.extra.is_empty()).then(|| self.extra.remove(0));
+        }
+    }
+
+    /// Make the locale the default, the former default stays selected
+    fn set_default(&mut self, code: &str) {
+        self.extra.retain(|x| x != code);
+        if let Some(old) = self.default.replace(code.to_string()) {
+            if old != code {
+                self.extra.push(old);
+            }
+        }
+    }
+}
+
+/// The UTF-8 locales supported by the live system, with the one without localization first
+pub(super) fn list_locales() -> Vec<LocaleEntry> {
+    let languages = install::get_locale_list().unwrap_or_default();
+    let countries = install::get_country_names()
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let mut codes = install::get_supported_locales(Path::new("/"))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|x| x.contains(".UTF-8"))
+        .collect::<Vec<_>>();
+    if codes.is_empty() {
+        codes = languages.iter().map(|x| x.1.to_string()).collect();
+    }
+    codes.retain(|x| x != FALLBACK_LOCALE);
+    codes.sort();
+    codes.dedup();
+    codes.insert(0, FALLBACK_LOCALE.to_string());
+
+    codes
+        .into_iter()
+        .map(|code| LocaleEntry {
+            description: describe_locale(&code, &languages, &countries),
+            code,
+        })
+        .collect()
+}
+
+/// The language and territory of the locale, with the name of the language in itself
+/// if it is known, e.g., "Deutsch - German (Germany)"
+fn describe_locale(
+    code: &str,
+    languages: &[(&str, &str, &str)],
+    countries: &HashMap<String, String>,
+) -> String {
+    if code == FALLBACK_LOCALE {
+        return tr!("No localization").to_string();
+    }
+
+    let known = languages.iter().find(|x| x.1 == code);
+    let (language, territory) = install::locale_identification(Path::new("/"), code)
+        .unwrap_or_else(|| {
+            let name = code.split(['.', '@']).next().unwrap_or(code);
+            let (language, territory) = name.split_once('_').unwrap_or((name, ""));
+            (
+                known
+                    .map(|x| x.2.to_string())
+                    .unwrap_or_else(|| language.to_string()),
+                countries.get(territory).cloned().unwrap_or_default(),
+            )
+        });
+    let mut description = match known {
+        Some((native, _, _)) if *native != language => format!("{native} - {language}"),
+        _ => language,
+    };
+    if !territory.is_empty() {
+        description = format!("{description} ({territory})");
+    }
+
+    description
+}
+
+/// The rows of the locales matching the query, each with a checkbox to select it and a
+/// radio button to make it the default
+fn locale_rows(
+    entries: &[LocaleEntry],
+    choice: &Rc<RefCell<LocaleChoice>>,
+    query: &str,
+) -> LinearLayout {
+    let query = query.trim().to_lowercase();
+    let current = choice.borrow().clone();
+    let mut group = RadioGroup::new();
+    let mut rows = LinearLayout::vertical();
+    for entry in entries.iter().filter(|x| {
+        query.is_empty()
+            || x.code.to_lowercase().contains(&query)
+            || x.description.to_lowercase().contains(&query)
+    }) {
+        let code = entry.code.clone();
+        let choice_copy = choice.clone();
+        let checkbox = Checkbox::new()
+            .with_checked(current.is_checked(&entry.code))
+            .on_change(move |s, checked| {
+                let default = {
+                    let mut choice = choice_copy.borrow_mut();
+                    choice.set_checked(&code, checked);
+                    choice.default.clone()
+                };
+                // the default may have been handed over to another locale
+                if let Some(default) = default {
+                    s.call_on_name(
+                        &format!("locale_default_{default}"),
+                        |view: &mut RadioButton<String>| {
+                            view.select();
+                        },
+                    );
+                }
+            })
+            .with_name(format!("locale_check_{}", entry.code));
+        let mut radio = group.button(entry.code.clone(), "");
+        if current.default.as_deref() == Some(entry.code.as_str()) {
+            radio.select();
+        }
+        rows.add_child(
+            LinearLayout::horizontal()
+                .child(checkbox)
+                .child(DummyView {}.fixed_width(1))
+                .child(radio.with_name(format!("locale_default_{}", entry.code)))
+                .child(TextView::new(format!(
+                    "{}  {}",
+                    entry.code, entry.description
+                ))),
+        );
+    }
+    if rows.is_empty() {
+        rows.add_child(TextView::new(tr!("No locale found.")));
+    }
+
+    let choice = choice.clone();
+    group.set_on_change(move |s, code: &String| {
+        choice.borrow_mut().set_default(code);
+        // a locale made the default is selected as well
+        s.call_on_name(&format!("locale_check_{code}"), |view: &mut Checkbox| {
+            view.set_checked(true);
+        });
+    });
+
+    rows
+}
+
+/// Select the locales to be generated and the system default among them, `on_done` is
+/// called with the choice once confirmed
+pub(super) fn locale_picker(
+    entries: Rc<Vec<LocaleEntry>>,
+    choice: Rc<RefCell<LocaleChoice>>,
+    query: Rc<RefCell<String>>,
+    on_done: Rc<dyn Fn(&mut Cursive, &LocaleChoice)>,
+) -> Dialog {
+    let rows = locale_rows(&entries, &choice, &query.borrow());
+    let choice_copy = choice.clone();
+    let query_view = EditView::new()
+        .content(query.borrow().clone())
+        .on_edit(move |s, text, _| {
+            // kept for reopening the list
+            query.replace(text.to_string());
+            let rows = locale_rows(&entries, &choice_copy, text);
+            s.call_on_name(LOCALE_ROWS, |view: &mut LinearLayout| {
+                view.clear();
+                view.add_child(rows);
+            });
+        });
+
+    wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(TextView::new(tr!(
+                "Please check the locales to be generated, and choose the system default among them with the round button. Search by the language, the territory or the code:"
+            )))
+            .child(query_view)
+            .child(DummyView {})
+            .child(
+                LinearLayout::vertical()
+                    .child(rows)
+                    .with_name(LOCALE_ROWS)
+                    .scrollable()
+                    .fixed_height(16),
+            ),
+        tr!("Select Locales"),
+        Some(72),
+    )
+    .button(tr!("OK"), move |s| {
+        let mut choice = choice.borrow().clone();
+        s.pop_layer();
+        if choice.default.is_none() {
+            choice.default = Some(FALLBACK_LOCALE.to_string());
+            show_msg(
+                s,
+                &tr!(
+                    "No locale has been selected, {} (no localization) will be used.",
+                    FALLBACK_LOCALE
+                ),
+            );
+        }
+        on_done(s, &choice);
+    })
+}
+
+#[test]
+fn test_locale_choice() {
+    let mut choice = LocaleChoice::default();
+    choice.set_checked("en_US.UTF-8", true);
+    choice.set_checked("zh_CN.UTF-8", true);
+    choice.set_checked("de_DE.UTF-8", true);
+    assert_eq!(choice.default.as_deref(), Some("en_US.UTF-8"));
+    assert_eq!(choice.extra, vec!["zh_CN.UTF-8", "de_DE.UTF-8"]);
+
+    choice.set_default("zh_CN.UTF-8");
+    assert_eq!(choice.default.as_deref(), Some("zh_CN.UTF-8"));
+    assert_eq!(choice.extra, vec!["de_DE.UTF-8", "en_US.UTF-8"]);
+
+    choice.set_checked("zh_CN.UTF-8", false);
+    assert_eq!(choice.default.as_deref(), Some("de_DE.UTF-8"));
+    assert_eq!(choice.extra, vec!["en_US.UTF-8"]);
+
+    choice.set_checked("de_DE.UTF-8", false);
+    choice.set_checked("en_US.UTF-8", false);
+    assert_eq!(choice.default, None);
+    assert!(!choice.is_checked("en_US.UTF-8"));
+}
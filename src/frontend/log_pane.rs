@@ -0,0 +1,175 @@
+use cursive::{
+    event::Key,
+    traits::*,
+    view::ScrollStrategy,
+    views::{Dialog, EditView, Layer, LinearLayout, Panel, ScrollView, TextView},
+    Cursive,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{log, tr};
+
+use super::{accessible::dialog_content, shortcuts::Shortcuts};
+
+const LOG_PANE: &str = "log_pane";
+const LOG_TEXT: &str = "log_text";
+const LOG_STATUS: &str = "log_status";
+/// How often the pane picks up the new lines of the log
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Every pane opened gets a new number, the updater of a closed pane stops when it changes
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+struct PaneState {
+    generation: u64,
+    /// Only the lines containing this (case-insensitively) are shown, if it is not empty
+    query: Mutex<String>,
+    /// How many lines had been written when the pane was last updated
+    shown: AtomicU64,
+}
+
+/// Open the log pane, or close it if it is open, the dialog under it keeps its focus
+pub(super) fn toggle_log_pane(siv: &mut Cursive) {
+    match siv.screen_mut().find_layer_from_name(LOG_PANE) {
+        Some(position) => {
+            GENERATION.fetch_add(1, Ordering::SeqCst);
+            siv.screen_mut().remove_layer(position);
+        }
+        None => open_log_pane(siv),
+    }
+}
+
+pub(super) fn is_log_pane_open(siv: &mut Cursive) -> bool {
+    siv.screen_mut().find_layer_from_name(LOG_PANE).is_some()
+}
+
+/// Close the log pane and everything opened over it (e.g., the search)
+pub(super) fn close_log_pane(siv: &mut Cursive) {
+    while is_log_pane_open(siv) {
+        siv.pop_layer();
+    }
+}
+
+fn open_log_pane(siv: &mut Cursive) {
+    let state = Arc::new(PaneState {
+        generation: GENERATION.fetch_add(1, Ordering::SeqCst) + 1,
+        query: Mutex::default(),
+        shown: AtomicU64::new(u64::MAX),
+    });
+    let state_copy = state.clone();
+
+    // following the end of the log stops when scrolled up, and resumes at the bottom
+    let text = ScrollView::new(TextView::empty().with_name(LOG_TEXT))
+        .scroll_strategy(ScrollStrategy::StickToBottom);
+    let pane = Shortcuts::new(
+        Panel::new(
+            LinearLayout::vertical()
+                .child(text.full_height())
+                .child(TextView::empty().with_name(LOG_STATUS)),
+        )
+        .title(tr!("Installer Log")),
+    )
+    .shortcut('/', "Search", move |s| search_log(s, state_copy.clone()))
+    .shortcut(Key::Esc, "Close", toggle_log_pane);
+    siv.add_fullscreen_layer(Layer::new(pane.with_name(LOG_PANE).full_screen()));
+    update_log_pane(siv, &state);
+
+    let cb_sink = siv.cb_sink().clone();
+    thread::spawn(move || loop {
+        thread::sleep(REFRESH_INTERVAL);
+        if GENERATION.load(Ordering::SeqCst) != state.generation {
+            return;
+        }
+        let state = state.clone();
+        if cb_sink
+            .send(Box::new(move |s| update_log_pane(s, &state)))
+            .is_err()
+        {
+            return;
+        }
+    });
+}
+
+/// Show the lines written since the last update, or all of them again if the query changed
+fn update_log_pane(siv: &mut Cursive, state: &PaneState) {
+    if siv.screen_mut().find_layer_from_name(LOG_PANE).is_none() {
+        // closed without being toggled, e.g., by a dialog taking its place
+        GENERATION
+            .compare_exchange(
+                state.generation,
+                state.generation + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .ok();
+        return;
+    }
+    let written = log::written_log_lines();
+    if state.shown.swap(written, Ordering::SeqCst) == written {
+        return;
+    }
+
+    let query = state.query.lock().unwrap().clone();
+    let lowercase = query.to_lowercase();
+    let (lines, _) = log::log_lines();
+    let text = lines
+        .iter()
+        .filter(|x| query.is_empty() || x.to_lowercase().contains(&lowercase))
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let status = if query.is_empty() {
+        tr!("Press </> to search, <Esc> or <F12> to close.").to_string()
+    } else {
+        tr!(
+            "Showing the lines containing \"{}\". Press </> to search, <Esc> or <F12> to close.",
+            query
+        )
+    };
+    siv.call_on_name(LOG_TEXT, |view: &mut TextView| view.set_content(text));
+    siv.call_on_name(LOG_STATUS, |view: &mut TextView| view.set_content(status));
+}
+
+fn search_log(siv: &mut Cursive, state: Arc<PaneState>) {
+    let state_copy = state.clone();
+    let query = state.query.lock().unwrap().clone();
+    let submit = move |s: &mut Cursive, query: &str| {
+        *state.query.lock().unwrap() = query.to_string();
+        state.shown.store(u64::MAX, Ordering::SeqCst);
+        s.pop_layer();
+        update_log_pane(s, &state);
+    };
+    siv.add_layer(
+        Dialog::around(dialog_content(
+            LinearLayout::vertical()
+                .child(TextView::new(tr!(
+                    "Only the lines containing the text will be shown, leave it empty to show all of them."
+                )))
+                .child(
+                    EditView::new()
+                        .content(query)
+                        .on_submit(submit)
+                        .min_width(40),
+                ),
+            80,
+        ))
+        .title(tr!("Search the Log"))
+        .button(tr!("Show All"), move |s| {
+            *state_copy.query.lock().unwrap() = String::new();
+            state_copy.shown.store(u64::MAX, Ordering::SeqCst);
+            s.pop_layer();
+            update_log_pane(s, &state_copy);
+        })
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        })
+        .padding_lrtb(2, 2, 1, 1),
+    );
+}
@@ -0,0 +1,292 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    i18n,
+    network::{Mirror, MirrorTest, SpeedtestLimits},
+    tr,
+};
+
+use super::disk_list::table;
+
+/// Bumped whenever a field of `bench-mirrors --json` is renamed, removed or changes its
+/// meaning, adding fields does not bump it
+const SCHEMA_VERSION: u32 = 1;
+
+/// The results of `bench-mirrors` as printed with --json, the mirrors from the fastest
+#[derive(Debug, Serialize)]
+pub(super) struct BenchReport<'a> {
+    version: u32,
+    /// How many times each mirror has been tested
+    repeat: usize,
+    /// The limits of each test, in seconds, bytes and bytes per second
+    duration: Option<f64>,
+    size: Option<u64>,
+    limit_rate: Option<u64>,
+    mirrors: Vec<BenchedMirror<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchedMirror<'a> {
+    name: &'a str,
+    name_tr: &'a str,
+    location: &'a str,
+    location_tr: &'a str,
+    url: &'a str,
+    /// Given with --mirror rather than listed in the manifest
+    local: bool,
+    /// How many tests have finished, some may have been skipped by Ctrl-C
+    runs: usize,
+    /// How many of them have failed, the timed out ones included
+    failures: usize,
+    timed_out: usize,
+    /// Bytes per second, none if every test has failed
+    throughput: Option<Stats>,
+    latency_ms: Option<Stats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct Stats {
+    min: f64,
+    median: f64,
+    max: f64,
+}
+
+impl Stats {
+    fn of(mut values: Vec<f64>) -> Option<Stats> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let middle = values.len() / 2;
+        let median = if values.len() % 2 == 0 {
+            (values[middle - 1] + values[middle]) / 2.0
+        } else {
+            values[middle]
+        };
+
+        Some(Stats {
+            min: values[0],
+            median,
+            max: values[values.len() - 1],
+        })
+    }
+}
+
+impl<'a> BenchReport<'a> {
+    /// The report of the tests of each mirror, `local_urls` are the mirrors given by URL
+    pub fn new(
+        mirrors: &'a [Mirror],
+        local_urls: &[String],
+        tests: &[Vec<MirrorTest>],
+        repeat: usize,
+        limits: &SpeedtestLimits,
+    ) -> Self {
+        let mut benched = mirrors
+            .iter()
+            .zip(tests)
+            .map(|(mirror, tests)| {
+                let speeds = tests
+                    .iter()
+                    .filter_map(|x| match x {
+                        MirrorTest::Done(speed) => Some(speed),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                BenchedMirror {
+                    name: &mirror.name,
+                    name_tr: &mirror.name_tr,
+                    location: &mirror.loc,
+                    location_tr: &mirror.loc_tr,
+                    url: &mirror.url,
+                    local: local_urls.contains(&mirror.url),
+                    runs: tests.len(),
+                    failures: tests.len() - speeds.len(),
+                    timed_out: tests.iter().filter(|x| **x == MirrorTest::TimedOut).count(),
+                    throughput: Stats::of(speeds.iter().map(|x| x.throughput).collect()),
+                    latency_ms: Stats::of(
+                        speeds
+                            .iter()
+                            .map(|x| x.latency.as_secs_f64() * 1000.0)
+                            .collect(),
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        // the sort is stable, the failed mirrors keep their order
+        benched.sort_by(|a, b| match (a.throughput, b.throughput) {
+            (Some(a), Some(b)) => b.median.total_cmp(&a.median),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+
+        BenchReport {
+            version: SCHEMA_VERSION,
+            repeat,
+            duration: limits.duration.map(|x| x.as_secs_f64()),
+            size: limits.size,
+            limit_rate: limits.rate,
+            mirrors: benched,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// An aligned table of the mirrors, with the minimum, median and maximum of each
+    pub fn table(&self) -> String {
+        let mut rows = vec![vec![
+            tr!("Mirror").to_string(),
+            tr!("Throughput (min / median / max)").to_string(),
+            tr!("Latency (min / median / max)").to_string(),
+            tr!("Failures").to_string(),
+            tr!("URL").to_string(),
+        ]];
+        for mirror in self.mirrors.iter() {
+            rows.push(vec![
+                format!(
+                    "{} ({})",
+                    i18n::localized(mirror.name, mirror.name_tr),
+                    i18n::localized(mirror.location, mirror.location_tr)
+                ),
+                mirror
+                    .throughput
+                    .map(|x| {
+                        format!(
+                            "{:.2} / {:.2} / {:.2} MB/s",
+                            x.min / 1_000_000.0,
+                            x.median / 1_000_000.0,
+                            x.max / 1_000_000.0
+                        )
+                    })
+                    .unwrap_or_else(|| "-".to_string()),
+                mirror
+                    .latency_ms
+                    .map(|x| format!("{:.0} / {:.0} / {:.0} ms", x.min, x.median, x.max))
+                    .unwrap_or_else(|| "-".to_string()),
+                format!("{}/{}", mirror.failures, mirror.runs),
+                mirror.url.to_string(),
+            ]);
+        }
+
+        table(&rows)
+    }
+}
+
+/// A duration such as `10s`, `500ms` or `2m`, in seconds without a unit
+pub(super) fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.');
+    let (number, unit) = s.split_at(split.unwrap_or(s.len()));
+    let scale = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        _ => {
+            return Err(anyhow!(
+                "expected a duration such as `10s`, `500ms` or `2m`"
+            ))
+        }
+    };
+    let duration = number
+        .parse::<f64>()
+        .ok()
+        .filter(|x| x.is_finite() && *x > 0.0)
+        .ok_or_else(|| anyhow!("expected a positive duration such as `10s`"))?;
+
+    Ok(Duration::from_secs_f64(duration * scale))
+}
+
+/// A size in bytes such as `16M` or `512K`, the suffixes are powers of 1024
+pub(super) fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let unit = s.trim_start_matches(|c: char| c.is_ascii_digit());
+    let number = &s[..s.len() - unit.len()];
+    let scale: u64 = match unit.trim_end_matches("iB").trim_end_matches('B') {
+        "" => 1,
+        "K" | "k" => 1 << 10,
+        "M" | "m" => 1 << 20,
+        "G" | "g" => 1 << 30,
+        _ => return Err(anyhow!("expected a size such as `16M`, `512K` or `1G`")),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|x| *x > 0)
+        .and_then(|x| x.checked_mul(scale))
+        .ok_or_else(|| anyhow!("expected a positive size such as `16M`"))
+}
+
+#[test]
+fn test_stats() {
+    assert_eq!(Stats::of(vec![]), None);
+    assert_eq!(
+        Stats::of(vec![3.0, 1.0, 2.0]),
+        Some(Stats {
+            min: 1.0,
+            median: 2.0,
+            max: 3.0
+        })
+    );
+    assert_eq!(Stats::of(vec![4.0, 1.0, 2.0, 8.0]).unwrap().median, 3.0);
+}
+
+#[test]
+fn test_bench_report() {
+    use crate::network::MirrorSpeed;
+
+    let mirror = |name: &str| Mirror {
+        name: name.to_string(),
+        name_tr: name.to_string(),
+        loc: "Local".to_string(),
+        loc_tr: "Local".to_string(),
+        url: format!("https://{name}/aosc-os"),
+        repo_url: None,
+    };
+    let done = |throughput: f64| {
+        MirrorTest::Done(MirrorSpeed {
+            latency: Duration::from_millis(40),
+            throughput,
+        })
+    };
+    let mirrors = [mirror("slow"), mirror("down"), mirror("fast")];
+    let tests = [
+        vec![done(1e6), MirrorTest::TimedOut, done(3e6)],
+        vec![MirrorTest::Failed; 3],
+        vec![done(9e6), done(8e6), done(7e6)],
+    ];
+    let limits = SpeedtestLimits::default();
+    let report = BenchReport::new(&mirrors, &[], &tests, 3, &limits);
+    let names = report.mirrors.iter().map(|x| x.name).collect::<Vec<_>>();
+    assert_eq!(names, ["fast", "slow", "down"]);
+    assert_eq!(report.mirrors[1].failures, 1);
+    assert_eq!(report.mirrors[1].timed_out, 1);
+    assert_eq!(report.mirrors[1].throughput.unwrap().median, 2e6);
+    assert_eq!(report.mirrors[2].latency_ms, None);
+    assert!(report
+        .table()
+        .lines()
+        .nth(1)
+        .unwrap()
+        .contains("7.00 / 8.00 / 9.00 MB/s"));
+}
+
+#[test]
+fn test_parse_limits() {
+    assert_eq!(parse_duration("10s").unwrap(), Duration::from_secs(10));
+    assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+    assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+    assert_eq!(parse_duration("3").unwrap(), Duration::from_secs(3));
+    assert!(parse_duration("0s").is_err());
+    assert!(parse_duration("10h").is_err());
+    assert_eq!(parse_size("16M").unwrap(), 16 * 1024 * 1024);
+    assert_eq!(parse_size("512KiB").unwrap(), 512 * 1024);
+    assert_eq!(parse_size("1000").unwrap(), 1000);
+    assert!(parse_size("M").is_err());
+    assert!(parse_size("16T").is_err());
+}
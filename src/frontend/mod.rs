@@ -1,53 +1,197 @@
 use std::{
+    collections::{HashMap, VecDeque},
     convert::TryInto,
-    io::Write,
+    io::{Read, Write},
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         mpsc::{self, Sender},
         Arc,
     },
     thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
+    boards,
+    cancel::{self, CancelToken, Cancelled},
     disks,
-    install::{self, log_system_info},
-    network, DEPLOYKIT_USER_AGENT, LOG_FILE,
+    exit_code::ExitCode,
+    install::{self, log_system_info, UnmountStatus},
+    log::{save_log, StepJournal, StepOutcome, StepTimer},
+    manifest::{self, Manifest},
+    network, tr, wifi,
 };
 use anyhow::{anyhow, Result};
 use cursive::utils::Counter;
-use log::info;
+use log::{error, info, warn};
+use number_prefix::NumberPrefix;
 // use nix::fcntl::FallocateFlags;
 use rand::{thread_rng, Rng};
 use rustix::{fd::AsFd, fs::FallocateFlags};
 use serde::{de::Visitor, Deserialize, Serialize};
 use std::sync::atomic;
 
+mod accessible;
 mod cli;
+mod cmdline;
+mod config_file;
+mod disk_list;
+mod dry_run;
 mod games;
+mod help;
+mod json_progress;
+mod keyboard;
+mod locale;
+mod log_pane;
+mod mirror_bench;
+mod mounts;
+mod network_setup;
+mod notify;
+mod password;
+mod resume;
+mod session;
+mod settings;
+mod shortcuts;
+mod step_indicator;
+mod theme;
+mod timezone;
 mod tui;
+mod validate;
 
 pub use cli::*;
 use sha2::{Digest, Sha256};
 pub use tui::tui_main;
 
+/// How often a stalled download wakes up to check for the cancellation
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+/// How many mismatching files to list in the error, the rest go to the log only
+const MAX_LISTED_MISMATCHES: usize = 10;
 pub const DEFAULT_EMPTY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// Free space to be left on the system partition after the installation, or a warning is shown
+const SPACE_CUSHION: u64 = 2 * 1024 * 1024 * 1024;
 
 const STEP1: &str = "Step 1 of 8: Formatting partitions";
 const STEP2: &str = "Step 2 of 8: Downloading system release";
 const STEP3: &str = "Step 3 of 8: Verifying system release";
 const STEP4: &str = "Step 4 of 8: Unpacking system release";
+const VERIFY_FILES: &str = "Step 4 of 8: Verifying unpacked files";
 const STEP5: &str = "Step 5 of 8: Generating initramfs (initial RAM filesystem)";
 const STEP6: &str = "Step 6 of 8: Installing and configuring GRUB bootloader";
 const STEP7: &str = "Step 7 of 8: Generating OpenSSH host keys";
 const STEP8: &str = "Step 8 of 8: Finalising installation";
+// not a step of its own while installing, but may be run again from the finish screen
+const FSTAB_STEP: &str = "Writing the filesystem table (/etc/fstab)";
+/// Seconds each step takes on a typical machine, until there are statistics from previous runs
+const STEP_ESTIMATES: &[(&str, f64)] = &[
+    (STEP1, 10.0),
+    (STEP2, 300.0),
+    (STEP3, 5.0),
+    (STEP4, 240.0),
+    (VERIFY_FILES, 120.0),
+    (STEP5, 60.0),
+    (STEP6, 30.0),
+    (STEP7, 5.0),
+    (STEP8, 60.0),
+];
+/// The step a status reported by `InstallProgress::Pending` belongs to, some statuses add
+/// details after the step (e.g., the verification rate)
+fn step_of(status: &str) -> Option<&'static str> {
+    STEP_ESTIMATES
+        .iter()
+        .map(|(step, _)| *step)
+        .find(|step| status.starts_with(step))
+}
+
+/// Partitions with less data than this are taken as empty when formatting them
+const TRIVIAL_USAGE: u64 = 64 * 1024 * 1024;
+// with this much RAM, compressed swap in it is recommended over swapping to the disk
+const ZRAM_RECOMMENDED_MEM: u64 = 8 * 1024 * 1024 * 1024;
+const ZRAM_UNIT: &str = "zramswap.service";
+const DATA_LOSS_WARNING: &str = "WARNING: {} is not empty!\n\n{}\n\nEverything on it will be erased when it is formatted, and can not be recovered. Please make sure it has been backed up, or choose another partition.";
+const UNEXAMINED_DATA_LOSS_WARNING: &str = "WARNING: Everything on {} will be erased when it is formatted.\n\n{}\n\nIts contents are not examined in a dry run, as that would mount it.";
+/// How many lines of the output of the running command are shown, at most how wide, and how
+/// often they are updated at most
+const COMMAND_TAIL_LINES: usize = 3;
+const COMMAND_TAIL_WIDTH: usize = 100;
+const COMMAND_TAIL_INTERVAL: Duration = Duration::from_millis(200);
+const LAZY_UNMOUNT_INFO: &str = "Some filesystems of the installed system were still in use and could not be unmounted cleanly. All data has been synced to disk, and they have been detached (lazily unmounted). It is safe to reboot.";
 
 pub(crate) enum InstallProgress {
     Pending(String, usize),
+    /// A step has failed, the frontend should answer whether to retry it
+    Retry(String, Sender<bool>),
+    /// The system is installed and still mounted at the given path, with the foreign OSes
+    /// detected by os-prober and a warning if an existing Windows appears to be missing from
+    /// the boot menu, and how each step went. The frontend may open a shell in it, and should
+    /// answer with what to do next, sent again after re-running a step
+    PostInstall(PathBuf, BootMenu, StepJournal, Sender<PostInstallAction>),
+    /// Something could not be set up as configured and has been worked around, to be
+    /// shown once the installation is done
+    Warning(String),
+    /// The installation is done, with how the installed system has been unmounted
+    Finished(UnmountStatus),
+    /// The steps the installation is going to run, with how many seconds each is expected
+    /// to take, for the overall progress
+    Steps(Vec<(&'static str, f64)>),
+    /// Estimated time until the installation is done, `None` without statistics from
+    /// previous runs to estimate it from
+    Remaining(Option<Duration>),
+    /// Bytes transferred, speed and ETA of the current download or unpack, `None` when it is over
+    Transfer(Option<TransferStatus>),
+    /// A line printed by the command running, e.g., mkfs or dracut
+    Output(String),
+}
+
+/// What to do with the installed system once the installation is over
+pub(crate) enum PostInstallAction {
+    /// Install the bootloader again
+    RetryBootloader,
+    /// Write /etc/fstab and configure the installed system again
+    RerunConfiguration,
+    /// Unmount the installed system
+    Finish,
+}
+
+/// What the installation is doing, which decides what quitting the installer leaves behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstallPhase {
+    /// Nothing has been written to the target yet
+    Preparing,
+    Formatting,
+    /// The target has been formatted, but the system release is not unpacked to it yet
+    Downloading,
+    Unpacking,
+    /// The installed system is being configured (e.g., the bootloader), there are no safe
+    /// points to stop at any more
+    Configuring,
+    /// The system is installed and still mounted
     Finished,
 }
 
+impl InstallPhase {
+    /// The phase the status reported by `InstallProgress::Pending` belongs to
+    fn of_step(status: &str) -> Self {
+        match step_of(status) {
+            Some(STEP1) => InstallPhase::Formatting,
+            Some(STEP2 | STEP3) => InstallPhase::Downloading,
+            Some(STEP4 | VERIFY_FILES) => InstallPhase::Unpacking,
+            _ => InstallPhase::Configuring,
+        }
+    }
+}
+
+/// The exit code of an installation failed at the step of `status`
+fn step_exit_code(status: &str) -> ExitCode {
+    match step_of(status) {
+        Some(STEP1) => ExitCode::Partitioning,
+        Some(STEP2 | STEP3) => ExitCode::Network,
+        Some(STEP4 | VERIFY_FILES) => ExitCode::Extraction,
+        Some(STEP6) => ExitCode::Bootloader,
+        _ => ExitCode::Internal,
+    }
+}
+
 macro_rules! send_error {
     ($error_channel_tx_copy:ident, $e:ident) => {
         $error_channel_tx_copy.send($e.to_string()).unwrap();
@@ -56,21 +200,110 @@ macro_rules! send_error {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct InstallConfig {
     variant: Option<Arc<network::VariantEntry>>,
     partition: Option<Arc<disks::Partition>>,
+    /// Repartition this whole disk when the installation starts, `partition` is then
+    /// only the planned system partition
+    wipe_disk: Option<Arc<disks::DkDerive>>,
+    /// Whether the storage has been laid out by the guided or the advanced mode
+    storage_mode: Option<StorageMode>,
+    /// Space left unpartitioned at the end of `wipe_disk`, for other operating systems
+    keep_free_space: Option<u64>,
+    /// Other partitions mounted into the installed system, chosen in the advanced mode
+    mounts: Option<Arc<Vec<mounts::MountAssignment>>>,
+    /// Encrypt the system partition with LUKS
+    encrypt: Option<bool>,
+    /// The passphrase of the encrypted system partition, never saved nor exported
+    #[serde(skip)]
+    encryption_passphrase: Option<Arc<String>>,
+    /// Pass TRIM (discard) through the encrypted partitions to the drives
+    encrypt_discard: Option<bool>,
     mirror: Option<Arc<network::Mirror>>,
+    /// A copy of the system release on this device, installed instead of downloading it
+    local_release: Option<Arc<PathBuf>>,
     full_name: Option<Arc<String>>,
     user: Option<Arc<String>>,
+    uid: Option<u32>,
+    groups: Option<Arc<Vec<String>>>,
+    /// Make the user an administrator (in the wheel group and allowed to use sudo), the default
+    admin: Option<bool>,
+    sudo_nopasswd: Option<bool>,
+    /// A plain text password, or a yescrypt (`$y$`) or SHA-512 (`$6$`) hash
     password: Option<Arc<String>>,
-    root_password: Option<Arc<String>>,
+    root_password: Option<Arc<RootPassword>>,
+    /// yescrypt cost factor for hashing the passwords
+    password_cost: Option<u32>,
+    /// Accept plain text passwords in the configuration file
+    allow_plaintext_password: Option<bool>,
+    /// Format the target partition without asking, even if there is data on it (for
+    /// unattended installs, the TUI always asks)
+    allow_data_loss: Option<bool>,
     hostname: Option<String>,
     locale: Option<Arc<String>>,
+    extra_locales: Option<Arc<Vec<String>>>,
     timezone: Option<Arc<String>>,
     tc: Option<Arc<String>>,
+    enable_ntp: Option<bool>,
+    enable_sshd: Option<bool>,
+    /// Extra systemd units to enable and disable, applied after the other services
+    enable_services: Option<Arc<Vec<String>>>,
+    disable_services: Option<Arc<Vec<String>>>,
+    ssh_keys: Option<Arc<Vec<String>>>,
+    ssh_disable_password_auth: Option<bool>,
+    copy_network_config: Option<bool>,
+    extra_packages: Option<Arc<Vec<String>>>,
+    kernel_cmdline: Option<Arc<String>>,
+    grub_timeout: Option<u32>,
+    grub_os_prober: Option<bool>,
+    grub_hidden_menu: Option<bool>,
+    /// Show a Plymouth boot splash, defaults to whether the variant is a desktop one
+    boot_splash: Option<bool>,
+    /// Set up the console (and the boot menu) for a HiDPI display, detected from the
+    /// display resolution by default
+    hidpi: Option<bool>,
+    /// Console font used on HiDPI displays
+    console_font: Option<Arc<String>>,
+    grub_gfxmode: Option<Arc<String>>,
+    generate_machine_id: Option<bool>,
+    keymap: Option<Arc<String>>,
+    default_target: Option<Arc<String>>,
+    disable_display_manager: Option<bool>,
+    autologin: Option<bool>,
+    shell: Option<Arc<String>>,
+    nvidia_driver: Option<bool>,
+    /// Point the package sources of the installed system at the selected mirror
+    use_mirror_repo: Option<bool>,
+    board: Option<Arc<String>>,
+    preserve_home: Option<bool>,
+    restore_users: Option<bool>,
+    /// Re-hash the unpacked files against the release's file manifest (the default)
+    verify_files: Option<bool>,
+    /// Create no user and leave the root account alone, the end user creates their
+    /// account on first boot instead (for preloaded machines)
+    oobe: Option<bool>,
+    /// Install into this existing directory instead of a partition, skipping the
+    /// partitioning, fstab and bootloader steps
+    target_dir: Option<PathBuf>,
+    ntp_servers: Option<Arc<Vec<String>>>,
+    /// Where the installed system swaps to, the swapfile is created if `use_swap` is set
+    swap: Option<SwapKind>,
+    /// The existing swap partition for `SwapKind::Partition`
+    swap_partition: Option<Arc<disks::Partition>>,
     use_swap: Arc<AtomicBoolWrapper>,
     swap_size: Arc<Option<f64>>,
+    /// Set up resuming from the swapfile or swap partition, to be able to hibernate
     is_hibernation: Arc<AtomicBoolWrapper>,
+    /// Shell commands run before partitioning and in the installed system, only set by
+    /// configuration files
+    hooks: Option<Arc<config_file::Hooks>>,
+    /// The release bulletin the user has seen, critical ones are repeated on the summary
+    #[serde(skip)]
+    bulletin: Option<Arc<network::Bulletin>>,
+    /// Go back to the summary once the current step is done, for changing a single item
+    #[serde(skip)]
+    back_to_summary: bool,
 }
 
 impl Default for InstallConfig {
@@ -78,14 +311,63 @@ impl Default for InstallConfig {
         InstallConfig {
             variant: None,
             partition: None,
+            wipe_disk: None,
+            storage_mode: None,
+            keep_free_space: None,
+            mounts: None,
+            encrypt: None,
+            encryption_passphrase: None,
+            encrypt_discard: None,
             mirror: None,
+            local_release: None,
             full_name: None,
             user: None,
+            uid: None,
+            groups: None,
+            admin: None,
+            sudo_nopasswd: None,
             password: None,
+            password_cost: None,
+            allow_plaintext_password: None,
+            allow_data_loss: None,
             hostname: None,
             locale: None,
+            extra_locales: None,
             timezone: None,
             tc: None,
+            enable_ntp: None,
+            enable_sshd: None,
+            enable_services: None,
+            disable_services: None,
+            ssh_keys: None,
+            ssh_disable_password_auth: None,
+            copy_network_config: None,
+            extra_packages: None,
+            kernel_cmdline: None,
+            grub_timeout: None,
+            grub_os_prober: None,
+            grub_hidden_menu: None,
+            boot_splash: None,
+            hidpi: None,
+            console_font: None,
+            grub_gfxmode: None,
+            generate_machine_id: None,
+            keymap: None,
+            default_target: None,
+            disable_display_manager: None,
+            autologin: None,
+            shell: None,
+            nvidia_driver: None,
+            use_mirror_repo: None,
+            board: None,
+            preserve_home: None,
+            restore_users: None,
+            verify_files: None,
+            oobe: None,
+            target_dir: None,
+            ntp_servers: None,
+            swap: None,
+            swap_partition: None,
             use_swap: Arc::new(AtomicBoolWrapper {
                 v: AtomicBool::new(false),
             }),
@@ -94,8 +376,334 @@ impl Default for InstallConfig {
                 v: AtomicBool::new(false),
             }),
             root_password: None,
+            hooks: None,
+            bulletin: None,
+            back_to_summary: false,
+        }
+    }
+}
+
+/// How the storage step lays out the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageMode {
+    /// Partition a whole disk automatically
+    Guided,
+    /// Choose the partitions by hand
+    Advanced,
+}
+
+/// Where the installed system swaps to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwapKind {
+    None,
+    /// Compressed swap in the RAM (zramswap)
+    Zram,
+    /// An existing swap partition
+    Partition,
+    /// A swapfile on the system partition
+    File,
+}
+
+impl SwapKind {
+    /// Whether the swap is on the disk, which can be resumed from after hibernation
+    pub fn is_on_disk(self) -> bool {
+        matches!(self, SwapKind::Partition | SwapKind::File)
+    }
+}
+
+/// How the system partition is spent by the installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SpaceBudget {
+    partition: u64,
+    /// The system release, kept on the system partition until it has been unpacked
+    download: u64,
+    installed: u64,
+    swapfile: u64,
+}
+
+/// Whether the system partition is large enough for the installation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpaceStatus {
+    Enough,
+    /// Less than `SPACE_CUSHION` is left after the installation
+    Tight,
+    /// The given number of bytes more are needed
+    Short(u64),
+}
+
+impl SpaceBudget {
+    /// Space left on the system partition after the installation, negative if it is too small
+    fn free(&self) -> i64 {
+        self.partition as i64 - self.installed as i64 - self.swapfile as i64
+    }
+
+    fn status(&self) -> SpaceStatus {
+        // the download is still there while it is unpacked
+        let needed = self.download + self.installed + self.swapfile;
+        if needed > self.partition {
+            SpaceStatus::Short(needed - self.partition)
+        } else if self.free() < SPACE_CUSHION as i64 {
+            SpaceStatus::Tight
+        } else {
+            SpaceStatus::Enough
+        }
+    }
+
+    /// The sizes adding up to the free space, one per line
+    fn describe(&self) -> String {
+        let mut lines = vec![
+            tr!("System partition: {}", human_size(self.partition)),
+            tr!("Installed system: {}", human_size(self.installed)),
+        ];
+        if self.swapfile > 0 {
+            lines.push(tr!("Swapfile: {}", human_size(self.swapfile)));
+        }
+        let free = match self.free() {
+            x if x < 0 => format!("-{}", human_size(x.unsigned_abs())),
+            x => human_size(x as u64),
+        };
+        lines.push(tr!("Free space after installation: {}", free));
+
+        lines.join("\n")
+    }
+
+    /// What the status means, empty if there is enough space
+    fn status_text(&self) -> String {
+        match self.status() {
+            SpaceStatus::Enough => String::new(),
+            SpaceStatus::Tight => tr!(
+                "Less than {} will be left free on the system partition, which may soon fill up with updates.",
+                human_size(SPACE_CUSHION)
+            ),
+            SpaceStatus::Short(missing) => tr!(
+                "The system partition is too small, {} more is needed (including the download of {}, which is kept on it until it has been unpacked).",
+                human_size(missing),
+                human_size(self.download)
+            ),
+        }
+    }
+}
+
+/// The swap recommended for a device with `mem` bytes of RAM
+fn recommended_swap(mem: u64) -> SwapKind {
+    if mem >= ZRAM_RECOMMENDED_MEM {
+        SwapKind::Zram
+    } else {
+        SwapKind::File
+    }
+}
+
+/// How the root account should be set up
+///
+/// In the config file this is a string: `none` leaves the root account as shipped,
+/// `locked` disables password login for root, and a yescrypt or SHA-512 crypt(3) hash
+/// is written as-is. Other values starting with `$` are refused, anything else is taken
+/// as a plain text password.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RootPassword {
+    None,
+    Locked,
+    Hash(String),
+    Password(String),
+}
+
+impl TryFrom<String> for RootPassword {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        Ok(match s.as_str() {
+            "none" => RootPassword::None,
+            "locked" => RootPassword::Locked,
+            x if install::is_password_hash(x) => RootPassword::Hash(s),
+            x if x.starts_with('$') => {
+                return Err(anyhow!(
+                    "Root password starting with `$` must be a yescrypt ($y$) or SHA-512 ($6$) hash without `:` or line breaks."
+                ))
+            }
+            _ => RootPassword::Password(s),
+        })
+    }
+}
+
+impl From<RootPassword> for String {
+    fn from(p: RootPassword) -> Self {
+        match p {
+            RootPassword::None => "none".to_string(),
+            RootPassword::Locked => "locked".to_string(),
+            RootPassword::Hash(s) | RootPassword::Password(s) => s,
+        }
+    }
+}
+
+impl InstallConfig {
+    /// Forget the chosen partition and the choices depending on it
+    fn reset_partition(&mut self) {
+        self.partition = None;
+        self.wipe_disk = None;
+        self.storage_mode = None;
+        self.keep_free_space = None;
+        self.encrypt = None;
+        self.encryption_passphrase = None;
+        self.encrypt_discard = None;
+        self.preserve_home = None;
+        self.restore_users = None;
+    }
+
+    /// Switch to another variant, the chosen partition is forgotten if the variant does not
+    /// fit in it, returns the notice telling what has been reset
+    fn change_variant(&mut self, variant: network::VariantEntry) -> Option<String> {
+        let required_size = variant.install_size + variant.size;
+        self.variant = Some(Arc::new(variant));
+        if self.partition.as_ref()?.size >= required_size {
+            return None;
+        }
+        self.reset_partition();
+
+        Some(tr!("The system partition selected before is too small for this variant, please select the system partition again.").to_string())
+    }
+
+    /// Switch to another disk, the partition chosen on the previous one is forgotten,
+    /// returns the notice telling what has been reset
+    fn change_disk(&mut self, disk: &Path) -> Option<String> {
+        let previous = match self.wipe_disk.as_ref() {
+            Some(device) => device.path.clone(),
+            None => self.partition.as_ref()?.parent_path.clone()?,
+        };
+        if previous == disk {
+            return None;
+        }
+        self.reset_partition();
+
+        Some(tr!(
+            "The system partition selected on {} has been reset, as another disk has been selected.",
+            previous.display()
+        ))
+    }
+
+    /// The configuration as JSON with the passwords redacted, for the installation log
+    fn redacted_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(password) = value.get_mut("password") {
+            if !password.is_null() {
+                *password = "<redacted>".into();
+            }
+        }
+        if let Some(root_password) = value.get_mut("root_password") {
+            if !matches!(root_password.as_str(), None | Some("none") | Some("locked")) {
+                *root_password = "<redacted>".into();
+            }
+        }
+
+        Ok(serde_json::to_string(&value)?)
+    }
+}
+
+impl RootPassword {
+    /// Plain text passwords that would be mistaken for another mode or refused when read back
+    fn is_ambiguous_password(password: &str) -> bool {
+        !matches!(
+            RootPassword::try_from(password.to_string()),
+            Ok(RootPassword::Password(_))
+        )
+    }
+}
+
+/// Whether to show a boot splash, desktop variants have it by default
+fn boot_splash(config: &InstallConfig) -> bool {
+    config.boot_splash.unwrap_or_else(|| {
+        config
+            .variant
+            .as_ref()
+            .map(|x| x.is_desktop())
+            .unwrap_or(false)
+    })
+}
+
+/// Whether to set up the console for a HiDPI display, detected from the largest connected
+/// display by default
+fn hidpi(config: &InstallConfig) -> bool {
+    config.hidpi.unwrap_or_else(|| {
+        install::detect_display_resolution()
+            .map(install::is_hidpi_resolution)
+            .unwrap_or(false)
+    })
+}
+
+/// Refuse plain text passwords from a configuration file, unless `allow_plaintext_password`
+/// is set, the passwords should be stored as hashes instead
+fn check_config_file_passwords(config: &InstallConfig) -> Result<()> {
+    if config.allow_plaintext_password.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let user_plaintext = config
+        .password
+        .as_ref()
+        .is_some_and(|x| !install::is_password_hash(x));
+    let root_plaintext = matches!(
+        config.root_password.as_deref(),
+        Some(RootPassword::Password(_))
+    );
+    if user_plaintext || root_plaintext {
+        return Err(anyhow!(
+            "The configuration file contains a plain text password. Please replace it with a yescrypt ($y$) or SHA-512 ($6$) hash, or set `allow_plaintext_password = true` to use it anyway."
+        ));
+    }
+
+    Ok(())
+}
+
+/// Hash the password with yescrypt, unless it is already hashed
+fn password_hash(password: &str, cost: Option<u32>) -> Result<String> {
+    if install::is_password_hash(password) {
+        Ok(password.to_string())
+    } else {
+        install::hash_password(password, cost)
+    }
+}
+
+/// Refuse configurations that leave nobody able to administrate the system
+fn check_root_account(config: &InstallConfig) -> Result<()> {
+    if config.oobe.unwrap_or(false) {
+        // the account created on first boot is the administrator, root is the way in until then
+        if config.root_password.as_deref() == Some(&RootPassword::Locked) {
+            return Err(anyhow!(
+                "The root account may not be locked when the user account is created on first boot."
+            ));
+        }
+        if config.autologin.unwrap_or(false)
+            || config.ssh_keys.as_ref().is_some_and(|x| !x.is_empty())
+        {
+            return Err(anyhow!(
+                "Automatic login and SSH keys need a user account, which will only be created on first boot."
+            ));
         }
+
+        return Ok(());
+    }
+
+    if config.root_password.as_deref() != Some(&RootPassword::Locked) {
+        return Ok(());
     }
+
+    let is_admin = config.user.is_some()
+        && config.admin.unwrap_or(true)
+        && config
+            .groups
+            .as_ref()
+            .map(|x| x.iter().any(|x| x == "wheel"))
+            .unwrap_or(true);
+
+    if !is_admin {
+        return Err(anyhow!(
+            "The root account is locked but no administrator (a user in the wheel group) will be created, the installed system would not be administrable."
+        ));
+    }
+
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -146,154 +754,817 @@ impl<'de> Deserialize<'de> for AtomicBoolWrapper {
     }
 }
 
+/// Install as planned by `config`, or continue the interrupted installation `resume`
+/// (planned by `config` as well)
 fn begin_install(
     sender: Sender<InstallProgress>,
     config: InstallConfig,
     tempdir: PathBuf,
+    cancel: CancelToken,
+    resume: Option<resume::Resumption>,
+) -> Result<()> {
+    let output_tx = sender.clone();
+    install::watch_command_output(Some(Box::new(move |line| {
+        output_tx
+            .send(InstallProgress::Output(line.to_string()))
+            .ok();
+    })));
+    let result = install_system(sender, config, tempdir, cancel, resume);
+    // the frontend knows the installation is over once all senders are gone
+    install::watch_command_output(None);
+
+    result
+}
+
+fn install_system(
+    sender: Sender<InstallProgress>,
+    config: InstallConfig,
+    tempdir: PathBuf,
+    cancel: CancelToken,
+    resume: Option<resume::Resumption>,
 ) -> Result<()> {
     log_system_info();
+    check_root_account(&config)?;
+    info!("Installation plan: {}", config.redacted_json()?);
+    let planned = planned_steps(&config);
+    let steps = journal_steps(&planned, config.target_dir.is_none());
+    // all the steps run, unless resuming
+    let runs = |step: &str| resume.as_ref().is_none_or(|x| x.runs(step));
+    let plan = planned
+        .into_iter()
+        .filter(|(x, _)| runs(x))
+        .collect::<Vec<_>>();
+    let mut timer = StepTimer::new(&plan, &[STEP2], &resume::named_steps());
+    sender.send(InstallProgress::Steps(timer.plan().to_vec()))?;
+    match resume.as_ref().filter(|x| x.from != STEP1) {
+        Some(resume) => {
+            info!("Resuming the installation from {}", resume.from);
+            let pending = steps.iter().copied().filter(|x| runs(x));
+            resume::record_pending(&pending.collect::<Vec<_>>());
+        }
+        // everything is done again
+        None => resume::start(&config, &steps),
+    }
 
     info!("Prepare trying unmount before deploykit mount partition ...");
     install::prepare_try_umount()?;
+    cancel.check()?;
 
-    let refresh_interval = std::time::Duration::from_millis(30);
-    let counter = Counter::new(0);
-    let url;
-    let file_size: usize;
-    let right_sha256;
-    let extract_done: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
-    let download_done: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
-
-    sender.send(InstallProgress::Pending(STEP1.to_string(), 0))?;
-    info!("{}", STEP1);
+    let root = config.target_dir.clone().unwrap_or(tempdir);
+    let target = match resume.as_ref().and_then(|x| x.target.as_ref()) {
+        Some(target) => {
+            mount_target(target, &config, &root)?;
+            target.clone()
+        }
+        None => {
+            if let Some(hooks) = config.hooks.as_ref() {
+                run_hooks("pre-install", &hooks.pre_install)?;
+            }
 
-    let partition = &config.partition.unwrap();
+            sender.send(InstallProgress::Pending(STEP1.to_string(), 0))?;
+            next_step(&sender, &mut timer, STEP1)?;
+            let target = prepare_target(&config, &root)?;
+            resume::record_target(&target, &config);
+            target
+        }
+    };
+    let partition = target.partition.as_ref();
+    let use_swap = config.use_swap.v.load(Ordering::SeqCst);
 
-    info!("Formatting partitions: {:?}", partition);
-    disks::format_partition(partition)?;
+    if runs(STEP2) {
+        let unpacked = download_and_extract(&sender, &config, &mut timer, &root, &cancel).and_then(
+            |manifest| {
+                if config.verify_files.unwrap_or(true) {
+                    verify_files(&sender, &config, &mut timer, &root, manifest, &cancel)
+                } else {
+                    info!("Skipping verification of the unpacked files");
+                    Ok(())
+                }
+            },
+        );
+        if let Err(e) = unpacked {
+            if cancel::is_cancelled(&e) {
+                wipe_cancelled(&config, &root, &cancel)?;
+            }
+            return Err(e);
+        }
+        resume::record_done(&[STEP2, STEP3, STEP4, VERIFY_FILES]);
+    }
 
-    info!("Mounting partitions: {:?}", partition);
-    let mount_path = install::auto_mount_root_path(&tempdir, partition)?;
-    let mount_path_copy = mount_path.clone();
-    let mut efi_path = mount_path.clone();
-    if disks::is_efi_booted() {
-        efi_path.push("efi");
+    // the steps so far have succeeded, or the installation would have stopped
+    let mut journal = StepJournal::new(&steps);
+    for step in steps
+        .iter()
+        .take_while(|x| **x != FSTAB_STEP && **x != STEP5)
+    {
+        journal.record(step, StepOutcome::Succeeded);
+    }
+    if let Some(resume) = resume.as_ref() {
+        for step in resume.completed.iter().filter(|x| !runs(x)) {
+            journal.record(step, StepOutcome::Succeeded);
+        }
+    }
 
-        info!("Finding ESP partition from: {:?}", partition.parent_path);
-        let mut esp_part = disks::find_esp_partition(partition.parent_path.as_ref().unwrap())?;
-        info!("ESP is: {:?}", esp_part);
+    let system = InstalledSystem {
+        sender: &sender,
+        config: &config,
+        root: &root,
+        partition,
+        previous_install: target.previous_install.as_ref(),
+        use_swap,
+        luks_uuid: target.luks_uuid.as_deref(),
+        // the guest may not be able to resolve names on its own
+        resolv_conf: std::fs::read("/etc/resolv.conf").ok(),
+    };
+    if runs(FSTAB_STEP) {
+        system.write_fstab(&mut journal).ok();
+    }
 
-        std::fs::create_dir_all(&efi_path).unwrap();
-        if esp_part.fs_type.is_none() {
-            // format the un-formatted ESP partition
-            esp_part.fs_type = Some("vfat".to_string());
+    // the last safe point, the installed system is configured from within from here on
+    cancel.set_finishing();
+    if let Err(e) = cancel.check() {
+        wipe_cancelled(&config, &root, &cancel)?;
+        return Err(e);
+    }
 
-            info!("Formatting ESP partition: {:?}", esp_part);
-            disks::format_partition(&esp_part)?;
+    // a failed step leaves the installed system mounted, so that it can be fixed from the
+    // finish screen
+    let skipped = steps
+        .iter()
+        .copied()
+        .filter(|x| !runs(x))
+        .collect::<Vec<_>>();
+    let mut boot_menu = system
+        .configure(&mut journal, &mut timer, &skipped)
+        .unwrap_or_default();
+    loop {
+        let (action_tx, action_rx) = mpsc::channel();
+        sender.send(InstallProgress::PostInstall(
+            root.clone(),
+            boot_menu.clone(),
+            journal.clone(),
+            action_tx,
+        ))?;
+        match action_rx.recv().unwrap_or(PostInstallAction::Finish) {
+            PostInstallAction::RetryBootloader => {
+                if let Ok(menu) = system.retry_bootloader(&mut journal) {
+                    boot_menu = menu;
+                }
+            }
+            PostInstallAction::RerunConfiguration => {
+                system.reconfigure(&mut journal).ok();
+            }
+            PostInstallAction::Finish => break,
         }
-        install::mount_root_path(&esp_part, &efi_path)?;
     }
-    if let Some(variant) = config.variant.as_ref() {
-        let mirror_url = &config.mirror.as_ref().unwrap().url;
-        file_size = variant.size.try_into().unwrap();
-        url = format!("{}{}", mirror_url, variant.url);
-        right_sha256 = variant.sha256sum.clone();
+    // the steps left may be run with `install --resume`
+    if !journal.is_incomplete() {
+        resume::discard();
+    }
 
-        info!(
-            "Mirror URL is: {}, file_size: {}, url: {}, right_sha256: {}",
-            mirror_url, file_size, url, right_sha256
-        );
+    timer.finish();
+    timer.save_stats();
+    info!("Saving installation log ...");
+    save_log(Some(&root));
+
+    info!("Trying to swapoff ...");
+    install::swapoff(&root);
+
+    info!("Unmounting the installed system ...");
+    let unmount = if partition.is_some() {
+        install::unmount_recursively(&root)?
     } else {
-        return Err(anyhow!(
-            "Installer could not parse release metadata: `variant` field not found."
-        ));
-    }
+        // the directory itself is managed by the user
+        install::unmount_beneath(&root)?
+    };
+
+    sender.send(InstallProgress::Finished(unmount))?;
+
+    Ok(())
+}
+
+/// Partition (or use the partition chosen), encrypt and format the target as planned, and
+/// mount it at `root`
+fn prepare_target(config: &InstallConfig, root: &Path) -> Result<resume::Target> {
+    // the bootloader of the board is written before the first partition, checked before
+    // anything is written
+    let board = config
+        .board
+        .as_ref()
+        .map(|x| boards::find_board(x))
+        .transpose()?;
+    let first_start = board
+        .as_ref()
+        .map(boards::partition_start)
+        .unwrap_or(disks::DEFAULT_PARTITION_START);
+    // no partition when installing into an existing directory
+    let partition = match config.target_dir.as_ref() {
+        Some(dir) => {
+            info!("Installing into directory {}", dir.display());
+            None
+        }
+        None => match config.wipe_disk.as_ref() {
+            Some(device) => {
+                if let Some(board) = board.as_ref() {
+                    boards::check_layout(board, disks::planned_raw_area(first_start))?;
+                }
+                info!("Partitioning {} ...", device.path.display());
+                // the filesystem of the system partition planned, ext4 unless chosen otherwise
+                let fs_type = config
+                    .partition
+                    .as_ref()
+                    .and_then(|x| x.fs_type.as_deref())
+                    .unwrap_or("ext4");
+                Some(disks::auto_create_partitions(
+                    &device.path,
+                    config.keep_free_space.unwrap_or(0),
+                    fs_type,
+                    first_start,
+                )?)
+            }
+            None => {
+                let partition = config.partition.as_deref().unwrap().clone();
+                if let (Some(board), Some(disk)) = (board.as_ref(), partition.parent_path.as_ref())
+                {
+                    boards::check_layout(board, disks::raw_area_before_partitions(disk)?)?;
+                }
+                Some(partition)
+            }
+        },
+    };
+    let (partition, luks_uuid) = match (partition, config.encrypt.unwrap_or(false)) {
+        (Some(partition), true) => {
+            let passphrase = config.encryption_passphrase.as_ref().ok_or_else(|| {
+                anyhow!(tr!(
+                    "The passphrase of the encrypted system partition has not been entered."
+                ))
+            })?;
+            info!("Encrypting {:?} ...", partition.path);
+            let (partition, uuid) = install::encrypt_partition(&partition, passphrase)?;
+            (Some(partition), Some(uuid))
+        }
+        (partition, _) => (partition, None),
+    };
+    let mut target = match partition {
+        Some(partition) => prepare_partition(
+            partition,
+            root,
+            config.preserve_home.unwrap_or(false),
+            assigned_mounts(config),
+        )?,
+        None => resume::Target::default(),
+    };
+    target.luks_uuid = luks_uuid;
 
     let use_swap = config.use_swap.v.load(Ordering::SeqCst);
     if use_swap {
         if let Some(swap_size) = config.swap_size.as_ref() {
             info!("Creating swapfile and trying swapon swapfile ...");
-            install::create_swapfile(*swap_size, use_swap, &tempdir)?;
+            install::create_swapfile(*swap_size, use_swap, root)?;
         }
     }
 
-    let extract_done_copy = extract_done.clone();
-    let download_done_copy = download_done.clone();
-    let (sha256_work_tx, sha256_work_rx) = mpsc::channel();
-    let (get_sha256_tx, get_sha256_rx) = mpsc::channel();
-    let (error_channel_tx, error_channel_rx) = mpsc::channel();
-    let error_channel_tx_copy = error_channel_tx.clone();
+    Ok(target)
+}
 
-    let (speed_tx, speed_rx) = std::sync::mpsc::channel();
+/// The steps for the journal, writing /etc/fstab is one on its own after unpacking
+fn journal_steps(plan: &[(&'static str, f64)], fstab: bool) -> Vec<&'static str> {
+    let mut steps = plan.iter().map(|(x, _)| *x).collect::<Vec<_>>();
+    if fstab {
+        let index = steps
+            .iter()
+            .position(|x| *x == STEP5)
+            .unwrap_or(steps.len());
+        steps.insert(index, FSTAB_STEP);
+    }
 
-    let cc = counter.clone();
+    steps
+}
 
-    let worker = thread::spawn(move || {
-        let mut tarball_file = mount_path.clone();
-        tarball_file.push("tarball");
-        let mut output = match std::fs::File::create(tarball_file.clone()) {
-            Ok(file) => {
-                info!("tarball file: {:?} is created", tarball_file);
+/// Run `step` and record how it went, in the state for `install --resume` as well
+fn run_step<T>(
+    journal: &mut StepJournal,
+    step: &'static str,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let result = journal.run(step, f)?;
+    resume::record_done(&[step]);
 
-                file
-            }
-            Err(e) => {
-                send_error!(error_channel_tx_copy, e);
-            }
+    Ok(result)
+}
+
+/// Run `f` chrooted into the system at `root`, coming back out whether it succeeds or not
+fn in_guest<T>(root: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    info!("Chroot to installed system ...");
+    let escape_vector = install::get_dir_fd(Path::new("/"))?;
+    install::dive_into_guest(root)?;
+    let result = f();
+    info!("Escaping chroot ...");
+    install::escape_chroot(escape_vector)?;
+
+    result
+}
+
+/// The unpacked system being configured, the steps from here on may be run again from
+/// the finish screen
+struct InstalledSystem<'a> {
+    sender: &'a Sender<InstallProgress>,
+    config: &'a InstallConfig,
+    root: &'a Path,
+    /// `None` when installing into a directory
+    partition: Option<&'a disks::Partition>,
+    previous_install: Option<&'a install::PreviousInstall>,
+    use_swap: bool,
+    luks_uuid: Option<&'a str>,
+    resolv_conf: Option<Vec<u8>>,
+}
+
+impl InstalledSystem<'_> {
+    fn write_fstab(&self, journal: &mut StepJournal) -> Result<()> {
+        let Some(partition) = self.partition else {
+            return Ok(());
         };
+        run_step(journal, FSTAB_STEP, || {
+            write_fstab(
+                partition,
+                self.root,
+                self.previous_install,
+                self.use_swap,
+                swap_partition(self.config),
+                assigned_mounts(self.config),
+            )
+        })
+    }
 
-        let runtime = match tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .worker_threads(2)
-            .build()
-        {
-            Ok(rt) => rt,
-            Err(e) => {
-                let e = anyhow!("Failed to create tokio runtime: {e}");
-                send_error!(error_channel_tx_copy, e);
-            }
+    /// Run the steps after unpacking but the `skipped` ones, stopping at the first one failing
+    fn configure(
+        &self,
+        journal: &mut StepJournal,
+        timer: &mut StepTimer,
+        skipped: &[&str],
+    ) -> Result<BootMenu> {
+        let mut rng = thread_rng();
+        let mut next = |step: &'static str| -> Result<()> {
+            self.sender.send(InstallProgress::Pending(
+                step.to_string(),
+                rng.gen_range(0..100),
+            ))?;
+            next_step(self.sender, timer, step)
         };
 
-        let client = match reqwest::Client::builder()
-            .user_agent(DEPLOYKIT_USER_AGENT!())
-            .build()
-        {
-            Ok(c) => c,
-            Err(e) => {
-                let e = anyhow!("Failed to create reqwest client: {e}");
-                send_error!(error_channel_tx_copy, e);
+        if !skipped.contains(&STEP5) {
+            next(STEP5)?;
+            run_step(journal, STEP5, || {
+                in_guest(self.root, || self.generate_initramfs())
+            })?;
+        }
+
+        let boot_menu = match self.partition.filter(|_| !skipped.contains(&STEP6)) {
+            Some(partition) => {
+                next(STEP6)?;
+                run_step(journal, STEP6, || {
+                    in_guest(self.root, || {
+                        install_bootloader(self.config, partition, self.luks_uuid)
+                    })
+                })?
             }
+            None => BootMenu::default(),
         };
 
-        let urlc = url.clone();
+        if !skipped.contains(&STEP7) {
+            next(STEP7)?;
+            run_step(journal, STEP7, || {
+                in_guest(self.root, || {
+                    info!("Generating SSH key ...");
+                    install::gen_ssh_key()
+                })
+            })?;
+        }
 
-        let tbl_file_c = tarball_file.clone();
+        if !skipped.contains(&STEP8) {
+            next(STEP8)?;
+            run_step(journal, STEP8, || self.finalise())?;
+        }
 
-        let ccc = cc.clone();
+        Ok(boot_menu)
+    }
 
-        let error_channel_tx_copy_copy = error_channel_tx_copy.clone();
+    /// Must be used in a chroot context
+    fn generate_initramfs(&self) -> Result<()> {
+        if let Some(keymap) = self.config.keymap.as_ref() {
+            // before generating the initramfs, so that the keymap gets included
+            info!("Setting keymap as {}", keymap);
+            let layouts = install::get_keyboard_layouts(Path::new("/"))?;
+            install::set_keymap(&install::check_keymap(keymap, &layouts)?)?;
+        }
 
-        runtime.block_on(async move {
-            let mut resp = match client.get(urlc).send().await.and_then(|x| x.error_for_status()) {
-                Ok(resp) => resp,
-                Err(e) => {
-                    send_error!(error_channel_tx_copy, e);
-                }
-            };
+        if install::plymouth_exists(Path::new("/")) {
+            let boot_splash = boot_splash(self.config);
+            info!("Setting up Plymouth boot splash: {}", boot_splash);
+            install::set_plymouth_dracut_module(boot_splash)?;
+        }
 
-            info!("Allocating tarball file: {:?}", &tbl_file_c);
-            if let Err(e) = rustix::fs::fallocate(
-                output.as_fd(),
-                FallocateFlags::empty(),
-                0,
-                file_size.try_into().unwrap(),
-            ) {
-                let e = anyhow!(
-                    "Installer failed to create temporary file for the download process:\n\n{}",
-                    e
-                );
+        if let Some(uuid) = self.luks_uuid {
+            info!(
+                "Setting up unlocking the encrypted system partition {}",
+                uuid
+            );
+            let data = assigned_mounts(self.config)
+                .iter()
+                .filter(|x| x.encrypt)
+                .map(|x| install::KeyfileVolume {
+                    name: install::data_mapper_name(&x.mount_point),
+                    device: x.partition.path.clone().unwrap_or_default(),
+                })
+                .collect::<Vec<_>>();
+            install::set_up_encryption(uuid, &data, self.config.encrypt_discard.unwrap_or(false))?;
+        }
+
+        info!("Running dracut ...");
+        install::execute_dracut()?;
+        if self.luks_uuid.is_some() {
+            // without it, the system partition could not be unlocked on boot
+            info!("Checking the initramfs for the crypt dracut module ...");
+            install::check_initramfs_module("crypt")?;
+        }
+
+        Ok(())
+    }
+
+    /// Configure the installed system as chosen, and flash the bootloader of the board
+    fn finalise(&self) -> Result<()> {
+        in_guest(self.root, || {
+            configure_guest(
+                self.sender,
+                self.config,
+                self.previous_install,
+                self.resolv_conf.as_deref(),
+            )
+        })?;
+
+        configure_services(self.config, self.root)?;
+        self.flash_board()?;
+
+        match self.config.hooks.as_ref() {
+            Some(hooks) if !hooks.post_install.is_empty() => {
+                in_guest(self.root, || run_hooks("post-install", &hooks.post_install))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn flash_board(&self) -> Result<()> {
+        let (Some(partition), Some(board)) = (self.partition, self.config.board.as_ref()) else {
+            return Ok(());
+        };
+        let board = boards::find_board(board)?;
+        let device = partition.parent_path.as_ref().unwrap();
+        let efi_path = self.root.join("efi");
+        info!("Installing bootloader for {} ...", board.description);
+        for action in boards::describe_actions(&board, device) {
+            info!("{}", action);
+        }
+
+        boards::flash_board(
+            &board,
+            self.root,
+            device,
+            disks::is_efi_booted().then_some(efi_path.as_path()),
+        )
+    }
+
+    /// Install the bootloader again, e.g., after fixing the EFI system partition in a shell
+    fn retry_bootloader(&self, journal: &mut StepJournal) -> Result<BootMenu> {
+        let Some(partition) = self.partition else {
+            return Ok(BootMenu::default());
+        };
+        info!("Installing the bootloader again ...");
+        run_step(journal, STEP6, || {
+            let boot_menu = in_guest(self.root, || {
+                install_bootloader(self.config, partition, self.luks_uuid)
+            })?;
+            self.flash_board()?;
+
+            Ok(boot_menu)
+        })
+    }
+
+    /// Write /etc/fstab and configure the installed system again
+    fn reconfigure(&self, journal: &mut StepJournal) -> Result<()> {
+        info!("Configuring the installed system again ...");
+        self.write_fstab(journal)?;
+        run_step(journal, STEP8, || self.finalise())
+    }
+}
+
+/// Run the hook commands of the configuration file one by one, stopping at the first one
+/// failing
+fn run_hooks(kind: &str, commands: &[String]) -> Result<()> {
+    for command in commands {
+        info!("Running {} hook: {}", kind, command);
+        install::run_command_logged("sh", ["-c", command.as_str()])
+            .map_err(|e| anyhow!("The {} hook `{}` has failed: {}", kind, command, e))?;
+    }
+
+    Ok(())
+}
+
+/// Wipe the incomplete system left by the cancelled installation from the target, if the
+/// user has asked to
+fn wipe_cancelled(config: &InstallConfig, root: &Path, cancel: &CancelToken) -> Result<()> {
+    if !cancel.wants_wipe() || !cancel.is_target_modified() {
+        return Ok(());
+    }
+
+    info!("Wiping the incomplete system from {} ...", root.display());
+    install::swapoff(root);
+    install::wipe_incomplete_system(root, config.preserve_home.unwrap_or(false)).map_err(|e| {
+        anyhow!(
+            "Installer failed to wipe the incomplete system from the target after cancelling:\n\n{}",
+            e
+        )
+    })?;
+    // there is nothing left to resume
+    resume::discard();
+
+    Ok(())
+}
+
+/// Format (unless reinstalling) and mount `partition`, the ESP and the other `assigned`
+/// partitions at `root`, with the previous installation when reinstalling over it
+fn prepare_partition(
+    partition: disks::Partition,
+    root: &Path,
+    preserve_home: bool,
+    assigned: &[mounts::MountAssignment],
+) -> Result<resume::Target> {
+    if preserve_home {
+        info!("Keeping the existing filesystem: {:?}", partition);
+    } else {
+        info!("Formatting partitions: {:?}", partition);
+        disks::format_partition(&partition)?;
+    }
+
+    info!("Mounting partitions: {:?}", partition);
+    install::auto_mount_root_path(root, &partition)?;
+    let previous_install = if preserve_home {
+        let previous = install::find_previous_install(root)?.ok_or_else(|| {
+            anyhow!("Installer could not find an existing AOSC OS installation on the specified partition to preserve /home from.")
+        })?;
+        info!("Removing the previous installation, preserving /home ...");
+        install::wipe_root_except_home(root)?;
+        Some(previous)
+    } else {
+        None
+    };
+    let mut efi_path = root.to_path_buf();
+    let mut esp = None;
+    if disks::is_efi_booted() && !mounts::has_esp(assigned) {
+        efi_path.push("efi");
+
+        info!("Finding ESP partition from: {:?}", partition.parent_path);
+        let mut esp_part = disks::find_esp_partition(partition.parent_path.as_ref().unwrap())?;
+        info!("ESP is: {:?}", esp_part);
+
+        std::fs::create_dir_all(&efi_path).unwrap();
+        if esp_part.fs_type.is_none() {
+            // format the un-formatted ESP partition
+            esp_part.fs_type = Some("vfat".to_string());
+
+            info!("Formatting ESP partition: {:?}", esp_part);
+            disks::format_partition(&esp_part)?;
+        }
+        install::mount_root_path(&esp_part, &efi_path)?;
+        esp = Some(esp_part);
+    }
+    mount_assigned(root, assigned, true)?;
+
+    Ok(resume::Target {
+        partition: Some(partition),
+        luks_uuid: None,
+        previous_install,
+        esp,
+    })
+}
+
+/// Mount the target prepared by an interrupted installation at `root` again, without
+/// formatting anything
+fn mount_target(target: &resume::Target, config: &InstallConfig, root: &Path) -> Result<()> {
+    let Some(partition) = target.partition.as_ref() else {
+        return Ok(());
+    };
+    info!("Mounting the prepared partitions again: {:?}", partition);
+    install::auto_mount_root_path(root, partition)?;
+    if let Some(esp) = target.esp.as_ref() {
+        let efi_path = root.join("efi");
+        std::fs::create_dir_all(&efi_path)?;
+        install::mount_root_path(esp, &efi_path)?;
+    }
+
+    mount_assigned(root, assigned_mounts(config), false)
+}
+
+/// Format the `assigned` partitions as chosen (if `format` is set), and mount them beneath
+/// `root`
+fn mount_assigned(root: &Path, assigned: &[mounts::MountAssignment], format: bool) -> Result<()> {
+    let mut assigned = assigned.iter().collect::<Vec<_>>();
+    // e.g. /var before /var/log
+    assigned.sort_by_key(|x| Path::new(&x.mount_point).components().count());
+    for mount in assigned {
+        let partition = if mount.encrypt {
+            info!("Opening the encrypted partition {:?}", mount.partition.path);
+            install::encrypt_data_partition(
+                &mount.target_partition(),
+                root,
+                &install::data_mapper_name(&mount.mount_point),
+                format && mount.format,
+            )?
+        } else {
+            mount.target_partition()
+        };
+        if format && mount.format {
+            info!("Formatting {:?} for {}", partition, mount.mount_point);
+            disks::format_partition_with_label(&partition, mount.label.as_deref())?;
+        }
+        let target = root.join(mount.mount_point.trim_start_matches('/'));
+        info!("Mounting {:?} at {}", partition.path, target.display());
+        std::fs::create_dir_all(&target)?;
+        install::mount_root_path(&partition, &target)?;
+    }
+
+    Ok(())
+}
+
+/// Download the system release, verify and extract it into `root`
+fn download_and_extract(
+    sender: &Sender<InstallProgress>,
+    config: &InstallConfig,
+    timer: &mut StepTimer,
+    root: &Path,
+    cancel: &CancelToken,
+) -> Result<Option<Manifest>> {
+    let refresh_interval = std::time::Duration::from_millis(30);
+    let counter = Counter::new(0);
+    let url;
+    let file_size: usize;
+    let right_sha256;
+    let extract_done: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let download_done: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+
+    if let Some(variant) = config.variant.as_ref() {
+        let mirror_url = &config.mirror.as_ref().unwrap().url;
+        file_size = variant.size.try_into().unwrap();
+        url = format!("{}{}", mirror_url, variant.url);
+        right_sha256 = variant.sha256sum.clone();
+
+        info!(
+            "Mirror URL is: {}, file_size: {}, url: {}, right_sha256: {}",
+            mirror_url, file_size, url, right_sha256
+        );
+    } else {
+        return Err(anyhow!(
+            "Installer could not parse release metadata: `variant` field not found."
+        ));
+    }
+
+    let mount_path = root.to_path_buf();
+    let extract_done_copy = extract_done.clone();
+    let download_done_copy = download_done.clone();
+    let (sha256_work_tx, sha256_work_rx) = mpsc::channel();
+    let (get_sha256_tx, get_sha256_rx) = mpsc::channel();
+    let (error_channel_tx, error_channel_rx) = mpsc::channel();
+    let (manifest_tx, manifest_rx) = mpsc::channel();
+    let error_channel_tx_copy = error_channel_tx.clone();
+
+    // 0 if unknown until the download is done, e.g., for a chunked response without metadata
+    let total_size = Arc::new(AtomicUsize::new(file_size));
+    let total_size_copy = total_size.clone();
+
+    let cc = counter.clone();
+    let cancel_copy = cancel.clone();
+    let right_sha256_copy = right_sha256.clone();
+    let local_release = config.local_release.clone();
+
+    let worker = thread::spawn(move || {
+        let mut tarball_file = mount_path.clone();
+        tarball_file.push("tarball");
+        let mut output = match std::fs::File::create(tarball_file.clone()) {
+            Ok(file) => {
+                info!("tarball file: {:?} is created", tarball_file);
+
+                file
+            }
+            Err(e) => {
+                send_error!(error_channel_tx_copy, e);
+            }
+        };
+
+        let runtime = match tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .worker_threads(2)
+            .build()
+        {
+            Ok(rt) => rt,
+            Err(e) => {
+                let e = anyhow!("Failed to create tokio runtime: {e}");
+                send_error!(error_channel_tx_copy, e);
+            }
+        };
+
+        let client = match network::client_builder().build() {
+            Ok(c) => c,
+            Err(e) => {
+                let e = anyhow!("Failed to create reqwest client: {e}");
                 send_error!(error_channel_tx_copy, e);
             }
+        };
+
+        let urlc = url.clone();
+
+        let tbl_file_c = tarball_file.clone();
+
+        let ccc = cc.clone();
+
+        let error_channel_tx_copy_copy = error_channel_tx_copy.clone();
+        let total_size_copy_copy = total_size_copy.clone();
+        let cancel_copy_copy = cancel_copy.clone();
+
+        runtime.block_on(async move {
+            // a local copy of the release is taken as if it had been downloaded
+            let local = match local_release.as_deref() {
+                Some(path) => match std::fs::File::open(path).and_then(|x| {
+                    let len = x.metadata()?.len();
+                    Ok((x, len))
+                }) {
+                    Ok((_, len)) if len as usize != file_size => {
+                        let e = anyhow!(
+                            "{} is not the system release selected, which has {} bytes instead of {}.",
+                            path.display(),
+                            len,
+                            file_size
+                        );
+                        send_error!(error_channel_tx_copy, e);
+                    }
+                    Ok(local) => Some(local),
+                    Err(e) => {
+                        send_error!(error_channel_tx_copy, e);
+                    }
+                },
+                None => None,
+            };
+            let mut resp = None;
+            let partial = match local {
+                Some(local) => {
+                    info!("Installing from the local copy of the release");
+                    Some(local)
+                }
+                None => {
+                    let partial = network::take_partial_download(&right_sha256_copy);
+                    let mut request = client.get(urlc);
+                    if let Some((_, len)) = partial.as_ref() {
+                        info!("Found a partial download of {} bytes, trying to resume", len);
+                        request = request.header(reqwest::header::RANGE, format!("bytes={len}-"));
+                    }
+                    let response = match request.send().await.and_then(|x| x.error_for_status()) {
+                        Ok(response) => response,
+                        Err(e) => {
+                            send_error!(error_channel_tx_copy, e);
+                        }
+                    };
+                    // the server may ignore the range and send the whole file
+                    let partial = partial
+                        .filter(|_| response.status() == reqwest::StatusCode::PARTIAL_CONTENT);
+                    resp = Some(response);
+                    partial
+                }
+            };
+            let resumed = partial.as_ref().map(|x| x.1 as usize).unwrap_or_default();
+
+            let file_size = if file_size > 0 {
+                file_size
+            } else {
+                resp.as_ref()
+                    .and_then(|x| x.content_length())
+                    .map(|x| x as usize + resumed)
+                    .unwrap_or_default()
+            };
+            total_size_copy.store(file_size, Ordering::SeqCst);
+
+            if file_size > 0 {
+                info!("Allocating tarball file: {:?}", &tbl_file_c);
+                if let Err(e) = rustix::fs::fallocate(
+                    output.as_fd(),
+                    FallocateFlags::empty(),
+                    0,
+                    file_size.try_into().unwrap(),
+                ) {
+                    let e = anyhow!(
+                        "Installer failed to create temporary file for the download process:\n\n{}",
+                        e
+                    );
+                    send_error!(error_channel_tx_copy, e);
+                }
+            }
 
             info!("Flushing tarball_file: {:?}", &tbl_file_c);
             if let Err(e) = output.flush() {
@@ -302,37 +1573,68 @@ fn begin_install(
             }
 
             let mut tarball_size = 0;
-
-            let mut timer = tokio::time::Instant::now();
-            let mut tarball_size_1s = 0;
+            if let Some((mut file, _)) = partial {
+                info!("Resuming the download from byte {}", resumed);
+                let mut buf = vec![0; 1024 * 1024];
+                loop {
+                    let len = match file.read(&mut buf) {
+                        Ok(0) => break,
+                        Ok(len) => len,
+                        Err(e) => {
+                            send_error!(error_channel_tx_copy, e);
+                        }
+                    };
+                    if let Err(e) = output.write_all(&buf[..len]) {
+                        send_error!(error_channel_tx_copy, e);
+                    }
+                    tarball_size += len;
+                    sha256_work_tx.send((buf[..len].to_vec(), len)).unwrap();
+                }
+                cc.set(tarball_size);
+            }
 
             loop {
-                if tarball_size == file_size {
+                if file_size > 0 && tarball_size == file_size {
                     info!("Download complete");
                     download_done_copy.fetch_or(true, Ordering::SeqCst);
                     break;
                 }
-                match resp.chunk().await {
+                if cancel_copy_copy.is_cancelled() {
+                    info!("Download cancelled after {} bytes", tarball_size);
+                    if tarball_size > 0 {
+                        if let Err(e) = network::save_partial_download(
+                            &tbl_file_c,
+                            tarball_size as u64,
+                            &right_sha256_copy,
+                        ) {
+                            warn!("Failed to keep the partial download: {}", e);
+                        }
+                    }
+                    return;
+                }
+                let Some(resp) = resp.as_mut() else {
+                    let e = "The local copy of the system release is incomplete.".to_string();
+                    send_error!(error_channel_tx_copy, e);
+                };
+                // wake up now and then on a stalled connection to check for the cancellation
+                let chunk = match tokio::time::timeout(CANCEL_CHECK_INTERVAL, resp.chunk()).await {
+                    Ok(chunk) => chunk,
+                    Err(_) => continue,
+                };
+                match chunk {
                     Ok(v) => {
                         if let Some(chunk) = v {
-                            let now = timer.elapsed().as_secs_f64();
-                            if now >= 1.0 {
-                                let speed = tarball_size_1s as f64 / 1024.0 / now;
-                                let eta = (file_size - tarball_size) as f64 / 1024.0 / speed;
-                                let (s, s2) = calc_speed(speed, eta);
-                                speed_tx.send((s, s2)).unwrap();
-                                tarball_size_1s = 0;
-                                timer = tokio::time::Instant::now();
-                            } else {
-                                tarball_size_1s += chunk.len();
-                            }
-
                             if let Err(e) = output.write_all(&chunk) {
                                 send_error!(error_channel_tx_copy, e);
                             }
                             tarball_size += chunk.len();
                             cc.set(tarball_size);
                             sha256_work_tx.send((chunk.to_vec(), chunk.len())).unwrap();
+                        } else if file_size == 0 {
+                            info!("Download complete, {} bytes", tarball_size);
+                            total_size_copy.store(tarball_size, Ordering::SeqCst);
+                            download_done_copy.fetch_or(true, Ordering::SeqCst);
+                            break;
                         } else {
                             let e = "The remote closed the connection prematurely.".to_string();
                             send_error!(error_channel_tx_copy, e);
@@ -351,15 +1653,36 @@ fn begin_install(
             }
         });
 
+        if cancel_copy.is_cancelled() {
+            std::fs::remove_file(&tarball_file).ok();
+            extract_done_copy.fetch_or(true, Ordering::SeqCst);
+            return;
+        }
+
         info!("Trying extract tarball file: {:?}", &tarball_file);
 
         ccc.set(0);
-
-        if let Err(e) =
-            install::extract_file(file_size as f64, url, &tarball_file, &mount_path, ccc)
-        {
-            let e = anyhow!("Installer failed to unpack system release:\n\n{}", e);
-            send_error!(error_channel_tx_copy_copy, e);
+        cancel_copy.set_target_modified();
+
+        let file_size = total_size_copy_copy.load(Ordering::SeqCst);
+        match install::extract_file(
+            file_size as f64,
+            url,
+            &tarball_file,
+            &mount_path,
+            ccc,
+            &cancel_copy,
+        ) {
+            Ok(manifest) => {
+                manifest_tx.send(manifest).ok();
+            }
+            Err(e) if cancel::is_cancelled(&e) => {
+                info!("Unpacking cancelled");
+            }
+            Err(e) => {
+                let e = anyhow!("Installer failed to unpack system release:\n\n{}", e);
+                send_error!(error_channel_tx_copy_copy, e);
+            }
         }
 
         extract_done_copy.fetch_or(true, Ordering::SeqCst);
@@ -389,33 +1712,42 @@ fn begin_install(
         }
     });
 
-    let file_size = file_size as f64;
     // Progress update
-    info!("{}", STEP2);
+    next_step(sender, timer, STEP2)?;
+    let mut stats = TransferStats::new(file_size);
+    let mut fake_counter = 0;
     loop {
-        // let counter_clone = counter.clone();
-        let tarball_downloaded_size = counter.get() as f64;
-        let count = (tarball_downloaded_size / file_size * 100.0) as usize;
         if let Ok(err) = error_channel_rx.try_recv() {
             return Err(anyhow!(err));
         }
-        let v = speed_rx.recv().ok();
-
-        let msg = if let Some((speed, eta)) = v {
-            format!("{STEP2} ({speed}, {eta})")
-        } else {
-            STEP2.to_string()
-        };
-
-        sender.send(InstallProgress::Pending(msg, count))?;
+        if cancel.is_cancelled() {
+            // the worker keeps the partial download before stopping
+            worker.join().unwrap();
+            sha256sum_work.join().unwrap();
+            return Err(Cancelled.into());
+        }
+        stats.total = total_size.load(Ordering::SeqCst);
+        let downloaded = counter.get();
+        if stats.update(downloaded) {
+            sender.send(InstallProgress::Transfer(Some(stats.status(downloaded))))?;
+            if let Some(eta) = stats.eta(downloaded) {
+                timer.set_remaining(eta);
+                send_remaining(sender, timer)?;
+            }
+        }
+        fake_counter = (fake_counter + 1) % 100;
+        sender.send(InstallProgress::Pending(
+            STEP2.to_string(),
+            stats.percent(downloaded).unwrap_or(fake_counter),
+        ))?;
         std::thread::sleep(refresh_interval);
         if download_done.load(Ordering::SeqCst) {
             break;
         }
     }
-    let mut fake_counter = 0;
+    sender.send(InstallProgress::Transfer(None))?;
 
-    info!("{}", STEP3);
+    next_step(sender, timer, STEP3)?;
     loop {
         sender.send(InstallProgress::Pending(STEP3.to_string(), fake_counter))?;
         std::thread::sleep(refresh_interval);
@@ -436,149 +1768,1661 @@ fn begin_install(
         }
     }
 
-    info!("{}", STEP4);
+    // the unpack progress is counted in compressed bytes read from the tarball
+    next_step(sender, timer, STEP4)?;
+    let mut stats = TransferStats::new(total_size.load(Ordering::SeqCst));
     loop {
-        let tarball_unpack_size = counter.get() as f64;
-        let count = (tarball_unpack_size / file_size * 100.0) as usize;
-        sender.send(InstallProgress::Pending(STEP4.to_string(), count))?;
+        let unpacked = counter.get();
+        if stats.update(unpacked) {
+            sender.send(InstallProgress::Transfer(Some(stats.status(unpacked))))?;
+            if let Some(eta) = stats.eta(unpacked) {
+                timer.set_remaining(eta);
+                send_remaining(sender, timer)?;
+            }
+        }
+        sender.send(InstallProgress::Pending(
+            STEP4.to_string(),
+            stats.percent(unpacked).unwrap_or_default(),
+        ))?;
         std::thread::sleep(refresh_interval);
         if extract_done.load(Ordering::SeqCst) {
             break;
         }
     }
+    sender.send(InstallProgress::Transfer(None))?;
 
     // GC the worker thread
     worker.join().unwrap();
     sha256sum_work.join().unwrap();
-    // genfstab to file
-    info!("Generating fstab ...");
-    install::genfstab_to_file(partition, &tempdir, Path::new("/"))?;
 
-    if disks::is_efi_booted() {
-        info!("Generating fstab efi entry...");
-        let esp_part = disks::find_esp_partition(partition.parent_path.as_ref().unwrap())?;
-        install::genfstab_to_file(&esp_part, &tempdir, Path::new("/efi"))?;
+    cancel.check()?;
+    if let Ok(err) = error_channel_rx.try_recv() {
+        return Err(anyhow!(err));
     }
-    let mut rng = thread_rng();
-    let fake_counter: usize = rng.gen_range(0..100);
 
-    sender.send(InstallProgress::Pending(STEP5.to_string(), fake_counter))?;
-    info!("{}", STEP5);
+    Ok(manifest_rx.try_recv().ok().flatten())
+}
 
-    info!("Chroot to installed system ...");
-    let escape_vector = install::get_dir_fd(Path::new("/"))?;
-    install::dive_into_guest(&mount_path_copy)?;
+/// The steps this installation will go through, with their default durations
+fn planned_steps(config: &InstallConfig) -> Vec<(&'static str, f64)> {
+    STEP_ESTIMATES
+        .iter()
+        .filter(|(step, _)| match *step {
+            STEP6 => config.target_dir.is_none(),
+            VERIFY_FILES => config.verify_files.unwrap_or(true),
+            _ => true,
+        })
+        .copied()
+        .collect()
+}
+
+/// Start the next step, and tell the frontend how long the installation will take now
+fn next_step(
+    sender: &Sender<InstallProgress>,
+    timer: &mut StepTimer,
+    step: &'static str,
+) -> Result<()> {
+    timer.next(step);
 
-    info!("Running dracut ...");
-    install::execute_dracut()?;
+    send_remaining(sender, timer)
+}
 
-    let fake_counter: usize = rng.gen_range(0..100);
-    sender.send(InstallProgress::Pending(STEP6.to_string(), fake_counter))?;
-    info!("{}", STEP6);
+fn send_remaining(sender: &Sender<InstallProgress>, timer: &mut StepTimer) -> Result<()> {
+    sender.send(InstallProgress::Remaining(timer.estimate()))?;
 
-    if disks::is_efi_booted() {
-        info!("Installing grub to UEFI partition ...");
-        install::execute_grub_install(None)?;
+    Ok(())
+}
+
+/// Steps reporting how far they actually are, the others report made-up numbers to show
+/// that they are making progress
+const MEASURED_STEPS: &[&str] = &[STEP2, STEP4, VERIFY_FILES];
+
+/// How far the whole installation is in percent, weighting the steps by how long they are
+/// expected to take, `None` if `status` is not from a planned step
+fn overall_percent(plan: &[(&str, f64)], status: &str, step_percent: usize) -> Option<usize> {
+    let step = step_of(status)?;
+    let index = plan.iter().position(|(x, _)| *x == step)?;
+    let total = plan.iter().map(|(_, x)| x).sum::<f64>();
+    if total <= 0.0 {
+        return None;
+    }
+    let current = if MEASURED_STEPS.contains(&step) {
+        plan[index].1 * step_percent.min(100) as f64 / 100.0
     } else {
-        info!("Installing grub to MBR partition ...");
-        install::execute_grub_install(Some(partition.parent_path.as_ref().unwrap()))?;
+        0.0
     };
+    let done = plan[..index].iter().map(|(_, x)| x).sum::<f64>() + current;
 
-    let fake_counter: usize = rng.gen_range(0..100);
-    sender.send(InstallProgress::Pending(STEP7.to_string(), fake_counter))?;
-    info!("{}", STEP7);
+    Some((done / total * 100.0) as usize)
+}
 
-    info!("Generating SSH key ...");
-    install::gen_ssh_key()?;
+/// The time since the installation has started, and the estimate of the time left, which
+/// counts down between the updates from the installation
+struct InstallClock {
+    started: Instant,
+    remaining: Option<(Instant, Duration)>,
+}
 
-    info!("{}", STEP8);
-    let fake_counter: usize = rng.gen_range(0..100);
-    sender.send(InstallProgress::Pending(STEP8.to_string(), fake_counter))?;
+impl InstallClock {
+    fn new() -> Self {
+        InstallClock {
+            started: Instant::now(),
+            remaining: None,
+        }
+    }
 
-    if use_swap {
-        info!("Generating swapfile entry to fstab");
-        install::write_swap_entry_to_fstab()?;
+    fn set_remaining(&mut self, remaining: Option<Duration>) {
+        self.remaining = remaining.map(|x| (Instant::now(), x));
     }
 
-    let tz = config.timezone.unwrap();
-    info!("Setting timezone as {}", &tz);
-    install::set_zoneinfo(&tz)?;
+    /// e.g., "3m 10s elapsed · About 12 minutes remaining", the elapsed time only if
+    /// there is no estimate
+    fn summary(&self) -> String {
+        let elapsed = short_duration(self.started.elapsed());
+        match self.remaining {
+            Some((at, remaining)) => tr!(
+                "{} elapsed · {}",
+                elapsed,
+                remaining_summary(remaining.saturating_sub(at.elapsed()))
+            ),
+            None => tr!("{} elapsed", elapsed),
+        }
+    }
+}
 
-    let tc = config.tc.unwrap();
-    info!("Setting hwclock (hardware clock) as {}", &tc);
-    install::set_hwclock_tc(match tc.as_str() {
-        "UTC" => true,
-        "RTC" => false,
-        _ => true,
-    })?;
+/// The last lines printed by the running command, shown under the progress bar so that a
+/// long command can be seen moving
+struct CommandTail {
+    lines: VecDeque<String>,
+    shown: Option<Instant>,
+}
 
-    let hostname = config.hostname.unwrap();
-    info!("Setting hostname as {}", &hostname);
-    install::set_hostname(&hostname)?;
+impl CommandTail {
+    fn new() -> Self {
+        CommandTail {
+            lines: VecDeque::new(),
+            shown: None,
+        }
+    }
 
-    info!("Setting username and password ...");
+    /// Add the line, returns the text to show if it is time to update it, the lines in
+    /// between are only in the log
+    fn push(&mut self, line: &str) -> Option<String> {
+        let line = tail_line(line);
+        if line.is_empty() {
+            return None;
+        }
+        self.lines.push_back(line);
+        while self.lines.len() > COMMAND_TAIL_LINES {
+            self.lines.pop_front();
+        }
+        if self
+            .shown
+            .is_some_and(|x| x.elapsed() < COMMAND_TAIL_INTERVAL)
+        {
+            return None;
+        }
+        self.shown = Some(Instant::now());
 
-    install::add_new_user(&config.user.clone().unwrap(), &config.password.unwrap())?;
+        Some(self.lines.iter().cloned().collect::<Vec<_>>().join("\n"))
+    }
 
-    if cfg!(feature = "is_retro") {
-        install::chpasswd("root", &config.root_password.unwrap())?;
+    fn clear(&mut self) {
+        self.lines.clear();
+        self.shown = None;
     }
+}
 
-    info!("Setting fullname ...");
-    if config.full_name.is_some() && config.full_name != Some("".to_string().into()) {
-        install::passwd_set_fullname(&config.full_name.unwrap(), &config.user.unwrap())?;
+/// The line as shown in the tail: without colors, only what is left after the last carriage
+/// return (progress counters redraw themselves with it), and cut to a sane length
+fn tail_line(line: &str) -> String {
+    let line = wifi::strip_ansi(line);
+    let line = line
+        .rsplit('\r')
+        .find(|x| !x.trim().is_empty())
+        .unwrap_or("");
+    let line = line.chars().filter(|x| !x.is_control()).collect::<String>();
+    let line = line.trim_end();
+    match line.char_indices().nth(COMMAND_TAIL_WIDTH) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
     }
+}
 
-    let locale = config.locale.as_ref().unwrap();
-    info!("Setting locale as {}", locale);
-    install::set_locale(locale)?;
+/// Describe the estimated time left, e.g., "About 12 minutes remaining"
+fn remaining_summary(remaining: Duration) -> String {
+    match remaining.as_secs() {
+        x if x < 60 => tr!("Less than a minute remaining").to_string(),
+        x if x < 90 => tr!("About a minute remaining").to_string(),
+        x if x < 60 * 60 => tr!("About {} minutes remaining", (x + 30) / 60),
+        x => tr!("About {}h {}m remaining", x / 60 / 60, (x / 60) % 60),
+    }
+}
 
-    info!("Escaping chroot ...");
-    install::escape_chroot(escape_vector)?;
+/// How often the transfer speed is sampled
+const TRANSFER_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+/// Weight of the latest sample in the smoothed transfer speed
+const TRANSFER_SPEED_WEIGHT: f64 = 0.3;
+
+/// Speed and ETA of a download or unpack, from the number of bytes done so far
+struct TransferStats {
+    /// Total bytes, 0 if unknown (e.g., the server sent no `Content-Length`)
+    total: usize,
+    last: (Instant, usize),
+    /// Smoothed speed in bytes per second
+    speed: Option<f64>,
+}
 
-    if disks::is_efi_booted() {
-        info!("Unmounting EFI partition ...");
-        install::umount_root_path(&efi_path)?;
-    }
-
-    info!("Copy log file to main partition");
-    let logfile = LOG_FILE.get().unwrap();
-    std::fs::copy(
-        logfile,
-        tempdir.join("var").join("log").join(
-            logfile
-                .file_name()
-                .ok_or_else(|| anyhow!("Can not get filename"))?,
-        ),
+impl TransferStats {
+    fn new(total: usize) -> Self {
+        TransferStats {
+            total,
+            last: (Instant::now(), 0),
+            speed: None,
+        }
+    }
+
+    /// Sample the speed if it is time to, returns whether it has been updated
+    fn update(&mut self, done: usize) -> bool {
+        let elapsed = self.last.0.elapsed();
+        if elapsed < TRANSFER_SAMPLE_INTERVAL {
+            return false;
+        }
+        let sample = done.saturating_sub(self.last.1) as f64 / elapsed.as_secs_f64();
+        self.speed = Some(match self.speed {
+            Some(speed) => speed + (sample - speed) * TRANSFER_SPEED_WEIGHT,
+            None => sample,
+        });
+        self.last = (Instant::now(), done);
+
+        true
+    }
+
+    fn percent(&self, done: usize) -> Option<usize> {
+        (self.total > 0).then(|| (done as f64 / self.total as f64 * 100.0).min(100.0) as usize)
+    }
+
+    fn eta(&self, done: usize) -> Option<Duration> {
+        let speed = self.speed.filter(|x| *x > 0.0)?;
+        if self.total == 0 {
+            return None;
+        }
+
+        Duration::try_from_secs_f64(self.total.saturating_sub(done) as f64 / speed).ok()
+    }
+
+    /// e.g., "1.2 GiB / 2.0 GiB, 5.3 MiB/s, 2m 10s left", or "1.2 GiB, 5.3 MiB/s"
+    /// if the total is unknown
+    fn describe(&self, done: usize) -> String {
+        let mut summary = human_size(done as u64);
+        if self.total > 0 {
+            summary += &format!(" / {}", human_size(self.total as u64));
+        }
+        if let Some(speed) = self.speed {
+            summary += &format!(", {}/s", human_size(speed as u64));
+        }
+        if let Some(eta) = self.eta(done) {
+            summary += &format!(", {} left", short_duration(eta));
+        }
+
+        summary
+    }
+
+    fn status(&self, done: usize) -> TransferStatus {
+        TransferStatus {
+            done: done as u64,
+            total: (self.total > 0).then_some(self.total as u64),
+            rate: self.speed,
+            eta: self.eta(done),
+            summary: self.describe(done),
+        }
+    }
+}
+
+/// How far a download or unpack has got, as sent to the frontends
+#[derive(Debug, Clone)]
+pub(crate) struct TransferStatus {
+    pub done: u64,
+    /// `None` if unknown
+    pub total: Option<u64>,
+    /// Bytes per second
+    pub rate: Option<f64>,
+    pub eta: Option<Duration>,
+    /// For people, e.g., "1.2 GiB / 2.0 GiB, 5.3 MiB/s, 2m 10s left"
+    pub summary: String,
+}
+
+/// e.g., "45s", "2m 10s" or "1h 5m"
+fn short_duration(duration: Duration) -> String {
+    match duration.as_secs() {
+        x if x < 60 => format!("{x}s"),
+        x if x < 60 * 60 => format!("{}m {}s", x / 60, x % 60),
+        x => format!("{}h {}m", x / 60 / 60, (x / 60) % 60),
+    }
+}
+
+#[inline]
+fn human_size(size: u64) -> String {
+    match NumberPrefix::binary(size as f64) {
+        NumberPrefix::Standalone(bytes) => format!("{bytes} B"),
+        NumberPrefix::Prefixed(prefix, n) => format!("{n:.1} {prefix}B"),
+    }
+}
+
+/// Render the Markdown-ish description of a variant as plain text: the lines of a paragraph
+/// are joined (to be wrapped by the text view), headings and list items get lines of
+/// their own, and the emphasis and code marks are dropped
+fn plain_description(text: &str) -> String {
+    let mut lines: Vec<String> = vec![];
+    let mut current = String::new();
+    let flush = |lines: &mut Vec<String>, current: &mut String| {
+        if !current.is_empty() {
+            lines.push(std::mem::take(current));
+        }
+    };
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            flush(&mut lines, &mut current);
+            if lines.last().map(|x| !x.is_empty()).unwrap_or(false) {
+                lines.push(String::new());
+            }
+        } else if trimmed.starts_with('#') {
+            flush(&mut lines, &mut current);
+            lines.push(plain_inline(trimmed.trim_start_matches('#').trim()));
+        } else if let Some(item) = list_item(trimmed) {
+            flush(&mut lines, &mut current);
+            current = format!("  {}", plain_inline(&item));
+        } else {
+            // continues the paragraph, or the list item above
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(&plain_inline(trimmed));
+        }
+    }
+    flush(&mut lines, &mut current);
+    while lines.last().map(|x| x.is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+
+    lines.join("\n")
+}
+
+/// "- item", "* item" and "+ item" as "- item", numbered items as they are
+fn list_item(line: &str) -> Option<String> {
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return Some(format!("- {}", item.trim()));
+        }
+    }
+    let digits = line.chars().take_while(|x| x.is_ascii_digit()).count();
+    let rest = &line[digits..];
+    if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        return Some(line.to_string());
+    }
+
+    None
+}
+
+/// Drop the emphasis and code marks, and show links as "text (URL)"
+fn plain_inline(text: &str) -> String {
+    let text = text.replace("**", "").replace("__", "").replace('`', "");
+    let mut result = String::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find('[') {
+        let link = rest[start..].find("](").and_then(|middle| {
+            let end = rest[start + middle..].find(')')? + start + middle;
+            Some((middle + start, end))
+        });
+        let Some((middle, end)) = link else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        result.push_str(&format!(
+            "{} ({})",
+            &rest[start + 1..middle],
+            &rest[middle + 2..end]
+        ));
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Re-hash the unpacked files against the file manifest of the release, or the one
+/// recorded while unpacking, and fail on any missing or corrupted file
+fn verify_files(
+    sender: &Sender<InstallProgress>,
+    config: &InstallConfig,
+    timer: &mut StepTimer,
+    root: &Path,
+    unpacked: Option<Manifest>,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let variant = config.variant.as_ref().unwrap();
+    let released = variant.manifest.as_ref().and_then(|path| {
+        let url = format!("{}{}", config.mirror.as_ref().unwrap().url, path);
+        info!("Fetching file manifest from {}", url);
+        match network::fetch_file_manifest(&url).and_then(|x| manifest::parse_manifest(&x)) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                warn!("Failed to fetch the file manifest of the release: {}", e);
+                None
+            }
+        }
+    });
+    let manifest = match released.or(unpacked) {
+        Some(manifest) => Arc::new(manifest),
+        None => {
+            warn!("No file manifest available, skipping verification of the unpacked files");
+            return Ok(());
+        }
+    };
+
+    next_step(sender, timer, VERIFY_FILES)?;
+    if let Err(e) = install::drop_page_cache() {
+        warn!(
+            "Failed to drop the page cache, files may be verified from memory: {}",
+            e
+        );
+    }
+    let threads = thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1);
+    info!(
+        "Verifying {} files on {} threads ...",
+        manifest.len(),
+        threads
+    );
+    let checked = Arc::new(AtomicUsize::new(0));
+    let worker = {
+        let root = root.to_path_buf();
+        let manifest = Arc::clone(&manifest);
+        let checked = Arc::clone(&checked);
+        let stop = cancel.flag();
+        thread::spawn(move || manifest::verify(&root, &manifest, threads, &checked, &stop))
+    };
+
+    let start = Instant::now();
+    while !worker.is_finished() {
+        let done = checked.load(Ordering::SeqCst);
+        let rate = done as f64 / start.elapsed().as_secs_f64().max(0.001);
+        if done > 0 {
+            if let Ok(remaining) =
+                Duration::try_from_secs_f64((manifest.len() - done) as f64 / rate)
+            {
+                timer.set_remaining(remaining);
+                send_remaining(sender, timer)?;
+            }
+        }
+        sender.send(InstallProgress::Pending(
+            format!("{VERIFY_FILES} ({rate:.0} files/s)"),
+            done * 100 / manifest.len().max(1),
+        ))?;
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let mismatches = worker.join().unwrap();
+    cancel.check()?;
+    if mismatches.is_empty() {
+        info!("All {} files verified", manifest.len());
+        return Ok(());
+    }
+
+    for mismatch in &mismatches {
+        error!("{}", mismatch);
+    }
+    let mut list = mismatches
+        .iter()
+        .take(MAX_LISTED_MISMATCHES)
+        .map(|x| format!("- {x}"))
+        .collect::<Vec<_>>();
+    if mismatches.len() > MAX_LISTED_MISMATCHES {
+        list.push(format!(
+            "... and {} more (see the installation log)",
+            mismatches.len() - MAX_LISTED_MISMATCHES
+        ));
+    }
+
+    Err(anyhow!(
+        "Installer found {} unpacked files that do not match the system release, the storage device or the memory may be faulty:\n\n{}",
+        mismatches.len(),
+        list.join("\n")
+    ))
+}
+
+/// Write the fstab entries of the partitions into the system at `root`
+fn write_fstab(
+    partition: &disks::Partition,
+    root: &Path,
+    previous_install: Option<&install::PreviousInstall>,
+    swapfile: bool,
+    swap_partition: Option<&disks::Partition>,
+    assigned: &[mounts::MountAssignment],
+) -> Result<()> {
+    // the partitions can not be probed in debug builds
+    if cfg!(debug_assertions) {
+        return Ok(());
+    }
+
+    info!("Generating fstab ...");
+    let fstab = mount_plan(
+        partition,
+        previous_install,
+        swapfile,
+        swap_partition,
+        assigned,
+    )?
+    .render()?;
+    info!("Generated fstab:\n{}", fstab);
+    install::write_fstab(root, &fstab)?;
+
+    Ok(())
+}
+
+/// The filesystems to be mounted by the installed system on `partition`
+fn mount_plan(
+    partition: &disks::Partition,
+    previous_install: Option<&install::PreviousInstall>,
+    swapfile: bool,
+    swap_partition: Option<&disks::Partition>,
+    assigned: &[mounts::MountAssignment],
+) -> Result<install::MountPlan> {
+    let mut partitions = vec![(partition.clone(), PathBuf::from("/"))];
+    if let Some(swap_partition) = swap_partition {
+        partitions.push((swap_partition.clone(), PathBuf::from("none")));
+    }
+    if disks::is_efi_booted() && !mounts::has_esp(assigned) {
+        let esp = disks::find_esp_partition(partition.parent_path.as_ref().unwrap())?;
+        partitions.push((esp, PathBuf::from(mounts::ESP_MOUNT_POINT)));
+    }
+    let mut options = HashMap::new();
+    for mount in assigned {
+        let mount_point = PathBuf::from(&mount.mount_point);
+        if let Some(x) = mount.options.as_ref() {
+            options.insert(mount_point.clone(), x.clone());
+        }
+        partitions.push((mount.mounted_partition(), mount_point));
+    }
+    // a separate /home of the previous installation is mounted as before
+    let extra = previous_install
+        .and_then(|x| x.home_fstab.clone())
+        .into_iter()
+        .collect();
+
+    Ok(install::MountPlan {
+        partitions,
+        swapfile,
+        extra,
+        options,
+    })
+}
+
+/// The partitions assigned to mount points in the advanced mode
+fn assigned_mounts(config: &InstallConfig) -> &[mounts::MountAssignment] {
+    config
+        .mounts
+        .as_deref()
+        .map(|x| x.as_slice())
+        .unwrap_or_default()
+}
+
+/// Stands for the UUIDs of the partitions not formatted yet
+const UUID_PLACEHOLDER: &str = "<UUID>";
+/// Stands for the offset of the swapfile not created yet
+const OFFSET_PLACEHOLDER: &str = "<offset>";
+
+/// The swap partition to be used, if the swap is on one
+fn swap_partition(config: &InstallConfig) -> Option<&disks::Partition> {
+    config
+        .swap_partition
+        .as_deref()
+        .filter(|_| config.swap == Some(SwapKind::Partition))
+}
+
+/// The kernel parameters to resume from the swap after hibernation, if it is to be set up,
+/// the UUID and the offset are only probed on the installed `partition` if it is given
+/// Must be used in a chroot context if `partition` is given
+fn resume_kernel_cmdline(
+    config: &InstallConfig,
+    partition: Option<&disks::Partition>,
+) -> Result<Option<String>> {
+    if !config.is_hibernation.v.load(Ordering::SeqCst) {
+        return Ok(None);
+    }
+    let uuid = |path: Option<&PathBuf>| match path.filter(|_| partition.is_some()) {
+        Some(path) => install::filesystem_uuid(path),
+        None => Ok(UUID_PLACEHOLDER.to_string()),
+    };
+
+    let cmdline = match config.swap {
+        Some(SwapKind::File) if config.use_swap.v.load(Ordering::SeqCst) => {
+            let uuid = uuid(partition.and_then(|x| x.path.as_ref()))?;
+            let offset = match partition {
+                Some(_) => install::swapfile_resume_offset(Path::new("/swapfile"))?.to_string(),
+                None => OFFSET_PLACEHOLDER.to_string(),
+            };
+            format!("resume=UUID={uuid} resume_offset={offset}")
+        }
+        Some(SwapKind::Partition) => match swap_partition(config).and_then(|x| x.path.as_ref()) {
+            Some(path) => format!("resume=UUID={}", uuid(Some(path))?),
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(cmdline))
+}
+
+/// The kernel command line of the installed system, from the distribution defaults in
+/// `base`: with the boot splash added or stripped (unless `splash` is `None`), the
+/// parameters unlocking the encrypted system partition with the LUKS header `luks_uuid`,
+/// and those resuming after hibernation, and then the user-supplied parameters, which take
+/// precedence
+fn kernel_cmdline(
+    base: &str,
+    config: &InstallConfig,
+    splash: Option<bool>,
+    luks_uuid: Option<&str>,
+    resume: Option<&str>,
+) -> String {
+    let mut cmdline = match splash {
+        Some(splash) => install::splash_kernel_cmdline(base, splash),
+        None => base.to_string(),
+    };
+    let luks = luks_uuid.map(|x| format!("rd.luks.uuid={x}"));
+    let user = config.kernel_cmdline.as_deref().map(|x| x.as_str());
+    for params in [luks.as_deref(), resume, user].into_iter().flatten() {
+        cmdline = install::merge_kernel_cmdline(&cmdline, params);
+    }
+
+    cmdline
+}
+
+/// The kernel command line shown on the summary, `None` if nothing is added to the
+/// distribution defaults
+fn planned_kernel_cmdline(config: &InstallConfig) -> Option<String> {
+    // the UUIDs are not known until the partitions are formatted
+    let luks_uuid = config.encrypt.unwrap_or(false).then_some(UUID_PLACEHOLDER);
+    let resume = resume_kernel_cmdline(config, None).ok().flatten();
+    let cmdline = kernel_cmdline(
+        "",
+        config,
+        Some(boot_splash(config)),
+        luks_uuid,
+        resume.as_deref(),
+    );
+
+    (!cmdline.is_empty()).then_some(cmdline)
+}
+
+/// Make the installed system bootable from `partition`, the system partition is unlocked
+/// with the LUKS header `luks_uuid` if it is encrypted, returns the foreign OSes detected
+/// Must be used in a chroot context
+fn install_bootloader(
+    config: &InstallConfig,
+    partition: &disks::Partition,
+    luks_uuid: Option<&str>,
+) -> Result<BootMenu> {
+    let boot_splash = boot_splash(config);
+    let splash = if boot_splash && !install::plymouth_exists(Path::new("/")) {
+        info!("Plymouth is not shipped, skipping boot splash");
+        None
+    } else {
+        Some(boot_splash)
+    };
+    let resume = resume_kernel_cmdline(config, Some(partition))?;
+    install::update_kernel_cmdline(|old| {
+        let cmdline = kernel_cmdline(old, config, splash, luks_uuid, resume.as_deref());
+        info!("Setting kernel command line: {}", cmdline);
+        cmdline
+    })?;
+
+    let gfxmode = config
+        .grub_gfxmode
+        .as_ref()
+        .map(|x| x.as_str())
+        .or_else(|| hidpi(config).then_some(install::HIDPI_GRUB_GFXMODE));
+    if let Some(gfxmode) = gfxmode {
+        info!("Setting GRUB graphics mode: {}", gfxmode);
+    }
+
+    info!("Setting GRUB options ...");
+    install::set_grub_options(
+        config.grub_timeout,
+        config.grub_os_prober,
+        config.grub_hidden_menu,
+        gfxmode,
     )?;
 
-    info!("Removing bind mounts ...");
-    install::remove_bind_mounts(&mount_path_copy)?;
+    if disks::is_efi_booted() {
+        info!("Installing grub to UEFI partition ...");
+        install::execute_grub_install(None)?;
+    } else if disks::needs_prep_partition() {
+        info!("Installing grub to PReP boot partition ...");
+        install::execute_grub_install(Some(partition.parent_path.as_ref().unwrap()))?;
+    } else {
+        info!("Installing grub to MBR partition ...");
+        install::execute_grub_install(Some(partition.parent_path.as_ref().unwrap()))?;
+    };
+
+    let os_prober = config.grub_os_prober.unwrap_or(false);
+    let foreign_os = if os_prober {
+        install::list_foreign_os().unwrap_or_else(|e| {
+            error!("Failed to run os-prober: {}", e);
+            vec![]
+        })
+    } else {
+        vec![]
+    };
+    for os in &foreign_os {
+        info!("Detected operating system: {}", os);
+    }
 
-    info!("Trying to swapoff ...");
-    install::swapoff(&tempdir);
+    let windows = disks::is_efi_booted()
+        .then(install::find_windows_boot)
+        .flatten();
+    if let Some(windows) = windows.as_ref() {
+        info!("Found an existing Windows: {}", windows);
+    }
+    let warning = windows_warning(os_prober, &foreign_os, windows.as_deref());
+    if let Some(warning) = warning.as_ref() {
+        warn!("{}", warning);
+    }
+
+    Ok(BootMenu {
+        foreign_os,
+        warning,
+    })
+}
+
+/// The other operating systems in the boot menu of the installed system
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BootMenu {
+    pub foreign_os: Vec<install::ForeignOs>,
+    /// Set if an existing Windows appears to be missing from the boot menu
+    pub warning: Option<String>,
+}
+
+/// Explain why the Windows found at `windows` is not in the boot menu, if it is not
+fn windows_warning(
+    os_prober: bool,
+    foreign_os: &[install::ForeignOs],
+    windows: Option<&str>,
+) -> Option<String> {
+    let windows = windows?;
+    if foreign_os.iter().any(|x| x.name.contains("Windows")) {
+        return None;
+    }
+
+    Some(if os_prober {
+        format!("Installer found an existing Windows ({windows}), but os-prober did not detect it, so it is not in the boot menu. Windows left hibernated by Fast Startup or encrypted with BitLocker can not be probed, and GRUB may be unable to chainload it with Secure Boot enabled. Windows can still be started from the firmware boot menu, or added to the boot menu by running `grub-mkconfig -o /boot/grub/grub.cfg` in the installed system once detectable.")
+    } else {
+        format!("Installer found an existing Windows ({windows}), but detecting other operating systems was disabled, so it is not in the boot menu. To add it, set GRUB_DISABLE_OS_PROBER=false in /etc/default/grub and run `grub-mkconfig -o /boot/grub/grub.cfg` in the installed system, or start Windows from the firmware boot menu.")
+    })
+}
+
+/// Configure the system, users and packages
+/// Must be used in a chroot context
+fn configure_guest(
+    sender: &Sender<InstallProgress>,
+    config: &InstallConfig,
+    previous_install: Option<&install::PreviousInstall>,
+    resolv_conf: Option<&[u8]>,
+) -> Result<()> {
+    let tz = config.timezone.as_ref().unwrap();
+    info!("Setting timezone as {}", tz);
+    install::set_zoneinfo(tz)?;
+
+    let tc = config.tc.as_ref().unwrap();
+    info!("Setting hwclock (hardware clock) as {}", tc);
+    install::set_hwclock_tc(match tc.as_str() {
+        "UTC" => true,
+        "RTC" => false,
+        _ => true,
+    })?;
+
+    info!("Resetting machine ID ...");
+    install::reset_machine_id(config.generate_machine_id.unwrap_or(false))?;
+    install::write_first_boot_marker()?;
+
+    let hostname = config.hostname.as_ref().unwrap();
+    info!("Setting hostname as {}", hostname);
+    install::set_hostname(hostname)?;
+
+    let old_users = previous_install
+        .map(|x| x.users.as_slice())
+        .unwrap_or_default();
+    if config.oobe.unwrap_or(false) {
+        info!("Skipping user creation and the root password, the user account will be created on first boot");
+        // so that the account created on first boot (in the wheel group) is an administrator
+        install::setup_admin("", &["wheel".to_string()], false)?;
+    } else if install::list_normal_users(Path::new("/"))?
+        .iter()
+        .any(|x| Some(x) == config.user.as_deref())
+    {
+        // configuring again from the finish screen
+        info!("The user account has been created already, skipping");
+    } else {
+        setup_users(config, old_users)?;
+    }
+
+    if config.restore_users.unwrap_or(false) {
+        for user in old_users
+            .iter()
+            .filter(|x| Some(&x.name) != config.user.as_deref())
+        {
+            info!("Re-creating user {} (UID {}) ...", user.name, user.uid);
+            if let Err(e) = install::restore_user(user) {
+                error!("Failed to re-create user {}: {}", user.name, e);
+            }
+        }
+    }
+
+    let locale = config.locale.as_ref().unwrap();
+    let mut locales = vec![locale.to_string()];
+    if let Some(extra_locales) = config.extra_locales.as_ref() {
+        locales.extend(
+            extra_locales
+                .iter()
+                .filter(|x| *x != locale.as_str())
+                .cloned(),
+        );
+    }
+    info!("Generating locales: {:?}", locales);
+    install::generate_locales(&locales)?;
+
+    info!("Setting locale as {}", locale);
+    install::set_locale(locale)?;
+
+    // before the additional packages, so that they come from the selected mirror as well
+    if config.use_mirror_repo.unwrap_or(true) {
+        let mirror = config.mirror.as_ref().unwrap();
+        match mirror.package_repo() {
+            Some(repo_url) => {
+                info!("Setting package repository as {}", repo_url);
+                install::set_package_mirror(&repo_url)?;
+            }
+            None => info!(
+                "Package repository of mirror {} is unknown, leaving the package sources unchanged",
+                mirror.url
+            ),
+        }
+    }
+
+    if let Some(packages) = config.extra_packages.as_ref().filter(|x| !x.is_empty()) {
+        info!("Installing additional packages: {:?}", packages);
+        install_packages_or_skip(sender, packages, resolv_conf, "additional packages")?;
+    }
+
+    // after the additional packages, which may provide the font
+    if hidpi(config) {
+        setup_console_font(sender, config, resolv_conf)?;
+    }
+
+    let nvidia_gpus = install::find_nvidia_gpus();
+    if !nvidia_gpus.is_empty() {
+        info!(
+            "Detected NVIDIA GPU(s): {:?}, proprietary driver requested: {}",
+            nvidia_gpus,
+            config.nvidia_driver.unwrap_or(false)
+        );
+    }
+    if config.nvidia_driver.unwrap_or(false) {
+        let packages = install::NVIDIA_DRIVER_PACKAGES
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        info!("Installing NVIDIA driver: {:?}", packages);
+        // nouveau must stay around if the driver could not be installed
+        if install_packages_or_skip(sender, &packages, resolv_conf, "the NVIDIA driver")? {
+            info!("Blacklisting nouveau ...");
+            install::blacklist_nouveau()?;
+
+            info!("Running dracut ...");
+            install::execute_dracut()?;
+        }
+    }
+
+    // after the additional packages, which may provide the shell
+    if let (Some(user), Some(shell)) = (
+        config
+            .user
+            .as_ref()
+            .filter(|_| !config.oobe.unwrap_or(false)),
+        config
+            .shell
+            .as_ref()
+            .filter(|x| x.as_str() != install::DEFAULT_SHELL),
+    ) {
+        info!("Setting login shell as {}", shell);
+        if !install::set_login_shell(user, shell)? {
+            sender.send(InstallProgress::Warning(format!(
+                "Shell {} is not available in the installed system, {} will log in with {} instead.",
+                shell,
+                user,
+                install::DEFAULT_SHELL
+            )))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Create the default user and set up the root account and SSH
+/// Must be used in a chroot context
+fn setup_users(config: &InstallConfig, old_users: &[install::OldUser]) -> Result<()> {
+    info!("Setting username and password ...");
+
+    let admin = config.admin.unwrap_or(true);
+    let mut groups = config
+        .groups
+        .as_ref()
+        .map(|x| x.to_vec())
+        .unwrap_or_else(default_groups);
+    if !admin {
+        groups.retain(|x| x != "wheel");
+    }
+    // reuse the old UID of the same user, so that the ownership of the preserved home matches
+    let uid = config.uid.or_else(|| {
+        old_users
+            .iter()
+            .find(|x| Some(&x.name) == config.user.as_deref())
+            .map(|x| x.uid)
+    });
+    install::add_new_user(
+        &config.user.clone().unwrap(),
+        &password_hash(config.password.as_ref().unwrap(), config.password_cost)?,
+        uid,
+        &groups,
+    )?;
+
+    if admin {
+        info!("Granting administrator privileges ...");
+        if let Some(tool) = install::setup_admin(
+            config.user.as_ref().unwrap(),
+            &groups,
+            config.sudo_nopasswd.unwrap_or(false),
+        )? {
+            info!("Configured {} for the new user", tool);
+        }
+    } else {
+        info!("The user is not an administrator, skipping sudo setup");
+    }
+
+    match config.root_password.as_deref() {
+        None | Some(RootPassword::None) => (),
+        Some(RootPassword::Locked) => {
+            info!("Locking root account ...");
+            install::lock_user_password("root")?;
+        }
+        Some(RootPassword::Hash(hash)) => {
+            info!("Setting root password (hashed) ...");
+            install::chpasswd_hash("root", hash)?;
+        }
+        Some(RootPassword::Password(password)) => {
+            info!("Setting root password ...");
+            install::chpasswd_hash(
+                "root",
+                &install::hash_password(password, config.password_cost)?,
+            )?;
+        }
+    }
 
-    info!("Unmounting main partition ...");
-    install::umount_root_path(&mount_path_copy).ok();
+    if let Some(keys) = config.ssh_keys.as_ref().filter(|x| !x.is_empty()) {
+        info!("Importing SSH authorized keys ...");
+        install::write_authorized_keys(config.user.as_ref().unwrap(), keys)?;
 
-    sender.send(InstallProgress::Finished)?;
+        if config.ssh_disable_password_auth.unwrap_or(false) {
+            info!("Disabling SSH password authentication ...");
+            install::disable_ssh_password_auth()?;
+        }
+    }
+
+    info!("Setting fullname ...");
+    if config.full_name.is_some() && config.full_name != Some("".to_string().into()) {
+        install::passwd_set_fullname(
+            config.full_name.as_ref().unwrap(),
+            config.user.as_ref().unwrap(),
+        )?;
+    }
 
     Ok(())
 }
 
-fn calc_speed(speed: f64, eta: f64) -> (String, String) {
-    let s = match speed {
-        x if x > 1000.0 * 1000.0 => format!("{:.1}GiB/s", speed / 1024.0 / 1024.0),
-        x if x > 1000.0 => format!("{:.1}MiB/s", speed / 1024.0),
-        x => format!("{x:.1}KiB/s"),
+/// Enable and configure the services of the installed system at `root`
+/// Must be used outside of the chroot context
+fn configure_services(config: &InstallConfig, root: &Path) -> Result<()> {
+    if install::plymouth_exists(root) {
+        let boot_splash = boot_splash(config);
+        info!(
+            "{} Plymouth units ...",
+            if boot_splash { "Enabling" } else { "Masking" }
+        );
+        install::set_plymouth_units(root, boot_splash)?;
+    }
+
+    if config.enable_sshd.unwrap_or(false) {
+        info!("Enabling SSH server ...");
+        install::systemctl_enable(root, "sshd.service")?;
+    }
+
+    let display_manager = install::find_display_manager(root);
+    let disable_display_manager = config.disable_display_manager.unwrap_or(false);
+    if let Some(unit) = display_manager.filter(|_| disable_display_manager) {
+        info!("Disabling display manager {} ...", unit);
+        install::systemctl_disable(root, unit)?;
+    }
+
+    if config.oobe.unwrap_or(false) {
+        info!("Enabling first boot user creation ...");
+        let unit = install::setup_oobe(root)?;
+        info!("Enabled {} to create the user account on first boot", unit);
+    }
+
+    if config.autologin.unwrap_or(false) {
+        let user = config.user.as_ref().unwrap();
+        info!("Enabling autologin for {} ...", user);
+        let path = install::set_autologin(
+            root,
+            user,
+            display_manager.filter(|_| !disable_display_manager),
+        )?;
+        info!(
+            "Autologin configured in /{}, remove or edit this file to disable it",
+            path.strip_prefix(root).unwrap_or(&path).display()
+        );
+    }
+
+    let default_target = match config.default_target.as_deref() {
+        Some(target) if !install::DEFAULT_TARGETS.contains(&target.as_str()) => {
+            return Err(anyhow!("Default target {} is not valid.", target));
+        }
+        Some(target) => target.as_str(),
+        None if display_manager.is_some() && !disable_display_manager => "graphical.target",
+        None => "multi-user.target",
     };
+    info!("Setting default target as {}", default_target);
+    install::set_default_target(root, default_target)?;
+
+    if config.enable_ntp.unwrap_or(true) {
+        info!("Enabling time synchronisation ...");
+        let servers = config
+            .ntp_servers
+            .as_ref()
+            .map(|x| x.to_vec())
+            .unwrap_or_default();
+        if let Some(unit) = install::enable_ntp(root, &servers)? {
+            info!("Enabled time synchronisation service: {}", unit);
+        }
+    }
+
+    if config.copy_network_config.unwrap_or(true) {
+        match install::detect_network_backend() {
+            Some(backend) => {
+                info!("Copying network configuration ({}) ...", backend.unit());
+                install::copy_network_config(root, backend)?;
+            }
+            None => info!("No active network backend found, not copying network configuration"),
+        }
+    }
 
-    let s2 = match eta {
-        x if x >= 60.0 * 60.0 * 24.0 => format!("{:.1}d", (x / 60.0 / 60.0 / 24.0).round()),
-        x if x >= 60.0 * 60.0 => format!("{:.1}h", (x / 60.0 / 60.0).round()),
-        x if x >= 60.0 => format!("{:.1}m", (eta / 60.0).round()),
-        x => format!("{:.0}s", x.round()),
+    if config.swap == Some(SwapKind::Zram) {
+        if install::unit_exists(root, ZRAM_UNIT) {
+            info!("Enabling compressed swap ...");
+            install::systemctl_enable(root, ZRAM_UNIT)?;
+        } else {
+            warn!(
+                "{} is not shipped in the installed system, skipping",
+                ZRAM_UNIT
+            );
+        }
+    }
+
+    // last, so that the services listed explicitly take precedence
+    apply_service_lists(config, root)?;
+
+    Ok(())
+}
+
+/// Enable and disable the units listed in the configuration, units not shipped
+/// in the installed system are skipped with a warning
+/// Must be used outside of the chroot context
+fn apply_service_lists(config: &InstallConfig, root: &Path) -> Result<()> {
+    let enable = config.enable_services.as_deref().map(|x| x.as_slice());
+    let disable = config.disable_services.as_deref().map(|x| x.as_slice());
+    let lists = [
+        (enable.unwrap_or_default(), true),
+        (disable.unwrap_or_default(), false),
+    ];
+    if lists.iter().all(|(x, _)| x.is_empty()) {
+        return Ok(());
+    }
+
+    let units = install::list_units(root);
+    let mut applied = vec![];
+    for (names, enable) in lists {
+        for unit in names.iter().map(|x| install::unit_name(x)) {
+            if !units.contains(&unit) {
+                let suggestions = install::suggest_similar(&unit, &units);
+                if suggestions.is_empty() {
+                    warn!("{} is not shipped in the installed system, skipping", unit);
+                } else {
+                    warn!(
+                        "{} is not shipped in the installed system, skipping. Did you mean: {}?",
+                        unit,
+                        suggestions.join(", ")
+                    );
+                }
+                continue;
+            }
+
+            if enable {
+                info!("Enabling {} ...", unit);
+                install::systemctl_enable(root, &unit)?;
+            } else {
+                info!("Disabling {} ...", unit);
+                install::systemctl_disable(root, &unit)?;
+            }
+            applied.push(unit);
+        }
+    }
+
+    for unit in applied {
+        match install::systemctl_is_enabled(root, &unit) {
+            Ok(state) => info!("{} is now {}", unit, state),
+            Err(e) => warn!("Failed to query the state of {}: {}", unit, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Set the larger console font, installing the font package if the font is missing
+/// Must be used in a chroot context
+fn setup_console_font(
+    sender: &Sender<InstallProgress>,
+    config: &InstallConfig,
+    resolv_conf: Option<&[u8]>,
+) -> Result<()> {
+    let font = config
+        .console_font
+        .as_ref()
+        .map(|x| x.as_str())
+        .unwrap_or(install::HIDPI_CONSOLE_FONT);
+    match install::detect_display_resolution() {
+        Some((width, height)) => info!(
+            "Display resolution: {}x{}, setting up console font {}",
+            width, height, font
+        ),
+        None => info!("Setting up console font {}", font),
+    }
+
+    let root = Path::new("/");
+    if !install::console_font_exists(root, font) && font.starts_with("ter-") {
+        info!(
+            "Console font {} is missing, installing {} ...",
+            font,
+            install::CONSOLE_FONT_PACKAGE
+        );
+        install_packages_or_skip(
+            sender,
+            &[install::CONSOLE_FONT_PACKAGE.to_string()],
+            resolv_conf,
+            "the console font",
+        )?;
+    }
+    if !install::console_font_exists(root, font) {
+        warn!(
+            "Console font {} is not available, leaving it unchanged",
+            font
+        );
+        return Ok(());
+    }
+
+    install::set_console_font(font)?;
+    info!("Console font set as {} in /etc/vconsole.conf", font);
+
+    Ok(())
+}
+
+/// Install the packages in the guest, asking the frontend whether to retry on failure,
+/// returns false if the user skipped it
+fn install_packages_or_skip(
+    sender: &Sender<InstallProgress>,
+    packages: &[String],
+    resolv_conf: Option<&[u8]>,
+    what: &str,
+) -> Result<bool> {
+    let status = format!("{STEP8}: installing {what}");
+    loop {
+        let mut lines = 0;
+        let result = install::install_packages(packages, resolv_conf, |_| {
+            // the package manager does not report its progress in a
+            // parsable way, so just show that it is making some
+            lines += 1;
+            sender
+                .send(InstallProgress::Pending(status.clone(), lines % 100))
+                .ok();
+        });
+
+        match result {
+            Ok(()) => return Ok(true),
+            Err(e) => {
+                error!("Failed to install {}: {}", what, e);
+                let (retry_tx, retry_rx) = mpsc::channel();
+                sender.send(InstallProgress::Retry(
+                    format!("Installer failed to install {what}: {e}"),
+                    retry_tx,
+                ))?;
+                if !retry_rx.recv().unwrap_or(false) {
+                    info!("Skipping {}", what);
+                    return Ok(false);
+                }
+            }
+        }
+    }
+}
+
+/// Which package repository the installed system will use
+fn package_repo_summary(mirror: &network::Mirror, use_mirror_repo: bool) -> String {
+    match mirror.package_repo() {
+        Some(repo_url) if use_mirror_repo => {
+            tr!("- The installed system will get its packages from {}.\n", repo_url)
+        }
+        Some(_) => {
+            tr!("- The package sources of the installed system will be left as shipped.\n")
+                .to_string()
+        }
+        None => tr!(
+            "- The package repository of {} is unknown, the package sources of the installed system will be left as shipped.\n",
+            mirror.url
+        ),
+    }
+}
+
+/// The partition is too small for the release, sizes in bytes
+fn not_enough_space_msg(available: u64, required: u64) -> String {
+    tr!(
+        "The specified partition does not contain enough space to install AOSC OS release!\n\nAvailable space: {}GiB\nRequired space: {}GiB",
+        format!("{:.3}", available as f32 / 1024.0 / 1024.0 / 1024.0),
+        format!("{:.3}", required as f32 / 1024.0 / 1024.0 / 1024.0)
+    )
+}
+
+/// The warning about the data on `partition` that formatting it will erase, if its
+/// filesystem holds an operating system or more than a trivial amount of data
+///
+/// Without `probe` (in dry runs), the partition is not mounted to look into it, and
+/// any filesystem on it is warned about.
+fn data_loss_warning(partition: &disks::Partition, probe: bool) -> Option<String> {
+    let fs_type = partition
+        .fs_type
+        .as_deref()
+        .filter(|x| !x.starts_with("linux-swap"))?;
+    let path = partition.path.as_deref()?;
+
+    let mut lines = vec![tr!("Filesystem: {}", fs_type)];
+    if let Some(label) = disks::partition_label(path) {
+        lines.push(tr!("Label: {}", label));
+    }
+    if !probe {
+        return Some(tr!(
+            UNEXAMINED_DATA_LOSS_WARNING,
+            path.display(),
+            lines.join("\n")
+        ));
+    }
+    let contents = install::probe_partition(partition, None).ok()?;
+    // a newly made filesystem takes some space for its metadata already
+    if contents.os.is_none() && contents.used <= TRIVIAL_USAGE.max(partition.size / 50) {
+        return None;
+    }
+    if let Some(os) = &contents.os {
+        lines.push(tr!("Contains: {}", os));
+    }
+    lines.push(tr!("Used space: {}", human_size(contents.used)));
+
+    Some(tr!(DATA_LOSS_WARNING, path.display(), lines.join("\n")))
+}
+
+/// What will be preserved and what will be destroyed when reinstalling over `previous`
+fn reinstall_summary(
+    path: &str,
+    fs: &str,
+    previous: &install::PreviousInstall,
+    restore_users: bool,
+) -> String {
+    let mut s = tr!(
+        "- Everything on {} except /home will be erased. The {} filesystem will not be formatted.\n",
+        path,
+        fs
+    );
+    match previous.home_fstab.as_ref() {
+        Some(entry) => s.push_str(&tr!(
+            "- /home is on a separate partition ({}), it will not be formatted and will be mounted as before.\n",
+            entry.split_whitespace().next().unwrap_or_default()
+        )),
+        None => s.push_str(&tr!("- /home on {} will be preserved.\n", path)),
+    }
+    if !previous.users.is_empty() {
+        let users = previous
+            .users
+            .iter()
+            .map(|x| format!("{} (UID {})", x.name, x.uid))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if restore_users {
+            s.push_str(&tr!(
+                "- Existing users will be re-created with their UIDs, passwords and groups: {}.\n",
+                users
+            ));
+        } else {
+            s.push_str(&tr!("- Existing users will NOT be re-created, their files in /home will be owned by unknown UIDs: {}.\n", users));
+        }
+    }
+
+    s
+}
+
+/// Read SSH public keys from a URL, a local file, or the key itself
+fn resolve_ssh_keys(source: &str) -> Result<Vec<String>> {
+    let source = source.trim();
+    let text = if source.starts_with("https://") || source.starts_with("http://") {
+        network::fetch_ssh_keys(source)?
+    } else if Path::new(source).is_file() {
+        std::fs::read_to_string(source)?
+    } else {
+        source.to_string()
+    };
+
+    install::parse_authorized_keys(&text)
+}
+
+fn default_groups() -> Vec<String> {
+    install::DEFAULT_USER_GROUPS
+        .iter()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+#[test]
+fn test_transfer_stats() {
+    let mut stats = TransferStats::new(0);
+    assert!(!stats.update(1024));
+    stats.last.0 -= Duration::from_secs(1);
+    assert!(stats.update(1024));
+    assert_eq!(stats.percent(1024), None);
+    assert_eq!(stats.eta(1024), None);
+    assert!(stats.describe(1024).starts_with("1.0 KiB, "));
+
+    stats.total = 4096;
+    stats.speed = Some(1024.0);
+    assert_eq!(stats.percent(1024), Some(25));
+    assert_eq!(stats.eta(1024), Some(Duration::from_secs(3)));
+    assert_eq!(
+        stats.describe(1024),
+        "1.0 KiB / 4.0 KiB, 1.0 KiB/s, 3s left"
+    );
+    assert_eq!(short_duration(Duration::from_secs(130)), "2m 10s");
+    assert_eq!(short_duration(Duration::from_secs(3900)), "1h 5m");
+}
+
+#[test]
+fn test_root_password() {
+    assert_eq!(
+        RootPassword::try_from("none".to_string()).unwrap(),
+        RootPassword::None
+    );
+    assert_eq!(
+        RootPassword::try_from("locked".to_string()).unwrap(),
+        RootPassword::Locked
+    );
+    assert_eq!(
+        RootPassword::try_from("$6$salt$hash".to_string()).unwrap(),
+        RootPassword::Hash("$6$salt$hash".to_string())
+    );
+    assert_eq!(
+        RootPassword::try_from("hunter2".to_string()).unwrap(),
+        RootPassword::Password("hunter2".to_string())
+    );
+    // MD5 crypt and hashes which would add lines to the chpasswd input are refused
+    assert!(RootPassword::try_from("$1$salt$hash".to_string()).is_err());
+    assert!(RootPassword::try_from("$6$salt$hash\nroot2:$6$salt$hash".to_string()).is_err());
+    assert!(RootPassword::try_from("$y$j9T$salt$hash:0".to_string()).is_err());
+    assert!(RootPassword::is_ambiguous_password("locked"));
+    assert!(RootPassword::is_ambiguous_password("$1$salt$hash"));
+    assert!(!RootPassword::is_ambiguous_password("hunter2"));
+
+    let mut config = InstallConfig {
+        user: Some(Arc::new("saki".to_string())),
+        root_password: Some(Arc::new(RootPassword::Locked)),
+        ..Default::default()
+    };
+    assert!(check_root_account(&config).is_ok());
+    config.admin = Some(false);
+    assert!(check_root_account(&config).is_err());
+    config.admin = None;
+    config.groups = Some(Arc::new(vec!["audio".to_string()]));
+    assert!(check_root_account(&config).is_err());
+    config.user = None;
+    config.groups = None;
+    assert!(check_root_account(&config).is_err());
+
+    let mut config = InstallConfig {
+        oobe: Some(true),
+        ..Default::default()
     };
+    assert!(check_root_account(&config).is_ok());
+    config.root_password = Some(Arc::new(RootPassword::Locked));
+    assert!(check_root_account(&config).is_err());
+    config.root_password = None;
+    config.autologin = Some(true);
+    assert!(check_root_account(&config).is_err());
+}
+
+#[test]
+fn test_config_file_passwords() {
+    let mut config = InstallConfig {
+        password: Some(Arc::new("$y$j9T$salt$hash".to_string())),
+        root_password: Some(Arc::new(RootPassword::Hash("$6$salt$hash".to_string()))),
+        ..Default::default()
+    };
+    assert!(check_config_file_passwords(&config).is_ok());
+    config.root_password = Some(Arc::new(RootPassword::Password("hunter2".to_string())));
+    assert!(check_config_file_passwords(&config).is_err());
+    config.root_password = None;
+    config.password = Some(Arc::new("hunter2".to_string()));
+    assert!(check_config_file_passwords(&config).is_err());
+    config.allow_plaintext_password = Some(true);
+    assert!(check_config_file_passwords(&config).is_ok());
+    assert_eq!(password_hash("$6$salt$hash", None).unwrap(), "$6$salt$hash");
+}
+
+#[test]
+fn test_redacted_json() {
+    let mut config = InstallConfig {
+        user: Some(Arc::new("saki".to_string())),
+        password: Some(Arc::new("hunter2".to_string())),
+        root_password: Some(Arc::new(RootPassword::Password("hunter3".to_string()))),
+        ..Default::default()
+    };
+    let json = config.redacted_json().unwrap();
+    assert!(json.contains("saki"));
+    assert!(!json.contains("hunter"));
+
+    config.root_password = Some(Arc::new(RootPassword::Locked));
+    assert!(config.redacted_json().unwrap().contains("locked"));
+}
+
+#[test]
+fn test_windows_warning() {
+    let windows = install::ForeignOs {
+        name: "Windows Boot Manager".to_string(),
+        device: "/dev/nvme0n1p1".to_string(),
+        loader: "efi".to_string(),
+    };
+    let found = Some("UEFI boot entry Boot0000 (Windows Boot Manager)");
+    assert_eq!(windows_warning(true, &[windows], found), None);
+    assert_eq!(windows_warning(true, &[], None), None);
+    assert!(windows_warning(true, &[], found)
+        .unwrap()
+        .contains("os-prober did not detect it"));
+    assert!(windows_warning(false, &[], found)
+        .unwrap()
+        .contains("GRUB_DISABLE_OS_PROBER=false"));
+}
 
-    (s, s2)
+#[test]
+fn test_change_variant_and_disk() {
+    let variant = |size| network::VariantEntry {
+        name: "Base".to_string(),
+        size: size / 4,
+        install_size: size - size / 4,
+        date: "20240101".to_string(),
+        sha256sum: String::new(),
+        url: String::new(),
+        manifest: None,
+        description: String::new(),
+        description_tr: String::new(),
+    };
+    let mut config = InstallConfig {
+        partition: Some(Arc::new(disks::Partition {
+            path: Some(PathBuf::from("/dev/sda1")),
+            parent_path: Some(PathBuf::from("/dev/sda")),
+            fs_type: Some("ext4".to_string()),
+            size: 8 << 30,
+        })),
+        preserve_home: Some(true),
+        ..Default::default()
+    };
+    assert!(config.change_variant(variant(4 << 30)).is_none());
+    assert!(config.change_disk(Path::new("/dev/sda")).is_none());
+    assert!(config.partition.is_some());
+
+    assert!(config.change_disk(Path::new("/dev/sdb")).is_some());
+    assert!(config.partition.is_none() && config.preserve_home.is_none());
+    assert!(config.change_disk(Path::new("/dev/sda")).is_none());
+
+    config.partition = Some(Arc::new(disks::Partition {
+        path: None,
+        parent_path: Some(PathBuf::from("/dev/sda")),
+        fs_type: Some("ext4".to_string()),
+        size: 8 << 30,
+    }));
+    assert!(config.change_variant(variant(16 << 30)).is_some());
+    assert!(config.partition.is_none());
+    assert_eq!(config.variant.unwrap().size, 4 << 30);
+}
+
+#[test]
+fn test_install_phase() {
+    assert_eq!(InstallPhase::of_step(STEP1), InstallPhase::Formatting);
+    assert_eq!(InstallPhase::of_step(STEP3), InstallPhase::Downloading);
+    assert_eq!(
+        InstallPhase::of_step(&format!("{VERIFY_FILES} (120 files/s)")),
+        InstallPhase::Unpacking
+    );
+    assert_eq!(InstallPhase::of_step(STEP6), InstallPhase::Configuring);
+}
+
+#[test]
+fn test_step_exit_code() {
+    assert_eq!(step_exit_code(STEP1), ExitCode::Partitioning);
+    assert_eq!(
+        step_exit_code(&format!("{VERIFY_FILES} (120 files/s)")),
+        ExitCode::Extraction
+    );
+    assert_eq!(step_exit_code(STEP6), ExitCode::Bootloader);
+    assert_eq!(step_exit_code(STEP7), ExitCode::Internal);
+    // cancelling is no failure of the step
+    let e = anyhow::Error::from(Cancelled);
+    assert_eq!(
+        ExitCode::of_error(&step_exit_code(STEP2).fallback(e)),
+        ExitCode::UserAbort
+    );
+}
+
+#[test]
+fn test_overall_percent() {
+    let plan = [(STEP1, 10.0), (STEP2, 50.0), (STEP4, 30.0), (STEP5, 10.0)];
+    assert_eq!(overall_percent(&plan, STEP1, 0), Some(0));
+    assert_eq!(overall_percent(&plan, STEP2, 50), Some(35));
+    // made-up progress is not counted
+    assert_eq!(overall_percent(&plan, STEP5, 70), Some(90));
+    assert_eq!(
+        overall_percent(&plan, &format!("{STEP8}: installing nvidia"), 10),
+        None
+    );
+}
+
+#[test]
+fn test_journal_steps() {
+    let plan = [(STEP4, 30.0), (STEP5, 10.0), (STEP8, 10.0)];
+    assert_eq!(
+        journal_steps(&plan, true),
+        vec![STEP4, FSTAB_STEP, STEP5, STEP8]
+    );
+    assert_eq!(journal_steps(&plan, false), vec![STEP4, STEP5, STEP8]);
+}
+
+#[test]
+fn test_command_tail() {
+    assert_eq!(
+        tail_line("\x1b[1mdracut\x1b[0m: *** Including module: base ***"),
+        "dracut: *** Including module: base ***"
+    );
+    assert_eq!(
+        tail_line("Writing inode tables: 1/8\r2/8\rdone   \r"),
+        "done"
+    );
+    assert_eq!(tail_line("\t"), "");
+    assert_eq!(
+        tail_line(&"x".repeat(150)),
+        format!("{}...", "x".repeat(COMMAND_TAIL_WIDTH))
+    );
+
+    let mut tail = CommandTail::new();
+    assert_eq!(tail.push("one"), Some("one".to_string()));
+    // too soon after the last update
+    assert_eq!(tail.push(""), None);
+    assert_eq!(tail.push("two"), None);
+    tail.shown = None;
+    tail.push("three");
+    tail.shown = None;
+    assert_eq!(tail.push("four"), Some("two\nthree\nfour".to_string()));
+    tail.clear();
+    assert_eq!(tail.push("five"), Some("five".to_string()));
+}
+
+#[test]
+fn test_space_budget() {
+    const GIB: u64 = 1024 * 1024 * 1024;
+    let mut budget = SpaceBudget {
+        partition: 20 * GIB,
+        download: 3 * GIB,
+        installed: 10 * GIB,
+        swapfile: 0,
+    };
+    assert_eq!(budget.free(), 10 * GIB as i64);
+    assert_eq!(budget.status(), SpaceStatus::Enough);
+    assert!(budget.status_text().is_empty());
+
+    budget.swapfile = 7 * GIB;
+    assert_eq!(budget.status(), SpaceStatus::Tight);
+    assert!(budget.describe().contains("Swapfile: 7.0 GiB"));
+
+    // the download does not fit along with the rest
+    budget.swapfile = 8 * GIB;
+    assert_eq!(budget.status(), SpaceStatus::Short(GIB));
+    budget.swapfile = 12 * GIB;
+    assert_eq!(budget.free(), -2 * GIB as i64);
+    assert_eq!(budget.status(), SpaceStatus::Short(5 * GIB));
+    assert!(budget
+        .describe()
+        .ends_with("Free space after installation: -2.0 GiB"));
+}
+
+#[test]
+fn test_plain_description() {
+    let text = "# Desktop\n\nThe **full** desktop,\nwith `KDE` Plasma.\n\n\nIncludes:\n- a browser\n  and mail\n* an office suite\n1. see [the wiki](https://wiki.aosc.io)\n\n";
+    assert_eq!(
+        plain_description(text),
+        "Desktop\n\nThe full desktop, with KDE Plasma.\n\nIncludes:\n  - a browser and mail\n  - an office suite\n  1. see the wiki (https://wiki.aosc.io)"
+    );
+    assert_eq!(plain_description(""), "");
+    assert_eq!(plain_inline("[not a link"), "[not a link");
+}
+
+#[test]
+fn test_kernel_cmdline() {
+    let mut config = InstallConfig {
+        kernel_cmdline: Some(Arc::new("loglevel=7".to_string())),
+        ..Default::default()
+    };
+    // the user-supplied parameters take precedence
+    assert_eq!(
+        kernel_cmdline(
+            "quiet loglevel=3",
+            &config,
+            Some(true),
+            Some("1111"),
+            Some("resume=UUID=2222 resume_offset=34"),
+        ),
+        "quiet splash rd.luks.uuid=1111 resume=UUID=2222 resume_offset=34 loglevel=7"
+    );
+    assert_eq!(
+        kernel_cmdline("quiet splash", &config, None, None, None),
+        "quiet splash loglevel=7"
+    );
+
+    config.kernel_cmdline = None;
+    assert_eq!(planned_kernel_cmdline(&config), None);
+    config.encrypt = Some(true);
+    assert_eq!(
+        planned_kernel_cmdline(&config).as_deref(),
+        Some("rd.luks.uuid=<UUID>")
+    );
 }
@@ -0,0 +1,937 @@
+use cursive::{
+    event::{EventResult, Key},
+    traits::*,
+    utils::markup::StyledString,
+    views::{
+        Checkbox, Dialog, DummyView, EditView, LinearLayout, NamedView, OnEventView, SelectView,
+        TextView,
+    },
+    Cursive,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{
+    disks::{self, ALLOWED_FS_TYPE},
+    install, tr,
+};
+
+use super::{
+    accessible::dropdown,
+    help::{help_button, help_on_f1, Screen},
+    human_size,
+    theme::{emphasis, Emphasis},
+    tui::{
+        encryption_options, read_encryption_options, run_in_background, show_msg, wrap_in_dialog,
+    },
+    InstallConfig,
+};
+
+const MOUNT_TABLE: &str = "mount_table";
+const MOUNT_ERRORS: &str = "mount_errors";
+/// Where GRUB expects the EFI system partition
+pub(super) const ESP_MOUNT_POINT: &str = "/efi";
+/// Offered as completions of the mount points, in this order
+const COMMON_MOUNT_POINTS: &[&str] = &[
+    "/home",
+    "/efi",
+    "/boot",
+    "/var",
+    "/var/log",
+    "/srv",
+    "/opt",
+    "/tmp",
+    "/usr/local",
+];
+/// Read by the bootloader, which can only unlock the system partition with its passphrase
+const BOOT_MOUNT_POINTS: &[&str] = &["/boot", ESP_MOUNT_POINT];
+/// Parts of the system that have to be on the system partition
+const RESERVED_MOUNT_POINTS: &[&str] = &[
+    "/bin", "/dev", "/etc", "/lib", "/lib64", "/proc", "/run", "/sbin", "/sys", "/usr",
+];
+/// Partitions smaller than this are not proposed for /home
+const HOME_MIN_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+const LINUX_FS_TYPES: &[&str] = &["ext4", "xfs", "btrfs", "f2fs"];
+
+/// A partition assigned to a mount point of the installed system in the advanced mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct MountAssignment {
+    /// The partition with its current filesystem
+    pub(super) partition: disks::Partition,
+    /// Empty if the partition is not mounted
+    pub(super) mount_point: String,
+    /// The filesystem to format the partition with, `None` keeps the current one
+    pub(super) fs_type: Option<String>,
+    pub(super) format: bool,
+    /// The label of the new filesystem
+    pub(super) label: Option<String>,
+    /// Mount options replacing the defaults of the filesystem
+    pub(super) options: Option<String>,
+    /// Encrypt the partition with a keyfile kept on the encrypted system partition
+    #[serde(default)]
+    pub(super) encrypt: bool,
+}
+
+impl MountAssignment {
+    fn new(partition: disks::Partition) -> Self {
+        MountAssignment {
+            partition,
+            mount_point: String::new(),
+            fs_type: None,
+            format: false,
+            label: None,
+            options: None,
+            encrypt: false,
+        }
+    }
+
+    /// The filesystem the partition will have in the installed system
+    pub(super) fn target_fs_type(&self) -> Option<&str> {
+        self.fs_type
+            .as_deref()
+            .or(self.partition.fs_type.as_deref())
+    }
+
+    /// The partition as it will be formatted and mounted
+    pub(super) fn target_partition(&self) -> disks::Partition {
+        let mut partition = self.partition.clone();
+        partition.fs_type = self.target_fs_type().map(|x| match x {
+            // mkfs only knows the FAT filesystems by this name
+            "fat16" | "fat32" if self.format => "vfat".to_string(),
+            x => x.to_string(),
+        });
+
+        partition
+    }
+
+    /// The partition as the installed system mounts it, through the device mapper if it
+    /// is encrypted
+    pub(super) fn mounted_partition(&self) -> disks::Partition {
+        let mut partition = self.target_partition();
+        if self.encrypt {
+            partition.path =
+                Some(Path::new("/dev/mapper").join(install::data_mapper_name(&self.mount_point)));
+        }
+
+        partition
+    }
+}
+
+fn is_fat(fs_type: &str) -> bool {
+    matches!(fs_type, "vfat" | "fat16" | "fat32")
+}
+
+/// Whether the partitions include one for the ESP, which is then not looked for
+pub(super) fn has_esp(assigned: &[MountAssignment]) -> bool {
+    assigned
+        .iter()
+        .any(|x| Path::new(&x.mount_point) == Path::new(ESP_MOUNT_POINT))
+}
+
+/// The common mount point `prefix` may be completed to
+fn complete(prefix: &str) -> Option<&'static str> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    COMMON_MOUNT_POINTS
+        .iter()
+        .find(|x| x.starts_with(prefix) && x.len() > prefix.len())
+        .copied()
+}
+
+/// The problem of the assignment at `index`, `esp` is the EFI system partition
+fn check_row(rows: &[MountAssignment], index: usize, esp: Option<&Path>) -> Option<String> {
+    let row = &rows[index];
+    let mount_point = Path::new(&row.mount_point);
+    if row.mount_point.is_empty() {
+        return None;
+    }
+    if !row.mount_point.starts_with('/')
+        || row.mount_point.contains(char::is_whitespace)
+        || mount_point.components().any(|x| x == Component::ParentDir)
+    {
+        return Some(tr!("Mount points must be absolute paths without spaces.").to_string());
+    }
+    if RESERVED_MOUNT_POINTS
+        .iter()
+        .any(|x| Path::new(x) == mount_point)
+    {
+        return Some(tr!(
+            "{} can not be on a separate partition.",
+            row.mount_point
+        ));
+    }
+    if rows
+        .iter()
+        .filter(|x| Path::new(&x.mount_point) == mount_point)
+        .count()
+        > 1
+    {
+        return Some(tr!(
+            "{} is assigned to more than one partition.",
+            row.mount_point
+        ));
+    }
+    let is_esp = esp.is_some() && row.partition.path.as_deref() == esp;
+    if is_esp && mount_point != Path::new(ESP_MOUNT_POINT) {
+        return Some(tr!(
+            "The EFI system partition can only be mounted at {}.",
+            ESP_MOUNT_POINT
+        ));
+    }
+    if row.fs_type.is_some() && !row.format {
+        return Some(tr!("Check \"Format\" to change the filesystem.").to_string());
+    }
+    if row.encrypt && mount_point != Path::new("/") {
+        let system_encrypted = rows
+            .iter()
+            .any(|x| Path::new(&x.mount_point) == Path::new("/") && x.encrypt);
+        if !system_encrypted {
+            return Some(tr!("Other partitions can only be encrypted along with the system partition, which keeps their keys.").to_string());
+        }
+        if is_esp
+            || BOOT_MOUNT_POINTS
+                .iter()
+                .any(|x| Path::new(x) == mount_point)
+        {
+            return Some(tr!(
+                "{} is read by the bootloader, it can not be encrypted.",
+                row.mount_point
+            ));
+        }
+        if !row.format {
+            return Some(tr!("Check \"Format\" to encrypt the partition.").to_string());
+        }
+    }
+    let Some(fs_type) = row.target_fs_type() else {
+        return Some(
+            tr!("The partition has no filesystem, please choose one to format it with.")
+                .to_string(),
+        );
+    };
+    if mount_point == Path::new(ESP_MOUNT_POINT) && !is_fat(fs_type) {
+        return Some(tr!("The EFI system partition must have a FAT filesystem.").to_string());
+    }
+    if fs_type.starts_with("linux-swap") || disks::fstab_options(fs_type).is_err() {
+        return Some(tr!(
+            "{} filesystems can not be mounted by AOSC OS, please choose a filesystem to format the partition with.",
+            fs_type
+        ));
+    }
+    if let Some(label) = row.label.as_deref() {
+        if !row.format {
+            return Some(tr!("The label is only set when formatting.").to_string());
+        }
+        let limit = disks::label_limit(fs_type);
+        if label.chars().count() > limit {
+            return Some(tr!(
+                "Labels of {} filesystems can be at most {} characters long.",
+                fs_type,
+                limit
+            ));
+        }
+    }
+    if row
+        .options
+        .as_deref()
+        .is_some_and(|x| x.contains(char::is_whitespace))
+    {
+        return Some(tr!("Mount options can not contain spaces.").to_string());
+    }
+
+    None
+}
+
+/// The problems of the assignments: one for each row (`None` if it is fine), and those of
+/// the assignments as a whole
+fn validate(rows: &[MountAssignment], esp: Option<&Path>) -> (Vec<Option<String>>, Option<String>) {
+    let errors = (0..rows.len())
+        .map(|i| check_row(rows, i, esp))
+        .collect::<Vec<_>>();
+    let root = rows
+        .iter()
+        .find(|x| !x.mount_point.is_empty() && Path::new(&x.mount_point) == Path::new("/"));
+    let general = match root {
+        None => Some(tr!("No partition is mounted at /, the root of the system.").to_string()),
+        Some(root) if root.encrypt && !root.format => Some(
+            tr!("The system partition can only be encrypted when it is formatted.").to_string(),
+        ),
+        Some(_) => None,
+    };
+
+    (errors, general)
+}
+
+/// Check the partitions assigned by a configuration file the same way as in the table, the
+/// system partition is mounted at / and formatted if `format` is set
+pub(super) fn check_assignments(
+    system: &disks::Partition,
+    format: bool,
+    assigned: &[MountAssignment],
+    esp: Option<&Path>,
+) -> Result<(), String> {
+    let mut rows = vec![MountAssignment {
+        mount_point: "/".to_string(),
+        format,
+        ..MountAssignment::new(system.clone())
+    }];
+    rows.extend(assigned.iter().cloned());
+    let (errors, general) = validate(&rows, esp);
+
+    match general.or_else(|| errors.into_iter().flatten().next()) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// Propose mount points for the partitions not assigned yet: the ESP, a root filesystem on
+/// the largest empty partition if there is none, and /home on the partition labelled
+/// "home", or otherwise the largest Linux filesystem without an operating system on it
+///
+/// The existing filesystems are kept, only empty partitions are to be formatted.
+fn auto_assign(rows: &mut [MountAssignment], infos: &[disks::PartitionInfo], esp: Option<&Path>) {
+    let assigned = |rows: &[MountAssignment], mount_point: &str| {
+        rows.iter()
+            .any(|x| Path::new(&x.mount_point) == Path::new(mount_point))
+    };
+    let free = |row: &MountAssignment| {
+        row.mount_point.is_empty() && (esp.is_none() || row.partition.path.as_deref() != esp)
+    };
+
+    if let Some(esp) = esp {
+        if !assigned(rows, ESP_MOUNT_POINT) {
+            if let Some(row) = rows
+                .iter_mut()
+                .find(|x| x.mount_point.is_empty() && x.partition.path.as_deref() == Some(esp))
+            {
+                row.mount_point = ESP_MOUNT_POINT.to_string();
+            }
+        }
+    }
+
+    if !assigned(rows, "/") {
+        let root = (0..rows.len())
+            .filter(|&i| free(&rows[i]) && rows[i].partition.fs_type.is_none())
+            .max_by_key(|&i| rows[i].partition.size);
+        if let Some(i) = root {
+            rows[i].mount_point = "/".to_string();
+            rows[i].fs_type = Some(ALLOWED_FS_TYPE[0].to_string());
+            rows[i].format = true;
+        }
+    }
+
+    if !assigned(rows, "/home") {
+        let linux = |i: usize| {
+            infos[i].os.is_none()
+                && rows[i]
+                    .partition
+                    .fs_type
+                    .as_deref()
+                    .is_some_and(|x| LINUX_FS_TYPES.contains(&x))
+        };
+        let labelled = (0..rows.len()).find(|&i| {
+            free(&rows[i])
+                && linux(i)
+                && infos[i]
+                    .label
+                    .as_deref()
+                    .is_some_and(|x| x.eq_ignore_ascii_case("home"))
+        });
+        let home = labelled.or_else(|| {
+            (0..rows.len())
+                .filter(|&i| free(&rows[i]) && linux(i) && rows[i].partition.size >= HOME_MIN_SIZE)
+                .max_by_key(|&i| rows[i].partition.size)
+        });
+        if let Some(i) = home {
+            rows[i].mount_point = "/home".to_string();
+        }
+    }
+}
+
+/// The partitions of the table, the first one is the system partition
+struct MountTable {
+    infos: Vec<disks::PartitionInfo>,
+    esp: Option<PathBuf>,
+}
+
+fn row_name(kind: &str, index: usize) -> String {
+    format!("mount_{kind}_{index}")
+}
+
+/// What is known about the partition, shown under it while there is no problem
+fn describe(info: &disks::PartitionInfo) -> String {
+    let mut items = vec![human_size(info.partition.size)];
+    items.push(
+        info.partition
+            .fs_type
+            .clone()
+            .unwrap_or_else(|| tr!("no filesystem").to_string()),
+    );
+    if let Some(label) = info.label.as_ref() {
+        items.push(format!("\"{label}\""));
+    }
+    if let Some(os) = info.os.as_ref() {
+        items.push(os.clone());
+    }
+
+    format!("  {}", items.join(", "))
+}
+
+fn optional(s: String) -> Option<String> {
+    let s = s.trim().to_string();
+    (!s.is_empty()).then_some(s)
+}
+
+/// The assignments as entered in the table
+fn read_rows(
+    siv: &mut Cursive,
+    table: &MountTable,
+    root: &MountAssignment,
+) -> Vec<MountAssignment> {
+    let mut rows = vec![root.clone()];
+    for (i, info) in table.infos.iter().enumerate().skip(1) {
+        let text = |siv: &mut Cursive, kind: &str| {
+            siv.call_on_name(&row_name(kind, i), |view: &mut EditView| {
+                view.get_content().to_string()
+            })
+            .unwrap_or_default()
+        };
+        let mount_point = text(siv, "point").trim().to_string();
+        let label = optional(text(siv, "label"));
+        let options = optional(text(siv, "options"));
+        let fs_type = siv
+            .call_on_name(
+                &row_name("fs", i),
+                |view: &mut SelectView<Option<String>>| view.selection(),
+            )
+            .flatten()
+            .and_then(|x| (*x).clone());
+        let format = siv
+            .call_on_name(&row_name("format", i), |view: &mut Checkbox| {
+                view.is_checked()
+            })
+            .unwrap_or(false);
+        let encrypt = siv
+            .call_on_name(&row_name("encrypt", i), |view: &mut Checkbox| {
+                view.is_checked()
+            })
+            .unwrap_or(false);
+        rows.push(MountAssignment {
+            partition: info.partition.clone(),
+            mount_point,
+            fs_type,
+            format,
+            label,
+            options,
+            encrypt,
+        });
+    }
+
+    rows
+}
+
+/// Show the problems (or the completion hints) under the rows, and only allow continuing
+/// once there is none
+fn refresh(siv: &mut Cursive, table: &MountTable, root: &MountAssignment) {
+    let rows = read_rows(siv, table, root);
+    let (errors, general) = validate(&rows, table.esp.as_deref());
+    let ready = general.is_none() && errors.iter().all(|x| x.is_none());
+    for (i, (row, error)) in rows.iter().zip(errors).enumerate().skip(1) {
+        let status = match (error, complete(&row.mount_point)) {
+            (Some(error), _) => {
+                StyledString::styled(format!("  {error}"), emphasis(Emphasis::Danger))
+            }
+            (None, Some(completion)) => StyledString::plain(tr!(
+                "  Press → to complete the mount point to {}.",
+                completion
+            )),
+            (None, None) => StyledString::plain(describe(&table.infos[i])),
+        };
+        siv.call_on_name(&row_name("status", i), |view: &mut TextView| {
+            view.set_content(status)
+        });
+    }
+    siv.call_on_name(MOUNT_ERRORS, |view: &mut TextView| {
+        view.set_content(StyledString::styled(
+            general.unwrap_or_default(),
+            emphasis(Emphasis::Danger),
+        ))
+    });
+    siv.call_on_name(MOUNT_TABLE, |view: &mut Dialog| {
+        if let Some(button) = view.buttons_mut().next() {
+            button.set_enabled(ready);
+        }
+    });
+}
+
+/// Put the assignments into the widgets of the table, e.g. after auto-assigning
+fn fill_rows(siv: &mut Cursive, rows: &[MountAssignment]) {
+    for (i, row) in rows.iter().enumerate().skip(1) {
+        siv.call_on_name(&row_name("point", i), |view: &mut EditView| {
+            view.set_content(row.mount_point.as_str());
+        });
+        siv.call_on_name(
+            &row_name("fs", i),
+            |view: &mut SelectView<Option<String>>| {
+                if let Some(index) = view.iter().position(|(_, x)| *x == row.fs_type) {
+                    view.set_selection(index);
+                }
+            },
+        );
+        siv.call_on_name(&row_name("format", i), |view: &mut Checkbox| {
+            view.set_checked(row.format);
+        });
+        siv.call_on_name(&row_name("encrypt", i), |view: &mut Checkbox| {
+            view.set_checked(row.encrypt);
+        });
+    }
+}
+
+/// The table header and the row of the system partition, which can not be changed here
+fn root_row(root: &MountAssignment) -> LinearLayout {
+    let cell = |s: &str, width: usize| TextView::new(s).fixed_width(width);
+    let gap = || DummyView {}.fixed_width(1);
+    let path = root
+        .partition
+        .path
+        .as_ref()
+        .map(|x| x.display().to_string())
+        .unwrap_or_default();
+    let yes_no = |x: bool| if x { tr!("Yes") } else { tr!("No") };
+
+    LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(cell(tr!("Partition"), 14))
+                .child(gap())
+                .child(cell(tr!("Mount Point"), 12))
+                .child(gap())
+                .child(cell(tr!("Filesystem"), 10))
+                .child(gap())
+                .child(cell(tr!("Format"), 6))
+                .child(gap())
+                .child(cell(tr!("Encrypt"), 7))
+                .child(gap())
+                .child(cell(tr!("Label"), 10))
+                .child(gap())
+                .child(cell(tr!("Options"), 14)),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(cell(&path, 14))
+                .child(gap())
+                .child(cell("/", 12))
+                .child(gap())
+                .child(cell(root.target_fs_type().unwrap_or_default(), 10))
+                .child(gap())
+                .child(cell(yes_no(root.format), 6))
+                .child(gap())
+                .child(cell(yes_no(root.encrypt), 7)),
+        )
+        .child(TextView::new(tr!("  The system partition chosen before.")))
+}
+
+fn edit_row(
+    table: &Rc<MountTable>,
+    root: &Rc<MountAssignment>,
+    row: &MountAssignment,
+    index: usize,
+) -> LinearLayout {
+    let on_edit = {
+        let table = table.clone();
+        let root = root.clone();
+        move |s: &mut Cursive, _: &str, _: usize| refresh(s, &table, &root)
+    };
+    let edit = |kind: &str, content: &str, width: usize| {
+        EditView::new()
+            .content(content)
+            .on_edit(on_edit.clone())
+            .with_name(row_name(kind, index))
+            .fixed_width(width)
+    };
+    let gap = || DummyView {}.fixed_width(1);
+    let path = row
+        .partition
+        .path
+        .as_ref()
+        .map(|x| x.display().to_string())
+        .unwrap_or_default();
+
+    // → at the end of the mount point takes the completion shown under the row
+    let mount_point = OnEventView::new(
+        EditView::new()
+            .content(row.mount_point.as_str())
+            .on_edit(on_edit.clone())
+            .with_name(row_name("point", index)),
+    )
+    .on_event_inner(Key::Right, |view: &mut NamedView<EditView>, _| {
+        let mut view = view.get_mut();
+        let completion = complete(&view.get_content())?;
+        Some(EventResult::Consumed(Some(view.set_content(completion))))
+    });
+
+    let keep = match row.partition.fs_type.as_deref() {
+        Some(fs_type) => tr!("Keep ({})", fs_type),
+        None => tr!("Keep").to_string(),
+    };
+    let mut fs_type = dropdown(SelectView::new());
+    fs_type.add_item(keep, None);
+    for fs in ALLOWED_FS_TYPE {
+        fs_type.add_item(*fs, Some(fs.to_string()));
+    }
+    if let Some(selected) = fs_type.iter().position(|(_, x)| *x == row.fs_type) {
+        fs_type.set_selection(selected);
+    }
+    let table_copy = table.clone();
+    let root_copy = root.clone();
+    let fs_type = fs_type.on_submit(move |s, fs_type: &Option<String>| {
+        // changing the filesystem means formatting
+        if fs_type.is_some() {
+            s.call_on_name(&row_name("format", index), |view: &mut Checkbox| {
+                view.set_checked(true);
+            });
+        }
+        refresh(s, &table_copy, &root_copy);
+    });
+
+    let table_copy = table.clone();
+    let root_copy = root.clone();
+    let mut format = Checkbox::new().on_change(move |s, _| refresh(s, &table_copy, &root_copy));
+    format.set_checked(row.format);
+
+    let table_copy = table.clone();
+    let root_copy = root.clone();
+    let mut encrypt = Checkbox::new().on_change(move |s, checked| {
+        // encrypting means formatting
+        if checked {
+            s.call_on_name(&row_name("format", index), |view: &mut Checkbox| {
+                view.set_checked(true);
+            });
+        }
+        refresh(s, &table_copy, &root_copy);
+    });
+    encrypt.set_checked(row.encrypt);
+    // the keyfiles are kept on the encrypted system partition
+    encrypt.set_enabled(root.encrypt || row.encrypt);
+
+    LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(TextView::new(path).fixed_width(14))
+                .child(gap())
+                .child(mount_point.fixed_width(12))
+                .child(gap())
+                .child(fs_type.with_name(row_name("fs", index)).fixed_width(10))
+                .child(gap())
+                .child(format.with_name(row_name("format", index)).fixed_width(6))
+                .child(gap())
+                .child(encrypt.with_name(row_name("encrypt", index)).fixed_width(7))
+                .child(gap())
+                .child(edit("label", row.label.as_deref().unwrap_or_default(), 10))
+                .child(gap())
+                .child(edit(
+                    "options",
+                    row.options.as_deref().unwrap_or_default(),
+                    14,
+                )),
+        )
+        .child(TextView::new(describe(&table.infos[index])).with_name(row_name("status", index)))
+}
+
+/// The partitions that may be mounted into the installed system: not in use, not swap and
+/// not on the installer medium
+fn mountable_partitions(
+    disks: Vec<disks::DiskInfo>,
+    system: Option<PathBuf>,
+) -> Vec<disks::PartitionInfo> {
+    disks
+        .into_iter()
+        .filter(|x| x.unusable.is_none())
+        .flat_map(|x| x.partitions)
+        .filter(|x| {
+            x.mount_point.is_none()
+                && x.partition.path.is_some()
+                && x.partition.path != system
+                && !x
+                    .partition
+                    .fs_type
+                    .as_deref()
+                    .is_some_and(|x| x.starts_with("linux-swap"))
+        })
+        .collect()
+}
+
+/// The mount point table of the advanced mode: the system partition is mounted at /, and
+/// the other partitions may be assigned to mount points, formatted and labelled
+pub(super) fn select_mount_points(
+    siv: &mut Cursive,
+    config: InstallConfig,
+    next: fn(&mut Cursive, InstallConfig),
+) {
+    let system = config.partition.as_ref().and_then(|x| x.path.clone());
+    let disk = config
+        .partition
+        .as_ref()
+        .and_then(|x| x.parent_path.clone());
+    run_in_background(
+        siv,
+        tr!("Looking for partitions to mount ..."),
+        move || {
+            let esp = disk
+                .filter(|_| disks::is_efi_booted())
+                .and_then(|x| disks::find_esp_partition(&x).ok())
+                .and_then(|x| x.path);
+            (mountable_partitions(disks::list_disks(0), system), esp)
+        },
+        move |s, (infos, esp)| show_mount_table(s, config, infos, esp, next),
+    );
+}
+
+fn show_mount_table(
+    siv: &mut Cursive,
+    config: InstallConfig,
+    partitions: Vec<disks::PartitionInfo>,
+    esp: Option<PathBuf>,
+    next: fn(&mut Cursive, InstallConfig),
+) {
+    let Some(partition) = config.partition.as_deref() else {
+        show_msg(siv, tr!("Please specify a system partition."));
+        return;
+    };
+    let root = Rc::new(MountAssignment {
+        partition: partition.clone(),
+        mount_point: "/".to_string(),
+        fs_type: None,
+        format: !config.preserve_home.unwrap_or(false),
+        label: None,
+        options: None,
+        encrypt: config.encrypt.unwrap_or(false),
+    });
+    let mut infos = vec![disks::PartitionInfo {
+        partition: partition.clone(),
+        label: None,
+        mount_point: None,
+        os: None,
+        free: None,
+        used: None,
+        esp: false,
+        unusable: None,
+    }];
+    infos.extend(partitions);
+    // the assignments made before are shown again, the ESP is proposed the first time
+    let previous = config.mounts.as_deref();
+    let mut rows = infos
+        .iter()
+        .map(|info| {
+            previous
+                .and_then(|x| {
+                    x.iter()
+                        .find(|x| x.partition.path == info.partition.path)
+                        .cloned()
+                })
+                .unwrap_or_else(|| MountAssignment::new(info.partition.clone()))
+        })
+        .collect::<Vec<_>>();
+    rows[0] = root.as_ref().clone();
+    if previous.is_none() {
+        if let Some(row) = rows
+            .iter_mut()
+            .find(|x| esp.is_some() && x.partition.path == esp)
+        {
+            row.mount_point = ESP_MOUNT_POINT.to_string();
+        }
+    }
+    let table = Rc::new(MountTable { infos, esp });
+
+    let mut rows_view = LinearLayout::vertical();
+    for (i, row) in rows.iter().enumerate().skip(1) {
+        rows_view.add_child(edit_row(&table, &root, row, i));
+    }
+    if rows.len() == 1 {
+        rows_view.add_child(TextView::new(tr!(
+            "There is no other partition to be mounted."
+        )));
+    }
+
+    let mut view = LinearLayout::vertical()
+        .child(TextView::new(tr!(
+            "Assign mount points to the other partitions to use them in AOSC OS, leave the mount point empty to leave a partition alone. Partitions are only formatted if \"Format\" is checked, a label is given to the new filesystem. Press → to take the completion of a mount point."
+        )))
+        .child(DummyView {})
+        .child(root_row(&root))
+        .child(rows_view.scrollable().max_height(12))
+        .child(DummyView {})
+        .child(TextView::new("").with_name(MOUNT_ERRORS));
+    // the passphrase is asked for here in the advanced mode
+    if root.encrypt {
+        view.add_child(DummyView {});
+        view.add_child(encryption_options(&config));
+    }
+
+    let table_copy = table.clone();
+    let root_copy = root.clone();
+    let table_copy_2 = table.clone();
+    let root_copy_2 = root.clone();
+    let dialog = wrap_in_dialog(view, tr!("Mount Points"), Some(80))
+        .button(tr!("Continue"), move |s| {
+            let rows = read_rows(s, &table_copy, &root_copy);
+            let (errors, general) = validate(&rows, table_copy.esp.as_deref());
+            if let Some(error) = general.or_else(|| errors.into_iter().flatten().next()) {
+                show_msg(s, &error);
+                return;
+            }
+            let mut config = config.clone();
+            if root_copy.encrypt {
+                let Some((passphrase, discard)) = read_encryption_options(s) else {
+                    return;
+                };
+                config.encryption_passphrase = Some(passphrase);
+                config.encrypt_discard = Some(discard);
+            }
+            let assigned = rows
+                .into_iter()
+                .skip(1)
+                .filter(|x| !x.mount_point.is_empty())
+                .collect::<Vec<_>>();
+            config.mounts = (!assigned.is_empty()).then(|| Arc::new(assigned));
+            s.pop_layer();
+            next(s, config);
+        })
+        .button(tr!("Auto-assign"), move |s| {
+            let mut rows = read_rows(s, &table_copy_2, &root_copy_2);
+            auto_assign(&mut rows, &table_copy_2.infos, table_copy_2.esp.as_deref());
+            fill_rows(s, &rows);
+            refresh(s, &table_copy_2, &root_copy_2);
+        })
+        .button(tr!("Back"), |s| {
+            s.pop_layer();
+        });
+
+    siv.add_layer(help_on_f1(
+        help_button(dialog, Screen::Mounts).with_name(MOUNT_TABLE),
+        Screen::Mounts,
+    ));
+    refresh(siv, &table, &root);
+}
+
+#[test]
+fn test_mount_assignments() {
+    let partition = |path: &str, fs_type: Option<&str>, size: u64| disks::Partition {
+        path: Some(PathBuf::from(path)),
+        parent_path: Some(PathBuf::from("/dev/sda")),
+        fs_type: fs_type.map(|x| x.to_string()),
+        size,
+    };
+    let info = |partition: &disks::Partition, label: Option<&str>, os: Option<&str>| {
+        disks::PartitionInfo {
+            partition: partition.clone(),
+            label: label.map(|x| x.to_string()),
+            mount_point: None,
+            os: os.map(|x| x.to_string()),
+            free: None,
+            used: None,
+            esp: false,
+            unusable: None,
+        }
+    };
+    let gib = 1024 * 1024 * 1024;
+    let partitions = [
+        partition("/dev/sda1", Some("fat32"), gib / 2),
+        partition("/dev/sda2", Some("ext4"), 100 * gib),
+        partition("/dev/sda3", Some("ntfs"), 200 * gib),
+        partition("/dev/sda4", Some("xfs"), 50 * gib),
+        partition("/dev/sda5", Some("ext4"), 20 * gib),
+        partition("/dev/sda6", None, 30 * gib),
+    ];
+    let infos = [
+        info(&partitions[0], None, None),
+        info(&partitions[1], None, None),
+        info(&partitions[2], None, Some("Windows")),
+        info(&partitions[3], None, None),
+        info(&partitions[4], Some("HOME"), None),
+        info(&partitions[5], None, None),
+    ];
+    let esp = Path::new("/dev/sda1");
+
+    let mut rows = partitions
+        .iter()
+        .cloned()
+        .map(MountAssignment::new)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        validate(&rows, Some(esp)).1.as_deref(),
+        Some(tr!("No partition is mounted at /, the root of the system."))
+    );
+    auto_assign(&mut rows, &infos, Some(esp));
+    let mount_points = rows
+        .iter()
+        .map(|x| x.mount_point.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(mount_points, ["/efi", "", "", "", "/home", "/"]);
+    assert!(rows[5].format);
+    assert!(!rows[4].format);
+    let (errors, general) = validate(&rows, Some(esp));
+    assert!(errors.iter().all(|x| x.is_none()));
+    assert!(general.is_none());
+
+    // the largest Linux filesystem without an OS, if none is labelled
+    let mut unlabelled = rows.clone();
+    unlabelled[4].mount_point.clear();
+    let mut infos_unlabelled = infos.clone();
+    infos_unlabelled[4].label = None;
+    auto_assign(&mut unlabelled, &infos_unlabelled, Some(esp));
+    assert_eq!(unlabelled[1].mount_point, "/home");
+
+    let check = |rows: &[MountAssignment], index: usize| check_row(rows, index, Some(esp));
+    let mut invalid = rows.clone();
+    invalid[1].mount_point = "/home".to_string();
+    assert!(check(&invalid, 1).is_some());
+    invalid[1].mount_point = "var".to_string();
+    assert!(check(&invalid, 1).is_some());
+    invalid[1].mount_point = "/usr".to_string();
+    assert!(check(&invalid, 1).is_some());
+    invalid[1].mount_point = "/var".to_string();
+    assert!(check(&invalid, 1).is_none());
+    invalid[1].label = Some("var".to_string());
+    assert!(check(&invalid, 1).is_some());
+    invalid[1].format = true;
+    assert!(check(&invalid, 1).is_none());
+    invalid[1].fs_type = Some("xfs".to_string());
+    invalid[1].label = Some("a-very-long-label".to_string());
+    assert!(check(&invalid, 1).is_some());
+    // the ESP must be FAT, and only mounted at /efi
+    invalid[3].mount_point = "/efi".to_string();
+    invalid[0].mount_point = "/boot".to_string();
+    assert!(check(&invalid, 3).is_some());
+    assert!(check(&invalid, 0).is_some());
+    // NTFS can not be mounted unless formatted
+    invalid[2].mount_point = "/srv".to_string();
+    assert!(check(&invalid, 2).is_some());
+    invalid[2].format = true;
+    invalid[2].fs_type = Some("ext4".to_string());
+    assert!(check(&invalid, 2).is_none());
+    // other partitions are only encrypted along with the system partition, when formatted
+    invalid[2].encrypt = true;
+    assert!(check(&invalid, 2).is_some());
+    invalid[5].encrypt = true;
+    assert!(check(&invalid, 2).is_none());
+    assert_eq!(
+        invalid[2].mounted_partition().path,
+        Some(PathBuf::from("/dev/mapper/aosc-data-srv"))
+    );
+    invalid[0].mount_point.clear();
+    invalid[2].mount_point = "/boot".to_string();
+    assert!(check(&invalid, 2).is_some());
+    invalid[5].format = false;
+    assert!(validate(&invalid, Some(esp)).1.is_some());
+
+    assert_eq!(complete("/h"), Some("/home"));
+    assert_eq!(complete("/var"), Some("/var/log"));
+    assert_eq!(complete("/home"), None);
+    assert_eq!(complete(""), None);
+}
@@ -0,0 +1,563 @@
+use anyhow::Result;
+use cursive::{
+    traits::*,
+    utils::markup::StyledString,
+    views::{
+        Dialog, DummyView, EditView, LayerPosition, LinearLayout, ListView, SelectView,
+        TextContent, TextView,
+    },
+    CbSink, Cursive,
+};
+use log::warn;
+use std::{
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    network::{self, CheckStage, Connectivity},
+    tr,
+    wifi::{self, Security, WifiBackend, WifiNetwork},
+};
+
+use super::{
+    accessible::dropdown,
+    step_indicator::{self, Stage},
+    theme::{emphasis, Emphasis},
+    tui::{show_blocking_message, show_msg, wrap_in_dialog},
+};
+
+const NETWORK_CHECK: &str = "network_check";
+const PROXY_URL: &str = "proxy_url";
+const LOCAL_RELEASE: &str = "local_release";
+/// How often the network is checked again while the check is shown
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const WIFI_INTERFACE: &str = "wifi_interface";
+const WIFI_NETWORKS: &str = "wifi_networks";
+const WIFI_PASSPHRASE: &str = "wifi_passphrase";
+const WIFI_HIDDEN_SSID: &str = "wifi_hidden_ssid";
+/// How many times the servers are tried after connecting, as getting an address takes a while
+const CONNECTIVITY_RETRIES: u32 = 5;
+
+/// Every network check shown gets a new number, the checks of a closed one stop when it changes
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Goes on with the installation, from where the network was needed
+pub(super) type Retry = Arc<dyn Fn(&mut Cursive) + Send + Sync>;
+
+/// What has been found for joining a Wi-Fi network, gathered in the background
+struct WifiState {
+    backend: Option<WifiBackend>,
+    interfaces: Vec<String>,
+    networks: Result<Vec<WifiNetwork>, String>,
+}
+
+/// Find the Wi-Fi interfaces and scan for the networks on the first one
+fn probe_wifi() -> WifiState {
+    let backend = wifi::detect_wifi_backend();
+    let interfaces = wifi::list_wifi_interfaces();
+    let networks = match (backend, interfaces.first()) {
+        (Some(backend), Some(interface)) => {
+            wifi::scan_wifi(backend, interface).map_err(|e| e.to_string())
+        }
+        _ => Ok(vec![]),
+    };
+
+    WifiState {
+        backend,
+        interfaces,
+        networks,
+    }
+}
+
+fn network_label(network: &WifiNetwork) -> String {
+    let security = match network.security {
+        Security::Open => tr!("Open"),
+        Security::Personal => tr!("Secured"),
+        Security::Enterprise => tr!("Enterprise (unsupported)"),
+    };
+
+    format!("{:3}%  {}  ({})", network.signal, network.ssid, security)
+}
+
+fn fill_networks(
+    view: &mut SelectView<WifiNetwork>,
+    status: &TextContent,
+    networks: Result<Vec<WifiNetwork>, String>,
+) {
+    view.clear();
+    let networks = match networks {
+        Ok(networks) => networks,
+        Err(e) => {
+            status.set_content(tr!("Failed to scan for Wi-Fi networks: {}", e));
+            return;
+        }
+    };
+    status.set_content(if networks.is_empty() {
+        tr!("No Wi-Fi network found, please scan again or enter a hidden network.").to_string()
+    } else {
+        tr!("Please select the Wi-Fi network to connect to:").to_string()
+    });
+    for network in networks {
+        view.add_item(network_label(&network), network);
+    }
+}
+
+fn selected_interface(siv: &mut Cursive) -> Option<String> {
+    siv.call_on_name(WIFI_INTERFACE, |view: &mut SelectView<String>| {
+        view.selection()
+    })
+    .flatten()
+    .map(|x| x.to_string())
+}
+
+fn rescan(siv: &mut Cursive, backend: WifiBackend, status: TextContent) {
+    let Some(interface) = selected_interface(siv) else {
+        return;
+    };
+    status.set_content(tr!("Scanning for Wi-Fi networks on {} ...", interface));
+    siv.call_on_name(WIFI_NETWORKS, |view: &mut SelectView<WifiNetwork>| {
+        view.clear();
+    });
+    let cb_sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let networks = wifi::scan_wifi(backend, &interface).map_err(|e| e.to_string());
+        cb_sink
+            .send(Box::new(move |s| {
+                s.call_on_name(WIFI_NETWORKS, |view: &mut SelectView<WifiNetwork>| {
+                    fill_networks(view, &status, networks)
+                });
+            }))
+            .unwrap();
+    });
+}
+
+fn wait_for_connectivity() -> Result<()> {
+    let mut result = network::check_connectivity().result();
+    for _ in 1..CONNECTIVITY_RETRIES {
+        if result.is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_secs(2));
+        result = network::check_connectivity().result();
+    }
+
+    result
+}
+
+/// Connect to the network in the background, and go on with `retry` once the servers
+/// can be reached
+fn connect(
+    siv: &mut Cursive,
+    backend: WifiBackend,
+    ssid: String,
+    passphrase: Option<String>,
+    hidden: bool,
+    retry: Retry,
+) {
+    let Some(interface) = selected_interface(siv) else {
+        return;
+    };
+    show_blocking_message(siv, &tr!("Connecting to {} ...", ssid));
+    let cb_sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let result = wifi::connect_wifi(backend, &interface, &ssid, passphrase.as_deref(), hidden)
+            .map_err(|e| tr!("Failed to connect to {}: {}", ssid, e))
+            .and_then(|_| {
+                wait_for_connectivity().map_err(|e| {
+                    tr!(
+                        "Connected to {}, but the AOSC OS servers still can not be reached: {}",
+                        ssid,
+                        e
+                    )
+                })
+            });
+        cb_sink
+            .send(Box::new(move |s| {
+                s.pop_layer();
+                match result {
+                    Ok(()) => {
+                        // the Wi-Fi setup, the network check under it goes on
+                        s.pop_layer();
+                        retry(s);
+                    }
+                    Err(e) => {
+                        warn!("{}", e);
+                        show_msg(s, &e);
+                    }
+                }
+            }))
+            .unwrap();
+    });
+}
+
+fn ask_passphrase(siv: &mut Cursive, backend: WifiBackend, ssid: String, retry: Retry) {
+    siv.add_layer(
+        wrap_in_dialog(
+            LinearLayout::vertical()
+                .child(TextView::new(tr!(
+                    "Please enter the passphrase of {}:",
+                    ssid
+                )))
+                .child(EditView::new().secret().with_name(WIFI_PASSPHRASE)),
+            tr!("Wi-Fi Passphrase"),
+            None,
+        )
+        .button(tr!("Connect"), move |s| {
+            let passphrase = s
+                .call_on_name(WIFI_PASSPHRASE, |view: &mut EditView| view.get_content())
+                .unwrap();
+            if passphrase.is_empty() {
+                show_msg(s, tr!("Please enter the passphrase."));
+                return;
+            }
+            s.pop_layer();
+            connect(
+                s,
+                backend,
+                ssid.clone(),
+                Some(passphrase.to_string()),
+                false,
+                retry.clone(),
+            );
+        })
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+fn ask_hidden_network(siv: &mut Cursive, backend: WifiBackend, retry: Retry) {
+    siv.add_layer(
+        wrap_in_dialog(
+            LinearLayout::vertical()
+                .child(TextView::new(tr!(
+                    "Please enter the name (SSID) of the hidden network, and its passphrase if it is secured. WPA2-Enterprise (802.1X) networks are not supported."
+                )))
+                .child(DummyView {})
+                .child(
+                    ListView::new()
+                        .child(tr!("SSID"), EditView::new().with_name(WIFI_HIDDEN_SSID))
+                        .child(
+                            tr!("Passphrase"),
+                            EditView::new().secret().with_name(WIFI_PASSPHRASE),
+                        ),
+                ),
+            tr!("Hidden Network"),
+            None,
+        )
+        .button(tr!("Connect"), move |s| {
+            let ssid = s
+                .call_on_name(WIFI_HIDDEN_SSID, |view: &mut EditView| view.get_content())
+                .unwrap();
+            let passphrase = s
+                .call_on_name(WIFI_PASSPHRASE, |view: &mut EditView| view.get_content())
+                .unwrap();
+            if ssid.trim().is_empty() {
+                show_msg(s, tr!("Please enter the name of the network."));
+                return;
+            }
+            s.pop_layer();
+            connect(
+                s,
+                backend,
+                ssid.trim().to_string(),
+                (!passphrase.is_empty()).then(|| passphrase.to_string()),
+                true,
+                retry.clone(),
+            );
+        })
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+/// Look for the Wi-Fi interfaces and networks in the background, and show them to
+/// connect to one
+fn open_wifi_setup(siv: &mut Cursive, retry: Retry) {
+    show_blocking_message(siv, tr!("Looking for Wi-Fi networks ..."));
+    let cb_sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let state = probe_wifi();
+        cb_sink
+            .send(Box::new(move |s| {
+                s.pop_layer();
+                match (state.backend, state.interfaces.is_empty()) {
+                    (_, true) => show_msg(
+                        s,
+                        tr!("No wireless hardware has been found. Please connect an Ethernet cable instead."),
+                    ),
+                    (None, false) => show_msg(
+                        s,
+                        tr!("Wi-Fi can not be set up here, as neither NetworkManager nor iwd is running. Please connect an Ethernet cable instead."),
+                    ),
+                    (Some(backend), false) => s.add_layer(wifi_setup(backend, state, retry)),
+                }
+            }))
+            .unwrap();
+    });
+}
+
+/// Join a Wi-Fi network, `retry` goes on with the installation once connected
+fn wifi_setup(backend: WifiBackend, state: WifiState, retry: Retry) -> Dialog {
+    let status = TextContent::new("");
+    let interface_view = dropdown(SelectView::new())
+        .with_all_str(state.interfaces)
+        .on_submit({
+            let status = status.clone();
+            move |s, _: &String| rescan(s, backend, status.clone())
+        })
+        .with_name(WIFI_INTERFACE);
+    let mut network_view = SelectView::new();
+    fill_networks(&mut network_view, &status, state.networks);
+    let network_view = network_view
+        .on_submit({
+            let retry = retry.clone();
+            move |s, network: &WifiNetwork| match network.security {
+                Security::Enterprise => show_msg(
+                    s,
+                    &tr!(
+                        "{} is a WPA2-Enterprise (802.1X) network, which Installer does not support. Please connect to another network, or use an Ethernet cable.",
+                        network.ssid
+                    ),
+                ),
+                Security::Open => connect(
+                    s,
+                    backend,
+                    network.ssid.clone(),
+                    None,
+                    false,
+                    retry.clone(),
+                ),
+                Security::Personal => {
+                    ask_passphrase(s, backend, network.ssid.clone(), retry.clone())
+                }
+            }
+        })
+        .with_name(WIFI_NETWORKS);
+
+    wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(ListView::new().child(tr!("Wi-Fi Interface"), interface_view))
+            .child(DummyView {})
+            .child(TextView::new_with_content(status.clone()))
+            .child(network_view.scrollable().fixed_height(10)),
+        tr!("Wi-Fi Setup"),
+        None,
+    )
+    .button(tr!("Scan Again"), {
+        let status = status.clone();
+        move |s| rescan(s, backend, status.clone())
+    })
+    .button(tr!("Hidden Network"), move |s| {
+        ask_hidden_network(s, backend, retry.clone())
+    })
+    .button(tr!("Close"), |s| {
+        s.pop_layer();
+    })
+}
+
+fn stage_name(stage: CheckStage) -> &'static str {
+    match stage {
+        CheckStage::Interface => tr!("Network interface"),
+        CheckStage::Dns => tr!("DNS lookup"),
+        CheckStage::Tcp => tr!("TCP connection"),
+        CheckStage::Tls => tr!("TLS handshake"),
+        CheckStage::Http => tr!("HTTP request"),
+    }
+}
+
+/// What may be done about the stage failing
+fn stage_hint(stage: CheckStage) -> &'static str {
+    match stage {
+        CheckStage::Interface => tr!("Please plug in an Ethernet cable, or set up Wi-Fi."),
+        CheckStage::Dns => tr!("The server name can not be resolved. Please check the DNS settings of the network or the address of the proxy."),
+        CheckStage::Tcp => tr!("The server can not be connected to. The network may be blocking it, in which case a proxy may be needed."),
+        CheckStage::Tls => tr!("The secure connection has failed. Please check the date and time of this device, or whether the network intercepts the connection."),
+        CheckStage::Http => tr!("The server has returned an error, it may be under maintenance. Please retry later."),
+    }
+}
+
+fn render_connectivity(connectivity: &Connectivity) -> StyledString {
+    let mut text = StyledString::new();
+    for stage in CheckStage::ALL {
+        let result = connectivity.stages.iter().find(|x| x.0 == *stage);
+        match result.map(|x| &x.1) {
+            Some(Ok(())) => {
+                text.append_styled("[ OK ] ", emphasis(Emphasis::Good));
+                text.append_plain(format!("{}\n", stage_name(*stage)));
+            }
+            Some(Err(e)) => {
+                text.append_styled("[FAIL] ", emphasis(Emphasis::Danger));
+                text.append_plain(format!(
+                    "{}\n       {}\n       {}\n",
+                    stage_name(*stage),
+                    e,
+                    stage_hint(*stage)
+                ));
+            }
+            None => text.append_plain(format!("[ -- ] {}\n", stage_name(*stage))),
+        }
+    }
+    if let Some(proxy) = network::proxy() {
+        text.append_plain(format!(
+            "\n{}",
+            tr!("Connecting through the proxy {}.", proxy)
+        ));
+    }
+
+    text
+}
+
+/// Show the new results of the check, and go on if the network works now, unless
+/// something else (e.g., the Wi-Fi setup) is open on top of it
+fn update_check(siv: &mut Cursive, generation: u64, connectivity: Connectivity, retry: &Retry) {
+    let screen = siv.screen_mut();
+    let top = screen.len().checked_sub(1).map(LayerPosition::FromBack);
+    let position = screen.find_layer_from_name(NETWORK_CHECK);
+    if position.is_none() {
+        GENERATION
+            .compare_exchange(
+                generation,
+                generation + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .ok();
+        return;
+    }
+    let text = render_connectivity(&connectivity);
+    siv.call_on_name(NETWORK_CHECK, |view: &mut TextView| view.set_content(text));
+    if connectivity.result().is_ok() && position == top {
+        GENERATION.fetch_add(1, Ordering::SeqCst);
+        retry(siv);
+    }
+}
+
+fn watch_connectivity(cb_sink: CbSink, generation: u64, retry: Retry) {
+    thread::spawn(move || loop {
+        thread::sleep(CHECK_INTERVAL);
+        if GENERATION.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let connectivity = network::check_connectivity();
+        let retry = retry.clone();
+        if cb_sink
+            .send(Box::new(move |s| {
+                update_check(s, generation, connectivity, &retry)
+            }))
+            .is_err()
+        {
+            return;
+        }
+    });
+}
+
+fn ask_proxy(siv: &mut Cursive) {
+    siv.add_layer(
+        wrap_in_dialog(
+            LinearLayout::vertical()
+                .child(TextView::new(tr!(
+                    "Please enter the address of the HTTP proxy, e.g., http://proxy.example.com:8080, or leave it empty to connect directly:"
+                )))
+                .child(
+                    EditView::new()
+                        .content(network::proxy().unwrap_or_default())
+                        .with_name(PROXY_URL),
+                ),
+            tr!("Proxy"),
+            None,
+        )
+        .button(tr!("OK"), |s| {
+            let url = s
+                .call_on_name(PROXY_URL, |view: &mut EditView| view.get_content())
+                .unwrap();
+            let url = url.trim();
+            if let Err(e) = network::set_proxy((!url.is_empty()).then_some(url)) {
+                show_msg(s, &tr!("Invalid proxy address: {}", e));
+                return;
+            }
+            s.pop_layer();
+        })
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+/// Ask for a copy of the system release on this device, and go on offline with it
+fn ask_local_release(siv: &mut Cursive, offline: Rc<dyn Fn(&mut Cursive, Option<PathBuf>)>) {
+    siv.add_layer(
+        wrap_in_dialog(
+            LinearLayout::vertical()
+                .child(TextView::new(tr!(
+                    "Installer will use the release information kept from the last time it was online. To install without downloading, please enter the path to a copy of the system release (e.g., on a USB drive) matching the variant to be selected. Otherwise, the system release will be downloaded once the installation starts."
+                )))
+                .child(DummyView {})
+                .child(EditView::new().with_name(LOCAL_RELEASE)),
+            tr!("Continue Offline"),
+            None,
+        )
+        .button(tr!("OK"), move |s| {
+            let path = s
+                .call_on_name(LOCAL_RELEASE, |view: &mut EditView| view.get_content())
+                .unwrap();
+            let path = path.trim();
+            let path = (!path.is_empty()).then(|| PathBuf::from(path));
+            if let Some(path) = path.as_ref().filter(|x| !x.is_file()) {
+                show_msg(s, &tr!("{} is not a file.", path.display()));
+                return;
+            }
+            s.pop_layer();
+            offline(s, path);
+        })
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+/// Show why the AOSC OS servers can not be reached, checking again every few seconds
+/// and going on with `retry` once they can, or `offline` with the release information
+/// kept from before and a local copy of the system release, if any
+pub(super) fn network_check(
+    connectivity: Connectivity,
+    cb_sink: CbSink,
+    retry: Retry,
+    offline: Rc<dyn Fn(&mut Cursive, Option<PathBuf>)>,
+) -> Dialog {
+    step_indicator::set_stage(Stage::Network);
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    watch_connectivity(cb_sink, generation, retry.clone());
+
+    wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(TextView::new(tr!(
+                "Installer can not reach the AOSC OS servers, which are needed for downloading AOSC OS. The network is checked again every few seconds, so plugging in an Ethernet cable is enough:"
+            )))
+            .child(DummyView {})
+            .child(TextView::new(render_connectivity(&connectivity)).with_name(NETWORK_CHECK)),
+        tr!("Network Check"),
+        Some(72),
+    )
+    .button(tr!("Retry"), {
+        let retry = retry.clone();
+        move |s| {
+            GENERATION.fetch_add(1, Ordering::SeqCst);
+            retry(s)
+        }
+    })
+    .button(tr!("Wi-Fi Setup"), move |s| open_wifi_setup(s, retry.clone()))
+    .button(tr!("Proxy"), ask_proxy)
+    .button(tr!("Continue Offline"), move |s| {
+        ask_local_release(s, offline.clone())
+    })
+    .button(tr!("Exit"), |s| s.quit())
+}
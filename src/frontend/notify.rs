@@ -0,0 +1,95 @@
+use cursive::{
+    traits::*,
+    view::{Offset, Position},
+    views::{Layer, Panel, TextView},
+    Cursive,
+};
+use std::{cell::RefCell, io::Write};
+
+use crate::tr;
+
+use super::{
+    games::{close_game, is_playing},
+    log_pane::{close_log_pane, is_log_pane_open},
+    shortcuts::Shortcuts,
+};
+
+const NOTIFY_BANNER: &str = "notify_banner";
+const NOTIFY_TEXT: &str = "notify_text";
+
+/// The ASCII bell, which makes the terminal beep or flash
+const BELL: &[u8] = b"\x07";
+
+type Pending = Box<dyn FnOnce(&mut Cursive)>;
+
+thread_local! {
+    /// What to show once the user is back, in the order of the notifications
+    static PENDING: RefCell<Vec<Pending>> = RefCell::default();
+}
+
+/// Whether the user is passing the time with a game or the log pane instead of watching
+/// the installation
+fn is_distracted(siv: &mut Cursive) -> bool {
+    is_playing(siv) || is_log_pane_open(siv)
+}
+
+/// Tell the user that something needs their attention (e.g., the installation has
+/// finished), and then show it. If a game or the log pane is on top, the terminal bell rings
+/// and a banner is shown over it, the game and the log pane are closed and `then` is run
+/// once a key is pressed.
+pub(super) fn notify<F>(siv: &mut Cursive, message: &str, then: F)
+where
+    F: FnOnce(&mut Cursive) + 'static,
+{
+    let has_banner = siv
+        .screen_mut()
+        .find_layer_from_name(NOTIFY_BANNER)
+        .is_some();
+    if !has_banner && !is_distracted(siv) {
+        then(siv);
+        return;
+    }
+
+    PENDING.with(|x| x.borrow_mut().push(Box::new(then)));
+    ring_bell();
+    let text = tr!("{}\n\nPress any key to continue.", message);
+    if has_banner {
+        // the latest news is shown, everything is caught up on with the next key
+        siv.call_on_name(NOTIFY_TEXT, |view: &mut TextView| view.set_content(text));
+        return;
+    }
+
+    let banner = Shortcuts::new(Layer::new(
+        Panel::new(TextView::new(text).with_name(NOTIFY_TEXT)).title(tr!("AOSC OS Installer")),
+    ))
+    .any_key("Continue", come_back);
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Absolute(1)),
+        banner.with_name(NOTIFY_BANNER),
+    );
+}
+
+/// Close the banner and whatever the user was distracted by, then show what they have
+/// been notified of
+fn come_back(siv: &mut Cursive) {
+    while siv
+        .screen_mut()
+        .find_layer_from_name(NOTIFY_BANNER)
+        .is_some()
+    {
+        siv.pop_layer();
+    }
+    close_game(siv);
+    close_log_pane(siv);
+
+    let pending = PENDING.with(|x| std::mem::take(&mut *x.borrow_mut()));
+    for then in pending {
+        then(siv);
+    }
+}
+
+fn ring_bell() {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(BELL).ok();
+    stdout.flush().ok();
+}
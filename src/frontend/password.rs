@@ -0,0 +1,461 @@
+use cursive::{
+    theme::Style,
+    traits::*,
+    utils::markup::StyledString,
+    views::{Checkbox, EditView, LinearLayout, ListView, TextView},
+    Cursive,
+};
+use std::rc::Rc;
+
+use crate::tr;
+
+use super::theme::{emphasis, Emphasis};
+
+/// Passwords shorter than this are weak, whatever they consist of
+const MIN_LENGTH: usize = 8;
+/// Passwords this long mixing several kinds of characters are strong
+const STRONG_LENGTH: usize = 12;
+/// Passphrases this long are strong even with words only
+const PASSPHRASE_LENGTH: usize = 20;
+/// Disk encryption passphrases shorter than this are weak, they have to withstand offline
+/// attacks
+const MIN_PASSPHRASE_LENGTH: usize = 12;
+/// Disk encryption passphrases this long mixing several kinds of characters are strong
+const STRONG_PASSPHRASE_LENGTH: usize = 16;
+/// ASCII symbols, which are on other keys in most layouts than on US-QWERTY
+const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+/// The symbols on the same keys as on US-QWERTY in most European layouts
+const COMMON_SYMBOLS: &str = "!$%,.";
+/// The letters and digits typed on other keys than on US-QWERTY with the keymaps, the
+/// symbols are also on other keys unless the keymap is listed in `SYMBOLS_AS_US`
+const MOVED_KEYS: &[(&str, &str)] = &[
+    ("de", "yzYZ"),
+    ("ch", "yzYZ"),
+    ("fr", "aqwzmAQWZM0123456789"),
+    ("be", "aqwzmAQWZM0123456789"),
+    (
+        "us-dvorak",
+        "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ",
+    ),
+    ("us-colemak", "defgijklnoprstuyDEFGIJKLNOPRSTUY"),
+];
+/// The keymaps with all symbols on the same keys as on US-QWERTY, and the symbols of those
+/// with only a few moved
+const SYMBOLS_AS_US: &[(&str, &str)] = &[
+    ("us", SYMBOLS),
+    ("gb", "!$%&'()*+,-./:;<=>?[]^_`{}"),
+    ("de", COMMON_SYMBOLS),
+    ("ch", COMMON_SYMBOLS),
+    ("es", COMMON_SYMBOLS),
+    ("it", COMMON_SYMBOLS),
+    ("pt", COMMON_SYMBOLS),
+    ("br", COMMON_SYMBOLS),
+    ("latam", COMMON_SYMBOLS),
+];
+/// The most frequently used passwords, one per line in lower case
+const COMMON_PASSWORDS: &str = include_str!("../../res/common-passwords.txt");
+/// `KDGKBLED` and `K_CAPSLOCK` from `linux/kd.h`
+const KDGKBLED: u32 = 0x4B64;
+const K_CAPSLOCK: libc::c_char = 0x04;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Strength {
+    Weak,
+    Fair,
+    Strong,
+}
+
+impl Strength {
+    fn name(&self) -> &'static str {
+        match self {
+            Strength::Weak => tr!("Weak"),
+            Strength::Fair => tr!("Fair"),
+            Strength::Strong => tr!("Strong"),
+        }
+    }
+
+    fn style(&self) -> Style {
+        match self {
+            Strength::Weak => emphasis(Emphasis::Danger),
+            Strength::Fair => emphasis(Emphasis::Warning),
+            Strength::Strong => emphasis(Emphasis::Good),
+        }
+    }
+}
+
+/// How strong the password is and why it is not, `related` are the words it should not be
+/// the same as (e.g., the username and hostname)
+pub(super) fn strength(password: &str, related: &[&str]) -> (Strength, Option<String>) {
+    let lower = password.to_lowercase();
+    if related
+        .iter()
+        .any(|x| !x.is_empty() && x.to_lowercase() == lower)
+    {
+        return (
+            Strength::Weak,
+            Some(tr!("The password is the same as the username or hostname.").to_string()),
+        );
+    }
+    if COMMON_PASSWORDS.lines().any(|x| x == lower) {
+        return (
+            Strength::Weak,
+            Some(tr!("The password is a commonly used one.").to_string()),
+        );
+    }
+    let length = password.chars().count();
+    if length < MIN_LENGTH {
+        return (
+            Strength::Weak,
+            Some(tr!(
+                "The password is shorter than {} characters.",
+                MIN_LENGTH
+            )),
+        );
+    }
+
+    let classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_numeric()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|x| *x)
+    .count();
+    if (length >= STRONG_LENGTH && classes >= 3) || length >= PASSPHRASE_LENGTH {
+        (Strength::Strong, None)
+    } else if classes >= 2 || length >= STRONG_LENGTH {
+        (
+            Strength::Fair,
+            Some(
+                tr!("A longer password mixing letters, digits and symbols is stronger.")
+                    .to_string(),
+            ),
+        )
+    } else {
+        (
+            Strength::Weak,
+            Some(tr!("The password consists of only one kind of character.").to_string()),
+        )
+    }
+}
+
+/// How strong the disk encryption passphrase is, which is mostly decided by its length
+pub(super) fn passphrase_strength(passphrase: &str) -> (Strength, Option<String>) {
+    let lower = passphrase.to_lowercase();
+    if COMMON_PASSWORDS.lines().any(|x| x == lower) {
+        return (
+            Strength::Weak,
+            Some(tr!("The passphrase is a commonly used one.").to_string()),
+        );
+    }
+    let length = passphrase.chars().count();
+    if length < MIN_PASSPHRASE_LENGTH {
+        return (
+            Strength::Weak,
+            Some(tr!(
+                "The passphrase is shorter than {} characters.",
+                MIN_PASSPHRASE_LENGTH
+            )),
+        );
+    }
+
+    let classes = [
+        passphrase.chars().any(|c| c.is_lowercase()),
+        passphrase.chars().any(|c| c.is_uppercase()),
+        passphrase.chars().any(|c| c.is_numeric()),
+        passphrase.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .into_iter()
+    .filter(|x| *x)
+    .count();
+    if length >= PASSPHRASE_LENGTH || (length >= STRONG_PASSPHRASE_LENGTH && classes >= 3) {
+        (Strength::Strong, None)
+    } else {
+        (
+            Strength::Fair,
+            Some(tr!("A longer passphrase, e.g. of several words, is stronger.").to_string()),
+        )
+    }
+}
+
+/// The characters of the passphrase typed on other keys with `keymap` than with US-QWERTY,
+/// which the boot prompt may fall back to, and those not on US-QWERTY at all
+pub(super) fn layout_mismatches(passphrase: &str, keymap: &str) -> (Vec<char>, Vec<char>) {
+    let layout = keymap.split('-').next().unwrap_or_default();
+    let find = |table: &[(&str, &'static str)]| {
+        table
+            .iter()
+            .find(|(x, _)| *x == keymap)
+            .or_else(|| table.iter().find(|(x, _)| *x == layout))
+            .map(|(_, chars)| *chars)
+    };
+    let moved = find(MOVED_KEYS).unwrap_or_default();
+    // the variants may move the symbols (e.g. with dead keys)
+    let same_symbols = SYMBOLS_AS_US
+        .iter()
+        .find(|(x, _)| *x == keymap)
+        .map(|(_, chars)| *chars)
+        .unwrap_or_default();
+
+    let mut other_keys = vec![];
+    let mut not_us = vec![];
+    for c in passphrase.chars() {
+        if !c.is_ascii() || c.is_ascii_control() {
+            if !not_us.contains(&c) {
+                not_us.push(c);
+            }
+        } else if (moved.contains(c) || (SYMBOLS.contains(c) && !same_symbols.contains(c)))
+            && !other_keys.contains(&c)
+        {
+            other_keys.push(c);
+        }
+    }
+
+    (other_keys, not_us)
+}
+
+/// Whether Caps Lock is on, only known on the Linux console
+fn caps_lock() -> bool {
+    let mut flags: libc::c_char = 0;
+    let result = unsafe { libc::ioctl(libc::STDIN_FILENO, KDGKBLED as _, &mut flags) };
+
+    result == 0 && flags & K_CAPSLOCK != 0
+}
+
+fn confirm_name(name: &str) -> String {
+    format!("{name}_confirm")
+}
+
+fn status_name(name: &str) -> String {
+    format!("{name}_status")
+}
+
+fn content(siv: &mut Cursive, name: &str) -> String {
+    siv.call_on_name(name, |view: &mut EditView| view.get_content().to_string())
+        .unwrap_or_default()
+}
+
+/// The line under the entries telling the strength, whether the passwords match, and
+/// whether Caps Lock is on
+fn status(password: &str, confirm: &str, related: &[&str], caps_lock: bool) -> StyledString {
+    let mut text = StyledString::new();
+    if !password.is_empty() {
+        let (strength, hint) = strength(password, related);
+        text.append_plain(tr!("Strength: "));
+        text.append_styled(strength.name(), strength.style());
+        if let Some(hint) = hint {
+            text.append_plain(format!("\n{hint}"));
+        }
+    }
+    if !confirm.is_empty() && confirm != password {
+        text.append_plain("\n");
+        text.append_styled(
+            tr!("The passwords do not match."),
+            emphasis(Emphasis::Danger),
+        );
+    }
+    if caps_lock {
+        text.append_plain(format!("\n{}", tr!("Caps Lock is on.")));
+    }
+
+    text
+}
+
+/// The line under the passphrase entries, like that of the passwords, also warning about
+/// the characters that may be typed differently at the boot prompt with `keymap`
+fn passphrase_status(
+    passphrase: &str,
+    confirm: &str,
+    keymap: &str,
+    caps_lock: bool,
+) -> StyledString {
+    let mut text = StyledString::new();
+    if !passphrase.is_empty() {
+        let (strength, hint) = passphrase_strength(passphrase);
+        text.append_plain(tr!("Strength: "));
+        text.append_styled(strength.name(), strength.style());
+        if let Some(hint) = hint {
+            text.append_plain(format!("\n{hint}"));
+        }
+    }
+    let (other_keys, not_us) = layout_mismatches(passphrase, keymap);
+    if !other_keys.is_empty() {
+        text.append_plain("\n");
+        text.append_styled(
+            tr!(
+                "{} are on other keys with the {} layout than with US-QWERTY, which the boot prompt may fall back to.",
+                other_keys.iter().collect::<String>(),
+                keymap
+            ),
+            emphasis(Emphasis::Warning),
+        );
+    }
+    if !not_us.is_empty() {
+        text.append_plain("\n");
+        text.append_styled(
+            tr!(
+                "{} can not be typed with US-QWERTY, which the boot prompt may fall back to.",
+                not_us.iter().collect::<String>()
+            ),
+            emphasis(Emphasis::Warning),
+        );
+    }
+    if !confirm.is_empty() && confirm != passphrase {
+        text.append_plain("\n");
+        text.append_styled(
+            tr!("The passphrases do not match."),
+            emphasis(Emphasis::Danger),
+        );
+    }
+    if caps_lock {
+        text.append_plain(format!("\n{}", tr!("Caps Lock is on.")));
+    }
+
+    text
+}
+
+/// Add the entries of a password (named `name`) and its confirmation to `list`, with a
+/// line of feedback under them, `related` gives the words the password should not be, and
+/// `on_edit` is called after either entry changes
+pub(super) fn add_password_entries(
+    list: ListView,
+    name: &'static str,
+    labels: (&str, &str),
+    related: Rc<dyn Fn(&mut Cursive) -> Vec<String>>,
+    on_edit: Rc<dyn Fn(&mut Cursive)>,
+) -> ListView {
+    let update = Rc::new(move |s: &mut Cursive| {
+        let related = related(s);
+        let related = related.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        let password = content(s, name);
+        let confirm = content(s, &confirm_name(name));
+        let text = status(&password, &confirm, &related, caps_lock());
+        s.call_on_name(&status_name(name), |view: &mut TextView| {
+            view.set_content(text)
+        });
+        on_edit(s);
+    });
+    let update_clone = Rc::clone(&update);
+
+    list.child(
+        labels.0,
+        EditView::new()
+            .secret()
+            .on_edit(move |s, _, _| update(s))
+            .with_name(name)
+            .min_width(20),
+    )
+    .child(
+        labels.1,
+        EditView::new()
+            .secret()
+            .on_edit(move |s, _, _| update_clone(s))
+            .with_name(confirm_name(name))
+            .min_width(20),
+    )
+    .child("", TextView::empty().with_name(status_name(name)))
+}
+
+/// Add the entries of a disk encryption passphrase (named `name`) and its confirmation to
+/// `list` like `add_password_entries`, with a toggle to show the passphrase, `keymap` is
+/// the layout it will be typed with on boot
+pub(super) fn add_passphrase_entries(
+    list: ListView,
+    name: &'static str,
+    keymap: String,
+) -> ListView {
+    let update = Rc::new(move |s: &mut Cursive| {
+        let passphrase = content(s, name);
+        let confirm = content(s, &confirm_name(name));
+        let text = passphrase_status(&passphrase, &confirm, &keymap, caps_lock());
+        s.call_on_name(&status_name(name), |view: &mut TextView| {
+            view.set_content(text)
+        });
+    });
+    let update_clone = Rc::clone(&update);
+
+    list.child(
+        tr!("Passphrase"),
+        EditView::new()
+            .secret()
+            .on_edit(move |s, _, _| update(s))
+            .with_name(name)
+            .min_width(20),
+    )
+    .child(
+        tr!("Confirm Passphrase"),
+        EditView::new()
+            .secret()
+            .on_edit(move |s, _, _| update_clone(s))
+            .with_name(confirm_name(name))
+            .min_width(20),
+    )
+    .child(
+        "",
+        LinearLayout::horizontal()
+            .child(Checkbox::new().on_change(move |s, checked| {
+                for entry in [name.to_string(), confirm_name(name)] {
+                    s.call_on_name(&entry, |view: &mut EditView| view.set_secret(!checked));
+                }
+            }))
+            .child(TextView::new(tr!(" Show passphrase"))),
+    )
+    .child("", TextView::empty().with_name(status_name(name)))
+}
+
+/// The password entered, if it is not empty and has been confirmed, or `Some(None)` if
+/// both entries have been left empty to keep the password entered before (with `keep`)
+pub(super) fn new_password(siv: &mut Cursive, name: &str, keep: bool) -> Option<Option<String>> {
+    let password = content(siv, name);
+    let confirm = content(siv, &confirm_name(name));
+    if keep && password.is_empty() && confirm.is_empty() {
+        return Some(None);
+    }
+
+    (!password.is_empty() && password == confirm).then_some(Some(password))
+}
+
+#[test]
+fn test_strength() {
+    assert_eq!(strength("aosc", &[]).0, Strength::Weak);
+    assert_eq!(strength("Password", &[]).0, Strength::Weak);
+    assert_eq!(strength("Aosc-PC", &["user", "aosc-pc"]).0, Strength::Weak);
+    assert_eq!(strength("zzxxccvvbb", &[]).0, Strength::Weak);
+    assert_eq!(strength("zzxxcc42", &[]).0, Strength::Fair);
+    assert_eq!(strength("Zzxx-cc42vvbb", &[]).0, Strength::Strong);
+    assert_eq!(
+        strength("correct horse battery staple", &[]).0,
+        Strength::Strong
+    );
+    assert!(COMMON_PASSWORDS
+        .lines()
+        .all(|x| !x.is_empty() && x.to_lowercase() == x));
+}
+
+#[test]
+fn test_passphrase() {
+    assert_eq!(passphrase_strength("Zzxx-cc42vv").0, Strength::Weak);
+    assert_eq!(passphrase_strength("zzxxccvvbbnn").0, Strength::Fair);
+    assert_eq!(passphrase_strength("Zzxx-cc42vvbbnnm").0, Strength::Strong);
+    assert_eq!(
+        passphrase_strength("correct horse battery staple").0,
+        Strength::Strong
+    );
+
+    let chars = |x: &str| x.chars().collect::<Vec<_>>();
+    assert_eq!(layout_mismatches("Zoey-42@ä", "us"), (vec![], chars("ä")));
+    assert_eq!(
+        layout_mismatches("Zoey-42@ä", "de"),
+        (chars("Zy-@"), chars("ä"))
+    );
+    assert_eq!(
+        layout_mismatches("Zoey-42@", "de-nodeadkeys"),
+        (chars("Zy-@"), vec![])
+    );
+    assert_eq!(layout_mismatches("zoe42", "fr"), (chars("z42"), vec![]));
+    assert_eq!(layout_mismatches("a,b.c!", "gb"), (vec![], vec![]));
+    assert_eq!(layout_mismatches("a\"b@", "gb"), (chars("\"@"), vec![]));
+    // all symbols are suspicious with the layouts not known
+    assert_eq!(layout_mismatches("ab!", "hu"), (chars("!"), vec![]));
+}
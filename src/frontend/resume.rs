@@ -0,0 +1,504 @@
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{disks, install, network, tr};
+
+use super::{
+    assigned_mounts, session, swap_partition, InstallConfig, FSTAB_STEP, STEP1, STEP2, STEP3,
+    STEP4, STEP5, STEP6, STEP7, STEP8, VERIFY_FILES,
+};
+
+const STATE_FILE: &str = "/run/deploykit/state.json";
+/// Bumped whenever the state file changes in a way older versions can not resume from
+const STATE_VERSION: u32 = 1;
+/// The names of the steps for `--from-step`
+const STEP_NAMES: &[(&str, &str)] = &[
+    ("partition", STEP1),
+    ("download", STEP2),
+    ("verify-release", STEP3),
+    ("unpack", STEP4),
+    ("verify-files", VERIFY_FILES),
+    ("fstab", FSTAB_STEP),
+    ("initramfs", STEP5),
+    ("bootloader", STEP6),
+    ("ssh-keys", STEP7),
+    ("finalise", STEP8),
+];
+/// The release is unpacked as it is downloaded, these steps are run again from downloading
+const UNPACK_STEPS: &[&str] = &[STEP2, STEP3, STEP4, VERIFY_FILES];
+
+static STATE_PATH: OnceCell<PathBuf> = OnceCell::new();
+
+/// The target as prepared by the first step, which the steps after it work on
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct Target {
+    /// The system partition (the unlocked one if encrypted), `None` when installing into
+    /// a directory
+    pub(super) partition: Option<disks::Partition>,
+    /// The LUKS header of the encrypted system partition
+    pub(super) luks_uuid: Option<String>,
+    pub(super) previous_install: Option<install::PreviousInstall>,
+    /// The EFI system partition found on the disk, mounted at /efi
+    pub(super) esp: Option<disks::Partition>,
+}
+
+/// A filesystem the installed system is on, identified by its UUID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Filesystem {
+    /// Where it is mounted, or `swap`
+    mount_point: String,
+    path: PathBuf,
+    uuid: String,
+}
+
+/// What `install --resume` continues from, updated as the installation goes
+#[derive(Serialize, Deserialize)]
+struct InstallState {
+    version: u32,
+    hardware: String,
+    /// The plan, with the passwords hashed
+    config: InstallConfig,
+    /// The steps of the installation in order, and those which have completed
+    steps: Vec<String>,
+    completed: Vec<String>,
+    target: Option<Target>,
+    filesystems: Vec<Filesystem>,
+    /// The SHA-256 checksum of the release tarball
+    release_sha256: Option<String>,
+}
+
+/// An interrupted installation to be continued
+pub(super) struct Resumption {
+    pub(super) config: InstallConfig,
+    /// The steps of the installation in order
+    pub(super) steps: Vec<&'static str>,
+    pub(super) completed: Vec<&'static str>,
+    /// The step to run first, the ones after it are run as well
+    pub(super) from: &'static str,
+    /// `None` when resuming from the first step
+    pub(super) target: Option<Target>,
+}
+
+impl Resumption {
+    /// Whether `step` is to be run again
+    pub(super) fn runs(&self, step: &str) -> bool {
+        position(&self.steps, step) >= position(&self.steps, self.from)
+    }
+}
+
+/// Keep the state in `path` instead of /run/deploykit/state.json
+pub(super) fn set_state_file(path: &Path) {
+    STATE_PATH.set(path.to_path_buf()).ok();
+}
+
+fn state_path() -> &'static Path {
+    STATE_PATH
+        .get()
+        .map_or(Path::new(STATE_FILE), |x| x.as_path())
+}
+
+/// The step named `name`, for `--from-step`
+pub(super) fn step_by_name(name: &str) -> Option<&'static str> {
+    STEP_NAMES
+        .iter()
+        .find(|(x, _)| *x == name)
+        .map(|(_, step)| *step)
+}
+
+pub(super) fn step_names() -> Vec<&'static str> {
+    STEP_NAMES.iter().map(|(x, _)| *x).collect()
+}
+
+/// The steps with their names, as (step, name) pairs
+pub(super) fn named_steps() -> Vec<(&'static str, &'static str)> {
+    STEP_NAMES
+        .iter()
+        .map(|(name, step)| (*step, *name))
+        .collect()
+}
+
+fn name_of(step: &str) -> &'static str {
+    STEP_NAMES
+        .iter()
+        .find(|(_, x)| *x == step)
+        .map_or("partition", |(name, _)| *name)
+}
+
+fn known_step(step: &str) -> Result<&'static str> {
+    STEP_NAMES
+        .iter()
+        .map(|(_, x)| *x)
+        .find(|x| *x == step)
+        .ok_or_else(|| anyhow!("The state file names an unknown step \"{}\".", step))
+}
+
+fn position(steps: &[&str], step: &str) -> usize {
+    steps.iter().position(|x| *x == step).unwrap_or(steps.len())
+}
+
+fn load_state() -> Result<InstallState> {
+    let path = state_path();
+    let data = fs::read(path).map_err(|e| {
+        anyhow!(tr!(
+            "Installer could not read the state of the interrupted installation from {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let state: InstallState = serde_json::from_slice(&data)?;
+    if state.version != STATE_VERSION {
+        return Err(anyhow!(tr!(
+            "{} has been written by another version of Installer, which can not be resumed.",
+            path.display()
+        )));
+    }
+
+    Ok(state)
+}
+
+fn save_state(state: &InstallState) -> Result<()> {
+    let path = state_path();
+    if let Some(dir) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec_pretty(state)?)?;
+
+    Ok(())
+}
+
+/// Update the state, failing to is not fatal, only the installation can not be resumed
+fn update_state(f: impl FnOnce(&mut InstallState)) {
+    let result = load_state().and_then(|mut state| {
+        f(&mut state);
+        save_state(&state)
+    });
+    if let Err(e) = result {
+        warn!(
+            "Failed to update the state for resuming the installation: {}",
+            e
+        );
+    }
+}
+
+/// Record the plan of a new installation, with none of its `steps` completed
+pub(super) fn start(config: &InstallConfig, steps: &[&'static str]) {
+    let result = config.exported(false).and_then(|config| {
+        save_state(&InstallState {
+            version: STATE_VERSION,
+            hardware: session::hardware_id(),
+            release_sha256: config.variant.as_ref().map(|x| x.sha256sum.clone()),
+            config,
+            steps: steps.iter().map(|x| x.to_string()).collect(),
+            completed: vec![],
+            target: None,
+            filesystems: vec![],
+        })
+    });
+    match result {
+        Ok(()) => info!("Installation state saved to {}", state_path().display()),
+        Err(e) => warn!(
+            "Failed to save the state for resuming the installation: {}",
+            e
+        ),
+    }
+}
+
+/// Record the target prepared by the first step, with the UUIDs of its filesystems
+pub(super) fn record_target(target: &Target, config: &InstallConfig) {
+    let mut partitions = vec![];
+    if let Some(partition) = target.partition.as_ref() {
+        partitions.push(("/".to_string(), partition.clone()));
+    }
+    if let Some(esp) = target.esp.as_ref() {
+        partitions.push(("/efi".to_string(), esp.clone()));
+    }
+    for mount in assigned_mounts(config)
+        .iter()
+        .filter(|x| !x.mount_point.is_empty())
+    {
+        partitions.push((mount.mount_point.clone(), mount.target_partition()));
+    }
+    if let Some(swap) = swap_partition(config) {
+        partitions.push(("swap".to_string(), swap.clone()));
+    }
+    let filesystems = partitions
+        .into_iter()
+        .filter_map(|(mount_point, partition)| {
+            let path = partition.path?;
+            match install::filesystem_uuid(&path) {
+                Ok(uuid) => Some(Filesystem {
+                    mount_point,
+                    path,
+                    uuid,
+                }),
+                Err(e) => {
+                    warn!("{}", e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    update_state(|state| {
+        state.target = Some(target.clone());
+        state.filesystems = filesystems;
+    });
+    record_done(&[STEP1]);
+}
+
+/// Record that `steps` have completed
+pub(super) fn record_done(steps: &[&str]) {
+    update_state(|state| {
+        for step in steps {
+            if state.steps.iter().any(|x| x == step) && !state.completed.iter().any(|x| x == step) {
+                state.completed.push(step.to_string());
+            }
+        }
+    });
+}
+
+/// Record that `steps` are run again, they have not completed until they succeed again
+pub(super) fn record_pending(steps: &[&str]) {
+    update_state(|state| state.completed.retain(|x| !steps.contains(&x.as_str())));
+}
+
+/// Remove the state once the installation has completed, or its target has been wiped
+pub(super) fn discard() {
+    fs::remove_file(state_path()).ok();
+}
+
+/// Whether there is an interrupted installation to resume
+pub(super) fn exists() -> bool {
+    state_path().is_file()
+}
+
+/// Check the interrupted installation against this machine, and find where to continue,
+/// from `from_step` if set even if it has completed
+pub(super) fn load(from_step: Option<&'static str>) -> Result<Resumption> {
+    let state = load_state()?;
+    let steps = state
+        .steps
+        .iter()
+        .map(|x| known_step(x))
+        .collect::<Result<Vec<_>>>()?;
+    let completed = state
+        .completed
+        .iter()
+        .map(|x| known_step(x))
+        .collect::<Result<Vec<_>>>()?;
+    if state.config.encrypt.unwrap_or(false) {
+        return Err(anyhow!(tr!(
+            "The passphrase of the encrypted system partition is never saved, the installation can not be resumed."
+        )));
+    }
+
+    let first_incomplete = steps.iter().copied().find(|x| !completed.contains(x));
+    let from = match (from_step, first_incomplete) {
+        (Some(step), _) => {
+            if !steps.contains(&step) {
+                return Err(anyhow!(tr!(
+                    "The interrupted installation has no step \"{}\".",
+                    name_of(step)
+                )));
+            }
+            step
+        }
+        (None, Some(step)) => step,
+        (None, None) => {
+            return Err(anyhow!(tr!(
+            "The installation recorded in {} has completed, use --from-step to run a step again.",
+            state_path().display()
+        )))
+        }
+    };
+    let from = resume_point(&steps, from);
+    // the installed system has to be there to run the steps after unpacking
+    let unpacked = steps
+        .iter()
+        .filter(|x| **x == STEP1 || UNPACK_STEPS.contains(x))
+        .all(|x| completed.contains(x));
+    if !unpacked && position(&steps, from) > position(&steps, STEP2) {
+        return Err(anyhow!(tr!(
+            "The system release has not been unpacked yet, the installation can only be resumed from \"{}\" or an earlier step.",
+            name_of(first_incomplete.unwrap_or(STEP1))
+        )));
+    }
+
+    let target = if from == STEP1 {
+        None
+    } else {
+        let diff = plan_diff(
+            &state,
+            &session::hardware_id(),
+            |path| install::filesystem_uuid(path).ok(),
+            // the local copy of the release is unpacked again
+            (from == STEP2).then_some(|path: &Path| network::file_sha256(path).ok()),
+        );
+        if !diff.is_empty() {
+            return Err(anyhow!(
+                "{}\n\n--- {}\n+++ {}\n{}",
+                tr!(
+                    "The interrupted installation does not match this machine any more, it has not been resumed:"
+                ),
+                tr!("recorded"),
+                tr!("current"),
+                diff.join("\n")
+            ));
+        }
+        Some(state.target.clone().unwrap_or_default())
+    };
+
+    Ok(Resumption {
+        config: state.config,
+        steps,
+        completed,
+        from,
+        target,
+    })
+}
+
+/// Where to continue to run `step`, the release is downloaded again to unpack it
+fn resume_point(steps: &[&'static str], step: &'static str) -> &'static str {
+    if UNPACK_STEPS.contains(&step) {
+        steps
+            .iter()
+            .copied()
+            .find(|x| UNPACK_STEPS.contains(x))
+            .unwrap_or(step)
+    } else {
+        step
+    }
+}
+
+/// The differences between the recorded plan and this machine, a line for each, with
+/// `current_uuid` giving the UUID of the filesystem on a device, and `current_sha256` the
+/// checksum of a file, if the local copy of the release (if any) is to be unpacked again
+fn plan_diff(
+    state: &InstallState,
+    hardware: &str,
+    current_uuid: impl Fn(&Path) -> Option<String>,
+    current_sha256: Option<impl Fn(&Path) -> Option<String>>,
+) -> Vec<String> {
+    let mut diff = vec![];
+    if state.hardware != hardware {
+        diff.push(format!("- machine: {}", state.hardware));
+        diff.push(format!("+ machine: {hardware}"));
+    }
+    for fs in state.filesystems.iter() {
+        let uuid = current_uuid(&fs.path);
+        if uuid.as_ref() == Some(&fs.uuid) {
+            continue;
+        }
+        let path = fs.path.display();
+        diff.push(format!("- {}: {path} (UUID {})", fs.mount_point, fs.uuid));
+        diff.push(match uuid {
+            Some(uuid) => format!("+ {}: {path} (UUID {uuid})", fs.mount_point),
+            None => format!("+ {}: {path} ({})", fs.mount_point, tr!("no filesystem")),
+        });
+    }
+    let release = state.config.local_release.as_deref();
+    if let (Some(recorded), Some(path), Some(current_sha256)) =
+        (state.release_sha256.as_ref(), release, current_sha256)
+    {
+        let sha256 = current_sha256(path);
+        if sha256.as_ref() != Some(recorded) {
+            let path = path.display();
+            diff.push(format!("- release: {path} (sha256 {recorded})"));
+            diff.push(match sha256 {
+                Some(sha256) => format!("+ release: {path} (sha256 {sha256})"),
+                None => format!("+ release: {path} ({})", tr!("not found")),
+            });
+        }
+    }
+
+    diff
+}
+
+#[test]
+fn test_resume_point() {
+    let steps = [
+        STEP1, STEP2, STEP3, STEP4, FSTAB_STEP, STEP5, STEP6, STEP7, STEP8,
+    ];
+    assert_eq!(resume_point(&steps, STEP4), STEP2);
+    assert_eq!(resume_point(&steps, STEP6), STEP6);
+    assert_eq!(step_by_name("bootloader"), Some(STEP6));
+    assert_eq!(step_by_name("grub"), None);
+    assert_eq!(name_of(FSTAB_STEP), "fstab");
+
+    let resumption = Resumption {
+        config: InstallConfig::default(),
+        steps: steps.to_vec(),
+        completed: vec![STEP1],
+        from: STEP6,
+        target: None,
+    };
+    assert!(resumption.runs(STEP7));
+    assert!(resumption.runs(STEP6));
+    assert!(!resumption.runs(STEP5));
+}
+
+#[test]
+fn test_plan_diff() {
+    let mut state = InstallState {
+        version: STATE_VERSION,
+        hardware: "x86_64 1234".to_string(),
+        config: InstallConfig {
+            local_release: Some(std::sync::Arc::new(PathBuf::from("/media/base.tar.xz"))),
+            ..Default::default()
+        },
+        steps: vec![],
+        completed: vec![],
+        target: None,
+        filesystems: vec![
+            Filesystem {
+                mount_point: "/".to_string(),
+                path: PathBuf::from("/dev/sda2"),
+                uuid: "1111".to_string(),
+            },
+            Filesystem {
+                mount_point: "/efi".to_string(),
+                path: PathBuf::from("/dev/sda1"),
+                uuid: "AAAA-BBBB".to_string(),
+            },
+        ],
+        release_sha256: Some("abcd".to_string()),
+    };
+    let current = |path: &Path| match path.to_str() {
+        Some("/dev/sda1") => Some("AAAA-BBBB".to_string()),
+        Some("/dev/sda2") => Some("1111".to_string()),
+        _ => None,
+    };
+    let sha256 = |_: &Path| Some("abcd".to_string());
+    assert!(plan_diff(&state, "x86_64 1234", current, Some(sha256)).is_empty());
+
+    let current = |path: &Path| (path == Path::new("/dev/sda1")).then(|| "2222".to_string());
+    let sha256 = |_: &Path| Some("ef01".to_string());
+    assert_eq!(
+        plan_diff(&state, "x86_64 5678", current, Some(sha256)),
+        [
+            "- machine: x86_64 1234",
+            "+ machine: x86_64 5678",
+            "- /: /dev/sda2 (UUID 1111)",
+            "+ /: /dev/sda2 (no filesystem)",
+            "- /efi: /dev/sda1 (UUID AAAA-BBBB)",
+            "+ /efi: /dev/sda1 (UUID 2222)",
+            "- release: /media/base.tar.xz (sha256 abcd)",
+            "+ release: /media/base.tar.xz (sha256 ef01)",
+        ]
+    );
+    // the release is only checked if it is unpacked again
+    state.filesystems.clear();
+    assert!(plan_diff(
+        &state,
+        "x86_64 1234",
+        current,
+        None::<fn(&Path) -> Option<String>>
+    )
+    .is_empty());
+}
@@ -0,0 +1,265 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    disks::{DkDerive, Partition},
+    network,
+};
+
+use super::{InstallConfig, RootPassword};
+
+const SESSION_FILE: &str = "/run/deploykit/session.json";
+/// Symlinks naming the partitions and disks the same way, whatever order they are probed in
+const STABLE_LINK_DIRS: &[&str] = &["/dev/disk/by-partuuid", "/dev/disk/by-id"];
+/// Identify the machine, the first ones are DMI (x86), the others device tree
+const HARDWARE_ID_FILES: &[&str] = &[
+    "/sys/class/dmi/id/product_uuid",
+    "/sys/class/dmi/id/board_serial",
+    "/sys/firmware/devicetree/base/serial-number",
+    "/sys/firmware/devicetree/base/model",
+];
+
+/// The steps of the wizard, in the order they are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(super) enum WizardStep {
+    Variant,
+    Disk,
+    User,
+    Hostname,
+    Timezone,
+    Swap,
+}
+
+impl WizardStep {
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            WizardStep::Variant => "variant selection",
+            WizardStep::Disk => "disk selection",
+            WizardStep::User => "user account",
+            WizardStep::Hostname => "hostname",
+            WizardStep::Timezone => "locale and timezone",
+            WizardStep::Swap => "swap",
+        }
+    }
+}
+
+/// The wizard answers so far, for the installer to continue where it has been left off
+#[derive(Serialize, Deserialize)]
+pub(super) struct Session {
+    /// The step the user has reached
+    pub(super) step: WizardStep,
+    hardware: String,
+    /// Stable paths to the selected partition and its disk
+    partition: Option<PathBuf>,
+    disk: Option<PathBuf>,
+    /// The answers, without the passwords
+    pub(super) config: InstallConfig,
+}
+
+impl Session {
+    /// Check the selected disk and partition against the current ones (their names may have
+    /// changed), and go back to the disk selection if they are gone, returns whether they are
+    pub(super) fn revalidate(
+        &mut self,
+        devices: &[DkDerive],
+        list_partitions: impl Fn(&Path) -> Vec<Partition>,
+    ) -> bool {
+        let disk = self.disk.as_deref().and_then(|x| fs::canonicalize(x).ok());
+        let partition = self
+            .partition
+            .as_deref()
+            .and_then(|x| fs::canonicalize(x).ok());
+        let config = &mut self.config;
+        let mut valid = true;
+        if let Some(old) = config.wipe_disk.clone() {
+            match disk.and_then(|path| {
+                devices
+                    .iter()
+                    .find(|x| x.path == path && x.model == old.model && x.size == old.size)
+            }) {
+                Some(device) => {
+                    config.wipe_disk = Some(Arc::new(device.clone()));
+                    if let Some(planned) = config.partition.clone() {
+                        config.partition = Some(Arc::new(Partition {
+                            parent_path: Some(device.path.clone()),
+                            ..planned.as_ref().clone()
+                        }));
+                    }
+                }
+                None => valid = false,
+            }
+        } else if let Some(old) = config.partition.clone() {
+            let exists = disk
+                .as_ref()
+                .zip(partition.as_ref())
+                .is_some_and(|(disk, path)| {
+                    list_partitions(disk)
+                        .iter()
+                        .any(|x| x.path.as_ref() == Some(path) && x.size == old.size)
+                });
+            if exists {
+                // the file system to be created has been chosen, keep it
+                config.partition = Some(Arc::new(Partition {
+                    path: partition,
+                    parent_path: disk,
+                    ..old.as_ref().clone()
+                }));
+            } else {
+                valid = false;
+            }
+        }
+
+        if !valid {
+            config.reset_partition();
+            self.step = self.step.min(WizardStep::Disk);
+        }
+        if let Some(old) = config.swap_partition.clone() {
+            let exists = old.parent_path.as_deref().is_some_and(|disk| {
+                list_partitions(disk)
+                    .iter()
+                    .any(|x| x.path == old.path && x.size == old.size)
+            });
+            if !exists {
+                config.swap = None;
+                config.swap_partition = None;
+                self.step = self.step.min(WizardStep::Swap);
+            }
+        }
+        // the passwords have not been saved, they have to be entered again
+        if !config.oobe.unwrap_or(false) {
+            self.step = self.step.min(WizardStep::User);
+        }
+        if config.encrypt.unwrap_or(false) {
+            self.step = self.step.min(WizardStep::Disk);
+        }
+
+        valid
+    }
+}
+
+/// Identify the machine, so that a session is not continued on another one
+pub(super) fn hardware_id() -> String {
+    let mut id = network::get_arch_name().unwrap_or_default().to_string();
+    for path in HARDWARE_ID_FILES {
+        if let Ok(data) = fs::read(path) {
+            id.push(' ');
+            id.push_str(
+                String::from_utf8_lossy(&data)
+                    .trim_matches(|c: char| c == '\0' || c.is_whitespace()),
+            );
+        }
+    }
+
+    id
+}
+
+/// Find a symlink to the device which does not depend on the probe order
+fn stable_path(path: &Path) -> Option<PathBuf> {
+    let path = fs::canonicalize(path).ok()?;
+    STABLE_LINK_DIRS
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .map(|x| x.path())
+        .find(|x| fs::canonicalize(x).ok().as_ref() == Some(&path))
+}
+
+/// Save the answers made before `step`, the passwords are left out
+pub(super) fn save(step: WizardStep, config: &InstallConfig) -> Result<()> {
+    let mut config = config.clone();
+    config.password = None;
+    if matches!(
+        config.root_password.as_deref(),
+        Some(RootPassword::Hash(_)) | Some(RootPassword::Password(_))
+    ) {
+        config.root_password = None;
+    }
+    let partition = config
+        .partition
+        .as_ref()
+        .and_then(|x| x.path.as_deref())
+        .and_then(stable_path);
+    let disk = match config.wipe_disk.as_ref() {
+        Some(device) => stable_path(&device.path),
+        None => config
+            .partition
+            .as_ref()
+            .and_then(|x| x.parent_path.as_deref())
+            .and_then(stable_path),
+    };
+    let session = Session {
+        step,
+        hardware: hardware_id(),
+        partition,
+        disk,
+        config,
+    };
+    let path = Path::new(SESSION_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec(&session)?)?;
+
+    Ok(())
+}
+
+/// The saved session, if it has been saved on this machine
+pub(super) fn load() -> Option<Session> {
+    let data = fs::read(SESSION_FILE).ok()?;
+    let session: Session = serde_json::from_slice(&data).ok()?;
+
+    (session.hardware == hardware_id()).then_some(session)
+}
+
+pub(super) fn remove() {
+    fs::remove_file(SESSION_FILE).ok();
+}
+
+#[test]
+fn test_revalidate_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let disk = fs::canonicalize(dir.path()).unwrap().join("sda");
+    let path = disk.with_file_name("sda1");
+    fs::write(&disk, b"").unwrap();
+    fs::write(&path, b"").unwrap();
+    let partition = Partition {
+        path: Some(path.clone()),
+        parent_path: Some(disk.clone()),
+        fs_type: Some("ext4".to_string()),
+        size: 1024,
+    };
+    let mut session = Session {
+        step: WizardStep::Timezone,
+        hardware: hardware_id(),
+        partition: Some(path.clone()),
+        disk: Some(disk.clone()),
+        config: InstallConfig {
+            partition: Some(Arc::new(partition.clone())),
+            hostname: Some("aosc-pc".to_string()),
+            ..Default::default()
+        },
+    };
+
+    let current = Partition {
+        fs_type: Some("btrfs".to_string()),
+        ..partition
+    };
+    assert!(session.revalidate(&[], |_| vec![current.clone()]));
+    assert_eq!(session.step, WizardStep::User);
+    let kept = session.config.partition.clone().unwrap();
+    assert_eq!(kept.path.as_ref(), Some(&path));
+    assert_eq!(kept.fs_type.as_deref(), Some("ext4"));
+
+    session.step = WizardStep::Timezone;
+    session.config.oobe = Some(true);
+    assert!(!session.revalidate(&[], |_| vec![]));
+    assert_eq!(session.step, WizardStep::Disk);
+    assert!(session.config.partition.is_none());
+    assert_eq!(session.config.hostname.as_deref(), Some("aosc-pc"));
+}
@@ -0,0 +1,302 @@
+use cursive::{
+    event::{Event, EventTrigger, Key},
+    theme::{ColorStyle, Effect, Style},
+    utils::markup::StyledString,
+    view::ViewWrapper,
+    views::OnEventView,
+    CbSink, Cursive, Printer, Vec2, View,
+};
+use std::{
+    cell::Cell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::tr;
+
+/// The footer is hidden on terminals shorter than this, to leave the room to the dialogs
+const MIN_ROWS: usize = 25;
+
+/// A key binding shown in the footer, `label` is translated when it is shown
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shortcut {
+    /// Any key if none
+    event: Option<Event>,
+    label: &'static str,
+}
+
+impl Shortcut {
+    fn key_name(&self) -> String {
+        match &self.event {
+            Some(Event::Char(c)) => c.to_string(),
+            Some(Event::CtrlChar(c)) => format!("Ctrl-{}", c.to_ascii_uppercase()),
+            Some(Event::AltChar(c)) => format!("Alt-{c}"),
+            Some(Event::Key(key)) => format!("{key:?}"),
+            Some(event) => format!("{event:?}"),
+            None => tr!("Any key").to_string(),
+        }
+    }
+}
+
+/// The key bindings of the view on top
+#[derive(Debug, Default)]
+struct Focused {
+    /// The frame in which the view was drawn on top
+    frame: u64,
+    shortcuts: Vec<Shortcut>,
+    /// Every key goes to the view, the global shortcuts do not work
+    captures_keys: bool,
+}
+
+/// The global shortcuts, in the order they have been added
+static GLOBAL: Mutex<Vec<Shortcut>> = Mutex::new(Vec::new());
+/// Counts the frames drawn, so that a view not drawn on top any more is noticed
+static FRAME: AtomicU64 = AtomicU64::new(0);
+static FOCUSED: Mutex<Option<Focused>> = Mutex::new(None);
+/// Redraws the screen, once the footer has to catch up with the view on top
+static REDRAW: Mutex<Option<CbSink>> = Mutex::new(None);
+
+/// Redraw the footer of `siv` when the view on top changes, again after restoring it
+pub(super) fn connect_footer(siv: &mut Cursive) {
+    *REDRAW.lock().unwrap() = Some(siv.cb_sink().clone());
+}
+
+fn request_redraw() {
+    if let Some(cb_sink) = REDRAW.lock().unwrap().as_ref() {
+        cb_sink.send(Box::new(|_| {})).ok();
+    }
+}
+
+/// Run `cb` on `event` wherever the focus is, and list it in the footer
+pub(super) fn add_global_shortcut<E, F>(siv: &mut Cursive, event: E, label: &'static str, cb: F)
+where
+    E: Into<Event>,
+    F: FnMut(&mut Cursive) + 'static,
+{
+    let event = event.into();
+    siv.add_global_callback(event.clone(), cb);
+    let mut global = GLOBAL.lock().unwrap();
+    global.retain(|x| x.event.as_ref() != Some(&event));
+    global.push(Shortcut {
+        event: Some(event),
+        label,
+    });
+}
+
+pub(super) fn clear_global_shortcut<E: Into<Event>>(siv: &mut Cursive, event: E) {
+    let event = event.into();
+    siv.clear_global_callbacks(event.clone());
+    GLOBAL
+        .lock()
+        .unwrap()
+        .retain(|x| x.event.as_ref() != Some(&event));
+}
+
+/// A view with key bindings of its own, which are listed in the footer while it is on top
+pub(super) struct Shortcuts<V> {
+    view: OnEventView<V>,
+    shortcuts: Vec<Shortcut>,
+    captures_keys: bool,
+    /// Whether the view was on top when it was drawn last
+    focused: Cell<bool>,
+}
+
+impl<V> Shortcuts<V> {
+    pub(super) fn new(view: V) -> Self {
+        Shortcuts {
+            view: OnEventView::new(view),
+            shortcuts: vec![],
+            captures_keys: false,
+            focused: Cell::new(false),
+        }
+    }
+
+    /// Run `cb` on `event`, wherever the focus is in the view
+    pub(super) fn shortcut<E, F>(mut self, event: E, label: &'static str, cb: F) -> Self
+    where
+        E: Into<Event>,
+        F: Fn(&mut Cursive) + 'static,
+    {
+        let event = event.into();
+        self.view.set_on_event(event.clone(), cb);
+        self.shortcuts.push(Shortcut {
+            event: Some(event),
+            label,
+        });
+
+        self
+    }
+
+    /// Run `cb` on any key, before the view (or the global shortcuts) gets it
+    pub(super) fn any_key<F>(mut self, label: &'static str, cb: F) -> Self
+    where
+        F: Fn(&mut Cursive) + 'static,
+    {
+        self.view
+            .set_on_pre_event(EventTrigger::from_fn(is_key_press), cb);
+        self.shortcuts.push(Shortcut { event: None, label });
+        self.captures_keys = true;
+
+        self
+    }
+
+    pub(super) fn get_inner_mut(&mut self) -> &mut V {
+        self.view.get_inner_mut()
+    }
+}
+
+impl<V: View> ViewWrapper for Shortcuts<V> {
+    cursive::wrap_impl!(self.view: OnEventView<V>);
+
+    fn wrap_draw(&self, printer: &Printer) {
+        // the top layer is drawn focused
+        if printer.focused {
+            *FOCUSED.lock().unwrap() = Some(Focused {
+                frame: FRAME.load(Ordering::SeqCst),
+                shortcuts: self.shortcuts.clone(),
+                captures_keys: self.captures_keys,
+            });
+        }
+        // the footer is drawn before the dialogs, it shows this in the next frame
+        if self.focused.replace(printer.focused) != printer.focused {
+            request_redraw();
+        }
+        self.view.draw(printer);
+    }
+}
+
+impl<V> Drop for Shortcuts<V> {
+    fn drop(&mut self) {
+        if self.focused.get() {
+            request_redraw();
+        }
+    }
+}
+
+pub(super) fn is_key_press(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Char(_)
+            | Event::CtrlChar(_)
+            | Event::AltChar(_)
+            | Event::Key(_)
+            | Event::Shift(_)
+            | Event::Alt(_)
+            | Event::AltShift(_)
+            | Event::Ctrl(_)
+            | Event::CtrlShift(_)
+            | Event::CtrlAlt(_)
+    )
+}
+
+/// The shortcuts of the view on top first, then the global ones. Help is dimmed if the view
+/// has none (e.g., a message), the global shortcuts if the view takes every key.
+fn footer_line(focused: Option<&Focused>, global: &[Shortcut]) -> StyledString {
+    let help = Shortcut {
+        event: Some(Event::Key(Key::F1)),
+        label: "Help",
+    };
+    let local = focused.map(|x| x.shortcuts.as_slice()).unwrap_or_default();
+    let captures_keys = focused.map(|x| x.captures_keys).unwrap_or(false);
+    let mut entries = local.iter().map(|x| (x, true)).collect::<Vec<_>>();
+    if !local.iter().any(|x| x.event == help.event) {
+        entries.push((&help, false));
+    }
+    entries.extend(global.iter().map(|x| (x, !captures_keys)));
+
+    let mut line = StyledString::new();
+    for (i, (shortcut, available)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            line.append_plain("  ");
+        }
+        if available {
+            line.append_styled(shortcut.key_name(), Style::from(Effect::Reverse));
+            line.append_plain(format!(" {}", tr!(shortcut.label)));
+        } else {
+            line.append_styled(
+                format!("{} {}", shortcut.key_name(), tr!(shortcut.label)),
+                Style::from(ColorStyle::secondary()),
+            );
+        }
+    }
+
+    line
+}
+
+/// Fills the screen under the dialogs, with one line at the bottom listing the shortcuts
+/// of the view on top
+pub(super) struct Footer;
+
+impl View for Footer {
+    fn draw(&self, printer: &Printer) {
+        // drawn before every other layer, so what is on top is known from the frame before
+        let frame = FRAME.fetch_add(1, Ordering::SeqCst);
+        // and the step indicator takes one line
+        if printer.size.y + 1 < MIN_ROWS {
+            return;
+        }
+        let focused = FOCUSED.lock().unwrap();
+        let focused = focused.as_ref().filter(|x| x.frame == frame);
+        let line = footer_line(focused, &GLOBAL.lock().unwrap());
+        let y = printer.size.y - 1;
+        printer.with_color(ColorStyle::primary(), |printer| {
+            printer.print_hline((0, y), printer.size.x, " ");
+            printer.print_styled((1, y), (&line).into());
+        });
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        constraint
+    }
+}
+
+#[test]
+fn test_footer_line() {
+    let global = [Shortcut {
+        event: Some(Event::Key(Key::F12)),
+        label: "Log",
+    }];
+    let focused = Focused {
+        frame: 0,
+        shortcuts: vec![
+            Shortcut {
+                event: Some(Event::Key(Key::Esc)),
+                label: "Cancel",
+            },
+            Shortcut {
+                event: Some(Event::Key(Key::F1)),
+                label: "Help",
+            },
+        ],
+        captures_keys: false,
+    };
+    assert_eq!(
+        footer_line(Some(&focused), &global).source(),
+        "Esc Cancel  F1 Help  F12 Log"
+    );
+    // help is not there on a message, but the log is
+    assert_eq!(footer_line(None, &global).source(), "F1 Help  F12 Log");
+
+    let banner = Focused {
+        frame: 0,
+        shortcuts: vec![Shortcut {
+            event: None,
+            label: "Continue",
+        }],
+        captures_keys: true,
+    };
+    assert_eq!(
+        footer_line(Some(&banner), &global).source(),
+        "Any key Continue  F1 Help  F12 Log"
+    );
+    assert_eq!(
+        Shortcut {
+            event: Some(Event::CtrlChar('t')),
+            label: "Theme",
+        }
+        .key_name(),
+        "Ctrl-T"
+    );
+}
@@ -0,0 +1,214 @@
+use cursive::{
+    theme::{ColorStyle, Effect, Style},
+    traits::*,
+    utils::markup::StyledString,
+    views::LinearLayout,
+    Cursive, Printer, Vec2, View,
+};
+use std::sync::Mutex;
+
+use crate::tr;
+
+use super::{
+    session::WizardStep,
+    shortcuts::Footer,
+    theme::{emphasis, Emphasis},
+};
+
+/// The stages of the installation shown in the step indicator, in the order they are gone
+/// through, the wizard steps after the storage are options of the installed system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum Stage {
+    Network,
+    Variant,
+    Mirror,
+    Storage,
+    Users,
+    Options,
+    Summary,
+    Install,
+}
+
+impl Stage {
+    const ALL: &'static [Stage] = &[
+        Stage::Network,
+        Stage::Variant,
+        Stage::Mirror,
+        Stage::Storage,
+        Stage::Users,
+        Stage::Options,
+        Stage::Summary,
+        Stage::Install,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Network => tr!("Network"),
+            Stage::Variant => tr!("Variant"),
+            Stage::Mirror => tr!("Mirror"),
+            Stage::Storage => tr!("Storage"),
+            Stage::Users => tr!("Users"),
+            Stage::Options => tr!("Options"),
+            Stage::Summary => tr!("Summary"),
+            Stage::Install => tr!("Install"),
+        }
+    }
+}
+
+impl From<WizardStep> for Stage {
+    fn from(step: WizardStep) -> Self {
+        match step {
+            WizardStep::Variant => Stage::Variant,
+            WizardStep::Disk => Stage::Storage,
+            WizardStep::User => Stage::Users,
+            WizardStep::Hostname | WizardStep::Timezone | WizardStep::Swap => Stage::Options,
+        }
+    }
+}
+
+/// Where the installation is, and which stages have been shown on the way
+#[derive(Debug)]
+struct Progress {
+    current: Option<Stage>,
+    visited: Vec<Stage>,
+}
+
+impl Progress {
+    const fn new() -> Self {
+        Progress {
+            current: None,
+            visited: Vec::new(),
+        }
+    }
+
+    fn mark(&self, stage: Stage) -> Mark {
+        match self.current {
+            Some(current) if current == stage => Mark::Current,
+            _ if self.visited.contains(&stage) => Mark::Done,
+            // e.g. the network check, shown only when the servers can not be reached
+            Some(current) if stage < current => Mark::Skipped,
+            _ => Mark::Pending,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Done,
+    Current,
+    Skipped,
+    Pending,
+}
+
+static PROGRESS: Mutex<Progress> = Mutex::new(Progress::new());
+
+/// Show `stage` as the current one, going back to an earlier stage keeps the later ones done
+pub(super) fn set_stage(stage: Stage) {
+    let mut progress = PROGRESS.lock().unwrap();
+    progress.current = Some(stage);
+    if !progress.visited.contains(&stage) {
+        progress.visited.push(stage);
+    }
+}
+
+/// All stages by name, e.g. "*Network · *Variant · [Mirror] · Storage · ..."
+fn full_line(progress: &Progress) -> StyledString {
+    let mut line = StyledString::new();
+    for (i, stage) in Stage::ALL.iter().enumerate() {
+        if i > 0 {
+            line.append_plain(" · ");
+        }
+        match progress.mark(*stage) {
+            Mark::Done => {
+                line.append_styled(format!("*{}", stage.name()), emphasis(Emphasis::Good))
+            }
+            Mark::Current => {
+                line.append_styled(format!("[{}]", stage.name()), Style::from(Effect::Reverse))
+            }
+            Mark::Skipped => line.append_styled(
+                format!("({})", stage.name()),
+                Style::from(ColorStyle::secondary()),
+            ),
+            Mark::Pending => line.append_plain(stage.name()),
+        }
+    }
+
+    line
+}
+
+/// A mark per stage and the name of the current one, for narrow terminals,
+/// e.g. "**>..... Mirror (3/8)"
+fn short_line(progress: &Progress) -> StyledString {
+    let mut line = StyledString::new();
+    for stage in Stage::ALL {
+        line.append_plain(match progress.mark(*stage) {
+            Mark::Done => "*",
+            Mark::Current => ">",
+            Mark::Skipped => "-",
+            Mark::Pending => ".",
+        });
+    }
+    if let Some(current) = progress.current {
+        let index = Stage::ALL.iter().position(|x| *x == current).unwrap_or(0);
+        line.append_plain(format!(
+            " {} ({}/{})",
+            current.name(),
+            index + 1,
+            Stage::ALL.len()
+        ));
+    }
+
+    line
+}
+
+/// One line showing the stages of the installation, abbreviated if it does not fit
+struct StepIndicator;
+
+impl View for StepIndicator {
+    fn draw(&self, printer: &Printer) {
+        let progress = PROGRESS.lock().unwrap();
+        let full = full_line(&progress);
+        // the line starts after a space
+        let line = if full.width() < printer.size.x {
+            full
+        } else {
+            short_line(&progress)
+        };
+        printer.with_color(ColorStyle::primary(), |printer| {
+            printer.print_hline((0, 0), printer.size.x, " ");
+            printer.print_styled((1, 0), (&line).into());
+        });
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        Vec2::new(constraint.x, 1)
+    }
+}
+
+/// Put the step indicator at the top of the screen and the shortcuts at the bottom, under
+/// all dialogs
+pub(super) fn add_step_indicator(siv: &mut Cursive) {
+    siv.add_fullscreen_layer(
+        LinearLayout::vertical()
+            .child(StepIndicator.full_width())
+            .child(Footer),
+    );
+}
+
+#[test]
+fn test_step_marks() {
+    let mut progress = Progress::new();
+    progress.current = Some(Stage::Mirror);
+    progress.visited = vec![Stage::Variant, Stage::Mirror];
+    assert_eq!(progress.mark(Stage::Network), Mark::Skipped);
+    assert_eq!(progress.mark(Stage::Variant), Mark::Done);
+    assert_eq!(progress.mark(Stage::Mirror), Mark::Current);
+    assert_eq!(progress.mark(Stage::Storage), Mark::Pending);
+    assert_eq!(short_line(&progress).source(), "-*>..... Mirror (3/8)");
+
+    // going back keeps the later stages done
+    progress.visited.push(Stage::Storage);
+    progress.current = Some(Stage::Variant);
+    assert_eq!(progress.mark(Stage::Mirror), Mark::Done);
+    assert_eq!(progress.mark(Stage::Storage), Mark::Done);
+}
@@ -0,0 +1,174 @@
+use cursive::{
+    theme::{BaseColor, BorderStyle, Color, Effect, Palette, PaletteColor, Style, Theme},
+    utils::markup::StyledString,
+    Cursive,
+};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::tr;
+
+use super::accessible;
+
+/// Terminals known to show neither colours nor box-drawing characters properly
+const PLAIN_TERMS: &[&str] = &["dumb", "vt52", "vt100", "vt102", "vt220"];
+
+static THEME: AtomicU8 = AtomicU8::new(ThemeName::Default as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[repr(u8)]
+pub enum ThemeName {
+    /// The colours of cursive
+    Default,
+    /// White and yellow on black
+    HighContrast,
+    /// Black and white only, without borders (cursive draws them with box-drawing characters)
+    Plain,
+}
+
+impl ThemeName {
+    pub const ALL: &'static [ThemeName] = &[
+        ThemeName::Default,
+        ThemeName::HighContrast,
+        ThemeName::Plain,
+    ];
+
+    /// The theme suiting the terminal, plain on dumb terminals and serial consoles, or if
+    /// NO_COLOR is set
+    pub fn detect() -> ThemeName {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let no_color = std::env::var("NO_COLOR").is_ok_and(|x| !x.is_empty());
+        if no_color || term.is_empty() || PLAIN_TERMS.contains(&term.as_str()) {
+            ThemeName::Plain
+        } else {
+            ThemeName::Default
+        }
+    }
+
+    fn theme(&self) -> Theme {
+        match self {
+            ThemeName::Default => Theme::default(),
+            ThemeName::HighContrast => {
+                let mut palette = Palette::default();
+                for (color, value) in [
+                    (PaletteColor::Background, Color::Dark(BaseColor::Black)),
+                    (PaletteColor::Shadow, Color::Dark(BaseColor::Black)),
+                    (PaletteColor::View, Color::Dark(BaseColor::Black)),
+                    (PaletteColor::Primary, Color::Light(BaseColor::White)),
+                    (PaletteColor::Secondary, Color::Light(BaseColor::Cyan)),
+                    (PaletteColor::Tertiary, Color::Light(BaseColor::White)),
+                    (PaletteColor::TitlePrimary, Color::Light(BaseColor::Yellow)),
+                    (PaletteColor::TitleSecondary, Color::Dark(BaseColor::Yellow)),
+                    (PaletteColor::Highlight, Color::Light(BaseColor::Yellow)),
+                    (
+                        PaletteColor::HighlightInactive,
+                        Color::Dark(BaseColor::White),
+                    ),
+                    (PaletteColor::HighlightText, Color::Dark(BaseColor::Black)),
+                ] {
+                    palette[color] = value;
+                }
+
+                Theme {
+                    shadow: false,
+                    borders: BorderStyle::Simple,
+                    palette,
+                }
+            }
+            ThemeName::Plain => {
+                let mut palette = Palette::default();
+                for color in [
+                    PaletteColor::Background,
+                    PaletteColor::Shadow,
+                    PaletteColor::View,
+                    PaletteColor::Primary,
+                    PaletteColor::Secondary,
+                    PaletteColor::Tertiary,
+                    PaletteColor::TitlePrimary,
+                    PaletteColor::TitleSecondary,
+                ] {
+                    palette[color] = Color::TerminalDefault;
+                }
+                // shown in reverse video on monochrome terminals
+                palette[PaletteColor::Highlight] = Color::Dark(BaseColor::White);
+                palette[PaletteColor::HighlightInactive] = Color::Dark(BaseColor::White);
+                palette[PaletteColor::HighlightText] = Color::Dark(BaseColor::Black);
+
+                Theme {
+                    shadow: false,
+                    borders: BorderStyle::None,
+                    palette,
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn current() -> ThemeName {
+    let value = THEME.load(Ordering::SeqCst);
+    ThemeName::ALL
+        .iter()
+        .copied()
+        .find(|x| *x as u8 == value)
+        .unwrap_or(ThemeName::Default)
+}
+
+pub(super) fn apply(siv: &mut Cursive, name: ThemeName) {
+    THEME.store(name as u8, Ordering::SeqCst);
+    siv.set_theme(name.theme());
+}
+
+/// Switch to the next theme, text already shown keeps the emphasis of the previous one
+pub(super) fn cycle(siv: &mut Cursive) {
+    if accessible::is_enabled() {
+        return;
+    }
+    let current = current();
+    let index = ThemeName::ALL
+        .iter()
+        .position(|x| *x == current)
+        .unwrap_or(0);
+    apply(siv, ThemeName::ALL[(index + 1) % ThemeName::ALL.len()]);
+}
+
+/// What the emphasised text means
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Emphasis {
+    /// Data loss, errors
+    Danger,
+    Warning,
+    Good,
+}
+
+/// The style of emphasised text in the current theme, the plain one has no colours
+pub(super) fn emphasis(kind: Emphasis) -> Style {
+    match (current(), kind) {
+        (ThemeName::Plain, Emphasis::Danger) => Style::from(Effect::Bold),
+        (ThemeName::Plain, Emphasis::Warning) => Style::from(Effect::Underline),
+        (ThemeName::Plain, Emphasis::Good) => Style::none(),
+        (_, Emphasis::Danger) => Style::from(Color::Light(BaseColor::Red)),
+        (_, Emphasis::Warning) => Style::from(Color::Light(BaseColor::Yellow)),
+        (_, Emphasis::Good) => Style::from(Color::Light(BaseColor::Green)),
+    }
+}
+
+/// The text with the emphasis, which is also spelled out in accessible mode as screen
+/// readers do not tell the styles
+pub(super) fn emphasized<S: Into<String>>(text: S, kind: Emphasis) -> StyledString {
+    let text = text.into();
+    let text = match kind {
+        _ if !accessible::is_enabled() => text,
+        Emphasis::Danger => tr!("Important: {}", text),
+        Emphasis::Warning => tr!("Note: {}", text),
+        Emphasis::Good => text,
+    };
+
+    StyledString::styled(text, emphasis(kind))
+}
+
+#[test]
+fn test_theme_names() {
+    for name in ThemeName::ALL {
+        assert_eq!(ThemeName::ALL[*name as usize], *name);
+    }
+    assert_eq!(ThemeName::Plain.theme().borders, BorderStyle::None);
+}
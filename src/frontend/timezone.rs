@@ -0,0 +1,372 @@
+use cursive::{
+    traits::*,
+    views::{Dialog, EditView, LinearLayout, SelectView, TextContent, TextView},
+    Cursive,
+};
+use std::{cell::RefCell, collections::HashMap, process::Command, rc::Rc, sync::Arc};
+
+use crate::{install, tr};
+
+use super::tui::wrap_in_dialog;
+
+const ZONE_REGIONS: &str = "zone_regions";
+const ZONE_CITIES: &str = "zone_cities";
+const ZONE_QUERY: &str = "zone_query";
+
+/// Former and common names of the cities, which are not in the names of their timezones
+const ALIASES: &[(&str, &str)] = &[
+    ("Peking", "Asia/Shanghai"),
+    ("Beijing", "Asia/Shanghai"),
+    ("Canton", "Asia/Shanghai"),
+    ("Guangzhou", "Asia/Shanghai"),
+    ("Shenzhen", "Asia/Shanghai"),
+    ("Chengdu", "Asia/Shanghai"),
+    ("Wuhan", "Asia/Shanghai"),
+    ("Macao", "Asia/Macau"),
+    ("Osaka", "Asia/Tokyo"),
+    ("Kyoto", "Asia/Tokyo"),
+    ("Bombay", "Asia/Kolkata"),
+    ("Mumbai", "Asia/Kolkata"),
+    ("Calcutta", "Asia/Kolkata"),
+    ("New Delhi", "Asia/Kolkata"),
+    ("Bangalore", "Asia/Kolkata"),
+    ("Madras", "Asia/Kolkata"),
+    ("Chennai", "Asia/Kolkata"),
+    ("Saigon", "Asia/Ho_Chi_Minh"),
+    ("Rangoon", "Asia/Yangon"),
+    ("Kiev", "Europe/Kyiv"),
+    ("Constantinople", "Europe/Istanbul"),
+    ("Saint Petersburg", "Europe/Moscow"),
+    ("Munich", "Europe/Berlin"),
+    ("Frankfurt", "Europe/Berlin"),
+    ("Milan", "Europe/Rome"),
+    ("Barcelona", "Europe/Madrid"),
+    ("Geneva", "Europe/Zurich"),
+    ("San Francisco", "America/Los_Angeles"),
+    ("Seattle", "America/Los_Angeles"),
+    ("Las Vegas", "America/Los_Angeles"),
+    ("Washington", "America/New_York"),
+    ("Boston", "America/New_York"),
+    ("Houston", "America/Chicago"),
+    ("Dallas", "America/Chicago"),
+    ("Montreal", "America/Toronto"),
+    ("Rio de Janeiro", "America/Sao_Paulo"),
+];
+
+/// A timezone with what it can be searched by
+#[derive(Debug, Clone)]
+pub(super) struct Zone {
+    pub name: String,
+    /// The names of the countries using the timezone
+    pub countries: Vec<String>,
+    /// Which part of the countries the timezone is for, if they have several
+    pub comment: String,
+    pub aliases: Vec<&'static str>,
+}
+
+impl Zone {
+    /// The part of the name before the city, e.g., "America" of America/Argentina/Salta
+    pub fn region(&self) -> &str {
+        self.name.split_once('/').map(|x| x.0).unwrap_or(&self.name)
+    }
+
+    /// The city of the timezone in words
+    pub fn city(&self) -> String {
+        let city = self.name.split_once('/').map(|x| x.1).unwrap_or(&self.name);
+        city.replace('_', " ").replace('/', " / ")
+    }
+
+    fn describe(&self) -> String {
+        let mut info = self.countries.clone();
+        info.extend(Some(self.comment.clone()).filter(|x| !x.is_empty()));
+        if info.is_empty() {
+            return self.name.clone();
+        }
+
+        format!("{} ({})", self.name, info.join(", "))
+    }
+
+    /// How well the query matches the timezone, None if it does not
+    fn score(&self, query: &str) -> Option<u32> {
+        let city = self.city();
+        [
+            (self.name.as_str(), 0),
+            (city.as_str(), 0),
+            (self.comment.as_str(), 2),
+        ]
+        .into_iter()
+        .chain(self.countries.iter().map(|x| (x.as_str(), 1)))
+        .chain(self.aliases.iter().map(|x| (*x, 1)))
+        .filter_map(|(text, penalty)| fuzzy_score(text, query).map(|x| x.saturating_sub(penalty)))
+        .max()
+    }
+}
+
+/// Match the query against the text ignoring case, spaces and underscores: a prefix match
+/// scores the most, then a substring, then the characters of the query in order
+fn fuzzy_score(text: &str, query: &str) -> Option<u32> {
+    let normalize = |x: &str| {
+        x.chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .flat_map(char::to_lowercase)
+            .collect::<String>()
+    };
+    let text = normalize(text);
+    let query = normalize(query);
+    if query.is_empty() {
+        return None;
+    }
+
+    if text.starts_with(&query) {
+        return Some(30);
+    }
+    if text.contains(&query) {
+        return Some(20);
+    }
+    let mut chars = text.chars();
+    query.chars().all(|c| chars.any(|x| x == c)).then_some(10)
+}
+
+/// All the timezones, in the order of their names
+pub(super) fn list_zones() -> Vec<Zone> {
+    let countries = install::get_country_names()
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let mut zones = install::get_zone_entries()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| Zone {
+            countries: entry
+                .countries
+                .iter()
+                .map(|x| countries.get(x).cloned().unwrap_or_else(|| x.clone()))
+                .collect(),
+            aliases: ALIASES
+                .iter()
+                .filter(|(_, zone)| *zone == entry.zone)
+                .map(|(alias, _)| *alias)
+                .collect(),
+            name: entry.zone,
+            comment: entry.comment,
+        })
+        .collect::<Vec<_>>();
+    zones.push(Zone {
+        name: "UTC".to_string(),
+        countries: vec![],
+        comment: String::new(),
+        aliases: vec!["GMT"],
+    });
+    zones.sort_by(|a, b| a.name.cmp(&b.name));
+
+    zones
+}
+
+/// The timezones matching the query, the best matches first
+pub(super) fn search<'a>(zones: &'a [Zone], query: &str) -> Vec<&'a Zone> {
+    let mut matches = zones
+        .iter()
+        .filter_map(|x| x.score(query).map(|score| (score, x)))
+        .collect::<Vec<_>>();
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+
+    matches.into_iter().map(|x| x.1).collect()
+}
+
+/// The current time in the timezone, e.g., "Mon 08:00 (CST, UTC+08:00)"
+fn current_time(zone: &str) -> Option<String> {
+    let output = Command::new("date")
+        .env("TZ", zone)
+        .arg("+%a %H:%M (%Z, UTC%:z)")
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// List the timezones as the cities, by their full names with the countries for search results
+fn fill_cities(siv: &mut Cursive, zones: &[&Zone], full_names: bool) {
+    siv.call_on_name(ZONE_CITIES, |view: &mut SelectView<String>| {
+        view.clear();
+        for zone in zones {
+            let label = if full_names {
+                zone.describe()
+            } else {
+                zone.city()
+            };
+            view.add_item(label, zone.name.clone());
+        }
+    });
+}
+
+fn show_preview(zones: &[Zone], preview: &TextContent, name: &str) {
+    let Some(zone) = zones.iter().find(|x| x.name == name) else {
+        preview.set_content("");
+        return;
+    };
+    let time = current_time(name)
+        .map(|x| tr!("Current time: {}", x))
+        .unwrap_or_default();
+    preview.set_content(format!("{}\n{}", zone.describe(), time));
+}
+
+/// Pick a timezone by region and city, or by searching for it
+pub(super) fn timezone_picker(
+    zones: Vec<Zone>,
+    timezone: Rc<RefCell<String>>,
+    status_text: Arc<TextContent>,
+) -> Dialog {
+    let zones = Rc::new(zones);
+    let current = timezone.borrow().clone();
+    let current_region = zones
+        .iter()
+        .find(|x| x.name == current)
+        .map(|x| x.region().to_string());
+    let mut regions = zones.iter().map(|x| x.region()).collect::<Vec<_>>();
+    regions.dedup();
+    let regions = regions
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    let preview = TextContent::new("");
+    show_preview(&zones, &preview, &current);
+
+    let mut region_view = SelectView::new();
+    region_view.add_all_str(regions.clone());
+    if let Some(index) = regions
+        .iter()
+        .position(|x| Some(x) == current_region.as_ref())
+    {
+        region_view.set_selection(index);
+    }
+    let zones_copy = zones.clone();
+    let preview_copy = preview.clone();
+    let region_view = region_view.on_select(move |s, region: &String| {
+        let cities = zones_copy
+            .iter()
+            .filter(|x| x.region() == region)
+            .collect::<Vec<_>>();
+        fill_cities(s, &cities, false);
+        if let Some(zone) = cities.first() {
+            show_preview(&zones_copy, &preview_copy, &zone.name);
+        }
+    });
+
+    let mut city_view = SelectView::new();
+    for zone in zones
+        .iter()
+        .filter(|x| Some(x.region()) == current_region.as_deref())
+    {
+        city_view.add_item(zone.city(), zone.name.clone());
+    }
+    if let Some(index) = city_view.iter().position(|(_, x)| *x == current) {
+        city_view.set_selection(index);
+    }
+    let zones_copy = zones.clone();
+    let preview_copy = preview.clone();
+    let timezone_copy = timezone.clone();
+    let status_text_copy = status_text.clone();
+    let city_view = city_view
+        .on_select(move |_, name: &String| show_preview(&zones_copy, &preview_copy, name))
+        .on_submit(move |s, name: &String| {
+            s.pop_layer();
+            timezone_copy.replace(name.clone());
+            status_text_copy.set_content(name);
+        });
+
+    let zones_copy = zones.clone();
+    let preview_copy = preview.clone();
+    let query_view = EditView::new()
+        .on_edit(move |s, query, _| {
+            let query = query.trim();
+            if query.is_empty() {
+                let region = s
+                    .call_on_name(ZONE_REGIONS, |view: &mut SelectView| view.selection())
+                    .flatten();
+                let cities = zones_copy
+                    .iter()
+                    .filter(|x| Some(x.region()) == region.as_deref().map(|x| x.as_str()))
+                    .collect::<Vec<_>>();
+                fill_cities(s, &cities, false);
+            } else {
+                fill_cities(s, &search(&zones_copy, query), true);
+            }
+            let selected = s
+                .call_on_name(ZONE_CITIES, |view: &mut SelectView<String>| {
+                    view.selection()
+                })
+                .flatten();
+            match selected {
+                Some(name) => show_preview(&zones_copy, &preview_copy, &name),
+                None => preview_copy.set_content(tr!("No timezone found.")),
+            }
+        })
+        .on_submit(move |s, _| {
+            // the best match is taken without moving into the list
+            let selected = s
+                .call_on_name(ZONE_CITIES, |view: &mut SelectView<String>| {
+                    view.selection()
+                })
+                .flatten();
+            if let Some(name) = selected {
+                s.pop_layer();
+                timezone.replace(name.to_string());
+                status_text.set_content(name.as_str());
+            }
+        });
+
+    wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(TextView::new(tr!(
+                "Search by city, country or timezone name, or select a region and a city:"
+            )))
+            .child(query_view.with_name(ZONE_QUERY))
+            .child(
+                LinearLayout::horizontal()
+                    .child(
+                        region_view
+                            .with_name(ZONE_REGIONS)
+                            .scrollable()
+                            .fixed_width(16),
+                    )
+                    .child(city_view.with_name(ZONE_CITIES).scrollable().full_width())
+                    .fixed_height(12),
+            )
+            .child(TextView::new_with_content(preview)),
+        tr!("Select Your Timezone"),
+        Some(64),
+    )
+}
+
+#[test]
+fn test_search_zones() {
+    let zone = |name: &str, country: &str, aliases: Vec<&'static str>| Zone {
+        name: name.to_string(),
+        countries: vec![country.to_string()],
+        comment: String::new(),
+        aliases,
+    };
+    let zones = vec![
+        zone("America/New_York", "United States", vec!["Boston"]),
+        zone("Asia/Shanghai", "China", vec!["Peking", "Beijing"]),
+        zone("Asia/Tokyo", "Japan", vec![]),
+        zone("Europe/Berlin", "Germany", vec![]),
+    ];
+    let names = |query| {
+        search(&zones, query)
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names("Peking"), vec!["Asia/Shanghai"]);
+    assert_eq!(names("new york"), vec!["America/New_York"]);
+    assert_eq!(names("japan"), vec!["Asia/Tokyo"]);
+    assert_eq!(names("brln"), vec!["Europe/Berlin"]);
+    assert_eq!(names("asia")[..2], ["Asia/Shanghai", "Asia/Tokyo"]);
+    assert!(names("xyz").is_empty());
+    assert_eq!(zones[0].region(), "America");
+    assert_eq!(zones[0].city(), "New York");
+}
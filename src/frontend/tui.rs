@@ -1,43 +1,109 @@
 use crate::{
-    disks::{
-        self, auto_create_partitions, device_is_empty, is_efi_booted, DkDerive, ALLOWED_FS_TYPE,
-    },
-    install::{self, find_language_by_locale, find_locale_by_language, read_locale, umount_all},
-    network::{self, Mirror, VariantEntry},
-    LOG_FILE,
+    boards,
+    cancel::{self, CancelToken},
+    disks::{self, device_is_empty, is_efi_booted, DkDerive, ALLOWED_FS_TYPE},
+    i18n,
+    install::{self, read_locale, read_timezone, umount_all, UnmountStatus},
+    log::{save_log, StepJournal, StepOutcome},
+    network::{self, BulletinLevel, Connectivity, Mirror, MirrorTest, VariantEntry},
+    report, tr, LOG_FILE, UI_THREAD,
 };
 use anyhow::Result;
+use cursive::views::Button;
 use cursive::{
-    event::Event,
+    event::{Event, EventTrigger, Key},
+    theme::PaletteColor,
+    utils::markup::StyledString,
     view::Selector,
     views::{
-        Dialog, DummyView, EditView, LinearLayout, ListView, NamedView, Panel, ProgressBar,
-        RadioGroup, ResizedView, ScrollView, SelectView, TextContent, TextView,
+        Checkbox, Dialog, DummyView, EditView, HideableView, Layer, LinearLayout, ListView,
+        NamedView, OnEventView, Panel, ProgressBar, RadioGroup, ResizedView, ScrollView,
+        SelectView, TextContent, TextView,
     },
 };
 use cursive::{traits::*, utils::Counter};
-use cursive::{view::SizeConstraint, views::Button};
-use cursive::{Cursive, View};
+use cursive::{CbSink, Cursive, View};
 use cursive_async_view::AsyncView;
 use cursive_table_view::{TableView, TableViewItem};
 use libparted::Device;
-use log::{error, info};
-use number_prefix::NumberPrefix;
+use log::{error, info, warn};
+use once_cell::sync::OnceCell;
 use send_wrapper::SendWrapper;
 use std::rc::Rc;
-use std::{cell::RefCell, path::Path, sync::Arc, thread};
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 use std::{env, fs, io::Read, path::PathBuf};
 use std::{
     process::Command,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 
 use super::{
-    begin_install, games::add_main_callback, AtomicBoolWrapper, InstallConfig, DEFAULT_EMPTY_SIZE,
+    accessible::{self, dialog_content, dropdown},
+    assigned_mounts, begin_install, boot_splash, check_config_file_passwords, config_file,
+    data_loss_warning, default_groups,
+    games::{add_main_callback, clear_callback},
+    help::{help_button, help_on_f1, show_help, with_help, Screen},
+    hidpi, human_size, keyboard, locale,
+    log_pane::toggle_log_pane,
+    mount_plan, mounts, network_setup, not_enough_space_msg,
+    notify::notify,
+    overall_percent, package_repo_summary, password, plain_description, planned_kernel_cmdline,
+    recommended_swap, reinstall_summary, resolve_ssh_keys,
+    session::{self, WizardStep},
+    short_duration,
+    shortcuts::{
+        add_global_shortcut, clear_global_shortcut, connect_footer, is_key_press, Shortcuts,
+    },
+    step_indicator::{self, add_step_indicator, Stage},
+    swap_partition,
+    theme::{self, emphasis, emphasized, Emphasis, ThemeName},
+    timezone, AtomicBoolWrapper, BootMenu, CommandTail, InstallClock, InstallConfig, InstallPhase,
+    PostInstallAction, RootPassword, SpaceBudget, SpaceStatus, StorageMode, SwapKind,
+    LAZY_UNMOUNT_INFO, STEP6,
 };
 
+/// Services offered on the options screen, SSH has its own option
+const CURATED_SERVICES: &[(&str, &str)] = &[
+    ("Enable Firewall (firewalld)", "firewalld.service"),
+    ("Enable Compressed Swap (zramswap)", "zramswap.service"),
+];
 const LAST_USER_CONFIG_FILE: &str = "/tmp/deploykit-config.json";
-const SAVE_USER_CONFIG_FILE: &str = "/root/deploykit-config.json";
+const SAVE_USER_CONFIG_FILE: &str = "/root/deploykit-config.toml";
+const USER_PASSWORD_DIALOG: &str = "user_password_dialog";
+const SWAP_DIALOG: &str = "swap_dialog";
+const BULLETIN_DIALOG: &str = "bulletin_dialog";
+const BULLETIN_TEXT: &str = "bulletin_text";
+const BULLETIN_ACKNOWLEDGED: &str = "bulletin_acknowledged";
+const DISK_LIST: &str = "disk_list";
+const BACKGROUND_TASK: &str = "background_task";
+const RECIPE_LOADING: &str = "recipe_loading";
+/// A hint to check the network is shown if fetching the recipe takes longer
+const RECIPE_SLOW: Duration = Duration::from_secs(15);
+const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Every fetch of the recipe gets a new number, the result of a cancelled one is dropped
+static RECIPE_FETCH: AtomicU64 = AtomicU64::new(0);
+/// Where the plan is written as an answer file once the summary is reached, set by
+/// `tui --export-config`
+static EXPORT_CONFIG: OnceCell<PathBuf> = OnceCell::new();
+/// The answers the wizard starts with, from the answer file given on the kernel command line
+static PRELOADED_CONFIG: Mutex<Option<InstallConfig>> = Mutex::new(None);
+/// Shown over the welcome screen, e.g., why the automatic installation has failed
+static STARTUP_MESSAGE: Mutex<Option<String>> = Mutex::new(None);
+const GUIDED_OPTIONS: &str = "guided_options";
+const ADVANCED_OPTIONS: &str = "advanced_options";
+const ENCRYPTION_PASSPHRASE: &str = "encryption_passphrase";
+const ENCRYPTION_DISCARD: &str = "encryption_discard";
+/// The dialogs are laid out for at least 80x24, the size of the Linux and serial consoles
+const MIN_TERMINAL_SIZE: (usize, usize) = (80, 24);
+const TERMINAL_TOO_SMALL: &str = "terminal_too_small";
 const NO_ESP_ERROR: &str = r"Error: Installer has detected that you are installing AOSC OS on an EFI/UEFI system, but could not detect a supported EFI System Partition (ESP) on your storage devices.
 
 In order to continue installing AOSC OS, you would need to create an EFI System Partition (ESP) on a GPT partition map, formatted as a FAT32 filesystem.";
@@ -47,14 +113,16 @@ enum VariantColumn {
     Name,
     Date,
     Size,
+    InstallSize,
 }
 
 impl TableViewItem<VariantColumn> for network::VariantEntry {
     fn to_column(&self, column: VariantColumn) -> String {
         match column {
             VariantColumn::Name => self.name.clone(),
-            VariantColumn::Date => self.date.clone(),
+            VariantColumn::Date => release_date(&self.date),
             VariantColumn::Size => human_size(self.size),
+            VariantColumn::InstallSize => human_size(self.install_size),
         }
     }
     fn cmp(&self, other: &Self, column: VariantColumn) -> std::cmp::Ordering
@@ -65,27 +133,46 @@ impl TableViewItem<VariantColumn> for network::VariantEntry {
             VariantColumn::Name => self.name.cmp(&other.name),
             VariantColumn::Date => self.date.cmp(&other.date),
             VariantColumn::Size => self.size.cmp(&other.size),
+            VariantColumn::InstallSize => self.install_size.cmp(&other.install_size),
         }
     }
 }
 
-macro_rules! SUMMARY_TEXT {
-    () => {
-        "Installer will perform the following operations:\n- {} will be erased and formatted as {}.\n- AOSC OS {} will be downloaded from {}.\n- User {} will be created.\n- AOSC OS will use the {} locale.\n- Your timezone will be set to {}, and will use {} as local time.\n"
-    };
+/// The release date as YYYY-MM-DD, the recipe has them as YYYYMMDD
+fn release_date(date: &str) -> String {
+    if date.len() == 8 && date.bytes().all(|x| x.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+    } else {
+        date.to_string()
+    }
 }
 
-macro_rules! SURE_FS_TYPE_INFO {
-    () => {
-        "AOSC OS Installation has detected that the specified partition is currently formatted as {}, would you like to format this partition using the original filesystem? For its proven reliability, we recommend formatting your system partition as ext4."
-    };
+/// The full description of the variant and the release file it will be installed from
+fn variant_details(variant: &VariantEntry) -> String {
+    let mut details = String::new();
+    let description = plain_description(i18n::localized(
+        &variant.description,
+        &variant.description_tr,
+    ));
+    if !description.is_empty() {
+        details.push_str(&description);
+        details.push_str("\n\n");
+    }
+    details.push_str(&tr!(
+        "Download size: {}, installed size: {}, built on {}.\nRelease file: {}\nSHA-256: {}",
+        human_size(variant.size),
+        human_size(variant.install_size),
+        release_date(&variant.date),
+        variant.url,
+        variant.sha256sum
+    ));
+
+    details
 }
 
-macro_rules! SURE_FS_FORMAT_INFO {
-    () => {
-        "Installer has detected an existing file system on the specified partition, {}. Please consider verifying if there is data in this partition that is yet to be backed up.\n\nAfter the final confirmation, coming up in a few steps, Installer will format this partition as {}. "
-    };
-}
+const SURE_FS_TYPE_INFO: &str = "AOSC OS Installation has detected that the specified partition is currently formatted as {}, would you like to format this partition using the original filesystem? For its proven reliability, we recommend formatting your system partition as ext4.";
+
+const SURE_FS_FORMAT_INFO: &str = "Installer has detected an existing file system on the specified partition, {}. Please consider verifying if there is data in this partition that is yet to be backed up.\n\nAfter the final confirmation, coming up in a few steps, Installer will format this partition as {}. ";
 
 const ADVANCED_METHOD_INFO: &str = "Installer detected an unsupported filesystem format in your system partition. If you proceed, the installer will format your system partition using the ext4 filesystem. Please refer to the manual installation guides if you prefer to use an unsupported filesystem.";
 const WELCOME_TEXT: &str = r#"Welcome to the AOSC OS Installer!
@@ -95,18 +182,42 @@ In the following pages, Installer will guide you through the variant selection,
 Note: The installation process requires a functional Internet connection. Please configure your Internet connection with the tray icon to the bottom right. If you are using the command-line interface, press Ctrl+C to exit now and configure your Internet connection with the 'nmtui' command."#;
 const VARIANT_TEXT: &str =
     "Shown below is a list of available AOSC OS distributions for your device.";
-const ENTER_USER_PASSWORD_TEXT: &str = r#"Please enter and confirm your desired username and password. Please note that your username must start with a lower-cased alphabetical letter (a-z), and contain only lower-cased letters a-z, numbers 0-9, and dash ("-").
+const ENTER_USER_PASSWORD_TEXT: &str = r#"Please enter and confirm your desired username and password. Please note that your username must start with a lower-cased alphabetical letter (a-z), contain only lower-cased letters a-z, numbers 0-9, dash ("-") and underscore ("_"), and be no longer than 32 characters.
 "#;
-const ENTER_HOSTNAME_TEXT: &str = r#"Now, please input your desired hostname. A hostname may only consist letters a-z, numbers 0-9, and dash ("-")."#;
-const ENTER_TIMEZONE_TEXT: &str = r#"Finally, please select your locale, timezone, and your clock preferences. Your locale setting will affect your installation's display language. UTC system time is the default setting for Linux systems, but may result in time discrepancy with your other operating systems, such as Windows. If you wish to prevent this from happening, please select local time as system time."#;
+const OOBE_TEXT: &str = r#"The configuration file asks for the user account to be created on first boot, so the installer will not create one, nor set the root password. The end user will be prompted for their username and password when the installed system boots for the first time. Until then, there will be no interactive login to the installed system."#;
+const NOT_ADMINISTRABLE: &str = "With root login disabled, the user has to be an administrator, or the installed system can not be administrated.";
+const ENTER_HOSTNAME_TEXT: &str = r#"Now, please input your desired hostname. A hostname may only consist letters a-z, numbers 0-9, and dash ("-"). You may also enable the SSH server, import SSH keys for remote access, and list additional packages to install, and adjust boot options here."#;
+const ENTER_TIMEZONE_TEXT: &str = r#"Finally, please select your locale, timezone, keyboard layout, and your clock preferences. Your locale setting will affect your installation's display language. UTC system time is the default setting for Linux systems, but may result in time discrepancy with your other operating systems, such as Windows. If you wish to prevent this from happening, please select local time as system time."#;
 const BENCHMARK_TEXT: &str = "Installer will now test all mirrors for download speed, and rank them from the fastest (top) to the slowest (bottom). This may take a few minutes.";
 const FINISHED_TEXT: &str = r#"AOSC OS has been successfully installed on your device.
 
-You may reboot to your installed system by choosing "Reboot," or return to LiveKit by selecting "Exit to LiveKit.""#;
+Select "Reboot Now" to boot into the installed system, and remove the installer medium (e.g., the USB drive) as the device restarts. Select "Stay in Live Session" to return to LiveKit instead.
+
+If you would like to make further changes (e.g., adding a kernel parameter or installing another package), you may open a shell in the installed system by choosing "Open Shell." Exit the shell (command prompt) to return to the installer."#;
+const INCOMPLETE_TEXT: &str = r#"AOSC OS has been installed on your device, but some steps have not completed, and the installed system may not boot.
+
+The installed system is still mounted. Select "View Log" to see what has gone wrong, and "Open Shell" to fix it in the installed system (e.g., free up space on the EFI System Partition). Then run the failed steps again below, without installing again."#;
+/// Seconds until the finish screen reboots into the installed system by itself
+const REBOOT_COUNTDOWN: u64 = 30;
+const CANCEL_TEXT: &str = "Installer has not yet completed the installation process. Are you sure that you would like to cancel the installation? The partially downloaded system release will be kept, and resumed the next time you install.";
+const CANCEL_UNPACKING_TEXT: &str = "Installer is already unpacking the system release to the target. If you cancel now, the target will contain an incomplete system that can not be booted.\n\nYou may wipe the incomplete system (/home is kept if you chose to preserve it), or keep it as is.";
+const CANCEL_TOO_LATE_TEXT: &str = "Installer is now configuring the installed system, and the installation can no longer be cancelled safely. Please wait for it to finish.";
+const CANCELLING_TEXT: &str = "Cancelling the installation, please wait ...";
+const CANCELLED_TEXT: &str = "The installation has been cancelled. You may review the installation settings and start again.";
+
+/// The shell to open while the TUI is suspended
+enum ShellRequest {
+    /// A shell in the live system, returns to the disk selection
+    Live(cursive::Dump),
+    /// A shell chrooted into the installed system, returns to the finish screen
+    Guest(cursive::Dump, PathBuf),
+    /// A partitioning program run on a disk, returns to the disk selection
+    Partitioner(cursive::Dump, &'static str, PathBuf),
+}
 
 macro_rules! fill_in_all_the_fields {
     ($s:ident) => {
-        show_msg($s, "Please fill in all the fields.");
+        show_msg($s, tr!("Please fill in all the fields."));
         return;
     };
 }
@@ -114,39 +225,111 @@ macro_rules! fill_in_all_the_fields {
 type PartitionButton = (&'static str, Box<dyn Fn(&mut Cursive, InstallConfig)>);
 
 fn show_error(siv: &mut Cursive, msg: &str) {
+    show_error_with_details(siv, msg, msg.to_string(), None);
+}
+
+/// Show the error, `details` (e.g., the whole error chain) and the installation `plan`
+/// go into the error details if the user saves them
+fn show_error_with_details(siv: &mut Cursive, msg: &str, details: String, plan: Option<String>) {
     siv.add_layer(
-        Dialog::around(TextView::new(msg).max_width(80))
-            .title("Error")
-            .button("Exit", |s| s.quit())
+        Dialog::around(dialog_content(TextView::new(msg).scrollable(), 80))
+            .title(tr!("Error"))
+            .button(tr!("Save Details"), move |s| {
+                save_error_details(s, &details, plan.as_deref())
+            })
+            .button(tr!("Exit"), |s| s.quit())
             .padding_lrtb(2, 2, 1, 1),
     );
 }
 
-fn show_msg(siv: &mut Cursive, msg: &str) {
+fn save_error_details(siv: &mut Cursive, details: &str, plan: Option<&str>) {
+    match report::save_report(details, plan) {
+        Ok(path) => show_msg(
+            siv,
+            &tr!(
+                "Error details have been saved to:\n\n{}\n\nPlease attach this file when reporting the problem. To copy it elsewhere, open another console with Ctrl+Alt+F2 and use the path above, e.g., cp {} /mnt.",
+                path.display(),
+                path.display()
+            ),
+        ),
+        Err(e) => show_msg(siv, &tr!("Failed to save the error details: {}", e)),
+    }
+}
+
+pub(super) fn show_msg(siv: &mut Cursive, msg: &str) {
     siv.add_layer(
-        Dialog::around(TextView::new(msg).max_width(80))
-            .title("AOSC OS Installer")
-            .button("OK", |s| {
+        Dialog::around(dialog_content(TextView::new(msg).scrollable(), 80))
+            .title(tr!("AOSC OS Installer"))
+            .button(tr!("OK"), |s| {
                 s.pop_layer();
             })
             .padding_lrtb(2, 2, 1, 1),
     );
 }
 
-fn show_blocking_message(siv: &mut Cursive, msg: &str) {
+pub(super) fn show_blocking_message(siv: &mut Cursive, msg: &str) {
     siv.add_layer(
-        Dialog::around(TextView::new(msg))
-            .title("AOSC OS Installer")
+        Dialog::around(dialog_content(TextView::new(msg).scrollable(), 80))
+            .title(tr!("AOSC OS Installer"))
             .padding_lrtb(2, 2, 1, 1),
     );
 }
 
+/// Remember the wizard step reached for continuing later, and show it in the step indicator
+fn enter_step(step: WizardStep, config: &InstallConfig) {
+    session::save(step, config).ok();
+    step_indicator::set_stage(step.into());
+}
+
+/// Run `work` on a background thread with a progress layer shown, so that the event loop
+/// is never blocked, then `done` with its result on the UI thread. Cancelling drops the
+/// result, `work` itself still runs to its end
+pub(super) fn run_in_background<T: Send + 'static>(
+    siv: &mut Cursive,
+    msg: &str,
+    work: impl FnOnce() -> T + Send + 'static,
+    done: impl FnOnce(&mut Cursive, T) + 'static,
+) {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let cancelled_copy = cancelled.clone();
+    siv.add_layer(
+        Dialog::around(dialog_content(TextView::new(msg).scrollable(), 80))
+            .title(tr!("AOSC OS Installer"))
+            .button(tr!("Cancel"), move |s| {
+                cancelled_copy.store(true, Ordering::SeqCst);
+                s.pop_layer();
+            })
+            .padding_lrtb(2, 2, 1, 1)
+            .with_name(BACKGROUND_TASK),
+    );
+    let done = SendWrapper::new(done);
+    let cb_sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let result = work();
+        cb_sink
+            .send(Box::new(move |s| {
+                if cancelled.load(Ordering::SeqCst) {
+                    return;
+                }
+                let screen = s.screen_mut();
+                if let Some(position) = screen.find_layer_from_name(BACKGROUND_TASK) {
+                    screen.remove_layer(position);
+                }
+                (done.take())(s, result);
+            }))
+            .ok();
+    });
+}
+
 fn partition_button(device_path: PathBuf) -> PartitionButton {
     if env::var("DISPLAY").is_ok() {
         return (
-            "Open GParted",
+            tr!("Open GParted"),
             Box::new(move |s, _| {
-                show_blocking_message(s, "Waiting for GParted Partitioning Program to exit ...");
+                show_blocking_message(
+                    s,
+                    tr!("Waiting for GParted Partitioning Program to exit ..."),
+                );
                 let cb_sink = s.cb_sink().clone();
                 let device_path = device_path.clone();
                 thread::spawn(move || {
@@ -156,7 +339,7 @@ fn partition_button(device_path: PathBuf) -> PartitionButton {
                             let device_path = device_path.clone();
                             let partitions =
                                 disks::list_partitions(Some(device_path.to_path_buf()));
-                            let (disk_list, disk_view) = make_partition_list(partitions);
+                            let (disk_list, disk_view) = make_partition_list(partitions, None);
                             s.set_user_data(SendWrapper::new(disk_list));
                             s.call_on_name("part_list", |view: &mut NamedView<LinearLayout>| {
                                 *view = disk_view;
@@ -170,25 +353,17 @@ fn partition_button(device_path: PathBuf) -> PartitionButton {
     }
 
     (
-        "Open Shell",
+        tr!("Open Shell"),
         Box::new(|s, config| {
             s.set_user_data(config);
             let dump = s.dump();
             s.quit();
-            s.set_user_data(dump);
+            s.set_user_data(ShellRequest::Live(dump));
         }),
     )
 }
 
-#[inline]
-fn human_size(size: u64) -> String {
-    match NumberPrefix::binary(size as f64) {
-        NumberPrefix::Standalone(bytes) => format!("{bytes} B"),
-        NumberPrefix::Prefixed(prefix, n) => format!("{n:.1} {prefix}B"),
-    }
-}
-
-fn make_device_list(devices: Vec<Device>) -> Vec<DkDerive> {
+pub(super) fn make_device_list(devices: Vec<Device>) -> Vec<DkDerive> {
     let mut res = vec![];
 
     for i in devices {
@@ -207,8 +382,10 @@ fn make_device_list(devices: Vec<Device>) -> Vec<DkDerive> {
     res
 }
 
+/// The partitions to choose from, the one at `selected` (chosen before) is selected
 fn make_partition_list(
     partitions: Vec<disks::Partition>,
+    selected: Option<&Path>,
 ) -> (RadioGroup<disks::Partition>, NamedView<LinearLayout>) {
     let mut disk_view = LinearLayout::vertical();
     let mut disk_list = RadioGroup::new();
@@ -218,17 +395,21 @@ fn make_partition_list(
         } else {
             "?".to_owned()
         };
-        let radio = disk_list.button(
+        let mut radio = disk_list.button(
             part.clone(),
             format!(
                 "{} ({}, {})",
                 path_name,
                 part.fs_type
                     .as_ref()
-                    .unwrap_or(&"Unknown/Unformatted".to_owned()),
+                    .map(|x| x.as_str())
+                    .unwrap_or(tr!("Unknown/Unformatted")),
                 human_size(part.size)
             ),
         );
+        if selected.is_some() && part.path.as_deref() == selected {
+            radio = radio.selected();
+        }
         disk_view.add_child(radio);
     }
     if partitions.is_empty() {
@@ -240,7 +421,7 @@ fn make_partition_list(
         };
         disk_view.add_child(disk_list.button(
             dummy_partition,
-            "Please select a system partition for AOSC OS.",
+            tr!("Please select a system partition for AOSC OS."),
         ));
     }
 
@@ -252,97 +433,721 @@ pub fn wrap_in_dialog<V: View, S: Into<String>>(
     title: S,
     width: Option<usize>,
 ) -> Dialog {
-    Dialog::around(ResizedView::new(
-        SizeConstraint::AtMost(width.unwrap_or(64)),
-        SizeConstraint::Free,
-        ScrollView::new(inner),
-    ))
-    .padding_lrtb(2, 2, 1, 1)
-    .title(title)
+    Dialog::around(dialog_content(ScrollView::new(inner), width.unwrap_or(64)))
+        .padding_lrtb(2, 2, 1, 1)
+        .title(title)
 }
 
 fn build_variant_list(
     mirrors: Vec<Mirror>,
     variants: Vec<VariantEntry>,
     config: InstallConfig,
-) -> Dialog {
+) -> Shortcuts<Dialog> {
     let mut config_view = LinearLayout::vertical();
+    // the variant chosen before stays selected when coming back
+    let selected = config
+        .variant
+        .as_ref()
+        .and_then(|x| variants.iter().position(|y| y.name == x.name))
+        .unwrap_or(0);
+    let details = TextContent::new(
+        variants
+            .get(selected)
+            .map(variant_details)
+            .unwrap_or_default(),
+    );
+    let details_copy = details.clone();
+    let variants_copy = variants.clone();
+    let variants_copy_2 = variants.clone();
+    let shown = Rc::new(Cell::new(selected));
+    let shown_copy = shown.clone();
 
+    // the columns can be sorted by selecting their headers
     let variant_view = TableView::<network::VariantEntry, VariantColumn>::new()
-        .column(VariantColumn::Name, "Available Distributions", |c| {
-            c.width(30)
+        .column(VariantColumn::Name, tr!("Available Distributions"), |c| c)
+        .column(VariantColumn::Date, tr!("Last Updated"), |c| c.width(14))
+        .column(VariantColumn::Size, tr!("Download Size"), |c| c.width(14))
+        .column(VariantColumn::InstallSize, tr!("Installed Size"), |c| {
+            c.width(14)
         })
-        .column(VariantColumn::Date, "Last Updated", |c| c.width(22))
-        .column(VariantColumn::Size, "Download Size", |c| c.width(22))
+        .default_column(VariantColumn::Name)
         .items(variants.clone())
+        .selected_item(selected)
+        .on_select(move |_, _row, index| {
+            if let Some(variant) = variants_copy.get(index) {
+                details_copy.set_content(variant_details(variant));
+                shown_copy.set(index);
+            }
+        })
         .on_submit(move |siv, _row, index| {
             let mut config = config.clone();
-            config.variant = Some(Arc::new(variants.get(index).unwrap().clone()));
+            let notice = config.change_variant(variants.get(index).unwrap().clone());
+            if config.back_to_summary {
+                // the mirror is kept, the partition has to be selected again if it is too small
+                match notice {
+                    None => return_to_summary(siv, config),
+                    Some(notice) => {
+                        config.back_to_summary = false;
+                        select_disk(siv, config);
+                        show_msg(siv, &notice);
+                    }
+                }
+                return;
+            }
             select_mirrors(siv, mirrors.clone(), config);
+            if let Some(notice) = notice {
+                show_msg(siv, &notice);
+            }
         })
-        .min_width(80)
-        .min_height(30);
-    let variant_view = Panel::new(variant_view).title("Variant");
-    config_view.add_child(TextView::new(VARIANT_TEXT));
+        // fits in 80 columns with the borders and padding around it
+        .min_width(72)
+        .min_height(10);
+    let variant_view = Panel::new(variant_view).title(tr!("Variant"));
+    config_view.add_child(TextView::new(tr!(VARIANT_TEXT)));
     config_view.add_child(variant_view);
+    config_view.add_child(Panel::new(TextView::new_with_content(details)).title(tr!("Details")));
     config_view.add_child(DummyView {});
 
-    wrap_in_dialog(config_view, "AOSC OS Installation", Some(128)).button("Exit", |s| s.quit())
+    let more_info = move |s: &mut Cursive| {
+        if let Some(variant) = variants_copy_2.get(shown.get()) {
+            show_variant_info(s, variant);
+        }
+    };
+    with_help(
+        wrap_in_dialog(config_view, tr!("AOSC OS Installation"), Some(128))
+            .button(tr!("More Info"), more_info.clone())
+            .button(tr!("Exit"), |s| s.quit()),
+        Screen::Variant,
+    )
+    .shortcut('i', "More Info", more_info)
+}
+
+/// The whole description of the variant and the details of its release file, for when
+/// they do not fit in the details under the list
+fn show_variant_info(siv: &mut Cursive, variant: &VariantEntry) {
+    siv.add_layer(
+        Dialog::around(dialog_content(
+            TextView::new(variant_details(variant)).scrollable(),
+            80,
+        ))
+        .title(tr!("About the {} Variant", variant.name))
+        .button(tr!("OK"), |s| {
+            s.pop_layer();
+        })
+        .padding_lrtb(2, 2, 1, 1),
+    );
 }
 
 fn select_variant(siv: &mut Cursive, config: InstallConfig) {
+    siv.pop_layer();
+    enter_step(WizardStep::Variant, &config);
+    // Only ask GeoIP for a hint if the live system does not know better
+    let need_geoip = config.timezone.is_none()
+        && matches!(
+            read_timezone().as_deref(),
+            None | Some("UTC") | Some("Etc/UTC")
+        );
+    let generation = RECIPE_FETCH.fetch_add(1, Ordering::SeqCst) + 1;
+    siv.add_layer(recipe_loading_dialog(config.clone()));
+    let cb_sink = siv.cb_sink().clone();
+    watch_recipe_fetch(cb_sink.clone(), generation);
+    thread::spawn(move || {
+        let result = fetch_release_info(need_geoip);
+        cb_sink
+            .send(Box::new(move |s| {
+                // cancelled, or the user has gone on without it
+                if RECIPE_FETCH
+                    .compare_exchange(
+                        generation,
+                        generation + 1,
+                        Ordering::SeqCst,
+                        Ordering::SeqCst,
+                    )
+                    .is_err()
+                {
+                    return;
+                }
+                s.pop_layer();
+                let view: Box<dyn View> = match result {
+                    Ok(Ok(info)) => release_info_view(info, config),
+                    Ok(Err(connectivity)) => variant_network_check(s, connectivity, config),
+                    Err(e) => Box::new(recipe_error_dialog(&e, config)),
+                };
+                s.add_layer(view);
+            }))
+            .ok();
+    });
+}
+
+/// The mirrors, the variants, the timezone from GeoIP (if asked for) and the bulletin
+type ReleaseInfo = (
+    Vec<Mirror>,
+    Vec<VariantEntry>,
+    Option<String>,
+    network::Bulletin,
+);
+
+/// Fetch the recipe and what is needed along with it, the connectivity if the AOSC OS
+/// servers can not be reached
+fn fetch_release_info(need_geoip: bool) -> Result<Result<ReleaseInfo, Connectivity>, String> {
+    let manifest = match network::fetch_recipe() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            warn!("Failed to fetch the recipe: {}", e);
+            // e.g. a laptop without Ethernet, which has to join a Wi-Fi network first
+            let connectivity = network::check_connectivity();
+            if connectivity.result().is_ok() {
+                return Err(e.to_string());
+            }
+            return Ok(Err(connectivity));
+        }
+    };
+    let mirrors = network::fetch_mirrors(&manifest);
+    let bulletin = manifest.bulletin.clone();
+    let variants = network::find_variant_candidates(manifest).map_err(|e| e.to_string())?;
+    let timezone = if need_geoip {
+        // only a timezone the system knows of is taken as a hint
+        network::fetch_geoip_timezone().ok().and_then(|zone| {
+            let zoneinfo_list = install::get_zoneinfo_list().ok()?;
+            install::check_timezone(&zone, &zoneinfo_list).ok()
+        })
+    } else {
+        None
+    };
+
+    Ok(Ok((mirrors, variants, timezone, bulletin)))
+}
+
+/// Shown while the recipe is being fetched, the elapsed time is filled in by `watch_recipe_fetch`
+fn recipe_loading_dialog(config: InstallConfig) -> Dialog {
+    wrap_in_dialog(
+        TextView::new(recipe_loading_text(0, Duration::ZERO)).with_name(RECIPE_LOADING),
+        tr!("AOSC OS Installation"),
+        Some(72),
+    )
+    .button(tr!("Cancel"), move |s| {
+        // the result of the fetch is dropped once it arrives
+        RECIPE_FETCH.fetch_add(1, Ordering::SeqCst);
+        s.pop_layer();
+        let connectivity = Connectivity { stages: vec![] };
+        let view = variant_network_check(s, connectivity, config.clone());
+        s.add_layer(view);
+    })
+    .button(tr!("Exit"), |s| s.quit())
+}
+
+/// e.g. "⠹ Fetching the release information ... (12s)", with a hint to check the network if
+/// it is slow
+fn recipe_loading_text(tick: usize, elapsed: Duration) -> String {
+    let text = tr!(
+        "{} Fetching the release information ... ({})",
+        SPINNER[tick % SPINNER.len()],
+        short_duration(elapsed)
+    );
+    if elapsed < RECIPE_SLOW {
+        return text;
+    }
+
+    format!(
+        "{}\n\n{}",
+        text,
+        tr!("This is taking longer than usual, Installer is still trying. Is the network working? Select \"Cancel\" to check it.")
+    )
+}
+
+/// Spin the loading dialog until the fetch of `generation` is over or cancelled
+fn watch_recipe_fetch(cb_sink: CbSink, generation: u64) {
+    let started = Instant::now();
+    thread::spawn(move || {
+        for tick in 1.. {
+            thread::sleep(SPINNER_INTERVAL);
+            if RECIPE_FETCH.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            let text = recipe_loading_text(tick, started.elapsed());
+            let update = cb_sink.send(Box::new(move |s| {
+                s.call_on_name(RECIPE_LOADING, |view: &mut TextView| view.set_content(text));
+            }));
+            if update.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// The variant list, after the bulletin if there is a new one
+fn release_info_view(info: ReleaseInfo, mut config: InstallConfig) -> Box<dyn View> {
+    let (mirrors, variants, timezone, bulletin) = info;
+    if config.timezone.is_none() {
+        config.timezone = timezone.map(Arc::new);
+    }
+    // a bulletin is shown once, not every time the user comes back here
+    let seen = config.bulletin.as_deref() == Some(&bulletin);
+    if bulletin.is_empty() || seen {
+        return Box::new(build_variant_list(mirrors, variants, config));
+    }
+    config.bulletin = Some(Arc::new(bulletin));
+
+    Box::new(bulletin_dialog(mirrors, variants, config))
+}
+
+/// The recipe could not be fetched although the network works, e.g., the server is broken
+fn recipe_error_dialog(e: &str, config: InstallConfig) -> Dialog {
+    wrap_in_dialog(
+        TextView::new(tr!(
+            "Installer failed to fetch the release information: {}",
+            e
+        )),
+        tr!("AOSC OS Installation"),
+        Some(72),
+    )
+    .button(tr!("Retry"), move |s| select_variant(s, config.clone()))
+    .button(tr!("Exit"), |s| s.quit())
+}
+
+/// The network check, going on with the variant list once the network works, or offline
+/// with the release information kept from before
+fn variant_network_check(
+    siv: &mut Cursive,
+    connectivity: Connectivity,
+    config: InstallConfig,
+) -> Box<dyn View> {
+    let retry = {
+        let config = config.clone();
+        Arc::new(move |s: &mut Cursive| select_variant(s, config.clone()))
+    };
+    let offline = move |s: &mut Cursive, path: Option<PathBuf>| {
+        let recipe = match network::cached_recipe() {
+            Ok(recipe) => recipe,
+            Err(e) => {
+                warn!("No recipe kept from before: {}", e);
+                show_msg(s, tr!("Installer has not kept the release information from being online before, so AOSC OS can not be installed offline."));
+                return;
+            }
+        };
+        // a release from `aoscdk-rs download` tells its variant, only that one is listed
+        let sidecar = match path.as_deref().map(network::ReleaseSidecar::read) {
+            Some(Ok(sidecar)) => sidecar,
+            Some(Err(e)) => {
+                warn!("Failed to read the description of the local release: {}", e);
+                None
+            }
+            None => None,
+        };
+        let mut config = config.clone();
+        config.local_release = path.map(Arc::new);
+        let mirrors = network::fetch_mirrors(&recipe);
+        let variants = network::find_variant_candidates(recipe).map(|variants| match &sidecar {
+            Some(sidecar) => variants
+                .into_iter()
+                .filter(|x| x.sha256sum == sidecar.sha256)
+                .collect(),
+            None => variants,
+        });
+        match (variants, &sidecar) {
+            (Ok(variants), Some(sidecar)) if variants.is_empty() => show_msg(
+                s,
+                &tr!(
+                    "The local copy of AOSC OS {} ({}) is not in the release information kept from before, please go online to install it.",
+                    sidecar.variant,
+                    sidecar.date
+                ),
+            ),
+            (Ok(variants), _) => {
+                s.pop_layer();
+                s.add_layer(build_variant_list(mirrors, variants, config));
+            }
+            (Err(e), _) => show_msg(s, &e.to_string()),
+        }
+    };
+
+    Box::new(network_setup::network_check(
+        connectivity,
+        siv.cb_sink().clone(),
+        retry,
+        Rc::new(offline),
+    ))
+}
+
+/// The title and body of the bulletin, in red if it is a warning
+fn bulletin_text(bulletin: &network::Bulletin) -> StyledString {
+    let text = format!(
+        "{}\n\n{}",
+        i18n::localized(&bulletin.title, &bulletin.title_tr),
+        i18n::localized(&bulletin.body, &bulletin.body_tr)
+    );
+    if bulletin.needs_acknowledgement() {
+        emphasized(text, Emphasis::Danger)
+    } else {
+        StyledString::plain(text)
+    }
+}
+
+/// Show the release bulletin before the variant list, a warning has to be acknowledged, and a
+/// critical one read to the end first
+fn bulletin_dialog(
+    mirrors: Vec<Mirror>,
+    variants: Vec<VariantEntry>,
+    config: InstallConfig,
+) -> NamedView<Dialog> {
+    let bulletin = config.bulletin.clone().unwrap();
+    let level = bulletin.level();
+    let text = TextView::new(bulletin_text(&bulletin));
+    let content = if level == BulletinLevel::Critical {
+        LinearLayout::vertical()
+            .child(text.scrollable().with_name(BULLETIN_TEXT).max_height(15))
+            .child(DummyView {})
+            .child(
+                LinearLayout::horizontal()
+                    .child(
+                        Checkbox::new()
+                            .on_change(acknowledge_bulletin)
+                            .with_name(BULLETIN_ACKNOWLEDGED),
+                    )
+                    .child(TextView::new(tr!(
+                        " I have read the bulletin to the end, and understand the risk of installing now"
+                    ))),
+            )
+    } else {
+        LinearLayout::vertical().child(text)
+    };
+    let dialog = wrap_in_dialog(content, tr!("Release Bulletin"), None);
+    let next = move |s: &mut Cursive| {
+        if bulletin.needs_acknowledgement() {
+            info!(
+                "Release bulletin {} ({}) acknowledged: {}",
+                bulletin.id(),
+                bulletin.type_,
+                bulletin.title
+            );
+        }
+        s.pop_layer();
+        s.add_layer(build_variant_list(
+            mirrors.clone(),
+            variants.clone(),
+            config.clone(),
+        ));
+    };
+    let dialog = match level {
+        BulletinLevel::Critical => {
+            let mut dialog = dialog
+                .button(tr!("Continue"), next)
+                .button(tr!("Exit"), |s| s.quit());
+            if let Some(button) = dialog.buttons_mut().next() {
+                button.disable();
+            }
+            dialog
+        }
+        BulletinLevel::Warning => dialog
+            .button(tr!("I Understand"), next)
+            .button(tr!("Exit"), |s| s.quit()),
+        BulletinLevel::None | BulletinLevel::Info => dialog.button(tr!("OK"), next),
+    };
+
+    dialog.with_name(BULLETIN_DIALOG)
+}
+
+/// Tick (or untick) the acknowledgement of a critical bulletin, which can only be ticked
+/// once it has been scrolled to the end
+fn acknowledge_bulletin(s: &mut Cursive, checked: bool) {
+    let read = s
+        .call_on_name(BULLETIN_TEXT, |view: &mut ScrollView<TextView>| {
+            view.is_at_bottom()
+        })
+        .unwrap_or(true);
+    if checked && !read {
+        s.call_on_name(BULLETIN_ACKNOWLEDGED, |view: &mut Checkbox| {
+            // the callback of unchecking is not run
+            view.set_checked(false);
+        });
+        show_msg(
+            s,
+            tr!("Please read the bulletin to the end first, scroll it down with the arrow keys."),
+        );
+        return;
+    }
+    s.call_on_name(BULLETIN_DIALOG, |view: &mut Dialog| {
+        if let Some(button) = view.buttons_mut().next() {
+            button.set_enabled(checked);
+        }
+    });
+}
+
+fn select_mirrors(siv: &mut Cursive, mirrors: Vec<Mirror>, config: InstallConfig) {
+    siv.pop_layer();
+    siv.add_layer(select_mirrors_view(mirrors, None, config));
+}
+
+/// Go back to the mirror list from the disk selection, the mirrors are fetched again
+fn back_to_mirrors(siv: &mut Cursive, config: InstallConfig) {
     siv.pop_layer();
     let loader = AsyncView::new_with_bg_creator(
         siv,
         move || {
             let manifest = network::fetch_recipe().map_err(|e| e.to_string())?;
-            let mirrors = network::fetch_mirrors(&manifest);
-            let variants = network::find_variant_candidates(manifest).map_err(|e| e.to_string())?;
-            Ok((mirrors, variants))
+            Ok(network::fetch_mirrors(&manifest))
         },
-        move |(mirrors, variants)| build_variant_list(mirrors, variants, config.clone()),
+        move |mirrors| select_mirrors_view(mirrors, None, config.clone()),
     );
-
     siv.add_layer(loader);
 }
 
-fn select_mirrors(siv: &mut Cursive, mirrors: Vec<Mirror>, config: InstallConfig) {
-    siv.pop_layer();
-    let (config_view, repo_list) = select_mirror_view_base(&mirrors);
-    siv.add_layer(select_mirrors_view(config_view, config, repo_list, mirrors));
-}
-
-fn select_mirror_view_base(mirrors: &[Mirror]) -> (LinearLayout, RadioGroup<Mirror>) {
+/// The mirror list, ranked by the speedtest `results` if the mirrors have been tested,
+/// otherwise in the order of the manifest, the mirror in `config` stays selected. Mirrors
+/// are grouped by their location in collapsible sections, the group of the selected mirror
+/// (or the first one) is expanded
+fn select_mirror_view_base(
+    mirrors: &[Mirror],
+    results: Option<&[(Mirror, MirrorTest)]>,
+    config: &InstallConfig,
+) -> (LinearLayout, RadioGroup<Mirror>) {
     let mut config_view = LinearLayout::vertical();
     let mut repo_list = RadioGroup::new();
-    let mirror_list = mirrors;
+    let mirror_list = match results {
+        Some(results) => results
+            .iter()
+            .map(|(x, y)| (x, Some(y)))
+            .collect::<Vec<_>>(),
+        None => mirrors.iter().map(|x| (x, None)).collect(),
+    };
+    let selected = config.mirror.as_ref().map(|x| x.url.as_str());
     let mut repo_view = LinearLayout::vertical()
         .child(TextView::new(
-            "Please select a mirror to download AOSC OS. Generally, a mirror closest to you geographically would be the best bet for download speeds.",
+            tr!("Please select a mirror to download AOSC OS. Generally, a mirror closest to you geographically would be the best bet for download speeds."),
         ))
         .child(DummyView {});
-    for mirror in mirror_list {
-        let radio = repo_list.button(mirror.clone(), format!("{} ({})", mirror.name, mirror.loc));
-        repo_view.add_child(radio);
+
+    // the ranking puts the fastest mirror first, whichever group it is in
+    let fastest = mirror_list
+        .first()
+        .filter(|(_, x)| matches!(x, Some(MirrorTest::Done(_))));
+    if let Some((mirror, Some(test))) = fastest {
+        repo_view.add_child(repo_list.button(
+            (*mirror).clone(),
+            tr!("Fastest overall: {}", mirror_test_label(mirror, test)),
+        ));
+        repo_view.add_child(DummyView {});
+    }
+
+    let groups = mirror_groups(&mirror_list);
+    let expanded = groups
+        .iter()
+        .position(|(_, x)| x.iter().any(|(x, _)| selected == Some(x.url.as_str())))
+        .unwrap_or(0);
+    for (index, (_, group)) in groups.iter().enumerate() {
+        let mut list = LinearLayout::vertical();
+        for (mirror, test) in group {
+            let label = match test {
+                Some(test) => mirror_test_label(mirror, test),
+                None => mirror_name(mirror),
+            };
+            let mut radio = repo_list.button(mirror.clone(), format!("  {label}"));
+            if selected == Some(mirror.url.as_str()) {
+                radio = radio.selected();
+            }
+            list.add_child(radio);
+        }
+
+        let header = mirror_group_header(group, index == expanded);
+        let header_name = format!("mirror_group_{index}");
+        let list_name = format!("mirror_group_list_{index}");
+        let group = group.clone();
+        repo_view.add_child(
+            Button::new_raw(header, move |s| {
+                // the header expands or collapses the group, it is not a mirror to select
+                let expand = s
+                    .call_on_name(&list_name, |view: &mut HideableView<LinearLayout>| {
+                        view.set_visible(!view.is_visible());
+                        view.is_visible()
+                    })
+                    .unwrap_or(false);
+                let header = mirror_group_header(&group, expand);
+                s.call_on_name(&header_name, |view: &mut Button| view.set_label_raw(header));
+            })
+            .with_name(format!("mirror_group_{index}")),
+        );
+        repo_view.add_child(
+            HideableView::new(list)
+                .visible(index == expanded)
+                .with_name(format!("mirror_group_list_{index}")),
+        );
     }
-    let repo_view = Panel::new(repo_view).title("Mirrors");
+    let repo_view = Panel::new(repo_view).title(tr!("Mirrors"));
     config_view.add_child(repo_view);
     config_view.add_child(DummyView {});
 
     (config_view, repo_list)
 }
 
-fn select_mirrors_view(
-    config_view: LinearLayout,
+/// A mirror with its speedtest, if it has been tested
+type MirrorEntry = (Mirror, Option<MirrorTest>);
+
+/// The mirrors grouped by location, in the order the locations first appear in `mirrors`
+fn mirror_groups(mirrors: &[(&Mirror, Option<&MirrorTest>)]) -> Vec<(String, Vec<MirrorEntry>)> {
+    let mut groups: Vec<(String, Vec<MirrorEntry>)> = vec![];
+    for (mirror, test) in mirrors {
+        let entry = ((*mirror).clone(), test.copied());
+        match groups.iter_mut().find(|(loc, _)| *loc == mirror.loc) {
+            Some((_, group)) => group.push(entry),
+            None => groups.push((mirror.loc.clone(), vec![entry])),
+        }
+    }
+
+    groups
+}
+
+/// e.g. "▾ China (5 mirrors, best 12.3 MiB/s)"
+fn mirror_group_header(group: &[MirrorEntry], expanded: bool) -> String {
+    let mark = if expanded { "▾" } else { "▸" };
+    let mirror = &group[0].0;
+    let loc = i18n::localized(&mirror.loc, &mirror.loc_tr);
+    let best = group
+        .iter()
+        .filter_map(|(_, test)| match test {
+            Some(MirrorTest::Done(speed)) => Some(speed.throughput),
+            _ => None,
+        })
+        .reduce(f64::max);
+    match best {
+        Some(best) => tr!(
+            "{} {} ({} mirrors, best {}/s)",
+            mark,
+            loc,
+            group.len(),
+            human_size(best as u64)
+        ),
+        None => tr!("{} {} ({} mirrors)", mark, loc, group.len()),
+    }
+}
+
+/// Name and location of the mirror, in the language of the interface
+fn mirror_name(mirror: &Mirror) -> String {
+    format!(
+        "{} ({})",
+        i18n::localized(&mirror.name, &mirror.name_tr),
+        i18n::localized(&mirror.loc, &mirror.loc_tr)
+    )
+}
+
+/// Name of the mirror with how far its speedtest has got
+fn mirror_test_label(mirror: &Mirror, test: &MirrorTest) -> String {
+    match test {
+        MirrorTest::Queued => tr!("{} - queued", mirror_name(mirror)),
+        MirrorTest::Testing => tr!("{} - testing ...", mirror_name(mirror)),
+        MirrorTest::Done(speed) => tr!(
+            "{} - {}/s, {} ms",
+            mirror_name(mirror),
+            human_size(speed.throughput as u64),
+            speed.latency.as_millis()
+        ),
+        MirrorTest::Failed => tr!("{} - failed", mirror_name(mirror)),
+        MirrorTest::TimedOut => tr!("{} - timed out", mirror_name(mirror)),
+    }
+}
+
+fn mirror_test_progress(tests: &[MirrorTest]) -> String {
+    tr!(
+        "{} of {} mirrors tested",
+        tests.iter().filter(|x| x.is_finished()).count(),
+        tests.len()
+    )
+}
+
+/// Show the mirrors ranked by how far they have been tested, the selected one stays selected
+fn finish_mirror_test(
+    siv: &mut Cursive,
+    mirrors: Vec<Mirror>,
+    tests: &[MirrorTest],
     config: InstallConfig,
-    repo_list: RadioGroup<Mirror>,
+) {
+    let results =
+        network::rank_mirrors(mirrors.iter().cloned().zip(tests.iter().copied()).collect());
+    siv.pop_layer();
+    siv.add_layer(select_mirrors_view(mirrors, Some(results), config));
+}
+
+/// Test the mirrors, showing the result of each as it comes in, then rank them
+fn test_mirrors(siv: &mut Cursive, mirrors: Vec<Mirror>, config: InstallConfig) {
+    let tests = Arc::new(Mutex::new(vec![MirrorTest::Queued; mirrors.len()]));
+    // set once the tests are over, either done or skipped
+    let stop = Arc::new(AtomicBool::new(false));
+    let labels = mirrors
+        .iter()
+        .map(|x| TextContent::new(mirror_test_label(x, &MirrorTest::Queued)))
+        .collect::<Vec<_>>();
+    let progress = TextContent::new(mirror_test_progress(&tests.lock().unwrap()));
+
+    let mut list_view = LinearLayout::vertical();
+    for label in labels.iter() {
+        list_view.add_child(TextView::new_with_content(label.clone()));
+    }
+    let view = LinearLayout::vertical()
+        .child(TextView::new_with_content(progress.clone()))
+        .child(DummyView {})
+        .child(Panel::new(list_view.scrollable()).title(tr!("Mirrors")));
+    let dialog = wrap_in_dialog(view, tr!("AOSC OS Installation"), None).button(tr!("Skip"), {
+        let mirrors = mirrors.clone();
+        let tests = tests.clone();
+        let stop = stop.clone();
+        let config = config.clone();
+        move |s| {
+            // keep what has been tested so far
+            if !stop.swap(true, Ordering::SeqCst) {
+                let tests = tests.lock().unwrap().clone();
+                finish_mirror_test(s, mirrors.clone(), &tests, config.clone());
+            }
+        }
+    });
+    siv.pop_layer();
+    siv.add_layer(dialog);
+
+    let cb_sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        network::speedtest_mirrors(&mirrors, &stop, |index, test| {
+            let progress_text = {
+                let mut tests = tests.lock().unwrap();
+                tests[index] = test;
+                mirror_test_progress(&tests)
+            };
+            let label = labels[index].clone();
+            let label_text = mirror_test_label(&mirrors[index], &test);
+            let progress = progress.clone();
+            cb_sink
+                .send(Box::new(move |_| {
+                    label.set_content(label_text);
+                    progress.set_content(progress_text);
+                }))
+                .ok();
+        });
+        cb_sink
+            .send(Box::new(move |s| {
+                if !stop.swap(true, Ordering::SeqCst) {
+                    let tests = tests.lock().unwrap().clone();
+                    finish_mirror_test(s, mirrors, &tests, config);
+                }
+            }))
+            .ok();
+    });
+}
+
+fn select_mirrors_view(
     mirrors: Vec<Mirror>,
-) -> Dialog {
+    results: Option<Vec<(Mirror, MirrorTest)>>,
+    config: InstallConfig,
+) -> Shortcuts<Dialog> {
+    step_indicator::set_stage(Stage::Mirror);
+    let (config_view, repo_list) = select_mirror_view_base(&mirrors, results.as_deref(), &config);
+    let repo_list = Rc::new(repo_list);
+    let repo_list_copy = repo_list.clone();
+    let repo_list_copy_2 = repo_list.clone();
+    let tested = results.is_some();
     let config_clone = config.clone();
     let config_clone_2 = config.clone();
     let config_clone_3 = config.clone();
-    wrap_in_dialog(config_view, "AOSC OS Installation", None)
-        .button("Continue", move |s| {
+    let config_clone_4 = config.clone();
+    let mirrors_clone = mirrors.clone();
+    let dialog = wrap_in_dialog(config_view, tr!("AOSC OS Installation"), None)
+        .button(tr!("Continue"), move |s| {
             let mut config = config.clone();
             let mirror = repo_list.selection();
             config.mirror = Some(Arc::new(Rc::as_ref(&mirror).clone()));
@@ -353,55 +1158,60 @@ fn select_mirrors_view(
                 select_disk(s, config);
             }
         })
-        .button("Benchmark Mirrors", move |s| {
-            let config_clone_2 = config_clone.clone();
-            let config_clone_3 = config_clone.clone();
-            let mirrors_clone = mirrors.clone();
-            let mirrors_clone_2 = mirrors.clone();
+        .button(
+            if tested {
+                tr!("Re-test")
+            } else {
+                tr!("Benchmark Mirrors")
+            },
+            move |s| {
+                // keep the mirror selected so far
+                let mut config = config_clone.clone();
+                config.mirror = Some(Arc::new(Rc::as_ref(&repo_list_copy.selection()).clone()));
+                if tested {
+                    test_mirrors(s, mirrors_clone.clone(), config);
+                    return;
+                }
+                let mirrors = mirrors_clone.clone();
+                s.add_layer(
+                    Dialog::around(dialog_content(TextView::new(tr!(BENCHMARK_TEXT)), 80))
+                        .title(tr!("AOSC OS Installer"))
+                        .button(tr!("OK"), move |s| {
+                            s.pop_layer();
+                            test_mirrors(s, mirrors.clone(), config.clone());
+                        })
+                        .button(tr!("Cancel"), |s| {
+                            s.pop_layer();
+                        })
+                        .padding_lrtb(2, 2, 1, 1),
+                );
+            },
+        );
+    let dialog = if tested {
+        let mirrors = mirrors.clone();
+        dialog.button(tr!("Skip Test"), move |s| {
+            // back to the order of the manifest
             s.pop_layer();
-            s.add_layer(
-                Dialog::around(TextView::new(BENCHMARK_TEXT).max_width(80))
-                    .title("AOSC OS Installer")
-                    .button("OK", move |s| {
-                        let config_clone_3 = config_clone_2.clone();
-                        let mirrors_clone_2 = mirrors_clone.clone();
-                        let loader = AsyncView::new_with_bg_creator(
-                            s,
-                            move || {
-                                let new_mirrors = network::speedtest_mirrors(mirrors_clone_2);
-                                Ok(new_mirrors)
-                            },
-                            move |mirrors| {
-                                let (config_view, repo_list) = select_mirror_view_base(&mirrors);
-                                select_mirrors_view(
-                                    config_view,
-                                    config_clone_3.clone(),
-                                    repo_list,
-                                    mirrors,
-                                )
-                            },
-                        );
-                        s.pop_layer();
-                        s.add_layer(loader);
-                    })
-                    .button("Cancel", move |s| {
-                        let mirrors_clone_3 = mirrors_clone_2.clone();
-                        let config_clone_4 = config_clone_3.clone();
-                        s.pop_layer();
-                        select_mirrors(s, mirrors_clone_3, config_clone_4);
-                    })
-                    .padding_lrtb(2, 2, 1, 1),
-            );
+            s.add_layer(select_mirrors_view(
+                mirrors.clone(),
+                None,
+                config_clone_4.clone(),
+            ));
         })
-        .button("Specify URL", move |s| {
+    } else {
+        dialog
+    };
+
+    let dialog = dialog
+        .button(tr!("Specify URL"), move |s| {
             let config_clone = config_clone_3.clone();
             let url_input = Rc::new(RefCell::new(String::new()));
             let url_input_copy = url_input.clone();
             s.add_layer(
-                Dialog::around(
+                Dialog::around(dialog_content(
                     LinearLayout::vertical()
                         .child(TextView::new(
-                            "This option is for expert users only!\nThe URL should include protocol and end with \"/aosc-os/\".",
+                            tr!("This option is for expert users only!\nThe URL should include protocol and end with \"/aosc-os/\"."),
                         ))
                         .child(DummyView {})
                         .child(
@@ -417,9 +1227,10 @@ fn select_mirrors_view(
                                 })
                                 .min_width(40),
                         ),
-                )
-                .title("Specify mirror URL")
-                .button("Continue", move |s| {
+                    80,
+                ))
+                .title(tr!("Specify mirror URL"))
+                .button(tr!("Continue"), move |s| {
                     let mut config_clone = config_clone.clone();
                     let url_input_copy = url_input.clone();
                     let url = url_input_copy.as_ref().to_owned().into_inner();
@@ -427,43 +1238,54 @@ fn select_mirrors_view(
 
                     // Verify URL usability. At least we should be able to HEAD this mirror
                     let test_url = format!("{}{}", url_clone, config_clone.variant.as_ref().unwrap().url);
-                    let bench_result = network::query_file_meta(&test_url);
-                    if bench_result.is_err() {
-                        // this mirror is not usable, ask the user to check input.
-                        s.add_layer(Dialog::info(format!("Cannot access mirror! - Please make sure you have typed the URL correctly.\n\nError details:\n{}", bench_result.unwrap_err())));
-                        return;
-                    }
+                    run_in_background(
+                        s,
+                        &tr!("Checking {} ...", url_clone),
+                        move || network::query_file_meta(&test_url).map(|_| ()),
+                        move |s, bench_result| {
+                            if let Err(e) = bench_result {
+                                // this mirror is not usable, ask the user to check input.
+                                show_msg(s, &tr!("Cannot access mirror! - Please make sure you have typed the URL correctly.\n\nError details:\n{}", e));
+                                return;
+                            }
 
-                    // pop input window and mirror selection once we confirmed a working mirror
-                    s.pop_layer();
-                    s.pop_layer();
+                            // pop input window and mirror selection once we confirmed a working mirror
+                            s.pop_layer();
+                            s.pop_layer();
 
-                    config_clone.mirror = Some(Arc::new(Mirror {
-                        name: String::from("User specified"),
-                        name_tr: String::from("user-name"),
-                        loc: String::from("User specified"),
-                        loc_tr: String::from("user-loc"),
-                        url,
-                    }));
+                            config_clone.mirror = Some(Arc::new(Mirror {
+                                name: String::from("User specified"),
+                                name_tr: String::new(),
+                                loc: String::from("User specified"),
+                                loc_tr: String::new(),
+                                url,
+                                repo_url: None,
+                            }));
 
-                    if config_clone.partition.is_some() {
-                        s.pop_layer();
-                        s.add_layer(select_user_password(config_clone.clone()));
-                    } else {
-                        select_disk(s, config_clone);
-                    }
+                            if config_clone.partition.is_some() {
+                                s.pop_layer();
+                                s.add_layer(select_user_password(config_clone.clone()));
+                            } else {
+                                select_disk(s, config_clone);
+                            }
+                        },
+                    );
                 })
-                .button("Cancel", |s| {
+                .button(tr!("Cancel"), |s| {
                     s.pop_layer();
                 })
                 .padding_lrtb(2, 2, 1, 1),
             );
         })
-        .button("Back", move |s| {
+        .button(tr!("Back"), move |s| {
+            let mut config = config_clone_2.clone();
+            config.mirror = Some(Arc::new(Rc::as_ref(&repo_list_copy_2.selection()).clone()));
             s.pop_layer();
-            select_variant(s, config_clone_2.clone());
+            select_variant(s, config);
         })
-        .button("Exit", |s| s.quit())
+        .button(tr!("Exit"), |s| s.quit());
+
+    with_help(dialog, Screen::Mirrors)
 }
 
 fn select_partition(siv: &mut Cursive, config: InstallConfig, dev: Rc<DkDerive>) {
@@ -471,12 +1293,13 @@ fn select_partition(siv: &mut Cursive, config: InstallConfig, dev: Rc<DkDerive>)
     let path = dev.path.clone();
 
     let cb_sink = siv.cb_sink().clone();
+    let selected = config.partition.as_ref().and_then(|x| x.path.clone());
 
     let view = AsyncView::new_with_bg_creator(
         siv,
         move || Ok(disks::list_partitions(Some(path))),
         move |partitions| {
-            let (disk_list, disk_view) = make_partition_list(partitions);
+            let (disk_list, disk_view) = make_partition_list(partitions, selected.as_deref());
             let disk_list = SendWrapper::new(disk_list);
             cb_sink
                 .send(Box::new(move |s| {
@@ -489,9 +1312,9 @@ fn select_partition(siv: &mut Cursive, config: InstallConfig, dev: Rc<DkDerive>)
     );
 
     let s = if env::var("DISPLAY").is_ok() {
-        "Please select a partition as AOSC OS system partition. If you would like to make changes to your partitions, please select \"Open GParted.\""
+        tr!("Please select a partition as AOSC OS system partition. If you would like to make changes to your partitions, please select \"Open GParted.\"")
     } else {
-        "Please select a partition as AOSC OS system partition. If you would like to make changes to your partitions, please select \"Open Shell.\""
+        tr!("Please select a partition as AOSC OS system partition. If you would like to make changes to your partitions, please select \"Open Shell.\"")
     };
 
     let dest_view = LinearLayout::vertical()
@@ -500,1255 +1323,4044 @@ fn select_partition(siv: &mut Cursive, config: InstallConfig, dev: Rc<DkDerive>)
         .child(view);
 
     let config_view = LinearLayout::vertical()
-        .child(Panel::new(dest_view).title("Select System Partition"))
+        .child(Panel::new(dest_view).title(tr!("Select System Partition")))
         .child(DummyView {});
 
     let (btn_label, btn_cb) = partition_button(dev.path.to_path_buf());
     let config_copy = config.clone();
     let config_copy_2 = config.clone();
-    let config_clone_3 = config.clone();
     let config_clone_4 = config.clone();
 
-    siv.add_layer(
-        wrap_in_dialog(config_view, "AOSC OS Installation", None)
-        .button("Continue", move |s| {
-            let disk_list = s.user_data::<SendWrapper<RadioGroup<disks::Partition>>>();
-            let variant = config_clone_3.variant.as_ref().unwrap();
-            let required_size = variant.install_size + variant.size;
-            if let Some(disk_list) = disk_list {
-                let disk_list = disk_list.clone().take();
-                let current_partition = if cfg!(debug_assertions) {
-                    // prevent developer/tester accidentally delete their partitions
-                    Rc::new(disks::Partition {
-                        fs_type: None,
-                        path: Some(PathBuf::from("/dev/loop30p1")),
-                        parent_path: Some(PathBuf::from("/dev/loop30")),
-                        size: required_size,
-                    })
-                } else {
-                    disk_list.selection()
-                };
-                if current_partition.parent_path.is_none() && current_partition.size == 0 {
-                    show_msg(s, "Please specify a system partition.");
-                    return;
-                }
-                if current_partition.size < required_size {
-                    show_msg(
-                        s,
-                        &format!(
-                            "The specified partition does not contain enough space to install AOSC OS release!\n\nAvailable space: {:.3}GiB\nRequired space: {:.3}GiB", 
-                            current_partition.size as f32 / 1024.0 / 1024.0 / 1024.0,
-                            required_size as f32 / 1024.0 / 1024.0 / 1024.0
-                        ));
-                    return;
-                }
-                let mut config = config.clone();
-                let config_copy = config.clone();
-                let config_copy_2 = config.clone();
-                let fs_type = current_partition.fs_type.clone();
-                let current_partition_clone = current_partition.clone();
-
-                if let Err(e) = disks::right_combine(current_partition.parent_path.as_deref()) {
-                    let view = wrap_in_dialog(LinearLayout::vertical()
-                    .child(TextView::new(e.to_string())), "AOSC OS Installer", None)
-                    .button("OK", |s| {
-                        s.pop_layer();
-                    })
-                    .button("Exit", |s| s.quit());
-                    s.add_layer(view);
-                    return;
-                }
-
-                if is_efi_booted() {
-                    let has_efi = disks::find_esp_partition(current_partition.parent_path.as_ref().unwrap()).is_ok();
-                    if !has_efi {
-                        show_msg(s, NO_ESP_ERROR);
-                        return;
-                    }
+    siv.add_layer(with_help(
+        wrap_in_dialog(config_view, tr!("AOSC OS Installation"), None)
+            .button(tr!("Continue"), move |s| {
+                let disk_list = s.user_data::<SendWrapper<RadioGroup<disks::Partition>>>();
+                if let Some(disk_list) = disk_list {
+                    let disk_list = disk_list.clone().take();
+                    use_partition(s, config.clone(), disk_list.selection());
                 }
+            })
+            .button(btn_label, move |s| {
+                btn_cb(s, config_copy.clone());
+            })
+            .button(tr!("Partition for Me"), move |s| {
+                let dev_clone = dev_clone.clone();
+                let path = dev.path.clone();
+                let dev_size = dev.size;
+                let select_device = format!(
+                    "{} ({}, {})",
+                    dev_clone.path.display(),
+                    dev_clone.model,
+                    human_size(dev_size)
+                );
 
-                if let Some(fs_type) = fs_type {
-                    if fs_type != "ext4" && ALLOWED_FS_TYPE.contains(&fs_type.as_str()) {
-                        let view = wrap_in_dialog(LinearLayout::vertical()
-                        .child(TextView::new(format!(SURE_FS_TYPE_INFO!(), &fs_type))), "AOSC OS Installer", None)
-                        .button("Use Ext4", move |s| {
-                            let new_part = disks::fill_fs_type(current_partition.as_ref(), true);
-                            let mut config_clone = config_copy_2.clone();
-                            config_clone.partition = Some(Arc::new(new_part.clone()));
-                            s.pop_layer();
-                            continue_to_format_hdd(s, config_clone, new_part.fs_type.expect("Must unwrap success"));
-                        })
-                        .button(format!("Use {fs_type}"), move |s| {
-                            let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), false);
-                            let mut config_clone = config_copy.clone();
-                            config_clone.partition = Some(Arc::new(new_part.clone()));
-                            s.pop_layer();
-                            continue_to_format_hdd(s, config_clone, new_part.fs_type.expect("Must unwrap success"));
-                        })
-                        .button("Cancel", move |s| {
-                            s.cb_sink()
-                            .send(Box::new(|s| {
-                                s.pop_layer();
-                            }))
-                            .unwrap()
-                        });
-                        s.add_layer(view);
-                    } else if fs_type == "ext4" {
-                        let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), true);
-                        config.partition = Some(Arc::new(new_part.clone()));
-                        continue_to_format_hdd(s, config, new_part.fs_type.expect("Must unwrap success"));
-                    } else if !ALLOWED_FS_TYPE.contains(&fs_type.as_str()) {
-                        let view = wrap_in_dialog(LinearLayout::vertical()
-                        .child(TextView::new(ADVANCED_METHOD_INFO)), "AOSC OS Installer", None)
-                        .button("OK", move |s| {
-                            let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), true);
-                            let mut config_clone = config_copy.clone();
-                            config_clone.partition = Some(Arc::new(new_part.clone()));
-                            s.pop_layer();
-                            continue_to_format_hdd(s, config_clone, new_part.fs_type.expect("Must unwrap success"));
-                        })
-                        .button("Cancel", move |s| {
-                            s.cb_sink()
-                            .send(Box::new(|s| {
-                                s.pop_layer();
-                            }))
-                            .unwrap()
-                        });
-                        s.add_layer(view);
-                    }
-                } else {
-                    let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), true);
-                    config.partition = Some(Arc::new(new_part.clone()));
-                    continue_to_format_hdd(s, config, new_part.fs_type.expect("Must success unwrap"));
-                }
-            }
-        })
-        .button(btn_label, move |s| {
-            btn_cb(s, config_copy.clone());
-        })
-        .button("Partition for Me", move |s| {
-            let dev_clone = dev_clone.clone();
-            let path = dev.path.clone();
-            let dev_size = dev.size;
-            let select_device = format!(
-                "{} ({}, {})",
-                dev_clone.path.display(),
-                dev_clone.model,
-                human_size(dev_size)
-            );
+                let desc = auto_partition_desc(dev_size, 0);
 
-            let desc = if is_efi_booted() {
-                format!("- A 512MiB EFI System Partition (ESP) will be created.\n- A {} system root partition will be created.", human_size(dev_size - 512 * 1024_u64.pow(2)))
-            } else {
-                format!("- A {} system root partition will be created.", human_size(dev_size))
-            };
+                auto_partition_view(
+                    s,
+                    config_clone_4.clone(),
+                    &select_device,
+                    &desc,
+                    dev_clone.as_ref().clone(),
+                    0,
+                    false,
+                )
+            })
+            .button(tr!("Back"), move |s| {
+                s.pop_layer();
+                select_disk(s, config_copy_2.clone());
+            })
+            .button(tr!("Exit"), |s| s.quit()),
+        Screen::Partition,
+    ));
+}
 
-            auto_partition_view(s, config_clone_4.clone(), &select_device, &desc, path)
+/// Check that the partition can hold AOSC OS, and go on to format or reinstall on it
+fn use_partition(s: &mut Cursive, mut config: InstallConfig, partition: Rc<disks::Partition>) {
+    config.storage_mode = Some(StorageMode::Advanced);
+    config.keep_free_space = None;
+    // the passphrase is asked for again with the mount points
+    config.encryption_passphrase = None;
+    let variant = config.variant.as_ref().unwrap();
+    let required_size = variant.install_size + variant.size;
+    let current_partition = if cfg!(debug_assertions) {
+        // prevent developer/tester accidentally delete their partitions
+        Rc::new(disks::Partition {
+            fs_type: None,
+            path: Some(PathBuf::from("/dev/loop30p1")),
+            parent_path: Some(PathBuf::from("/dev/loop30")),
+            size: required_size,
         })
-        .button("Back", move |s| {
+    } else {
+        partition
+    };
+    if current_partition.parent_path.is_none() && current_partition.size == 0 {
+        show_msg(s, tr!("Please specify a system partition."));
+        return;
+    }
+    if current_partition.size < required_size {
+        show_msg(
+            s,
+            &not_enough_space_msg(current_partition.size, required_size),
+        );
+        return;
+    }
+    if let Err(e) = disks::right_combine(current_partition.parent_path.as_deref()) {
+        let view = wrap_in_dialog(
+            LinearLayout::vertical().child(TextView::new(e.to_string())),
+            tr!("AOSC OS Installer"),
+            None,
+        )
+        .button(tr!("OK"), |s| {
             s.pop_layer();
-            select_disk(s, config_copy_2.clone());
         })
-        .button("Exit", |s| s.quit())
-    );
-}
+        .button(tr!("Exit"), |s| s.quit());
+        s.add_layer(view);
+        return;
+    }
 
-fn select_disk(siv: &mut Cursive, config: InstallConfig) {
-    siv.pop_layer();
-    let config_clone = config.clone();
-    let cb_sink = siv.cb_sink().clone();
+    if is_efi_booted() {
+        let has_efi =
+            disks::find_esp_partition(current_partition.parent_path.as_ref().unwrap()).is_ok();
+        if !has_efi {
+            show_msg(s, tr!(NO_ESP_ERROR));
+            return;
+        }
+    }
 
-    let disk_view = AsyncView::new_with_bg_creator(
-        siv,
+    let partition = current_partition.as_ref().clone();
+    run_in_background(
+        s,
+        tr!("Looking for an existing installation on the partition ..."),
         move || {
-            let devices = disks::list_devices();
-            let devices = make_device_list(devices);
-
-            Ok(devices)
+            partition
+                .fs_type
+                .as_ref()
+                .filter(|x| ALLOWED_FS_TYPE.contains(&x.as_str()))
+                .and_then(|_| install::probe_previous_install(&partition).ok().flatten())
         },
-        move |devices| {
-            let mut disk_view = LinearLayout::vertical();
-            let mut disk_list = RadioGroup::new();
-
-            for i in devices {
-                let radio = disk_list.button(
-                    i.clone(),
-                    format!("{} ({}, {})", i.path.display(), i.model, human_size(i.size)),
-                );
-                disk_view.add_child(radio);
+        move |s, previous| {
+            if let Some(previous) = previous {
+                select_reinstall_mode(s, config, current_partition, previous);
+            } else {
+                probe_data_loss(s, config, current_partition);
             }
-
-            let disk_list = SendWrapper::new(disk_list);
-            cb_sink
-                .send(Box::new(move |s| {
-                    s.set_user_data(disk_list);
-                }))
-                .unwrap();
-
-            disk_view
         },
     );
+}
 
-    let dest_view = LinearLayout::vertical()
-        .child(TextView::new(
-            "Please select a device as AOSC OS system drive.",
-        ))
-        .child(DummyView {})
-        .child(disk_view);
-
-    let config_view = LinearLayout::vertical()
-        .child(Panel::new(dest_view).title("Select System Disk"))
-        .child(DummyView {});
+/// A line of the disk list, the partitions of a disk are listed under it
+#[derive(Debug, Clone)]
+enum DiskRow {
+    Disk(Rc<disks::DiskInfo>),
+    Partition(Rc<disks::DiskInfo>, Rc<disks::PartitionInfo>),
+}
 
-    siv.add_layer(
-        wrap_in_dialog(config_view, "AOSC OS Installation", None)
-            .button("Continue", move |siv| {
-                if let Some(d) = siv.user_data::<SendWrapper<RadioGroup<DkDerive>>>() {
-                    let d = d.clone().take();
-                    let device = if cfg!(debug_assertions) {
-                        Rc::new(DkDerive {
-                            path: PathBuf::from("/dev/loop30"),
-                            model: "Test".to_string(),
-                            size: 50 * 1024_u64.pow(3),
-                        })
-                    } else {
-                        d.selection()
-                    };
+impl DiskRow {
+    fn unusable(&self) -> Option<&disks::Unusable> {
+        match self {
+            DiskRow::Disk(disk) => disk.unusable.as_ref(),
+            DiskRow::Partition(_, partition) => partition.unusable.as_ref(),
+        }
+    }
+}
 
-                    siv.pop_layer();
-                    select_auto_make_partitions(siv, config_clone.clone(), device.to_owned());
-                }
-            })
-            .button("Back", move |s| {
-                s.pop_layer();
-                select_variant(s, config.clone());
-            })
-            .button("Exit", move |s| {
-                s.quit();
-            }),
-    );
+/// Why the disk or partition can not be installed to, in short for the list or in full
+fn unusable_reason(reason: &disks::Unusable, short: bool) -> String {
+    match (reason, short) {
+        (disks::Unusable::LiveMedium, true) => tr!("live medium").to_string(),
+        (disks::Unusable::LiveMedium, false) => {
+            tr!("Installer is running from this device, it can not be installed to.").to_string()
+        }
+        (disks::Unusable::Unsupported, true) => tr!("unsupported").to_string(),
+        (disks::Unusable::Unsupported, false) => tr!(
+            "AOSC OS can not be installed to this kind of device, e.g., the RPMB or boot area of eMMC storage."
+        )
+        .to_string(),
+        (disks::Unusable::TooSmall, true) => tr!("too small").to_string(),
+        (disks::Unusable::TooSmall, false) => {
+            tr!("It is too small for the selected variant.").to_string()
+        }
+        (disks::Unusable::InUse(_), true) => tr!("in use").to_string(),
+        (disks::Unusable::InUse(x), false) if x == "[SWAP]" => {
+            tr!("It is being used as swap, and can not be installed to.").to_string()
+        }
+        (disks::Unusable::InUse(x), false) => {
+            tr!("It is mounted at {}, and can not be installed to.", x)
+        }
+        (disks::Unusable::ReadOnly, true) => tr!("read-only").to_string(),
+        (disks::Unusable::ReadOnly, false) => {
+            tr!("The device is read-only, and can not be installed to.").to_string()
+        }
+    }
 }
 
-fn select_auto_make_partitions(s: &mut Cursive, config: InstallConfig, device: Rc<DkDerive>) {
-    let is_empty = device_is_empty(&device.path).unwrap_or(true);
+/// The lines of the disk list, with the partitions of the disks in `expanded` under them
+fn disk_rows(disks: &[Rc<disks::DiskInfo>], expanded: &[PathBuf]) -> Vec<(StyledString, DiskRow)> {
+    let mut rows = vec![];
+    for disk in disks {
+        let is_expanded = expanded.contains(&disk.device.path);
+        let mut info = vec![human_size(disk.device.size)];
+        info.extend(disk.transport.clone());
+        info.extend(disk.table.clone());
+        let text = format!(
+            "{} {} {} ({})",
+            if disk.partitions.is_empty() {
+                "   "
+            } else if is_expanded {
+                "[-]"
+            } else {
+                "[+]"
+            },
+            disk.device.path.display(),
+            disk.device.model,
+            info.join(", ")
+        );
+        let mut label = row_label(text, disk.unusable.as_ref());
+        if disk.healthy == Some(false) {
+            label.append_styled(format!(" [{}]", tr!("failing")), emphasis(Emphasis::Danger));
+        }
+        rows.push((label, DiskRow::Disk(disk.clone())));
+        if !is_expanded {
+            continue;
+        }
 
-    let tips = r#"AOSC OS Installer has detected that the specified drive is empty or has no valid partition. AOSC OS Installer can automatically partition the drive for you, would you like to do that?
+        for partition in &disk.partitions {
+            let mut info = vec![
+                partition
+                    .partition
+                    .fs_type
+                    .clone()
+                    .unwrap_or_else(|| tr!("Unknown/Unformatted").to_string()),
+                human_size(partition.partition.size),
+            ];
+            info.extend(partition.label.as_ref().map(|x| format!("\"{x}\"")));
+            info.extend(partition.os.clone());
+            let text = format!(
+                "      {} ({})",
+                partition
+                    .partition
+                    .path
+                    .as_ref()
+                    .map(|x| x.display().to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+                info.join(", ")
+            );
+            rows.push((
+                row_label(text, partition.unusable.as_ref()),
+                DiskRow::Partition(disk.clone(), Rc::new(partition.clone())),
+            ));
+        }
+    }
 
-If you continue, the contents of your hard disk will be erased. Please make sure that the specified drive has no data on it!"#;
+    rows
+}
 
-    let select_device = format!(
-        "{} ({}, {})",
-        device.path.display(),
-        device.model,
-        human_size(device.size)
-    );
+/// Unusable disks and partitions are greyed out, with the reason after them
+fn row_label(text: String, unusable: Option<&disks::Unusable>) -> StyledString {
+    match unusable {
+        Some(reason) => StyledString::styled(
+            format!("{text} [{}]", unusable_reason(reason, true)),
+            PaletteColor::Secondary,
+        ),
+        None => StyledString::plain(text),
+    }
+}
 
-    let config_clone_2 = config.clone();
-    let config_clone_3 = config.clone();
+/// Everything known about the disk or partition, shown under the list
+fn disk_row_details(row: &DiskRow) -> StyledString {
+    let mut lines = vec![];
+    let unusable = match row {
+        DiskRow::Disk(disk) => {
+            lines.push(tr!("Model: {}", disk.device.model));
+            lines.push(tr!("Size: {}", human_size(disk.device.size)));
+            if let Some(transport) = &disk.transport {
+                lines.push(tr!("Connection: {}", transport));
+            }
+            lines.push(tr!(
+                "Partition table: {}",
+                disk.table.as_deref().unwrap_or(tr!("None"))
+            ));
+            lines.push(tr!("Unpartitioned space: {}", human_size(disk.unallocated)));
+            lines.push(tr!(
+                "Health (SMART): {}",
+                match disk.healthy {
+                    Some(true) => tr!("Good"),
+                    Some(false) => tr!("Failing, back up its data and replace it soon"),
+                    None => tr!("Unknown"),
+                }
+            ));
+            disk.unusable.as_ref()
+        }
+        DiskRow::Partition(_, partition) => {
+            lines.push(tr!(
+                "Filesystem: {}",
+                partition
+                    .partition
+                    .fs_type
+                    .as_deref()
+                    .unwrap_or(tr!("Unknown/Unformatted"))
+            ));
+            if let Some(label) = &partition.label {
+                lines.push(tr!("Label: {}", label));
+            }
+            lines.push(tr!("Size: {}", human_size(partition.partition.size)));
+            if let Some(used) = partition.used {
+                lines.push(tr!("Used space: {}", human_size(used)));
+            }
+            if let Some(free) = partition.free {
+                lines.push(tr!("Free space: {}", human_size(free)));
+            }
+            if let Some(os) = &partition.os {
+                lines.push(tr!("Contains: {}", os));
+            }
+            if let Some(mount_point) = &partition.mount_point {
+                lines.push(tr!("Mounted at: {}", mount_point));
+            }
+            partition.unusable.as_ref()
+        }
+    };
 
-    let (btn_label, btn_cb) = partition_button(device.path.to_path_buf());
+    let mut text = StyledString::plain(lines.join("\n"));
+    if let Some(reason) = unusable {
+        text.append_plain("\n");
+        text.append_styled(unusable_reason(reason, false), emphasis(Emphasis::Warning));
+    }
+
+    text
+}
 
-    let device_path_1 = device.path.clone();
+/// What the disk list shows: the disks, the ones expanded and the storage mode chosen
+struct DiskListState {
+    disks: Vec<Rc<disks::DiskInfo>>,
+    expanded: Vec<PathBuf>,
+    mode: StorageMode,
+}
 
-    let desc = if is_efi_booted() {
-        format!("- A 512MiB EFI System Partition (ESP) will be created.\n- A {} system root partition will be created.", human_size(device.size - 512 * 1024_u64.pow(2)))
-    } else {
-        format!(
-            "- A {} system root partition will be created.",
-            human_size(device.size)
-        )
+/// Fill the disk list again, keeping `select` selected
+///
+/// In guided mode, only the disks are listed as a whole disk is to be used.
+fn refresh_disk_list(siv: &mut Cursive, state: &DiskListState, select: Option<&Path>) {
+    let rows = match state.mode {
+        StorageMode::Guided => disk_rows(&state.disks, &[]),
+        StorageMode::Advanced => disk_rows(&state.disks, &state.expanded),
     };
+    siv.call_on_name(DISK_LIST, |view: &mut SelectView<DiskRow>| {
+        view.clear();
+        let index = rows
+            .iter()
+            .position(|(_, row)| matches!(row, DiskRow::Disk(x) if Some(x.device.path.as_path()) == select))
+            .unwrap_or(0);
+        view.add_all(rows);
+        view.set_selection(index);
+    });
+}
 
-    if is_empty {
-        s.add_layer(
-            wrap_in_dialog(
-                TextView::new(format!(
-                    "{tips}\n\nSelect device: {select_device}\n\n{desc}"
-                )),
-                "AOSC OS Installer",
-                None,
-            )
-            .button("Continue", move |s| {
-                let device_path = device_path_1.clone();
-                let config_clone = config.clone();
-                s.pop_layer();
-                auto_partition_view(s, config_clone, &select_device, &desc, device_path);
-            })
-            .button(btn_label, move |s| {
-                select_partition(s, config_clone_3.clone(), device.clone());
-                btn_cb(s, config_clone_3.clone());
-            })
-            .button("Back", move |s| {
-                s.pop_layer();
-                select_disk(s, config_clone_2.clone());
-            })
-            .button("Quit", |s| s.quit()),
-        )
-    } else {
-        select_partition(s, config, device);
+/// Expand or collapse the partitions of the disk on the row
+fn toggle_disk_row(siv: &mut Cursive, state: &RefCell<DiskListState>, disk: &Path) {
+    {
+        let mut state = state.borrow_mut();
+        if state.mode == StorageMode::Guided {
+            return;
+        }
+        match state.expanded.iter().position(|x| x == disk) {
+            Some(index) => {
+                state.expanded.remove(index);
+            }
+            None => state.expanded.push(disk.to_path_buf()),
+        }
     }
+    refresh_disk_list(siv, &state.borrow(), Some(disk));
 }
 
-fn auto_partition_view(
-    s: &mut Cursive,
-    config_clone: InstallConfig,
-    select_device: &str,
-    desc: &str,
-    device_path: PathBuf,
-) {
-    let tips = format!("WARNING: This will DESTROY ALL DATA ON THE SPECIFIED DRIVE, are you sure that you would want to proceed?\n\nSelect device: {select_device}\n\n{desc}");
-    s.add_layer(
-        wrap_in_dialog(TextView::new(tips), "AOSC OS Installer", None)
-            .button("Yes, Please Partition My Drive!", move |s| {
-                let config_clone = config_clone.clone();
-                let config = config_clone.clone();
-                let variant = config.variant.unwrap();
-                let device_path = device_path.clone();
-                let required_size = variant.install_size + variant.size;
-                let view = AsyncView::new_with_bg_creator(
-                    s,
-                    move || match auto_create_partitions(&device_path) {
-                        Ok(part) => {
-                            if required_size > part.size {
-                                return Err(format!(
-                                    "The specified partition does not contain enough space to install AOSC OS release!\n\nAvailable space: {:.3}GiB\nRequired space: {:.3}GiB", 
-                                    part.size as f32 / 1024.0 / 1024.0 / 1024.0,
-                                    required_size as f32 / 1024.0 / 1024.0 / 1024.0
-                                ));
-                            }
+/// Switch the disk step between guided and advanced mode
+fn set_storage_mode(siv: &mut Cursive, state: &RefCell<DiskListState>, mode: StorageMode) {
+    let selected = siv
+        .call_on_name(DISK_LIST, |view: &mut SelectView<DiskRow>| view.selection())
+        .flatten()
+        .map(|row| match row.as_ref() {
+            DiskRow::Disk(x) | DiskRow::Partition(x, _) => x.device.path.clone(),
+        });
+    state.borrow_mut().mode = mode;
+    refresh_disk_list(siv, &state.borrow(), selected.as_deref());
+    siv.call_on_name(GUIDED_OPTIONS, |view: &mut HideableView<LinearLayout>| {
+        view.set_visible(mode == StorageMode::Guided)
+    });
+    siv.call_on_name(ADVANCED_OPTIONS, |view: &mut HideableView<LinearLayout>| {
+        view.set_visible(mode == StorageMode::Advanced)
+    });
+}
 
-                            Ok(part)
-                        },
-                        Err(e) => Err(e.to_string()),
-                    },
-                    move |res| {
-                        let mut config = config_clone.clone();
-                        config.partition = Some(Arc::new(res));
-                        select_user_password(config)
-                    },
-                );
+/// Buttons to run the partitioning programs on the selected disk, the ones not installed
+/// are disabled
+fn partitioner_buttons(config: &InstallConfig) -> LinearLayout {
+    let mut view = LinearLayout::horizontal();
+    for &program in disks::PARTITIONERS {
+        let config = config.clone();
+        let installed = disks::find_program(program).is_some();
+        let button = Button::new(tr!("Open {} on This Disk", program), move |s| {
+            open_partitioner(s, config.clone(), program)
+        })
+        .with_enabled(installed);
+        view.add_child(button);
+        if !installed {
+            view.add_child(TextView::new(tr!("({} is not installed)", program)));
+        }
+        view.add_child(DummyView {}.fixed_width(2));
+    }
 
-                s.pop_layer();
-                s.add_layer(view);
-            })
-            .button("No", move |s| {
-                s.pop_layer();
-            }),
-    );
+    view
 }
 
-fn continue_to_format_hdd(s: &mut Cursive, config_clone: InstallConfig, fs_type: String) {
-    let path = config_clone
+/// Suspend the TUI to run the partitioning program on the disk of the selected row
+fn open_partitioner(s: &mut Cursive, config: InstallConfig, program: &'static str) {
+    let row = s
+        .call_on_name(DISK_LIST, |view: &mut SelectView<DiskRow>| view.selection())
+        .flatten();
+    let Some(row) = row else {
+        show_msg(s, tr!("Please select a device as AOSC OS system drive."));
+        return;
+    };
+    let disk = match row.as_ref() {
+        DiskRow::Disk(x) | DiskRow::Partition(x, _) => x.clone(),
+    };
+    if let Some(reason @ (disks::Unusable::LiveMedium | disks::Unusable::ReadOnly)) =
+        disk.unusable.as_ref()
+    {
+        show_msg(s, &unusable_reason(reason, false));
+        return;
+    }
+
+    s.set_user_data(config);
+    let dump = s.dump();
+    s.quit();
+    s.set_user_data(ShellRequest::Partitioner(
+        dump,
+        program,
+        disk.device.path.clone(),
+    ));
+}
+
+/// Back to the disk selection after the partitioning program exited, the disks are listed
+/// again there
+fn after_partitioner(
+    siv: &mut Cursive,
+    mut config: InstallConfig,
+    program: &str,
+    device_path: &Path,
+    status: Result<std::process::ExitStatus>,
+) {
+    // the partition chosen before may have been removed, moved or resized
+    let lost = config
         .partition
         .as_ref()
-        .expect("Must unwrap success")
-        .path
-        .as_ref()
-        .expect("Must unwrap success")
-        .to_str()
-        .expect("Must as string");
+        .filter(|x| x.path.is_some() && x.parent_path.as_deref() == Some(device_path))
+        .filter(|x| !disks::partition_unchanged(x))
+        .and_then(|x| x.path.clone());
+    if lost.is_some() {
+        config.reset_partition();
+    }
+    if config.partition.is_none() {
+        // come back to the advanced mode the program was run from
+        config.storage_mode = Some(StorageMode::Advanced);
+    }
 
-    let dialog = LinearLayout::vertical().child(TextView::new(format!(
-        SURE_FS_FORMAT_INFO!(),
-        path, fs_type
-    )));
+    select_disk(siv, config);
+    match status {
+        Ok(status) if !status.success() => show_msg(
+            siv,
+            &tr!(
+                "{} exited with an error ({}), the partitions on {} may not have been changed.",
+                program,
+                status,
+                device_path.display()
+            ),
+        ),
+        Err(e) => show_error(siv, &tr!("Failed to run {}: {}", program, e)),
+        _ => (),
+    }
+    if let Some(lost) = lost {
+        show_msg(
+            siv,
+            &tr!(
+                "The partition selected before ({}) no longer exists or has been changed. Please select the system partition again.",
+                lost.display()
+            ),
+        );
+    }
+}
 
-    let view = wrap_in_dialog(dialog, "AOSC OS Installer", None)
-        .button("OK", move |s| {
+/// Go on with the disk chosen in guided mode, to use it as a whole
+fn use_disk_guided(siv: &mut Cursive, config: InstallConfig, row: &DiskRow) {
+    let disk = match row {
+        DiskRow::Disk(disk) => disk,
+        DiskRow::Partition(..) => {
+            show_msg(siv, tr!("Please select a device as AOSC OS system drive."));
+            return;
+        }
+    };
+    if let Some(reason) = row.unusable() {
+        show_msg(siv, &unusable_reason(reason, false));
+        return;
+    }
+    let encrypt = siv
+        .call_on_name("encrypt", |view: &mut Checkbox| view.is_checked())
+        .unwrap_or(false);
+    let keep_free = siv
+        .call_on_name("keep_free", |view: &mut EditView| view.get_content())
+        .map(|x| x.trim().to_string())
+        .unwrap_or_default();
+    let keep_free = if keep_free.is_empty() {
+        0
+    } else {
+        match keep_free.parse::<f64>() {
+            Ok(x) if x >= 0.0 && x.is_finite() => (x * 1024_f64.powi(3)) as u64,
+            _ => {
+                show_msg(
+                    siv,
+                    tr!("The space to keep free should be a number of GiB, such as 100."),
+                );
+                return;
+            }
+        }
+    };
+
+    let device = if cfg!(debug_assertions) {
+        DkDerive {
+            path: PathBuf::from("/dev/loop30"),
+            model: "Test".to_string(),
+            size: 50 * 1024_u64.pow(3),
+        }
+    } else {
+        disk.device.clone()
+    };
+    let required_size = config
+        .variant
+        .as_ref()
+        .map(|x| x.install_size + x.size)
+        .unwrap_or(0);
+    let available = auto_partition_system_size(device.size, keep_free);
+    if available < required_size {
+        show_msg(siv, &not_enough_space_msg(available, required_size));
+        return;
+    }
+
+    let mut config = config;
+    let notice = config.change_disk(&device.path);
+    let select_device = format!(
+        "{} ({}, {})",
+        device.path.display(),
+        device.model,
+        human_size(device.size)
+    );
+    let desc = auto_partition_desc(device.size, keep_free);
+    auto_partition_view(
+        siv,
+        config,
+        &select_device,
+        &desc,
+        device,
+        keep_free,
+        encrypt,
+    );
+    if let Some(notice) = notice {
+        show_msg(siv, &notice);
+    }
+}
+
+/// Go on with the disk (to partition it or choose a partition on it) or partition chosen
+fn use_disk_row(siv: &mut Cursive, config: InstallConfig, row: &DiskRow) {
+    if let Some(reason) = row.unusable() {
+        show_msg(siv, &unusable_reason(reason, false));
+        return;
+    }
+
+    // the partition chosen on another disk is of no use any more
+    let mut config = config;
+    let encrypt = siv.call_on_name("encrypt", |view: &mut Checkbox| view.is_checked());
+    match row {
+        DiskRow::Disk(disk) => {
+            let device = if cfg!(debug_assertions) {
+                Rc::new(DkDerive {
+                    path: PathBuf::from("/dev/loop30"),
+                    model: "Test".to_string(),
+                    size: 50 * 1024_u64.pow(3),
+                })
+            } else {
+                Rc::new(disk.device.clone())
+            };
+            let notice = config.change_disk(&device.path);
+            config.encrypt = encrypt;
+            siv.pop_layer();
+            select_auto_make_partitions(siv, config, device);
+            if let Some(notice) = notice {
+                show_msg(siv, &notice);
+            }
+        }
+        DiskRow::Partition(disk, partition) => {
+            let notice = config.change_disk(&disk.device.path);
+            config.encrypt = encrypt;
+            use_partition(siv, config, Rc::new(partition.partition.clone()));
+            if let Some(notice) = notice {
+                show_msg(siv, &notice);
+            }
+        }
+    }
+}
+
+fn select_disk(siv: &mut Cursive, config: InstallConfig) {
+    siv.pop_layer();
+    enter_step(WizardStep::Disk, &config);
+    let config_clone = config.clone();
+    let config_clone_2 = config.clone();
+    let current_disk = match config.wipe_disk.as_ref() {
+        Some(device) => Some(device.path.clone()),
+        None => config
+            .partition
+            .as_ref()
+            .and_then(|x| x.parent_path.clone()),
+    };
+    let current_partition = config.partition.as_ref().and_then(|x| x.path.clone());
+    let required_size = config
+        .variant
+        .as_ref()
+        .map(|x| x.install_size + x.size)
+        .unwrap_or(0);
+    let mode = config.storage_mode.unwrap_or(StorageMode::Guided);
+    let details = TextContent::new("");
+    let details_copy = details.clone();
+    let state = Rc::new(RefCell::new(DiskListState {
+        disks: vec![],
+        expanded: vec![],
+        mode,
+    }));
+    let state_copy = state.clone();
+    let state_copy_2 = state.clone();
+
+    let disk_view = AsyncView::new_with_bg_creator(
+        siv,
+        move || Ok(disks::list_disks(required_size)),
+        move |disks| {
+            // the mode may have been switched while the disks were being listed
+            let mode = state_copy.borrow().mode;
+            let disks = disks.into_iter().map(Rc::new).collect::<Vec<_>>();
+            let expanded = disks
+                .iter()
+                .map(|x| x.device.path.clone())
+                .collect::<Vec<_>>();
+            let rows = match mode {
+                StorageMode::Guided => disk_rows(&disks, &[]),
+                StorageMode::Advanced => disk_rows(&disks, &expanded),
+            };
+            let selected = rows
+                .iter()
+                .position(|(_, row)| match row {
+                    DiskRow::Partition(_, x) => {
+                        current_partition.is_some() && x.partition.path == current_partition
+                    }
+                    DiskRow::Disk(x) => {
+                        (mode == StorageMode::Guided || current_partition.is_none())
+                            && Some(&x.device.path) == current_disk.as_ref()
+                    }
+                })
+                .unwrap_or(0);
+            if let Some((_, row)) = rows.get(selected) {
+                details_copy.set_content(disk_row_details(row));
+            }
+            {
+                let mut state = state_copy.borrow_mut();
+                state.disks = disks;
+                state.expanded = expanded;
+            }
+
+            let details_copy = details_copy.clone();
+            let state_copy = state_copy.clone();
+            let config_clone_2 = config_clone_2.clone();
+            let mut view = SelectView::new()
+                .on_select(move |_, row: &DiskRow| details_copy.set_content(disk_row_details(row)))
+                .on_submit(move |s, row: &DiskRow| match row {
+                    DiskRow::Disk(disk) => {
+                        if state_copy.borrow().mode == StorageMode::Guided {
+                            use_disk_guided(s, config_clone_2.clone(), row);
+                        } else {
+                            toggle_disk_row(s, &state_copy, &disk.device.path);
+                        }
+                    }
+                    DiskRow::Partition(..) => use_disk_row(s, config_clone_2.clone(), row),
+                });
+            view.add_all(rows);
+            view.set_selection(selected);
+
+            view.with_name(DISK_LIST)
+        },
+    );
+
+    let mut mode_group = RadioGroup::new();
+    let state_copy = state.clone();
+    mode_group.set_on_change(move |s, mode: &StorageMode| {
+        set_storage_mode(s, &state_copy, *mode);
+    });
+    let mode_view = LinearLayout::horizontal()
+        .child(
+            mode_group
+                .button(StorageMode::Guided, tr!("Guided: use a whole disk"))
+                .with(|x| {
+                    if mode == StorageMode::Guided {
+                        x.select();
+                    }
+                }),
+        )
+        .child(DummyView {}.fixed_width(2))
+        .child(
+            mode_group
+                .button(StorageMode::Advanced, tr!("Advanced: choose partitions"))
+                .with(|x| {
+                    if mode == StorageMode::Advanced {
+                        x.select();
+                    }
+                }),
+        );
+
+    let keep_free = config
+        .keep_free_space
+        .filter(|x| *x > 0)
+        .map(|x| format!("{}", x as f64 / 1024_f64.powi(3)))
+        .unwrap_or_default();
+    // in the advanced mode, the passphrase is asked for with the mount points
+    let encrypt_option = ListView::new().child(
+        tr!("Encrypt the system partition"),
+        Checkbox::new()
+            .with_checked(config.encrypt.unwrap_or(false))
+            .with_name("encrypt"),
+    );
+    let guided_options = HideableView::new(
+        LinearLayout::vertical().child(
+            ListView::new().child(
+                tr!("Keep free space for other systems (GiB)"),
+                EditView::new()
+                    .content(keep_free)
+                    .with_name("keep_free")
+                    .fixed_width(10),
+            ),
+        ),
+    )
+    .visible(mode == StorageMode::Guided)
+    .with_name(GUIDED_OPTIONS);
+    let advanced_options = HideableView::new(
+        LinearLayout::vertical()
+            .child(DummyView {})
+            .child(partitioner_buttons(&config)),
+    )
+    .visible(mode == StorageMode::Advanced)
+    .with_name(ADVANCED_OPTIONS);
+
+    let dest_view = LinearLayout::vertical()
+        .child(TextView::new(tr!(
+            "Please select a device as AOSC OS system drive, or a partition on it in advanced mode. Press <Enter> on a device to show or hide its partitions."
+        )))
+        .child(DummyView {})
+        .child(mode_view)
+        .child(DummyView {})
+        .child(encrypt_option)
+        .child(guided_options)
+        .child(advanced_options)
+        .child(DummyView {})
+        .child(disk_view)
+        .child(DummyView {})
+        .child(TextView::new_with_content(details));
+
+    let config_view = LinearLayout::vertical()
+        .child(Panel::new(dest_view).title(tr!("Select System Disk")))
+        .child(DummyView {});
+
+    siv.add_layer(with_help(
+        wrap_in_dialog(config_view, tr!("AOSC OS Installation"), Some(80))
+            .button(tr!("Continue"), move |siv| {
+                let row = siv
+                    .call_on_name(DISK_LIST, |view: &mut SelectView<DiskRow>| view.selection())
+                    .flatten();
+                match row {
+                    Some(row) if state_copy_2.borrow().mode == StorageMode::Guided => {
+                        use_disk_guided(siv, config_clone.clone(), &row)
+                    }
+                    Some(row) => use_disk_row(siv, config_clone.clone(), &row),
+                    None => show_msg(siv, tr!("Please select a device as AOSC OS system drive.")),
+                }
+            })
+            .button(tr!("Back"), move |s| {
+                back_to_mirrors(s, config.clone());
+            })
+            .button(tr!("Exit"), move |s| {
+                s.quit();
+            }),
+        Screen::Disk,
+    ));
+}
+
+fn select_auto_make_partitions(s: &mut Cursive, config: InstallConfig, device: Rc<DkDerive>) {
+    let is_empty = device_is_empty(&device.path).unwrap_or(true);
+
+    let tips = tr!(
+        r#"AOSC OS Installer has detected that the specified drive is empty or has no valid partition. AOSC OS Installer can automatically partition the drive for you, would you like to do that?
+
+If you continue, the contents of your hard disk will be erased. Please make sure that the specified drive has no data on it!"#
+    );
+
+    let select_device = format!(
+        "{} ({}, {})",
+        device.path.display(),
+        device.model,
+        human_size(device.size)
+    );
+
+    let config_clone_2 = config.clone();
+    let config_clone_3 = config.clone();
+
+    let (btn_label, btn_cb) = partition_button(device.path.to_path_buf());
+
+    let device_1 = device.as_ref().clone();
+
+    let desc = auto_partition_desc(device.size, 0);
+
+    if is_empty {
+        s.add_layer(
+            wrap_in_dialog(
+                TextView::new(tr!(
+                    "{}\n\nSelect device: {}\n\n{}",
+                    tips,
+                    select_device,
+                    desc
+                )),
+                tr!("AOSC OS Installer"),
+                None,
+            )
+            .button(tr!("Continue"), move |s| {
+                let device = device_1.clone();
+                let config_clone = config.clone();
+                s.pop_layer();
+                let encrypt = config_clone.encrypt.unwrap_or(false);
+                auto_partition_view(s, config_clone, &select_device, &desc, device, 0, encrypt);
+            })
+            .button(btn_label, move |s| {
+                select_partition(s, config_clone_3.clone(), device.clone());
+                btn_cb(s, config_clone_3.clone());
+            })
+            .button(tr!("Back"), move |s| {
+                s.pop_layer();
+                select_disk(s, config_clone_2.clone());
+            })
+            .button(tr!("Quit"), |s| s.quit()),
+        )
+    } else {
+        select_partition(s, config, device);
+    }
+}
+
+/// Confirm partitioning the whole disk, leaving `keep_free` bytes unpartitioned, and ask
+/// for the passphrase if the system partition is to be encrypted
+fn auto_partition_view(
+    s: &mut Cursive,
+    config_clone: InstallConfig,
+    select_device: &str,
+    desc: &str,
+    device: DkDerive,
+    keep_free: u64,
+    encrypt: bool,
+) {
+    let tips = tr!("WARNING: This will DESTROY ALL DATA ON THE SPECIFIED DRIVE, are you sure that you would want to proceed? The drive will be partitioned after the final confirmation.\n\nSelect device: {}\n\n{}", select_device, desc);
+    let mut view = LinearLayout::vertical().child(TextView::new(tips));
+    if encrypt {
+        view.add_child(DummyView {});
+        view.add_child(encryption_options(&config_clone));
+    }
+    s.add_layer(
+        wrap_in_dialog(view, tr!("AOSC OS Installer"), None)
+            .button(tr!("Yes, Please Partition My Drive!"), move |s| {
+                let mut config = config_clone.clone();
+                let variant = config.variant.clone().unwrap();
+                let required_size = variant.install_size + variant.size;
+                // the system partition to be created, nothing is written before the summary is confirmed
+                let part = disks::Partition {
+                    path: None,
+                    parent_path: Some(device.path.clone()),
+                    fs_type: Some("ext4".to_string()),
+                    size: auto_partition_system_size(device.size, keep_free),
+                };
+                if required_size > part.size {
+                    show_msg(s, &not_enough_space_msg(part.size, required_size));
+                    return;
+                }
+                let (passphrase, discard) = if encrypt {
+                    match read_encryption_options(s) {
+                        Some((passphrase, discard)) => (Some(passphrase), Some(discard)),
+                        None => return,
+                    }
+                } else {
+                    (None, None)
+                };
+                config.partition = Some(Arc::new(part));
+                config.wipe_disk = Some(Arc::new(device.clone()));
+                config.storage_mode = Some(StorageMode::Guided);
+                config.keep_free_space = Some(keep_free);
+                config.encrypt = Some(encrypt);
+                config.encryption_passphrase = passphrase;
+                config.encrypt_discard = discard;
+                config.preserve_home = None;
+                config.restore_users = None;
+                config.mounts = None;
+                partition_view_to_next(s, config);
+            })
+            .button(tr!("No"), move |s| {
+                s.pop_layer();
+            }),
+    );
+}
+
+/// The passphrase entries of the system partition to be encrypted, and whether to allow
+/// TRIM on the encrypted partitions
+pub(super) fn encryption_options(config: &InstallConfig) -> LinearLayout {
+    // the layout is chosen in a later step, US-QWERTY until then
+    let keymap = config
+        .keymap
+        .as_ref()
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| "us".to_string());
+
+    LinearLayout::vertical()
+        .child(TextView::new(tr!(
+            "The system partition will be encrypted. Please enter the passphrase to unlock it with on every boot, it can not be recovered if forgotten."
+        )))
+        .child(TextView::new(StyledString::styled(
+            tr!(
+                "The passphrase will be typed with the {} keyboard layout at the boot prompt, the layout may be changed with the regional settings later.",
+                keymap
+            ),
+            emphasis(Emphasis::Warning),
+        )))
+        .child(password::add_passphrase_entries(
+            ListView::new(),
+            ENCRYPTION_PASSPHRASE,
+            keymap,
+        ))
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Checkbox::new()
+                        .with_checked(config.encrypt_discard.unwrap_or(false))
+                        .with_name(ENCRYPTION_DISCARD),
+                )
+                .child(TextView::new(tr!(
+                    " Allow TRIM on SSDs, which reveals the unused blocks of the encrypted partitions"
+                ))),
+        )
+}
+
+/// The passphrase and the TRIM choice entered with `encryption_options`, or `None` (with a
+/// message shown) if the passphrase has not been confirmed
+pub(super) fn read_encryption_options(s: &mut Cursive) -> Option<(Arc<String>, bool)> {
+    let Some(Some(passphrase)) = password::new_password(s, ENCRYPTION_PASSPHRASE, false) else {
+        show_msg(s, tr!("Please enter and confirm the passphrase."));
+        return None;
+    };
+    let discard = s
+        .call_on_name(ENCRYPTION_DISCARD, |view: &mut Checkbox| view.is_checked())
+        .unwrap_or(false);
+
+    Some((Arc::new(passphrase), discard))
+}
+
+/// Look for the data on the partition to be erased, then ask how to format it
+fn probe_data_loss(
+    s: &mut Cursive,
+    config: InstallConfig,
+    current_partition: Rc<disks::Partition>,
+) {
+    let partition = current_partition.as_ref().clone();
+    run_in_background(
+        s,
+        tr!("Looking for data on the partition ..."),
+        move || data_loss_warning(&partition, true),
+        move |s, warning| select_fs_type(s, config, current_partition, warning),
+    );
+}
+
+fn select_fs_type(
+    s: &mut Cursive,
+    mut config: InstallConfig,
+    current_partition: Rc<disks::Partition>,
+    warning: Option<String>,
+) {
+    // the partition will be formatted
+    config.preserve_home = None;
+    config.restore_users = None;
+    config.wipe_disk = None;
+    let config_copy = config.clone();
+    let config_copy_2 = config.clone();
+    let fs_type = current_partition.fs_type.clone();
+    let current_partition_clone = current_partition.clone();
+    // probed before asking, as the filesystem type of the partition gets replaced
+    let warning = warning.map(Rc::new);
+    let warning_copy = warning.clone();
+
+    if let Some(fs_type) = fs_type {
+        if fs_type != "ext4" && ALLOWED_FS_TYPE.contains(&fs_type.as_str()) {
+            let view = wrap_in_dialog(
+                LinearLayout::vertical().child(TextView::new(tr!(SURE_FS_TYPE_INFO, fs_type))),
+                tr!("AOSC OS Installer"),
+                None,
+            )
+            .button(tr!("Use Ext4"), move |s| {
+                let new_part = disks::fill_fs_type(current_partition.as_ref(), true);
+                let mut config_clone = config_copy_2.clone();
+                config_clone.partition = Some(Arc::new(new_part.clone()));
+                s.pop_layer();
+                continue_to_format_hdd(
+                    s,
+                    config_clone,
+                    new_part.fs_type.expect("Must unwrap success"),
+                    warning_copy.clone(),
+                );
+            })
+            .button(tr!("Use {}", fs_type), move |s| {
+                let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), false);
+                let mut config_clone = config_copy.clone();
+                config_clone.partition = Some(Arc::new(new_part.clone()));
+                s.pop_layer();
+                continue_to_format_hdd(
+                    s,
+                    config_clone,
+                    new_part.fs_type.expect("Must unwrap success"),
+                    warning.clone(),
+                );
+            })
+            .button(tr!("Cancel"), move |s| {
+                s.cb_sink()
+                    .send(Box::new(|s| {
+                        s.pop_layer();
+                    }))
+                    .unwrap()
+            });
+            s.add_layer(view);
+        } else if fs_type == "ext4" {
+            let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), true);
+            config.partition = Some(Arc::new(new_part.clone()));
+            continue_to_format_hdd(
+                s,
+                config,
+                new_part.fs_type.expect("Must unwrap success"),
+                warning,
+            );
+        } else if !ALLOWED_FS_TYPE.contains(&fs_type.as_str()) {
+            let view = wrap_in_dialog(
+                LinearLayout::vertical().child(TextView::new(tr!(ADVANCED_METHOD_INFO))),
+                tr!("AOSC OS Installer"),
+                None,
+            )
+            .button(tr!("OK"), move |s| {
+                let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), true);
+                let mut config_clone = config_copy.clone();
+                config_clone.partition = Some(Arc::new(new_part.clone()));
+                s.pop_layer();
+                continue_to_format_hdd(
+                    s,
+                    config_clone,
+                    new_part.fs_type.expect("Must unwrap success"),
+                    warning.clone(),
+                );
+            })
+            .button(tr!("Cancel"), move |s| {
+                s.cb_sink()
+                    .send(Box::new(|s| {
+                        s.pop_layer();
+                    }))
+                    .unwrap()
+            });
+            s.add_layer(view);
+        }
+    } else {
+        let new_part = disks::fill_fs_type(current_partition_clone.as_ref(), true);
+        config.partition = Some(Arc::new(new_part.clone()));
+        continue_to_format_hdd(
+            s,
+            config,
+            new_part.fs_type.expect("Must success unwrap"),
+            warning,
+        );
+    }
+}
+
+fn select_reinstall_mode(
+    s: &mut Cursive,
+    config: InstallConfig,
+    current_partition: Rc<disks::Partition>,
+    previous: install::PreviousInstall,
+) {
+    let path = current_partition
+        .path
+        .as_ref()
+        .map(|x| x.display().to_string())
+        .unwrap_or_default();
+    let mut view = LinearLayout::vertical().child(TextView::new(tr!(
+        "Installer has found an existing AOSC OS installation on {0}. You may reinstall AOSC OS while preserving /home, in which case everything else on {0} will be erased, but its filesystem will not be formatted. Or, you may erase everything on {0} and format it.",
+        path
+    )));
+    let users = previous
+        .users
+        .iter()
+        .map(|x| format!("{} (UID {})", x.name, x.uid))
+        .collect::<Vec<_>>();
+    if !users.is_empty() {
+        view.add_child(DummyView {});
+        view.add_child(
+            LinearLayout::horizontal()
+                .child(Checkbox::new().checked().with_name("restore_users"))
+                .child(TextView::new(tr!(
+                    " Re-create existing users: {}",
+                    users.join(", ")
+                ))),
+        );
+    }
+
+    let config_copy = config.clone();
+    let current_partition_copy = current_partition.clone();
+    s.add_layer(
+        wrap_in_dialog(view, tr!("AOSC OS Installer"), None)
+            .button(tr!("Preserve /home"), move |s| {
+                let restore_users = s
+                    .call_on_name("restore_users", |view: &mut Checkbox| view.is_checked())
+                    .unwrap_or(false);
+                let mut config = config_copy.clone();
+                // keep the existing filesystem as-is
+                config.partition = Some(Arc::new(current_partition_copy.as_ref().clone()));
+                config.preserve_home = Some(true);
+                config.restore_users = Some(restore_users);
+                config.wipe_disk = None;
+                partition_view_to_next(s, config);
+            })
+            .button(tr!("Erase Everything"), move |s| {
+                s.pop_layer();
+                probe_data_loss(s, config.clone(), current_partition.clone());
+            })
+            .button(tr!("Cancel"), |s| {
+                s.pop_layer();
+            }),
+    );
+}
+
+/// Confirm formatting the partition, or warn about the data on it to be lost (`warning`)
+fn continue_to_format_hdd(
+    s: &mut Cursive,
+    config_clone: InstallConfig,
+    fs_type: String,
+    warning: Option<Rc<String>>,
+) {
+    if let Some(warning) = warning {
+        s.add_layer(
+            wrap_in_dialog(
+                TextView::new(StyledString::styled(
+                    warning.as_str(),
+                    emphasis(Emphasis::Danger),
+                )),
+                tr!("Data Loss Warning"),
+                None,
+            )
+            .button(tr!("Erase and Use"), move |s| {
+                partition_view_to_next(s, config_clone.clone())
+            })
+            .button(tr!("Go Back"), |s| {
+                s.pop_layer();
+            }),
+        );
+        return;
+    }
+
+    let path = config_clone
+        .partition
+        .as_ref()
+        .expect("Must unwrap success")
+        .path
+        .as_ref()
+        .expect("Must unwrap success")
+        .to_str()
+        .expect("Must as string");
+
+    let dialog =
+        LinearLayout::vertical().child(TextView::new(tr!(SURE_FS_FORMAT_INFO, path, fs_type)));
+
+    let view = wrap_in_dialog(dialog, tr!("AOSC OS Installer"), None)
+        .button(tr!("OK"), move |s| {
             partition_view_to_next(s, config_clone.clone())
         })
-        .button("Cancel", move |s| {
-            s.cb_sink()
-                .send(Box::new(|s| {
+        .button(tr!("Cancel"), move |s| {
+            s.cb_sink()
+                .send(Box::new(|s| {
+                    s.pop_layer();
+                }))
+                .unwrap()
+        });
+
+    s.add_layer(view);
+}
+
+fn partition_view_to_next(s: &mut Cursive, config: InstallConfig) {
+    s.pop_layer();
+    if config.storage_mode == Some(StorageMode::Advanced) {
+        mounts::select_mount_points(s, config, select_user);
+    } else {
+        select_user(s, config);
+    }
+}
+
+fn select_user(s: &mut Cursive, config_clone: InstallConfig) {
+    // the passwords are not kept in a continued session, and the user account entered
+    // before going back is shown again instead
+    let last_config = Path::new(LAST_USER_CONFIG_FILE).exists();
+    if last_config && config_clone.user.is_some() && config_clone.password.is_some() {
+        is_use_last_config(s, config_clone);
+    } else {
+        s.add_layer(select_user_password(config_clone));
+    }
+}
+
+/// Only allow continuing once the username is valid, and the passwords asked for have been
+/// entered and confirmed (or left empty to keep the ones entered before)
+/// The login shell chosen on the user screen, or the custom one entered
+fn read_shell(s: &mut Cursive) -> Option<Arc<String>> {
+    let shell = s
+        .call_on_name("shell", |view: &mut SelectView| view.selection())
+        .flatten()?;
+    if !shell.is_empty() {
+        return Some(Arc::new(shell.to_string()));
+    }
+
+    s.call_on_name("custom_shell", |view: &mut EditView| view.get_content())
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .map(Arc::new)
+}
+
+/// Whether the user will be an administrator and the groups to join, `other` is the
+/// comma-separated list entered besides the optional groups
+fn read_user_groups(s: &mut Cursive, other: &str) -> (bool, Vec<String>) {
+    let admin = s
+        .call_on_name("admin", |view: &mut Checkbox| view.is_checked())
+        .unwrap_or(true);
+    let mut groups = other
+        .split(',')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty() && x != "wheel")
+        .collect::<Vec<_>>();
+    for group in install::OPTIONAL_USER_GROUPS {
+        let checked = s
+            .call_on_name(&format!("group_{group}"), |view: &mut Checkbox| {
+                view.is_checked()
+            })
+            .unwrap_or(false);
+        if checked && !groups.iter().any(|x| x == group) {
+            groups.push(group.to_string());
+        }
+    }
+    if admin {
+        groups.push("wheel".to_string());
+    }
+
+    (admin, groups)
+}
+
+fn update_user_continue(s: &mut Cursive, keep_password: bool, keep_root_password: bool) {
+    let lock_root = s
+        .call_on_name("lock_root", |view: &mut Checkbox| view.is_checked())
+        .unwrap_or(false);
+    let name = s
+        .call_on_name("user", |view: &mut ResizedView<EditView>| {
+            view.get_inner().get_content()
+        })
+        .unwrap_or_default();
+    let admin = s
+        .call_on_name("admin", |view: &mut Checkbox| view.is_checked())
+        .unwrap_or(true);
+    let text = if lock_root && !admin {
+        StyledString::styled(tr!(NOT_ADMINISTRABLE), emphasis(Emphasis::Danger))
+    } else {
+        StyledString::new()
+    };
+    s.call_on_name("admin_status", |view: &mut TextView| view.set_content(text));
+    let ready = install::check_username(&name).is_ok()
+        && (admin || !lock_root)
+        && password::new_password(s, "pwd", keep_password).is_some()
+        && (!cfg!(feature = "is_retro")
+            || lock_root
+            || password::new_password(s, "root_pwd", keep_root_password).is_some());
+    s.call_on_name(USER_PASSWORD_DIALOG, |view: &mut Dialog| {
+        if let Some(button) = view.buttons_mut().next() {
+            button.set_enabled(ready);
+        }
+    });
+}
+
+fn select_user_password(config: InstallConfig) -> Shortcuts<NamedView<Dialog>> {
+    enter_step(WizardStep::User, &config);
+    if config.oobe.unwrap_or(false) {
+        return help_on_f1(
+            help_button(select_oobe(config), Screen::User).with_name(USER_PASSWORD_DIALOG),
+            Screen::User,
+        );
+    }
+
+    // the account entered before going back is shown again
+    let name = Rc::new(RefCell::new(
+        config
+            .user
+            .as_ref()
+            .map(|x| x.to_string())
+            .unwrap_or_default(),
+    ));
+    let name_copy = Rc::clone(&name);
+    let name_copy_2 = Rc::clone(&name);
+    let name_copy_3 = Rc::clone(&name);
+    let name_copy_4 = Rc::clone(&name);
+    let full_name = Rc::new(RefCell::new(
+        config
+            .full_name
+            .as_ref()
+            .map(|x| x.to_string())
+            .unwrap_or_default(),
+    ));
+    let full_name_copy = Rc::clone(&full_name);
+    let full_name_copy_2 = Rc::clone(&full_name);
+    // only the hashes have been kept, the passwords can not be shown again
+    let keep_password = config.password.is_some();
+    let keep_root_password = matches!(
+        config.root_password.as_deref(),
+        Some(RootPassword::Hash(_)) | Some(RootPassword::Password(_))
+    );
+    let lock_root = config.root_password.as_deref() == Some(&RootPassword::Locked);
+    let mut user_password_text = tr!(ENTER_USER_PASSWORD_TEXT).to_string();
+    if keep_password || keep_root_password {
+        user_password_text.push_str(tr!(
+            "\nLeave the passwords empty to keep the ones entered before.\n"
+        ));
+    }
+    let user_password_textview = TextView::new(user_password_text).max_width(80);
+    let hostname = config
+        .hostname
+        .as_ref()
+        .map(|x| x.to_string())
+        .unwrap_or_default();
+    // the passwords should not be the same as the username or hostname
+    let related: Rc<dyn Fn(&mut Cursive) -> Vec<String>> =
+        Rc::new(move |_| vec![name_copy_2.borrow().clone(), hostname.clone()]);
+    let on_password_edit: Rc<dyn Fn(&mut Cursive)> =
+        Rc::new(move |s| update_user_continue(s, keep_password, keep_root_password));
+    let uid = Rc::new(RefCell::new(
+        config.uid.map(|x| x.to_string()).unwrap_or_default(),
+    ));
+    let uid_copy = Rc::clone(&uid);
+    let uid_copy_2 = Rc::clone(&uid);
+    let groups = config
+        .groups
+        .as_ref()
+        .map(|x| x.to_vec())
+        .unwrap_or_else(default_groups);
+    let admin = config.admin.unwrap_or(true);
+    // wheel follows the administrator checkbox and the optional groups have their own
+    let other_groups = Rc::new(RefCell::new(
+        groups
+            .iter()
+            .filter(|x| *x != "wheel" && !install::OPTIONAL_USER_GROUPS.contains(&x.as_str()))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(","),
+    ));
+    let mut optional_groups = LinearLayout::horizontal();
+    for group in install::OPTIONAL_USER_GROUPS {
+        optional_groups.add_child(
+            Checkbox::new()
+                .with_checked(groups.iter().any(|x| x == group))
+                .with_name(format!("group_{group}")),
+        );
+        optional_groups.add_child(TextView::new(format!(" {group}  ")));
+    }
+    let groups = other_groups;
+    let groups_copy = Rc::clone(&groups);
+    let groups_copy_2 = Rc::clone(&groups);
+    // the system release is not unpacked yet, the shell is checked again on installing
+    let shells = install::variant_shells(
+        config
+            .variant
+            .as_ref()
+            .map(|x| x.name.as_str())
+            .unwrap_or_default(),
+    );
+    let custom_shell = config
+        .shell
+        .as_ref()
+        .map(|x| x.to_string())
+        .filter(|x| !shells.contains(x));
+    let mut shell_list = dropdown(SelectView::new())
+        .autojump()
+        .with_all_str(shells.iter());
+    // an empty value stands for the custom shell entered below
+    shell_list.add_item(tr!("Other..."), String::new());
+    shell_list.set_selection(if custom_shell.is_some() {
+        shells.len()
+    } else {
+        shells
+            .iter()
+            .position(|x| Some(x.as_str()) == config.shell.as_ref().map(|x| x.as_str()))
+            .or_else(|| shells.iter().position(|x| x == install::DEFAULT_SHELL))
+            .unwrap_or(0)
+    });
+
+    let mut user_password_view = ListView::new()
+        .child(
+            tr!("Full name"),
+            EditView::new()
+                .content(full_name.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    full_name_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("full_name"),
+        )
+        .child(
+            tr!("Username"),
+            EditView::new()
+                .content(name.borrow().clone())
+                .on_edit_mut(move |s, c, _| {
+                    name_copy.replace(c.to_owned());
+                    // tell the rule broken as soon as it is, but not for an empty username
+                    let text = match install::check_username(c) {
+                        Err(e) if !c.is_empty() => {
+                            StyledString::styled(tr!(e.rule()), emphasis(Emphasis::Danger))
+                        }
+                        _ => StyledString::new(),
+                    };
+                    s.call_on_name("user_status", |view: &mut TextView| view.set_content(text));
+                    update_user_continue(s, keep_password, keep_root_password);
+                })
+                .min_width(20)
+                .with_name("user"),
+        )
+        .child("", TextView::empty().with_name("user_status"));
+    user_password_view = password::add_password_entries(
+        user_password_view,
+        "pwd",
+        (tr!("Password"), tr!("Confirm Password")),
+        Rc::clone(&related),
+        Rc::clone(&on_password_edit),
+    );
+    user_password_view = user_password_view
+        .child(
+            tr!("UID (optional)"),
+            EditView::new()
+                .content(uid.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    uid_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("uid"),
+        )
+        .child(
+            tr!("Login Shell"),
+            shell_list
+                .on_submit(|s, shell: &String| {
+                    s.call_on_name("custom_shell", |view: &mut EditView| {
+                        view.set_enabled(shell.is_empty())
+                    });
+                })
+                .with_name("shell"),
+        )
+        .child(
+            tr!("Custom Shell"),
+            EditView::new()
+                .content(custom_shell.clone().unwrap_or_default())
+                .with_enabled(custom_shell.is_some())
+                .with_name("custom_shell")
+                .min_width(20),
+        )
+        .child(
+            tr!("Administrator"),
+            Checkbox::new()
+                .with_checked(admin)
+                .on_change(move |s, _| update_user_continue(s, keep_password, keep_root_password))
+                .with_name("admin"),
+        )
+        .child(tr!("Supplementary Groups"), optional_groups)
+        .child(
+            tr!("Other Groups"),
+            EditView::new()
+                .content(groups.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    groups_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("groups"),
+        );
+
+    user_password_view = user_password_view
+        .child(
+            tr!("Passwordless Sudo"),
+            Checkbox::new()
+                .with_checked(config.sudo_nopasswd.unwrap_or(false))
+                .with_name("sudo_nopasswd"),
+        )
+        .child(
+            tr!("Automatic Login"),
+            Checkbox::new()
+                .with_checked(config.autologin.unwrap_or(false))
+                .on_change(|s, checked| {
+                    if checked {
+                        show_msg(s, tr!("With automatic login enabled, anyone with physical access to this device will be able to use your account without a password."));
+                    }
+                })
+                .with_name("autologin"),
+        )
+        .child(
+            tr!("Disable Root Login"),
+            Checkbox::new()
+                .with_checked(lock_root)
+                .on_change(move |s, _| update_user_continue(s, keep_password, keep_root_password))
+                .with_name("lock_root"),
+        )
+        .child("", TextView::empty().with_name("admin_status"));
+
+    if cfg!(feature = "is_retro") {
+        user_password_view = password::add_password_entries(
+            user_password_view,
+            "root_pwd",
+            (tr!("Root Password"), tr!("Root Password Confirm")),
+            related,
+            on_password_edit,
+        );
+    }
+
+    let config_clone = config.clone();
+    let mut user_password_dialog = wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(user_password_textview)
+            .child(DummyView {})
+            .child(user_password_view),
+        tr!("AOSC OS Installer"),
+        None,
+    )
+    .button(tr!("Continue"), move |s| {
+        let name = name.as_ref().to_owned().into_inner();
+        let full_name = full_name.as_ref().to_owned().into_inner();
+
+        if full_name.contains('\n') || full_name.contains(':') {
+            show_msg(s, tr!("Full name is not valid, please refer to the criteria specified on top of the dialog."));
+            return;
+        }
+
+        if let Err(e) = install::check_username(&name) {
+            show_msg(s, tr!(e.rule()));
+            return;
+        }
+
+        let uid = uid.as_ref().to_owned().into_inner();
+        let uid = if uid.trim().is_empty() {
+            None
+        } else {
+            match uid.trim().parse::<u32>() {
+                Ok(uid) if uid >= 1000 => Some(uid),
+                _ => {
+                    show_msg(s, tr!("UID is not valid, please enter a number no less than 1000 or leave it empty."));
+                    return;
+                }
+            }
+        };
+
+        let lock_root = s
+            .call_on_name("lock_root", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let sudo_nopasswd = s
+            .call_on_name("sudo_nopasswd", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let autologin = s
+            .call_on_name("autologin", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let shell = read_shell(s);
+        if let Some(shell) = shell.as_ref().filter(|x| !install::is_valid_shell_path(x)) {
+            show_msg(s, &tr!("Shell {} is not valid, please enter the absolute path of the shell, e.g., /usr/bin/zsh.", shell));
+            return;
+        }
+
+        let (admin, groups) = read_user_groups(s, &groups.borrow());
+        if let Some(group) = groups.iter().find(|x| !install::is_valid_group_name(x)) {
+            show_msg(s, &tr!("Group {} is not valid, please enter comma-separated group names.", group));
+            return;
+        }
+
+        // the created user must be able to administrate the system without root
+        if lock_root && !admin {
+            show_msg(s, tr!(NOT_ADMINISTRABLE));
+            return;
+        }
+
+        // empty passwords are refused, the root account may be locked instead, `None` keeps
+        // the password entered before going back
+        let password = password::new_password(s, "pwd", keep_password);
+        let root_password = if cfg!(feature = "is_retro") && !lock_root {
+            password::new_password(s, "root_pwd", keep_root_password)
+        } else {
+            Some(None)
+        };
+        let (Some(password), Some(root_password)) = (password, root_password) else {
+            show_msg(s, tr!("Please enter the passwords and confirm them."));
+            return;
+        };
+        if name.is_empty() {
+            fill_in_all_the_fields!(s);
+        }
+
+        if root_password.as_deref().is_some_and(RootPassword::is_ambiguous_password) {
+            show_msg(s, tr!("Root password may not be \"none\" or \"locked\", or start with \"$\"."));
+            return;
+        }
+
+        // only the hashes are kept, so that the saved configuration has no plain text passwords
+        let cost = config.password_cost;
+        let hash = move |x: Option<String>| x.map(|x| install::hash_password(&x, cost)).transpose();
+        let mut config = config.clone();
+        run_in_background(
+            s,
+            tr!("Hashing the passwords ..."),
+            move || hash(password).and_then(|password| Ok((password, hash(root_password)?))),
+            move |s, hashes| {
+                let (password, root_password) = match hashes {
+                    Ok(hashes) => hashes,
+                    Err(e) => {
+                        show_error(s, &e.to_string());
+                        return;
+                    }
+                };
+
+                if let Some(password) = password {
+                    config.password = Some(Arc::new(password));
+                }
+                config.user = Some(Arc::new(name));
+                config.full_name = Some(Arc::new(full_name));
+                config.uid = uid;
+                config.sudo_nopasswd = Some(sudo_nopasswd);
+                config.autologin = Some(autologin);
+                config.shell = shell;
+                config.admin = Some(admin);
+                config.groups = Some(Arc::new(groups));
+                config.root_password = match root_password {
+                    _ if lock_root => Some(Arc::new(RootPassword::Locked)),
+                    Some(hash) => Some(Arc::new(RootPassword::Hash(hash))),
+                    None if keep_root_password => config.root_password.clone(),
+                    None => None,
+                };
+                s.pop_layer();
+                advance_to(s, config, select_hostname);
+            },
+        );
+    })
+    .button(tr!("Back"), move |s| {
+        // keep what has been entered so far for coming back, it is checked on continuing
+        let mut config = config_clone.clone();
+        let name = name_copy_3.borrow().clone();
+        config.user = (!name.is_empty()).then(|| Arc::new(name));
+        config.full_name = Some(Arc::new(full_name_copy_2.borrow().clone()));
+        if let Ok(uid) = uid_copy_2.borrow().trim().parse::<u32>() {
+            config.uid = Some(uid);
+        }
+        let (admin, groups) = read_user_groups(s, &groups_copy_2.borrow());
+        config.admin = Some(admin);
+        config.groups = Some(Arc::new(groups));
+        config.sudo_nopasswd = s.call_on_name("sudo_nopasswd", |view: &mut Checkbox| view.is_checked());
+        config.autologin = s.call_on_name("autologin", |view: &mut Checkbox| view.is_checked());
+        config.shell = read_shell(s);
+        s.pop_layer();
+        select_disk(s, config);
+    })
+    .button(tr!("Exit"), |s| s.quit());
+
+    // ready as it is only when coming back with everything kept
+    let ready = install::check_username(&name_copy_4.borrow()).is_ok()
+        && (admin || !lock_root)
+        && keep_password
+        && (!cfg!(feature = "is_retro") || lock_root || keep_root_password);
+    if let Some(button) = user_password_dialog.buttons_mut().next() {
+        button.set_enabled(ready);
+    }
+    user_password_dialog
+        .focus_view(&Selector::Name("full_name"))
+        .ok();
+
+    help_on_f1(
+        help_button(user_password_dialog, Screen::User).with_name(USER_PASSWORD_DIALOG),
+        Screen::User,
+    )
+}
+
+/// Stands in for the user account screen when the user account is created on first boot
+fn select_oobe(config: InstallConfig) -> Dialog {
+    let config_clone = config.clone();
+    wrap_in_dialog(
+        TextView::new(tr!(OOBE_TEXT)),
+        tr!("AOSC OS Installer"),
+        None,
+    )
+    .button(tr!("Continue"), move |s| {
+        s.pop_layer();
+        advance_to(s, config.clone(), select_hostname);
+    })
+    .button(tr!("Back"), move |s| {
+        s.pop_layer();
+        select_disk(s, config_clone.clone());
+    })
+    .button(tr!("Exit"), |s| s.quit())
+}
+
+fn select_hostname(siv: &mut Cursive, config: InstallConfig) {
+    siv.pop_layer();
+    enter_step(WizardStep::Hostname, &config);
+    let default_hostname = config.hostname.clone().unwrap_or_else(|| {
+        install::gen_default_hostname(
+            config
+                .variant
+                .as_ref()
+                .map(|x| x.name.as_str())
+                .unwrap_or_default(),
+        )
+    });
+    let hostname = Rc::new(RefCell::new(default_hostname.clone()));
+    let hostname_copy = Rc::clone(&hostname);
+    let hostname_copy_2 = Rc::clone(&hostname);
+    let ssh_key = Rc::new(RefCell::new(
+        config
+            .ssh_keys
+            .as_ref()
+            .map(|x| x.join("\n"))
+            .unwrap_or_default(),
+    ));
+    let ssh_key_copy = Rc::clone(&ssh_key);
+    let ssh_key_copy_2 = Rc::clone(&ssh_key);
+    let extra_packages = Rc::new(RefCell::new(
+        config
+            .extra_packages
+            .as_ref()
+            .map(|x| x.join(" "))
+            .unwrap_or_default(),
+    ));
+    let extra_packages_copy = Rc::clone(&extra_packages);
+    let extra_packages_copy_2 = Rc::clone(&extra_packages);
+    let kernel_cmdline = Rc::new(RefCell::new(
+        config
+            .kernel_cmdline
+            .as_ref()
+            .map(|x| x.to_string())
+            .unwrap_or_default(),
+    ));
+    let kernel_cmdline_copy = Rc::clone(&kernel_cmdline);
+    let kernel_cmdline_copy_2 = Rc::clone(&kernel_cmdline);
+    let grub_timeout = Rc::new(RefCell::new(
+        config
+            .grub_timeout
+            .map(|x| x.to_string())
+            .unwrap_or_default(),
+    ));
+    let grub_timeout_copy = Rc::clone(&grub_timeout);
+    let grub_timeout_copy_2 = Rc::clone(&grub_timeout);
+    let hostname_textview = TextView::new(tr!(ENTER_HOSTNAME_TEXT));
+    let mut hostname_view = ListView::new()
+        .child(
+            tr!("Hostname"),
+            EditView::new()
+                .content(default_hostname)
+                .on_edit_mut(move |_, c, _| {
+                    hostname_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("hostname"),
+        )
+        .delimiter()
+        .child(
+            tr!("Enable SSH Server"),
+            Checkbox::new()
+                .with_checked(config.enable_sshd.unwrap_or(false))
+                .with_name("enable_sshd"),
+        )
+        .child(
+            tr!("SSH Key (key, file or URL)"),
+            EditView::new()
+                .content(ssh_key.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    ssh_key_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("ssh_key"),
+        )
+        .child(
+            tr!("Disable SSH Password Login"),
+            Checkbox::new()
+                .with_checked(config.ssh_disable_password_auth.unwrap_or(false))
+                .with_name("ssh_disable_password_auth"),
+        )
+        .delimiter();
+    let enable_services = config.enable_services.clone().unwrap_or_default();
+    for (label, unit) in CURATED_SERVICES {
+        hostname_view.add_child(
+            label,
+            Checkbox::new()
+                .with_checked(
+                    enable_services
+                        .iter()
+                        .any(|x| install::unit_name(x) == *unit),
+                )
+                .with_name(*unit),
+        );
+    }
+    let hostname_view = hostname_view
+        .delimiter()
+        .child(
+            tr!("Additional Packages"),
+            EditView::new()
+                .content(extra_packages.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    extra_packages_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("extra_packages"),
+        )
+        .child(
+            tr!("Kernel Parameters"),
+            EditView::new()
+                .content(kernel_cmdline.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    kernel_cmdline_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("kernel_cmdline"),
+        )
+        .child(
+            tr!("Boot Menu Timeout (seconds)"),
+            EditView::new()
+                .content(grub_timeout.borrow().clone())
+                .on_edit_mut(move |_, c, _| {
+                    grub_timeout_copy.replace(c.to_owned());
+                })
+                .min_width(20)
+                .with_name("grub_timeout"),
+        )
+        .child(
+            tr!("Hide Boot Menu"),
+            Checkbox::new()
+                .with_checked(config.grub_hidden_menu.unwrap_or(false))
+                .with_name("grub_hidden_menu"),
+        )
+        .child(
+            tr!("Detect Other Operating Systems"),
+            Checkbox::new()
+                .with_checked(config.grub_os_prober.unwrap_or(false))
+                .with_name("grub_os_prober"),
+        )
+        .child(
+            tr!("Boot Splash"),
+            Checkbox::new()
+                .with_checked(boot_splash(&config))
+                .with_name("boot_splash"),
+        )
+        .child(
+            tr!("HiDPI Console Font"),
+            Checkbox::new()
+                .with_checked(hidpi(&config))
+                .with_name("hidpi"),
+        )
+        .delimiter()
+        .child(
+            tr!("Default Target"),
+            dropdown(SelectView::new())
+                .autojump()
+                .with_all(
+                    std::iter::once((tr!("Automatic").to_string(), None)).chain(
+                        install::DEFAULT_TARGETS
+                            .iter()
+                            .map(|x| (x.to_string(), Some(x.to_string()))),
+                    ),
+                )
+                .selected(
+                    config
+                        .default_target
+                        .as_ref()
+                        .and_then(|x| {
+                            install::DEFAULT_TARGETS
+                                .iter()
+                                .position(|y| x.as_str() == *y)
+                        })
+                        .map(|x| x + 1)
+                        .unwrap_or(0),
+                )
+                .with_name("default_target"),
+        )
+        .child(
+            tr!("Disable Display Manager"),
+            Checkbox::new()
+                .with_checked(config.disable_display_manager.unwrap_or(false))
+                .with_name("disable_display_manager"),
+        );
+    let config_clone = config.clone();
+    let hostname_dialog = wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(hostname_textview)
+            .child(DummyView {})
+            .child(hostname_view),
+        tr!("AOSC OS Installer"),
+        None,
+    )
+    .button(tr!("Continue"), move |s| {
+        let hostname = hostname.as_ref().to_owned().into_inner();
+        if hostname.is_empty() {
+            fill_in_all_the_fields!(s);
+        }
+        if let Some(lower) = install::suggest_hostname(&hostname) {
+            let config = config.clone();
+            s.add_layer(
+                wrap_in_dialog(
+                    TextView::new(tr!("Hostname may not contain uppercase letters. Would you like to use \"{}\" instead?", lower)),
+                    tr!("AOSC OS Installer"),
+                    None,
+                )
+                .button(tr!("Yes"), move |s| {
+                    let mut config = config.clone();
+                    config.hostname = Some(lower.clone());
                     s.pop_layer();
-                }))
-                .unwrap()
+                    advance_to(s, config, select_timezone);
+                })
+                .button(tr!("No"), |s| {
+                    s.pop_layer();
+                }),
+            );
+            return;
+        }
+        if !install::is_valid_hostname(&hostname) {
+            show_msg(s, tr!("Hostname is not vaild!"));
+            return;
+        }
+        let enable_sshd = s
+            .call_on_name("enable_sshd", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let ssh_disable_password_auth = s
+            .call_on_name("ssh_disable_password_auth", |view: &mut Checkbox| {
+                view.is_checked()
+            })
+            .unwrap_or(false);
+        let ssh_key = ssh_key.as_ref().to_owned().into_inner();
+        if ssh_disable_password_auth && ssh_key.trim().is_empty() {
+            show_msg(s, tr!("Please supply an SSH key before disabling SSH password login."));
+            return;
+        }
+        let extra_packages = extra_packages
+            .as_ref()
+            .to_owned()
+            .into_inner()
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        if let Some(package) = extra_packages.iter().find(|x| !install::is_valid_package_name(x)) {
+            show_msg(s, &tr!("Package name {} is not valid, please enter space-separated package names.", package));
+            return;
+        }
+        let kernel_cmdline = kernel_cmdline.as_ref().to_owned().into_inner();
+        if !install::is_valid_kernel_cmdline(&kernel_cmdline) {
+            show_msg(s, tr!("Kernel parameters may not contain double quotes, \"$\", \"`\" or \"\\\"."));
+            return;
+        }
+        let grub_timeout = grub_timeout.as_ref().to_owned().into_inner();
+        let grub_timeout = if grub_timeout.trim().is_empty() {
+            None
+        } else {
+            match grub_timeout.trim().parse::<u32>() {
+                Ok(timeout) => Some(timeout),
+                Err(_) => {
+                    show_msg(s, tr!("Boot menu timeout is not valid, please enter a number of seconds or leave it empty."));
+                    return;
+                }
+            }
+        };
+        let grub_hidden_menu = s
+            .call_on_name("grub_hidden_menu", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let grub_os_prober = s
+            .call_on_name("grub_os_prober", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let boot_splash = s
+            .call_on_name("boot_splash", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let hidpi = s
+            .call_on_name("hidpi", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        let default_target = s
+            .call_on_name("default_target", |view: &mut SelectView<Option<String>>| {
+                view.selection()
+            })
+            .flatten()
+            .and_then(|x| x.as_ref().clone());
+        let disable_display_manager = s
+            .call_on_name("disable_display_manager", |view: &mut Checkbox| {
+                view.is_checked()
+            })
+            .unwrap_or(false);
+        // units from the configuration file that are not offered here are kept
+        let mut enable_services = config
+            .enable_services
+            .as_ref()
+            .map(|x| x.to_vec())
+            .unwrap_or_default();
+        enable_services.retain(|x| {
+            !CURATED_SERVICES
+                .iter()
+                .any(|(_, unit)| install::unit_name(x) == *unit)
         });
+        for (_, unit) in CURATED_SERVICES {
+            if s.call_on_name(unit, |view: &mut Checkbox| view.is_checked())
+                .unwrap_or(false)
+            {
+                enable_services.push(unit.to_string());
+            }
+        }
+        let mut config = config.clone();
+        config.hostname = Some(hostname);
+        config.default_target = default_target.map(Arc::new);
+        config.disable_display_manager = Some(disable_display_manager);
+        config.grub_timeout = grub_timeout;
+        config.grub_hidden_menu = Some(grub_hidden_menu);
+        config.grub_os_prober = Some(grub_os_prober);
+        config.boot_splash = Some(boot_splash);
+        config.hidpi = Some(hidpi);
+        config.extra_packages = Some(Arc::new(extra_packages));
+        config.kernel_cmdline = Some(Arc::new(kernel_cmdline.trim().to_string()));
+        config.enable_sshd = Some(enable_sshd);
+        config.enable_services = Some(Arc::new(enable_services));
+        config.ssh_disable_password_auth = Some(ssh_disable_password_auth);
+        if ssh_key.trim().is_empty() {
+            config.ssh_keys = Some(Arc::new(vec![]));
+            advance_to(s, config, select_timezone);
+            return;
+        }
+        // the keys may be fetched from a URL, which can take until the network times out
+        run_in_background(
+            s,
+            tr!("Importing SSH keys ..."),
+            move || resolve_ssh_keys(&ssh_key),
+            move |s, ssh_keys| {
+                let ssh_keys = match ssh_keys {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        show_msg(s, &tr!("Failed to import SSH key: {}", e));
+                        return;
+                    }
+                };
+                if ssh_disable_password_auth && ssh_keys.is_empty() {
+                    show_msg(s, tr!("Please supply an SSH key before disabling SSH password login."));
+                    return;
+                }
+                config.ssh_keys = Some(Arc::new(ssh_keys));
+                advance_to(s, config, select_timezone);
+            },
+        );
+    })
+    .button(tr!("Back"), move |s| {
+        // keep what has been entered so far for coming back, it is checked on continuing
+        let mut config = config_clone.clone();
+        let checked = |s: &mut Cursive, name: &str| {
+            s.call_on_name(name, |view: &mut Checkbox| view.is_checked())
+        };
+        config.hostname = Some(hostname_copy_2.borrow().clone()).filter(|x| !x.is_empty());
+        config.enable_sshd = checked(s, "enable_sshd");
+        config.ssh_keys = Some(Arc::new(
+            ssh_key_copy_2
+                .borrow()
+                .lines()
+                .filter(|x| !x.trim().is_empty())
+                .map(|x| x.to_string())
+                .collect(),
+        ));
+        config.ssh_disable_password_auth = checked(s, "ssh_disable_password_auth");
+        config.extra_packages = Some(Arc::new(
+            extra_packages_copy_2
+                .borrow()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_string())
+                .collect(),
+        ));
+        config.kernel_cmdline = Some(Arc::new(kernel_cmdline_copy_2.borrow().trim().to_string()));
+        config.grub_timeout = grub_timeout_copy_2.borrow().trim().parse().ok();
+        config.grub_hidden_menu = checked(s, "grub_hidden_menu");
+        config.grub_os_prober = checked(s, "grub_os_prober");
+        config.boot_splash = checked(s, "boot_splash");
+        config.hidpi = checked(s, "hidpi");
+        config.disable_display_manager = checked(s, "disable_display_manager");
+        s.pop_layer();
+        s.add_layer(select_user_password(config));
+    })
+    .button(tr!("Exit"), |s| s.quit());
 
-    s.add_layer(view);
+    siv.add_layer(with_help(hostname_dialog, Screen::Hostname));
 }
 
-fn partition_view_to_next(s: &mut Cursive, config_clone: InstallConfig) {
-    s.pop_layer();
-    if config_clone.user.is_some() {
-        is_use_last_config(s, config_clone);
-    } else {
-        s.add_layer(select_user_password(config_clone));
-    }
-}
+fn select_timezone(siv: &mut Cursive, config: InstallConfig) {
+    siv.pop_layer();
+    enter_step(WizardStep::Timezone, &config);
 
-fn select_user_password(config: InstallConfig) -> Dialog {
-    let password = Rc::new(RefCell::new(String::new()));
-    let password_copy = Rc::clone(&password);
-    let password_confirm = Rc::new(RefCell::new(String::new()));
-    let password_confirm_copy = Rc::clone(&password_confirm);
-    let name = Rc::new(RefCell::new(String::new()));
-    let name_copy = Rc::clone(&name);
-    let full_name = Rc::new(RefCell::new(String::new()));
-    let full_name_copy = Rc::clone(&full_name);
-    let user_password_textview = TextView::new(ENTER_USER_PASSWORD_TEXT).max_width(80);
-    let root_password = Rc::new(RefCell::new(String::new()));
-    let root_password_copy = Rc::clone(&root_password);
-    let root_password_confirm = Rc::new(RefCell::new(String::new()));
-    let root_password_confirm_copy = Rc::clone(&root_password_confirm);
+    let now_locale = config
+        .locale
+        .as_ref()
+        .map(|x| x.to_string())
+        .or_else(read_locale)
+        .unwrap_or_else(|| locale::FALLBACK_LOCALE.to_string());
+    let locale_choice = Rc::new(RefCell::new(locale::LocaleChoice {
+        default: Some(now_locale.clone()),
+        extra: config
+            .extra_locales
+            .as_ref()
+            .map(|x| x.to_vec())
+            .unwrap_or_default(),
+    }));
+    let locale_choice_copy = Rc::clone(&locale_choice);
+    let locale_query = Rc::new(RefCell::new(String::new()));
+    let zoneinfo_list = install::get_zoneinfo_list().unwrap_or_default();
+    let now_timezone = config
+        .timezone
+        .as_ref()
+        .map(|x| x.to_string())
+        .or_else(read_timezone)
+        .and_then(|x| install::check_timezone(&x, &zoneinfo_list).ok())
+        .unwrap_or_else(|| "UTC".to_string());
+    let timezone = Rc::new(RefCell::new(now_timezone.clone()));
+    let timezone_copy = Rc::clone(&timezone);
+    // RTC/UTC default is UTC
+    let tc = Rc::new(RefCell::new(
+        config
+            .tc
+            .as_ref()
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| String::from("UTC")),
+    ));
+    let is_rtc = tc.borrow().as_str() == "RTC";
+    let tc_copy = Rc::clone(&tc);
+    let timezone_textview = TextView::new(tr!(ENTER_TIMEZONE_TEXT));
+    let mut timezone_selected_status = TextView::new(now_timezone);
+    let timezone_status_text = Arc::new(timezone_selected_status.get_shared_content());
+    let mut locale_selected_status = TextView::new(now_locale);
+    let locale_status_text = locale_selected_status.get_shared_content();
+    let mut extra_locales_selected_status =
+        TextView::new(extra_locales_summary(&locale_choice.borrow().extra));
+    let extra_locales_status_text = extra_locales_selected_status.get_shared_content();
+    let show_locales: Rc<dyn Fn(&mut Cursive, &locale::LocaleChoice)> =
+        Rc::new(move |_, choice| {
+            locale_status_text.set_content(choice.default.clone().unwrap_or_default());
+            extra_locales_status_text.set_content(extra_locales_summary(&choice.extra));
+        });
 
-    let mut user_password_view = ListView::new()
+    let keyboard_layouts = Rc::new(keyboard::sort_layouts(
+        install::get_keyboard_layouts(Path::new("/")).unwrap_or_default(),
+    ));
+    let keyboard_layout = keyboard_layouts
+        .iter()
+        .find(|x| Some(x.keymap()) == config.keymap.as_ref().map(|x| x.to_string()))
+        .or_else(|| keyboard_layouts.iter().find(|x| x.keymap() == "us"))
+        .or_else(|| keyboard_layouts.first());
+    let keymap = Rc::new(RefCell::new(
+        keyboard_layout.map(|x| x.keymap()).unwrap_or_default(),
+    ));
+    let keymap_copy = Rc::clone(&keymap);
+    let mut keymap_selected_status = TextView::new(
+        keyboard_layout
+            .map(keyboard::describe_layout)
+            .unwrap_or_default(),
+    );
+    let keymap_status_text = keymap_selected_status.get_shared_content();
+    let encrypt = config.encrypt.unwrap_or(false);
+    let timezone_view = ListView::new()
         .child(
-            "Full name",
-            EditView::new()
-                .on_edit_mut(move |_, c, _| {
-                    full_name_copy.replace(c.to_owned());
-                })
-                .min_width(20)
-                .with_name("full_name"),
+            tr!("Timezone"),
+            Button::new(tr!("Select timezone"), move |s| {
+                s.add_layer(timezone::timezone_picker(
+                    timezone::list_zones(),
+                    timezone_copy.clone(),
+                    timezone_status_text.clone(),
+                ))
+            }),
         )
+        .child(tr!("Selected Timezone"), timezone_selected_status.center())
         .child(
-            "Username",
-            EditView::new()
-                .on_edit_mut(move |_, c, _| {
-                    name_copy.replace(c.to_owned());
-                })
-                .min_width(20)
-                .with_name("user"),
+            tr!("Locales"),
+            Button::new(tr!("Select locales"), move |s| {
+                // a copy is edited, so that the choice is not applied until confirmed
+                let choice = locale_choice_copy.borrow().clone();
+                let locale_choice = locale_choice_copy.clone();
+                let show_locales = show_locales.clone();
+                s.add_layer(locale::locale_picker(
+                    Rc::new(locale::list_locales()),
+                    Rc::new(RefCell::new(choice)),
+                    locale_query.clone(),
+                    Rc::new(move |s, choice| {
+                        locale_choice.replace(choice.clone());
+                        show_locales(s, choice);
+                    }),
+                ))
+            }),
         )
+        .child(tr!("Selected locale"), locale_selected_status.center())
         .child(
-            "Password",
-            EditView::new()
-                .secret()
-                .on_edit_mut(move |_, c, _| {
-                    password_copy.replace(c.to_owned());
-                })
-                .min_width(20)
-                .with_name("pwd"),
+            tr!("Selected Additional"),
+            extra_locales_selected_status.center(),
         )
         .child(
-            "Confirm Password",
-            EditView::new()
-                .secret()
-                .on_edit_mut(move |_, c, _| {
-                    password_confirm_copy.replace(c.to_owned());
+            tr!("RTC Timezone"),
+            dropdown(SelectView::new())
+                .autojump()
+                .with_all(vec![
+                    (tr!("UTC (Recommended)"), "UTC"),
+                    (tr!("Local time (like Windows)"), "RTC"),
+                ])
+                .selected(usize::from(is_rtc))
+                .on_submit(move |_, c: &str| {
+                    tc_copy.replace(c.to_string());
                 })
-                .min_width(20)
-                .with_name("pwd2"),
+                .min_width(20),
+        )
+        .child(
+            tr!("Keyboard Layout"),
+            Button::new(tr!("Select layout"), move |s| {
+                s.add_layer(keyboard::keyboard_picker(
+                    keyboard_layouts.clone(),
+                    keymap_copy.clone(),
+                    keymap_status_text.clone(),
+                    encrypt,
+                ))
+            }),
+        )
+        .child(tr!("Selected Layout"), keymap_selected_status.center())
+        .child(
+            tr!("Network Time Sync"),
+            Checkbox::new()
+                .with_checked(config.enable_ntp.unwrap_or(true))
+                .with_name("enable_ntp"),
         );
+    let read_form = Rc::new(move |s: &mut Cursive, config: &mut InstallConfig| {
+        let choice = locale_choice.borrow().clone();
+        let locale = choice
+            .default
+            .unwrap_or_else(|| locale::FALLBACK_LOCALE.to_string());
 
-    if cfg!(feature = "is_retro") {
-        user_password_view = user_password_view
-            .child(
-                "Root Password",
-                EditView::new()
-                    .secret()
-                    .on_edit_mut(move |_, c, _| {
-                        root_password_copy.replace(c.to_owned());
-                    })
-                    .min_width(20)
-                    .with_name("root_pwd"),
-            )
-            .child(
-                "Root Password Confirm",
-                EditView::new()
-                    .secret()
-                    .on_edit_mut(move |_, c, _| {
-                        root_password_confirm_copy.replace(c.to_owned());
-                    })
-                    .min_width(20)
-                    .with_name("root_pwd2"),
-            )
-    }
+        let mut timezone = timezone.as_ref().to_owned().into_inner();
 
+        // tzdata 的北京时间是 Asia/Shanghai
+        if timezone == "Asia/Beijing" {
+            timezone = "Asia/Shanghai".to_string();
+        }
+
+        let tc = tc.as_ref().to_owned().into_inner();
+        config.locale = Some(Arc::new(locale.to_string()));
+        config.extra_locales = Some(Arc::new(choice.extra));
+        config.timezone = Some(Arc::new(timezone));
+        config.tc = Some(Arc::new(tc));
+        config.enable_ntp = s
+            .call_on_name("enable_ntp", |view: &mut Checkbox| view.is_checked())
+            .or(Some(true));
+        let keymap = keymap.borrow();
+        config.keymap = (!keymap.is_empty()).then(|| Arc::new(keymap.to_string()));
+    });
+    let read_form_copy = Rc::clone(&read_form);
     let config_clone = config.clone();
-    let mut user_password_dialog = wrap_in_dialog(
+    let timezone_dialog = wrap_in_dialog(
         LinearLayout::vertical()
-            .child(user_password_textview)
+            .child(timezone_textview)
             .child(DummyView {})
-            .child(user_password_view),
-        "AOSC OS Installer",
+            .child(timezone_view),
+        tr!("AOSC OS Installer"),
         None,
     )
-    .button("Continue", move |s| {
-        let password = password.as_ref().to_owned().into_inner();
-        let password_confirm = password_confirm.as_ref().to_owned().into_inner();
-        let name = name.as_ref().to_owned().into_inner();
-        let full_name = full_name.as_ref().to_owned().into_inner();
-        let root_password = root_password.as_ref().to_owned().into_inner();
-        let root_password_confirm = root_password_confirm.as_ref().to_owned().into_inner();
-
-        if full_name.contains('\n') || full_name.contains(':') {
-            show_msg(s, "Full name is not valid, please refer to the criteria specified on top of the dialog.");
-            return;
+    .button(tr!("Continue"), move |s| {
+        let mut config = config.clone();
+        read_form(s, &mut config);
+        let filled = [&config.locale, &config.timezone, &config.tc]
+            .into_iter()
+            .all(|x| x.as_ref().is_some_and(|x| !x.is_empty()));
+        if !filled {
+            fill_in_all_the_fields!(s);
         }
 
-        if !install::is_acceptable_username(&name) {
-            show_msg(s, "Username is not valid, please refer to the criteria specified on top of the dialog.");
-            return;
-        }
+        advance_to(s, config, select_swap);
+    })
+    .button(tr!("Back"), move |s| {
+        // the selections are kept for coming back
+        let mut config = config_clone.clone();
+        read_form_copy(s, &mut config);
+        s.pop_layer();
+        select_hostname(s, config);
+    })
+    .button(tr!("Exit"), |s| s.quit());
 
-        if password.is_empty() || password_confirm.is_empty() || name.is_empty() || (cfg!(feature = "is_retro") && (root_password.is_empty() || root_password_confirm.is_empty())) {
-            fill_in_all_the_fields!(s);
-        }
+    siv.add_layer(with_help(timezone_dialog, Screen::Timezone));
+}
 
-        if password != password_confirm {
-            show_msg(s, "Passwords password do not match.");
-            return;
-        }
+fn extra_locales_summary(extra_locales: &[String]) -> String {
+    if extra_locales.is_empty() {
+        tr!("None").to_string()
+    } else {
+        extra_locales.join(", ")
+    }
+}
 
-        if root_password != root_password_confirm {
-            show_msg(s, "Passwords (root) password do not match.");
-            return;
+/// The size in GiB to show in the swapfile size field
+fn swap_size_text(size: u64) -> String {
+    format!("{}", (size as f64 / 1024_f64.powi(3) * 10.0).round() / 10.0)
+}
+
+/// Show the options of the swap kind selected
+fn show_swap_options(siv: &mut Cursive, kind: SwapKind) {
+    for (name, visible) in [
+        ("swap_partition_options", kind == SwapKind::Partition),
+        ("swap_file_options", kind == SwapKind::File),
+        ("hibernation_options", kind.is_on_disk()),
+    ] {
+        siv.call_on_name(name, |view: &mut HideableView<ListView>| {
+            view.set_visible(visible)
+        });
+    }
+}
+
+/// The swapfile size entered on the swap screen in bytes, if it is valid
+fn read_swapfile_size(s: &mut Cursive) -> Option<f64> {
+    s.call_on_name("swap_size", |view: &mut EditView| view.get_content())
+        .map(|x| x.trim().parse::<f64>())
+        .and_then(|x| x.ok())
+        .filter(|x| *x > 0.0 && x.is_finite())
+        .map(|x| x * 1024.0 * 1024.0 * 1024.0)
+}
+
+/// Show how the system partition is spent with the swap selected, the installation can only
+/// go on if it is large enough
+fn update_space_budget(s: &mut Cursive, budget: SpaceBudget) {
+    let swapfile = s
+        .call_on_name("swap_file_options", |view: &mut HideableView<ListView>| {
+            view.is_visible()
+        })
+        .unwrap_or(false);
+    let budget = SpaceBudget {
+        swapfile: if swapfile {
+            read_swapfile_size(s).unwrap_or(0.0) as u64
+        } else {
+            0
+        },
+        ..budget
+    };
+    let status = match budget.status() {
+        SpaceStatus::Enough => StyledString::new(),
+        SpaceStatus::Tight => emphasized(budget.status_text(), Emphasis::Warning),
+        SpaceStatus::Short(_) => emphasized(budget.status_text(), Emphasis::Danger),
+    };
+    s.call_on_name("space_budget", |view: &mut TextView| {
+        view.set_content(budget.describe())
+    });
+    s.call_on_name("space_status", |view: &mut TextView| {
+        view.set_content(status)
+    });
+    s.call_on_name(SWAP_DIALOG, |view: &mut Dialog| {
+        if let Some(button) = view.buttons_mut().next() {
+            button.set_enabled(!matches!(budget.status(), SpaceStatus::Short(_)));
         }
+    });
+}
 
-        let mut config = config.clone();
-        config.password = Some(Arc::new(password));
-        config.user = Some(Arc::new(name));
-        config.full_name = Some(Arc::new(full_name));
-        config.root_password = if !root_password.is_empty() {
-            Some(Arc::new(root_password))
+fn select_swap(siv: &mut Cursive, config: InstallConfig) {
+    let config_clone = config.clone();
+    let variant = config.variant.as_ref().unwrap();
+    let budget = SpaceBudget {
+        partition: config.partition.as_ref().unwrap().size,
+        download: variant.size,
+        installed: variant.install_size,
+        swapfile: 0,
+    };
+    siv.pop_layer();
+    enter_step(WizardStep::Swap, &config);
+
+    let mem = sysinfo::System::new_all().total_memory();
+    let recommended = recommended_swap(mem);
+    let swap_partitions = disks::list_swap_partitions();
+    let kind = match config.swap.unwrap_or(recommended) {
+        SwapKind::Partition if swap_partitions.is_empty() => SwapKind::File,
+        kind => kind,
+    };
+    let hibernation = kind.is_on_disk() && config.is_hibernation.v.load(Ordering::SeqCst);
+    let swap_size = (*config.swap_size)
+        .filter(|_| kind == SwapKind::File)
+        .map(|x| x as u64)
+        .unwrap_or_else(|| disks::recommend_swap_size(mem, hibernation));
+
+    let mut group = RadioGroup::new();
+    group.set_on_change(move |s, kind: &SwapKind| {
+        show_swap_options(s, *kind);
+        update_space_budget(s, budget);
+    });
+    let mut kinds = LinearLayout::vertical();
+    for (value, label) in [
+        (SwapKind::None, tr!("No swap")),
+        (SwapKind::Zram, tr!("Compressed swap in the RAM (zram)")),
+        (SwapKind::Partition, tr!("An existing swap partition")),
+        (SwapKind::File, tr!("A swapfile on the system partition")),
+    ] {
+        let label = if value == recommended {
+            tr!("{} (recommended)", label)
+        } else if value == SwapKind::Partition && swap_partitions.is_empty() {
+            tr!("{} (none found)", label)
         } else {
-            None
+            label.to_string()
         };
-        s.pop_layer();
-        select_hostname(s, config);
-    })
-    .button("Back", move |s| {
-        s.pop_layer();
-        select_disk(s, config_clone.clone());
-    })
-    .button("Exit", |s| s.quit());
+        let mut button = group.button(value, label);
+        if value == kind {
+            button.select();
+        }
+        if value == SwapKind::Partition && swap_partitions.is_empty() {
+            button.disable();
+        }
+        kinds.add_child(button);
+    }
+
+    let current_partition = config.swap_partition.as_ref().and_then(|x| x.path.clone());
+    let mut partition_list = dropdown(SelectView::new());
+    for partition in swap_partitions {
+        let path = partition.path.clone().unwrap_or_default();
+        partition_list.add_item(
+            format!("{} ({})", path.display(), human_size(partition.size)),
+            partition,
+        );
+    }
+    if let Some(index) = partition_list
+        .iter()
+        .position(|(_, x)| x.path == current_partition && current_partition.is_some())
+    {
+        partition_list.set_selection(index);
+    }
+    let partition_options = HideableView::new(ListView::new().child(
+        tr!("Swap Partition"),
+        partition_list.with_name("swap_partition"),
+    ))
+    .visible(kind == SwapKind::Partition)
+    .with_name("swap_partition_options");
+    let file_options = HideableView::new(
+        ListView::new().child(
+            tr!("Swapfile Size (GiB)"),
+            EditView::new()
+                .content(swap_size_text(swap_size))
+                .on_edit(move |s, _, _| update_space_budget(s, budget))
+                .with_name("swap_size")
+                .fixed_width(10),
+        ),
+    )
+    .visible(kind == SwapKind::File)
+    .with_name("swap_file_options");
+    let hibernation_options = HideableView::new(
+        ListView::new().child(
+            tr!("Enable hibernation"),
+            Checkbox::new()
+                .with_checked(hibernation)
+                .on_change(move |s, checked| {
+                    // the swapfile needs room for the whole RAM to hibernate
+                    s.call_on_name("swap_size", |view: &mut EditView| {
+                        view.set_content(swap_size_text(disks::recommend_swap_size(mem, checked)))
+                    });
+                    update_space_budget(s, budget);
+                })
+                .with_name("hibernation"),
+        ),
+    )
+    .visible(kind.is_on_disk())
+    .with_name("hibernation_options");
+
+    let view = LinearLayout::vertical()
+        .child(TextView::new(tr!(
+            "Please select how the system should swap when the memory is full. The recommendation is based on the RAM of this device ({}).",
+            human_size(mem)
+        )))
+        .child(DummyView {})
+        .child(kinds)
+        .child(DummyView {})
+        .child(partition_options)
+        .child(file_options)
+        .child(hibernation_options)
+        .child(DummyView {})
+        .child(Panel::new(TextView::new("").with_name("space_budget")).title(tr!("Space")))
+        .child(TextView::new("").with_name("space_status"));
+
+    let dialog = wrap_in_dialog(view, tr!("AOSC OS Installer"), None)
+        .button(tr!("Continue"), move |s| {
+            let mut config = config.clone();
+            let kind = *group.selection();
+            let hibernation = kind.is_on_disk()
+                && s
+                    .call_on_name("hibernation", |view: &mut Checkbox| view.is_checked())
+                    .unwrap_or(false);
+            let mut swap_size = None;
+            let mut swap_partition = None;
+            match kind {
+                SwapKind::File => {
+                    let Some(size) = read_swapfile_size(s) else {
+                        show_msg(s, tr!("Invalid custom swapfile size!"));
+                        return;
+                    };
+                    let budget = SpaceBudget {
+                        swapfile: size as u64,
+                        ..budget
+                    };
+                    if let SpaceStatus::Short(_) = budget.status() {
+                        show_msg(s, &budget.status_text());
+                        return;
+                    }
+                    if hibernation && (size as u64) < mem {
+                        show_msg(s, &tr!("To hibernate, the swap should be at least as large as the RAM ({}).", human_size(mem)));
+                        return;
+                    }
+                    swap_size = Some(size);
+                }
+                SwapKind::Partition => {
+                    let partition = s
+                        .call_on_name("swap_partition", |view: &mut SelectView<disks::Partition>| {
+                            view.selection()
+                        })
+                        .flatten();
+                    let Some(partition) = partition else {
+                        show_msg(s, tr!("Please select a swap partition."));
+                        return;
+                    };
+                    if hibernation && partition.size < mem {
+                        show_msg(s, &tr!("To hibernate, the swap should be at least as large as the RAM ({}).", human_size(mem)));
+                        return;
+                    }
+                    swap_partition = Some(Arc::new(partition.as_ref().clone()));
+                }
+                SwapKind::None | SwapKind::Zram => (),
+            }
+
+            config.swap = Some(kind);
+            config.swap_partition = swap_partition;
+            config.swap_size = Arc::new(swap_size);
+            config.use_swap = Arc::new(AtomicBoolWrapper {
+                v: AtomicBool::new(kind == SwapKind::File),
+            });
+            config.is_hibernation = Arc::new(AtomicBoolWrapper {
+                v: AtomicBool::new(hibernation),
+            });
+
+            show_summary(s, config);
+        })
+        .button(tr!("Back"), move |s| {
+            s.pop_layer();
+            select_timezone(s, config_clone.clone());
+        })
+        .button(tr!("Exit"), move |s| s.quit());
+    siv.add_layer(help_on_f1(
+        help_button(dialog, Screen::Swap).with_name(SWAP_DIALOG),
+        Screen::Swap,
+    ));
+    update_space_budget(siv, budget);
+}
+
+fn is_use_last_config(siv: &mut Cursive, config: InstallConfig) {
+    siv.pop_layer();
+    let config_copy = config.clone();
+    siv.add_layer(
+        wrap_in_dialog(
+            TextView::new(tr!(
+                "Would you like to load your previous AOSC OS installation configuration?"
+            )),
+            tr!("AOSC OS Installer"),
+            None,
+        )
+        .button(tr!("Yes"), move |s| show_summary(s, config_copy.clone()))
+        .button(tr!("No"), move |s| {
+            fs::remove_file(LAST_USER_CONFIG_FILE).ok();
+            let new_config = InstallConfig {
+                partition: config.clone().partition,
+                wipe_disk: config.clone().wipe_disk,
+                ..Default::default()
+            };
+            select_variant(s, new_config);
+        })
+        .button(tr!("Exit"), |s| s.quit()),
+    );
+}
+
+/// Size of the system partition `auto_create_partitions` will create on the disk
+pub(super) fn auto_partition_system_size(dev_size: u64, keep_free: u64) -> u64 {
+    let mut system_size = dev_size;
+    if is_efi_booted() {
+        system_size -= 512 * 1024_u64.pow(2);
+    }
+    if disks::needs_prep_partition() {
+        system_size -= 8 * 1024_u64.pow(2);
+    }
+
+    system_size.saturating_sub(keep_free)
+}
+
+fn auto_partition_desc(dev_size: u64, keep_free: u64) -> String {
+    let mut desc = String::new();
+    if is_efi_booted() {
+        desc.push_str(tr!(
+            "- A 512MiB EFI System Partition (ESP) will be created."
+        ));
+        desc.push('\n');
+    }
+    if disks::needs_prep_partition() {
+        desc.push_str(tr!(
+            "- An 8MiB PReP boot partition will be created for the bootloader."
+        ));
+        desc.push('\n');
+    }
+    desc.push_str(&tr!(
+        "- A {} system root partition will be created.",
+        human_size(auto_partition_system_size(dev_size, keep_free))
+    ));
+    if keep_free > 0 {
+        desc.push('\n');
+        desc.push_str(&tr!(
+            "- {} will be left unpartitioned at the end of the drive, for other operating systems.",
+            human_size(keep_free)
+        ));
+    }
+
+    desc
+}
+
+/// A line of the summary, with the wizard step it has been decided in for the user to go back to
+struct SummaryLine {
+    step: WizardStep,
+    text: String,
+    /// Data will be lost, highlighted in red
+    destructive: bool,
+}
+
+impl SummaryLine {
+    fn new<S: Into<String>>(step: WizardStep, text: S) -> Self {
+        SummaryLine {
+            step,
+            text: text.into(),
+            destructive: false,
+        }
+    }
+
+    fn destructive<S: Into<String>>(step: WizardStep, text: S) -> Self {
+        SummaryLine {
+            step,
+            text: text.into(),
+            destructive: true,
+        }
+    }
+}
 
-    user_password_dialog
-        .focus_view(&Selector::Name("full_name"))
-        .ok();
+/// Go back to `step` of the wizard, the settings made so far are kept in `config`
+fn goto_step(siv: &mut Cursive, step: WizardStep, config: InstallConfig) {
+    match step {
+        WizardStep::Variant => select_variant(siv, config),
+        WizardStep::Disk => select_disk(siv, config),
+        WizardStep::User => {
+            siv.pop_layer();
+            siv.add_layer(select_user_password(config));
+        }
+        WizardStep::Hostname => select_hostname(siv, config),
+        WizardStep::Timezone => select_timezone(siv, config),
+        WizardStep::Swap => select_swap(siv, config),
+    }
+}
 
-    user_password_dialog
+/// Go on to the `next` step of the wizard, or back to the summary if only a single item
+/// of it is being changed
+fn advance_to(siv: &mut Cursive, config: InstallConfig, next: fn(&mut Cursive, InstallConfig)) {
+    if config.back_to_summary {
+        return_to_summary(siv, config);
+    } else {
+        next(siv, config);
+    }
 }
 
-fn select_hostname(siv: &mut Cursive, config: InstallConfig) {
-    siv.pop_layer();
-    let hostname = Rc::new(RefCell::new(String::new()));
-    let hostname_copy = Rc::clone(&hostname);
-    let hostname_textview = TextView::new(ENTER_HOSTNAME_TEXT);
-    let hostname_view = ListView::new()
-        .child(
-            "Hostname",
-            EditView::new()
-                .on_edit_mut(move |_, c, _| {
-                    hostname_copy.replace(c.to_owned());
-                })
-                .min_width(20)
-                .with_name("hostname"),
+/// Show the summary again after changing a single item of it, over the last step of the
+/// wizard as when coming from there
+fn return_to_summary(siv: &mut Cursive, config: InstallConfig) {
+    select_swap(siv, config.clone());
+    show_summary(siv, config);
+}
+
+/// Change a single item of the summary: its step is shown with the current settings, and
+/// continuing from it comes back to the summary
+fn change_summary_item(siv: &mut Cursive, step: WizardStep, mut config: InstallConfig) {
+    read_summary_options(siv, &mut config);
+    if step != WizardStep::Disk {
+        config.back_to_summary = true;
+        siv.pop_layer();
+        goto_step(siv, step, config);
+        return;
+    }
+    // the partitions decide what the following steps offer, e.g. the swap partitions
+    siv.add_layer(
+        wrap_in_dialog(
+            TextView::new(tr!("The storage can not be changed on its own: the following steps, e.g. the swap, depend on how the drive is laid out, so they will have to be gone through again. All other settings are kept.\n\nGo back to the storage step?")),
+            tr!("AOSC OS Installer"),
+            None,
         )
-        .delimiter();
-    let config_clone = config.clone();
-    let hostname_dialog = wrap_in_dialog(
-        LinearLayout::vertical()
-            .child(hostname_textview)
-            .child(DummyView {})
-            .child(hostname_view),
-        "AOSC OS Installer",
-        None,
-    )
-    .button("Continue", move |s| {
-        let hostname = hostname.as_ref().to_owned().into_inner();
-        if hostname.is_empty() {
-            fill_in_all_the_fields!(s);
+        .button(tr!("Go Back"), move |s| {
+            s.pop_layer();
+            s.pop_layer();
+            goto_step(s, WizardStep::Disk, config.clone());
+        })
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        }),
+    );
+}
+
+/// Everything the installation is going to do, grouped by the wizard step it has been
+/// decided in
+fn summary_lines(
+    config: &InstallConfig,
+    previous_install: Option<&install::PreviousInstall>,
+) -> Result<Vec<SummaryLine>> {
+    let mut lines = vec![];
+    let partition = config.partition.as_ref();
+    let path = partition
+        .and_then(|x| x.path.as_ref())
+        .map(|x| x.display().to_string())
+        .unwrap_or_default();
+    let fs = partition
+        .and_then(|x| x.fs_type.clone())
+        .unwrap_or_default();
+    let disk = partition
+        .and_then(|x| x.parent_path.as_ref())
+        .map(|x| x.display().to_string())
+        .unwrap_or_default();
+
+    match config.storage_mode {
+        Some(StorageMode::Guided) => lines.push(SummaryLine::new(
+            WizardStep::Disk,
+            tr!("- The storage layout has been made in the guided mode."),
+        )),
+        Some(StorageMode::Advanced) => lines.push(SummaryLine::new(
+            WizardStep::Disk,
+            tr!("- The storage layout has been chosen in the advanced mode."),
+        )),
+        None => (),
+    }
+    if let Some(device) = config.wipe_disk.as_ref() {
+        lines.push(SummaryLine::destructive(
+            WizardStep::Disk,
+            tr!(
+                "- ALL DATA ON {} ({}, {}) WILL BE DESTROYED, and it will be partitioned as follows:",
+                device.path.display(),
+                device.model,
+                human_size(device.size)
+            ),
+        ));
+        for line in auto_partition_desc(device.size, config.keep_free_space.unwrap_or(0)).lines() {
+            lines.push(SummaryLine::destructive(
+                WizardStep::Disk,
+                format!("  {line}"),
+            ));
         }
-        if !install::is_valid_hostname(&hostname) {
-            show_msg(s, "Hostname is not vaild!");
-            return;
+    } else if let Some(previous) = previous_install {
+        let summary =
+            reinstall_summary(&path, &fs, previous, config.restore_users.unwrap_or(false));
+        for (i, line) in summary.lines().enumerate() {
+            // only the first line is about erasing
+            lines.push(SummaryLine {
+                step: WizardStep::Disk,
+                text: line.to_string(),
+                destructive: i == 0,
+            });
         }
-        let mut config = config.clone();
-        config.hostname = Some(hostname);
-        select_timezone(s, config);
-    })
-    .button("Back", move |s| {
-        s.pop_layer();
-        s.add_layer(select_user_password(config_clone.clone()));
-    })
-    .button("Exit", |s| s.quit());
-
-    siv.add_layer(hostname_dialog);
-}
+    } else if partition.is_some() {
+        lines.push(SummaryLine::destructive(
+            WizardStep::Disk,
+            tr!("- {} will be erased and formatted as {}.", path, fs),
+        ));
+    }
+    for mount in assigned_mounts(config) {
+        let path = mount
+            .partition
+            .path
+            .as_ref()
+            .map(|x| x.display().to_string())
+            .unwrap_or_default();
+        let fs = mount.target_fs_type().unwrap_or_default();
+        if mount.format {
+            lines.push(SummaryLine::destructive(
+                WizardStep::Disk,
+                tr!(
+                    "- {} will be erased and formatted as {}, and mounted at {}.",
+                    path,
+                    fs,
+                    mount.mount_point
+                ),
+            ));
+        } else {
+            lines.push(SummaryLine::new(
+                WizardStep::Disk,
+                tr!(
+                    "- {} ({}) will be mounted at {}, it will not be formatted.",
+                    path,
+                    fs,
+                    mount.mount_point
+                ),
+            ));
+        }
+        if mount.encrypt {
+            lines.push(SummaryLine::new(
+                WizardStep::Disk,
+                tr!(
+                    "- {} will be encrypted with LUKS, and unlocked on boot with a keyfile kept on the system partition.",
+                    path
+                ),
+            ));
+        }
+    }
 
-fn select_timezone(siv: &mut Cursive, config: InstallConfig) {
-    siv.pop_layer();
+    if config.encrypt.unwrap_or(false) {
+        lines.push(SummaryLine::new(
+            WizardStep::Disk,
+            tr!("- The system partition will be encrypted with LUKS, the passphrase will be asked for on every boot."),
+        ));
+        if config.encrypt_discard.unwrap_or(false) {
+            lines.push(SummaryLine::new(
+                WizardStep::Disk,
+                tr!("- TRIM will be allowed on the encrypted partitions."),
+            ));
+        }
+    }
 
-    let now_locale = read_locale().unwrap_or_else(|| "C.UTF-8".to_string());
-    let now_language = find_language_by_locale(&now_locale).unwrap_or("No localization (UTF-8)");
+    if partition.is_some() {
+        if is_efi_booted() {
+            let esp = partition
+                .filter(|_| !mounts::has_esp(assigned_mounts(config)))
+                .and_then(|x| x.parent_path.as_ref())
+                .filter(|_| config.wipe_disk.is_none())
+                .and_then(|x| disks::find_esp_partition(x).ok());
+            match esp {
+                Some(esp) if esp.fs_type.is_none() => lines.push(SummaryLine::destructive(
+                    WizardStep::Disk,
+                    tr!(
+                        "- The EFI System Partition {} will be formatted as FAT32.",
+                        esp.path.unwrap_or_default().display()
+                    ),
+                )),
+                Some(esp) => lines.push(SummaryLine::new(
+                    WizardStep::Disk,
+                    tr!(
+                        "- The existing EFI System Partition {} will be mounted at /efi, it will not be formatted.",
+                        esp.path.unwrap_or_default().display()
+                    ),
+                )),
+                None => (),
+            }
+            lines.push(SummaryLine::new(
+                WizardStep::Disk,
+                tr!("- GRUB will be installed to the EFI System Partition."),
+            ));
+        } else if disks::needs_prep_partition() {
+            let prep = partition
+                .and_then(|x| x.parent_path.as_ref())
+                .filter(|_| config.wipe_disk.is_none())
+                .and_then(|x| disks::find_prep_partition(x).ok())
+                .and_then(|x| x.path);
+            lines.push(SummaryLine::new(
+                WizardStep::Disk,
+                match prep {
+                    Some(prep) => tr!(
+                        "- GRUB will be installed to the PReP boot partition {}.",
+                        prep.display()
+                    ),
+                    None => {
+                        tr!(
+                            "- GRUB will be installed to the PReP boot partition on {}.",
+                            disk
+                        )
+                    }
+                },
+            ));
+        } else {
+            lines.push(SummaryLine::new(
+                WizardStep::Disk,
+                tr!(
+                    "- GRUB will be installed to the master boot record (MBR) of {}.",
+                    disk
+                ),
+            ));
+        }
+    }
 
-    let locale = Rc::new(RefCell::new(String::from(now_language)));
-    let locale_copy = Rc::clone(&locale);
-    let timezone = Rc::new(RefCell::new(String::from("UTC")));
-    let timezone_copy = Rc::clone(&timezone);
-    // RTC/UTC default is UTC
-    let tc = Rc::new(RefCell::new(String::from("UTC")));
-    let tc_copy = Rc::clone(&tc);
-    let locales = Arc::new(install::get_locale_list().unwrap());
-    let timezone_textview = TextView::new(ENTER_TIMEZONE_TEXT);
-    let mut timezone_selected_status = TextView::new("UTC");
-    let timezone_status_text = Arc::new(timezone_selected_status.get_shared_content());
-    let mut locale_selected_status = TextView::new(now_language);
-    let locale_status_text = Arc::new(locale_selected_status.get_shared_content());
+    let variant = config.variant.as_ref().unwrap();
+    lines.push(SummaryLine::new(
+        WizardStep::Variant,
+        tr!(
+            "- AOSC OS {} ({} download, {} installed) will be downloaded from {}.",
+            variant.name,
+            human_size(variant.size),
+            human_size(variant.install_size),
+            mirror_name(config.mirror.as_ref().unwrap())
+        ),
+    ));
+    if let Some(path) = config.local_release.as_ref() {
+        lines.push(SummaryLine::new(
+            WizardStep::Variant,
+            tr!(
+                "- The system release will be installed from {} instead.",
+                path.display()
+            ),
+        ));
+    }
 
-    let timezone_view = ListView::new()
-        .child(
-            "Timezone",
-            Button::new("Select timezone", move |s| {
-                let zoneinfo = install::get_zoneinfo_list().unwrap();
-                s.add_layer(set_timezone(
-                    zoneinfo,
-                    timezone_copy.clone(),
-                    timezone_status_text.clone(),
-                ))
-            }),
-        )
-        .child("Selected Timezone", timezone_selected_status.center())
-        .child(
-            "Locale",
-            Button::new("Select locale", move |s| {
-                s.add_layer(set_locales(
-                    locales.to_vec(),
-                    locale_copy.clone(),
-                    locale_status_text.clone(),
-                ))
-            }),
-        )
-        .child("Selected locale", locale_selected_status.center())
-        .child(
-            "RTC Timezone",
-            SelectView::new()
-                .autojump()
-                .popup()
-                .with_all_str(vec!["UTC (Recommended)", "Local time (like Windows)"])
-                .on_submit(move |_, c: &str| {
-                    let selected = match c {
-                        "UTC (Recommended)" => "UTC",
-                        "Local time (like Windows)" => "RTC",
-                        _ => unreachable!(),
-                    };
-                    tc_copy.replace(selected.to_string());
-                })
-                .min_width(20),
-        );
-    let config_clone = config.clone();
-    let timezone_dialog = wrap_in_dialog(
-        LinearLayout::vertical()
-            .child(timezone_textview)
-            .child(DummyView {})
-            .child(timezone_view),
-        "AOSC OS Installer",
-        None,
-    )
-    .button("Continue", move |s| {
-        // language to locale
-        let locale = locale.as_ref().to_owned().into_inner();
-        let locale = find_locale_by_language(&locale).unwrap_or("C.UTF-8");
+    let oobe = config.oobe.unwrap_or(false);
+    let user = config
+        .user
+        .as_deref()
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+    if oobe {
+        lines.push(SummaryLine::new(WizardStep::User, tr!("- No user account will be created, and the root password will not be set. The end user will create their account on first boot: until then, there will be NO interactive login to the system.")));
+    } else {
+        lines.push(SummaryLine::new(
+            WizardStep::User,
+            tr!("- User {} will be created.", user),
+        ));
+        lines.push(SummaryLine::new(
+            WizardStep::User,
+            tr!(
+                "- The user account will be: {}:x:{}::{}:/home/{}:{} (groups: {}).",
+                user,
+                config
+                    .uid
+                    .map(|x| x.to_string())
+                    .unwrap_or_else(|| tr!("auto").to_string()),
+                config
+                    .full_name
+                    .as_ref()
+                    .map(|x| x.as_str())
+                    .unwrap_or_default(),
+                user,
+                config
+                    .shell
+                    .as_ref()
+                    .map(|x| x.as_str())
+                    .unwrap_or(install::DEFAULT_SHELL),
+                config
+                    .groups
+                    .as_ref()
+                    .map(|x| x.join(","))
+                    .unwrap_or_else(|| install::DEFAULT_USER_GROUPS.join(",")),
+            ),
+        ));
+    }
+    if !config.admin.unwrap_or(true) && !oobe {
+        lines.push(SummaryLine::new(
+            WizardStep::User,
+            tr!("- The user will not be an administrator, and will not be able to use sudo."),
+        ));
+    } else if config.sudo_nopasswd.unwrap_or(false) && !oobe {
+        lines.push(SummaryLine::new(
+            WizardStep::User,
+            tr!("- The user will be able to use sudo without a password."),
+        ));
+    }
+    if config.autologin.unwrap_or(false) {
+        lines.push(SummaryLine::new(
+            WizardStep::User,
+            tr!("- The user will be logged in automatically on boot, without a password."),
+        ));
+    }
+    match config.root_password.as_deref().filter(|_| !oobe) {
+        Some(RootPassword::Locked) => lines.push(SummaryLine::new(
+            WizardStep::User,
+            tr!("- Direct root login will be disabled."),
+        )),
+        Some(RootPassword::Hash(_)) | Some(RootPassword::Password(_)) => lines.push(
+            SummaryLine::new(WizardStep::User, tr!("- The root password will be set.")),
+        ),
+        _ => (),
+    }
 
-        let mut timezone = timezone.as_ref().to_owned().into_inner();
+    if let Some(hostname) = config.hostname.as_ref() {
+        lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!("- The hostname will be {}.", hostname),
+        ));
+    }
+    if config.enable_sshd.unwrap_or(false) {
+        lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!("- The SSH server will be enabled."),
+        ));
+    }
+    match config.ssh_keys.as_ref() {
+        Some(keys) if !keys.is_empty() => lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            if config.ssh_disable_password_auth.unwrap_or(false) {
+                tr!(
+                    "- {} SSH authorized key(s) will be imported for {}, and SSH password login will be disabled.",
+                    keys.len(),
+                    user
+                )
+            } else {
+                tr!(
+                    "- {} SSH authorized key(s) will be imported for {}.",
+                    keys.len(),
+                    user
+                )
+            },
+        )),
+        _ => (),
+    }
+    match config.enable_services.as_ref() {
+        Some(units) if !units.is_empty() => lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!("- Services {} will be enabled.", units.join(", ")),
+        )),
+        _ => (),
+    }
+    match config.disable_services.as_ref() {
+        Some(units) if !units.is_empty() => lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!("- Services {} will be disabled.", units.join(", ")),
+        )),
+        _ => (),
+    }
+    match config.extra_packages.as_ref() {
+        Some(packages) if !packages.is_empty() => lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!(
+                "- Additional packages {} will be installed.",
+                packages.join(", ")
+            ),
+        )),
+        _ => (),
+    }
+    if let Some(cmdline) = planned_kernel_cmdline(config) {
+        lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!(
+                "- The kernel command line will be: (distribution defaults) {}.",
+                cmdline
+            ),
+        ));
+    }
+    lines.push(SummaryLine::new(
+        WizardStep::Hostname,
+        tr!(
+            "- The boot menu will be {}{}{}.",
+            if config.grub_hidden_menu.unwrap_or(false) {
+                tr!("hidden")
+            } else {
+                tr!("shown")
+            },
+            config
+                .grub_timeout
+                .map(|x| tr!(" with a {}-second timeout", x))
+                .unwrap_or_default(),
+            if config.grub_os_prober.unwrap_or(false) {
+                tr!(", and other operating systems will be added to it")
+            } else {
+                ""
+            },
+        ),
+    ));
+    lines.push(SummaryLine::new(
+        WizardStep::Hostname,
+        if boot_splash(config) {
+            tr!("- A boot splash will be shown on boot (if Plymouth is shipped).")
+        } else {
+            tr!("- Boot messages will be shown as plain text.")
+        },
+    ));
+    if hidpi(config) {
+        lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!(
+                "- The console will use the larger {} font, and the boot menu a lower resolution ({}).",
+                config
+                    .console_font
+                    .as_deref()
+                    .map(|x| x.as_str())
+                    .unwrap_or(install::HIDPI_CONSOLE_FONT),
+                config
+                    .grub_gfxmode
+                    .as_deref()
+                    .map(|x| x.as_str())
+                    .unwrap_or(install::HIDPI_GRUB_GFXMODE)
+            ),
+        ));
+    }
+    lines.push(SummaryLine::new(
+        WizardStep::Hostname,
+        match config.default_target.as_ref() {
+            Some(target) => tr!("- The system will boot into {} by default.", target),
+            None if config.disable_display_manager.unwrap_or(false) => {
+                tr!("- The system will boot into multi-user.target by default.").to_string()
+            }
+            None => tr!("- The system will boot into graphical.target by default if a display manager is shipped, otherwise multi-user.target.").to_string(),
+        },
+    ));
+    if config.disable_display_manager.unwrap_or(false) {
+        lines.push(SummaryLine::new(
+            WizardStep::Hostname,
+            tr!("- The display manager will be disabled."),
+        ));
+    }
 
-        // tzdata 的北京时间是 Asia/Shanghai
-        if timezone == "Asia/Beijing" {
-            timezone = "Asia/Shanghai".to_string();
+    lines.push(SummaryLine::new(
+        WizardStep::Timezone,
+        tr!(
+            "- AOSC OS will use the {} locale.",
+            config.locale.as_ref().unwrap()
+        ),
+    ));
+    match config.extra_locales.as_ref() {
+        Some(extra_locales) if !extra_locales.is_empty() => lines.push(SummaryLine::new(
+            WizardStep::Timezone,
+            tr!(
+                "- Additional locales {} will be generated.",
+                extra_locales.join(", ")
+            ),
+        )),
+        _ => (),
+    }
+    lines.push(SummaryLine::new(
+        WizardStep::Timezone,
+        tr!(
+            "- Your timezone will be set to {}, and will use {} as local time.",
+            config.timezone.as_ref().unwrap(),
+            config.tc.as_ref().unwrap()
+        ),
+    ));
+    if let Some(keymap) = config.keymap.as_ref() {
+        lines.push(SummaryLine::new(
+            WizardStep::Timezone,
+            tr!("- The keyboard layout will be {}.", keymap),
+        ));
+        // the passphrase may have been entered with another layout in the installer
+        if config.encrypt.unwrap_or(false) && keymap.as_str() != "us" {
+            lines.push(SummaryLine::new(
+                WizardStep::Timezone,
+                tr!("- The passphrase of the encrypted system partition will be typed with the {} layout on every boot.", keymap),
+            ));
+            let passphrase = config.encryption_passphrase.as_deref();
+            let (other_keys, _) = password::layout_mismatches(
+                passphrase.map(|x| x.as_str()).unwrap_or_default(),
+                keymap,
+            );
+            if !other_keys.is_empty() {
+                lines.push(SummaryLine::new(
+                    WizardStep::Timezone,
+                    tr!(
+                        "  Its characters {} are on other keys with this layout than with US-QWERTY.",
+                        other_keys.iter().collect::<String>()
+                    ),
+                ));
+            }
         }
+    }
+    lines.push(SummaryLine::new(
+        WizardStep::Timezone,
+        if config.enable_ntp.unwrap_or(true) {
+            let servers = match config.ntp_servers.as_ref() {
+                Some(servers) if !servers.is_empty() => {
+                    tr!(" using {}", servers.join(", "))
+                }
+                _ => String::new(),
+            };
+            // the live system comes with the same services as the system to be installed
+            match install::ntp_unit(Path::new("/")) {
+                Some(unit) => tr!(
+                    "- Network time synchronisation ({}) will be enabled{}.",
+                    unit,
+                    servers
+                ),
+                None => tr!(
+                    "- No network time synchronisation service is shipped, it will not be enabled."
+                )
+                .to_string(),
+            }
+        } else {
+            tr!("- Network time synchronisation will not be enabled.").to_string()
+        },
+    ));
 
-        let tc = tc.as_ref().to_owned().into_inner();
-        if locale.is_empty() || timezone.is_empty() || tc.is_empty() {
-            fill_in_all_the_fields!(s);
+    let swap_size = (*config.swap_size).unwrap_or(0.0);
+    let hibernation = config.is_hibernation.v.load(Ordering::SeqCst);
+    let mem = sysinfo::System::new_all().total_memory();
+    let swap_file = config.use_swap.v.load(Ordering::SeqCst) && swap_size != 0.0;
+    let swap = match (config.swap, swap_partition(config)) {
+        (Some(SwapKind::Zram), _) => {
+            tr!("- Compressed swap in the RAM (zram) will be enabled.").to_string()
         }
+        (Some(SwapKind::Partition), Some(partition)) => tr!(
+            "- {} will be used as swap.",
+            partition
+                .path
+                .as_ref()
+                .map(|x| x.display().to_string())
+                .unwrap_or_default()
+        ),
+        _ if swap_file => tr!(
+            "- A {}GiB swapfile will be created and enabled ({}).",
+            (swap_size / 1024.0 / 1024.0 / 1024.0).round(),
+            if swap_size as u64 == disks::recommend_swap_size(mem, hibernation) {
+                tr!("installer default")
+            } else {
+                tr!("custom size")
+            }
+        ),
+        _ => tr!("- No swap will be set up.").to_string(),
+    };
+    lines.push(SummaryLine::new(WizardStep::Swap, swap));
+    if hibernation && config.swap.unwrap_or(SwapKind::File).is_on_disk() {
+        lines.push(SummaryLine::new(
+            WizardStep::Swap,
+            tr!("- Hibernation will be set up to resume from the swap.").to_string(),
+        ));
+    }
 
-        let mut config = config.clone();
-        config.locale = Some(Arc::new(locale.to_string()));
-        config.timezone = Some(Arc::new(timezone));
-        config.tc = Some(Arc::new(tc));
-        select_swap(s, config);
-    })
-    .button("Back", move |s| {
-        s.pop_layer();
-        select_hostname(s, config_clone.clone());
-    })
-    .button("Exit", |s| s.quit());
-
-    siv.add_layer(timezone_dialog);
+    Ok(lines)
 }
 
-// Filter cities with names containing query string. You can implement your own logic here!
-fn search_fn<T: std::iter::IntoIterator<Item = String>>(items: T, query: &str) -> Vec<String> {
-    items
-        .into_iter()
-        .filter(|item| {
-            let item = item.to_lowercase();
-            let query = query.to_lowercase();
-            item.contains(&query)
-        })
-        .collect()
+/// The options on the summary itself
+fn read_summary_options(siv: &mut Cursive, config: &mut InstallConfig) {
+    config.copy_network_config = read_copy_network_config(siv);
+    config.nvidia_driver = read_nvidia_driver(siv);
+    config.use_mirror_repo = read_use_mirror_repo(siv);
+    config.board = read_board(siv);
 }
 
-fn search_fn_locales(items: Vec<(String, String, String)>, query: &str) -> Vec<String> {
-    items
-        .into_iter()
-        .filter(|item| {
-            let (lang, locale, lang_english) = item;
-            let lang = lang.to_lowercase();
-            let locale = locale.to_lowercase();
-            let lang_english = lang_english.to_lowercase();
-            let query = query.to_lowercase();
-
-            lang.contains(&query) || locale.contains(&query) || lang_english.contains(&query)
-        })
-        .map(|x| x.0.to_string())
-        .collect()
+fn show_summary(siv: &mut Cursive, mut config: InstallConfig) {
+    config.back_to_summary = false;
+    probe_preserved_install(siv, config, build_summary);
 }
 
-fn replace_item(
+/// Look for the installation whose /home is preserved in the background, what is kept
+/// of it is listed in the summary
+fn probe_preserved_install(
     siv: &mut Cursive,
-    item: &str,
-    timezone: Rc<RefCell<String>>,
-    status_text: Arc<TextContent>,
+    config: InstallConfig,
+    done: impl FnOnce(&mut Cursive, InstallConfig, Option<install::PreviousInstall>) + 'static,
 ) {
-    siv.pop_layer();
-    timezone.replace(item.to_string());
-    status_text.set_content(item);
+    let Some(partition) = config
+        .partition
+        .clone()
+        .filter(|_| config.preserve_home.unwrap_or(false))
+    else {
+        done(siv, config, None);
+        return;
+    };
+    run_in_background(
+        siv,
+        tr!("Looking for an existing installation on the partition ..."),
+        move || install::probe_previous_install(&partition).ok().flatten(),
+        move |s, previous_install| done(s, config, previous_install),
+    );
 }
 
-fn on_submit(
+fn build_summary(
     siv: &mut Cursive,
-    query: &str,
-    timezone_clone: Rc<RefCell<String>>,
-    status_text: Arc<TextContent>,
+    config: InstallConfig,
+    previous_install: Option<install::PreviousInstall>,
 ) {
-    let matches = siv.find_name::<SelectView>("matches").unwrap();
-    if matches.is_empty() {
-        // not all people live in big cities. If none of the cities in the list matches, use the value of the query.
-        replace_item(siv, query, timezone_clone, status_text);
-    } else {
-        // pressing "Enter" without moving the focus into the `matches` view will submit the first match result
-        let item = &*matches.selection().unwrap();
-        replace_item(siv, item, timezone_clone, status_text);
-    };
-}
-
-fn seatch_select_view_by_timezone(
-    list: Vec<String>,
-    status_text: Arc<TextContent>,
-    result: Rc<RefCell<String>>,
-    name: &str,
-) -> Dialog {
-    let list_clone = list.clone();
-    let locale_clone = result.clone();
-    let status_text_clone = status_text.clone();
-    let on_edit = move |siv: &mut Cursive, query: &str, _cursor: usize| {
-        let matches = search_fn(list_clone.clone(), query);
-        // Update the `matches` view with the filtered array of cities
-        siv.call_on_name("matches", |v: &mut SelectView| {
-            v.clear();
-            v.add_all_str(matches);
-        });
+    step_indicator::set_stage(Stage::Summary);
+    let lines = match summary_lines(&config, previous_install.as_ref()) {
+        Ok(lines) => lines,
+        Err(e) => {
+            show_error(siv, &e.to_string());
+            return;
+        }
     };
-
-    wrap_in_dialog(
-        LinearLayout::vertical()
-            .child(TextView::new(format!("Search {name}")))
-            .child(
-                EditView::new()
-                    // update results every time the query changes
-                    .on_edit(on_edit)
-                    // submit the focused (first) item of the matches
-                    .on_submit(move |s: &mut Cursive, c| {
-                        on_submit(s, c, locale_clone.clone(), status_text.clone())
-                    })
-                    .with_name("query"),
-            )
-            .child(DummyView {})
-            .child(
-                SelectView::new()
-                    .with_all_str(list)
-                    .on_submit(move |s: &mut Cursive, item| {
-                        replace_item(s, item, result.clone(), status_text_clone.clone())
-                    })
-                    .with_name("matches")
-                    .scrollable(),
+    write_answer_file(siv, &config);
+    // the partition does not exist yet when the whole disk is going to be wiped
+    let fstab = config
+        .partition
+        .as_ref()
+        .filter(|_| config.wipe_disk.is_none())
+        .and_then(|x| {
+            mount_plan(
+                x,
+                previous_install.as_ref(),
+                config.use_swap.v.load(Ordering::SeqCst),
+                swap_partition(&config),
+                assigned_mounts(&config),
             )
-            .fixed_height(10),
-        format!("Select Your {name}"),
-        None,
-    )
-}
-
-fn seatch_select_view_by_locales(
-    list: Vec<(String, String, String)>,
-    status_text: Arc<TextContent>,
-    result: Rc<RefCell<String>>,
-    name: &str,
-) -> Dialog {
-    let list_clone = list.clone();
-    let locale_clone = result.clone();
-    let status_text_clone = status_text.clone();
-    let on_edit = move |siv: &mut Cursive, query: &str, _cursor: usize| {
-        let matches = search_fn_locales(list_clone.clone(), query);
-        // Update the `matches` view with the filtered array of cities
-        siv.call_on_name("matches", |v: &mut SelectView| {
-            v.clear();
-            v.add_all_str(matches);
+            .and_then(|x| x.preview())
+            .ok()
         });
-    };
 
-    wrap_in_dialog(
-        LinearLayout::vertical()
-            .child(TextView::new(format!("Search {name}")))
+    let mut summary_view = LinearLayout::vertical();
+    if let Some(bulletin) = config
+        .bulletin
+        .as_ref()
+        .filter(|x| x.needs_acknowledgement())
+    {
+        summary_view = summary_view
+            .child(TextView::new(bulletin_text(bulletin)))
+            .child(DummyView {});
+    }
+    summary_view = summary_view.child(TextView::new(tr!(
+        "Installer will perform the following operations:"
+    )));
+    // every item has a button to change it, the indented lines after it belong to it
+    let mut groups: Vec<Vec<SummaryLine>> = vec![];
+    for line in lines {
+        match groups.last_mut() {
+            Some(group) if group[0].step == line.step && line.text.starts_with("  ") => {
+                group.push(line)
+            }
+            _ => groups.push(vec![line]),
+        }
+    }
+    for group in groups {
+        let mut text = StyledString::new();
+        for (i, line) in group.iter().enumerate() {
+            if i > 0 {
+                text.append_plain("\n");
+            }
+            if line.destructive {
+                // spelled out after the indentation of the line
+                let body = line.text.trim_start();
+                text.append_plain(&line.text[..line.text.len() - body.len()]);
+                text.append(emphasized(body, Emphasis::Danger));
+            } else {
+                text.append_plain(&line.text);
+            }
+        }
+        let step = group[0].step;
+        let config = config.clone();
+        summary_view = summary_view.child(
+            LinearLayout::horizontal()
+                .child(TextView::new(text).full_width())
+                .child(Button::new(tr!("Change"), move |s| {
+                    change_summary_item(s, step, config.clone())
+                })),
+        );
+    }
+    if let Some(fstab) = fstab {
+        summary_view = summary_view.child(DummyView {}).child(TextView::new(tr!(
+            "The following /etc/fstab will be generated (with the UUIDs of the partitions once formatted):\n\n{}",
+            fstab
+        )));
+    }
+    if let Some(backend) = install::detect_network_backend() {
+        summary_view = summary_view.child(DummyView {}).child(
+            ListView::new().child(
+                &tr!(
+                    "Copy network configuration ({}, including Wi-Fi passwords)",
+                    backend.unit()
+                ),
+                Checkbox::new()
+                    .with_checked(config.copy_network_config.unwrap_or(true))
+                    .with_name("copy_network_config"),
+            ),
+        );
+    }
+    let mirror = config.mirror.clone().unwrap();
+    let repo_summary = TextView::new(package_repo_summary(
+        &mirror,
+        config.use_mirror_repo.unwrap_or(true),
+    ));
+    summary_view = summary_view.child(DummyView {});
+    if mirror.package_repo().is_some() {
+        let repo_summary_text = repo_summary.get_shared_content();
+        summary_view = summary_view.child(
+            ListView::new().child(
+                tr!("Use this mirror for system updates"),
+                Checkbox::new()
+                    .with_checked(config.use_mirror_repo.unwrap_or(true))
+                    .on_change(move |_, checked| {
+                        repo_summary_text.set_content(package_repo_summary(&mirror, checked))
+                    })
+                    .with_name("use_mirror_repo"),
+            ),
+        );
+    }
+    summary_view = summary_view.child(repo_summary);
+    let boards = boards::list_boards().unwrap_or_default();
+    if let Some(device) = config
+        .partition
+        .as_ref()
+        .and_then(|x| x.parent_path.clone())
+        .filter(|_| !boards.is_empty())
+    {
+        let selected = config
+            .board
+            .as_ref()
+            .and_then(|x| boards.iter().position(|y| x.as_str() == y.name))
+            .map(|x| x + 1)
+            .unwrap_or(0);
+        let describe = move |board: &Option<boards::Board>| match board {
+            Some(board) => boards::describe_actions(board, &device).join("\n"),
+            None => tr!("No board-specific bootloader will be installed.").to_string(),
+        };
+        let board_actions = TextView::new(describe(
+            &selected.checked_sub(1).and_then(|x| boards.get(x)).cloned(),
+        ));
+        let board_actions_text = board_actions.get_shared_content();
+        summary_view = summary_view
+            .child(DummyView {})
             .child(
-                EditView::new()
-                    // update results every time the query changes
-                    .on_edit(on_edit)
-                    // submit the focused (first) item of the matches
-                    .on_submit(move |s: &mut Cursive, c| {
-                        on_submit(s, c, locale_clone.clone(), status_text.clone())
-                    })
-                    .with_name("query"),
+                ListView::new().child(
+                    tr!("Board"),
+                    dropdown(SelectView::new())
+                        .autojump()
+                        .with_all(
+                            std::iter::once((tr!("None").to_string(), None)).chain(
+                                boards.into_iter().map(|x| (x.description.clone(), Some(x))),
+                            ),
+                        )
+                        .selected(selected)
+                        .on_select(move |_, board| board_actions_text.set_content(describe(board)))
+                        .with_name("board"),
+                ),
             )
+            .child(board_actions);
+    }
+    let nvidia_gpus = install::find_nvidia_gpus();
+    if !nvidia_gpus.is_empty() {
+        info!("Detected NVIDIA GPU(s): {:?}", nvidia_gpus);
+        summary_view = summary_view
             .child(DummyView {})
+            .child(TextView::new(tr!(
+                "Installer has detected an NVIDIA graphics card. The proprietary driver may be installed for better performance, which requires network access and adds several minutes to the installation."
+            )))
             .child(
-                SelectView::new()
-                    .with_all_str(list.iter().map(|x| x.0.to_string()).collect::<Vec<_>>())
-                    .on_submit(move |s: &mut Cursive, item| {
-                        replace_item(s, item, result.clone(), status_text_clone.clone())
-                    })
-                    .with_name("matches")
-                    .scrollable(),
-            )
-            .fixed_height(10),
-        format!("Select Your {name}"),
-        None,
-    )
-}
-
-fn set_timezone(
-    zoneinfo: Vec<String>,
-    timezone_result: Rc<RefCell<String>>,
-    status_text: Arc<TextContent>,
-) -> Dialog {
-    seatch_select_view_by_timezone(zoneinfo, status_text, timezone_result, "timezone")
+                ListView::new().child(
+                    tr!("Install NVIDIA driver"),
+                    Checkbox::new()
+                        .with_checked(config.nvidia_driver.unwrap_or(false))
+                        .with_name("nvidia_driver"),
+                ),
+            );
+    }
+    let config_copy = config.clone();
+    siv.add_layer(with_help(
+        wrap_in_dialog(summary_view, tr!("Pre-Installation Confirmation"), None)
+            .button(tr!("Install"), move |s| {
+                let mut config = config_copy.clone();
+                read_summary_options(s, &mut config);
+                // with the options of the summary as confirmed
+                write_answer_file(s, &config);
+                if config.wipe_disk.is_some() {
+                    confirm_wipe_disk(s, config);
+                } else {
+                    s.pop_layer();
+                    start_install(s, config);
+                }
+            })
+            .button(tr!("Save Configuration"), move |s| {
+                let mut config = config.clone();
+                read_summary_options(s, &mut config);
+                export_config(s, config);
+            })
+            .button(tr!("Cancel"), |s| {
+                s.pop_layer();
+            }),
+        Screen::Summary,
+    ));
 }
 
-fn set_locales(
-    locales: Vec<(&str, &str, &str)>,
-    locale_result: Rc<RefCell<String>>,
-    status_text: Arc<TextContent>,
-) -> Dialog {
-    seatch_select_view_by_locales(
-        locales
-            .iter()
-            .map(|x| (x.0.to_string(), x.1.to_string(), x.2.to_string()))
-            .collect(),
-        status_text,
-        locale_result,
-        "locale",
-    )
+/// Write the plan to the answer file of `tui --export-config`, if it is given
+fn write_answer_file(siv: &mut Cursive, config: &InstallConfig) {
+    let Some(path) = EXPORT_CONFIG.get() else {
+        return;
+    };
+    let written = config_file::export(config, false).and_then(|x| Ok(fs::write(path, x)?));
+    match written {
+        Ok(()) => info!("Wrote the answer file {}", path.display()),
+        Err(e) => show_error(
+            siv,
+            &tr!(
+                "Installer failed to write the configuration file {}: {}",
+                path.display(),
+                e
+            ),
+        ),
+    }
 }
 
-fn select_swap(siv: &mut Cursive, config: InstallConfig) {
-    let config_clone = config.clone();
-    let config_clone_2 = config.clone();
-    let partition_size = config.partition.as_ref().unwrap().size;
-    let installed_size = config.variant.as_ref().unwrap().install_size;
-    siv.pop_layer();
-    let swap_size = Rc::new(RefCell::new(None));
-    let swap_size_copy = Rc::clone(&swap_size);
-    let is_hibernation = Arc::new(AtomicBool::new(false));
-    let is_hibernation_clone_2 = is_hibernation;
-    let use_swap = Arc::new(AtomicBool::new(false));
-    let use_swap_clone = use_swap.clone();
-
-    let view = ListView::new().child(
-        "Swapfile Size",
-        SelectView::new()
-            .popup()
-            .autojump()
-            .with_all_str(vec!["Automatic", "Custom", "Disabled"])
-            .with_name("select_swap_config"),
+/// Save the plan to a file of the user's choice (e.g., on the installation medium), to be
+/// loaded for installing more machines the same way
+fn export_config(siv: &mut Cursive, config: InstallConfig) {
+    let has_password = config.password.is_some()
+        || matches!(
+            config.root_password.as_deref(),
+            Some(RootPassword::Password(_))
+        );
+    let mut view = LinearLayout::vertical().child(
+        ListView::new().child(
+            tr!("Save to"),
+            EditView::new()
+                .content(SAVE_USER_CONFIG_FILE)
+                .with_name("export_config_path")
+                .min_width(40),
+        ),
+    );
+    if has_password {
+        view.add_child(DummyView {});
+        view.add_child(TextView::new(tr!("Passwords will be stored as hashes. If you store them as plain text instead, anyone with access to the file will be able to read them.")));
+        view.add_child(ListView::new().child(
+            tr!("Store passwords as plain text"),
+            Checkbox::new().with_name("export_config_plaintext"),
+        ));
+    }
+    siv.add_layer(
+        wrap_in_dialog(view, tr!("Save Configuration"), None)
+            .button(tr!("Save"), move |s| {
+                let path = s
+                    .call_on_name("export_config_path", |view: &mut EditView| {
+                        view.get_content()
+                    })
+                    .unwrap();
+                let plaintext = s
+                    .call_on_name("export_config_plaintext", |view: &mut Checkbox| {
+                        view.is_checked()
+                    })
+                    .unwrap_or(false);
+                let saved = config_file::export(&config, plaintext)
+                    .and_then(|x| Ok(fs::write(path.as_str(), x)?));
+                match saved {
+                    Ok(()) => {
+                        s.pop_layer();
+                        show_msg(
+                            s,
+                            &tr!(
+                                "Installer has successfully saved your installation configuration: {}.",
+                                path
+                            ),
+                        );
+                    }
+                    Err(e) => show_error(s, &e.to_string()),
+                }
+            })
+            .button(tr!("Cancel"), |s| {
+                s.pop_layer();
+            }),
     );
+}
 
-    let textview = TextView::new("Would you like to create a swapfile?\n");
+/// The whole disk is going to be wiped, make the user type its name before starting
+fn confirm_wipe_disk(siv: &mut Cursive, config: InstallConfig) {
+    let device = config.wipe_disk.clone().unwrap();
+    let name = device
+        .path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
     siv.add_layer(
         wrap_in_dialog(
-            LinearLayout::vertical().child(textview).child(view),
-            "AOSC OS Installer",
+            LinearLayout::vertical()
+                .child(TextView::new(tr!(
+                    "ALL DATA ON {} ({}, {}) WILL BE DESTROYED. This can not be undone.\n\nPlease type the name of the drive ({}) to confirm:",
+                    device.path.display(),
+                    device.model,
+                    human_size(device.size),
+                    name
+                )))
+                .child(EditView::new().with_name("confirm_wipe_disk")),
+            tr!("AOSC OS Installer"),
             None,
         )
-        .button("Continue", move |s| {
-            let selected = s
-                .find_name::<SelectView>("select_swap_config")
-                .expect("select_swap_config must have value")
-                .selected_id()
-                .expect("select_swap_config must have value");
-            match selected {
-                0 => auto_swap(
-                    installed_size,
-                    partition_size,
-                    s,
-                    swap_size_copy.clone(),
-                    use_swap_clone.clone(),
-                    config_clone.clone(),
-                ),
-                1 => custom_swap_size(
-                    installed_size,
-                    partition_size,
-                    s,
-                    swap_size_copy.clone(),
-                    config.clone(),
-                    is_hibernation_clone_2.clone(),
-                    use_swap.clone(),
-                ),
-                2 => disable_swap(config.clone(), s),
-                _ => unreachable!(),
+        .button(tr!("Erase and Install"), move |s| {
+            let input = s
+                .call_on_name("confirm_wipe_disk", |view: &mut EditView| {
+                    view.get_content()
+                })
+                .unwrap();
+            let input = input.trim();
+            if input != name && Path::new(input) != device.path {
+                show_msg(s, tr!("The name does not match the drive to be erased."));
+                return;
             }
-        })
-        .button("Back", move |s| {
+            // the confirmation and the summary
+            s.pop_layer();
             s.pop_layer();
-            select_timezone(s, config_clone_2.clone());
+            start_install(s, config.clone());
         })
-        .button("Exit", move |s| s.quit()),
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        }),
     );
 }
 
-fn auto_swap(
-    installed_size: u64,
-    partition_size: u64,
-    s: &mut Cursive,
-    swap_size: Rc<RefCell<Option<f64>>>,
-    use_swap: Arc<AtomicBool>,
-    config: InstallConfig,
-) {
-    let mut config = config;
-    let mem = sysinfo::System::new_all().total_memory();
-    let auto_size = disks::get_recommend_swap_size(mem);
-
-    match auto_size {
-        Ok(auto_size) => {
-            if installed_size + auto_size as u64 > partition_size - DEFAULT_EMPTY_SIZE {
-                show_msg(s, &format!("There is not enough available space in the system partition to create a swapfile! Default swapfile size: {} GiB", (auto_size / 1024.0 / 1024.0 / 1024.0).round()));
-                return;
-            }
+fn read_copy_network_config(siv: &mut Cursive) -> Option<bool> {
+    siv.call_on_name("copy_network_config", |view: &mut Checkbox| {
+        view.is_checked()
+    })
+    .or(Some(false))
+}
 
-            swap_size.replace(Some(auto_size));
-            use_swap.store(true, Ordering::SeqCst);
-        }
-        Err(e) => {
-            show_msg(s, &e.to_string());
-            return;
-        }
-    }
+fn read_nvidia_driver(siv: &mut Cursive) -> Option<bool> {
+    siv.call_on_name("nvidia_driver", |view: &mut Checkbox| view.is_checked())
+        .or(Some(false))
+}
 
-    let swap_size = swap_size.as_ref().to_owned().into_inner();
-    config.swap_size = Arc::new(swap_size);
-    config.use_swap = Arc::new(AtomicBoolWrapper {
-        v: AtomicBool::new(use_swap.load(Ordering::SeqCst)),
-    });
-    config.is_hibernation = Arc::new(AtomicBoolWrapper {
-        v: AtomicBool::new(true),
-    });
+fn read_use_mirror_repo(siv: &mut Cursive) -> Option<bool> {
+    // the checkbox is not shown if the package repository of the mirror is unknown
+    siv.call_on_name("use_mirror_repo", |view: &mut Checkbox| view.is_checked())
+        .or(Some(false))
+}
 
-    show_summary(s, config);
+fn read_board(siv: &mut Cursive) -> Option<Arc<String>> {
+    siv.call_on_name("board", |view: &mut SelectView<Option<boards::Board>>| {
+        view.selection()
+    })
+    .flatten()
+    .and_then(|x| x.as_ref().as_ref().map(|x| Arc::new(x.name.clone())))
 }
 
-fn custom_swap_size(
-    installed_size: u64,
-    partition_size: u64,
-    s: &mut Cursive,
-    swap_size: Rc<RefCell<Option<f64>>>,
-    config: InstallConfig,
-    is_hibernation_clone_2: Arc<AtomicBool>,
-    use_swap: Arc<AtomicBool>,
-) {
-    let swap_size_input = Rc::new(RefCell::new(String::new()));
-    let swap_size_input_clone = swap_size_input.clone();
+/// The running installation, as seen from the quit confirmation
+#[derive(Clone)]
+struct RunningInstall {
+    cancel: CancelToken,
+    phase: Arc<Mutex<InstallPhase>>,
+    /// Exit the installer once the cancelled installation has been cleaned up
+    quit: Arc<AtomicBool>,
+    /// The disk, partition or directory installed to
+    target: String,
+}
 
-    let swap_size_clone = swap_size.clone();
-    let use_swap_clone = use_swap.clone();
+fn install_target(config: &InstallConfig) -> String {
+    let target = match (&config.target_dir, &config.wipe_disk, &config.partition) {
+        (Some(dir), _, _) => Some(dir.clone()),
+        (None, Some(disk), _) => Some(disk.path.clone()),
+        (None, None, Some(partition)) => partition.path.clone(),
+        _ => None,
+    };
 
-    let is_hibernation_clone_3 = is_hibernation_clone_2.clone();
+    target.map(|x| x.display().to_string()).unwrap_or_default()
+}
 
-    s.add_layer(
-        wrap_in_dialog(
-            LinearLayout::vertical()
-                .child(TextView::new(
-                    "Please enter your desired swapfile size (GiB): ",
-                ))
-                .child(
-                    EditView::new()
-                        .on_edit_mut(move |_, c, _| {
-                            swap_size_input_clone.replace(c.to_owned());
-                        })
-                        .min_width(20)
-                        .with_name("size"),
-                ),
-            "Customize Swapfile Size",
-            None,
-        )
-        .button("OK", move |s| {
-            let mut config = config.clone();
-            let size = swap_size_input.as_ref().to_owned().into_inner();
-            let size = size.parse::<f64>();
-            if size.is_err() {
-                show_msg(s, "Invalid custom swapfile size!");
-                return;
-            }
+/// Quitting goes through `confirm_quit` while installing, and exits right away again
+/// once the installation is over
+fn set_quit_callbacks(siv: &mut Cursive, install: Option<RunningInstall>) {
+    siv.clear_global_callbacks(Event::Exit);
+    clear_global_shortcut(siv, Event::CtrlChar('c'));
+    match install {
+        Some(install) => {
+            let install_copy = install.clone();
+            siv.add_global_callback(Event::Exit, move |s| confirm_quit(s, &install_copy));
+            add_global_shortcut(siv, Event::CtrlChar('c'), "Quit", move |s| {
+                confirm_quit(s, &install)
+            });
+        }
+        None => {
+            siv.add_global_callback(Event::Exit, |s| s.quit());
+            add_global_shortcut(siv, Event::CtrlChar('c'), "Quit", |s| s.quit());
+        }
+    }
+}
 
-            let is_hibernation_clone = is_hibernation_clone_2.clone();
-            let size = size.unwrap() * 1024.0 * 1024.0 * 1024.0;
-            if installed_size + size as u64 > partition_size - DEFAULT_EMPTY_SIZE {
-                show_msg(s, &format!("There is not enough space available in the system partition to create a custom swapfile! Custom swapfile size: {} GiB",  (size / 1024.0 / 1024.0 / 1024.0).round()));
-                return;
-            }
+fn start_install(siv: &mut Cursive, config: InstallConfig) {
+    add_main_callback(siv);
 
-            let is_h = match disks::is_enable_hibernation(size) {
-                Ok(is_h) => {
-                    is_h
-                }
-                Err(e) => {
-                    show_msg(s, &e.to_string());
-                    false
-                }
-            };
+    save_user_config_to_file(config.clone(), LAST_USER_CONFIG_FILE).ok();
+    session::remove();
+    step_indicator::set_stage(Stage::Install);
+    siv.pop_layer();
+    let counter = Counter::new(0);
+    let counter_clone = counter.clone();
+    let overall = Counter::new(0);
+    let overall_clone = overall.clone();
+    let mut status_message = TextView::new("");
+    let status_text = Arc::new(status_message.get_shared_content());
+    let mut remaining_message = TextView::new("");
+    let remaining_text = Arc::new(remaining_message.get_shared_content());
+    let mut transfer_message = TextView::new("");
+    let transfer_text = Arc::new(transfer_message.get_shared_content());
+    let mut output_message = TextView::new("");
+    let output_text = Arc::new(output_message.get_shared_content());
+
+    let cancel = CancelToken::new();
+    let cancel_copy = cancel.clone();
+    let cancel_copy_2 = cancel.clone();
+    let running = RunningInstall {
+        cancel: cancel.clone(),
+        phase: Arc::new(Mutex::new(InstallPhase::Preparing)),
+        quit: Arc::new(AtomicBool::new(false)),
+        target: install_target(&config),
+    };
+    set_quit_callbacks(siv, Some(running.clone()));
+    let install_dialog = wrap_in_dialog(
+        LinearLayout::vertical()
+            .child(TextView::new(tr!(
+                "Please wait while installation takes place. This may take minutes or in extreme cases, hours, depending on your device's performance.\n\nGot some time to kill? Press <g> to start a game."
+            )))
+            .child(DummyView {})
+            .child(status_message)
+            .child(ProgressBar::new().max(100).with_value(counter))
+            .child(transfer_message)
+            .child(output_message)
+            .child(DummyView {})
+            .child(TextView::new(tr!("Overall progress")))
+            .child(ProgressBar::new().max(100).with_value(overall))
+            .child(remaining_message)
+            .child(DummyView {}),
+        tr!("Installing"),
+        None,
+    )
+    .button(tr!("Show Log"), toggle_log_pane)
+    .button(tr!("Help"), |s| show_help(s, Screen::Installing))
+    .button(tr!("Cancel"), move |s| confirm_cancel(s, &cancel_copy));
+    siv.add_layer(
+        Shortcuts::new(install_dialog)
+            .shortcut(Key::Esc, "Cancel", move |s| {
+                confirm_cancel(s, &cancel_copy_2)
+            })
+            .shortcut(Key::F1, "Help", |s| show_help(s, Screen::Installing))
+            .with_name("installing"),
+    );
+    let (tx, rx) = std::sync::mpsc::channel();
+    siv.set_autorefresh(true);
+    let cb_sink = siv.cb_sink().clone();
 
-            is_hibernation_clone.store(is_h, Ordering::SeqCst);
-            swap_size_clone.replace(Some(size));
-            use_swap_clone.store(true, Ordering::SeqCst);
+    let tempdir = tempfile::Builder::new()
+        .prefix(".dkmount")
+        .tempdir()
+        .expect("Installer failed to create temporary file for the download process.")
+        .into_path();
 
-            let swap_size = swap_size.as_ref().to_owned().into_inner();
-            config.swap_size = Arc::new(swap_size);
-            config.use_swap = Arc::new(AtomicBoolWrapper { v: AtomicBool::new(use_swap.load(Ordering::SeqCst) )});
-            config.is_hibernation = Arc::new(AtomicBoolWrapper { v: AtomicBool::new(is_hibernation_clone_3.load(Ordering::SeqCst) )});
+    let tempdir_copy = tempdir.clone();
 
-            show_summary(s, config);
-        })
-        .button("Cancel", move |s| s.cb_sink().send(Box::new(|s| {
-            s.pop_layer();
-        }))
-        .unwrap()),
-    );
-}
+    let rfc = install::get_dir_fd(Path::new("/"))
+        .expect("Installer failed to get root file descriptor.\n\nPlease restart your installation environment.");
+    let config_copy = config.clone();
+    let install_thread =
+        thread::spawn(move || begin_install(tx, config, tempdir_copy, cancel, None));
 
-fn disable_swap(config: InstallConfig, s: &mut Cursive) {
-    let mut config = config;
-    config.swap_size = Arc::new(None);
-    config.use_swap = Arc::new(AtomicBoolWrapper {
-        v: AtomicBool::new(false),
-    });
-    config.is_hibernation = Arc::new(AtomicBoolWrapper {
-        v: AtomicBool::new(false),
+    // the clock is dropped once the installation is over, which stops updating the time
+    let clock = Arc::new(Mutex::new(InstallClock::new()));
+    let weak_clock = Arc::downgrade(&clock);
+    let remaining_text_copy = remaining_text.clone();
+    thread::spawn(move || loop {
+        let Some(clock) = weak_clock.upgrade() else {
+            return;
+        };
+        remaining_text_copy.set_content(clock.lock().unwrap().summary());
+        drop(clock);
+        thread::sleep(Duration::from_secs(1));
     });
 
-    show_summary(s, config);
+    let mut plan = vec![];
+    let mut warnings = vec![];
+    let mut countdown = true;
+    let mut status = String::new();
+    let mut output = CommandTail::new();
+    thread::spawn(move || loop {
+        if let Ok(progress) = rx.recv() {
+            match progress {
+                super::InstallProgress::Pending(msg, pct) => {
+                    let phase = InstallPhase::of_step(&msg);
+                    let previous = std::mem::replace(&mut *running.phase.lock().unwrap(), phase);
+                    if previous == InstallPhase::Downloading && phase != previous {
+                        cb_sink
+                            .send(Box::new(|s| {
+                                notify(s, tr!("The system release has been downloaded."), |_| {})
+                            }))
+                            .unwrap();
+                    }
+                    counter_clone.set(pct);
+                    // the overall progress never goes back
+                    if let Some(x) = overall_percent(&plan, &msg, pct) {
+                        overall_clone.set(overall_clone.get().max(x));
+                    }
+                    // the output belongs to the command of the last status
+                    if msg != status {
+                        output.clear();
+                        output_text.set_content("");
+                    }
+                    status_text.set_content(format!("{} ...", tr!(&msg)));
+                    status = msg;
+                }
+                super::InstallProgress::Steps(steps) => {
+                    plan = steps;
+                }
+                super::InstallProgress::Remaining(remaining) => {
+                    let mut clock = clock.lock().unwrap();
+                    clock.set_remaining(remaining);
+                    remaining_text.set_content(clock.summary());
+                }
+                super::InstallProgress::Transfer(transfer) => {
+                    transfer_text.set_content(transfer.map(|x| x.summary).unwrap_or_default());
+                }
+                super::InstallProgress::Retry(msg, retry_tx) => {
+                    cb_sink
+                        .send(Box::new(move |s| {
+                            notify(
+                                s,
+                                tr!("A step has failed, and needs your decision."),
+                                move |s| show_retry(s, &msg, retry_tx),
+                            )
+                        }))
+                        .unwrap();
+                }
+                super::InstallProgress::Warning(warning) => {
+                    warnings.push(warning);
+                }
+                super::InstallProgress::Output(line) => {
+                    if let Some(text) = output.push(&line) {
+                        output_text.set_content(text);
+                    }
+                }
+                super::InstallProgress::PostInstall(root, boot_menu, journal, action_tx) => {
+                    *running.phase.lock().unwrap() = InstallPhase::Finished;
+                    overall_clone.set(100);
+                    let finished = FinishedInstall {
+                        root,
+                        boot_menu,
+                        journal,
+                        warnings: warnings.clone(),
+                        action_tx,
+                    };
+                    // not again after re-running a step
+                    let countdown = std::mem::replace(&mut countdown, false);
+                    cb_sink
+                        .send(Box::new(move |s| {
+                            notify(s, tr!("AOSC OS has been installed."), move |s| {
+                                s.pop_layer();
+                                show_finished(s, Rc::new(finished), countdown);
+                            })
+                        }))
+                        .unwrap();
+                }
+                super::InstallProgress::Finished(unmount) => {
+                    cb_sink
+                        .send(Box::new(move |s| leave_installer(s, unmount)))
+                        .unwrap();
+                    info!("Install finished");
+                    return;
+                }
+            }
+        } else {
+            let err = install_thread.join().unwrap().unwrap_err();
+            if cancel::is_cancelled(&err) {
+                info!("Installation cancelled");
+                umount_all(&tempdir, rfc);
+                if running.quit.load(Ordering::SeqCst) {
+                    info!("Quitting after the installation has been cleaned up");
+                    cb_sink.send(Box::new(|s| s.quit())).unwrap();
+                    return;
+                }
+                cb_sink
+                    .send(Box::new(move |s| show_cancelled(s, config_copy)))
+                    .unwrap();
+                return;
+            }
+            error!("{}", err);
+
+            umount_all(&tempdir, rfc);
+            let mut saved_to = save_log(None);
+            if saved_to.is_empty() {
+                saved_to.extend(LOG_FILE.get().cloned());
+            }
+            let plan = config_copy.redacted_json().ok();
+            cb_sink
+                .send(Box::new(move |s| {
+                    set_quit_callbacks(s, None);
+                    notify(s, tr!("The installation has failed."), move |s| {
+                        show_error_with_details(
+                            s,
+                            &tr!(
+                                "{}\n\nPress <F12> to see installer log.\n\nLog file is saved to {}",
+                                err,
+                                saved_to
+                                    .iter()
+                                    .map(|x| x.display().to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                            format!("{err:?}"),
+                            plan,
+                        );
+                    });
+                }))
+                .unwrap();
+            return;
+        }
+    });
 }
 
-fn is_use_last_config(siv: &mut Cursive, config: InstallConfig) {
-    siv.pop_layer();
-    let config_copy = config.clone();
-    siv.add_layer(
+/// Ask the user to confirm cancelling the installation, and whether to wipe the target if
+/// the system release is being unpacked to it already
+fn confirm_cancel(siv: &mut Cursive, cancel: &CancelToken) {
+    if cancel.is_cancelled() {
+        return;
+    }
+    if !cancel.is_cancellable() {
+        show_msg(siv, tr!(CANCEL_TOO_LATE_TEXT));
+        return;
+    }
+
+    let cancel_copy = cancel.clone();
+    let dialog = if cancel.is_target_modified() {
+        let cancel_copy_2 = cancel.clone();
         wrap_in_dialog(
-            TextView::new(
-                "Would you like to load your previous AOSC OS installation configuration?",
-            ),
-            "AOSC OS Installer",
+            TextView::new(tr!(CANCEL_UNPACKING_TEXT)),
+            tr!("AOSC OS Installer"),
             None,
         )
-        .button("Yes", move |s| show_summary(s, config_copy.clone()))
-        .button("No", move |s| {
-            fs::remove_file(LAST_USER_CONFIG_FILE).ok();
-            let new_config = InstallConfig {
-                partition: config.clone().partition,
-                ..Default::default()
-            };
-            select_variant(s, new_config);
+        .button(tr!("Wipe and Cancel"), move |s| {
+            request_cancel(s, &cancel_copy, true)
+        })
+        .button(tr!("Keep and Cancel"), move |s| {
+            request_cancel(s, &cancel_copy_2, false)
+        })
+        .button(tr!("Continue Installing"), |s| {
+            s.pop_layer();
         })
-        .button("Exit", |s| s.quit()),
-    );
-}
-
-fn show_summary(siv: &mut Cursive, config: InstallConfig) {
-    let mut path = String::new();
-    let mut fs = String::new();
-    let config_copy = config.clone();
-    let config_copy_2 = config.clone();
-    if let Some(partition) = config.partition {
-        if let Some(partition) = &partition.path {
-            path = partition.to_string_lossy().to_string();
-        }
-        if let Some(fs_type) = &partition.fs_type {
-            fs = fs_type.clone();
-        }
-    }
-    let swap_size = if let Some(swap_size) = *config.swap_size {
-        swap_size
-    } else {
-        0.0
-    };
-    let swap_str;
-    let mem = sysinfo::System::new_all().total_memory();
-    match disks::get_recommend_swap_size(mem) {
-        Ok(rs) => {
-            if swap_size == rs {
-                swap_str = "installer default"
-            } else if swap_size == 0.0 {
-                swap_str = "No swapfile will be created."
-            } else {
-                swap_str = "custom size"
-            };
-        }
-        Err(e) => {
-            show_error(siv, &e.to_string());
-            return;
-        }
-    };
-    let s = format!(
-        SUMMARY_TEXT!(),
-        path,
-        fs,
-        config.variant.unwrap().name,
-        config.mirror.unwrap().name,
-        config.user.unwrap(),
-        config.locale.unwrap(),
-        config.timezone.unwrap(),
-        config.tc.unwrap(),
-    );
-    let swap_s = if swap_size != 0.0 {
-        format!(
-            "- A {}GiB swapfile will be created and enabled ({}).",
-            (swap_size / 1024.0 / 1024.0 / 1024.0).round(),
-            swap_str
-        )
     } else {
-        format!("- {swap_str}")
-    };
-    siv.add_layer(
         wrap_in_dialog(
-            TextView::new(format!("{s}{swap_s}")),
-            "Pre-Installation Confirmation",
+            TextView::new(tr!(CANCEL_TEXT)),
+            tr!("AOSC OS Installer"),
             None,
         )
-        .button("Proceed", move |s| {
-            s.pop_layer();
-            start_install(s, config_copy.clone());
-        })
-        .button("Save Configuration", move |s| {
-            if let Err(e) = save_user_config_to_file(config_copy_2.clone(), SAVE_USER_CONFIG_FILE) {
-                show_error(s, &e.to_string())
+        .button(tr!("Yes"), move |s| {
+            if cancel_copy.is_target_modified() {
+                // the download has finished in the meantime
+                s.pop_layer();
+                confirm_cancel(s, &cancel_copy);
             } else {
-                show_msg(
-                    s,
-                    &format!(
-                        "Installer has successfully saved your installation configuration: {SAVE_USER_CONFIG_FILE}."
-                    ),
-                )
+                request_cancel(s, &cancel_copy, false);
             }
         })
-        .button("Cancel", |s| {
+        .button(tr!("No"), |s| {
             s.pop_layer();
-        }),
-    );
+        })
+    };
+    siv.add_layer(dialog);
 }
 
-fn start_install(siv: &mut Cursive, config: InstallConfig) {
-    siv.clear_global_callbacks(Event::Exit);
-    siv.clear_global_callbacks(Event::CtrlChar('c'));
-    add_main_callback(siv);
+/// Ask before quitting the installer during the installation, telling what is running and
+/// what is going to be cleaned up before exiting
+fn confirm_quit(siv: &mut Cursive, install: &RunningInstall) {
+    if install.cancel.is_cancelled() {
+        // being cleaned up already, exit once it is done
+        install.quit.store(true, Ordering::SeqCst);
+        return;
+    }
 
-    save_user_config_to_file(config.clone(), LAST_USER_CONFIG_FILE).ok();
-    siv.pop_layer();
-    let counter = Counter::new(0);
-    let counter_clone = counter.clone();
-    let mut status_message = TextView::new("");
-    let status_text = Arc::new(status_message.get_shared_content());
+    let phase = *install.phase.lock().unwrap();
+    let target = &install.target;
+    let (text, button) = match phase {
+        InstallPhase::Preparing => {
+            info!("User quit the installer before anything has been written");
+            install.cancel.cancel(false);
+            siv.quit();
+            return;
+        }
+        InstallPhase::Formatting => (
+            tr!("Installer is formatting the target ({}).\n\nIf you quit now, the target will be left without a usable system. Installer will stop before downloading the system release, unmount the target and then exit.", target),
+            tr!("Quit"),
+        ),
+        InstallPhase::Downloading => (
+            tr!("Installer is downloading the system release. The target ({}) has been formatted, but nothing has been installed to it yet.\n\nIf you quit now, the partial download will be kept, and resumed the next time you install. Installer will unmount the target and then exit.", target),
+            tr!("Quit and Keep Download"),
+        ),
+        InstallPhase::Unpacking => (
+            tr!("Installer is unpacking the system release to the target ({}). If you quit now, the target will contain an incomplete system that can not be booted.\n\nInstaller will wipe the incomplete system (/home is kept if you chose to preserve it), unmount the target and then exit.", target),
+            tr!("Wipe and Quit"),
+        ),
+        InstallPhase::Configuring => {
+            show_msg(siv, &tr!("Installer is configuring the installed system on {} (e.g., installing the bootloader), which can no longer be stopped safely: quitting now would leave the target unusable. Please wait for the installation to finish.", target));
+            return;
+        }
+        InstallPhase::Finished => {
+            show_msg(siv, tr!("The installed system is still mounted. Please select \"Stay in Live Session\" to unmount it and leave the installer."));
+            return;
+        }
+    };
 
-    let (user_interrup_tx, user_interrup_rx) = std::sync::mpsc::channel();
-    siv.add_layer(wrap_in_dialog(
-        LinearLayout::vertical()
-            .child(TextView::new(
-                "Please wait while installation takes place. This may take minutes or in extreme cases, hours, depending on your device's performance.\n\nGot some time to kill? Press <g> to start a game.",
-            ))
-            .child(DummyView {})
-            .child(ProgressBar::new().max(100).with_value(counter))
-            .child(status_message)
-            .child(DummyView {}),
-        "Installing",
-        None,
-    ).button("Cancel", move |s| {
-        let user_interrup_tx = user_interrup_tx.clone();
-        s.add_layer(wrap_in_dialog(TextView::new(
-            "Installer has not yet completed the installation process. Are you sure that you would like to abort the installation?"), "AOSC OS Installer", None)
-            .button("Yes", move |_| {
-                user_interrup_tx.send(true).unwrap();
+    let install = install.clone();
+    siv.add_layer(
+        wrap_in_dialog(TextView::new(text), tr!("Quit Installer"), None)
+            .button(button, move |s| {
+                s.pop_layer();
+                if phase != InstallPhase::Unpacking && install.cancel.is_target_modified() {
+                    // the unpacking has started in the meantime
+                    confirm_quit(s, &install);
+                    return;
+                }
+                if !install.cancel.is_cancellable() {
+                    show_msg(s, tr!(CANCEL_TOO_LATE_TEXT));
+                    return;
+                }
+                info!("User requested to quit the installer ({:?})", phase);
+                install.quit.store(true, Ordering::SeqCst);
+                install.cancel.cancel(phase == InstallPhase::Unpacking);
+                show_blocking_message(s, tr!("Cleaning up before quitting, please wait ..."));
             })
-            .button("No", |s| s.cb_sink().send(Box::new(|s| {
+            .button(tr!("Continue Installing"), |s| {
                 s.pop_layer();
-            }))
-            .unwrap()));
-    }));
-    let (tx, rx) = std::sync::mpsc::channel();
-    siv.set_autorefresh(true);
-    let cb_sink = siv.cb_sink().clone();
-    let cb_sink_clone = siv.cb_sink().clone();
+            }),
+    );
+}
 
-    let tempdir = tempfile::Builder::new()
-        .prefix(".dkmount")
-        .tempdir()
-        .expect("Installer failed to create temporary file for the download process.")
-        .into_path();
+fn request_cancel(siv: &mut Cursive, cancel: &CancelToken, wipe: bool) {
+    siv.pop_layer();
+    if !cancel.is_cancellable() {
+        show_msg(siv, tr!(CANCEL_TOO_LATE_TEXT));
+        return;
+    }
 
-    let tempdir_copy = tempdir.clone();
-    let tempdir_copy_2 = tempdir.clone();
+    info!("User requested to cancel the installation (wipe: {})", wipe);
+    cancel.cancel(wipe);
+    show_blocking_message(siv, tr!(CANCELLING_TEXT));
+}
 
-    let root_fd = install::get_dir_fd(Path::new("/"))
-        .expect("Installer failed to get root file descriptor.\n\nPlease restart your installation environment.");
-    let rfc = root_fd.try_clone().unwrap();
-    let install_thread = thread::spawn(move || begin_install(tx, config, tempdir_copy));
-    thread::spawn(move || {
-        let user_exit = user_interrup_rx.recv();
-        if let Ok(user_exit) = user_exit {
-            if user_exit {
-                info!("User request to exit the installer");
-                umount_all(&tempdir_copy_2, root_fd);
-                cb_sink_clone.send(Box::new(|s| s.quit())).unwrap();
-            }
-        }
-    });
-    thread::spawn(move || loop {
-        if let Ok(progress) = rx.recv() {
-            match progress {
-                super::InstallProgress::Pending(msg, pct) => {
-                    counter_clone.set(pct);
-                    status_text.set_content(format!("{msg} ..."));
-                }
-                super::InstallProgress::Finished => {
-                    cb_sink.send(Box::new(show_finished)).unwrap();
-                    info!("Install finished");
-                    return;
-                }
-            }
-        } else {
-            let err = install_thread.join().unwrap().unwrap_err();
-            error!("{}", err);
+/// Return to the summary once the cancelled installation has been cleaned up
+fn show_cancelled(siv: &mut Cursive, config: InstallConfig) {
+    while siv.find_name::<Shortcuts<Dialog>>("installing").is_some() {
+        siv.pop_layer();
+    }
+    siv.set_autorefresh(false);
+    clear_callback(siv);
+    set_quit_callbacks(siv, None);
 
-            umount_all(&tempdir, rfc);
-            cb_sink
-                .send(Box::new(move |s| {
-                    show_error(
-                        s,
-                        &format!(
-                            "{}\n\nPress <~> to see installer log.\n\nLog file is save to {}",
-                            err,
-                            LOG_FILE.get().unwrap().display()
-                        ),
-                    );
-                }))
-                .unwrap();
-            return;
-        }
+    probe_preserved_install(siv, config, |s, config, previous_install| {
+        build_summary(s, config, previous_install);
+        show_msg(s, tr!(CANCELLED_TEXT));
     });
 }
 
+fn show_retry(siv: &mut Cursive, msg: &str, retry_tx: std::sync::mpsc::Sender<bool>) {
+    let skip_tx = retry_tx.clone();
+    let details = msg.to_string();
+    siv.add_layer(
+        wrap_in_dialog(
+            TextView::new(tr!(
+                "{}\n\nPlease check your network connection. You may retry, or skip this step and continue with the installation.",
+                tr!(msg)
+            )),
+            tr!("AOSC OS Installer"),
+            None,
+        )
+        .button(tr!("Retry"), move |s| {
+            retry_tx.send(true).ok();
+            s.pop_layer();
+        })
+        .button(tr!("Skip"), move |s| {
+            skip_tx.send(false).ok();
+            s.pop_layer();
+        })
+        .button(tr!("Save Details"), move |s| {
+            save_error_details(s, &details, None)
+        }),
+    );
+}
+
 fn save_user_config_to_file(config: InstallConfig, path: &str) -> Result<()> {
     let mut config_copy = config;
     config_copy.partition = None;
+    config_copy.preserve_home = None;
+    config_copy.restore_users = None;
+    config_copy.wipe_disk = None;
+    config_copy.mounts = None;
     let file_str = serde_json::to_string(&config_copy)?;
     fs::File::create(LAST_USER_CONFIG_FILE)?;
     fs::write(path, file_str)?;
@@ -1764,59 +5376,502 @@ fn read_user_config_on_file() -> Result<InstallConfig> {
     Ok(serde_json::from_slice(&buffer)?)
 }
 
-fn show_finished(siv: &mut Cursive) {
+/// What to do once the installed system has been unmounted
+enum FinishAction {
+    /// Reboot, powering off the installer medium at the path first if given
+    Reboot(Option<PathBuf>),
+    Stay,
+}
+
+fn countdown_text(seconds: u64) -> String {
+    tr!(
+        "Rebooting in {} seconds, press any key to stop the countdown.",
+        seconds
+    )
+}
+
+/// The finish screen, shown while the installed system is still mounted so that a shell may
+/// be opened in it. Rebooting happens by itself after a countdown, unless `countdown` is false
+/// The installed system still mounted for the finish screen, kept while a shell is open in it
+struct FinishedInstall {
+    root: PathBuf,
+    boot_menu: BootMenu,
+    journal: StepJournal,
+    warnings: Vec<String>,
+    action_tx: Sender<PostInstallAction>,
+}
+
+/// A line per step with how it went, e.g. "✓ Step 1 of 8: Formatting partitions"
+fn journal_text(journal: &StepJournal) -> StyledString {
+    let mut text = StyledString::new();
+    for (step, outcome) in journal.steps() {
+        match outcome {
+            StepOutcome::Succeeded => {
+                text.append_styled(format!("✓ {}\n", tr!(step)), emphasis(Emphasis::Good))
+            }
+            StepOutcome::Failed(e) => text.append_styled(
+                tr!("✗ {} (failed: {})\n", tr!(step), e),
+                emphasis(Emphasis::Danger),
+            ),
+            StepOutcome::NotRun => text.append_plain(tr!("- {} (not run)\n", tr!(step))),
+        }
+    }
+
+    text
+}
+
+fn show_finished(siv: &mut Cursive, finished: Rc<FinishedInstall>, countdown: bool) {
+    let boot_menu = &finished.boot_menu;
+    let complete = !finished.journal.is_incomplete();
+    // the installed system may not boot, so it is not rebooted into by itself
+    let countdown = countdown && complete;
+    let mut text = tr!(if complete {
+        FINISHED_TEXT
+    } else {
+        INCOMPLETE_TEXT
+    })
+    .to_string();
+    if !boot_menu.foreign_os.is_empty() {
+        text = tr!(
+            "{}\n\nThe following operating systems were detected and added to the boot menu:\n\n{}",
+            text,
+            boot_menu
+                .foreign_os
+                .iter()
+                .map(|x| format!("- {x}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+    for warning in boot_menu.warning.iter().chain(finished.warnings.iter()) {
+        text = tr!("{}\n\nWarning: {}", text, warning);
+    }
+    let medium = disks::ejectable_live_medium();
+    let stopped = Arc::new(AtomicBool::new(!countdown));
+    let countdown_content = TextContent::new(if countdown {
+        countdown_text(REBOOT_COUNTDOWN)
+    } else {
+        String::new()
+    });
+
+    let mut finished_view = LinearLayout::vertical()
+        .child(TextView::new(text))
+        .child(DummyView {})
+        .child(TextView::new(journal_text(&finished.journal)));
+    let mut tools = LinearLayout::horizontal();
+    if finished.journal.contains(STEP6) {
+        let finished = finished.clone();
+        tools.add_child(Button::new(tr!("Re-run Bootloader Step"), move |s| {
+            rerun_step(s, &finished, PostInstallAction::RetryBootloader)
+        }));
+        tools.add_child(DummyView {}.fixed_width(2));
+    }
+    let finished_copy = finished.clone();
+    tools.add_child(Button::new(tr!("Re-run Configuration Steps"), move |s| {
+        rerun_step(s, &finished_copy, PostInstallAction::RerunConfiguration)
+    }));
+    finished_view = finished_view
+        .child(tools)
+        .child(DummyView {})
+        .child(TextView::new_with_content(countdown_content.clone()));
+    if let Some(medium) = medium.as_ref() {
+        finished_view = finished_view
+            .child(DummyView {})
+            .child(ListView::new().child(
+                &tr!(
+                    "Power off the installer medium ({}) before rebooting",
+                    medium.display()
+                ),
+                Checkbox::new().with_checked(true).with_name("eject_medium"),
+            ));
+    }
+
+    if countdown {
+        let cb_sink = siv.cb_sink().clone();
+        let stopped = stopped.clone();
+        let action_tx = finished.action_tx.clone();
+        let medium = medium.clone();
+        let countdown_content = countdown_content.clone();
+        thread::spawn(move || {
+            for left in (0..REBOOT_COUNTDOWN).rev() {
+                thread::sleep(Duration::from_secs(1));
+                if stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+                countdown_content.set_content(countdown_text(left));
+            }
+            cb_sink
+                .send(Box::new(move |s| {
+                    // a key may have been pressed in the meantime
+                    if !stopped.load(Ordering::SeqCst) {
+                        finish_install(s, &stopped, &action_tx, medium.as_deref(), true);
+                    }
+                }))
+                .ok();
+        });
+    }
+
+    let stopped_copy = stopped.clone();
+    let stopped_copy_2 = stopped.clone();
+    let stopped_copy_3 = stopped.clone();
+    let finished_copy = finished.clone();
+    let finished_copy_2 = finished.clone();
+    let title = if complete {
+        tr!("Installation Complete")
+    } else {
+        tr!("Installation Not Complete")
+    };
+    let dialog = wrap_in_dialog(finished_view, title, None)
+        .button(tr!("Reboot Now"), move |s| {
+            finish_install(
+                s,
+                &stopped_copy,
+                &finished_copy.action_tx,
+                medium.as_deref(),
+                true,
+            )
+        })
+        .button(tr!("Stay in Live Session"), move |s| {
+            finish_install(s, &stopped_copy_2, &finished_copy_2.action_tx, None, false)
+        })
+        .button(tr!("View Log"), toggle_log_pane)
+        .button(tr!("Open Shell"), move |s| {
+            stopped_copy_3.store(true, Ordering::SeqCst);
+            s.pop_layer();
+            s.set_user_data(finished.clone());
+            let dump = s.dump();
+            s.quit();
+            s.set_user_data(ShellRequest::Guest(dump, finished.root.clone()));
+        });
+    // the key is still handled by the dialog
+    siv.add_layer(OnEventView::new(dialog).on_pre_event_inner(
+        EventTrigger::from_fn(is_key_press),
+        move |_, _| {
+            if !stopped.swap(true, Ordering::SeqCst) {
+                countdown_content.set_content(tr!("The countdown has been stopped."));
+            }
+            None
+        },
+    ));
+}
+
+/// Have the installer run a step again, the finish screen is shown again once it is done
+fn rerun_step(siv: &mut Cursive, finished: &FinishedInstall, action: PostInstallAction) {
     siv.pop_layer();
-    siv.add_layer(
-        wrap_in_dialog(TextView::new(FINISHED_TEXT), "Installation Complete", None)
-            .button("Reboot", |s| {
-                install::sync_and_reboot().ok();
-                s.quit();
-            })
-            .button("Exit to LiveKit", |s| s.quit()),
+    show_blocking_message(
+        siv,
+        match action {
+            PostInstallAction::RetryBootloader => tr!("Installing the bootloader again ..."),
+            _ => tr!("Configuring the installed system again ..."),
+        },
     );
+    finished.action_tx.send(action).ok();
 }
 
-pub fn tui_main() {
-    let mut siv = cursive::default();
+/// Let the installer unmount the installed system, what to do afterwards is left for
+/// `leave_installer`
+fn finish_install(
+    siv: &mut Cursive,
+    stopped: &AtomicBool,
+    action_tx: &Sender<PostInstallAction>,
+    medium: Option<&Path>,
+    reboot: bool,
+) {
+    stopped.store(true, Ordering::SeqCst);
+    let action = if reboot {
+        let eject = siv
+            .call_on_name("eject_medium", |view: &mut Checkbox| view.is_checked())
+            .unwrap_or(false);
+        FinishAction::Reboot(medium.filter(|_| eject).map(|x| x.to_path_buf()))
+    } else {
+        FinishAction::Stay
+    };
+    siv.pop_layer();
+    siv.set_user_data(action);
+    show_blocking_message(siv, tr!("Unmounting the installed system ..."));
+    action_tx.send(PostInstallAction::Finish).ok();
+}
 
-    siv.add_global_callback('~', cursive::Cursive::toggle_debug_console);
+/// Reboot or return to the live session as chosen on the finish screen, now that the
+/// installed system has been unmounted
+fn leave_installer(siv: &mut Cursive, unmount: UnmountStatus) {
+    siv.pop_layer();
+    match siv.take_user_data::<FinishAction>() {
+        Some(FinishAction::Reboot(eject)) => {
+            if let Err(e) = install::sync_and_reboot(eject.as_deref()) {
+                error!("Failed to reboot: {}", e);
+            }
+            siv.quit();
+        }
+        _ if unmount == UnmountStatus::Lazy => {
+            siv.add_layer(
+                wrap_in_dialog(
+                    TextView::new(tr!(LAZY_UNMOUNT_INFO)),
+                    tr!("Installation Complete"),
+                    None,
+                )
+                .button(tr!("OK"), |s| s.quit()),
+            );
+        }
+        _ => siv.quit(),
+    }
+}
+
+/// Start the wizard with `config` rather than with nothing, it is kept for starting over
+pub(super) fn preload(config: InstallConfig) {
+    *PRELOADED_CONFIG.lock().unwrap() = Some(config);
+}
+
+/// Show `msg` once the TUI has started
+pub(super) fn show_at_start(msg: String) {
+    *STARTUP_MESSAGE.lock().unwrap() = Some(msg);
+}
+
+fn start_wizard(siv: &mut Cursive) {
+    let preloaded = PRELOADED_CONFIG.lock().unwrap().clone();
+    if let Some(config) = preloaded {
+        select_variant(siv, config);
+    } else if let Ok(config) = read_user_config_on_file() {
+        if let Err(e) = check_config_file_passwords(&config) {
+            select_variant(siv, InstallConfig::default());
+            show_msg(siv, &e.to_string());
+        } else {
+            select_disk(siv, config);
+        }
+    } else {
+        let config = InstallConfig::default();
+        select_variant(siv, config);
+    }
+}
+
+/// The installer has been left in the middle of the wizard, offer to continue from there
+fn continue_session(siv: &mut Cursive, mut session: session::Session) {
+    run_in_background(
+        siv,
+        tr!("Checking the disks ..."),
+        move || {
+            let devices = make_device_list(disks::list_devices());
+            let disk_valid =
+                session.revalidate(&devices, |x| disks::list_partitions(Some(x.to_path_buf())));
+            (session, disk_valid)
+        },
+        |s, (session, disk_valid)| offer_session(s, session, disk_valid),
+    );
+}
 
+fn offer_session(siv: &mut Cursive, session: session::Session, disk_valid: bool) {
+    let mut text = tr!(
+        "Installer was left at the {} step last time. Would you like to continue where you left off?",
+        tr!(session.step.name())
+    );
+    if !disk_valid {
+        text.push_str("\n\n");
+        text.push_str(tr!(
+            "The selected disk or partition is no longer available, please select it again."
+        ));
+    }
+    if !session.config.oobe.unwrap_or(false) {
+        text.push_str("\n\n");
+        text.push_str(tr!(
+            "Passwords are not saved, you will be asked for them again."
+        ));
+    }
+    let step = session.step;
+    let config = session.config;
     siv.add_layer(
-        Dialog::around(TextView::new(WELCOME_TEXT))
-            .title("Welcome")
-            .button("Let's Go", |s| {
-                if let Ok(config) = read_user_config_on_file() {
-                    select_disk(s, config);
-                } else {
-                    let config = InstallConfig::default();
-                    select_variant(s, config);
-                }
+        wrap_in_dialog(TextView::new(text), tr!("AOSC OS Installer"), None)
+            .button(tr!("Continue where you left off"), move |s| {
+                s.pop_layer();
+                goto_step(s, step, config.clone());
             })
-            .padding_lrtb(2, 2, 1, 1)
-            .max_width(80),
+            .button(tr!("Start Over"), |s| {
+                session::remove();
+                s.pop_layer();
+                start_wizard(s);
+            }),
+    );
+}
+
+fn show_welcome(siv: &mut Cursive) {
+    siv.add_layer(
+        Dialog::around(dialog_content(
+            TextView::new(tr!(WELCOME_TEXT)).scrollable(),
+            80,
+        ))
+        .title(tr!("Welcome"))
+        .button(tr!("Let's Go"), |s| match session::load() {
+            Some(session) if session.step > WizardStep::Variant => continue_session(s, session),
+            _ => start_wizard(s),
+        })
+        .button(tr!("Language"), select_language)
+        .padding_lrtb(2, 2, 1, 1),
+    );
+}
+
+/// Switch the language of the interface, the welcome screen is shown again in it
+fn select_language(siv: &mut Cursive) {
+    let current = i18n::language();
+    siv.add_layer(
+        Dialog::around(dialog_content(
+            SelectView::new()
+                .with_all(i18n::Language::ALL.iter().map(|x| (x.name(), *x)))
+                .selected(
+                    i18n::Language::ALL
+                        .iter()
+                        .position(|x| *x == current)
+                        .unwrap_or(0),
+                )
+                .on_submit(|s, language| {
+                    i18n::set_language(*language);
+                    // the language selection and the welcome screen
+                    s.pop_layer();
+                    s.pop_layer();
+                    show_welcome(s);
+                }),
+            80,
+        ))
+        .title(tr!("Language"))
+        .button(tr!("Cancel"), |s| {
+            s.pop_layer();
+        })
+        .padding_lrtb(2, 2, 1, 1),
+    );
+}
+
+/// The size of the terminal in columns and rows
+fn terminal_size() -> Option<(usize, usize)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+
+    (result == 0 && size.ws_col > 0).then_some((size.ws_col as usize, size.ws_row as usize))
+}
+
+/// Cover the screen with a notice while the terminal is too small for the dialogs (their
+/// buttons may be out of reach), and take it away once the terminal is large enough
+fn check_terminal_size(siv: &mut Cursive) {
+    let Some((columns, rows)) = terminal_size() else {
+        return;
+    };
+    let position = siv.screen_mut().find_layer_from_name(TERMINAL_TOO_SMALL);
+    if columns >= MIN_TERMINAL_SIZE.0 && rows >= MIN_TERMINAL_SIZE.1 {
+        if let Some(position) = position {
+            siv.screen_mut().remove_layer(position);
+        }
+        return;
+    }
+
+    let text = tr!(
+        "The terminal is too small (have {}×{}, need {}×{}).\n\nPlease enlarge the window, the installer will continue once it is large enough.",
+        columns,
+        rows,
+        MIN_TERMINAL_SIZE.0,
+        MIN_TERMINAL_SIZE.1
     );
+    match position {
+        Some(position) => {
+            // dialogs may have been added over it in the meantime
+            siv.screen_mut().move_to_front(position);
+            siv.call_on_name(TERMINAL_TOO_SMALL, |view: &mut TextView| {
+                view.set_content(text)
+            });
+        }
+        None => siv.add_fullscreen_layer(Layer::new(
+            TextView::new(text)
+                .with_name(TERMINAL_TOO_SMALL)
+                .scrollable()
+                .full_screen(),
+        )),
+    }
+}
+
+/// Redraw everything when the terminal is resized (leftovers of the old layout would stay
+/// on the screen otherwise, e.g., during the installation), and check whether it still fits
+fn watch_terminal_size(siv: &mut Cursive) {
+    siv.set_on_pre_event(Event::WindowResize, |s| {
+        s.clear();
+        check_terminal_size(s);
+    });
+    check_terminal_size(siv);
+}
+
+pub fn tui_main(theme_name: Option<ThemeName>, accessible: bool, export_config: Option<PathBuf>) {
+    UI_THREAD.set(thread::current().id()).ok();
+    if let Some(path) = export_config {
+        EXPORT_CONFIG.set(path).ok();
+    }
+    let mut siv = cursive::default();
+
+    if accessible::detect(accessible) {
+        accessible::enable();
+    }
+    // the plain theme draws no box-drawing characters, and its emphasis is not in colours
+    let theme_name = match theme_name {
+        _ if accessible::is_enabled() => ThemeName::Plain,
+        Some(theme_name) => theme_name,
+        None => ThemeName::detect(),
+    };
+    theme::apply(&mut siv, theme_name);
+    add_step_indicator(&mut siv);
+    siv.add_global_callback('~', cursive::Cursive::toggle_debug_console);
+    add_global_shortcut(&mut siv, Key::F12, "Log", toggle_log_pane);
+    add_global_shortcut(&mut siv, Event::CtrlChar('t'), "Theme", theme::cycle);
+    show_welcome(&mut siv);
+    if let Some(msg) = STARTUP_MESSAGE.lock().unwrap().take() {
+        show_msg(&mut siv, &msg);
+    }
+    watch_terminal_size(&mut siv);
+    connect_footer(&mut siv);
 
     siv.run();
 
-    loop {
-        let dump = siv.take_user_data::<cursive::Dump>();
-        if let Some(dump) = dump {
-            drop(siv);
-            println!("You may use tools like cfdisk or gdisk to modify your partitions.\nExit the shell (command prompt) to return to the installer.");
-            std::process::Command::new("bash")
-                .spawn()
-                .unwrap()
-                .wait()
-                .unwrap();
-            siv = cursive::default();
-            siv.restore(dump);
-            let config = siv.take_user_data::<InstallConfig>();
-            if let Some(config) = config {
-                select_disk(&mut siv, config);
-                siv.run();
+    while let Some(request) = siv.take_user_data::<ShellRequest>() {
+        drop(siv);
+        match request {
+            ShellRequest::Live(dump) => {
+                println!("{}", tr!("You may use tools like cfdisk or gdisk to modify your partitions.\nExit the shell (command prompt) to return to the installer."));
+                std::process::Command::new("bash")
+                    .spawn()
+                    .unwrap()
+                    .wait()
+                    .unwrap();
+                siv = cursive::default();
+                siv.restore(dump);
+                theme::apply(&mut siv, theme::current());
+                watch_terminal_size(&mut siv);
+                connect_footer(&mut siv);
+                let config = siv.take_user_data::<InstallConfig>();
+                if let Some(config) = config {
+                    select_disk(&mut siv, config);
+                }
+            }
+            ShellRequest::Partitioner(dump, program, device_path) => {
+                let status = disks::run_partitioner(program, &device_path);
+                siv = cursive::default();
+                siv.restore(dump);
+                theme::apply(&mut siv, theme::current());
+                watch_terminal_size(&mut siv);
+                connect_footer(&mut siv);
+                let config = siv.take_user_data::<InstallConfig>();
+                if let Some(config) = config {
+                    after_partitioner(&mut siv, config, program, &device_path, status);
+                }
+            }
+            ShellRequest::Guest(dump, root) => {
+                println!("{}", tr!("You are now in the installed system.\nExit the shell (command prompt) to return to the installer."));
+                if let Err(e) = install::open_guest_shell(&root) {
+                    error!("Failed to open a shell in the installed system: {}", e);
+                }
+                siv = cursive::default();
+                siv.restore(dump);
+                theme::apply(&mut siv, theme::current());
+                watch_terminal_size(&mut siv);
+                connect_footer(&mut siv);
+                siv.set_autorefresh(true);
+                if let Some(finished) = siv.take_user_data::<Rc<FinishedInstall>>() {
+                    show_finished(&mut siv, finished, false);
+                }
             }
-        } else {
-            break;
         }
+        siv.run();
     }
 }
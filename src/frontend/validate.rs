@@ -0,0 +1,238 @@
+use std::{fmt::Display, path::Path};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use super::config_file::ConfigFile;
+
+/// Bumped whenever a field of `validate-config --json` is renamed, removed or changes its
+/// meaning, adding fields does not bump it
+const SCHEMA_VERSION: u32 = 1;
+
+/// What `validate-config` found wrong in a configuration file
+#[derive(Debug, Serialize)]
+pub(super) struct Diagnostic {
+    /// The key of the answer file it is about, e.g., `region.timezone`
+    key: Option<String>,
+    /// Where the key or the error is in the file, both start at 1
+    line: Option<usize>,
+    column: Option<usize>,
+    message: String,
+}
+
+/// The result of `validate-config` as printed with --json
+#[derive(Debug, Serialize)]
+pub(super) struct Report<'a> {
+    version: u32,
+    path: &'a Path,
+    valid: bool,
+    diagnostics: &'a [Diagnostic],
+}
+
+impl Diagnostic {
+    /// A problem with `key` of the answer file `text`, pointing at the line it is set on
+    pub fn new(text: &str, key: Option<&str>, message: impl Display) -> Self {
+        let position = key.and_then(|x| key_position(text, x));
+        Diagnostic {
+            key: key.map(|x| x.to_string()),
+            line: position.map(|x| x.0),
+            column: position.map(|x| x.1),
+            message: message.to_string(),
+        }
+    }
+
+    /// A problem with the file as a whole, e.g., of a configuration saved by the TUI
+    pub fn unplaced(message: impl Display) -> Self {
+        Diagnostic {
+            key: None,
+            line: None,
+            column: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl<'a> Report<'a> {
+    pub fn new(path: &'a Path, diagnostics: &'a [Diagnostic]) -> Self {
+        Report {
+            version: SCHEMA_VERSION,
+            path,
+            valid: diagnostics.is_empty(),
+            diagnostics,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// One line for each problem, as compilers print them: `path:line:column: message`
+    pub fn text(&self) -> String {
+        let path = self.path.display();
+        self.diagnostics
+            .iter()
+            .map(|x| match (x.line, x.column) {
+                (Some(line), Some(column)) => format!("{path}:{line}:{column}: {}\n", x.message),
+                _ => format!("{path}: {}\n", x.message),
+            })
+            .collect()
+    }
+}
+
+/// Parse the answer file, reporting every key which is unknown or of the wrong type
+///
+/// serde stops at the first error, so the line of each one is blanked out and the file is
+/// parsed again, until it parses or the error is not on a line of its own (e.g., a syntax
+/// error, or a key missing from a table). The file without those lines is returned if it
+/// parses then, to check the keys left.
+pub(super) fn parse(text: &str) -> (Option<ConfigFile>, Vec<Diagnostic>) {
+    let mut lines = text
+        .split_inclusive('\n')
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    let mut diagnostics = vec![];
+    loop {
+        let current = lines.concat();
+        let e = match toml::from_str::<ConfigFile>(&current) {
+            Ok(file) => return (Some(file), diagnostics),
+            Err(e) => e,
+        };
+        let syntax_error = current.parse::<toml::Table>().is_err();
+        if syntax_error && !diagnostics.is_empty() {
+            // a value spanning lines, of which one has been blanked out
+            break;
+        }
+        let position = e.span().map(|x| line_column(&current, x.start));
+        diagnostics.push(Diagnostic {
+            key: None,
+            line: position.map(|x| x.0),
+            column: position.map(|x| x.1),
+            message: e.message().to_string(),
+        });
+
+        let Some((line, _)) = position.filter(|_| !syntax_error) else {
+            break;
+        };
+        let content = lines[line - 1].trim_start();
+        if content.is_empty() || content.starts_with(['[', '#']) {
+            break;
+        }
+        lines[line - 1] = "\n".to_string();
+    }
+
+    (None, diagnostics)
+}
+
+/// The key a problem of `ConfigFile::problems` is about, which its message starts with
+pub(super) fn key_of(message: &str) -> Option<&str> {
+    message
+        .strip_prefix('`')
+        .and_then(|x| x.split('`').next())
+        .map(|x| x.split('[').next().unwrap_or(x))
+}
+
+/// The line and the column of the byte at `offset`
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |x| x + 1);
+
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// Where `key` (e.g., `region.timezone`) is set in the answer file, or the table holding
+/// it if it is not set there itself, e.g., a key missing from `[user]`
+fn key_position(text: &str, key: &str) -> Option<(usize, usize)> {
+    let wanted = key.split('.').collect::<Vec<_>>();
+    let mut table: Vec<&str> = vec![];
+    let mut holding = None;
+    for (i, line) in text.lines().enumerate() {
+        let content = line.trim_start();
+        let column = line.len() - content.len() + 1;
+        if content.starts_with('[') {
+            let name = content.trim_start_matches('[').split(']').next();
+            table = name
+                .unwrap_or_default()
+                .split('.')
+                .map(|x| x.trim().trim_matches('"'))
+                .collect();
+            if wanted.starts_with(&table) {
+                holding = Some((i + 1, column));
+            }
+            continue;
+        }
+        if content.starts_with('#') {
+            continue;
+        }
+        let Some((name, _)) = content.split_once('=') else {
+            continue;
+        };
+        // dotted keys, e.g., `target.disk = ...` at the top
+        let path = table
+            .iter()
+            .copied()
+            .chain(name.split('.').map(|x| x.trim().trim_matches('"')))
+            .collect::<Vec<_>>();
+        if path == wanted {
+            return Some((i + 1, column));
+        }
+        // an inline table, e.g., `target = { disk = ... }`
+        if wanted.starts_with(&path) {
+            holding = Some((i + 1, column));
+        }
+    }
+
+    holding
+}
+
+#[test]
+fn test_parse_reports_every_key() {
+    let text = "variant = \"Base\"\nhostnme = \"aosc\"\n\n[user]\nname = \"aosc\"\nuid = \"1001\"\nshel = \"/bin/zsh\"\n";
+    let (file, diagnostics) = parse(text);
+    assert_eq!(file.unwrap().user.unwrap().shell, None);
+    let lines = diagnostics.iter().map(|x| x.line).collect::<Vec<_>>();
+    assert_eq!(lines, [Some(2), Some(6), Some(7)]);
+    assert!(diagnostics[0].message.contains("hostnme"));
+
+    // a syntax error stops it
+    let (file, diagnostics) = parse("variant = \"Base\"\nuser = [\n");
+    assert!(file.is_none());
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].line.is_some());
+
+    let (file, diagnostics) = parse("variant = \"Base\"\n[target]\ndisk = \"/dev/sda\"\n");
+    assert!(file.is_some() && diagnostics.is_empty());
+}
+
+#[test]
+fn test_key_position() {
+    let text = "variant = \"Base\"\ntarget = { disk = \"/dev/sda\" }\n\n[user]\nname = \"aosc\"\n  shell = \"bash\"\n\n[region]\ntimezone = \"UTC\"\n";
+    assert_eq!(key_position(text, "variant"), Some((1, 1)));
+    assert_eq!(key_position(text, "target.disk"), Some((2, 1)));
+    assert_eq!(key_position(text, "user.shell"), Some((6, 3)));
+    assert_eq!(key_position(text, "user.password"), Some((4, 1)));
+    assert_eq!(key_position(text, "region.timezone"), Some((9, 1)));
+    assert_eq!(key_position(text, "swap.size"), None);
+    assert_eq!(
+        key_of("`target.mounts[0].filesystem` is only used"),
+        Some("target.mounts")
+    );
+    assert_eq!(key_of("hostname is not valid"), None);
+
+    let diagnostics = [
+        Diagnostic::new(
+            text,
+            Some("user.shell"),
+            "shell bash is not an absolute path!",
+        ),
+        Diagnostic::unplaced("`user.password` is required."),
+    ];
+    let report = Report::new(Path::new("site.toml"), &diagnostics);
+    assert!(!report.valid);
+    assert_eq!(
+        report.text(),
+        "site.toml:6:3: shell bash is not an absolute path!\nsite.toml: `user.password` is required.\n"
+    );
+}
@@ -0,0 +1,188 @@
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The catalogs map the English strings to their translations, a string missing from the
+/// catalog is shown in English
+const CATALOG_ZH_CN: &[u8] = include_bytes!("../res/i18n/zh_CN.json");
+/// Environment variables telling the language of the messages, in order of precedence
+const LANGUAGE_VARS: &[&str] = &["LC_ALL", "LC_MESSAGES", "LANG"];
+/// Not decided yet, detected from the environment on first use
+const LANGUAGE_UNSET: u8 = u8::MAX;
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(LANGUAGE_UNSET);
+static ZH_CN: Lazy<HashMap<String, String>> =
+    Lazy::new(|| serde_json::from_slice(CATALOG_ZH_CN).unwrap_or_default());
+
+/// Translate a string literal (or a `&str` constant), `{}` in the string are filled in with
+/// the arguments in order, translations may refer to them as `{0}`, `{1}` ... instead
+#[macro_export]
+macro_rules! tr {
+    ($msgid:expr) => {
+        $crate::i18n::tr($msgid)
+    };
+    ($msgid:expr, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::fill($crate::i18n::tr($msgid), &[$(&$arg as &dyn std::fmt::Display),+])
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Language {
+    English,
+    Chinese,
+}
+
+impl Language {
+    pub const ALL: &'static [Language] = &[Language::English, Language::Chinese];
+
+    /// The name of the language, in the language itself
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::English => "English",
+            Language::Chinese => "简体中文",
+        }
+    }
+
+    /// Parse `en` or `zh` (or a locale like `zh_CN.UTF-8`)
+    pub fn from_code(code: &str) -> Option<Language> {
+        match code.split(['_', '-', '.', '@']).next()? {
+            "C" | "POSIX" | "en" => Some(Language::English),
+            "zh" => Some(Language::Chinese),
+            _ => None,
+        }
+    }
+
+    fn catalog(&self) -> Option<&'static HashMap<String, String>> {
+        match self {
+            Language::English => None,
+            Language::Chinese => Some(&ZH_CN),
+        }
+    }
+}
+
+/// The language from the environment, English if it is not translated, or if the Linux
+/// console (which can not show CJK characters) is in use
+pub fn detect() -> Language {
+    let language = LANGUAGE_VARS
+        .iter()
+        .filter_map(|x| std::env::var(x).ok())
+        .find(|x| !x.is_empty())
+        .and_then(|x| Language::from_code(&x))
+        .unwrap_or(Language::English);
+    if language == Language::Chinese && std::env::var("TERM").as_deref() == Ok("linux") {
+        return Language::English;
+    }
+
+    language
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::SeqCst) {
+        LANGUAGE_UNSET => {
+            let language = detect();
+            set_language(language);
+
+            language
+        }
+        x => Language::ALL
+            .iter()
+            .copied()
+            .find(|y| *y as u8 == x)
+            .unwrap_or(Language::English),
+    }
+}
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::SeqCst);
+}
+
+/// The translation of `msgid` in the current language
+pub fn tr(msgid: &str) -> &str {
+    language()
+        .catalog()
+        .and_then(|x| x.get(msgid))
+        .map(|x| x.as_str())
+        .unwrap_or(msgid)
+}
+
+/// Pick the translated field of the recipe (e.g., `name-tr`) in Chinese, if it is there
+pub fn localized<'a>(text: &'a str, text_tr: &'a str) -> &'a str {
+    if language() == Language::Chinese && !text_tr.is_empty() {
+        text_tr
+    } else {
+        text
+    }
+}
+
+/// Fill the `{}` (in order) and `{0}`, `{1}` ... placeholders in `template`
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut result = String::new();
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let index = match &rest[1..end] {
+            "" => {
+                next += 1;
+                Some(next - 1)
+            }
+            x => x.parse::<usize>().ok(),
+        };
+        match index.and_then(|x| args.get(x)) {
+            Some(arg) => result.push_str(&arg.to_string()),
+            None => result.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Placeholders of the string, for checking the translations against
+#[cfg(test)]
+fn placeholders(template: &str) -> Vec<usize> {
+    let mut next = 0;
+    let mut result = template
+        .split('{')
+        .skip(1)
+        .filter_map(|x| x.split_once('}'))
+        .filter_map(|(x, _)| match x {
+            "" => {
+                next += 1;
+                Some(next - 1)
+            }
+            x => x.parse().ok(),
+        })
+        .collect::<Vec<_>>();
+    result.sort();
+
+    result
+}
+
+#[test]
+fn test_fill() {
+    assert_eq!(fill("{} of {}", &[&1, &"two"]), "1 of two");
+    assert_eq!(fill("{1} 的 {0}", &[&1, &"two"]), "two 的 1");
+    assert_eq!(fill("{{x}} {2}", &[&1]), "{{x}} {2}");
+    assert_eq!(fill("unclosed {", &[&1]), "unclosed {");
+    assert_eq!(Language::from_code("zh_CN.UTF-8"), Some(Language::Chinese));
+    assert_eq!(Language::from_code("C.UTF-8"), Some(Language::English));
+    assert_eq!(Language::from_code("fr_FR.UTF-8"), None);
+}
+
+#[test]
+fn test_catalogs() {
+    let catalog: HashMap<String, String> = serde_json::from_slice(CATALOG_ZH_CN).unwrap();
+    for (msgid, msgstr) in catalog {
+        assert_eq!(placeholders(&msgid), placeholders(&msgstr), "{msgid}");
+    }
+}
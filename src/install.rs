@@ -1,41 +1,190 @@
 use anyhow::{anyhow, bail, Context, Result};
 use cursive::utils::ProgressReader;
-use log::info;
+use log::{debug, info, warn};
+use rand::{thread_rng, Rng};
 use rustix::fd::{AsFd, OwnedFd};
 use rustix::fs::{self, FallocateFlags, Mode, OFlags};
 use rustix::io::Errno;
+use rustix::process::{Pid, Signal};
 use rustix::{mount, process};
-use std::ffi::OsStr;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsStr};
 use std::fmt::Debug;
 use std::io::{prelude::*, SeekFrom, Write};
 use std::os::unix::io::AsRawFd;
-use std::os::unix::prelude::{OsStrExt, PermissionsExt};
+use std::os::unix::prelude::{MetadataExt, OpenOptionsExt, OsStrExt, PermissionsExt};
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use std::{fs::File, path::Path};
 use sysinfo::System;
 
-use crate::disks::{fstab_entries, is_efi_booted, Partition};
+use crate::cancel::{CancelToken, CancellableReader, Cancelled};
+use crate::disks::{fstab_entries, fstab_options, is_efi_booted, Partition};
+use crate::manifest::{self, Manifest};
 use crate::network;
-use crate::parser::{list_mounts, list_zoneinfo, parse_languagelist};
+use crate::parser::{
+    list_countries, list_mounts, list_supported_locales, list_zone_entries, list_zoneinfo,
+    parse_languagelist, ZoneEntry,
+};
 
 const BIND_MOUNTS: &[&str] = &["/dev", "/proc", "/sys", "/run/udev"];
+const UNMOUNT_RETRIES: u32 = 5;
 const EFIVARS_PATH: &str = "/sys/firmware/efi/efivars";
+const EFI_GLOBAL_VARIABLE_GUID: &str = "8be4df61-93ca-11d2-aa0d-00e098032b8c";
+const WINDOWS_BOOT_MANAGER_PATH: &str = "/efi/EFI/Microsoft/Boot/bootmgfw.efi";
+const WINDOWS_BOOT_MANAGER_NAME: &str = "Windows Boot Manager";
+const DROP_CACHES_PATH: &str = "/proc/sys/vm/drop_caches";
+const SUPPORTED_LOCALES_PATH: &str = "/usr/share/i18n/SUPPORTED";
+const LOCALE_SOURCES_PATH: &str = "/usr/share/i18n/locales";
+const SYSTEMD_UNIT_PATHS: &[&str] = &[
+    "/etc/systemd/system",
+    "/usr/lib/systemd/system",
+    "/lib/systemd/system",
+];
+const NTP_UNITS: &[&str] = &["chronyd.service", "systemd-timesyncd.service"];
+const DISPLAY_MANAGER_UNITS: &[&str] = &[
+    "sddm.service",
+    "gdm.service",
+    "lightdm.service",
+    "lxdm.service",
+];
+pub const DEFAULT_TARGETS: &[&str] = &["graphical.target", "multi-user.target"];
+const PLYMOUTH_PATH: &str = "/usr/bin/plymouth";
+const PLYMOUTH_UNITS: &[&str] = &[
+    "plymouth-start.service",
+    "plymouth-read-write.service",
+    "plymouth-quit.service",
+    "plymouth-quit-wait.service",
+];
+const SPLASH_KERNEL_PARAMS: &[&str] = &["quiet", "splash"];
+const PLYMOUTH_DRACUT_CONF_PATH: &str = "/etc/dracut.conf.d/deploykit-plymouth.conf";
+const CRYPT_DRACUT_CONF_PATH: &str = "/etc/dracut.conf.d/deploykit-crypt.conf";
+const CRYPTTAB_PATH: &str = "/etc/crypttab";
+/// The device mapper name of the opened encrypted system partition
+const LUKS_MAPPER_NAME: &str = "aosc-root";
+/// Prefix of the device mapper names of the other encrypted partitions
+const DATA_MAPPER_PREFIX: &str = "aosc-data";
+/// Where the keyfiles of the other encrypted partitions are kept on the system partition
+const CRYPTSETUP_KEYS_DIR: &str = "/etc/cryptsetup-keys.d";
+/// Size of the random keyfiles in bytes, as long as a 512-bit volume key
+const KEYFILE_SIZE: usize = 64;
+const SUDOERS_DROPIN_PATH: &str = "/etc/sudoers.d/10-deploykit";
+const DOAS_CONF_PATH: &str = "/etc/doas.conf";
+const SSHD_CONFIG_PATH: &str = "/etc/ssh/sshd_config";
+const APT_SOURCES_PATH: &str = "/etc/apt/sources.list";
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+const GRUB_DEFAULT_PATH: &str = "/etc/default/grub";
+const MACHINE_ID_PATH: &str = "/etc/machine-id";
+const XKB_RULES_PATH: &str = "/usr/share/X11/xkb/rules/base.lst";
+const KBD_KEYMAPS_PATH: &str = "/usr/share/kbd/keymaps";
+const VCONSOLE_CONF_PATH: &str = "/etc/vconsole.conf";
+const CONSOLE_FONTS_PATH: &str = "/usr/share/kbd/consolefonts";
+const DRM_PATH: &str = "/sys/class/drm";
+/// Displays at least this tall get the larger console font
+const HIDPI_MIN_HEIGHT: u32 = 1600;
+pub const HIDPI_CONSOLE_FONT: &str = "ter-132n";
+pub const CONSOLE_FONT_PACKAGE: &str = "terminus-font";
+/// Low enough for the boot menu to stay legible on HiDPI displays
+pub const HIDPI_GRUB_GFXMODE: &str = "1280x720,auto";
+const X11_KEYBOARD_CONF_PATH: &str = "/etc/X11/xorg.conf.d/00-keyboard.conf";
+const DBUS_MACHINE_ID_PATH: &str = "/var/lib/dbus/machine-id";
+const FIRST_BOOT_MARKER_PATH: &str = "/var/lib/deploykit/first-boot";
+const OOBE_UNITS: &[&str] = &["aosc-oobe.service"];
+const FIRST_BOOT_UNIT: &str = "deploykit-first-boot.service";
+const FIRST_BOOT_UNIT_FILE: &str = include_str!("../res/deploykit-first-boot.service");
+const FIRST_BOOT_SCRIPT: &str = include_str!("../res/first-boot-user");
+const FIRST_BOOT_SCRIPT_PATH: &str = "/usr/lib/deploykit/first-boot-user";
+const SSH_KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ssh-dss",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+const YESCRYPT_PREFIX: &str = "$y$";
+const SHA512_CRYPT_PREFIX: &str = "$6$";
+const YESCRYPT_COST_RANGE: std::ops::RangeInclusive<u32> = 1..=11;
+const FSTAB_HEADER: &str =
+    "# /etc/fstab: static file system information, generated by AOSC OS Installer\n";
+const SWAPFILE_FSTAB_ENTRY: &str = "/swapfile none swap defaults,nofail 0 0";
+const MIN_USER_UID: u32 = 1000;
+const MAX_USER_UID: u32 = 60000;
+const MAX_USERNAME_LENGTH: usize = 32;
+/// Accounts and groups of the system release, `useradd` fails to create a user (and its
+/// group) with these names
+const RESERVED_USERNAMES: &[&str] = &[
+    "adm", "audio", "avahi", "bin", "cdrom", "colord", "daemon", "dbus", "disk", "floppy", "ftp",
+    "games", "http", "input", "kmem", "kvm", "ldap", "locate", "lp", "mail", "mysql", "named",
+    "nobody", "nogroup", "plugdev", "polkitd", "postgres", "pulse", "render", "root", "rpc",
+    "rtkit", "saned", "sddm", "shadow", "sshd", "sys", "tape", "tss", "tty", "unbound", "users",
+    "usbmux", "utmp", "uucp", "uuidd", "video", "wheel",
+];
+pub const DEFAULT_SHELL: &str = "/bin/bash";
+pub const NVIDIA_DRIVER_PACKAGES: &[&str] = &["nvidia"];
+const NVIDIA_PCI_VENDOR: &str = "0x10de";
+const PCI_DEVICES_PATH: &str = "/sys/bus/pci/devices";
+const NOUVEAU_BLACKLIST_PATH: &str = "/etc/modprobe.d/deploykit-nouveau-blacklist.conf";
+const SHELLS_PATH: &str = "/etc/shells";
+// the login shells of each variant, until its /etc/shells can be read after unpacking
+const VARIANT_SHELLS: &[(&str, &[&str])] = &[
+    ("Base", &["/bin/bash", "/bin/sh"]),
+    ("Server", &["/bin/bash", "/bin/sh", "/usr/bin/zsh"]),
+    (
+        "Desktop",
+        &["/bin/bash", "/bin/sh", "/usr/bin/zsh", "/usr/bin/fish"],
+    ),
+    (
+        "Workstation",
+        &["/bin/bash", "/bin/sh", "/usr/bin/zsh", "/usr/bin/fish"],
+    ),
+];
+pub const DEFAULT_USER_GROUPS: &[&str] = &["audio", "cdrom", "video", "wheel", "plugdev"];
+// offered on the user screen, only joined if the system release has them
+pub const OPTIONAL_USER_GROUPS: &[&str] = &["libvirt", "docker", "dialout", "plugdev"];
+const BUILTIN_LOCALES: &[&str] = &["C", "C.UTF-8", "POSIX"];
+const ZONEINFO_PATH: &str = "/usr/share/zoneinfo";
 const SYSTEM_ZONEINFO1970_PATH: &str = "/usr/share/zoneinfo/zone1970.tab";
 const BUNDLED_ZONEINFO_LIST: &[u8] = include_bytes!("../res/zone1970.tab");
+const SYSTEM_ISO3166_PATH: &str = "/usr/share/zoneinfo/iso3166.tab";
+const BUNDLED_ISO3166_LIST: &[u8] = include_bytes!("../res/iso3166.tab");
 pub const LANGUAGE_LIST: &[u8] = include_bytes!("../res/languagelist");
 
+/// Where the output of the running command is shown besides the log, e.g., under the progress bar
+static OUTPUT_WATCHER: Mutex<Option<Box<dyn Fn(&str) + Send>>> = Mutex::new(None);
+
+/// Hand each line the commands print (both stdout and stderr) to `watcher`, until it is unset
+pub fn watch_command_output(watcher: Option<Box<dyn Fn(&str) + Send>>) {
+    *OUTPUT_WATCHER.lock().unwrap() = watcher;
+}
+
+fn show_command_output(line: &str) {
+    if let Some(watcher) = OUTPUT_WATCHER.lock().unwrap().as_ref() {
+        watcher(line);
+    }
+}
+
 fn run_command<I, S>(command: &str, args: I) -> Result<()>
 where
     I: IntoIterator<Item = S> + Debug,
     S: AsRef<OsStr>,
 {
     let cmd_str = format!("{command} {args:?}");
-    info!("Running {}", cmd_str);
+    debug!("Running {}", cmd_str);
 
+    let start = Instant::now();
     let cmd = Command::new(command).args(args).output()?;
+    for line in String::from_utf8_lossy(&cmd.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&cmd.stderr).lines())
+    {
+        debug!("{}", line);
+    }
 
     if !cmd.status.success() {
         return Err(anyhow!(
@@ -45,7 +194,73 @@ where
         ));
     }
 
-    info!("Run {} Successfully!", cmd_str);
+    debug!("Run {} Successfully in {:.1?}!", cmd_str, start.elapsed());
+
+    Ok(())
+}
+
+/// Run the command and stream its output to the installer log (and the output watcher)
+/// line by line
+pub(crate) fn run_command_logged<I, S>(command: &str, args: I) -> Result<()>
+where
+    I: IntoIterator<Item = S> + Debug,
+    S: AsRef<OsStr>,
+{
+    run_command_with_output(command, args, |_| ())
+}
+
+/// Same as `run_command_logged`, but also hands each line of stdout to `on_line`
+fn run_command_with_output<I, S, F>(command: &str, args: I, mut on_line: F) -> Result<()>
+where
+    I: IntoIterator<Item = S> + Debug,
+    S: AsRef<OsStr>,
+    F: FnMut(&str),
+{
+    let cmd_str = format!("{command} {args:?}");
+    debug!("Running {}", cmd_str);
+
+    let start = Instant::now();
+    let mut child = Command::new(command)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stderr = child.stderr.take();
+    let stderr_thread = std::thread::spawn(move || {
+        let mut lines = vec![];
+        if let Some(stderr) = stderr {
+            for line in std::io::BufReader::new(stderr)
+                .lines()
+                .map_while(|x| x.ok())
+            {
+                debug!("{}", line);
+                show_command_output(&line);
+                lines.push(line);
+            }
+        }
+        lines
+    });
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in std::io::BufReader::new(stdout)
+            .lines()
+            .map_while(|x| x.ok())
+        {
+            debug!("{}", line);
+            show_command_output(&line);
+            on_line(&line);
+        }
+    }
+
+    let status = child.wait()?;
+    let stderr = stderr_thread.join().unwrap_or_default();
+
+    if !status.success() {
+        return Err(anyhow!("Run {} failed!\n\n{}", cmd_str, stderr.join("\n")));
+    }
+
+    debug!("Run {} Successfully in {:.1?}!", cmd_str, start.elapsed());
 
     Ok(())
 }
@@ -79,6 +294,88 @@ pub fn get_locale_list() -> Result<Vec<(&'static str, &'static str, &'static str
     Ok(res)
 }
 
+/// Get the list of locales supported by the glibc in `root`
+pub fn get_supported_locales(root: &Path) -> Result<Vec<String>> {
+    let data = std::fs::read(root.join(&SUPPORTED_LOCALES_PATH[1..]))?;
+    let locales = list_supported_locales(&data)
+        .map_err(|_| anyhow!("Installer failed to gather supported locales."))?
+        .1;
+
+    Ok(locales)
+}
+
+/// The language and territory of the locale in English, e.g., ("German", "Germany") for
+/// de_DE.UTF-8, from the LC_IDENTIFICATION of its source
+pub fn locale_identification(root: &Path, locale: &str) -> Option<(String, String)> {
+    let name = locale.replacen(".UTF-8", "", 1);
+    let data = std::fs::read_to_string(root.join(&LOCALE_SOURCES_PATH[1..]).join(name)).ok()?;
+
+    parse_locale_identification(&data)
+}
+
+fn parse_locale_identification(data: &str) -> Option<(String, String)> {
+    let field = |key: &str| {
+        data.lines()
+            .filter_map(|line| line.trim().strip_prefix(key))
+            .find(|x| x.starts_with(char::is_whitespace))
+            .map(|x| x.trim().trim_matches('"').to_string())
+    };
+
+    Some((field("language")?, field("territory").unwrap_or_default()))
+}
+
+/// Check whether the locale is supported, suggesting the closest matches if it is not
+pub fn check_locale(locale: &str, supported: &[String]) -> Result<()> {
+    if BUILTIN_LOCALES.contains(&locale) || supported.iter().any(|x| x == locale) {
+        return Ok(());
+    }
+
+    let suggestions = suggest_similar(locale, supported);
+    if suggestions.is_empty() {
+        bail!("Locale {} is not supported.", locale);
+    }
+
+    bail!(
+        "Locale {} is not supported. Did you mean: {}?",
+        locale,
+        suggestions.join(", ")
+    )
+}
+
+/// Return up to 3 candidates that are close to `input`, closest first
+pub fn suggest_similar<S: AsRef<str>>(input: &str, candidates: &[S]) -> Vec<String> {
+    let input = input.to_lowercase();
+    let max_distance = (input.chars().count() / 3).max(2);
+    let mut res = candidates
+        .iter()
+        .map(|x| x.as_ref())
+        .map(|x| (edit_distance(&input, &x.to_lowercase()), x))
+        .filter(|(d, _)| *d <= max_distance)
+        .collect::<Vec<_>>();
+    res.sort_by_key(|(d, _)| *d);
+
+    res.into_iter()
+        .take(3)
+        .map(|(_, x)| x.to_string())
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
 pub fn read_locale() -> Option<String> {
     let f = std::fs::read_to_string("/etc/locale.conf").ok()?;
     let lang = f.trim().strip_prefix("LANG=")?;
@@ -137,16 +434,29 @@ pub fn get_zoneinfo_list() -> Result<Vec<String>> {
     Ok(zoneinfo_list)
 }
 
-/// Extract the given .tar.xz stream and preserve all the file attributes
-fn extract_tar_xz<P: AsRef<Path>, R: Read>(reader: R, path: P) -> Result<()> {
+/// The timezones with the countries using them, from zone1970.tab
+pub fn get_zone_entries() -> Result<Vec<ZoneEntry>> {
+    let data = read_system_zoneinfo_list().unwrap_or_else(|_| BUNDLED_ZONEINFO_LIST.to_vec());
+    let entries = list_zone_entries(&data)
+        .map_err(|_| anyhow!("Installer failed to gather available timezones."))?
+        .1;
+
+    Ok(entries)
+}
+
+/// The names of the countries by their ISO 3166 codes
+pub fn get_country_names() -> Vec<(String, String)> {
+    let data = std::fs::read(SYSTEM_ISO3166_PATH).unwrap_or_else(|_| BUNDLED_ISO3166_LIST.to_vec());
+
+    list_countries(&data).map(|x| x.1).unwrap_or_default()
+}
+
+/// Extract the given .tar.xz stream and preserve all the file attributes, returns the
+/// checksums of the extracted files
+fn extract_tar_xz<P: AsRef<Path>, R: Read>(reader: R, path: P) -> Result<Manifest> {
     let decompress = xz2::read::XzDecoder::new(reader);
-    let mut tar_processor = tar::Archive::new(decompress);
-    tar_processor.set_unpack_xattrs(true);
-    tar_processor.set_preserve_permissions(true);
-    tar_processor.set_preserve_ownerships(true);
-    tar_processor.unpack(path)?;
 
-    Ok(())
+    manifest::unpack_tar(decompress, path.as_ref())
 }
 
 /// Extract the .squashfs and callback download progress
@@ -155,6 +465,7 @@ fn extract_squashfs<P: AsRef<Path>>(
     archive: P,
     path: P,
     counter: cursive::utils::Counter,
+    cancel: &CancelToken,
 ) -> Result<()> {
     let mut sys = System::new_all();
     sys.refresh_memory();
@@ -169,20 +480,22 @@ fn extract_squashfs<P: AsRef<Path>>(
         move |count| {
             counter.set((file_size * count as f64 / 100.0) as usize);
         },
-        Arc::new(AtomicBool::new(false)),
+        cancel.flag(),
     )?;
 
     Ok(())
 }
 
-/// Extract .tar.xz or .squashfs
+/// Extract .tar.xz or .squashfs, returns the checksums of the extracted files when they
+/// could be recorded on the way (.tar.xz only), or `Cancelled` if stopped by `cancel`
 pub fn extract_file(
     file_size: f64,
     url: String,
     archive_path: &Path,
     extract_path: &Path,
     counter: cursive::utils::Counter,
-) -> Result<()> {
+    cancel: &CancelToken,
+) -> Result<Option<Manifest>> {
     let extract_file_type = if url.ends_with(".squashfs") {
         ExtractFileType::Squashfs
     } else if url.ends_with(".tar.xz") {
@@ -191,15 +504,25 @@ pub fn extract_file(
         return Err(anyhow!("Unsupport format!"));
     };
 
-    match extract_file_type {
+    let extracted = match extract_file_type {
         ExtractFileType::Tar => extract_tar_xz(
-            ProgressReader::new(counter, std::fs::File::open(archive_path)?),
+            CancellableReader::new(
+                ProgressReader::new(counter, std::fs::File::open(archive_path)?),
+                cancel.clone(),
+            ),
             extract_path,
-        ),
+        )
+        .map(Some),
         ExtractFileType::Squashfs => {
-            extract_squashfs(file_size, archive_path, extract_path, counter)
+            extract_squashfs(file_size, archive_path, extract_path, counter, cancel).map(|_| None)
         }
+    };
+    // the unpacker reports the interrupted read (or unsquashfs its own error) otherwise
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
     }
+
+    extracted
 }
 
 pub fn auto_mount_root_path(tmp_path: &Path, partition: &Partition) -> Result<PathBuf> {
@@ -208,16 +531,63 @@ pub fn auto_mount_root_path(tmp_path: &Path, partition: &Partition) -> Result<Pa
     Ok(tmp_path.to_path_buf())
 }
 
-/// Sync the filesystem and then reboot IMMEDIATELY (ignores init)
-pub fn sync_and_reboot() -> Result<()> {
+/// Sync the filesystem, power off the installer medium at `eject` (so that the firmware
+/// boots into the installed system) and then reboot IMMEDIATELY (ignores init)
+pub fn sync_and_reboot(eject: Option<&Path>) -> Result<()> {
     fs::sync();
+    if let Some(disk) = eject {
+        info!("Powering off the installer medium {} ...", disk.display());
+        if let Err(e) = run_command(
+            "udisksctl",
+            [OsStr::new("power-off"), OsStr::new("-b"), disk.as_os_str()],
+        )
+        .or_else(|_| run_command("eject", [disk]))
+        {
+            warn!("Failed to power off {}: {}", disk.display(), e);
+        }
+    }
     run_command("systemctl", ["reboot"])?;
 
     Ok(())
 }
 
+/// Write back and drop the page cache, so that the files are read again from the disk
+pub fn drop_page_cache() -> Result<()> {
+    fs::sync();
+    std::fs::write(DROP_CACHES_PATH, "1")?;
+
+    Ok(())
+}
+
 /// Mount the filesystem
 pub fn mount_root_path(partition: &Partition, target: &Path) -> Result<()> {
+    mount_with_flags(partition, target, mount::MountFlags::empty(), "")
+}
+
+/// Mount `partition` read-only to look into it, without replaying the journal of its
+/// filesystem, so nothing is written to the disk
+fn mount_for_probe(partition: &Partition, target: &Path) -> Result<()> {
+    let data = probe_mount_options(partition.fs_type.as_deref().unwrap_or_default());
+    mount_with_flags(partition, target, mount::MountFlags::RDONLY, data)
+}
+
+/// The mount options skipping the journal replay, which a read-only mount of these
+/// filesystems still does
+fn probe_mount_options(fs_type: &str) -> &'static str {
+    match fs_type {
+        "ext3" | "ext4" => "noload",
+        "xfs" => "norecovery",
+        "btrfs" => "rescue=nologreplay",
+        _ => "",
+    }
+}
+
+fn mount_with_flags(
+    partition: &Partition,
+    target: &Path,
+    flags: mount::MountFlags,
+    data: &str,
+) -> Result<()> {
     if partition.fs_type.is_none() || partition.path.is_none() {
         return Err(anyhow!(
             "Installer failed to determine user-specified partition."
@@ -233,223 +603,2672 @@ pub fn mount_root_path(partition: &Partition, target: &Path) -> Result<()> {
         source.unwrap_or(&PathBuf::from("")),
         target,
         fs_type,
-        mount::MountFlags::empty(),
-        "",
+        flags,
+        data,
     )?;
 
     Ok(())
 }
 
-/// Gen fstab to /etc/fstab
-pub fn genfstab_to_file(partition: &Partition, root_path: &Path, mount_path: &Path) -> Result<()> {
-    if cfg!(debug_assertions) {
-        return Ok(());
-    }
-    let fs_type = partition.fs_type.as_ref().ok_or_else(|| {
-        anyhow!("Installer failed to detect filesystem type for the specified partition.")
-    })?;
-    let s = fstab_entries(partition.path.as_ref(), fs_type, Some(mount_path))?;
-    let mut f = std::fs::OpenOptions::new()
-        .append(true)
-        .open(root_path.join("etc/fstab"))?;
-    f.write_all(s.as_bytes())?;
+/// An existing AOSC OS installation on the target partition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreviousInstall {
+    /// Normal users found in its /etc/passwd
+    pub users: Vec<OldUser>,
+    /// Its /etc/fstab entry for /home, if /home is on a separate partition
+    pub home_fstab: Option<String>,
+}
 
-    Ok(())
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OldUser {
+    pub name: String,
+    pub uid: u32,
+    /// The primary group
+    #[serde(default)]
+    pub gid: u32,
+    /// Name of the primary group from /etc/group, `None` if it is not listed there
+    #[serde(default)]
+    pub group: Option<String>,
+    pub home: String,
+    pub shell: String,
+    /// Password hash from /etc/shadow
+    pub hash: Option<String>,
+    /// Supplementary groups from /etc/group
+    pub groups: Vec<String>,
 }
 
-/// Unmount the filesystem given at `root` and then do a sync
-pub fn umount_root_path(root: &Path) -> Result<()> {
-    mount::unmount(root, mount::UnmountFlags::DETACH)?;
-    fs::sync();
+/// Mount `partition` read-only and look for an AOSC OS installation on it
+pub fn probe_previous_install(partition: &Partition) -> Result<Option<PreviousInstall>> {
+    crate::assert_not_ui_thread("Probing for a previous installation");
 
-    Ok(())
-}
+    let tempdir = tempfile::Builder::new().prefix(".dkprobe").tempdir()?;
+    mount_for_probe(partition, tempdir.path())?;
+    let result = find_previous_install(tempdir.path());
+    mount::unmount(tempdir.path(), mount::UnmountFlags::DETACH)?;
 
-/// Get the open file descriptor to the specified path
-pub fn get_dir_fd(path: &Path) -> Result<OwnedFd> {
-    let fd = fs::open(
-        path,
-        OFlags::RDONLY | OFlags::DIRECTORY | OFlags::NONBLOCK,
-        Mode::empty(),
-    )?;
+    result
+}
 
-    Ok(fd)
+/// What has been found on a partition
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionContents {
+    /// The operating system (or boot loaders) on it
+    pub os: Option<String>,
+    /// Space left in its filesystem
+    pub free: u64,
+    /// Space taken in its filesystem
+    pub used: u64,
 }
 
-/// Escape the chroot context using the previously obtained `root_fd` as a trampoline
-pub fn escape_chroot<F: AsFd>(root_fd: F) -> Result<()> {
-    process::fchdir(root_fd)?;
-    process::chroot(".")?;
-    std::env::set_current_dir("/")?; // reset cwd (on host)
+/// The operating system on `partition` and the space used on it, looked for in
+/// `mount_point` if it is mounted already, or by mounting it read-only
+pub fn probe_partition(
+    partition: &Partition,
+    mount_point: Option<&Path>,
+) -> Result<PartitionContents> {
+    if let Some(mount_point) = mount_point {
+        return find_partition_contents(mount_point);
+    }
 
-    info!("Escaped chroot environment");
+    let mut partition = partition.clone();
+    // the in-kernel NTFS driver is named ntfs3
+    if partition.fs_type.as_deref() == Some("ntfs") {
+        partition.fs_type = Some("ntfs3".to_string());
+    }
+    let tempdir = tempfile::Builder::new().prefix(".dkprobe").tempdir()?;
+    mount_for_probe(&partition, tempdir.path())?;
+    let result = find_partition_contents(tempdir.path());
+    mount::unmount(tempdir.path(), mount::UnmountFlags::DETACH)?;
 
-    Ok(())
+    result
 }
 
-/// Setup all the necessary bind mounts
-pub fn setup_bind_mounts(root: &Path) -> Result<()> {
-    for mount in BIND_MOUNTS {
-        let mut root = root.to_owned();
-        root.push(&mount[1..]);
-        std::fs::create_dir_all(root.clone())?;
-        mount::mount(*mount, &root, "", mount::MountFlags::BIND, "")?;
-    }
+fn find_partition_contents(root: &Path) -> Result<PartitionContents> {
+    let stat = fs::statvfs(root)?;
 
-    if is_efi_booted() {
-        let root = root.join(&EFIVARS_PATH[1..]);
-        std::fs::create_dir_all(&root)?;
-        mount::mount(EFIVARS_PATH, &root, "", mount::MountFlags::BIND, "")?;
+    Ok(PartitionContents {
+        os: find_os(root),
+        free: stat.f_bavail * stat.f_frsize,
+        used: stat.f_blocks.saturating_sub(stat.f_bfree) * stat.f_frsize,
+    })
+}
+
+/// The name of the operating system in `root`, or the boot loaders if it is an ESP
+fn find_os(root: &Path) -> Option<String> {
+    for os_release in ["etc/os-release", "usr/lib/os-release"] {
+        let name = std::fs::read_to_string(root.join(os_release))
+            .unwrap_or_default()
+            .lines()
+            .find_map(|x| x.strip_prefix("PRETTY_NAME="))
+            .map(|x| x.trim_matches('"').to_string());
+        if name.is_some() {
+            return name;
+        }
+    }
+    if root.join("Windows/System32").is_dir() {
+        return Some("Windows".to_string());
     }
 
-    Ok(())
+    let mut loaders = std::fs::read_dir(root.join("EFI"))
+        .ok()?
+        .flatten()
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .filter(|x| !x.eq_ignore_ascii_case("boot"))
+        .collect::<Vec<_>>();
+    loaders.sort();
+
+    (!loaders.is_empty()).then(|| format!("EFI: {}", loaders.join(", ")))
 }
 
-/// Remove bind mounts
-/// Note: This function should be called outside of the chroot context
-pub fn remove_bind_mounts(root: &Path) -> Result<()> {
-    for mount in BIND_MOUNTS {
-        let mut root = root.to_owned();
-        root.push(&mount[1..]);
-        mount::unmount(&root, mount::UnmountFlags::DETACH)?;
+/// Look for an AOSC OS installation in `root`
+pub fn find_previous_install(root: &Path) -> Result<Option<PreviousInstall>> {
+    let os_release = match std::fs::read_to_string(root.join("etc/os-release")) {
+        Ok(os_release) => os_release,
+        Err(_) => return Ok(None),
+    };
+    if !os_release.lines().any(|x| x.trim() == "ID=aosc") {
+        return Ok(None);
     }
 
-    Ok(())
+    let read = |path: &str| std::fs::read_to_string(root.join(path)).unwrap_or_default();
+    let users = parse_old_users(&read("etc/passwd"), &read("etc/shadow"), &read("etc/group"));
+    let home_fstab = fstab_home_entry(&read("etc/fstab"));
+
+    Ok(Some(PreviousInstall { users, home_fstab }))
 }
 
-/// Setup bind mounts and chroot into the guest system
-/// Warning: This will make the program trapped in the new root directory
-pub fn dive_into_guest(root: &Path) -> Result<()> {
-    setup_bind_mounts(root)?;
-    process::chroot(root)?;
-    std::env::set_current_dir("/")?; // jump to the root directory after chroot
+fn parse_old_users(passwd: &str, shadow: &str, group: &str) -> Vec<OldUser> {
+    let names = normal_users(passwd);
+    passwd
+        .lines()
+        .map(|x| x.split(':').collect::<Vec<_>>())
+        .filter(|x| x.len() >= 7 && names.iter().any(|name| name == x[0]))
+        .filter_map(|x| {
+            let name = x[0].to_string();
+            let hash = shadow
+                .lines()
+                .map(|x| x.split(':').collect::<Vec<_>>())
+                .find(|x| x[0] == name)
+                .and_then(|x| x.get(1).map(|x| x.to_string()))
+                .filter(|x| !x.is_empty());
+            let groups = group
+                .lines()
+                .map(|x| x.split(':').collect::<Vec<_>>())
+                .filter(|x| {
+                    x.get(3)
+                        .map(|members| members.split(',').any(|x| x == name))
+                        .unwrap_or(false)
+                })
+                .map(|x| x[0].to_string())
+                .collect();
+            let gid: u32 = x[3].parse().ok()?;
+            let primary = group
+                .lines()
+                .map(|x| x.split(':').collect::<Vec<_>>())
+                .find(|x| x.len() >= 3 && x[2] == gid.to_string())
+                .map(|x| x[0].to_string());
+
+            Some(OldUser {
+                uid: x[2].parse().ok()?,
+                gid,
+                group: primary,
+                home: x[5].to_string(),
+                shell: x[6].to_string(),
+                name,
+                hash,
+                groups,
+            })
+        })
+        .collect()
+}
 
-    Ok(())
+fn fstab_home_entry(fstab: &str) -> Option<String> {
+    fstab
+        .lines()
+        .find(|x| !x.trim_start().starts_with('#') && x.split_whitespace().nth(1) == Some("/home"))
+        .map(|x| x.to_string())
 }
 
-/// Runs dracut
-/// Must be used in a chroot context
-#[cfg(not(feature = "is_retro"))]
-pub fn execute_dracut() -> Result<()> {
-    let cmd = "/usr/bin/update-initramfs";
-    run_command(cmd, &[] as &[&str])?;
+/// Remove everything in `root` but /home, to reinstall over a previous installation
+/// Must be used outside of the chroot context
+pub fn wipe_root_except_home(root: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "home" || name == "lost+found" {
+            continue;
+        }
+        let path = entry.path();
+        info!("Removing {}", path.display());
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+    }
 
     Ok(())
 }
 
-/// Runs dracut (dummy function for retro mode)
-/// Must be used in a chroot context
-#[cfg(feature = "is_retro")]
-pub fn execute_dracut() -> Result<()> {
-    no_need_to_run_info("dracut", true);
+/// Remove the incomplete system left in `root` by a cancelled installation, keeping /home
+/// if it is preserved from a previous installation, and whatever is mounted beneath `root`
+/// (e.g., the ESP, or a separate /var/log inside /var)
+/// Must be used outside of the chroot context
+pub fn wipe_incomplete_system(root: &Path, keep_home: bool) -> Result<()> {
+    let mut buf = Vec::new();
+    std::fs::File::open("/proc/mounts")?.read_to_end(&mut buf)?;
+    let mounts = list_mounts(&buf)
+        .map_err(|e| anyhow!("Failed to get mounts, {}", e))?
+        .1;
+    let mounts = submounts(&mounts, root)
+        .into_iter()
+        .filter(|x| x != root)
+        .collect::<Vec<_>>();
 
-    Ok(())
+    wipe_keeping_mounts(root, &mounts, &|name| {
+        name == "lost+found" || (keep_home && name == "home")
+    })
 }
 
-/// Runs ssh-keygen -A (dummy function for non-retro mode)
-/// Must be used in a chroot context
-#[cfg(not(feature = "is_retro"))]
-pub fn gen_ssh_key() -> Result<()> {
-    no_need_to_run_info("ssh-keygen", false);
+/// Remove everything in `dir` except the entries named as `keep` and the `mounts`, the
+/// directories with mounts beneath are descended into instead
+fn wipe_keeping_mounts(
+    dir: &Path,
+    mounts: &[PathBuf],
+    keep: &dyn Fn(&OsStr) -> bool,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if keep(&entry.file_name()) {
+            continue;
+        }
+        let path = entry.path();
+        if mounts.contains(&path) {
+            info!("Keeping mount point {}", path.display());
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() && mounts.iter().any(|x| x.starts_with(&path)) {
+            wipe_keeping_mounts(&path, mounts, &|_| false)?;
+        } else if metadata.is_dir() {
+            info!("Removing {}", path.display());
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            info!("Removing {}", path.display());
+            std::fs::remove_file(&path)?;
+        }
+    }
 
     Ok(())
 }
 
-/// Runs ssh-keygen -A
+/// Re-create a user from the previous installation with the same UID, password and groups,
+/// so that the ownership of their preserved files still matches
 /// Must be used in a chroot context
-#[cfg(feature = "is_retro")]
-pub fn gen_ssh_key() -> Result<()> {
-    run_command("ssh-keygen", &["-A"])?;
+pub fn restore_user(user: &OldUser) -> Result<()> {
+    let root = Path::new("/");
+    check_uid(root, user.uid)?;
+
+    let shell = if Path::new(&user.shell).exists() {
+        user.shell.as_str()
+    } else {
+        DEFAULT_SHELL
+    };
+    let uid = user.uid.to_string();
+    let gid = user.gid.to_string();
+    let mut args = vec![
+        "-M",
+        "-u",
+        uid.as_str(),
+        "-d",
+        user.home.as_str(),
+        "-s",
+        shell,
+    ];
+    // the preserved files are owned by the primary group as well
+    if let Some(group) = user.group.as_deref() {
+        let group_file = std::fs::read_to_string(root.join("etc/group"))?;
+        if !group_exists(&group_file, group, user.gid)? {
+            run_command("groupadd", ["-g", gid.as_str(), group])?;
+        }
+        args.extend(["-g", gid.as_str()]);
+    }
+    args.push(&user.name);
+    run_command("useradd", &args)?;
+
+    // groups that no longer exist in the new system are dropped
+    let missing = missing_groups(root, &user.groups)?;
+    let groups = user
+        .groups
+        .iter()
+        .filter(|x| !missing.contains(x))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !groups.is_empty() {
+        run_command("usermod", ["-aG", &groups.join(","), &user.name])?;
+    }
+
+    if let Some(hash) = user.hash.as_ref() {
+        chpasswd_hash(&user.name, hash)?;
+    }
 
     Ok(())
 }
 
-/// Sets hostname in the guest environment
-/// Must be used in a chroot context
-pub fn set_hostname(name: &str) -> Result<()> {
-    let mut f = File::create("/etc/hostname")?;
+/// Whether the group named `name` with the GID exists in `group_file` (/etc/group), fails
+/// if the name or the GID is taken by another one
+fn group_exists(group_file: &str, name: &str, gid: u32) -> Result<bool> {
+    let gid_str = gid.to_string();
+    for x in group_file.lines().map(|x| x.split(':').collect::<Vec<_>>()) {
+        if x.len() < 3 {
+            continue;
+        }
+        match (x[0] == name, x[2] == gid_str) {
+            (true, true) => return Ok(true),
+            (true, false) => bail!("Group {} already exists with GID {}.", name, x[2]),
+            (false, true) => bail!("GID {} is already used by group {}.", gid, x[0]),
+            (false, false) => (),
+        }
+    }
 
-    Ok(f.write_all(name.as_bytes())?)
+    Ok(false)
 }
 
-/// Sets locale in the guest environment
-/// Must be used in a chroot context
-pub fn set_locale(locale: &str) -> Result<()> {
-    let mut f = File::create("/etc/locale.conf")?;
-    f.write_all(b"LANG=")?;
-
-    Ok(f.write_all(format!("{locale}\n").as_bytes())?)
+/// The filesystems to be mounted by the installed system, from which its fstab is generated
+#[derive(Debug, Clone)]
+pub struct MountPlan {
+    /// Partitions with their mount points, including the root, and a swap partition
+    /// mounted at `none`
+    pub partitions: Vec<(Partition, PathBuf)>,
+    pub swapfile: bool,
+    /// Existing fstab lines to keep as-is, e.g. the separate /home of the previous installation
+    pub extra: Vec<String>,
+    /// Mount options replacing the defaults of the filesystem, by mount point
+    pub options: HashMap<PathBuf, String>,
 }
 
-/// Sets zoneinfo in the guest environment
-/// Must be used in a chroot context
-pub fn set_zoneinfo(zone: &str) -> Result<()> {
-    if Path::new("/etc/localtime").exists() {
-        std::fs::remove_file("/etc/localtime")?;
+impl MountPlan {
+    /// Render the complete fstab, with the UUIDs of the (formatted) partitions
+    pub fn render(&self) -> Result<String> {
+        self.render_with(|partition, mount_path| {
+            let fs_type = partition_fs_type(partition)?;
+            let s = fstab_entries(
+                partition.path.as_ref(),
+                fs_type,
+                Some(mount_path).filter(|_| fs_type != "swap"),
+                self.options.get(mount_path).map(|x| x.as_str()),
+            )?;
+
+            Ok(s.to_string_lossy().trim_end().to_string())
+        })
+    }
+
+    /// Render the fstab with the device paths instead of the UUIDs, which are not known
+    /// until the partitions are formatted
+    pub fn preview(&self) -> Result<String> {
+        self.render_with(|partition, mount_path| {
+            let fs_type = partition_fs_type(partition)?;
+            let (_, default_options) = fstab_options(fs_type)?;
+            let options = self
+                .options
+                .get(mount_path)
+                .map(|x| x.as_str())
+                .unwrap_or(default_options);
+            let device = partition
+                .path
+                .as_ref()
+                .map(|x| x.display().to_string())
+                .unwrap_or_default();
+            let pass = match mount_path.to_str() {
+                Some("/") => 1,
+                _ if fs_type == "swap" => 0,
+                _ => 2,
+            };
+
+            Ok(format!(
+                "{device} {} {fs_type} {options} 0 {pass}",
+                mount_path.display()
+            ))
+        })
     }
 
-    std::os::unix::fs::symlink(format!("/usr/share/zoneinfo/{zone}"), "/etc/localtime")?;
+    fn render_with<F>(&self, f: F) -> Result<String>
+    where
+        F: Fn(&Partition, &Path) -> Result<String>,
+    {
+        let mut entries = self
+            .partitions
+            .iter()
+            .map(|(partition, mount_path)| f(partition, mount_path))
+            .collect::<Result<Vec<_>>>()?;
+        if self.swapfile {
+            entries.push(SWAPFILE_FSTAB_ENTRY.to_string());
+        }
+        entries.extend(self.extra.iter().cloned());
 
-    Ok(())
+        assemble_fstab(&entries)
+    }
 }
 
-/// Sets utc/rtc time in the guest environment
-/// Must be used in a chroot context
-pub fn set_hwclock_tc(utc: bool) -> Result<()> {
-    let adjtime_file = std::fs::File::open("/etc/adjtime");
-    let status_is_rtc = if let Ok(mut adjtime_file) = adjtime_file {
-        let mut buf = String::new();
-        adjtime_file.read_to_string(&mut buf)?;
-        let line: Vec<&str> = buf.split('\n').collect();
-        if line.len() < 3 || line.get(2) == Some(&"UTC") {
-            false
-        } else {
-            line[2] == "LOCAL"
-        }
-    } else {
-        false
-    };
+fn partition_fs_type(partition: &Partition) -> Result<&str> {
+    match partition.fs_type.as_deref() {
+        Some(x) if x.starts_with("linux-swap") => Ok("swap"),
+        Some(x) => Ok(x),
+        None => Err(anyhow!(
+            "Installer failed to detect filesystem type for the specified partition."
+        )),
+    }
+}
 
-    info!("Status is rtc: {}", status_is_rtc);
-    if utc {
-        if !status_is_rtc {
-            return Ok(());
-        } else {
-            run_command("hwclock", ["-wu"])?;
+/// Order the fstab entries (root first, then by depth, swap last) and check that they
+/// make sense together
+fn assemble_fstab(entries: &[String]) -> Result<String> {
+    let mut parsed = vec![];
+    for entry in entries {
+        let fields = entry.split_whitespace().collect::<Vec<_>>();
+        if fields.len() < 4 {
+            bail!("Malformed fstab entry: {}", entry);
         }
-    } else if status_is_rtc {
-        return Ok(());
-    } else {
-        run_command("hwclock", ["-wl"])?;
+        parsed.push((entry.trim(), fields));
     }
 
-    Ok(())
-}
+    let is_swap = |fields: &[&str]| fields[2] == "swap";
+    let mut mount_points = vec![];
+    let mut sources = vec![];
+    for (entry, fields) in &parsed {
+        if !is_swap(fields) {
+            if mount_points.contains(&fields[1]) {
+                bail!("Mount point {} appears more than once in fstab.", fields[1]);
+            }
+            mount_points.push(fields[1]);
+        }
+        // subvolumes of the same btrfs filesystem share the UUID
+        let is_subvolume = fields[2] == "btrfs" && fields[3].contains("subvol");
+        if !is_subvolume && fields[0].starts_with("UUID=") {
+            if sources.contains(&fields[0]) {
+                bail!("{} appears more than once in fstab: {}", fields[0], entry);
+            }
+            sources.push(fields[0]);
+        }
+    }
+    if !mount_points.contains(&"/") {
+        bail!("fstab has no entry for the root filesystem.");
+    }
 
-/// Adds a new normal user to the guest environment
-/// Must be used in a chroot context
-pub fn add_new_user(name: &str, password: &str) -> Result<()> {
-    run_command("useradd", ["-m", "-s", "/bin/bash", name])?;
-    run_command("usermod", ["-aG", "audio,cdrom,video,wheel,plugdev", name])?;
+    parsed.sort_by_key(|(_, fields)| (is_swap(fields), Path::new(fields[1]).components().count()));
 
-    chpasswd(name, password)?;
+    let mut s = FSTAB_HEADER.to_string();
+    for (entry, _) in parsed {
+        s.push_str(entry);
+        s.push('\n');
+    }
 
-    Ok(())
+    Ok(s)
 }
 
-pub fn chpasswd(name: &str, password: &str) -> Result<()> {
-    info!("Running chpasswd ...");
-    let command = Command::new("chpasswd").stdin(Stdio::piped()).spawn()?;
-    let mut stdin = command.stdin.ok_or_else(|| {
-        anyhow!("Installer can not get your stdin! please restart your environment")
-    })?;
-    stdin.write_all(format!("{name}:{password}\n").as_bytes())?;
-    stdin.flush()?;
-    info!("Running chpasswd successfully");
+/// Replace /etc/fstab of the system at `root` with `fstab` atomically
+pub fn write_fstab(root: &Path, fstab: &str) -> Result<()> {
+    let path = root.join("etc/fstab");
+    let tmp_path = root.join("etc/fstab.deploykit");
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(fstab.as_bytes())?;
+    f.sync_all()?;
+    std::fs::rename(tmp_path, path)?;
 
     Ok(())
 }
 
+/// Unmount the filesystem given at `root` and then do a sync
+pub fn umount_root_path(root: &Path) -> Result<()> {
+    mount::unmount(root, mount::UnmountFlags::DETACH)?;
+    fs::sync();
+
+    Ok(())
+}
+
+/// How the installed system has been unmounted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmountStatus {
+    Clean,
+    /// Some filesystems were still busy, and have been lazily unmounted after a sync
+    Lazy,
+}
+
+/// Unmount `root` and everything mounted beneath it (ESP, bind mounts), deepest first
+///
+/// Busy filesystems are retried with backoff: processes holding them are reported, then
+/// terminated and killed on later attempts. As a last resort they are lazily unmounted.
+/// Note: This function should be called outside of the chroot context
+pub fn unmount_recursively(root: &Path) -> Result<UnmountStatus> {
+    unmount_submounts(root, true)
+}
+
+/// Same as `unmount_recursively`, but leaves `root` itself mounted
+pub fn unmount_beneath(root: &Path) -> Result<UnmountStatus> {
+    unmount_submounts(root, false)
+}
+
+fn unmount_submounts(root: &Path, include_root: bool) -> Result<UnmountStatus> {
+    let mut buf = Vec::new();
+    std::fs::File::open("/proc/mounts")?.read_to_end(&mut buf)?;
+    let mounts = list_mounts(&buf)
+        .map_err(|e| anyhow!("Failed to get mounts, {}", e))?
+        .1;
+
+    fs::sync();
+    let mut status = UnmountStatus::Clean;
+    for mount in submounts(&mounts, root)
+        .into_iter()
+        .filter(|x| include_root || x != root)
+    {
+        if unmount_with_retry(&mount)? == UnmountStatus::Lazy {
+            status = UnmountStatus::Lazy;
+        }
+    }
+    fs::sync();
+
+    Ok(status)
+}
+
+/// Mount points at or beneath `root`, in the order they should be unmounted
+fn submounts(mounts: &[(&str, &str)], root: &Path) -> Vec<PathBuf> {
+    // mounts may be stacked on top of earlier ones, so go in the reverse order
+    mounts
+        .iter()
+        .rev()
+        .map(|(_, mount_path)| PathBuf::from(mount_path))
+        .filter(|x| x.starts_with(root))
+        .collect()
+}
+
+fn unmount_with_retry(mount: &Path) -> Result<UnmountStatus> {
+    let mut delay = Duration::from_millis(200);
+    for attempt in 0..UNMOUNT_RETRIES {
+        match mount::unmount(mount, mount::UnmountFlags::empty()) {
+            Ok(()) => {
+                info!("Unmounted {}", mount.display());
+                return Ok(UnmountStatus::Clean);
+            }
+            Err(Errno::BUSY) => (),
+            // already gone along with its parent
+            Err(Errno::INVAL) | Err(Errno::NOENT) => return Ok(UnmountStatus::Clean),
+            Err(e) => bail!("Failed to unmount {}: {}", mount.display(), e),
+        }
+
+        let holders = find_mount_holders(mount);
+        for (pid, name) in &holders {
+            warn!(
+                "{} is busy, held by {} (pid {})",
+                mount.display(),
+                name,
+                pid
+            );
+        }
+        // give them a chance to go away by themselves first
+        let signal = match attempt {
+            0 | 1 => None,
+            2 => Some(Signal::Term),
+            _ => Some(Signal::Kill),
+        };
+        if let Some(signal) = signal {
+            for (pid, name) in &holders {
+                info!("Sending {:?} to {} (pid {})", signal, name, pid);
+                if let Some(pid) = Pid::from_raw(*pid) {
+                    process::kill_process(pid, signal).ok();
+                }
+            }
+        }
+
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+
+    warn!("{} is still busy, unmounting it lazily", mount.display());
+    fs::sync();
+    mount::unmount(mount, mount::UnmountFlags::DETACH)?;
+
+    Ok(UnmountStatus::Lazy)
+}
+
+/// Find the processes using files beneath `mount` like fuser(1) does, returns their PIDs and names
+fn find_mount_holders(mount: &Path) -> Vec<(i32, String)> {
+    let procs = match std::fs::read_dir("/proc") {
+        Ok(procs) => procs,
+        Err(_) => return vec![],
+    };
+
+    procs
+        .flatten()
+        .filter_map(|entry| {
+            let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+            if pid as u32 == std::process::id() {
+                return None;
+            }
+            let dir = entry.path();
+            let mut links = ["cwd", "root", "exe"]
+                .iter()
+                .filter_map(|x| std::fs::read_link(dir.join(x)).ok())
+                .collect::<Vec<_>>();
+            if let Ok(fds) = std::fs::read_dir(dir.join("fd")) {
+                links.extend(
+                    fds.flatten()
+                        .filter_map(|x| std::fs::read_link(x.path()).ok()),
+                );
+            }
+            if !links.iter().any(|x| x.starts_with(mount)) {
+                return None;
+            }
+            let name = std::fs::read_to_string(dir.join("comm"))
+                .map(|x| x.trim().to_string())
+                .unwrap_or_default();
+
+            Some((pid, name))
+        })
+        .collect()
+}
+
+/// Get the open file descriptor to the specified path
+pub fn get_dir_fd(path: &Path) -> Result<OwnedFd> {
+    let fd = fs::open(
+        path,
+        OFlags::RDONLY | OFlags::DIRECTORY | OFlags::NONBLOCK,
+        Mode::empty(),
+    )?;
+
+    Ok(fd)
+}
+
+/// Escape the chroot context using the previously obtained `root_fd` as a trampoline
+pub fn escape_chroot<F: AsFd>(root_fd: F) -> Result<()> {
+    process::fchdir(root_fd)?;
+    process::chroot(".")?;
+    std::env::set_current_dir("/")?; // reset cwd (on host)
+
+    info!("Escaped chroot environment");
+
+    Ok(())
+}
+
+/// Setup all the necessary bind mounts
+pub fn setup_bind_mounts(root: &Path) -> Result<()> {
+    for mount in BIND_MOUNTS {
+        let mut root = root.to_owned();
+        root.push(&mount[1..]);
+        std::fs::create_dir_all(root.clone())?;
+        mount::mount(*mount, &root, "", mount::MountFlags::BIND, "")?;
+    }
+
+    if is_efi_booted() {
+        let root = root.join(&EFIVARS_PATH[1..]);
+        std::fs::create_dir_all(&root)?;
+        mount::mount(EFIVARS_PATH, &root, "", mount::MountFlags::BIND, "")?;
+    }
+
+    Ok(())
+}
+
+/// Setup bind mounts and chroot into the guest system
+/// Warning: This will make the program trapped in the new root directory
+pub fn dive_into_guest(root: &Path) -> Result<()> {
+    setup_bind_mounts(root)?;
+    process::chroot(root)?;
+    std::env::set_current_dir("/")?; // jump to the root directory after chroot
+
+    Ok(())
+}
+
+/// Runs dracut
+/// Must be used in a chroot context
+#[cfg(not(feature = "is_retro"))]
+pub fn execute_dracut() -> Result<()> {
+    let cmd = "/usr/bin/update-initramfs";
+    run_command_logged(cmd, &[] as &[&str])?;
+
+    Ok(())
+}
+
+/// Runs dracut (dummy function for retro mode)
+/// Must be used in a chroot context
+#[cfg(feature = "is_retro")]
+pub fn execute_dracut() -> Result<()> {
+    no_need_to_run_info("dracut", true);
+
+    Ok(())
+}
+
+/// Run cryptsetup with the passphrase on its standard input, which is never logged
+fn cryptsetup_with_passphrase(args: &[&OsStr], passphrase: &str) -> Result<()> {
+    debug!("Running cryptsetup {args:?}");
+    let mut child = Command::new("cryptsetup")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(passphrase.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Run cryptsetup {:?} failed!\n\n{}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+/// Encrypt `partition` with LUKS and open it, returns the opened device to be formatted
+/// and installed to, and the UUID of the LUKS header
+pub fn encrypt_partition(partition: &Partition, passphrase: &str) -> Result<(Partition, String)> {
+    let path = partition
+        .path
+        .as_ref()
+        .ok_or_else(|| anyhow!("Installer failed to determine user-specified partition."))?;
+    // GRUB reads /boot from the encrypted partition, and can not unlock Argon2 keyslots
+    cryptsetup_with_passphrase(
+        &[
+            "luksFormat".as_ref(),
+            "--type".as_ref(),
+            "luks2".as_ref(),
+            "--pbkdf".as_ref(),
+            "pbkdf2".as_ref(),
+            "--batch-mode".as_ref(),
+            "--key-file".as_ref(),
+            "-".as_ref(),
+            path.as_os_str(),
+        ],
+        passphrase,
+    )?;
+    cryptsetup_with_passphrase(
+        &[
+            "open".as_ref(),
+            "--key-file".as_ref(),
+            "-".as_ref(),
+            path.as_os_str(),
+            LUKS_MAPPER_NAME.as_ref(),
+        ],
+        passphrase,
+    )?;
+
+    let uuid = luks_uuid(path)?;
+
+    Ok((
+        Partition {
+            path: Some(Path::new("/dev/mapper").join(LUKS_MAPPER_NAME)),
+            ..partition.clone()
+        },
+        uuid,
+    ))
+}
+
+/// The UUID of the LUKS header on `path`
+fn luks_uuid(path: &Path) -> Result<String> {
+    let output = Command::new("cryptsetup")
+        .arg("luksUUID")
+        .arg(path)
+        .output()?;
+    let uuid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || uuid.is_empty() {
+        return Err(anyhow!(
+            "Installer failed to read the UUID of the encrypted partition {}.",
+            path.display()
+        ));
+    }
+
+    Ok(uuid)
+}
+
+/// The device mapper name of a partition encrypted with a keyfile, e.g. aosc-data-var-log
+/// for /var/log
+pub fn data_mapper_name(mount_point: &str) -> String {
+    format!(
+        "{DATA_MAPPER_PREFIX}-{}",
+        mount_point.trim_matches('/').replace('/', "-")
+    )
+}
+
+/// The keyfile unlocking the partition opened as `name`, relative to the root
+fn keyfile_path(name: &str) -> PathBuf {
+    Path::new(CRYPTSETUP_KEYS_DIR).join(format!("{name}.key"))
+}
+
+/// Open `partition` as `name` with its keyfile kept on the system partition mounted at
+/// `root`, the partition is encrypted with a new random keyfile first if `format` is set
+///
+/// Returns the opened device to be formatted and mounted.
+pub fn encrypt_data_partition(
+    partition: &Partition,
+    root: &Path,
+    name: &str,
+    format: bool,
+) -> Result<Partition> {
+    let path = partition
+        .path
+        .as_ref()
+        .ok_or_else(|| anyhow!("Installer failed to determine user-specified partition."))?;
+    let keyfile = root.join(keyfile_path(name).strip_prefix("/")?);
+    if format {
+        let keys_dir = keyfile.parent().unwrap();
+        std::fs::create_dir_all(keys_dir)?;
+        std::fs::set_permissions(keys_dir, std::fs::Permissions::from_mode(0o700))?;
+        let mut key = [0u8; KEYFILE_SIZE];
+        thread_rng().fill(&mut key[..]);
+        // only readable by root from the start
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&keyfile)?
+            .write_all(&key)?;
+        run_command(
+            "cryptsetup",
+            [
+                "luksFormat".as_ref(),
+                "--type".as_ref(),
+                "luks2".as_ref(),
+                "--batch-mode".as_ref(),
+                "--key-file".as_ref(),
+                keyfile.as_os_str(),
+                path.as_os_str(),
+            ],
+        )?;
+    }
+    run_command(
+        "cryptsetup",
+        [
+            "open".as_ref(),
+            "--key-file".as_ref(),
+            keyfile.as_os_str(),
+            path.as_os_str(),
+            OsStr::new(name),
+        ],
+    )?;
+
+    Ok(Partition {
+        path: Some(Path::new("/dev/mapper").join(name)),
+        ..partition.clone()
+    })
+}
+
+/// A partition unlocked on boot with its keyfile on the encrypted system partition
+pub struct KeyfileVolume {
+    /// The device mapper name, see `data_mapper_name`
+    pub name: String,
+    /// The encrypted partition
+    pub device: PathBuf,
+}
+
+/// The crypttab of the system partition with the LUKS header `uuid`, and of the other
+/// partitions (their device mapper names and LUKS header UUIDs) unlocked with keyfiles,
+/// `discard` passes TRIM through to the drives
+fn crypttab_with(uuid: &str, data: &[(String, String)], discard: bool) -> String {
+    let options = if discard { "luks,discard" } else { "luks" };
+    let mut crypttab = format!(
+        "# Generated by AOSC OS Installer\n{LUKS_MAPPER_NAME} UUID={uuid} none {options}\n"
+    );
+    for (name, uuid) in data {
+        crypttab.push_str(&format!(
+            "{name} UUID={uuid} {} {options}\n",
+            keyfile_path(name).display()
+        ));
+    }
+
+    crypttab
+}
+
+/// Unlock the encrypted system partition (with the LUKS header `uuid`) on boot, in GRUB
+/// for /boot and in the initramfs for the root filesystem, and then the `data`
+/// partitions with their keyfiles
+/// Must be used in a chroot context, before generating the initramfs
+pub fn set_up_encryption(uuid: &str, data: &[KeyfileVolume], discard: bool) -> Result<()> {
+    let data = data
+        .iter()
+        .map(|x| Ok((x.name.clone(), luks_uuid(&x.device)?)))
+        .collect::<Result<Vec<_>>>()?;
+    std::fs::write(CRYPTTAB_PATH, crypttab_with(uuid, &data, discard))?;
+    std::fs::create_dir_all("/etc/dracut.conf.d")?;
+    std::fs::write(
+        CRYPT_DRACUT_CONF_PATH,
+        "# Generated by AOSC OS Installer\nadd_dracutmodules+=\" crypt \"\n",
+    )?;
+    let grub_default = std::fs::read_to_string(GRUB_DEFAULT_PATH).unwrap_or_default();
+    std::fs::write(
+        GRUB_DEFAULT_PATH,
+        grub_default_with(&grub_default, "GRUB_ENABLE_CRYPTODISK", |_| "y".to_string()),
+    )?;
+
+    Ok(())
+}
+
+/// Close the encrypted partitions opened by `encrypt_data_partition`, and then the system
+/// partition opened by `encrypt_partition`, if they are open
+pub fn close_encrypted_partition() {
+    let mut names = std::fs::read_dir("/dev/mapper")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .filter(|x| x.starts_with(DATA_MAPPER_PREFIX))
+        .collect::<Vec<_>>();
+    if Path::new("/dev/mapper").join(LUKS_MAPPER_NAME).exists() {
+        names.push(LUKS_MAPPER_NAME.to_string());
+    }
+    for name in names {
+        if let Err(e) = run_command("cryptsetup", ["close", name.as_str()]) {
+            warn!("Failed to close the encrypted partition {}: {}", name, e);
+        }
+    }
+}
+
+/// Whether the module is in the dracut module listing of `lsinitrd --mod`
+fn has_dracut_module(listing: &str, module: &str) -> bool {
+    listing.lines().any(|x| x.trim() == module)
+}
+
+/// Check that the initramfs of every installed kernel includes the dracut `module`, e.g.
+/// crypt to unlock the encrypted system partition
+/// Must be used in a chroot context, after generating the initramfs
+#[cfg(not(feature = "is_retro"))]
+pub fn check_initramfs_module(module: &str) -> Result<()> {
+    let mut kernels = std::fs::read_dir("/usr/lib/modules")?
+        .flatten()
+        .filter(|x| x.path().is_dir())
+        .map(|x| x.file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    kernels.sort();
+    if kernels.is_empty() {
+        return Err(anyhow!(
+            "Installer could not find any kernel in the installed system."
+        ));
+    }
+    for kernel in kernels {
+        let output = Command::new("lsinitrd")
+            .args(["--kver", &kernel, "--mod"])
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Installer failed to list the dracut modules in the initramfs of kernel {}.\n\n{}",
+                kernel,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        if !has_dracut_module(&String::from_utf8_lossy(&output.stdout), module) {
+            return Err(anyhow!(
+                "The initramfs of kernel {} does not include the {} dracut module, the system would not boot.",
+                kernel,
+                module
+            ));
+        }
+        info!("The initramfs of kernel {} includes {}", kernel, module);
+    }
+
+    Ok(())
+}
+
+/// Check the dracut module of the initramfs (dummy function for retro mode)
+/// Must be used in a chroot context
+#[cfg(feature = "is_retro")]
+pub fn check_initramfs_module(_module: &str) -> Result<()> {
+    no_need_to_run_info("lsinitrd", true);
+
+    Ok(())
+}
+
+/// Runs ssh-keygen -A (dummy function for non-retro mode)
+/// Must be used in a chroot context
+#[cfg(not(feature = "is_retro"))]
+pub fn gen_ssh_key() -> Result<()> {
+    no_need_to_run_info("ssh-keygen", false);
+
+    Ok(())
+}
+
+/// Runs ssh-keygen -A
+/// Must be used in a chroot context
+#[cfg(feature = "is_retro")]
+pub fn gen_ssh_key() -> Result<()> {
+    run_command("ssh-keygen", &["-A"])?;
+
+    Ok(())
+}
+
+/// Sets hostname in the guest environment
+/// Must be used in a chroot context
+pub fn set_hostname(name: &str) -> Result<()> {
+    let mut f = File::create("/etc/hostname")?;
+    f.write_all(format!("{name}\n").as_bytes())?;
+
+    let hosts = std::fs::read_to_string("/etc/hosts").unwrap_or_default();
+    std::fs::write("/etc/hosts", hosts_with_hostname(&hosts, name))?;
+
+    Ok(())
+}
+
+/// Reset the machine ID of the guest, so that machines installed from the same
+/// tarball do not share one: either leave it empty for systemd to generate on
+/// first boot, or generate it right away
+/// Must be used in a chroot context
+pub fn reset_machine_id(generate: bool) -> Result<()> {
+    std::fs::write(MACHINE_ID_PATH, "")?;
+    if generate {
+        run_command("systemd-machine-id-setup", &[] as &[&str])?;
+    }
+
+    // D-Bus falls back to /etc/machine-id when its own copy is absent
+    match std::fs::remove_file(DBUS_MACHINE_ID_PATH) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Write the marker telling first-boot tooling (e.g. OOBE) that the system is pristine,
+/// it is up to that tooling to remove the marker
+/// Must be used in a chroot context
+pub fn write_first_boot_marker() -> Result<()> {
+    let marker = Path::new(FIRST_BOOT_MARKER_PATH);
+    if let Some(parent) = marker.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(
+        marker,
+        format!("AOSC DeployKit/{}\n", env!("CARGO_PKG_VERSION")),
+    )?;
+
+    Ok(())
+}
+
+/// Let the end user create their account on first boot, through the OOBE shipped in the
+/// system at `root`, or the simple prompt on tty1 bundled with the installer if there is none
+/// Returns the unit enabled
+/// Must be used outside of the chroot context
+pub fn setup_oobe(root: &Path) -> Result<&'static str> {
+    if let Some(unit) = OOBE_UNITS.iter().find(|x| unit_exists(root, x)) {
+        systemctl_enable(root, unit)?;
+        return Ok(unit);
+    }
+
+    let script = root.join(&FIRST_BOOT_SCRIPT_PATH[1..]);
+    if let Some(parent) = script.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let group_file = std::fs::read_to_string(root.join("etc/group"))?;
+    std::fs::write(&script, first_boot_script(&group_file))?;
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+    std::fs::write(
+        root.join("etc/systemd/system").join(FIRST_BOOT_UNIT),
+        FIRST_BOOT_UNIT_FILE,
+    )?;
+    systemctl_enable(root, FIRST_BOOT_UNIT)?;
+
+    Ok(FIRST_BOOT_UNIT)
+}
+
+/// The first boot prompt, which joins the default groups existing in `group_file`
+/// (/etc/group of the installed system), as useradd fails on any missing one
+fn first_boot_script(group_file: &str) -> String {
+    let existing = group_file
+        .lines()
+        .filter_map(|x| x.split(':').next())
+        .collect::<Vec<_>>();
+    let (groups, skipped): (Vec<_>, Vec<_>) = DEFAULT_USER_GROUPS
+        .iter()
+        .partition(|x| existing.contains(*x));
+    for group in skipped {
+        info!(
+            "Skipping group {} as the system release does not have it",
+            group
+        );
+    }
+
+    FIRST_BOOT_SCRIPT.replace("@GROUPS@", &groups.join(","))
+}
+
+/// Add (or replace) the `127.0.1.1` entry for the hostname, keeping the other entries intact
+fn hosts_with_hostname(hosts: &str, name: &str) -> String {
+    let mut s = String::new();
+    let mut replaced = false;
+
+    for line in hosts.lines() {
+        if line.split_whitespace().next() == Some("127.0.1.1") {
+            if !replaced {
+                s.push_str(&format!("127.0.1.1\t{name}\n"));
+                replaced = true;
+            }
+            continue;
+        }
+        s.push_str(line);
+        s.push('\n');
+    }
+
+    if !replaced {
+        s.push_str(&format!("127.0.1.1\t{name}\n"));
+    }
+
+    s
+}
+
+/// Generate a default hostname like `aosc-workstation-x7k2`
+pub fn gen_default_hostname(variant: &str) -> String {
+    let mut rng = thread_rng();
+    let variant = variant
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>();
+    let variant = variant.trim_matches('-');
+    let suffix = (0..4)
+        .map(|_| {
+            let c = rng.gen_range(0..36);
+            std::char::from_digit(c, 36).unwrap()
+        })
+        .collect::<String>();
+
+    if variant.is_empty() {
+        format!("aosc-{suffix}")
+    } else {
+        format!("aosc-{variant}-{suffix}")
+    }
+}
+
+/// Sets locale in the guest environment
+/// Must be used in a chroot context
+pub fn set_locale(locale: &str) -> Result<()> {
+    let mut f = File::create("/etc/locale.conf")?;
+    f.write_all(b"LANG=")?;
+
+    Ok(f.write_all(format!("{locale}\n").as_bytes())?)
+}
+
+/// Uncomment (or append) the given locales in `/etc/locale.gen` and run locale-gen
+/// Must be used in a chroot context
+pub fn generate_locales(locales: &[String]) -> Result<()> {
+    let supported = get_supported_locales(Path::new("/")).unwrap_or_default();
+    if !supported.is_empty() {
+        for locale in locales {
+            check_locale(locale, &supported)?;
+        }
+    }
+
+    let locales = locales
+        .iter()
+        .filter(|x| !BUILTIN_LOCALES.contains(&x.as_str()))
+        .collect::<Vec<_>>();
+
+    if locales.is_empty() || !Path::new("/etc/locale.gen").exists() {
+        info!("No need to run locale-gen");
+        return Ok(());
+    }
+
+    let locale_gen = std::fs::read_to_string("/etc/locale.gen")?;
+    std::fs::write("/etc/locale.gen", locale_gen_with(&locale_gen, &locales))?;
+
+    run_command_logged("locale-gen", &[] as &[&str])?;
+
+    Ok(())
+}
+
+fn locale_gen_with(locale_gen: &str, locales: &[&String]) -> String {
+    let mut s = String::new();
+    let mut found = vec![false; locales.len()];
+
+    for line in locale_gen.lines() {
+        let entry = line.trim_start_matches('#').trim_start();
+        let name = entry.split_whitespace().next();
+        if let Some(index) = locales.iter().position(|x| Some(x.as_str()) == name) {
+            if !found[index] {
+                found[index] = true;
+                s.push_str(entry);
+                s.push('\n');
+                continue;
+            }
+        }
+        s.push_str(line);
+        s.push('\n');
+    }
+
+    for (locale, found) in locales.iter().zip(found) {
+        if !found {
+            let charset = locale.split('.').nth(1).unwrap_or("UTF-8");
+            s.push_str(&format!("{locale} {charset}\n"));
+        }
+    }
+
+    s
+}
+
+/// A keyboard layout (with an optional variant) from the XKB rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyboardLayout {
+    pub layout: String,
+    pub variant: Option<String>,
+    pub description: String,
+}
+
+impl KeyboardLayout {
+    /// The keymap name, `<layout>` or `<layout>-<variant>`
+    pub fn keymap(&self) -> String {
+        match self.variant.as_ref() {
+            Some(variant) => format!("{}-{}", self.layout, variant),
+            None => self.layout.clone(),
+        }
+    }
+}
+
+/// Get the keyboard layouts and their variants from the XKB data in the system at `root`
+pub fn get_keyboard_layouts(root: &Path) -> Result<Vec<KeyboardLayout>> {
+    let data = std::fs::read_to_string(root.join(&XKB_RULES_PATH[1..]))
+        .context("Installer failed to gather keyboard layouts.")?;
+
+    Ok(parse_xkb_rules(&data))
+}
+
+fn parse_xkb_rules(data: &str) -> Vec<KeyboardLayout> {
+    let mut layouts: Vec<KeyboardLayout> = vec![];
+    let mut variants = vec![];
+    let mut section = "";
+    for line in data.lines() {
+        if let Some(name) = line.strip_prefix('!') {
+            section = name.trim();
+            continue;
+        }
+
+        let mut fields = line.trim().splitn(2, char::is_whitespace);
+        let (name, description) = match (fields.next(), fields.next()) {
+            (Some(name), Some(description)) if !name.is_empty() => (name, description.trim()),
+            _ => continue,
+        };
+
+        match section {
+            "layout" => layouts.push(KeyboardLayout {
+                layout: name.to_string(),
+                variant: None,
+                description: description.to_string(),
+            }),
+            // e.g. `dvorak          us: English (Dvorak)`
+            "variant" => {
+                if let Some((layout, description)) = description.split_once(':') {
+                    variants.push(KeyboardLayout {
+                        layout: layout.to_string(),
+                        variant: Some(name.to_string()),
+                        description: description.trim().to_string(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // put the variants right after their layouts
+    let mut result = vec![];
+    for layout in layouts {
+        let layout_name = layout.layout.clone();
+        result.push(layout);
+        result.extend(variants.iter().filter(|x| x.layout == layout_name).cloned());
+    }
+
+    result
+}
+
+/// Find the keyboard layout by its keymap name
+pub fn check_keymap(keymap: &str, layouts: &[KeyboardLayout]) -> Result<KeyboardLayout> {
+    if let Some(layout) = layouts.iter().find(|x| x.keymap() == keymap) {
+        return Ok(layout.clone());
+    }
+
+    let keymaps = layouts.iter().map(|x| x.keymap()).collect::<Vec<_>>();
+    let suggestions = suggest_similar(keymap, &keymaps);
+    if suggestions.is_empty() {
+        bail!("Keymap {} is not supported.", keymap);
+    }
+
+    bail!(
+        "Keymap {} is not supported. Did you mean: {}?",
+        keymap,
+        suggestions.join(", ")
+    )
+}
+
+/// Set the keyboard layout for both the console and X11 in the guest environment
+/// Must be used in a chroot context
+pub fn set_keymap(layout: &KeyboardLayout) -> Result<()> {
+    let vconsole = std::fs::read_to_string(VCONSOLE_CONF_PATH).unwrap_or_default();
+    let keymap = layout.keymap();
+    // the console keymaps do not always share the names of the XKB layouts
+    let keymap = if console_keymap_exists(Path::new(KBD_KEYMAPS_PATH), &keymap) {
+        Some(keymap)
+    } else {
+        info!("No console keymap named {}, leaving it to XKB", keymap);
+        None
+    };
+    std::fs::write(
+        VCONSOLE_CONF_PATH,
+        vconsole_conf_with(&vconsole, keymap.as_deref(), layout),
+    )?;
+
+    let x11_conf = Path::new(X11_KEYBOARD_CONF_PATH);
+    if let Some(parent) = x11_conf.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(x11_conf, x11_keyboard_conf(layout))?;
+
+    Ok(())
+}
+
+fn console_keymap_exists(dir: &Path, keymap: &str) -> bool {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    entries.flatten().any(|x| {
+        let path = x.path();
+        if path.is_dir() {
+            return console_keymap_exists(&path, keymap);
+        }
+
+        path.file_name()
+            .and_then(|x| x.to_str())
+            .map(|x| x == format!("{keymap}.map") || x == format!("{keymap}.map.gz"))
+            .unwrap_or(false)
+    })
+}
+
+fn vconsole_conf_with(vconsole: &str, keymap: Option<&str>, layout: &KeyboardLayout) -> String {
+    const KEYS: &[&str] = &["KEYMAP", "XKBLAYOUT", "XKBVARIANT"];
+
+    let mut s = String::new();
+    for line in vconsole.lines() {
+        let key = line.split('=').next().unwrap_or_default().trim();
+        if !KEYS.contains(&key) {
+            s.push_str(line);
+            s.push('\n');
+        }
+    }
+
+    if let Some(keymap) = keymap {
+        s.push_str(&format!("KEYMAP={keymap}\n"));
+    }
+    s.push_str(&format!("XKBLAYOUT={}\n", layout.layout));
+    if let Some(variant) = layout.variant.as_ref() {
+        s.push_str(&format!("XKBVARIANT={variant}\n"));
+    }
+
+    s
+}
+
+/// Whether the live system has a console keymap for the keyboard layout
+pub fn has_console_keymap(layout: &KeyboardLayout) -> bool {
+    console_keymap_exists(Path::new(KBD_KEYMAPS_PATH), &layout.keymap())
+}
+
+/// Load the keymap to the console of the live system, or the one it was booted with if
+/// `keymap` is `None`
+pub fn load_console_keymap(keymap: Option<&str>) -> Result<()> {
+    let live_keymap = std::fs::read_to_string(VCONSOLE_CONF_PATH)
+        .ok()
+        .and_then(|x| vconsole_keymap(&x));
+    match keymap.or(live_keymap.as_deref()) {
+        Some(keymap) => run_command("loadkeys", [keymap]),
+        None => run_command("loadkeys", ["--default"]),
+    }
+}
+
+fn vconsole_keymap(vconsole: &str) -> Option<String> {
+    vconsole
+        .lines()
+        .filter_map(|x| x.trim().strip_prefix("KEYMAP="))
+        .map(|x| x.trim_matches('"').to_string())
+        .last()
+        .filter(|x| !x.is_empty())
+}
+
+/// Find the resolution of the largest connected display, from the preferred (first) mode
+/// of the DRM connectors
+pub fn detect_display_resolution() -> Option<(u32, u32)> {
+    std::fs::read_dir(DRM_PATH)
+        .ok()?
+        .flatten()
+        .filter(|x| {
+            std::fs::read_to_string(x.path().join("status"))
+                .map(|x| x.trim() == "connected")
+                .unwrap_or(false)
+        })
+        .filter_map(|x| {
+            let modes = std::fs::read_to_string(x.path().join("modes")).ok()?;
+            parse_drm_mode(modes.lines().next()?)
+        })
+        .max_by_key(|(width, height)| width * height)
+}
+
+/// Parse a DRM mode, e.g., `3840x2160` or `1920x1080i`
+fn parse_drm_mode(mode: &str) -> Option<(u32, u32)> {
+    let (width, height) = mode.trim().split_once('x')?;
+    let height = height.trim_end_matches(|c: char| !c.is_ascii_digit());
+
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+pub fn is_hidpi_resolution(resolution: (u32, u32)) -> bool {
+    resolution.1 >= HIDPI_MIN_HEIGHT
+}
+
+pub fn is_valid_console_font(font: &str) -> bool {
+    !font.is_empty()
+        && font
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, '-' | '_' | '.'))
+}
+
+pub fn console_font_exists(root: &Path, font: &str) -> bool {
+    let dir = root.join(&CONSOLE_FONTS_PATH[1..]);
+    ["psf", "psfu", "psf.gz", "psfu.gz"]
+        .iter()
+        .any(|x| dir.join(format!("{font}.{x}")).is_file())
+}
+
+/// Set the console font in vconsole.conf
+/// Must be used in a chroot context
+pub fn set_console_font(font: &str) -> Result<()> {
+    let vconsole = std::fs::read_to_string(VCONSOLE_CONF_PATH).unwrap_or_default();
+    std::fs::write(VCONSOLE_CONF_PATH, vconsole_conf_with_font(&vconsole, font))?;
+
+    Ok(())
+}
+
+fn vconsole_conf_with_font(vconsole: &str, font: &str) -> String {
+    let mut s = vconsole
+        .lines()
+        .filter(|x| x.split('=').next().unwrap_or_default().trim() != "FONT")
+        .map(|x| format!("{x}\n"))
+        .collect::<String>();
+    s.push_str(&format!("FONT={font}\n"));
+
+    s
+}
+
+/// Whether `mode` is a valid GRUB_GFXMODE, e.g., `1280x720,auto` or `1024x768x32`
+pub fn is_valid_grub_gfxmode(mode: &str) -> bool {
+    mode.split(',').all(|x| {
+        x == "auto" || {
+            let parts = x.split('x').collect::<Vec<_>>();
+            (2..=3).contains(&parts.len())
+                && parts
+                    .iter()
+                    .all(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit()))
+        }
+    })
+}
+
+fn x11_keyboard_conf(layout: &KeyboardLayout) -> String {
+    let mut s = String::from(
+        "Section \"InputClass\"\n        Identifier \"system-keyboard\"\n        MatchIsKeyboard \"on\"\n",
+    );
+    s.push_str(&format!(
+        "        Option \"XkbLayout\" \"{}\"\n",
+        layout.layout
+    ));
+    if let Some(variant) = layout.variant.as_ref() {
+        s.push_str(&format!("        Option \"XkbVariant\" \"{variant}\"\n"));
+    }
+    s.push_str("EndSection\n");
+
+    s
+}
+
+/// Sets zoneinfo in the guest environment
+/// Must be used in a chroot context
+pub fn set_zoneinfo(zone: &str) -> Result<()> {
+    if !Path::new(ZONEINFO_PATH).join(zone).is_file() {
+        let zoneinfo_list = get_zoneinfo_list().unwrap_or_default();
+        check_timezone(zone, &zoneinfo_list)?;
+        bail!(
+            "Timezone {} is not available in the installed system.",
+            zone
+        );
+    }
+
+    if Path::new("/etc/localtime").is_symlink() || Path::new("/etc/localtime").exists() {
+        std::fs::remove_file("/etc/localtime")?;
+    }
+
+    std::os::unix::fs::symlink(format!("{ZONEINFO_PATH}/{zone}"), "/etc/localtime")?;
+
+    Ok(())
+}
+
+/// Read the timezone of the live system from `/etc/localtime`
+pub fn read_timezone() -> Option<String> {
+    let link = std::fs::read_link("/etc/localtime").ok()?;
+    let link = link.to_str()?;
+    let zone = link.split_once("zoneinfo/")?.1;
+    let zone = zone.strip_prefix("posix/").unwrap_or(zone);
+
+    Some(zone.to_string())
+}
+
+/// Match the timezone against the list, ignoring case
+///
+/// Returns the canonical name of the timezone, or an error with the closest matches.
+pub fn check_timezone(zone: &str, zoneinfo_list: &[String]) -> Result<String> {
+    if let Some(zone) = zoneinfo_list.iter().find(|x| x.eq_ignore_ascii_case(zone)) {
+        return Ok(zone.to_string());
+    }
+
+    let suggestions = suggest_similar(zone, zoneinfo_list);
+    if suggestions.is_empty() {
+        bail!(
+            "Timezone {} is not valid. Please refer to the `aoscdk-rs list-timezone` output for a list of available timezones.",
+            zone
+        );
+    }
+
+    bail!(
+        "Timezone {} is not valid. Did you mean: {}?",
+        zone,
+        suggestions.join(", ")
+    )
+}
+
+/// Sets utc/rtc time in the guest environment
+/// Must be used in a chroot context
+pub fn set_hwclock_tc(utc: bool) -> Result<()> {
+    let adjtime = std::fs::read_to_string("/etc/adjtime").unwrap_or_default();
+    info!("Hardware clock is {}", if utc { "UTC" } else { "LOCAL" });
+    std::fs::write("/etc/adjtime", adjtime_with(&adjtime, utc))?;
+
+    Ok(())
+}
+
+fn adjtime_with(adjtime: &str, utc: bool) -> String {
+    let mut lines = adjtime.lines();
+    let drift = lines.next().unwrap_or("0.0 0 0.0");
+    let last_calibration = lines.next().unwrap_or("0");
+    let mode = if utc { "UTC" } else { "LOCAL" };
+
+    format!("{drift}\n{last_calibration}\n{mode}\n")
+}
+
+/// Check whether the systemd unit is shipped in the system at `root`
+pub fn unit_exists(root: &Path, unit: &str) -> bool {
+    SYSTEMD_UNIT_PATHS
+        .iter()
+        .any(|x| root.join(&x[1..]).join(unit).exists())
+}
+
+/// List the systemd units shipped in the system at `root`
+pub fn list_units(root: &Path) -> Vec<String> {
+    let mut units = SYSTEMD_UNIT_PATHS
+        .iter()
+        .filter_map(|x| std::fs::read_dir(root.join(&x[1..])).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|x| x.file_name().to_str().map(|x| x.to_string()))
+        .filter(|x| x.contains('.'))
+        .collect::<Vec<_>>();
+    units.sort();
+    units.dedup();
+
+    units
+}
+
+/// Complete the unit name with `.service` if it has no type suffix, like systemctl does
+pub fn unit_name(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!("{name}.service")
+    }
+}
+
+/// Query whether the systemd unit is enabled in the system at `root`, e.g. `enabled`
+pub fn systemctl_is_enabled(root: &Path, unit: &str) -> Result<String> {
+    let root = format!("--root={}", root.display());
+    // is-enabled exits with non-zero for disabled units, only the output matters
+    let output = Command::new("systemctl")
+        .args(["is-enabled", &root, unit])
+        .output()?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Enable the systemd unit in the system at `root`
+/// Must be used outside of the chroot context
+pub fn systemctl_enable(root: &Path, unit: &str) -> Result<()> {
+    let root = format!("--root={}", root.display());
+    run_command("systemctl", ["enable", &root, unit])?;
+
+    Ok(())
+}
+
+/// Disable the systemd unit in the system at `root`
+/// Must be used outside of the chroot context
+pub fn systemctl_disable(root: &Path, unit: &str) -> Result<()> {
+    let root = format!("--root={}", root.display());
+    run_command("systemctl", ["disable", &root, unit])?;
+
+    Ok(())
+}
+
+/// Mask the systemd unit in the system at `root`
+/// Must be used outside of the chroot context
+pub fn systemctl_mask(root: &Path, unit: &str) -> Result<()> {
+    let root = format!("--root={}", root.display());
+    run_command("systemctl", ["mask", &root, unit])?;
+
+    Ok(())
+}
+
+/// Unmask the systemd unit in the system at `root`
+/// Must be used outside of the chroot context
+pub fn systemctl_unmask(root: &Path, unit: &str) -> Result<()> {
+    let root = format!("--root={}", root.display());
+    run_command("systemctl", ["unmask", &root, unit])?;
+
+    Ok(())
+}
+
+/// Set the default systemd target of the system at `root`
+/// Must be used outside of the chroot context
+pub fn set_default_target(root: &Path, target: &str) -> Result<()> {
+    let root = format!("--root={}", root.display());
+    run_command("systemctl", ["set-default", &root, target])?;
+
+    Ok(())
+}
+
+/// Find the display manager shipped in the system at `root`
+pub fn find_display_manager(root: &Path) -> Option<&'static str> {
+    DISPLAY_MANAGER_UNITS
+        .iter()
+        .find(|x| unit_exists(root, x))
+        .copied()
+}
+
+/// List the login shells in /etc/shells of the system at `root` that are actually shipped
+pub fn list_shells(root: &Path) -> Result<Vec<String>> {
+    let shells = std::fs::read_to_string(root.join(&SHELLS_PATH[1..]))?;
+
+    Ok(parse_shells(&shells)
+        .into_iter()
+        .filter(|x| root.join(&x[1..]).is_file())
+        .collect())
+}
+
+/// The login shells the variant is known to ship, only bash and sh for an unknown variant
+pub fn variant_shells(variant: &str) -> Vec<String> {
+    VARIANT_SHELLS
+        .iter()
+        .find(|(name, _)| *name == variant)
+        .map(|(_, shells)| *shells)
+        .unwrap_or(&[DEFAULT_SHELL, "/bin/sh"])
+        .iter()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// A custom login shell has to be an absolute path fitting in /etc/passwd
+pub fn is_valid_shell_path(shell: &str) -> bool {
+    shell.len() > 1
+        && shell.starts_with('/')
+        && !shell.ends_with('/')
+        && !shell.contains(|c: char| c == ':' || c.is_whitespace() || c.is_control())
+}
+
+fn parse_shells(shells: &str) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    for line in shells.lines() {
+        let line = line.trim();
+        if line.starts_with('/') && !result.iter().any(|x| x == line) {
+            result.push(line.to_string());
+        }
+    }
+
+    result
+}
+
+/// Change the login shell of the user, returns false (leaving the shell as is)
+/// if the shell is not available in the guest environment
+/// Must be used in a chroot context
+pub fn set_login_shell(user: &str, shell: &str) -> Result<bool> {
+    let root = Path::new("/");
+    if list_shells(root)?.iter().any(|x| x == shell) {
+        run_command("chsh", ["-s", shell, user])?;
+        return Ok(true);
+    }
+
+    // a custom shell may be missing from /etc/shells, which chsh insists on
+    let executable = std::fs::metadata(root.join(&shell[1..]))
+        .map(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false);
+    if executable {
+        warn!("Shell {} is not listed in {}", shell, SHELLS_PATH);
+        run_command("usermod", ["-s", shell, user])?;
+        return Ok(true);
+    }
+
+    warn!(
+        "Shell {} is not available in the installed system, keeping {}",
+        shell, DEFAULT_SHELL
+    );
+
+    Ok(false)
+}
+
+/// List the normal (non-system) users in the system at `root`
+pub fn list_normal_users(root: &Path) -> Result<Vec<String>> {
+    let passwd = std::fs::read_to_string(root.join("etc/passwd"))?;
+
+    Ok(normal_users(&passwd))
+}
+
+fn normal_users(passwd: &str) -> Vec<String> {
+    passwd
+        .lines()
+        .map(|x| x.split(':').collect::<Vec<_>>())
+        .filter(|x| {
+            x.get(2)
+                .and_then(|x| x.parse::<u32>().ok())
+                .map(|x| (MIN_USER_UID..MAX_USER_UID).contains(&x))
+                .unwrap_or(false)
+        })
+        .map(|x| x[0].to_string())
+        .collect()
+}
+
+/// Log the user in automatically on boot, through the display manager if one is given,
+/// or on tty1 otherwise. Returns the written file
+/// Must be used outside of the chroot context
+pub fn set_autologin(root: &Path, user: &str, display_manager: Option<&str>) -> Result<PathBuf> {
+    let users = list_normal_users(root)?;
+    if users.len() != 1 || users[0] != user {
+        bail!(
+            "Autologin requires {} to be the only user, but found: {}",
+            user,
+            users.join(", ")
+        );
+    }
+
+    let (path, content) = match display_manager {
+        Some("sddm.service") => {
+            let session = find_desktop_session(root)
+                .map(|x| format!("Session={x}\n"))
+                .unwrap_or_default();
+            (
+                root.join("etc/sddm.conf.d/10-deploykit-autologin.conf"),
+                format!("[Autologin]\nUser={user}\n{session}"),
+            )
+        }
+        Some("lightdm.service") => (
+            root.join("etc/lightdm/lightdm.conf.d/10-deploykit-autologin.conf"),
+            format!("[Seat:*]\nautologin-user={user}\n"),
+        ),
+        Some("gdm.service") => {
+            let path = root.join("etc/gdm/custom.conf");
+            let conf = std::fs::read_to_string(&path).unwrap_or_default();
+            let conf = gdm_conf_with_autologin(&conf, user);
+            (path, conf)
+        }
+        _ => (
+            root.join("etc/systemd/system/getty@tty1.service.d/10-deploykit-autologin.conf"),
+            format!("[Service]\nExecStart=\nExecStart=-/sbin/agetty -o '-p -f -- \\\\u' --noclear --autologin {user} %I $TERM\n"),
+        ),
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, content)?;
+
+    Ok(path)
+}
+
+/// Find the name of a desktop session (e.g. `plasma`) shipped in the system at `root`
+fn find_desktop_session(root: &Path) -> Option<String> {
+    ["usr/share/wayland-sessions", "usr/share/xsessions"]
+        .iter()
+        .filter_map(|x| std::fs::read_dir(root.join(x)).ok())
+        .flat_map(|x| x.flatten())
+        .map(|x| x.path())
+        .filter(|x| x.extension() == Some(OsStr::new("desktop")))
+        .find_map(|x| {
+            x.file_stem()
+                .and_then(|x| x.to_str())
+                .map(|x| x.to_string())
+        })
+}
+
+/// Set the autologin options in the `[daemon]` section of GDM's custom.conf
+fn gdm_conf_with_autologin(conf: &str, user: &str) -> String {
+    let options = format!("AutomaticLoginEnable=True\nAutomaticLogin={user}\n");
+    let mut s = String::new();
+    let mut in_daemon = false;
+    let mut written = false;
+    for line in conf.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_daemon = trimmed == "[daemon]";
+            s.push_str(line);
+            s.push('\n');
+            if in_daemon && !written {
+                s.push_str(&options);
+                written = true;
+            }
+            continue;
+        }
+
+        if in_daemon
+            && (trimmed.starts_with("AutomaticLoginEnable=")
+                || trimmed.starts_with("AutomaticLogin="))
+        {
+            continue;
+        }
+        s.push_str(line);
+        s.push('\n');
+    }
+
+    if !written {
+        s.push_str(&format!("[daemon]\n{options}"));
+    }
+
+    s
+}
+
+/// The time synchronisation service shipped in the system at `root`, chronyd if both are
+pub fn ntp_unit(root: &Path) -> Option<&'static str> {
+    NTP_UNITS.iter().find(|x| unit_exists(root, x)).copied()
+}
+
+/// Enable time synchronisation in the system at `root`, returns the enabled unit
+/// Must be used outside of the chroot context
+pub fn enable_ntp(root: &Path, servers: &[String]) -> Result<Option<&'static str>> {
+    let unit = match ntp_unit(root) {
+        Some(unit) => unit,
+        None => {
+            info!("No time synchronisation service found, skipping");
+            return Ok(None);
+        }
+    };
+
+    if !servers.is_empty() {
+        if unit == "chronyd.service" {
+            let path = root.join("etc/chrony.conf");
+            let conf = std::fs::read_to_string(&path).unwrap_or_default();
+            std::fs::write(path, chrony_conf_with(&conf, servers))?;
+        } else {
+            let dir = root.join("etc/systemd/timesyncd.conf.d");
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(
+                dir.join("10-deploykit.conf"),
+                format!("[Time]\nNTP={}\n", servers.join(" ")),
+            )?;
+        }
+    }
+
+    systemctl_enable(root, unit)?;
+
+    Ok(Some(unit))
+}
+
+/// Replace the `server`/`pool` entries in chrony.conf with the given servers
+fn chrony_conf_with(conf: &str, servers: &[String]) -> String {
+    let mut s = String::new();
+    for line in conf.lines() {
+        let directive = line.split_whitespace().next();
+        if directive == Some("server") || directive == Some("pool") {
+            s.push_str(&format!("# {line}\n"));
+        } else {
+            s.push_str(line);
+            s.push('\n');
+        }
+    }
+
+    for server in servers {
+        s.push_str(&format!("server {server} iburst\n"));
+    }
+
+    s
+}
+
+/// Check whether the string is a valid Debian package name
+pub fn is_valid_package_name(name: &str) -> bool {
+    name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+}
+
+/// Point the APT sources of the guest to the package repository `repo_url`
+/// Must be used in a chroot context
+pub fn set_package_mirror(repo_url: &str) -> Result<()> {
+    let sources = std::fs::read_to_string(APT_SOURCES_PATH).unwrap_or_default();
+    std::fs::write(APT_SOURCES_PATH, apt_sources_with(&sources, repo_url))?;
+
+    Ok(())
+}
+
+fn apt_sources_with(sources: &str, repo_url: &str) -> String {
+    let debs = repo_url.trim_end_matches('/');
+    let mut s = String::new();
+    let mut replaced = false;
+    for line in sources.lines() {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        if fields.len() >= 3
+            && fields[0] == "deb"
+            && fields[1].trim_end_matches('/').ends_with("/debs")
+        {
+            s.push_str(&format!("deb {} {}\n", debs, fields[2..].join(" ")));
+            replaced = true;
+        } else {
+            s.push_str(line);
+            s.push('\n');
+        }
+    }
+
+    if !replaced {
+        s.push_str(&format!("deb {debs} stable main\n"));
+    }
+
+    s
+}
+
+/// Install the packages with oma (or apt-get if oma is not available),
+/// `on_line` is called with each line of the package manager output
+/// `resolv_conf` is the content of the host resolv.conf, used for name resolution in the guest
+/// Must be used in a chroot context
+pub fn install_packages<F: FnMut(&str)>(
+    packages: &[String],
+    resolv_conf: Option<&[u8]>,
+    mut on_line: F,
+) -> Result<()> {
+    with_resolv_conf(Path::new(RESOLV_CONF_PATH), resolv_conf, || {
+        let mut args = vec!["install", "-y"];
+        args.extend(packages.iter().map(|x| x.as_str()));
+        if Path::new("/usr/bin/oma").exists() {
+            run_command_with_output("oma", &args, &mut on_line)
+        } else {
+            run_command_with_output("apt-get", ["update"], &mut on_line)
+                .and_then(|_| run_command_with_output("apt-get", &args, &mut on_line))
+        }
+    })
+}
+
+/// Temporarily replace the resolv.conf at `resolv_conf_path` with `resolv_conf` while running `f`
+fn with_resolv_conf<T, F: FnOnce() -> Result<T>>(
+    resolv_conf_path: &Path,
+    resolv_conf: Option<&[u8]>,
+    f: F,
+) -> Result<T> {
+    // resolv.conf may be a symlink into /run, which is not available in the chroot
+    let old_link = std::fs::read_link(resolv_conf_path).ok();
+    let old_conf = if old_link.is_none() {
+        std::fs::read(resolv_conf_path).ok()
+    } else {
+        None
+    };
+    if let Some(resolv_conf) = resolv_conf {
+        if old_link.is_some() {
+            std::fs::remove_file(resolv_conf_path)?;
+        }
+        std::fs::write(resolv_conf_path, resolv_conf)?;
+    }
+
+    let result = f();
+
+    if resolv_conf.is_some() {
+        std::fs::remove_file(resolv_conf_path).ok();
+        if let Some(link) = old_link {
+            std::os::unix::fs::symlink(link, resolv_conf_path)?;
+        } else if let Some(conf) = old_conf {
+            std::fs::write(resolv_conf_path, conf)?;
+        }
+    }
+
+    result
+}
+
+/// Spawn an interactive shell chrooted into the system at `root` and wait for it to exit
+/// The bind mounts must have been set up, and this must be used outside of the chroot context
+pub fn open_guest_shell(root: &Path) -> Result<()> {
+    let resolv_conf = std::fs::read(RESOLV_CONF_PATH).ok();
+    with_resolv_conf(
+        &root.join(&RESOLV_CONF_PATH[1..]),
+        resolv_conf.as_deref(),
+        || {
+            info!("Opening a shell in {}", root.display());
+            let status = Command::new("chroot")
+                .arg(root)
+                .args([DEFAULT_SHELL, "--login"])
+                .status()?;
+            info!("Shell exited with {}", status);
+
+            Ok(())
+        },
+    )
+}
+
+/// Find the NVIDIA GPUs in the system, returns their PCI addresses and device IDs
+pub fn find_nvidia_gpus() -> Vec<String> {
+    let devices = match std::fs::read_dir(PCI_DEVICES_PATH) {
+        Ok(devices) => devices,
+        Err(_) => return vec![],
+    };
+
+    let read = |path: &Path, name: &str| {
+        std::fs::read_to_string(path.join(name))
+            .map(|x| x.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    devices
+        .flatten()
+        .map(|x| x.path())
+        // display controllers (class 0x03xxxx) made by NVIDIA
+        .filter(|x| read(x, "vendor") == NVIDIA_PCI_VENDOR && read(x, "class").starts_with("0x03"))
+        .map(|x| {
+            format!(
+                "{} ({}:{})",
+                x.file_name().unwrap_or_default().to_string_lossy(),
+                NVIDIA_PCI_VENDOR.trim_start_matches("0x"),
+                read(&x, "device").trim_start_matches("0x")
+            )
+        })
+        .collect()
+}
+
+/// Check whether Plymouth is shipped in the system at `root`
+pub fn plymouth_exists(root: &Path) -> bool {
+    root.join(&PLYMOUTH_PATH[1..]).exists()
+}
+
+/// Include (or leave out) the Plymouth module in the initramfs of the guest,
+/// must be run before the initramfs is generated
+/// Must be used in a chroot context
+pub fn set_plymouth_dracut_module(enabled: bool) -> Result<()> {
+    let directive = if enabled {
+        "add_dracutmodules"
+    } else {
+        "omit_dracutmodules"
+    };
+    std::fs::create_dir_all("/etc/dracut.conf.d")?;
+    std::fs::write(
+        PLYMOUTH_DRACUT_CONF_PATH,
+        format!("# Generated by AOSC OS Installer\n{directive}+=\" plymouth \"\n"),
+    )?;
+
+    Ok(())
+}
+
+/// Enable (or mask) the Plymouth units in the system at `root`
+/// Must be used outside of the chroot context
+pub fn set_plymouth_units(root: &Path, enabled: bool) -> Result<()> {
+    for unit in PLYMOUTH_UNITS.iter().filter(|x| unit_exists(root, x)) {
+        if enabled {
+            systemctl_unmask(root, unit)?;
+            systemctl_enable(root, unit)?;
+        } else {
+            systemctl_mask(root, unit)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add (or strip) `quiet splash` to the kernel parameters
+pub fn splash_kernel_cmdline(cmdline: &str, enabled: bool) -> String {
+    if enabled {
+        merge_kernel_cmdline(cmdline, &SPLASH_KERNEL_PARAMS.join(" "))
+    } else {
+        cmdline
+            .split_whitespace()
+            .filter(|x| !SPLASH_KERNEL_PARAMS.contains(x))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Prevent nouveau from being loaded in the guest environment
+/// Must be used in a chroot context
+pub fn blacklist_nouveau() -> Result<()> {
+    std::fs::create_dir_all("/etc/modprobe.d")?;
+    std::fs::write(
+        NOUVEAU_BLACKLIST_PATH,
+        "blacklist nouveau\noptions nouveau modeset=0\n",
+    )?;
+
+    Ok(())
+}
+
+/// Network configuration backends that can be carried over from the live session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkBackend {
+    NetworkManager,
+    Iwd,
+    Networkd,
+}
+
+impl NetworkBackend {
+    const ALL: &'static [NetworkBackend] = &[
+        NetworkBackend::NetworkManager,
+        NetworkBackend::Iwd,
+        NetworkBackend::Networkd,
+    ];
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            NetworkBackend::NetworkManager => "NetworkManager.service",
+            NetworkBackend::Iwd => "iwd.service",
+            NetworkBackend::Networkd => "systemd-networkd.service",
+        }
+    }
+
+    fn config_dir(&self) -> &'static str {
+        match self {
+            NetworkBackend::NetworkManager => "etc/NetworkManager/system-connections",
+            NetworkBackend::Iwd => "var/lib/iwd",
+            NetworkBackend::Networkd => "etc/systemd/network",
+        }
+    }
+}
+
+/// Find out which network backend is managing the connections of the live session
+pub fn detect_network_backend() -> Option<NetworkBackend> {
+    NetworkBackend::ALL.iter().copied().find(|x| {
+        Command::new("systemctl")
+            .args(["is-active", "--quiet", x.unit()])
+            .status()
+            .map(|x| x.success())
+            .unwrap_or(false)
+    })
+}
+
+/// Copy the network profiles of the live session into the system at `root`
+/// and enable the matching service, returns false if the system does not ship it
+/// Must be used outside of the chroot context
+pub fn copy_network_config(root: &Path, backend: NetworkBackend) -> Result<bool> {
+    if !unit_exists(root, backend.unit()) {
+        warn!(
+            "{} is not shipped in the installed system, not copying network configuration",
+            backend.unit()
+        );
+        return Ok(false);
+    }
+
+    let src_dir = Path::new("/").join(backend.config_dir());
+    let dst_dir = root.join(backend.config_dir());
+    std::fs::create_dir_all(&dst_dir)?;
+
+    for entry in std::fs::read_dir(&src_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let dst = dst_dir.join(entry.file_name());
+        info!("Copying {} to {}", entry.path().display(), dst.display());
+        std::fs::copy(entry.path(), &dst)?;
+        std::os::unix::fs::chown(&dst, Some(0), Some(0))?;
+        // profiles may contain Wi-Fi PSKs, systemd-networkd needs to read its
+        // files as an unprivileged user so they keep their original mode
+        if backend != NetworkBackend::Networkd {
+            std::fs::set_permissions(&dst, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    systemctl_enable(root, backend.unit())?;
+
+    Ok(true)
+}
+
+/// Adds a new normal user to the guest environment
+/// Must be used in a chroot context
+/// `password_hash` is a crypt(3) hash, see `hash_password`
+pub fn add_new_user(
+    name: &str,
+    password_hash: &str,
+    uid: Option<u32>,
+    groups: &[String],
+) -> Result<()> {
+    let root = Path::new("/");
+    check_username_unused(root, name)?;
+    if let Some(uid) = uid {
+        check_uid(root, uid)?;
+    }
+
+    let (skipped, missing): (Vec<_>, Vec<_>) = missing_groups(root, groups)?
+        .into_iter()
+        .partition(|x| OPTIONAL_USER_GROUPS.contains(&x.as_str()));
+    for group in missing {
+        info!("Creating missing group {}", group);
+        run_command("groupadd", [group])?;
+    }
+    for group in skipped.iter() {
+        info!(
+            "Skipping group {} as the system release does not have it",
+            group
+        );
+    }
+    let groups = groups
+        .iter()
+        .filter(|x| !skipped.contains(x))
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>();
+
+    let uid = uid.map(|x| x.to_string());
+    let mut args = vec!["-m", "-s", DEFAULT_SHELL];
+    if let Some(uid) = uid.as_ref() {
+        args.extend(["-u", uid.as_str()]);
+    }
+    args.push(name);
+    run_command("useradd", &args)?;
+
+    if !groups.is_empty() {
+        run_command("usermod", ["-aG", &groups.join(","), name])?;
+    }
+
+    chpasswd_hash(name, password_hash)?;
+
+    Ok(())
+}
+
+/// Grant administrator privileges through a sudoers drop-in (or doas.conf)
+/// Returns the name of the tool configured
+/// Must be used in a chroot context
+pub fn setup_admin(user: &str, groups: &[String], nopasswd: bool) -> Result<Option<&'static str>> {
+    let grantee = if groups.iter().any(|x| x == "wheel") {
+        None
+    } else {
+        Some(user)
+    };
+
+    if Path::new("/usr/bin/sudo").exists() {
+        std::fs::create_dir_all("/etc/sudoers.d")?;
+        write_validated(
+            Path::new(SUDOERS_DROPIN_PATH),
+            &sudoers_entry(grantee, nopasswd),
+            0o440,
+            ("visudo", &["-cf"][..]),
+        )?;
+
+        return Ok(Some("sudo"));
+    }
+
+    if Path::new("/usr/bin/doas").exists() {
+        let old = std::fs::read_to_string(DOAS_CONF_PATH).unwrap_or_default();
+        let entry = doas_entry(grantee, nopasswd);
+        if !old.lines().any(|x| x.trim() == entry.trim()) {
+            write_validated(
+                Path::new(DOAS_CONF_PATH),
+                &format!("{old}{entry}"),
+                0o600,
+                ("doas", &["-C"][..]),
+            )?;
+        }
+
+        return Ok(Some("doas"));
+    }
+
+    info!("Neither sudo nor doas is installed, skipping");
+
+    Ok(None)
+}
+
+/// Write the file with the given mode next to `path`, validate it with
+/// `validator <args> <path>` and move it in place atomically, so that `path` is left as
+/// it was if the validation fails
+fn write_validated(
+    path: &Path,
+    content: &str,
+    mode: u32,
+    validator: (&str, &[&str]),
+) -> Result<()> {
+    // sudo skips the files in /etc/sudoers.d with a dot in their names
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid path {}.", path.display()))?
+        .to_os_string();
+    tmp_name.push(".deploykit");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut f = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&tmp_path)?;
+    f.write_all(content.as_bytes())?;
+    f.sync_all()?;
+    std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(mode))?;
+
+    let (cmd, args) = validator;
+    let mut args = args.iter().map(OsStr::new).collect::<Vec<_>>();
+    args.push(tmp_path.as_os_str());
+
+    if let Err(e) = run_command(cmd, &args) {
+        std::fs::remove_file(&tmp_path)?;
+        return Err(e);
+    }
+    std::fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+fn sudoers_entry(user: Option<&str>, nopasswd: bool) -> String {
+    let grantee = user
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| "%wheel".to_string());
+    let tag = if nopasswd { "NOPASSWD: " } else { "" };
+
+    format!("# Generated by AOSC OS Installer\n{grantee} ALL=(ALL:ALL) {tag}ALL\n")
+}
+
+fn doas_entry(user: Option<&str>, nopasswd: bool) -> String {
+    let grantee = user
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| ":wheel".to_string());
+    let option = if nopasswd { "nopass" } else { "persist" };
+
+    format!("permit {option} {grantee}\n")
+}
+
+/// Parse and validate the keys in authorized_keys format, ignoring blank lines and comments
+pub fn parse_authorized_keys(text: &str) -> Result<Vec<String>> {
+    let mut keys = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if !is_valid_ssh_key(line) {
+            bail!("Invalid SSH public key: {}", line);
+        }
+        keys.push(line.to_string());
+    }
+
+    if keys.is_empty() {
+        bail!("No SSH public key found.");
+    }
+
+    Ok(keys)
+}
+
+fn is_valid_ssh_key(line: &str) -> bool {
+    // skip the leading options, if any
+    let mut fields = line
+        .split_whitespace()
+        .skip_while(|x| !SSH_KEY_TYPES.contains(x));
+
+    if fields.next().is_none() {
+        return false;
+    }
+
+    match fields.next() {
+        Some(data) => {
+            data.len() % 4 == 0
+                && data.len() >= 16
+                && data
+                    .bytes()
+                    .all(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'/' || c == b'=')
+        }
+        None => false,
+    }
+}
+
+/// Write the keys to `~/.ssh/authorized_keys` of the user with the correct ownership
+/// Must be used in a chroot context
+pub fn write_authorized_keys(user: &str, keys: &[String]) -> Result<()> {
+    let passwd = std::fs::read_to_string("/etc/passwd")?;
+    let entry = passwd
+        .lines()
+        .map(|x| x.split(':').collect::<Vec<_>>())
+        .find(|x| x.first() == Some(&user) && x.len() >= 6)
+        .ok_or_else(|| anyhow!("Can not find user {} in /etc/passwd", user))?;
+    let uid = entry[2].parse::<u32>()?;
+    let gid = entry[3].parse::<u32>()?;
+
+    let ssh_dir = Path::new(entry[5]).join(".ssh");
+    std::fs::create_dir_all(&ssh_dir)?;
+    std::fs::set_permissions(&ssh_dir, std::fs::Permissions::from_mode(0o700))?;
+    std::os::unix::fs::chown(&ssh_dir, Some(uid), Some(gid))?;
+
+    let authorized_keys = ssh_dir.join("authorized_keys");
+    std::fs::write(&authorized_keys, keys.join("\n") + "\n")?;
+    std::fs::set_permissions(&authorized_keys, std::fs::Permissions::from_mode(0o600))?;
+    std::os::unix::fs::chown(&authorized_keys, Some(uid), Some(gid))?;
+
+    Ok(())
+}
+
+/// Disable password authentication for sshd
+/// Must be used in a chroot context
+pub fn disable_ssh_password_auth() -> Result<()> {
+    let conf = std::fs::read_to_string(SSHD_CONFIG_PATH)?;
+    std::fs::write(SSHD_CONFIG_PATH, sshd_config_without_password_auth(&conf))?;
+
+    Ok(())
+}
+
+fn sshd_config_without_password_auth(conf: &str) -> String {
+    let mut s = String::new();
+    // whether it is turned off for all connections, not only in a `Match` block
+    let mut replaced = false;
+    let mut in_match = false;
+    for line in conf.lines() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let commented = line.trim_start().starts_with('#');
+        let keyword = line
+            .trim_start_matches(|c: char| c == '#' || c.is_whitespace())
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+        if !commented && keyword.eq_ignore_ascii_case("Match") {
+            in_match = true;
+        }
+        // every one in effect, so that none after the first (e.g., in a `Match` block)
+        // turns it on again, the first commented out one outside `Match` is taken over
+        if keyword.eq_ignore_ascii_case("PasswordAuthentication")
+            && (!commented || !(replaced || in_match))
+        {
+            s.push_str(indent);
+            s.push_str("PasswordAuthentication no\n");
+            replaced |= !in_match;
+            continue;
+        }
+        s.push_str(line);
+        s.push('\n');
+    }
+
+    if !replaced {
+        // put it on the top so that it won't end up in a `Match` block
+        s.insert_str(0, "PasswordAuthentication no\n");
+    }
+
+    s
+}
+
+/// Check that the UID is in the normal user range and unused in the system at `root`
+pub fn check_uid(root: &Path, uid: u32) -> Result<()> {
+    if !(MIN_USER_UID..MAX_USER_UID).contains(&uid) {
+        bail!(
+            "UID {} is not in the range of normal users, please use a UID from {} to {}.",
+            uid,
+            MIN_USER_UID,
+            MAX_USER_UID - 1
+        );
+    }
+
+    let passwd = std::fs::read_to_string(root.join("etc/passwd"))?;
+    if let Some(user) = passwd
+        .lines()
+        .map(|x| x.split(':').collect::<Vec<_>>())
+        .find(|x| x.get(2) == Some(&uid.to_string().as_str()))
+    {
+        bail!("UID {} is already used by user {}.", uid, user[0]);
+    }
+
+    Ok(())
+}
+
+/// Check that no account or group of the system at `root` is named `name`
+pub fn check_username_unused(root: &Path, name: &str) -> Result<()> {
+    for (path, kind) in [("etc/passwd", "user"), ("etc/group", "group")] {
+        let data = std::fs::read_to_string(root.join(path))?;
+        if data.lines().any(|x| x.split(':').next() == Some(name)) {
+            bail!(
+                "There is already a {} named {} in the system release, please choose another username.",
+                kind,
+                name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Return the groups that do not exist yet in the system at `root`
+pub fn missing_groups<'a>(root: &Path, groups: &'a [String]) -> Result<Vec<&'a String>> {
+    let group = std::fs::read_to_string(root.join("etc/group"))?;
+    let existing = group
+        .lines()
+        .filter_map(|x| x.split(':').next())
+        .collect::<Vec<_>>();
+
+    Ok(groups
+        .iter()
+        .filter(|x| !existing.contains(&x.as_str()))
+        .collect())
+}
+
+/// Group names follow the same rule as usernames, but may also contain `-` and `_`
+pub fn is_valid_group_name(group: &str) -> bool {
+    let mut chars = group.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_lowercase() || c == '_' => (),
+        _ => return false,
+    }
+
+    group.len() <= 32
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+#[link(name = "crypt")]
+extern "C" {
+    fn crypt_gensalt_ra(
+        prefix: *const libc::c_char,
+        count: libc::c_ulong,
+        rbytes: *const libc::c_char,
+        nrbytes: libc::c_int,
+    ) -> *mut libc::c_char;
+    fn crypt_ra(
+        phrase: *const libc::c_char,
+        setting: *const libc::c_char,
+        data: *mut *mut libc::c_void,
+        size: *mut libc::c_int,
+    ) -> *mut libc::c_char;
+}
+
+/// Whether `password` is a yescrypt or SHA-512 crypt(3) hash, which is written to
+/// /etc/shadow verbatim
+pub fn is_password_hash(password: &str) -> bool {
+    (password.starts_with(YESCRYPT_PREFIX) || password.starts_with(SHA512_CRYPT_PREFIX))
+        && !password.contains([':', '\n'])
+}
+
+/// Check the yescrypt cost factor, see crypt(5)
+pub fn check_password_cost(cost: u32) -> Result<()> {
+    if !YESCRYPT_COST_RANGE.contains(&cost) {
+        bail!(
+            "Password hashing cost must be between {} and {}.",
+            YESCRYPT_COST_RANGE.start(),
+            YESCRYPT_COST_RANGE.end()
+        );
+    }
+
+    Ok(())
+}
+
+/// Hash the password with yescrypt (through libxcrypt of the live environment),
+/// `cost` is the yescrypt cost factor, the libxcrypt default is used if not given
+pub fn hash_password(password: &str, cost: Option<u32>) -> Result<String> {
+    if let Some(cost) = cost {
+        check_password_cost(cost)?;
+    }
+
+    let prefix = CString::new(YESCRYPT_PREFIX)?;
+    // with no random bytes given, libxcrypt gets them from the OS
+    let setting = unsafe {
+        crypt_gensalt_ra(
+            prefix.as_ptr(),
+            cost.unwrap_or(0).into(),
+            std::ptr::null(),
+            0,
+        )
+    };
+    if setting.is_null() {
+        bail!(
+            "Installer could not generate a salt for the password: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+
+    let hash = crypt_password(password, unsafe { CStr::from_ptr(setting) });
+    unsafe { libc::free(setting as *mut libc::c_void) };
+
+    hash
+}
+
+fn crypt_password(password: &str, setting: &CStr) -> Result<String> {
+    let phrase =
+        CString::new(password).map_err(|_| anyhow!("Password must not contain NUL characters."))?;
+    let mut data = std::ptr::null_mut();
+    let mut size = 0;
+    let hash = unsafe { crypt_ra(phrase.as_ptr(), setting.as_ptr(), &mut data, &mut size) };
+    let result = if hash.is_null() {
+        Err(anyhow!(
+            "Installer could not hash the password: {}",
+            std::io::Error::last_os_error()
+        ))
+    } else {
+        let hash = unsafe { CStr::from_ptr(hash) }
+            .to_string_lossy()
+            .to_string();
+        // failures are reported as an invalid hash starting with `*`
+        if hash.starts_with('*') {
+            Err(anyhow!("Installer could not hash the password."))
+        } else {
+            Ok(hash)
+        }
+    };
+    unsafe { libc::free(data) };
+
+    result
+}
+
+/// Sets an already hashed password (e.g. `$y$...`) for the user
+/// Must be used in a chroot context
+pub fn chpasswd_hash(name: &str, hash: &str) -> Result<()> {
+    debug!("Running chpasswd ...");
+    let mut command = Command::new("chpasswd")
+        .arg("-e")
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = command.stdin.take().ok_or_else(|| {
+        anyhow!("Installer can not get your stdin! please restart your environment")
+    })?;
+    stdin.write_all(format!("{name}:{hash}\n").as_bytes())?;
+    drop(stdin);
+
+    let status = command.wait()?;
+    if !status.success() {
+        bail!(
+            "chpasswd failed to set the password of {}: {}",
+            name,
+            status
+        );
+    }
+    info!("Running chpasswd successfully");
+
+    Ok(())
+}
+
+/// Locks the password of the user, so that it can not be used to log in
+/// Must be used in a chroot context
+pub fn lock_user_password(name: &str) -> Result<()> {
+    let shadow = std::fs::read_to_string("/etc/shadow")?;
+    std::fs::write("/etc/shadow", shadow_with_password(&shadow, name, "!")?)?;
+
+    Ok(())
+}
+
+fn shadow_with_password(shadow: &str, name: &str, hash: &str) -> Result<String> {
+    let mut s = String::new();
+    let mut found = false;
+
+    for line in shadow.lines() {
+        let mut fields = line.split(':').collect::<Vec<_>>();
+        if fields.first() == Some(&name) && fields.len() > 1 {
+            fields[1] = hash;
+            found = true;
+            s.push_str(&fields.join(":"));
+        } else {
+            s.push_str(line);
+        }
+        s.push('\n');
+    }
+
+    if !found {
+        bail!("Can not find user {} in /etc/shadow", name);
+    }
+
+    Ok(s)
+}
+
 struct Passwd {
     username: String,
     time: String,
@@ -551,20 +3370,234 @@ pub fn passwd_set_fullname(full_name: &str, username: &str) -> Result<()> {
     let mut buf = String::new();
     f.read_to_string(&mut buf)?;
 
-    let passwd = buf.split('\n').collect::<Vec<_>>();
+    let passwd = buf.split('\n').collect::<Vec<_>>();
+
+    let s = set_full_name(full_name, username, passwd)?;
+    f.seek(SeekFrom::Start(0))?;
+
+    f.write_all(s.as_bytes())?;
+
+    Ok(())
+}
+
+/// Check that the kernel parameters can be put into the shell-quoted GRUB defaults
+pub fn is_valid_kernel_cmdline(cmdline: &str) -> bool {
+    cmdline
+        .chars()
+        .all(|c| !c.is_control() && !"\"$`\\".contains(c))
+}
+
+/// Set GRUB_CMDLINE_LINUX_DEFAULT of the guest to the kernel parameters computed from the
+/// current ones
+/// Must be used in a chroot context
+pub fn update_kernel_cmdline<F: FnOnce(&str) -> String>(f: F) -> Result<()> {
+    let grub_default = std::fs::read_to_string(GRUB_DEFAULT_PATH).unwrap_or_default();
+    std::fs::write(
+        GRUB_DEFAULT_PATH,
+        grub_default_with(&grub_default, "GRUB_CMDLINE_LINUX_DEFAULT", |old| {
+            format!("\"{}\"", f(old.unwrap_or_default()))
+        }),
+    )?;
+
+    Ok(())
+}
+
+/// Set the GRUB menu timeout, whether to run os-prober, and whether to hide the menu
+/// Must be used in a chroot context
+pub fn set_grub_options(
+    timeout: Option<u32>,
+    os_prober: Option<bool>,
+    hidden_menu: Option<bool>,
+    gfxmode: Option<&str>,
+) -> Result<()> {
+    let mut grub_default = std::fs::read_to_string(GRUB_DEFAULT_PATH).unwrap_or_default();
+    if let Some(timeout) = timeout {
+        grub_default = grub_default_with(&grub_default, "GRUB_TIMEOUT", |_| timeout.to_string());
+    }
+    if let Some(os_prober) = os_prober {
+        grub_default = grub_default_with(&grub_default, "GRUB_DISABLE_OS_PROBER", |_| {
+            (!os_prober).to_string()
+        });
+    }
+    if let Some(hidden_menu) = hidden_menu {
+        grub_default = grub_default_with(&grub_default, "GRUB_TIMEOUT_STYLE", |_| {
+            if hidden_menu { "hidden" } else { "menu" }.to_string()
+        });
+    }
+    if let Some(gfxmode) = gfxmode {
+        grub_default = grub_default_with(&grub_default, "GRUB_GFXMODE", |_| gfxmode.to_string());
+    }
+    std::fs::write(GRUB_DEFAULT_PATH, grub_default)?;
+
+    Ok(())
+}
+
+/// Set `key` in the GRUB defaults to the value computed from its old (unquoted) value,
+/// the first existing assignment is replaced and any later one is dropped
+fn grub_default_with<F: FnOnce(Option<&str>) -> String>(
+    grub_default: &str,
+    key: &str,
+    f: F,
+) -> String {
+    let prefix = format!("{key}=");
+    let old = grub_default
+        .lines()
+        .find_map(|x| x.trim().strip_prefix(&prefix))
+        .map(|x| x.trim_matches(|c| c == '"' || c == '\''));
+    let line = format!("{prefix}{}\n", f(old));
+
+    let mut s = String::new();
+    let mut replaced = false;
+    for l in grub_default.lines() {
+        if l.trim().starts_with(&prefix) {
+            if !replaced {
+                s.push_str(&line);
+                replaced = true;
+            }
+            continue;
+        }
+        s.push_str(l);
+        s.push('\n');
+    }
+
+    if !replaced {
+        s.push_str(&line);
+    }
+
+    s
+}
+
+/// An operating system detected by os-prober
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignOs {
+    pub name: String,
+    pub device: String,
+    /// How GRUB boots it: `efi`, `chain`, `linux`, etc.
+    pub loader: String,
+}
+
+impl std::fmt::Display for ForeignOs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let loader = match self.loader.as_str() {
+            "efi" => "UEFI boot loader",
+            "chain" => "chainloaded",
+            "linux" => "Linux kernel",
+            "macosx" => "XNU kernel",
+            x => x,
+        };
+
+        write!(f, "{} on {} ({})", self.name, self.device, loader)
+    }
+}
+
+/// Run os-prober and return the detected operating systems
+/// Must be used in a chroot context
+pub fn list_foreign_os() -> Result<Vec<ForeignOs>> {
+    let output = Command::new("os-prober").output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    debug!("os-prober output:\n{}", stdout);
+
+    Ok(parse_os_prober(&stdout))
+}
+
+fn parse_os_prober(output: &str) -> Vec<ForeignOs> {
+    // <device>:<long name>:<short name>:<type>
+    output
+        .lines()
+        .filter_map(|x| {
+            let mut fields = x.split(':');
+            let device = fields.next()?;
+            let name = fields.next().filter(|x| !x.is_empty())?;
+            let loader = fields.nth(1).unwrap_or_default();
+
+            Some(ForeignOs {
+                name: name.to_string(),
+                device: device.split('@').next().unwrap_or(device).to_string(),
+                loader: loader.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Look for an existing Windows in the EFI system partition and the UEFI boot entries,
+/// returns where it has been found
+/// Must be used in a chroot context
+pub fn find_windows_boot() -> Option<String> {
+    if Path::new(WINDOWS_BOOT_MANAGER_PATH).is_file() {
+        return Some(format!(
+            "{WINDOWS_BOOT_MANAGER_PATH} on the EFI system partition"
+        ));
+    }
+
+    let mut entries = std::fs::read_dir(EFIVARS_PATH)
+        .ok()?
+        .filter_map(|x| x.ok())
+        .filter_map(|x| {
+            let name = x.file_name().to_string_lossy().to_string();
+            let (var, guid) = name.split_once('-')?;
+            if guid != EFI_GLOBAL_VARIABLE_GUID
+                || var.len() != 8
+                || !var.starts_with("Boot")
+                || !var[4..].bytes().all(|x| x.is_ascii_hexdigit())
+            {
+                return None;
+            }
+            // efivarfs prefixes the data with the 4-byte variable attributes
+            let data = std::fs::read(x.path()).ok()?;
+            let description = load_option_description(data.get(4..)?)?;
+
+            Some((var.to_string(), description))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    entries
+        .into_iter()
+        .find(|(_, description)| description.contains(WINDOWS_BOOT_MANAGER_NAME))
+        .map(|(var, description)| format!("UEFI boot entry {var} ({description})"))
+}
+
+/// Read the description of an `EFI_LOAD_OPTION`
+fn load_option_description(data: &[u8]) -> Option<String> {
+    // UINT32 Attributes, UINT16 FilePathListLength, then the NUL-terminated CHAR16 Description
+    let description = data
+        .get(6..)?
+        .chunks_exact(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]))
+        .take_while(|x| *x != 0)
+        .collect::<Vec<_>>();
+
+    String::from_utf16(&description).ok()
+}
 
-    let s = set_full_name(full_name, username, passwd)?;
-    f.seek(SeekFrom::Start(0))?;
+/// Append the parameters in `extra` to `base`, replacing those with the same key
+pub fn merge_kernel_cmdline(base: &str, extra: &str) -> String {
+    let key = |x: &str| x.split('=').next().unwrap_or_default().to_string();
+    let extra = extra.split_whitespace().collect::<Vec<_>>();
+    let extra_keys = extra.iter().map(|x| key(x)).collect::<Vec<_>>();
+
+    base.split_whitespace()
+        .filter(|x| !extra_keys.contains(&key(x)))
+        .chain(extra)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
 
-    f.write_all(s.as_bytes())?;
+/// Runs grub-install and grub-mkconfig
+/// Must be used in a chroot context
+pub fn execute_grub_install(mbr_dev: Option<&PathBuf>) -> Result<()> {
+    crate::assert_not_ui_thread("Installing GRUB");
+    for (command, args) in grub_commands(mbr_dev)? {
+        run_command_logged(command, &args)?;
+    }
 
     Ok(())
 }
 
-/// Runs grub-install and grub-mkconfig
-/// Must be used in a chroot context
+/// The commands installing GRUB, to the MBR of `mbr_dev` if there is one, nothing on
+/// architectures GRUB does not support
 #[cfg(not(target_arch = "powerpc64"))]
-pub fn execute_grub_install(mbr_dev: Option<&PathBuf>) -> Result<()> {
+pub fn grub_commands(mbr_dev: Option<&PathBuf>) -> Result<Vec<(&'static str, Vec<String>)>> {
     use log::warn;
 
     let mut grub_install_args = vec![];
@@ -584,11 +3617,11 @@ pub fn execute_grub_install(mbr_dev: Option<&PathBuf>) -> Result<()> {
             Some("loongarch64") => (&["--target=loongarch64-efi", "--removable"][..], true),
             Some(arch) => {
                 info!("This architecture {arch} does not support grub");
-                return Ok(());
+                return Ok(vec![]);
             }
             None => {
                 warn!("Install GRUB: What is this architecture???");
-                return Ok(());
+                return Ok(vec![]);
             }
         };
         grub_install_args.push("--bootloader-id=AOSC OS");
@@ -598,46 +3631,55 @@ pub fn execute_grub_install(mbr_dev: Option<&PathBuf>) -> Result<()> {
         }
     };
 
-    run_command("grub-install", &grub_install_args)?;
-    run_command("grub-mkconfig", ["-o", "/boot/grub/grub.cfg"])?;
-
-    Ok(())
+    Ok(vec![
+        (
+            "grub-install",
+            grub_install_args.iter().map(|x| x.to_string()).collect(),
+        ),
+        grub_mkconfig_command(),
+    ])
 }
 
 #[cfg(target_arch = "powerpc64")]
-pub fn execute_grub_install(_mbr_dev: Option<&PathBuf>) -> Result<()> {
-    use std::io::BufReader;
-
+pub fn grub_commands(mbr_dev: Option<&PathBuf>) -> Result<Vec<(&'static str, Vec<String>)>> {
     let target = network::get_arch_name();
 
-    let cpuinfo = std::fs::File::open("/proc/cpuinfo")?;
-    let r = BufReader::new(cpuinfo);
-
-    let find = r.lines().flatten().find(|x| x.starts_with("firmware"));
-
-    let needs_install = if let Some(find) = find {
-        let s = find.split(':').nth(1).map(|x| x.trim());
-
-        s != Some("OPAL")
-    } else {
-        true
-    };
-
     let install_args = match target {
         Some("ppc64el") | Some("ppc64") | Some("powerpc") => "--target=powerpc-ieee1275",
         _ => {
             info!("This architecture does not support grub");
-            return Ok(());
+            return Ok(vec![]);
         }
     };
 
-    if needs_install {
-        run_command("grub-install", &[install_args])?;
+    let mut commands = vec![];
+    // OPAL (PowerNV) firmware boots through petitboot, which reads grub.cfg directly
+    if crate::disks::needs_prep_partition() {
+        let mbr_dev = mbr_dev.ok_or_else(|| {
+            anyhow!("Installer could not detect the disk to install the bootloader to.")
+        })?;
+        let prep = crate::disks::find_prep_partition(mbr_dev)?;
+        let prep = prep
+            .path
+            .as_ref()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| anyhow!("Can not PReP partition path to str!"))?;
+        info!("Installing GRUB to the PReP boot partition {prep}");
+        commands.push((
+            "grub-install",
+            vec![install_args.to_string(), prep.to_string()],
+        ));
     }
+    commands.push(grub_mkconfig_command());
 
-    run_command("grub-mkconfig", ["-o", "/boot/grub/grub.cfg"])?;
+    Ok(commands)
+}
 
-    Ok(())
+fn grub_mkconfig_command() -> (&'static str, Vec<String>) {
+    (
+        "grub-mkconfig",
+        vec!["-o".to_string(), "/boot/grub/grub.cfg".to_string()],
+    )
 }
 
 pub fn prepare_try_umount() -> Result<()> {
@@ -656,6 +3698,7 @@ pub fn prepare_try_umount() -> Result<()> {
     for (_, mount_path) in dk_mounts {
         umount_root_path(Path::new(mount_path)).ok();
     }
+    close_encrypted_partition();
 
     Ok(())
 }
@@ -707,25 +3750,53 @@ pub fn create_swapfile(size: f64, use_swap: bool, tempdir: &Path) -> Result<()>
     info!("Set swapfile permission as 600");
     std::fs::set_permissions(&swap_path, std::fs::Permissions::from_mode(0o600))?;
 
-    run_command("mkswap", [&swap_path])?;
+    run_command_logged("mkswap", [&swap_path])?;
     run_command("swapon", [swap_path]).ok();
 
     Ok(())
 }
 
-pub fn swapoff(tempdir: &Path) {
-    run_command("swapoff", [tempdir.join("swapfile")]).ok();
+/// The UUID of the filesystem (or swap) on the device
+/// Must be used in a chroot context
+pub fn filesystem_uuid(path: &Path) -> Result<String> {
+    let output = Command::new("blkid")
+        .args(["-s", "UUID", "-o", "value"])
+        .arg(path)
+        .output()?;
+    let uuid = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || uuid.is_empty() {
+        return Err(anyhow!(
+            "Installer could not obtain the UUID of {}!",
+            path.display()
+        ));
+    }
+
+    Ok(uuid)
 }
 
-/// Must be used in a chroot context
-pub fn write_swap_entry_to_fstab() -> Result<()> {
-    let s = "/swapfile none swap defaults,nofail 0 0\n";
-    let mut fstab = std::fs::OpenOptions::new()
-        .append(true)
-        .open("/etc/fstab")?;
-    fstab.write_all(s.as_bytes())?;
+/// Where the swapfile at `path` starts on its filesystem, in pages, for `resume_offset=`
+pub fn swapfile_resume_offset(path: &Path) -> Result<u64> {
+    // FIBMAP, maps a block of the file to the block of the filesystem
+    const FIBMAP: libc::c_ulong = 1;
 
-    Ok(())
+    let swapfile = File::open(path)?;
+    let mut block: libc::c_int = 0;
+    let res = unsafe { libc::ioctl(swapfile.as_raw_fd(), FIBMAP as _, &mut block) };
+    if res != 0 {
+        return Err(anyhow!(
+            "Installer could not locate {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        ));
+    }
+    let block_size = swapfile.metadata()?.blksize();
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+
+    Ok(block as u64 * block_size / page_size)
+}
+
+pub fn swapoff(tempdir: &Path) {
+    run_command("swapoff", [tempdir.join("swapfile")]).ok();
 }
 
 /// Run umount -R
@@ -733,49 +3804,116 @@ pub fn umount_all<F: AsFd>(mount_path: &Path, root_fd: F) {
     info!("Cleaning up mount path ...");
 
     escape_chroot(root_fd).ok();
-    let efi_path = mount_path.join("efi");
-    if is_efi_booted() {
-        umount_root_path(&efi_path).ok();
-    }
     swapoff(mount_path);
-    umount_root_path(mount_path).ok();
+    if let Err(e) = unmount_recursively(mount_path) {
+        warn!("Failed to unmount {}: {}", mount_path.display(), e);
+    }
+    close_encrypted_partition();
+}
+
+/// Same as `umount_all`, but for installing into a directory managed by the user,
+/// which itself stays mounted
+pub fn umount_guest<F: AsFd>(root: &Path, root_fd: F) {
+    info!("Cleaning up mounts beneath {} ...", root.display());
+
+    escape_chroot(root_fd).ok();
+    if let Err(e) = unmount_beneath(root) {
+        warn!("Failed to unmount {}: {}", root.display(), e);
+    }
 }
 
+/// Validate the hostname against RFC 1123
+///
+/// Uppercase letters are rejected, use [`suggest_hostname`] to offer a lowercased one.
 pub fn is_valid_hostname(hostname: &str) -> bool {
-    if hostname.is_empty() || hostname.starts_with('-') {
+    if hostname.is_empty() || hostname.len() > 253 {
         return false;
     }
-    for c in hostname.as_bytes() {
-        if c.is_ascii_alphanumeric() || *c == b'-' {
-            continue;
-        } else {
+
+    for label in hostname.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return false;
+        }
+        if label.starts_with('-') || label.ends_with('-') {
             return false;
         }
+        for c in label.as_bytes() {
+            if c.is_ascii_lowercase() || c.is_ascii_digit() || *c == b'-' {
+                continue;
+            } else {
+                return false;
+            }
+        }
     }
 
     true
 }
 
-pub fn is_acceptable_username(username: &str) -> bool {
-    if username.is_empty() {
-        return false;
+/// Return the lowercased hostname if that is the only thing wrong with it
+pub fn suggest_hostname(hostname: &str) -> Option<String> {
+    let lower = hostname.to_ascii_lowercase();
+    if lower != hostname && is_valid_hostname(&lower) {
+        Some(lower)
+    } else {
+        None
     }
+}
 
-    if username == "root" {
-        return false;
-    }
+/// Why a username can not be used, see [`check_username`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidUsername {
+    Empty,
+    TooLong,
+    FirstCharacter,
+    Character,
+    Reserved,
+}
 
-    for (i, c) in username.as_bytes().iter().enumerate() {
-        if i == 0 {
-            if !c.is_ascii_lowercase() {
-                return false;
+impl InvalidUsername {
+    /// The rule the username breaks
+    pub fn rule(&self) -> &'static str {
+        match self {
+            InvalidUsername::Empty => "Username may not be empty.",
+            InvalidUsername::TooLong => "Username may not be longer than 32 characters.",
+            InvalidUsername::FirstCharacter => {
+                "Username must start with a lower-cased letter (a-z)."
             }
-        } else if !c.is_ascii_lowercase() && !c.is_ascii_digit() {
-            return false;
+            InvalidUsername::Character => {
+                "Username may only contain lower-cased letters a-z, numbers 0-9, dash (\"-\") and underscore (\"_\")."
+            }
+            InvalidUsername::Reserved => "Username is reserved for a system account or group.",
         }
     }
+}
 
-    true
+impl std::fmt::Display for InvalidUsername {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.rule())
+    }
+}
+
+impl std::error::Error for InvalidUsername {}
+
+/// Check the username against the rules of `useradd`, and the accounts of the system
+/// release (the release itself is checked again by [`add_new_user`])
+pub fn check_username(username: &str) -> std::result::Result<(), InvalidUsername> {
+    let mut chars = username.chars();
+    match chars.next() {
+        None => return Err(InvalidUsername::Empty),
+        Some(c) if !c.is_ascii_lowercase() => return Err(InvalidUsername::FirstCharacter),
+        _ => (),
+    }
+    if !chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+        return Err(InvalidUsername::Character);
+    }
+    if username.len() > MAX_USERNAME_LENGTH {
+        return Err(InvalidUsername::TooLong);
+    }
+    if RESERVED_USERNAMES.contains(&username) || username.starts_with("systemd-") {
+        return Err(InvalidUsername::Reserved);
+    }
+
+    Ok(())
 }
 
 #[test]
@@ -786,22 +3924,72 @@ fn test_hostname_validation() {
     assert!(!is_valid_hostname("invalid_host"));
     assert!(!is_valid_hostname("-invalid"));
     assert!(!is_valid_hostname("+invalid"));
-    assert!(is_valid_hostname("JellyDimension"));
+    assert!(!is_valid_hostname("JellyDimension"));
     assert!(!is_valid_hostname("Jelly_Dimension"));
+    assert!(!is_valid_hostname("invalid-"));
+    assert!(!is_valid_hostname(&"a".repeat(64)));
+    assert!(is_valid_hostname("aosc.lan"));
+    assert!(!is_valid_hostname("aosc..lan"));
+    assert_eq!(
+        suggest_hostname("JellyDimension"),
+        Some("jellydimension".to_string())
+    );
+    assert_eq!(suggest_hostname("Jelly_Dimension"), None);
+    assert!(is_valid_hostname(&gen_default_hostname("Workstation")));
+    assert!(is_valid_hostname(&gen_default_hostname("KDE (Desktop)")));
+}
+
+#[test]
+fn test_hosts_with_hostname() {
+    let hosts = "127.0.0.1\tlocalhost\n::1\tlocalhost\n";
+    assert_eq!(
+        hosts_with_hostname(hosts, "aosc"),
+        "127.0.0.1\tlocalhost\n::1\tlocalhost\n127.0.1.1\taosc\n"
+    );
+
+    let hosts = "127.0.0.1 localhost\n127.0.1.1 old\n";
+    assert_eq!(
+        hosts_with_hostname(hosts, "aosc"),
+        "127.0.0.1 localhost\n127.0.1.1\taosc\n"
+    );
+    assert_eq!(hosts_with_hostname("", "aosc"), "127.0.1.1\taosc\n");
 }
 
 #[test]
 fn test_username_validation() {
-    assert!(is_acceptable_username("foo"));
-    assert!(is_acceptable_username("cth451"));
-    assert!(!is_acceptable_username("老白"));
-    assert!(!is_acceptable_username("BAIMINGCONG"));
-    assert!(!is_acceptable_username("root"));
-    assert!(!is_acceptable_username("/root"));
-    assert!(!is_acceptable_username("root:root"));
-    assert!(!is_acceptable_username("root\n"));
-    assert!(!is_acceptable_username("root\t"));
-    assert!(!is_acceptable_username("ro ot"));
+    assert!(check_username("foo").is_ok());
+    assert!(check_username("cth451").is_ok());
+    assert!(check_username("mag_mell-2").is_ok());
+    assert_eq!(check_username("老白"), Err(InvalidUsername::FirstCharacter));
+    assert_eq!(
+        check_username("BAIMINGCONG"),
+        Err(InvalidUsername::FirstCharacter)
+    );
+    assert_eq!(
+        check_username("1saki"),
+        Err(InvalidUsername::FirstCharacter)
+    );
+    assert_eq!(check_username("saki mell"), Err(InvalidUsername::Character));
+    assert_eq!(
+        check_username(&"a".repeat(33)),
+        Err(InvalidUsername::TooLong)
+    );
+    assert!(check_username(&"a".repeat(32)).is_ok());
+    assert_eq!(check_username(""), Err(InvalidUsername::Empty));
+    assert_eq!(check_username("root"), Err(InvalidUsername::Reserved));
+    assert_eq!(check_username("nobody"), Err(InvalidUsername::Reserved));
+    assert_eq!(
+        check_username("systemd-oom"),
+        Err(InvalidUsername::Reserved)
+    );
+    assert_eq!(
+        check_username("/root"),
+        Err(InvalidUsername::FirstCharacter)
+    );
+    assert!(check_username("root:root").is_err());
+    assert!(check_username("root\n").is_err());
+    assert!(check_username("root\t").is_err());
+    assert!(check_username("ro ot").is_err());
 }
 
 #[test]
@@ -858,3 +4046,625 @@ _apt:x:976:976::/var/lib/apt:/sbin/nologin
     assert!(set_full_name("Mag Mell\n", "saki", passwd.clone()).is_err());
     assert!(set_full_name("Mag Mell:", "saki", passwd.clone()).is_err());
 }
+
+#[test]
+fn test_check_locale() {
+    let supported = vec![
+        "en_US.UTF-8".to_string(),
+        "zh_CN.UTF-8".to_string(),
+        "zh_TW.UTF-8".to_string(),
+    ];
+    assert!(check_locale("en_US.UTF-8", &supported).is_ok());
+    assert!(check_locale("C.UTF-8", &supported).is_ok());
+    let err = check_locale("zh_CN.UTF8", &supported).unwrap_err();
+    assert!(err.to_string().contains("zh_CN.UTF-8"));
+    assert!(check_locale("fr_FR.UTF-8", &supported).is_err());
+}
+
+#[test]
+fn test_locale_gen_with() {
+    let locale_gen = "# en_US.UTF-8 UTF-8\n#zh_CN.UTF-8 UTF-8\n# ja_JP.UTF-8 UTF-8\n";
+    let en = "en_US.UTF-8".to_string();
+    let zh = "zh_CN.UTF-8".to_string();
+    let de = "de_DE.UTF-8".to_string();
+    assert_eq!(
+        locale_gen_with(locale_gen, &[&en, &zh, &de]),
+        "en_US.UTF-8 UTF-8\nzh_CN.UTF-8 UTF-8\n# ja_JP.UTF-8 UTF-8\nde_DE.UTF-8 UTF-8\n"
+    );
+}
+
+#[test]
+fn test_check_timezone() {
+    let list = vec![
+        "UTC".to_string(),
+        "Asia/Shanghai".to_string(),
+        "Asia/Singapore".to_string(),
+        "Europe/Berlin".to_string(),
+    ];
+    assert_eq!(
+        check_timezone("asia/shanghai", &list).unwrap(),
+        "Asia/Shanghai"
+    );
+    let err = check_timezone("Asia/Shanghi", &list).unwrap_err();
+    assert!(err.to_string().contains("Asia/Shanghai"));
+    assert!(check_timezone("Mars/Olympus", &list).is_err());
+}
+
+#[test]
+fn test_adjtime_with() {
+    assert_eq!(adjtime_with("", false), "0.0 0 0.0\n0\nLOCAL\n");
+    assert_eq!(
+        adjtime_with("0.013 1600000000 0.0\n1600000000\nLOCAL\n", true),
+        "0.013 1600000000 0.0\n1600000000\nUTC\n"
+    );
+}
+
+#[test]
+fn test_chrony_conf_with() {
+    let conf = "pool pool.ntp.org iburst\ndriftfile /var/lib/chrony/drift\n";
+    assert_eq!(
+        chrony_conf_with(conf, &["ntp.example.com".to_string()]),
+        "# pool pool.ntp.org iburst\ndriftfile /var/lib/chrony/drift\nserver ntp.example.com iburst\n"
+    );
+}
+
+#[cfg(test)]
+fn fixture_target() -> tempfile::TempDir {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("etc")).unwrap();
+    std::fs::write(
+        root.path().join("etc/passwd"),
+        "root:x:0:0:root:/root:/bin/bash\nsaki:x:1000:1001::/home/saki:/bin/bash\n",
+    )
+    .unwrap();
+    std::fs::write(
+        root.path().join("etc/group"),
+        "root:x:0:\nwheel:x:1:\naudio:x:2:\nvideo:x:3:\n",
+    )
+    .unwrap();
+
+    root
+}
+
+#[test]
+fn test_check_uid() {
+    let root = fixture_target();
+    assert!(check_uid(root.path(), 1001).is_ok());
+    assert!(check_uid(root.path(), 1000).is_err());
+    assert!(check_uid(root.path(), 999).is_err());
+    assert!(check_uid(root.path(), MAX_USER_UID - 1).is_ok());
+    assert!(check_uid(root.path(), MAX_USER_UID).is_err());
+    // nobody, and (uid_t) -1 which chown(2) takes as "unchanged"
+    assert!(check_uid(root.path(), 65534).is_err());
+    assert!(check_uid(root.path(), 4294967295).is_err());
+}
+
+#[test]
+fn test_check_username_unused() {
+    let root = fixture_target();
+    assert!(check_username_unused(root.path(), "mell").is_ok());
+    assert!(check_username_unused(root.path(), "saki").is_err());
+    assert!(check_username_unused(root.path(), "video").is_err());
+}
+
+#[test]
+fn test_missing_groups() {
+    let root = fixture_target();
+    let groups = DEFAULT_USER_GROUPS
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        missing_groups(root.path(), &groups).unwrap(),
+        vec!["cdrom", "plugdev"]
+    );
+    assert!(is_valid_group_name("plugdev"));
+    assert!(is_valid_group_name("_apt"));
+    assert!(!is_valid_group_name("Wheel"));
+    assert!(!is_valid_group_name("wheel,audio"));
+}
+
+#[test]
+fn test_shadow_with_password() {
+    let shadow = "root:$6$salt$hash:19000:0:99999:7:::\nsaki:$6$salt$hash:19000:0:99999:7:::\n";
+    assert_eq!(
+        shadow_with_password(shadow, "root", "!").unwrap(),
+        "root:!:19000:0:99999:7:::\nsaki:$6$salt$hash:19000:0:99999:7:::\n"
+    );
+    assert!(shadow_with_password(shadow, "nobody", "!").is_err());
+
+    // the hash must survive a round trip through /etc/shadow
+    let hash = hash_password("hunter2", Some(5)).unwrap();
+    assert!(hash.starts_with("$y$") && is_password_hash(&hash));
+    let shadow = shadow_with_password(shadow, "saki", &hash).unwrap();
+    let fields = shadow
+        .lines()
+        .find(|x| x.starts_with("saki:"))
+        .unwrap()
+        .split(':')
+        .collect::<Vec<_>>();
+    assert_eq!(fields.len(), 9);
+    assert_eq!(fields[1], hash);
+    let setting = CString::new(fields[1]).unwrap();
+    assert_eq!(crypt_password("hunter2", &setting).unwrap(), hash);
+    assert_ne!(crypt_password("hunter3", &setting).unwrap(), hash);
+
+    assert!(is_password_hash("$6$salt$hash"));
+    assert!(!is_password_hash("$1$salt$hash"));
+    assert!(!is_password_hash("$y$j9T$salt$hash:0"));
+    assert!(!is_password_hash("hunter2"));
+    assert!(hash_password("hunter2", Some(12)).is_err());
+}
+
+#[test]
+fn test_admin_entries() {
+    assert_eq!(
+        sudoers_entry(None, false),
+        "# Generated by AOSC OS Installer\n%wheel ALL=(ALL:ALL) ALL\n"
+    );
+    assert_eq!(
+        sudoers_entry(Some("saki"), true),
+        "# Generated by AOSC OS Installer\nsaki ALL=(ALL:ALL) NOPASSWD: ALL\n"
+    );
+    assert_eq!(doas_entry(None, false), "permit persist :wheel\n");
+    assert_eq!(doas_entry(Some("saki"), true), "permit nopass saki\n");
+}
+
+#[test]
+fn test_parse_authorized_keys() {
+    let keys = parse_authorized_keys(
+        "# my keys\nssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4f saki@aosc\n\nno-pty ssh-rsa AAAAB3NzaC1yc2EAAAADAQAB\n",
+    )
+    .unwrap();
+    assert_eq!(keys.len(), 2);
+    assert!(parse_authorized_keys("ssh-ed25519 not-base64!").is_err());
+    assert!(parse_authorized_keys("hello world").is_err());
+    assert!(parse_authorized_keys("\n# nothing\n").is_err());
+}
+
+#[test]
+fn test_sshd_config_without_password_auth() {
+    assert_eq!(
+        sshd_config_without_password_auth("Port 22\n#PasswordAuthentication yes\n"),
+        "Port 22\nPasswordAuthentication no\n"
+    );
+    assert_eq!(
+        sshd_config_without_password_auth("Port 22\nMatch User foo\n"),
+        "PasswordAuthentication no\nPort 22\nMatch User foo\n"
+    );
+    assert_eq!(
+        sshd_config_without_password_auth(
+            "#PasswordAuthentication yes\nPasswordAuthentication yes\n#PasswordAuthentication no\n"
+        ),
+        "PasswordAuthentication no\nPasswordAuthentication no\n#PasswordAuthentication no\n"
+    );
+    assert_eq!(
+        sshd_config_without_password_auth(
+            "Port 22\nMatch User foo\n\tPasswordAuthentication yes\n\t#PasswordAuthentication yes\n"
+        ),
+        "PasswordAuthentication no\nPort 22\nMatch User foo\n\tPasswordAuthentication no\n\t#PasswordAuthentication yes\n"
+    );
+}
+
+#[test]
+fn test_crypttab_with() {
+    assert_eq!(
+        crypttab_with("1111", &[], false),
+        "# Generated by AOSC OS Installer\naosc-root UUID=1111 none luks\n"
+    );
+    assert_eq!(data_mapper_name("/var/log"), "aosc-data-var-log");
+    assert_eq!(
+        crypttab_with("1111", &[(data_mapper_name("/home"), "2222".to_string())], true),
+        "# Generated by AOSC OS Installer\naosc-root UUID=1111 none luks,discard\naosc-data-home UUID=2222 /etc/cryptsetup-keys.d/aosc-data-home.key luks,discard\n"
+    );
+
+    let listing = "bash\nsystemd\ncrypt\ndm\nkernel-modules\n";
+    assert!(has_dracut_module(listing, "crypt"));
+    assert!(!has_dracut_module(listing, "lvm"));
+    assert!(!has_dracut_module("systemd-cryptsetup\n", "crypt"));
+}
+
+#[test]
+fn test_apt_sources_with() {
+    assert_eq!(
+        apt_sources_with(
+            "# comment\ndeb https://repo.aosc.io/debs/ stable main\n",
+            "https://mirrors.tuna.tsinghua.edu.cn/anthon/debs/"
+        ),
+        "# comment\ndeb https://mirrors.tuna.tsinghua.edu.cn/anthon/debs stable main\n"
+    );
+    assert_eq!(
+        apt_sources_with("", "https://repo.aosc.io/debs"),
+        "deb https://repo.aosc.io/debs stable main\n"
+    );
+    assert!(is_valid_package_name("vim"));
+    assert!(is_valid_package_name("g++"));
+    assert!(!is_valid_package_name("Vim"));
+    assert!(!is_valid_package_name("-y"));
+}
+
+#[test]
+fn test_kernel_cmdline() {
+    assert!(is_valid_kernel_cmdline(
+        "quiet splash console=ttyS0,115200n8"
+    ));
+    assert!(!is_valid_kernel_cmdline("quiet\" init=/bin/sh"));
+    assert!(!is_valid_kernel_cmdline("$(reboot)"));
+    assert_eq!(
+        merge_kernel_cmdline("quiet loglevel=3", "loglevel=7 mitigations=off"),
+        "quiet loglevel=7 mitigations=off"
+    );
+}
+
+#[test]
+fn test_units() {
+    let root = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(root.path().join("usr/lib/systemd/system")).unwrap();
+    std::fs::create_dir_all(root.path().join("etc/systemd/system")).unwrap();
+    for unit in ["sshd.service", "bluetooth.service", "zramswap.service"] {
+        std::fs::write(root.path().join("usr/lib/systemd/system").join(unit), "").unwrap();
+    }
+    std::fs::write(root.path().join("etc/systemd/system/sshd.service"), "").unwrap();
+
+    let units = list_units(root.path());
+    assert_eq!(
+        units,
+        ["bluetooth.service", "sshd.service", "zramswap.service"]
+    );
+    assert_eq!(unit_name("sshd"), "sshd.service");
+    assert_eq!(unit_name("fstrim.timer"), "fstrim.timer");
+    assert_eq!(
+        suggest_similar("zramswp.service", &units),
+        ["zramswap.service"]
+    );
+}
+
+#[test]
+fn test_splash_kernel_cmdline() {
+    assert_eq!(
+        splash_kernel_cmdline("loglevel=3 quiet", true),
+        "loglevel=3 quiet splash"
+    );
+    assert_eq!(
+        splash_kernel_cmdline("quiet loglevel=3 splash", false),
+        "loglevel=3"
+    );
+    assert_eq!(splash_kernel_cmdline("", true), "quiet splash");
+}
+
+#[test]
+fn test_grub_default_with() {
+    let grub_default = "GRUB_TIMEOUT=5\nGRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\nGRUB_TIMEOUT=3\n";
+    assert_eq!(
+        grub_default_with(grub_default, "GRUB_CMDLINE_LINUX_DEFAULT", |old| {
+            format!(
+                "\"{}\"",
+                merge_kernel_cmdline(old.unwrap_or_default(), "splash")
+            )
+        }),
+        "GRUB_TIMEOUT=5\nGRUB_CMDLINE_LINUX_DEFAULT=\"quiet splash\"\nGRUB_TIMEOUT=3\n"
+    );
+    assert_eq!(
+        grub_default_with(grub_default, "GRUB_TIMEOUT", |_| "0".to_string()),
+        "GRUB_TIMEOUT=0\nGRUB_CMDLINE_LINUX_DEFAULT=\"quiet\"\n"
+    );
+    assert_eq!(
+        grub_default_with("GRUB_TIMEOUT=5\n", "GRUB_DISABLE_OS_PROBER", |_| "false"
+            .to_string()),
+        "GRUB_TIMEOUT=5\nGRUB_DISABLE_OS_PROBER=false\n"
+    );
+}
+
+#[test]
+fn test_parse_os_prober() {
+    assert_eq!(
+        parse_os_prober("/dev/nvme0n1p1@/EFI/Microsoft/Boot/bootmgfw.efi:Windows Boot Manager:Windows:efi\n/dev/sda2:Debian GNU/Linux 12 (bookworm):Debian:linux\n"),
+        vec![
+            ForeignOs {
+                name: "Windows Boot Manager".to_string(),
+                device: "/dev/nvme0n1p1".to_string(),
+                loader: "efi".to_string(),
+            },
+            ForeignOs {
+                name: "Debian GNU/Linux 12 (bookworm)".to_string(),
+                device: "/dev/sda2".to_string(),
+                loader: "linux".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        parse_os_prober("/dev/sda1:Windows 10:Windows:chain\n")[0].to_string(),
+        "Windows 10 on /dev/sda1 (chainloaded)"
+    );
+}
+
+#[test]
+fn test_load_option_description() {
+    let mut data = vec![1, 0, 0, 0, 0x74, 0];
+    data.extend(
+        "Windows Boot Manager"
+            .encode_utf16()
+            .flat_map(|x| x.to_le_bytes()),
+    );
+    data.extend([0, 0, 4, 1, 42, 0]);
+    assert_eq!(
+        load_option_description(&data).as_deref(),
+        Some("Windows Boot Manager")
+    );
+    assert_eq!(load_option_description(&[1, 0, 0]), None);
+}
+
+#[test]
+fn test_keyboard_layouts() {
+    let layouts = parse_xkb_rules(
+        "! model\n  pc105           Generic 105-key PC\n\n! layout\n  us              English (US)\n  de              German\n\n! variant\n  nodeadkeys      de: German (no dead keys)\n  dvorak          us: English (Dvorak)\n",
+    );
+    assert_eq!(
+        layouts.iter().map(|x| x.keymap()).collect::<Vec<_>>(),
+        vec!["us", "us-dvorak", "de", "de-nodeadkeys"]
+    );
+    assert_eq!(layouts[3].description, "German (no dead keys)");
+    assert!(check_keymap("de-nodeadkeys", &layouts).is_ok());
+    assert!(check_keymap("fr", &layouts).is_err());
+
+    assert_eq!(
+        vconsole_conf_with(
+            "KEYMAP=us\nFONT=lat9w-16\n",
+            Some("de-nodeadkeys"),
+            &layouts[3]
+        ),
+        "FONT=lat9w-16\nKEYMAP=de-nodeadkeys\nXKBLAYOUT=de\nXKBVARIANT=nodeadkeys\n"
+    );
+    assert_eq!(
+        vconsole_keymap("FONT=lat9w-16\nKEYMAP=\"fr\"\n").as_deref(),
+        Some("fr")
+    );
+    assert_eq!(vconsole_keymap("FONT=lat9w-16\nKEYMAP=\n"), None);
+}
+
+#[test]
+fn test_console_font() {
+    assert_eq!(parse_drm_mode("3840x2160\n"), Some((3840, 2160)));
+    assert_eq!(parse_drm_mode("1920x1080i"), Some((1920, 1080)));
+    assert_eq!(parse_drm_mode("preferred"), None);
+    assert!(is_hidpi_resolution((3840, 2160)));
+    assert!(!is_hidpi_resolution((1920, 1080)));
+
+    assert!(is_valid_console_font("ter-132n"));
+    assert!(!is_valid_console_font("../ter-132n"));
+    assert_eq!(
+        vconsole_conf_with_font("KEYMAP=us\nFONT=lat9w-16\n", "ter-132n"),
+        "KEYMAP=us\nFONT=ter-132n\n"
+    );
+
+    assert!(is_valid_grub_gfxmode("1280x720,auto"));
+    assert!(is_valid_grub_gfxmode("1024x768x32"));
+    assert!(!is_valid_grub_gfxmode("1280x"));
+    assert!(!is_valid_grub_gfxmode("1280x720;reboot"));
+}
+
+#[test]
+fn test_autologin() {
+    assert_eq!(
+        normal_users("root:x:0:0::/root:/bin/bash\nsaki:x:1000:1001::/home/saki:/bin/bash\nnobody:x:65534:65534::/:/bin/false\n"),
+        vec!["saki".to_string()]
+    );
+    assert_eq!(
+        gdm_conf_with_autologin("[daemon]\nAutomaticLogin=foo\nWaylandEnable=false\n\n[security]\n", "saki"),
+        "[daemon]\nAutomaticLoginEnable=True\nAutomaticLogin=saki\nWaylandEnable=false\n\n[security]\n"
+    );
+    assert_eq!(
+        gdm_conf_with_autologin("", "saki"),
+        "[daemon]\nAutomaticLoginEnable=True\nAutomaticLogin=saki\n"
+    );
+}
+
+#[test]
+fn test_parse_shells() {
+    assert_eq!(
+        parse_shells(
+            "# Pathnames of valid login shells.\n/bin/sh\n/bin/bash\n/bin/sh\n\n/usr/bin/zsh\n"
+        ),
+        vec!["/bin/sh", "/bin/bash", "/usr/bin/zsh"]
+    );
+
+    assert!(variant_shells("Desktop").contains(&"/usr/bin/zsh".to_string()));
+    assert_eq!(variant_shells("Retro")[0], DEFAULT_SHELL);
+    assert!(is_valid_shell_path("/usr/local/bin/nu"));
+    assert!(!is_valid_shell_path("zsh"));
+    assert!(!is_valid_shell_path("/bin/"));
+    assert!(!is_valid_shell_path("/bin/my shell"));
+    assert!(!is_valid_shell_path("/bin/a:b"));
+}
+
+#[test]
+fn test_submounts() {
+    let mounts = [
+        ("/dev/sda2", "/"),
+        ("/dev/sdb1", "/tmp/.dkmount1"),
+        ("/dev/sdb2", "/tmp/.dkmount1/efi"),
+        ("udev", "/tmp/.dkmount1/dev"),
+        ("efivarfs", "/tmp/.dkmount1/sys/firmware/efi/efivars"),
+        ("/dev/sdc1", "/tmp/.dkmount10"),
+    ];
+    assert_eq!(
+        submounts(&mounts, Path::new("/tmp/.dkmount1")),
+        vec![
+            PathBuf::from("/tmp/.dkmount1/sys/firmware/efi/efivars"),
+            PathBuf::from("/tmp/.dkmount1/dev"),
+            PathBuf::from("/tmp/.dkmount1/efi"),
+            PathBuf::from("/tmp/.dkmount1"),
+        ]
+    );
+}
+
+#[test]
+fn test_wipe_keeping_mounts() {
+    let root = tempfile::tempdir().unwrap();
+    let root = root.path();
+    for dir in ["var/log", "var/cache", "etc", "home/saki"] {
+        std::fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    std::fs::write(root.join("var/log/boot.log"), "").unwrap();
+    std::fs::write(root.join("etc/fstab"), "").unwrap();
+    std::fs::write(root.join("init"), "").unwrap();
+
+    wipe_keeping_mounts(root, &[root.join("var/log")], &|name| name == "home").unwrap();
+    assert!(root.join("var/log/boot.log").exists());
+    assert!(!root.join("var/cache").exists());
+    assert!(!root.join("etc").exists());
+    assert!(!root.join("init").exists());
+    assert!(root.join("home/saki").exists());
+}
+
+#[test]
+fn test_assemble_fstab() {
+    let fstab = |entries: &[&str]| {
+        assemble_fstab(&entries.iter().map(|x| x.to_string()).collect::<Vec<_>>())
+    };
+
+    // plain ext4
+    assert_eq!(
+        fstab(&[
+            "UUID=1111 / ext4 defaults 0 1",
+            "UUID=2222 /efi vfat defaults,nofail 0 2",
+            SWAPFILE_FSTAB_ENTRY,
+        ])
+        .unwrap(),
+        format!("{FSTAB_HEADER}UUID=1111 / ext4 defaults 0 1\nUUID=2222 /efi vfat defaults,nofail 0 2\n/swapfile none swap defaults,nofail 0 0\n")
+    );
+    // btrfs subvolumes, nested mounts after their parents
+    assert_eq!(
+        fstab(&[
+            "UUID=1111 /var/log btrfs subvol=@log 0 0",
+            "UUID=1111 /home btrfs subvol=@home 0 0",
+            "UUID=1111 / btrfs subvol=@ 0 0",
+        ])
+        .unwrap(),
+        format!("{FSTAB_HEADER}UUID=1111 / btrfs subvol=@ 0 0\nUUID=1111 /home btrfs subvol=@home 0 0\nUUID=1111 /var/log btrfs subvol=@log 0 0\n")
+    );
+    // LUKS
+    assert_eq!(
+        fstab(&[
+            "UUID=2222 /efi vfat defaults,nofail 0 2",
+            "/dev/mapper/cryptroot / ext4 defaults 0 1",
+        ])
+        .unwrap(),
+        format!("{FSTAB_HEADER}/dev/mapper/cryptroot / ext4 defaults 0 1\nUUID=2222 /efi vfat defaults,nofail 0 2\n")
+    );
+    // separate /home carried over from the previous installation
+    assert_eq!(
+        fstab(&[
+            "UUID=1111 / xfs defaults 0 1",
+            SWAPFILE_FSTAB_ENTRY,
+            "UUID=3333 /home xfs defaults 0 2",
+        ])
+        .unwrap(),
+        format!("{FSTAB_HEADER}UUID=1111 / xfs defaults 0 1\nUUID=3333 /home xfs defaults 0 2\n/swapfile none swap defaults,nofail 0 0\n")
+    );
+
+    assert!(fstab(&["UUID=2222 /efi vfat defaults,nofail 0 2"]).is_err());
+    assert!(fstab(&[
+        "UUID=1111 / ext4 defaults 0 1",
+        "UUID=2222 / xfs defaults 0 1"
+    ])
+    .is_err());
+    assert!(fstab(&[
+        "UUID=1111 / ext4 defaults 0 1",
+        "UUID=1111 /home ext4 defaults 0 2"
+    ])
+    .is_err());
+    assert!(fstab(&["UUID=1111 /"]).is_err());
+}
+
+#[test]
+fn test_previous_install() {
+    let passwd = "root:x:0:0::/root:/bin/bash\nsaki:x:1000:1001::/home/saki:/bin/zsh\nmutsumi:x:1001:1002::/home/mutsumi:/bin/bash\nnobody:x:65534:65534::/:/bin/false\n";
+    let shadow = "root:!:19000::::::\nsaki:$6$salt$hash:19000::::::\nmutsumi::19000::::::\n";
+    let group = "wheel:x:1:saki\naudio:x:2:saki,mutsumi\nsaki:x:1001:\n";
+    assert_eq!(
+        parse_old_users(passwd, shadow, group),
+        vec![
+            OldUser {
+                name: "saki".to_string(),
+                uid: 1000,
+                gid: 1001,
+                group: Some("saki".to_string()),
+                home: "/home/saki".to_string(),
+                shell: "/bin/zsh".to_string(),
+                hash: Some("$6$salt$hash".to_string()),
+                groups: vec!["wheel".to_string(), "audio".to_string()],
+            },
+            OldUser {
+                name: "mutsumi".to_string(),
+                uid: 1001,
+                gid: 1002,
+                group: None,
+                home: "/home/mutsumi".to_string(),
+                shell: "/bin/bash".to_string(),
+                hash: None,
+                groups: vec!["audio".to_string()],
+            },
+        ]
+    );
+
+    assert_eq!(
+        fstab_home_entry("# /home was on /dev/sda3\nUUID=1234 / ext4 defaults 0 1\nUUID=5678 /home xfs defaults 0 2\n"),
+        Some("UUID=5678 /home xfs defaults 0 2".to_string())
+    );
+    assert_eq!(fstab_home_entry("UUID=1234 / ext4 defaults 0 1\n"), None);
+
+    // the primary group is re-created with the same GID, unless it is taken
+    let group = "root:x:0:\nwheel:x:1:\nsaki:x:1001:\n";
+    assert!(group_exists(group, "saki", 1001).unwrap());
+    assert!(!group_exists(group, "mutsumi", 1002).unwrap());
+    assert!(group_exists(group, "saki", 1002).is_err());
+    assert!(group_exists(group, "mutsumi", 1001).is_err());
+}
+
+#[test]
+fn test_parse_locale_identification() {
+    let data = "LC_IDENTIFICATION\ntitle      \"German locale for Germany\"\nlanguage   \"German\"\nterritory  \"Germany\"\nEND LC_IDENTIFICATION\nLC_ADDRESS\nlang_name \"Deutsch\"\nEND LC_ADDRESS\n";
+    assert_eq!(
+        parse_locale_identification(data),
+        Some(("German".to_string(), "Germany".to_string()))
+    );
+    assert_eq!(parse_locale_identification("title \"C\"\n"), None);
+}
+
+#[test]
+fn test_first_boot_script() {
+    let script = first_boot_script("root:x:0:\naudio:x:2:\ncdrom:x:3:\nvideo:x:4:\nwheel:x:10:\n");
+    assert!(script.contains("GROUPS_TO_JOIN=\"audio,cdrom,video,wheel\"\n"));
+    assert!(!script.contains("@GROUPS@"));
+    assert!(!script.contains("plugdev"));
+
+    let script = first_boot_script("root:x:0:\n");
+    assert!(script.contains("GROUPS_TO_JOIN=\"\"\n"));
+}
+
+#[test]
+fn test_probe_mount_options() {
+    assert_eq!(probe_mount_options("ext4"), "noload");
+    assert_eq!(probe_mount_options("xfs"), "norecovery");
+    assert_eq!(probe_mount_options("btrfs"), "rescue=nologreplay");
+    assert_eq!(probe_mount_options("vfat"), "");
+}
+
+#[test]
+fn test_write_validated() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("deploykit");
+    std::fs::write(&path, "old\n").unwrap();
+    assert!(write_validated(&path, "bad\n", 0o440, ("false", &[][..])).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    write_validated(&path, "good\n", 0o440, ("true", &[][..])).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "good\n");
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+        0o440
+    );
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
@@ -1,32 +1,198 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
-use log::info;
+use log::{error, info, warn, Level, LevelFilter};
+use once_cell::sync::{Lazy, OnceCell};
 use time::OffsetDateTime;
 
-/// Log message to console and file
-pub fn setup_logger(is_cli: bool) -> Result<PathBuf> {
+use crate::LOG_FILE;
+
+/// Where the log files are written by default
+const LOG_DIR: &str = "/var/log/deploykit";
+/// Where the installation log is kept in the installed system
+const TARGET_LOG_PATH: &str = "var/log/deploykit/install.log";
+const TMP_LOG_PATH: &str = "/tmp/deploykit-install.log";
+/// The live medium, as mounted by dracut's dmsquash-live module
+pub const LIVE_MEDIUM_PATH: &str = "/run/initramfs/live";
+const STEP_STATS_NAME: &str = "step-stats.json";
+const TMP_STEP_STATS_PATH: &str = "/tmp/deploykit-step-stats.json";
+/// Weight of the latest run in the step statistics
+const STATS_WEIGHT: f64 = 0.5;
+/// Bounds of how much slower or faster this machine is taken to be than the statistics
+const MIN_SCALE: f64 = 0.25;
+const MAX_SCALE: f64 = 4.0;
+/// The estimate shown keeps counting down unless it has gone up by more than this, so that
+/// it does not bounce around (e.g., while the unpack speed varies)
+const ESTIMATE_SLACK: Duration = Duration::from_secs(60);
+/// Lines of the log kept in memory for the log pane of the TUI, older ones are dropped
+const MAX_LOG_LINES: usize = 2000;
+/// Longer lines (e.g., the whole output of a command) are cut short in memory
+const MAX_LOG_LINE_LENGTH: usize = 512;
+
+static LOG_BUFFER: Lazy<Mutex<LogBuffer>> = Lazy::new(Default::default);
+static VERBOSITY: OnceCell<Verbosity> = OnceCell::new();
+
+/// The latest lines of the log
+#[derive(Default)]
+struct LogBuffer {
+    lines: VecDeque<String>,
+    /// How many lines have been written in total, including the dropped ones
+    written: u64,
+}
+
+impl LogBuffer {
+    fn push(&mut self, message: &str) {
+        for line in message.lines() {
+            let mut line = line.to_string();
+            if let Some((index, _)) = line.char_indices().nth(MAX_LOG_LINE_LENGTH) {
+                line.truncate(index);
+                line.push_str(" ...");
+            }
+            if self.lines.len() >= MAX_LOG_LINES {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line);
+            self.written += 1;
+        }
+    }
+}
+
+/// The latest lines of the log (at most `MAX_LOG_LINES`), and how many have been written
+pub fn log_lines() -> (Vec<String>, u64) {
+    let buffer = LOG_BUFFER.lock().unwrap();
+
+    (buffer.lines.iter().cloned().collect(), buffer.written)
+}
+
+/// How many lines have been written to the log so far
+pub fn written_log_lines() -> u64 {
+    LOG_BUFFER.lock().unwrap().written
+}
+
+/// How much of the log the CLI prints, set by `-q` and `-v`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// Nothing but the errors and the result
+    Quiet,
+    /// The warnings as well
+    #[default]
+    Normal,
+    /// What the installer is doing (-v)
+    Verbose,
+    /// The commands run, with their whole output (-vv)
+    Debug,
+}
+
+impl Verbosity {
+    pub fn new(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+
+    /// Of the log printed to stderr, the errors are left out as they end up in the result
+    fn console_level(self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::Off,
+            Verbosity::Normal => LevelFilter::Warn,
+            Verbosity::Verbose => LevelFilter::Info,
+            Verbosity::Debug => LevelFilter::Debug,
+        }
+    }
+}
+
+/// Whether `-q` has been given, the CLI then prints nothing but the errors and the result
+pub fn is_quiet() -> bool {
+    VERBOSITY.get() == Some(&Verbosity::Quiet)
+}
+
+/// Where the log is written without `--log-file`, only when running as root
+fn default_log_path(is_cli: bool) -> Option<PathBuf> {
+    if !rustix::process::geteuid().is_root() {
+        return None;
+    }
     let now = OffsetDateTime::now_utc();
-    let path = Path::new(&format!("/var/log/dklog-{now}.log")).to_path_buf();
+    let name = format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}.log",
+        if is_cli { "cli" } else { "tui" },
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    );
 
-    let fern_log = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            let now = OffsetDateTime::now_utc();
-            out.finish(format_args!(
-                "{}[{}][{}] {}",
-                now,
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Info)
-        .chain(fern::log_file(&path)?);
+    Some(Path::new(LOG_DIR).join(name))
+}
 
+/// Log to the log file (`log_file`, or the default one), and to the console as much as
+/// `verbosity` says or to the log pane of the TUI, returns where the log file is
+///
+/// The log file always has everything down to the output of the commands run, the
+/// dependencies (e.g., reqwest) are only logged from `info` up.
+pub fn setup_logger(
+    is_cli: bool,
+    verbosity: Verbosity,
+    log_file: Option<&Path>,
+) -> Result<Option<PathBuf>> {
+    VERBOSITY.set(verbosity).ok();
+    let path = log_file
+        .map(|x| x.to_path_buf())
+        .or_else(|| default_log_path(is_cli));
+
+    let mut logged = fern::Dispatch::new().format(move |out, message, record| {
+        let now = OffsetDateTime::now_utc();
+        out.finish(format_args!(
+            "{}[{}][{}] {}",
+            now,
+            record.target(),
+            record.level(),
+            message
+        ))
+    });
+    if let Some(path) = path.as_ref() {
+        if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        logged = logged.chain(fern::log_file(path)?);
+    }
+
+    let fern_log = fern::Dispatch::new()
+        .level(LevelFilter::Info)
+        .level_for(env!("CARGO_CRATE_NAME"), LevelFilter::Debug);
     let fern_log = if !is_cli {
-        fern_log.chain(Box::new(cursive::logger::get_logger()) as Box<dyn log::Log>)
+        fern_log.chain(
+            logged
+                .chain(Box::new(cursive::logger::get_logger()) as Box<dyn log::Log>)
+                .chain(fern::Output::call(|record| {
+                    LOG_BUFFER.lock().unwrap().push(&record.args().to_string())
+                })),
+        )
     } else {
-        fern_log
+        fern_log.chain(logged).chain(
+            fern::Dispatch::new()
+                .level(verbosity.console_level())
+                .filter(|x| x.level() != Level::Error)
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        "{}: {}",
+                        record.level().as_str().to_lowercase(),
+                        message
+                    ))
+                })
+                .chain(std::io::stderr()),
+        )
     };
 
     fern_log.apply()?;
@@ -38,3 +204,424 @@ pub fn setup_logger(is_cli: bool) -> Result<PathBuf> {
 
     Ok(path)
 }
+
+/// Log how long each installation step took, and estimate how long the rest will take
+#[derive(Default)]
+pub struct StepTimer {
+    step: Option<(&'static str, Instant)>,
+    /// The steps expected to run, with how many seconds each is expected to take
+    plan: Vec<(&'static str, f64)>,
+    /// Steps taking as long as the network, rather than the machine, makes them
+    network_steps: Vec<&'static str>,
+    /// The stable names of the steps (as in `--from-step`) the statistics are kept under, so
+    /// that they survive rewording the step labels
+    names: Vec<(&'static str, &'static str)>,
+    done: Vec<(&'static str, Duration)>,
+    /// Remaining time of the current step as measured by the step, e.g., from the download speed
+    current_remaining: Option<Duration>,
+    /// Whether the plan comes from previous runs, rather than rough defaults
+    has_stats: bool,
+    /// The last estimate given out by `estimate`, and when
+    shown: Option<(Instant, Duration)>,
+}
+
+impl StepTimer {
+    /// Start timing the planned `steps`, the defaults are replaced by the durations recorded
+    /// in previous runs under the names given by `names` as (step, name) pairs
+    pub fn new(
+        steps: &[(&'static str, f64)],
+        network_steps: &[&'static str],
+        names: &[(&'static str, &'static str)],
+    ) -> Self {
+        let mut timer = StepTimer {
+            network_steps: network_steps.to_vec(),
+            names: names.to_vec(),
+            ..Default::default()
+        };
+        let stats = load_step_stats();
+        let recorded = |step: &str| stats.get(timer.name(step)?).copied();
+        let has_stats = steps.iter().any(|(step, _)| recorded(step).is_some());
+        let plan = steps
+            .iter()
+            .map(|(step, default)| (*step, recorded(step).unwrap_or(*default)))
+            .collect::<Vec<_>>();
+        info!("Expected step durations: {:?}", plan);
+        timer.plan = plan;
+        timer.has_stats = has_stats;
+
+        timer
+    }
+
+    /// The name the statistics of `step` are kept under
+    fn name(&self, step: &str) -> Option<&'static str> {
+        self.names
+            .iter()
+            .find(|(x, _)| *x == step)
+            .map(|(_, name)| *name)
+    }
+
+    /// The planned steps with how many seconds each is expected to take
+    pub fn plan(&self) -> &[(&'static str, f64)] {
+        &self.plan
+    }
+
+    /// Finish the current step (if any) and start `step`
+    pub fn next(&mut self, step: &'static str) {
+        self.finish();
+        info!("{}", step);
+        self.step = Some((step, Instant::now()));
+    }
+
+    pub fn finish(&mut self) {
+        if let Some((step, start)) = self.step.take() {
+            info!("{} took {:.1?}", step, start.elapsed());
+            self.done.push((step, start.elapsed()));
+        }
+        self.current_remaining = None;
+    }
+
+    /// Set the remaining time of the current step, as measured by the step itself
+    pub fn set_remaining(&mut self, remaining: Duration) {
+        self.current_remaining = Some(remaining);
+    }
+
+    /// Estimate how long the installation will take to finish: the expected durations of the
+    /// steps left are scaled by how fast the finished steps have been on this machine
+    pub fn remaining(&self) -> Option<Duration> {
+        let (step, start) = self.step?;
+        let index = self.plan.iter().position(|(x, _)| *x == step)?;
+        let expected = |step: &str| self.plan.iter().find(|(x, _)| *x == step).map(|(_, x)| *x);
+
+        let (actual, planned) = self
+            .done
+            .iter()
+            .filter(|(step, _)| !self.network_steps.contains(step))
+            .filter_map(|(step, took)| Some((took.as_secs_f64(), expected(*step)?)))
+            .fold((0.0, 0.0), |acc, x| (acc.0 + x.0, acc.1 + x.1));
+        let scale = if planned > 0.0 {
+            (actual / planned).clamp(MIN_SCALE, MAX_SCALE)
+        } else {
+            1.0
+        };
+
+        let scaled = |step: &str, expected: f64| {
+            if self.network_steps.iter().any(|x| *x == step) {
+                expected
+            } else {
+                expected * scale
+            }
+        };
+        let current = match self.current_remaining {
+            Some(remaining) => remaining.as_secs_f64(),
+            None => (scaled(step, self.plan[index].1) - start.elapsed().as_secs_f64()).max(0.0),
+        };
+        let upcoming = self.plan[index + 1..]
+            .iter()
+            .map(|(step, x)| scaled(*step, *x))
+            .sum::<f64>();
+
+        Duration::try_from_secs_f64(current + upcoming).ok()
+    }
+
+    /// The remaining time to show, `None` without statistics from previous runs, as the
+    /// defaults are too rough a guess. It keeps counting down from the last estimate shown
+    /// unless the new one is more than `ESTIMATE_SLACK` longer, so it ends up at "less than
+    /// a minute" near the end rather than bouncing between small numbers
+    pub fn estimate(&mut self) -> Option<Duration> {
+        if !self.has_stats {
+            return None;
+        }
+        let remaining = self.remaining()?;
+        if let Some((at, shown)) = self.shown {
+            let counted_down = shown.saturating_sub(at.elapsed());
+            if remaining >= counted_down && remaining <= counted_down + ESTIMATE_SLACK {
+                return Some(counted_down);
+            }
+        }
+        self.shown = Some((Instant::now(), remaining));
+
+        Some(remaining)
+    }
+
+    /// Blend the durations of the finished steps into `stats`, steps without a name are
+    /// left out
+    fn blend_stats(&self, stats: &mut HashMap<String, f64>) {
+        for (step, took) in &self.done {
+            let Some(name) = self.name(step) else {
+                continue;
+            };
+            let took = took.as_secs_f64();
+            let value = match stats.get(name) {
+                Some(old) => old * (1.0 - STATS_WEIGHT) + took * STATS_WEIGHT,
+                None => took,
+            };
+            stats.insert(name.to_string(), value);
+        }
+    }
+
+    /// Blend the durations of the finished steps into the statistics for future estimates
+    pub fn save_stats(&self) {
+        let mut stats = load_step_stats();
+        self.blend_stats(&mut stats);
+
+        let data = match serde_json::to_vec_pretty(&stats) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Failed to serialize the step statistics: {}", e);
+                return;
+            }
+        };
+        for path in step_stats_paths() {
+            match write_log(&path, &data) {
+                Ok(()) => info!("Step statistics saved to {}", path.display()),
+                Err(e) => warn!(
+                    "Failed to save the step statistics to {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+/// How a step of the installation went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// Not run (yet), e.g., after an earlier step has failed
+    NotRun,
+    Succeeded,
+    Failed(String),
+}
+
+/// The outcome of each step, for the finish screen to tell what has been done and what
+/// has to be fixed
+#[derive(Debug, Clone, Default)]
+pub struct StepJournal {
+    steps: Vec<(&'static str, StepOutcome)>,
+}
+
+impl StepJournal {
+    /// A journal of the planned `steps`, none of which has run
+    pub fn new(steps: &[&'static str]) -> Self {
+        StepJournal {
+            steps: steps.iter().map(|x| (*x, StepOutcome::NotRun)).collect(),
+        }
+    }
+
+    /// Record the outcome of `step`, replacing the one of an earlier run of it
+    pub fn record(&mut self, step: &'static str, outcome: StepOutcome) {
+        match &outcome {
+            StepOutcome::Failed(e) => error!("{} failed: {}", step, e),
+            StepOutcome::Succeeded => info!("{} succeeded", step),
+            StepOutcome::NotRun => (),
+        }
+        match self.steps.iter_mut().find(|(x, _)| *x == step) {
+            Some((_, x)) => *x = outcome,
+            None => self.steps.push((step, outcome)),
+        }
+    }
+
+    /// Run `step` and record how it went
+    pub fn run<T>(&mut self, step: &'static str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let result = f();
+        self.record(
+            step,
+            match &result {
+                Ok(_) => StepOutcome::Succeeded,
+                Err(e) => StepOutcome::Failed(e.to_string()),
+            },
+        );
+
+        result
+    }
+
+    pub fn steps(&self) -> &[(&'static str, StepOutcome)] {
+        &self.steps
+    }
+
+    pub fn contains(&self, step: &str) -> bool {
+        self.steps.iter().any(|(x, _)| *x == step)
+    }
+
+    /// Whether any step has failed, or not run because of that
+    pub fn is_incomplete(&self) -> bool {
+        self.steps.iter().any(|(_, x)| *x != StepOutcome::Succeeded)
+    }
+}
+
+/// Where the step statistics are kept, the live medium (if any) keeps them across boots
+fn step_stats_paths() -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if Path::new(LIVE_MEDIUM_PATH).is_dir() {
+        paths.push(
+            Path::new(LIVE_MEDIUM_PATH)
+                .join("deploykit")
+                .join(STEP_STATS_NAME),
+        );
+    }
+    paths.push(PathBuf::from(TMP_STEP_STATS_PATH));
+
+    paths
+}
+
+fn load_step_stats() -> HashMap<String, f64> {
+    step_stats_paths()
+        .iter()
+        .find_map(|x| serde_json::from_slice(&std::fs::read(x).ok()?).ok())
+        .unwrap_or_default()
+}
+
+/// Save a copy of the installation log into the installed system at `target` (if any),
+/// /tmp, and the live medium (if writable), returns where the log has been saved to
+///
+/// Failing to write any of the copies (e.g., read-only or full filesystems) is not fatal.
+pub fn save_log(target: Option<&Path>) -> Vec<PathBuf> {
+    let log = match LOG_FILE.get() {
+        Some(log) => log,
+        None => return vec![],
+    };
+    let data = match std::fs::read(log) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to read the installation log: {}", e);
+            return vec![];
+        }
+    };
+
+    let mut paths = vec![];
+    if let Some(target) = target {
+        paths.push(target.join(TARGET_LOG_PATH));
+    }
+    paths.push(PathBuf::from(TMP_LOG_PATH));
+    if let Some(name) = log.file_name() {
+        if Path::new(LIVE_MEDIUM_PATH).is_dir() {
+            paths.push(Path::new(LIVE_MEDIUM_PATH).join("deploykit").join(name));
+        }
+    }
+
+    paths
+        .into_iter()
+        .filter(|path| match write_log(path, &data) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(
+                    "Failed to save the installation log to {}: {}",
+                    path.display(),
+                    e
+                );
+                // do not leave a truncated copy behind on a full filesystem
+                std::fs::remove_file(path).ok();
+                false
+            }
+        })
+        .collect()
+}
+
+pub fn write_log(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut f = File::create(path)?;
+    f.write_all(data)?;
+    f.sync_all()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_remaining() {
+    let mut timer = StepTimer {
+        plan: vec![("format", 10.0), ("download", 100.0), ("unpack", 30.0)],
+        network_steps: vec!["download"],
+        ..Default::default()
+    };
+    assert_eq!(timer.remaining(), None);
+
+    // twice as slow as expected so far
+    timer.done.push(("format", Duration::from_secs(20)));
+    timer.step = Some(("download", Instant::now()));
+    let remaining = timer.remaining().unwrap().as_secs_f64();
+    assert!((159.0..=160.0).contains(&remaining));
+
+    timer.set_remaining(Duration::from_secs(10));
+    let remaining = timer.remaining().unwrap().as_secs_f64();
+    assert!((69.0..=70.0).contains(&remaining));
+
+    // only shown with statistics, and not going up by a little
+    assert_eq!(timer.estimate(), None);
+    timer.has_stats = true;
+    let shown = timer.estimate().unwrap();
+    timer.set_remaining(Duration::from_secs(40));
+    assert!(timer.estimate().unwrap() <= shown);
+    timer.set_remaining(Duration::from_secs(300));
+    assert!(timer.estimate().unwrap() > shown);
+}
+
+#[test]
+fn test_blend_stats() {
+    let mut timer = StepTimer {
+        names: vec![("Step 1 of 8: Formatting partitions", "partition")],
+        ..Default::default()
+    };
+    timer.done.push((
+        "Step 1 of 8: Formatting partitions",
+        Duration::from_secs(20),
+    ));
+    timer.done.push(("Some other step", Duration::from_secs(5)));
+
+    let mut stats = HashMap::new();
+    timer.blend_stats(&mut stats);
+    assert_eq!(stats, HashMap::from([("partition".to_string(), 20.0)]));
+    timer.blend_stats(&mut stats);
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats["partition"], 20.0);
+}
+
+#[test]
+fn test_step_journal() {
+    let mut journal = StepJournal::new(&["unpack", "bootloader", "finalise"]);
+    assert!(journal.is_incomplete());
+    journal.record("unpack", StepOutcome::Succeeded);
+    assert!(journal
+        .run("bootloader", || anyhow::bail!("no ESP"))
+        .is_err());
+    assert_eq!(
+        journal.steps()[1],
+        ("bootloader", StepOutcome::Failed("no ESP".to_string()))
+    );
+    assert_eq!(journal.steps()[2].1, StepOutcome::NotRun);
+
+    // a re-run replaces the outcome
+    journal.run("bootloader", || Ok(())).unwrap();
+    journal.run("finalise", || Ok(())).unwrap();
+    journal.run("fstab", || Ok(())).unwrap();
+    assert!(!journal.is_incomplete());
+    assert!(journal.contains("fstab"));
+}
+
+#[test]
+fn test_log_buffer() {
+    let mut buffer = LogBuffer::default();
+    buffer.push("first\nsecond");
+    buffer.push(&"x".repeat(MAX_LOG_LINE_LENGTH + 10));
+    assert_eq!(buffer.written, 3);
+    assert_eq!(buffer.lines[1], "second");
+    assert_eq!(buffer.lines[2].len(), MAX_LOG_LINE_LENGTH + 4);
+
+    for i in 0..MAX_LOG_LINES {
+        buffer.push(&i.to_string());
+    }
+    assert_eq!(buffer.lines.len(), MAX_LOG_LINES);
+    assert_eq!(buffer.written, MAX_LOG_LINES as u64 + 3);
+    assert_eq!(buffer.lines[0], "0");
+}
+
+#[test]
+fn test_verbosity() {
+    assert_eq!(Verbosity::new(false, 0), Verbosity::Normal);
+    assert_eq!(Verbosity::new(false, 2), Verbosity::Debug);
+    assert_eq!(Verbosity::new(false, 5), Verbosity::Debug);
+    assert_eq!(Verbosity::new(true, 0), Verbosity::Quiet);
+    assert_eq!(Verbosity::Verbose.console_level(), LevelFilter::Info);
+    assert_eq!(Verbosity::Quiet.console_level(), LevelFilter::Off);
+}
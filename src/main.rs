@@ -1,51 +1,70 @@
 use anyhow::{anyhow, Result};
-use log::setup_logger;
+use log::{setup_logger, Verbosity};
 use once_cell::sync::OnceCell;
 use std::{
     io::{Read, Write},
     path::{Path, PathBuf},
     str::FromStr,
+    thread::ThreadId,
 };
 use sysinfo::{Pid, System};
 
-use clap::Parser;
+use exit_code::ExitCode;
 use frontend::Args;
 
+mod boards;
+mod cancel;
 mod disks;
+mod exit_code;
 mod frontend;
+mod i18n;
 mod install;
 mod log;
+mod manifest;
 mod network;
 mod parser;
+mod report;
+mod wifi;
 
 const LOCK: &str = "/run/lock/aoscdk.lock";
 
+/// The log file, if there is one: non-root users only get one with `--log-file`
 pub static LOG_FILE: OnceCell<PathBuf> = OnceCell::new();
+/// The thread running the event loop of the TUI, which must never be blocked
+pub static UI_THREAD: OnceCell<ThreadId> = OnceCell::new();
 
 fn main() {
     if let Err(e) = create_lock() {
-        eprintln!("Installer failed to obtain the instance lock: {e}");
-        std::process::exit(1);
+        let code = ExitCode::LockConflict;
+        eprintln!(
+            "{}: Installer failed to obtain the instance lock: {e}",
+            code.name()
+        );
+        std::process::exit(code.code());
     }
 
     if let Err(e) = execute() {
-        eprintln!("{e}");
+        let code = ExitCode::of_error(&e);
+        eprintln!("{}: {e}", code.name());
         remove_lock().ok();
-        std::process::exit(1);
+        std::process::exit(code.code());
     }
     remove_lock().ok();
     std::process::exit(0);
 }
 
 fn execute() -> Result<()> {
-    let args = std::env::args();
-    if args.len() < 2 {
-        LOG_FILE.get_or_try_init(|| setup_logger(false))?;
-        frontend::tui_main();
-    } else {
-        let args = Args::parse();
-        LOG_FILE.get_or_try_init(|| setup_logger(true))?;
-        frontend::execute(args)?;
+    // without arguments, this starts the TUI
+    let args = Args::parse_with_sources();
+    init_log(!args.is_tui(), args.verbosity(), args.log_file())?;
+    frontend::execute(args)?;
+
+    Ok(())
+}
+
+fn init_log(is_cli: bool, verbosity: Verbosity, log_file: Option<&Path>) -> Result<()> {
+    if let Some(path) = setup_logger(is_cli, verbosity, log_file)? {
+        LOG_FILE.set(path).ok();
     }
 
     Ok(())
@@ -82,3 +101,12 @@ fn remove_lock() -> Result<()> {
 
     Ok(())
 }
+
+/// Flag a blocking (network or disk) operation run on the UI thread in debug builds, it
+/// freezes the interface until it returns
+pub fn assert_not_ui_thread(operation: &str) {
+    debug_assert!(
+        UI_THREAD.get() != Some(&std::thread::current().id()),
+        "{operation} blocks the UI thread"
+    );
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, bail, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Expected SHA-256 checksums of the regular files, relative to the root of the installed system
+pub type Manifest = Vec<(PathBuf, String)>;
+
+/// A file in the installed system that does not match the manifest
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Missing(PathBuf),
+    Corrupted(PathBuf),
+    Unreadable(PathBuf, String),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing(path) => write!(f, "/{} is missing", path.display()),
+            Mismatch::Corrupted(path) => write!(f, "/{} is corrupted", path.display()),
+            Mismatch::Unreadable(path, e) => {
+                write!(f, "/{} could not be read: {}", path.display(), e)
+            }
+        }
+    }
+}
+
+/// Parse a manifest in the `sha256sum` format, e.g., `<hash>  ./usr/bin/bash`
+pub fn parse_manifest(text: &str) -> Result<Manifest> {
+    let mut manifest = vec![];
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || anyhow!("Invalid line {} in the file manifest: {}", index + 1, line);
+        let (hash, path) = line.split_once(' ').ok_or_else(invalid)?;
+        // binary mode is marked with `*` in place of the second space
+        let path = path
+            .strip_prefix(|c: char| c == ' ' || c == '*')
+            .ok_or_else(invalid)?;
+        if hash.len() != 64 || !hash.bytes().all(|x| x.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let path = relative_path(Path::new(path)).ok_or_else(invalid)?;
+        manifest.push((path, hash.to_ascii_lowercase()));
+    }
+
+    if manifest.is_empty() {
+        bail!("The file manifest is empty.");
+    }
+
+    Ok(manifest)
+}
+
+/// Strip the leading `./` or `/` off `path`, and refuse paths escaping the root
+fn relative_path(path: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(x) => relative.push(x),
+            Component::RootDir | Component::CurDir => (),
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    (!relative.as_os_str().is_empty()).then_some(relative)
+}
+
+/// The regular file being read through by the tar archive
+struct EntryHasher {
+    start: u64,
+    end: u64,
+    hasher: Sha256,
+}
+
+/// Hashes the data of the current tar entry as the archive reads past it
+struct HashingReader<R> {
+    inner: R,
+    pos: u64,
+    current: Rc<RefCell<Option<EntryHasher>>>,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if let Some(entry) = self.current.borrow_mut().as_mut() {
+            let start = entry.start.max(self.pos);
+            let end = entry.end.min(self.pos + len as u64);
+            if start < end {
+                entry
+                    .hasher
+                    .update(&buf[(start - self.pos) as usize..(end - self.pos) as usize]);
+            }
+        }
+        self.pos += len as u64;
+
+        Ok(len)
+    }
+}
+
+/// Unpack the tar stream into `path` (preserving all the file attributes) like
+/// `tar::Archive::unpack` does, and record the checksums of the regular files on the way
+pub fn unpack_tar<R: Read>(reader: R, path: &Path) -> Result<Manifest> {
+    let current = Rc::new(RefCell::new(None));
+    let mut archive = tar::Archive::new(HashingReader {
+        inner: reader,
+        pos: 0,
+        current: Rc::clone(&current),
+    });
+    archive.set_unpack_xattrs(true);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_ownerships(true);
+
+    let mut manifest = vec![];
+    let mut directories = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        match entry_type {
+            // directories are unpacked last, so that their permissions and mtime stick
+            tar::EntryType::Directory => directories.push(entry),
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let start = entry.raw_file_position();
+                current.replace(Some(EntryHasher {
+                    start,
+                    end: start + entry.size(),
+                    hasher: Sha256::new(),
+                }));
+                let name = relative_path(&entry.path()?);
+                entry.unpack_in(path)?;
+                let hasher = current.take().unwrap().hasher;
+                if let Some(name) = name {
+                    manifest.push((name, hex::encode(hasher.finalize())));
+                }
+            }
+            _ => {
+                entry.unpack_in(path)?;
+            }
+        }
+    }
+
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut directory in directories {
+        directory.unpack_in(path)?;
+    }
+
+    Ok(manifest)
+}
+
+fn check_file(root: &Path, path: &Path, hash: &str) -> Option<Mismatch> {
+    let full_path = root.join(path);
+    let unreadable = |e: io::Error| Mismatch::Unreadable(path.to_path_buf(), e.to_string());
+    match std::fs::symlink_metadata(&full_path) {
+        Ok(metadata) if !metadata.is_file() => return Some(Mismatch::Corrupted(path.into())),
+        Ok(_) => (),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Some(Mismatch::Missing(path.into()))
+        }
+        Err(e) => return Some(unreadable(e)),
+    }
+
+    let mut hasher = Sha256::new();
+    let copied = std::fs::File::open(&full_path).and_then(|mut f| io::copy(&mut f, &mut hasher));
+    if let Err(e) = copied {
+        return Some(unreadable(e));
+    }
+
+    (hex::encode(hasher.finalize()) != hash).then(|| Mismatch::Corrupted(path.into()))
+}
+
+/// Re-hash the files under `root` on `threads` worker threads and return those not matching
+/// the manifest, `checked` counts the files done so far for the progress, and setting `stop`
+/// makes the workers return early
+pub fn verify(
+    root: &Path,
+    manifest: &[(PathBuf, String)],
+    threads: usize,
+    checked: &AtomicUsize,
+    stop: &AtomicBool,
+) -> Vec<Mismatch> {
+    let next = &AtomicUsize::new(0);
+    let mut mismatches = std::thread::scope(|s| {
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                s.spawn(move || {
+                    std::iter::from_fn(|| {
+                        if stop.load(Ordering::SeqCst) {
+                            return None;
+                        }
+                        manifest.get(next.fetch_add(1, Ordering::SeqCst))
+                    })
+                    .filter_map(|(path, hash)| {
+                        let mismatch = check_file(root, path, hash);
+                        checked.fetch_add(1, Ordering::SeqCst);
+
+                        mismatch
+                    })
+                    .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|x| x.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    mismatches.sort_by(|a, b| format!("{a}").cmp(&format!("{b}")));
+
+    mismatches
+}
+
+#[test]
+fn test_parse_manifest() {
+    let hash = hex::encode(Sha256::digest(b"hello\n"));
+    let manifest =
+        parse_manifest(&format!("{hash}  ./usr/bin/hello\n\n{hash} */etc/hello\n")).unwrap();
+    assert_eq!(
+        manifest,
+        vec![
+            (PathBuf::from("usr/bin/hello"), hash.clone()),
+            (PathBuf::from("etc/hello"), hash.clone()),
+        ]
+    );
+
+    assert!(parse_manifest("").is_err());
+    assert!(parse_manifest(&format!("{hash}  ../etc/shadow")).is_err());
+    assert!(parse_manifest("deadbeef  ./usr/bin/hello").is_err());
+    assert!(parse_manifest(&format!("{hash}./usr/bin/hello")).is_err());
+}
+
+#[test]
+fn test_unpack_and_verify() {
+    let mut builder = tar::Builder::new(vec![]);
+    for (path, data) in [("usr/bin/hello", &b"hello\n"[..]), ("etc/empty", &b""[..])] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        // ownerships are preserved when unpacking
+        header.set_uid(rustix::process::getuid().as_raw() as u64);
+        header.set_gid(rustix::process::getgid().as_raw() as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive = builder.into_inner().unwrap();
+
+    let root = tempfile::tempdir().unwrap();
+    let manifest = unpack_tar(archive.as_slice(), root.path()).unwrap();
+    assert_eq!(
+        manifest,
+        vec![
+            (
+                PathBuf::from("usr/bin/hello"),
+                hex::encode(Sha256::digest(b"hello\n"))
+            ),
+            (PathBuf::from("etc/empty"), hex::encode(Sha256::digest(b""))),
+        ]
+    );
+
+    let checked = AtomicUsize::new(0);
+    assert!(verify(root.path(), &manifest, 2, &checked, &AtomicBool::new(false)).is_empty());
+    assert_eq!(checked.load(Ordering::SeqCst), 2);
+
+    std::fs::write(root.path().join("usr/bin/hello"), b"hellO\n").unwrap();
+    std::fs::remove_file(root.path().join("etc/empty")).unwrap();
+    assert_eq!(
+        verify(
+            root.path(),
+            &manifest,
+            2,
+            &AtomicUsize::new(0),
+            &AtomicBool::new(false)
+        ),
+        vec![
+            Mismatch::Missing(PathBuf::from("etc/empty")),
+            Mismatch::Corrupted(PathBuf::from("usr/bin/hello")),
+        ]
+    );
+    assert!(verify(root.path(), &manifest, 2, &checked, &AtomicBool::new(true)).is_empty());
+}
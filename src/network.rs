@@ -1,16 +1,44 @@
 use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use log::{info, warn};
 use reqwest::{self, Client, Url};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
-    io::Write,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
     time::{Duration, Instant},
 };
 
+use crate::exit_code::ExitCode;
+
 const MANIFEST_URL: &str = "https://releases.aosc.io/manifest/recipe.json";
+const RELEASES_URL: &str = "https://releases.aosc.io/";
+const DEFAULT_REPO_URL: &str = "https://repo.aosc.io/debs";
+/// Over HTTPS, the public IP address is not sent in the clear and the reply can not be
+/// spoofed on the way
+const GEOIP_TIMEZONE_URL: &str = "https://ipapi.co/timezone/";
 const IS_RETRO: bool = cfg!(feature = "is_retro");
+/// Where a cancelled download is kept to be resumed later, outside of the target partition
+/// which would be formatted again
+const PARTIAL_DOWNLOAD_DIR: &str = "/var/cache/deploykit";
+const RECIPE_CACHE_FILE: &str = "recipe.json";
+const NET_CLASS_PATH: &str = "/sys/class/net";
+const PROXY_ENV_VARS: &[&str] = &["http_proxy", "https_proxy", "HTTP_PROXY", "HTTPS_PROXY"];
 const SPEEDTEST_FILE_CHECKSUM: &str =
     "30e14955ebf1352266dc2ff8067e68104607e750abb9d3b36582b8af909fcb58";
+/// How many mirrors are tested at once
+const SPEEDTEST_JOBS: usize = 4;
+
+/// The proxy set by the user, as reqwest only reads the environment once
+static PROXY: Mutex<Option<String>> = Mutex::new(None);
+/// The release manifest set by the user, instead of `MANIFEST_URL`
+static MANIFEST: Mutex<Option<String>> = Mutex::new(None);
 
 #[macro_export]
 macro_rules! DEPLOYKIT_USER_AGENT {
@@ -29,6 +57,28 @@ pub struct Mirror {
     #[serde(rename = "loc-tr")]
     pub loc_tr: String,
     pub url: String,
+    /// URL of the package repository on this mirror, if the manifest provides it
+    #[serde(rename = "repo-url", default)]
+    pub repo_url: Option<String>,
+}
+
+impl Mirror {
+    /// The package repository (the `debs` directory) on this mirror, to be used by the
+    /// installed system, or `None` if it can not be told from the release URL
+    pub fn package_repo(&self) -> Option<String> {
+        if let Some(repo_url) = self.repo_url.as_ref() {
+            return Some(repo_url.trim_end_matches('/').to_string());
+        }
+        if self.url == RELEASES_URL {
+            return Some(DEFAULT_REPO_URL.to_string());
+        }
+
+        // mirrors of AOSC OS are laid out as /anthon/{aosc-os,debs}
+        self.url
+            .trim_end_matches('/')
+            .strip_suffix("/anthon/aosc-os")
+            .map(|x| format!("{x}/anthon/debs"))
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -66,6 +116,9 @@ struct SystemRootFs {
     inst_size: i64,
     path: String,
     sha256sum: String,
+    /// Path to the per-file checksum manifest (in `sha256sum` format), if the release has one
+    #[serde(default)]
+    manifest: Option<String>,
     // inodes: Option<u32>,
 }
 
@@ -80,8 +133,26 @@ pub struct Variant {
     squashfs: Vec<SystemRootFs>,
 }
 
-#[derive(Deserialize, Debug)]
+/// How much a release bulletin stands in the way of installing, by its `type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BulletinLevel {
+    /// Nothing to show (`none`)
+    None,
+    /// A notice to be shown (`info`, and any type not known)
+    Info,
+    /// Has to be acknowledged before installing (`warning`)
+    Warning,
+    /// The release is known to be broken, e.g., it does not boot on some devices (`critical`):
+    /// it has to be read to the end before being acknowledged, and unattended installs have to
+    /// acknowledge it by its ID
+    Critical,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Bulletin {
+    /// Identifies the bulletin for acknowledging it, the hash of its contents if not given
+    #[serde(default)]
+    pub id: Option<String>,
     #[serde(rename = "type")]
     pub type_: String,
     pub title: String,
@@ -92,6 +163,44 @@ pub struct Bulletin {
     pub body_tr: String,
 }
 
+impl Bulletin {
+    /// Whether there is a notice to show, the recipe has a `none` bulletin otherwise
+    pub fn is_empty(&self) -> bool {
+        self.type_ == "none" || (self.title.trim().is_empty() && self.body.trim().is_empty())
+    }
+
+    pub fn level(&self) -> BulletinLevel {
+        if self.is_empty() {
+            return BulletinLevel::None;
+        }
+        match self.type_.as_str() {
+            "warning" => BulletinLevel::Warning,
+            "critical" => BulletinLevel::Critical,
+            _ => BulletinLevel::Info,
+        }
+    }
+
+    /// Warnings and critical notices have to be acknowledged before installing
+    pub fn needs_acknowledgement(&self) -> bool {
+        self.level() >= BulletinLevel::Warning
+    }
+
+    /// The ID given in the recipe, or the first 12 digits of the SHA-256 of the type, title
+    /// and body, so that a changed bulletin has to be acknowledged again
+    pub fn id(&self) -> String {
+        if let Some(id) = self.id.as_ref().filter(|x| !x.trim().is_empty()) {
+            return id.trim().to_string();
+        }
+        let mut hasher = Sha256::new();
+        for part in [&self.type_, &self.title, &self.body] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+
+        hex::encode(hasher.finalize())[..12].to_string()
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Recipe {
     pub version: usize,
@@ -108,12 +217,475 @@ pub struct VariantEntry {
     pub date: String,
     pub sha256sum: String,
     pub url: String,
+    /// Path to the per-file checksum manifest, relative to the mirror
+    #[serde(default)]
+    pub manifest: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub description_tr: String,
+}
+
+impl VariantEntry {
+    /// Whether the variant ships a desktop environment
+    pub fn is_desktop(&self) -> bool {
+        matches!(self.name.as_str(), "Desktop" | "Workstation")
+    }
 }
 
+/// Set the proxy to connect through, e.g. http://proxy.example.com:8080, for the
+/// installer itself as well as the programs it runs
+pub fn set_proxy(url: Option<&str>) -> Result<()> {
+    if let Some(url) = url {
+        let parsed = Url::parse(url)?;
+        if parsed.host_str().is_none() {
+            return Err(anyhow!("Proxy {} has no host name.", url));
+        }
+        reqwest::Proxy::all(url)?;
+    }
+
+    for var in PROXY_ENV_VARS {
+        match url {
+            Some(url) => std::env::set_var(var, url),
+            None => std::env::remove_var(var),
+        }
+    }
+    *PROXY.lock().unwrap() = url.map(|x| x.to_string());
+
+    Ok(())
+}
+
+/// The proxy set with [`set_proxy`]
+pub fn proxy() -> Option<String> {
+    PROXY.lock().unwrap().clone()
+}
+
+/// A blocking client going through the proxy, if there is one
+pub fn blocking_client_builder() -> reqwest::blocking::ClientBuilder {
+    let builder = reqwest::blocking::ClientBuilder::new().user_agent(DEPLOYKIT_USER_AGENT!());
+    match proxy().and_then(|x| reqwest::Proxy::all(x).ok()) {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    }
+}
+
+/// An async client going through the proxy, if there is one
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = Client::builder().user_agent(DEPLOYKIT_USER_AGENT!());
+    match proxy().and_then(|x| reqwest::Proxy::all(x).ok()) {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    }
+}
+
+/// Fetch the release manifest from `url` instead of the official one, e.g., from a
+/// local mirror
+pub fn set_manifest_url(url: &str) {
+    *MANIFEST.lock().unwrap() = Some(url.to_string());
+}
+
+/// Fetch the recipe, which is kept for installing offline later
 pub fn fetch_recipe() -> Result<Recipe> {
-    Ok(reqwest::blocking::get(MANIFEST_URL)?
+    crate::assert_not_ui_thread("Fetching the recipe");
+
+    let url = MANIFEST
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| MANIFEST_URL.to_string());
+    let data = blocking_client_builder()
+        .build()?
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    // a broken manifest is no fault of the configuration
+    let recipe = serde_json::from_str(&data).map_err(|e| ExitCode::Network.wrap(e.into()))?;
+    if let Err(e) = std::fs::create_dir_all(PARTIAL_DOWNLOAD_DIR)
+        .and_then(|_| std::fs::write(cached_recipe_path(), &data))
+    {
+        warn!("Failed to keep the recipe: {}", e);
+    }
+
+    Ok(recipe)
+}
+
+fn cached_recipe_path() -> PathBuf {
+    Path::new(PARTIAL_DOWNLOAD_DIR).join(RECIPE_CACHE_FILE)
+}
+
+/// The recipe fetched the last time, for installing offline
+pub fn cached_recipe() -> Result<Recipe> {
+    let data = std::fs::read_to_string(cached_recipe_path())?;
+
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// A stage of reaching the AOSC OS servers, each depends on the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStage {
+    /// A network interface is up
+    Interface,
+    /// The name of the server (or the proxy) resolves
+    Dns,
+    /// A TCP connection to the server (or the proxy) can be made
+    Tcp,
+    /// The TLS handshake with the server succeeds
+    Tls,
+    /// The server answers the request
+    Http,
+}
+
+impl CheckStage {
+    pub const ALL: &'static [CheckStage] = &[
+        CheckStage::Interface,
+        CheckStage::Dns,
+        CheckStage::Tcp,
+        CheckStage::Tls,
+        CheckStage::Http,
+    ];
+}
+
+/// The results of the stages checked, up to the first that failed
+#[derive(Debug, Clone)]
+pub struct Connectivity {
+    pub stages: Vec<(CheckStage, Result<(), String>)>,
+}
+
+impl Connectivity {
+    pub fn result(&self) -> Result<()> {
+        match self.stages.iter().find_map(|(_, x)| x.as_ref().err()) {
+            Some(e) => Err(anyhow!("{}", e)),
+            None if self.stages.len() == CheckStage::ALL.len() => Ok(()),
+            None => Err(anyhow!("Connectivity check has not been finished.")),
+        }
+    }
+}
+
+/// The description of the error and its causes, as reqwest keeps the useful parts in the sources
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut s = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        s.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+
+    s
+}
+
+fn interfaces_up() -> Result<(), String> {
+    let interfaces = std::fs::read_dir(NET_CLASS_PATH)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .filter_map(|x| x.file_name().into_string().ok())
+        .filter(|x| x != "lo")
+        .map(|x| {
+            let state =
+                std::fs::read_to_string(Path::new(NET_CLASS_PATH).join(&x).join("operstate"))
+                    .unwrap_or_default();
+            (x, state.trim().to_string())
+        })
+        .collect::<Vec<_>>();
+    if interfaces.iter().any(|x| x.1 == "up") {
+        return Ok(());
+    }
+    if interfaces.is_empty() {
+        return Err("No network interface found".to_string());
+    }
+
+    Err(format!(
+        "No network interface is up ({})",
+        interfaces
+            .iter()
+            .map(|(name, state)| format!("{name}: {state}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+/// Check the stages of reaching the AOSC OS servers, and stop at the first one failing
+pub fn check_connectivity() -> Connectivity {
+    crate::assert_not_ui_thread("Checking the connectivity");
+
+    let mut passed = vec![];
+    let failed = check_stages(&mut passed).err();
+    let mut stages = passed.into_iter().map(|x| (x, Ok(()))).collect::<Vec<_>>();
+    if let Some((stage, e)) = failed {
+        warn!("Connectivity check failed at {:?}: {}", stage, e);
+        stages.push((stage, Err(e)));
+    }
+
+    Connectivity { stages }
+}
+
+fn check_stages(passed: &mut Vec<CheckStage>) -> Result<(), (CheckStage, String)> {
+    interfaces_up().map_err(|e| (CheckStage::Interface, e))?;
+    passed.push(CheckStage::Interface);
+
+    // through a proxy, it is the proxy to be reached directly
+    let target = proxy().unwrap_or_else(|| RELEASES_URL.to_string());
+    let url = Url::parse(&target).map_err(|e| (CheckStage::Dns, e.to_string()))?;
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| (CheckStage::Dns, format!("{host}: {e}")))?
+        .collect::<Vec<_>>();
+    passed.push(CheckStage::Dns);
+
+    let mut error = format!("{host} has no address");
+    let connected = addrs.iter().any(|addr| {
+        TcpStream::connect_timeout(addr, Duration::from_secs(5))
+            .map_err(|e| error = format!("{addr}: {e}"))
+            .is_ok()
+    });
+    if !connected {
+        return Err((CheckStage::Tcp, error));
+    }
+    passed.push(CheckStage::Tcp);
+
+    let client = blocking_client_builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| (CheckStage::Tls, error_chain(&e)))?;
+    let response = match client.head(RELEASES_URL).send() {
+        // the TCP connection has just been made, so it is the handshake failing
+        Err(e) if e.is_connect() => return Err((CheckStage::Tls, error_chain(&e))),
+        result => {
+            passed.push(CheckStage::Tls);
+            result.map_err(|e| (CheckStage::Http, error_chain(&e)))?
+        }
+    };
+    response
+        .error_for_status()
+        .map_err(|e| (CheckStage::Http, error_chain(&e)))?;
+    passed.push(CheckStage::Http);
+
+    Ok(())
+}
+
+/// Guess the timezone of the device from its public IP address
+pub fn fetch_geoip_timezone() -> Result<String> {
+    crate::assert_not_ui_thread("Looking up the timezone");
+
+    let client = blocking_client_builder()
+        .timeout(Duration::from_secs(3))
+        .build()?;
+    let timezone = client
+        .get(GEOIP_TIMEZONE_URL)
+        .send()?
         .error_for_status()?
-        .json()?)
+        .text()?;
+    let timezone = timezone.trim();
+
+    if timezone.is_empty() {
+        return Err(anyhow!("GeoIP service returned an empty timezone"));
+    }
+
+    Ok(timezone.to_string())
+}
+
+/// Fetch SSH public keys, e.g. from https://github.com/<user>.keys
+pub fn fetch_ssh_keys(url: &str) -> Result<String> {
+    let client = blocking_client_builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+
+    Ok(client.get(url).send()?.error_for_status()?.text()?)
+}
+
+/// Fetch an answer file, e.g., the one given on the kernel command line, `file://` URLs are
+/// read from this device
+pub fn fetch_config(url: &str) -> Result<String> {
+    let parsed = Url::parse(url)?;
+    if parsed.scheme() == "file" {
+        let path = parsed
+            .to_file_path()
+            .map_err(|_| anyhow!("{} is not a valid file URL.", url))?;
+        return Ok(std::fs::read_to_string(path)?);
+    }
+    let client = blocking_client_builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    Ok(client.get(parsed).send()?.error_for_status()?.text()?)
+}
+
+/// Fetch the per-file checksum manifest of a system release
+pub fn fetch_file_manifest(url: &str) -> Result<String> {
+    let client = blocking_client_builder()
+        .timeout(Duration::from_secs(60))
+        .build()?;
+
+    Ok(client.get(url).send()?.error_for_status()?.text()?)
+}
+
+fn partial_download_path(sha256: &str) -> PathBuf {
+    Path::new(PARTIAL_DOWNLOAD_DIR).join(format!("{sha256}.part"))
+}
+
+/// Keep the first `len` bytes downloaded to `path` of the release with the checksum `sha256`,
+/// so that a later installation may resume from there
+pub fn save_partial_download(path: &Path, len: u64, sha256: &str) -> Result<()> {
+    std::fs::create_dir_all(PARTIAL_DOWNLOAD_DIR)?;
+    let stat = rustix::fs::statvfs(PARTIAL_DOWNLOAD_DIR)?;
+    let available = stat.f_bavail * stat.f_frsize;
+    if available < len {
+        return Err(anyhow!(
+            "Not enough space in {} to keep the partial download ({} bytes needed, {} available).",
+            PARTIAL_DOWNLOAD_DIR,
+            len,
+            available
+        ));
+    }
+
+    let dest = partial_download_path(sha256);
+    let mut output = std::fs::File::create(&dest)?;
+    if let Err(e) = std::io::copy(&mut std::fs::File::open(path)?.take(len), &mut output) {
+        std::fs::remove_file(&dest).ok();
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Take the partial download of the release with the checksum `sha256` kept by a
+/// cancelled installation, with its length
+pub fn take_partial_download(sha256: &str) -> Option<(std::fs::File, u64)> {
+    let path = partial_download_path(sha256);
+    let file = std::fs::File::open(&path).ok()?;
+    // the file stays readable after being removed, and is never resumed twice
+    std::fs::remove_file(&path).ok();
+    let len = file.metadata().ok()?.len();
+
+    (len > 0).then_some((file, len))
+}
+
+/// What `aoscdk-rs download` writes next to the system release (as `<release>.json`), so
+/// that it can be matched with a variant when installing from the copy
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseSidecar {
+    pub variant: String,
+    /// Where it has been downloaded from
+    pub url: String,
+    pub date: String,
+    pub sha256: String,
+    /// In bytes
+    pub size: u64,
+}
+
+impl ReleaseSidecar {
+    pub fn new(variant: &VariantEntry, url: &str) -> Self {
+        ReleaseSidecar {
+            variant: variant.name.clone(),
+            url: url.to_string(),
+            date: variant.date.clone(),
+            sha256: variant.sha256sum.clone(),
+            size: variant.size,
+        }
+    }
+
+    /// Where the sidecar of the system release at `release` is
+    pub fn path_of(release: &Path) -> PathBuf {
+        let mut name = release.file_name().unwrap_or_default().to_os_string();
+        name.push(".json");
+
+        release.with_file_name(name)
+    }
+
+    /// The sidecar of the system release at `release`, if it has one
+    pub fn read(release: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(Self::path_of(release)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Some(serde_json::from_str(&text)?))
+    }
+
+    pub fn write(&self, release: &Path) -> Result<()> {
+        std::fs::write(
+            Self::path_of(release),
+            serde_json::to_string_pretty(self)? + "\n",
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Download the system release at `url` to `path`, resuming from `<path>.part` left by an
+/// interrupted download. `on_progress` is called with the bytes downloaded so far
+pub fn download_release(
+    url: &str,
+    path: &Path,
+    size: u64,
+    mut on_progress: impl FnMut(u64),
+) -> Result<()> {
+    crate::assert_not_ui_thread("Downloading the system release");
+    let mut part = path.as_os_str().to_os_string();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let resumed = std::fs::metadata(&part).map(|x| x.len()).unwrap_or(0);
+    if resumed == size {
+        std::fs::rename(&part, path)?;
+        return Ok(());
+    }
+
+    // the release takes a while, there is no timeout but for connecting
+    let client = blocking_client_builder()
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(None)
+        .build()?;
+    let mut request = client.get(url);
+    if resumed > 0 && resumed < size {
+        info!(
+            "Found a partial download of {} bytes, trying to resume",
+            resumed
+        );
+        request = request.header(reqwest::header::RANGE, format!("bytes={resumed}-"));
+    }
+    let mut response = request.send()?.error_for_status()?;
+    // the server may ignore the range and send the whole file
+    let (mut output, mut received) = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+        let file = std::fs::OpenOptions::new().append(true).open(&part)?;
+        (file, resumed)
+    } else {
+        (std::fs::File::create(&part)?, 0)
+    };
+    on_progress(received);
+
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let len = response.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        output.write_all(&buf[..len])?;
+        received += len as u64;
+        on_progress(received);
+    }
+    output.flush()?;
+    if received != size {
+        return Err(anyhow!(
+            "The download has stopped at {} of {} bytes, it is resumed when downloading again.",
+            received,
+            size
+        ));
+    }
+    std::fs::rename(&part, path)?;
+    info!("Downloaded {} bytes to {}", received, path.display());
+
+    Ok(())
+}
+
+/// The SHA-256 of the file at `path`, in hex
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
 }
 
 pub fn fetch_mirrors(recipe: &Recipe) -> Vec<Mirror> {
@@ -167,9 +739,9 @@ pub(crate) fn get_arch_name() -> Option<&'static str> {
 ///
 /// If the server returned a error code the response becomes an error.
 pub fn query_file_meta(url: &String) -> Result<reqwest::blocking::Response> {
-    let client = reqwest::blocking::ClientBuilder::new()
-        .user_agent(DEPLOYKIT_USER_AGENT!())
-        .build()?;
+    crate::assert_not_ui_thread("Querying a file");
+
+    let client = blocking_client_builder().build()?;
     let head_response = client.head(url).send();
 
     let server_response = head_response?;
@@ -178,50 +750,182 @@ pub fn query_file_meta(url: &String) -> Result<reqwest::blocking::Response> {
     Ok(server_success)
 }
 
-pub fn speedtest_mirrors(mirrors: Vec<Mirror>) -> Vec<Mirror> {
-    let mut speedtest_mirror = vec![];
+/// How fast a mirror has been to download the test file from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MirrorSpeed {
+    /// Time until the response headers arrived
+    pub latency: Duration,
+    /// Bytes per second
+    pub throughput: f64,
+}
+
+/// How far the speedtest of a mirror has got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MirrorTest {
+    Queued,
+    Testing,
+    Done(MirrorSpeed),
+    Failed,
+    TimedOut,
+}
+
+impl MirrorTest {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, MirrorTest::Queued | MirrorTest::Testing)
+    }
+}
+
+/// Test the download speed of the mirrors, a few at a time. `on_update` is called with
+/// the index of a mirror whenever its test starts or finishes, and the tests stop once
+/// `stop` is set, leaving the mirrors not tested yet as they are
+pub fn speedtest_mirrors(
+    mirrors: &[Mirror],
+    stop: &AtomicBool,
+    on_update: impl Fn(usize, MirrorTest),
+) {
+    crate::assert_not_ui_thread("Testing the mirrors");
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .worker_threads(2)
         .build()
         .unwrap();
-    let client = reqwest::Client::builder()
-        .user_agent(DEPLOYKIT_USER_AGENT!())
+    let client = client_builder()
         .timeout(Duration::from_secs(10))
         .build()
         .unwrap();
 
-    runtime.block_on(async move {
-        let mut task = vec![];
-        for mirror in &mirrors {
-            task.push(get_mirror_speed_score(&mirror.url, &client))
+    runtime.block_on(async {
+        let mut tests = futures::stream::iter(mirrors.iter().enumerate())
+            .map(|(index, mirror)| {
+                let client = &client;
+                let on_update = &on_update;
+                async move {
+                    on_update(index, MirrorTest::Testing);
+                    let limits = SpeedtestLimits::default();
+                    (index, get_mirror_speed(&mirror.url, client, &limits).await)
+                }
+            })
+            .buffer_unordered(SPEEDTEST_JOBS);
+        while let Some((index, result)) = tests.next().await {
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            on_update(index, speedtest_result(result));
         }
-        let results = futures::future::join_all(task).await;
-        for (index, result) in results.into_iter().enumerate() {
-            if let Ok(score) = result {
-                speedtest_mirror.push((mirrors[index].loc_tr.to_owned(), score));
+    });
+}
+
+/// Rank the mirrors from the fastest to the slowest, the mirrors that failed (or have
+/// not been tested) come last in their original order
+pub fn rank_mirrors(mut results: Vec<(Mirror, MirrorTest)>) -> Vec<(Mirror, MirrorTest)> {
+    // the sort is stable, the failed mirrors keep their order
+    results.sort_by(|(_, a), (_, b)| match (a, b) {
+        (MirrorTest::Done(a), MirrorTest::Done(b)) => b.throughput.total_cmp(&a.throughput),
+        (MirrorTest::Done(_), _) => std::cmp::Ordering::Less,
+        (_, MirrorTest::Done(_)) => std::cmp::Ordering::Greater,
+        _ => std::cmp::Ordering::Equal,
+    });
+
+    results
+}
+
+/// What a speed test of a mirror may download
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpeedtestLimits {
+    /// Stop after this many bytes of the test file
+    pub size: Option<u64>,
+    /// Stop after downloading for this long
+    pub duration: Option<Duration>,
+    /// Download no faster than this many bytes per second
+    pub rate: Option<u64>,
+}
+
+/// Test the mirrors one after another so that they do not compete for the connection,
+/// `repeat` times each. `on_result` is called with the index of the mirror and the
+/// result of each test, which `stop` skips once set
+pub fn bench_mirrors(
+    mirrors: &[Mirror],
+    repeat: usize,
+    limits: &SpeedtestLimits,
+    stop: &AtomicBool,
+    mut on_result: impl FnMut(usize, MirrorTest),
+) {
+    crate::assert_not_ui_thread("Benchmarking the mirrors");
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    // the test is stopped by the limits, the timeout is for a mirror not answering at all
+    let client = client_builder()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(limits.duration.unwrap_or(Duration::from_secs(60)) + Duration::from_secs(10))
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        for (index, mirror) in mirrors.iter().enumerate() {
+            for _ in 0..repeat {
+                if stop.load(Ordering::SeqCst) {
+                    return;
+                }
+                let result = get_mirror_speed(&mirror.url, &client, limits).await;
+                on_result(index, speedtest_result(result));
             }
         }
-        speedtest_mirror.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
-        let mut new_mirrors = vec![];
-        for (name, _) in speedtest_mirror {
-            let index = mirrors.iter().position(|x| x.loc_tr == name).unwrap();
-            new_mirrors.push(mirrors[index].to_owned());
+    });
+}
+
+fn speedtest_result(result: Result<MirrorSpeed>) -> MirrorTest {
+    match result {
+        Ok(speed) => MirrorTest::Done(speed),
+        Err(e) => {
+            warn!("{}", e);
+            let timed_out = e
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout());
+            if timed_out {
+                MirrorTest::TimedOut
+            } else {
+                MirrorTest::Failed
+            }
         }
-        new_mirrors
-    })
+    }
 }
 
-async fn get_mirror_speed_score(mirror_url: &str, client: &Client) -> Result<f32> {
+/// Download the test file from the mirror until it ends or a limit is reached, the file is
+/// only checked if it has been downloaded whole
+async fn get_mirror_speed(
+    mirror_url: &str,
+    client: &Client,
+    limits: &SpeedtestLimits,
+) -> Result<MirrorSpeed> {
     let download_url = Url::parse(mirror_url)?.join("../.repotest")?;
     let timer = Instant::now();
-    let file = client.get(download_url).send().await?.bytes().await?;
+    let mut response = client.get(download_url).send().await?.error_for_status()?;
+    let latency = timer.elapsed();
     let mut hasher = Sha256::new();
-    hasher.write_all(&file)?;
+    let mut received = 0;
+    let mut whole = true;
+    while let Some(chunk) = response.chunk().await? {
+        hasher.write_all(&chunk)?;
+        received += chunk.len() as u64;
+        if let Some(delay) = throttle_delay(received, limits.rate, timer.elapsed()) {
+            tokio::time::sleep(delay).await;
+        }
+        let size_reached = limits.size.is_some_and(|x| received >= x);
+        let time_reached = limits.duration.is_some_and(|x| timer.elapsed() >= x);
+        if size_reached || time_reached {
+            whole = false;
+            break;
+        }
+    }
 
-    if hex::encode(hasher.finalize()) == SPEEDTEST_FILE_CHECKSUM {
-        let result_time = timer.elapsed().as_secs_f32();
-        return Ok(result_time);
+    if !whole || hex::encode(hasher.finalize()) == SPEEDTEST_FILE_CHECKSUM {
+        let elapsed = timer.elapsed().as_secs_f64().max(0.001);
+        return Ok(MirrorSpeed {
+            latency,
+            throughput: received as f64 / elapsed,
+        });
     }
 
     Err(anyhow!(
@@ -230,13 +934,30 @@ async fn get_mirror_speed_score(mirror_url: &str, client: &Client) -> Result<f32
     ))
 }
 
+/// How long to wait after receiving `received` bytes in `elapsed` to stay under `rate`
+/// bytes per second
+fn throttle_delay(received: u64, rate: Option<u64>, elapsed: Duration) -> Option<Duration> {
+    let rate = rate.filter(|x| *x > 0)?;
+    let due = Duration::from_secs_f64(received as f64 / rate as f64);
+
+    due.checked_sub(elapsed).filter(|x| !x.is_zero())
+}
+
 pub fn find_variant_candidates(recipes: Recipe) -> Result<Vec<VariantEntry>> {
+    let arch_name = get_arch_name()
+        .ok_or_else(|| ExitCode::UnsupportedArch.wrap(anyhow!("Unsupported architecture.")))?;
+
+    find_variants(recipes, arch_name, false)
+}
+
+/// The variants with a release for `arch_name`, the latest release of each, or every one of
+/// them (the latest first) with `all_dates`
+pub fn find_variants(
+    recipes: Recipe,
+    arch_name: &str,
+    all_dates: bool,
+) -> Result<Vec<VariantEntry>> {
     let mut results: Vec<VariantEntry> = Vec::new();
-    let arch_name = get_arch_name();
-    if arch_name.is_none() {
-        return Err(anyhow!("Unsupported architecture."));
-    }
-    let arch_name = arch_name.unwrap();
     // filter: tarballs array is not empty and the mainline/retro switch matches
     let mut all_empty = true;
     let right_recipes = recipes
@@ -271,17 +992,219 @@ pub fn find_variant_candidates(recipes: Recipe) -> Result<Vec<VariantEntry>> {
         }
         all_empty = false;
 
-        let candidate_rootfs = sorted_rootfs.first().unwrap();
-        results.push(VariantEntry {
-            name: recipe.name,
-            size: candidate_rootfs.download_size as u64,
-            install_size: candidate_rootfs.inst_size as u64,
-            date: candidate_rootfs.date.clone(),
-            url: candidate_rootfs.path.clone(),
-            sha256sum: candidate_rootfs.sha256sum.clone(),
-        });
+        if !all_dates {
+            sorted_rootfs.truncate(1);
+        }
+        for candidate_rootfs in sorted_rootfs {
+            results.push(VariantEntry {
+                name: recipe.name.clone(),
+                size: candidate_rootfs.download_size as u64,
+                install_size: candidate_rootfs.inst_size as u64,
+                date: candidate_rootfs.date,
+                url: candidate_rootfs.path,
+                sha256sum: candidate_rootfs.sha256sum,
+                manifest: candidate_rootfs.manifest,
+                description: recipe.description.clone(),
+                description_tr: recipe.description_tr.clone(),
+            });
+        }
     }
+    // stable, the releases of a variant stay the latest first
     results.sort_by(|a, b| a.name.cmp(&b.name));
 
     Ok(results)
 }
+
+#[test]
+fn test_package_repo() {
+    let mirror = |url: &str, repo_url: Option<&str>| Mirror {
+        name: String::new(),
+        name_tr: String::new(),
+        loc: String::new(),
+        loc_tr: String::new(),
+        url: url.to_string(),
+        repo_url: repo_url.map(|x| x.to_string()),
+    };
+    assert_eq!(
+        mirror("https://releases.aosc.io/", None).package_repo(),
+        Some("https://repo.aosc.io/debs".to_string())
+    );
+    assert_eq!(
+        mirror("https://mirrors.tuna.tsinghua.edu.cn/anthon/aosc-os/", None).package_repo(),
+        Some("https://mirrors.tuna.tsinghua.edu.cn/anthon/debs".to_string())
+    );
+    assert_eq!(
+        mirror(
+            "https://example.com/aosc/",
+            Some("https://example.com/debs/")
+        )
+        .package_repo(),
+        Some("https://example.com/debs".to_string())
+    );
+    assert_eq!(
+        mirror("https://example.com/aosc/", None).package_repo(),
+        None
+    );
+}
+
+#[test]
+fn test_bulletin() {
+    let bulletin: Bulletin = serde_json::from_str(
+        r#"{"type": "none", "title": "", "title-tr": "", "body": "", "body-tr": ""}"#,
+    )
+    .unwrap();
+    assert!(bulletin.is_empty());
+    assert_eq!(bulletin.level(), BulletinLevel::None);
+    assert!(!bulletin.needs_acknowledgement());
+
+    let bulletin = Bulletin {
+        type_: "critical".to_string(),
+        title: "Broken snapshot".to_string(),
+        ..bulletin
+    };
+    assert!(!bulletin.is_empty());
+    assert_eq!(bulletin.level(), BulletinLevel::Critical);
+    assert!(bulletin.needs_acknowledgement());
+    let id = bulletin.id();
+    assert_eq!(id.len(), 12);
+    // a changed bulletin is another one
+    let changed = Bulletin {
+        body: "Do not install on XYZ".to_string(),
+        ..bulletin.clone()
+    };
+    assert_ne!(changed.id(), id);
+    let named = Bulletin {
+        id: Some("2024-01-broken".to_string()),
+        ..bulletin.clone()
+    };
+    assert_eq!(named.id(), "2024-01-broken");
+
+    let notice = Bulletin {
+        type_: "info".to_string(),
+        ..bulletin
+    };
+    assert_eq!(notice.level(), BulletinLevel::Info);
+    assert!(!notice.needs_acknowledgement());
+}
+
+#[test]
+fn test_release_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let release = dir.path().join("aosc-os_base_20240301_amd64.squashfs");
+    assert_eq!(
+        ReleaseSidecar::path_of(&release),
+        dir.path().join("aosc-os_base_20240301_amd64.squashfs.json")
+    );
+    assert_eq!(ReleaseSidecar::read(&release).unwrap(), None);
+
+    let sidecar = ReleaseSidecar {
+        variant: "Base".to_string(),
+        url: "https://repo.aosc.io/aosc-os/os-amd64/base/aosc-os_base_20240301_amd64.squashfs"
+            .to_string(),
+        date: "20240301".to_string(),
+        sha256: "b".to_string(),
+        size: 3,
+    };
+    sidecar.write(&release).unwrap();
+    assert_eq!(ReleaseSidecar::read(&release).unwrap(), Some(sidecar));
+
+    std::fs::write(&release, "abc").unwrap();
+    assert_eq!(
+        file_sha256(&release).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn test_throttle_delay() {
+    let second = Duration::from_secs(1);
+    assert_eq!(throttle_delay(1_000_000, None, second), None);
+    assert_eq!(throttle_delay(1_000_000, Some(1_000_000), second), None);
+    assert_eq!(throttle_delay(500_000, Some(1_000_000), second), None);
+    assert_eq!(
+        throttle_delay(3_000_000, Some(1_000_000), second),
+        Some(2 * second)
+    );
+}
+
+#[test]
+fn test_rank_mirrors() {
+    let mirror = |name: &str| Mirror {
+        name: name.to_string(),
+        name_tr: String::new(),
+        loc: String::new(),
+        loc_tr: String::new(),
+        url: String::new(),
+        repo_url: None,
+    };
+    let speed = |throughput: f64| MirrorSpeed {
+        latency: Duration::from_millis(50),
+        throughput,
+    };
+    let ranked = rank_mirrors(vec![
+        (mirror("failed-1"), MirrorTest::Failed),
+        (mirror("slow"), MirrorTest::Done(speed(1024.0))),
+        (mirror("untested"), MirrorTest::Queued),
+        (mirror("fast"), MirrorTest::Done(speed(10240.0))),
+    ]);
+    assert_eq!(
+        ranked.iter().map(|x| x.0.name.as_str()).collect::<Vec<_>>(),
+        vec!["fast", "slow", "failed-1", "untested"]
+    );
+}
+
+#[test]
+fn test_connectivity() {
+    let mut connectivity = Connectivity {
+        stages: vec![
+            (CheckStage::Interface, Ok(())),
+            (CheckStage::Dns, Err("no such host".to_string())),
+        ],
+    };
+    assert_eq!(
+        connectivity.result().unwrap_err().to_string(),
+        "no such host"
+    );
+    connectivity.stages = CheckStage::ALL.iter().map(|x| (*x, Ok(()))).collect();
+    assert!(connectivity.result().is_ok());
+    connectivity.stages.pop();
+    assert!(connectivity.result().is_err());
+}
+
+#[test]
+fn test_find_variants() {
+    let rootfs = r#"[
+        {"arch": "amd64", "date": "20240101", "downloadSize": 1, "instSize": 2, "path": "os-amd64/base/old.squashfs", "sha256sum": "a"},
+        {"arch": "amd64", "date": "20240301", "downloadSize": 3, "instSize": 4, "path": "os-amd64/base/new.squashfs", "sha256sum": "b"},
+        {"arch": "arm64", "date": "20240201", "downloadSize": 5, "instSize": 6, "path": "os-arm64/base/arm.squashfs", "sha256sum": "c"}
+    ]"#;
+    let variant = |name: &str| {
+        format!(
+            r#"{{"name": "{name}", "retro": {IS_RETRO}, "description": "", "description-tr": "", "tarballs": {rootfs}, "squashfs": {rootfs}}}"#
+        )
+    };
+    let recipe = || -> Recipe {
+        serde_json::from_str(&format!(
+            r#"{{"version": 1, "bulletin": {{"type": "none", "title": "", "title-tr": "", "body": "", "body-tr": ""}}, "variants": [{}, {}], "mirrors": []}}"#,
+            variant("Base"),
+            variant("BuildKit")
+        ))
+        .unwrap()
+    };
+
+    let latest = find_variants(recipe(), "amd64", false).unwrap();
+    assert_eq!(latest.len(), 1);
+    assert_eq!(latest[0].name, "Base");
+    assert_eq!(latest[0].date, "20240301");
+    assert_eq!(latest[0].url, "os-amd64/base/new.squashfs");
+
+    let all = find_variants(recipe(), "amd64", true).unwrap();
+    let dates = all.iter().map(|x| x.date.as_str()).collect::<Vec<_>>();
+    assert_eq!(dates, ["20240301", "20240101"]);
+
+    let arm = find_variants(recipe(), "arm64", true).unwrap();
+    assert_eq!(arm.len(), 1);
+    assert_eq!(arm[0].size, 5);
+
+    assert!(find_variants(recipe(), "riscv64", false).is_err());
+}
@@ -99,6 +99,98 @@ pub fn list_zoneinfo(input: &[u8]) -> IResult<&[u8], Vec<String>> {
     Ok((input, result.into_iter().map(|x| x.into()).collect()))
 }
 
+/// A timezone listed in zone1970.tab
+#[derive(Debug, PartialEq, Eq)]
+pub struct ZoneEntry {
+    /// The ISO 3166 codes of the countries using the timezone
+    pub countries: Vec<String>,
+    pub zone: String,
+    /// Which part of the countries the timezone is for, if they have several
+    pub comment: String,
+}
+
+#[inline]
+fn tab_field(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    take_while1(|c| c != b'\t' && c != b'\n')(input)
+}
+
+#[inline]
+fn zone1970_entry(input: &[u8]) -> IResult<&[u8], (&[u8], &[u8], &[u8])> {
+    let (input, (countries, _, _, _, tz, rest, _)) = tuple((
+        tab_field,
+        tag("\t"),
+        tab_field,
+        tag("\t"),
+        tab_field,
+        take_until("\n"),
+        line_rest,
+    ))(input)?;
+
+    Ok((
+        input,
+        (countries, tz, rest.strip_prefix(b"\t").unwrap_or(rest)),
+    ))
+}
+
+/// Parse the timezones with their countries from `zone1970.tab`
+pub fn list_zone_entries(input: &[u8]) -> IResult<&[u8], Vec<ZoneEntry>> {
+    many0(preceded(
+        hr,
+        map_res(zone1970_entry, |(countries, zone, comment)| {
+            Ok::<ZoneEntry, Utf8Error>(ZoneEntry {
+                countries: std::str::from_utf8(countries)?
+                    .split(',')
+                    .map(|x| x.to_string())
+                    .collect(),
+                zone: std::str::from_utf8(zone)?.to_string(),
+                comment: std::str::from_utf8(comment)?.to_string(),
+            })
+        }),
+    ))(input)
+}
+
+#[inline]
+fn iso3166_single_line(input: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
+    let (input, (code, _, name, _)) =
+        tuple((tab_field, tag("\t"), take_until("\n"), line_rest))(input)?;
+
+    Ok((input, (code, name)))
+}
+
+/// Parse the country codes and names from `iso3166.tab`
+pub fn list_countries(input: &[u8]) -> IResult<&[u8], Vec<(String, String)>> {
+    many0(preceded(
+        hr,
+        map_res(iso3166_single_line, |(code, name)| {
+            Ok::<(String, String), Utf8Error>((
+                std::str::from_utf8(code)?.to_string(),
+                std::str::from_utf8(name)?.to_string(),
+            ))
+        }),
+    ))(input)
+}
+
+#[inline]
+fn supported_locale_single_line(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (input, (locale, _)) = tuple((
+        take_while1(|c| c != b' ' && c != b'\t' && c != b'\n'),
+        line_rest,
+    ))(input)?;
+
+    Ok((input, locale))
+}
+
+/// Parse the locale names from `/usr/share/i18n/SUPPORTED`
+#[inline]
+pub fn list_supported_locales(input: &[u8]) -> IResult<&[u8], Vec<String>> {
+    let (input, result) = many0(preceded(
+        hr,
+        map_res(supported_locale_single_line, std::str::from_utf8),
+    ))(input)?;
+
+    Ok((input, result.into_iter().map(|x| x.into()).collect()))
+}
+
 #[inline]
 fn mounts_single_line(input: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
     let (input, (dev, _, mount_path, _, _)) = tuple((
@@ -247,3 +339,36 @@ fn test_list_zoneinfo() {
     let buf = &b"#commit1\tcommit2\t\na\tb\tc/c\nd\te\tf/f\tg\n#commit3\nh\ti\tj/j\n"[..];
     assert_eq!(list_zoneinfo(buf).unwrap().1, vec!["c/c", "f/f", "j/j"]);
 }
+
+#[test]
+fn test_list_zone_entries() {
+    let buf = &b"#comment\nAD\t+4230+00131\tEurope/Andorra\nAE,OM,RE\t+2518+05518\tAsia/Dubai\nAQ\t-6617+11031\tAntarctica/Casey\tCasey\n"[..];
+    let entries = list_zone_entries(buf).unwrap().1;
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].zone, "Europe/Andorra");
+    assert_eq!(entries[0].comment, "");
+    assert_eq!(entries[1].countries, vec!["AE", "OM", "RE"]);
+    assert_eq!(entries[2].zone, "Antarctica/Casey");
+    assert_eq!(entries[2].comment, "Casey");
+}
+
+#[test]
+fn test_list_countries() {
+    let buf = &b"#code\tname\nAD\tAndorra\nAE\tUnited Arab Emirates\n"[..];
+    assert_eq!(
+        list_countries(buf).unwrap().1,
+        vec![
+            ("AD".to_string(), "Andorra".to_string()),
+            ("AE".to_string(), "United Arab Emirates".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_list_supported_locales() {
+    let buf = &b"# comment\nen_US.UTF-8 UTF-8\nen_US ISO-8859-1\nzh_CN.UTF-8 UTF-8\n"[..];
+    assert_eq!(
+        list_supported_locales(buf).unwrap().1,
+        vec!["en_US.UTF-8", "en_US", "zh_CN.UTF-8"]
+    );
+}
@@ -0,0 +1,190 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use sysinfo::{Networks, System};
+use time::OffsetDateTime;
+
+use crate::{
+    disks::is_efi_booted,
+    log::{write_log, LIVE_MEDIUM_PATH},
+    network, LOG_FILE,
+};
+
+const TMP_REPORT_DIR: &str = "/tmp";
+/// Lines at the end of the installation log put into the report
+const REPORT_LOG_LINES: usize = 300;
+const DMI_PATH: &str = "/sys/class/dmi/id";
+
+/// The last `count` lines of the installation log
+fn log_tail(count: usize) -> String {
+    let log = match LOG_FILE.get().map(std::fs::read_to_string) {
+        Some(Ok(log)) => log,
+        Some(Err(e)) => return format!("(failed to read the installation log: {e})"),
+        None => return "(no installation log)".to_string(),
+    };
+    let lines = log.lines().collect::<Vec<_>>();
+
+    lines[lines.len().saturating_sub(count)..].join("\n")
+}
+
+fn network_info() -> String {
+    let mut s = String::new();
+    match network::proxy() {
+        Some(proxy) => writeln!(s, "Proxy: {proxy}").ok(),
+        None => writeln!(s, "Proxy: none").ok(),
+    };
+    let mut networks = Networks::new_with_refreshed_list()
+        .iter()
+        .map(|(name, data)| {
+            let operstate = std::fs::read_to_string(format!("/sys/class/net/{name}/operstate"))
+                .unwrap_or_default();
+            format!(
+                "{}: {}, received {} bytes ({} errors), transmitted {} bytes ({} errors)",
+                name,
+                operstate.trim(),
+                data.total_received(),
+                data.total_errors_on_received(),
+                data.total_transmitted(),
+                data.total_errors_on_transmitted()
+            )
+        })
+        .collect::<Vec<_>>();
+    networks.sort();
+    for line in networks {
+        writeln!(s, "{line}").ok();
+    }
+
+    s
+}
+
+fn dmi(name: &str) -> String {
+    std::fs::read_to_string(Path::new(DMI_PATH).join(name))
+        .map(|x| x.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn hardware_info() -> String {
+    let sys = System::new_all();
+    let mut s = String::new();
+    writeln!(
+        s,
+        "Architecture: {}, firmware: {}",
+        std::env::consts::ARCH,
+        if is_efi_booted() { "UEFI" } else { "BIOS" }
+    )
+    .ok();
+    writeln!(
+        s,
+        "Kernel: {}",
+        System::kernel_version().unwrap_or_default()
+    )
+    .ok();
+    writeln!(s, "Machine: {} {}", dmi("sys_vendor"), dmi("product_name")).ok();
+    writeln!(
+        s,
+        "CPU: {} ({} threads)",
+        sys.cpus().first().map(|x| x.brand()).unwrap_or_default(),
+        sys.cpus().len()
+    )
+    .ok();
+    writeln!(
+        s,
+        "Memory: {} MiB, swap: {} MiB",
+        sys.total_memory() / 1024 / 1024,
+        sys.total_swap() / 1024 / 1024
+    )
+    .ok();
+    match Command::new("lsblk")
+        .args(["-o", "NAME,SIZE,TYPE,FSTYPE,PTTYPE,MOUNTPOINT,MODEL"])
+        .output()
+    {
+        Ok(output) => s.push_str(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            writeln!(s, "(failed to run lsblk: {e})").ok();
+        }
+    }
+
+    s
+}
+
+/// The error, the end of the installation log, the installation plan (with the
+/// passwords redacted) and what the network and the hardware look like, for debugging
+fn build_report(error: &str, plan: Option<&str>) -> String {
+    format!(
+        "AOSC OS Installer {}, {}\n\n\
+         == Error ==\n{}\n\n\
+         == Installation plan ==\n{}\n\n\
+         == Network ==\n{}\n\
+         == Hardware ==\n{}\n\
+         == Installation log (last {} lines) ==\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        OffsetDateTime::now_utc(),
+        error.trim(),
+        plan.unwrap_or("(none)"),
+        network_info(),
+        hardware_info(),
+        REPORT_LOG_LINES,
+        log_tail(REPORT_LOG_LINES)
+    )
+}
+
+/// Where the report is saved to, the live medium (if writable) survives rebooting
+fn report_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    if Path::new(LIVE_MEDIUM_PATH).is_dir() {
+        dirs.push(Path::new(LIVE_MEDIUM_PATH).join("deploykit"));
+    }
+    dirs.push(PathBuf::from(TMP_REPORT_DIR));
+
+    dirs
+}
+
+/// Save the details of the error for a bug report to a timestamped file, returns where
+/// it has been saved to
+pub fn save_report(error: &str, plan: Option<&str>) -> Result<PathBuf> {
+    let now = OffsetDateTime::now_utc();
+    let name = format!(
+        "deploykit-error-{:04}{:02}{:02}-{:02}{:02}{:02}.txt",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    );
+    let report = build_report(error, plan);
+    for dir in report_dirs() {
+        let path = dir.join(&name);
+        match write_log(&path, report.as_bytes()) {
+            Ok(()) => {
+                info!("Error details saved to {}", path.display());
+                return Ok(path);
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to save the error details to {}: {}",
+                    path.display(),
+                    e
+                );
+                std::fs::remove_file(&path).ok();
+            }
+        }
+    }
+
+    bail!("No writable location has been found for saving the error details.")
+}
+
+#[test]
+fn test_build_report() {
+    let report = build_report(
+        "Failed to format /dev/sda1\n",
+        Some("{\"password\":\"<redacted>\"}"),
+    );
+    assert!(report.contains("== Error ==\nFailed to format /dev/sda1\n\n"));
+    assert!(report.contains("{\"password\":\"<redacted>\"}"));
+    assert!(report.contains("(no installation log)"));
+}
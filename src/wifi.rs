@@ -0,0 +1,307 @@
+use anyhow::{bail, Result};
+use log::{debug, info};
+use std::process::Command;
+
+use crate::{
+    disks::find_program,
+    install::{detect_network_backend, NetworkBackend},
+};
+
+const NET_CLASS_PATH: &str = "/sys/class/net";
+const IWCTL_GRAY: &str = "\x1b[1;90m";
+const IWCTL_COLOR_OFF: &str = "\x1b[0m";
+
+/// The program used to manage the Wi-Fi connections of the live session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WifiBackend {
+    NetworkManager,
+    Iwd,
+}
+
+/// How a Wi-Fi network is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    Open,
+    /// WPA/WPA2/WPA3 with a passphrase
+    Personal,
+    /// 802.1X (WPA2-Enterprise), which is not supported
+    Enterprise,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiNetwork {
+    pub ssid: String,
+    pub security: Security,
+    /// Signal strength in percent
+    pub signal: u8,
+}
+
+/// Find the Wi-Fi backend running in the live session, whose command line tool is shipped
+pub fn detect_wifi_backend() -> Option<WifiBackend> {
+    match detect_network_backend()? {
+        NetworkBackend::NetworkManager if find_program("nmcli").is_some() => {
+            Some(WifiBackend::NetworkManager)
+        }
+        NetworkBackend::Iwd if find_program("iwctl").is_some() => Some(WifiBackend::Iwd),
+        _ => None,
+    }
+}
+
+/// The wireless network interfaces, e.g. wlan0
+pub fn list_wifi_interfaces() -> Vec<String> {
+    let mut interfaces = std::fs::read_dir(NET_CLASS_PATH)
+        .map(|x| {
+            x.flatten()
+                .filter(|x| {
+                    x.path().join("wireless").exists() || x.path().join("phy80211").exists()
+                })
+                .filter_map(|x| x.file_name().into_string().ok())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    interfaces.sort();
+
+    interfaces
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String> {
+    debug!("Running {} {:?}", program, args);
+    let output = Command::new(program).args(args).output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    debug!(
+        "{} exited with {}: {}",
+        program,
+        output.status,
+        stdout.trim()
+    );
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "{} failed: {}",
+            program,
+            strip_ansi(if stderr.trim().is_empty() {
+                &stdout
+            } else {
+                &stderr
+            })
+            .trim()
+        );
+    }
+
+    Ok(stdout)
+}
+
+/// Scan for the Wi-Fi networks nearby, the strongest first
+pub fn scan_wifi(backend: WifiBackend, interface: &str) -> Result<Vec<WifiNetwork>> {
+    crate::assert_not_ui_thread("Scanning for Wi-Fi networks");
+
+    info!("Scanning for Wi-Fi networks on {}", interface);
+    let mut networks = match backend {
+        WifiBackend::NetworkManager => parse_nmcli_networks(&run(
+            "nmcli",
+            &[
+                "--terse",
+                "--fields",
+                "SSID,SECURITY,SIGNAL",
+                "device",
+                "wifi",
+                "list",
+                "ifname",
+                interface,
+                "--rescan",
+                "yes",
+            ],
+        )?),
+        WifiBackend::Iwd => {
+            run("iwctl", &["station", interface, "scan"])?;
+            // iwd returns before the scan is done
+            std::thread::sleep(std::time::Duration::from_secs(3));
+            parse_iwctl_networks(&run("iwctl", &["station", interface, "get-networks"])?)
+        }
+    };
+    networks.sort_by(|a, b| b.signal.cmp(&a.signal));
+    // the same network may be seen from several access points
+    let mut seen = vec![];
+    networks.retain(|x| {
+        let new = !seen.contains(&x.ssid);
+        seen.push(x.ssid.clone());
+        new
+    });
+
+    Ok(networks)
+}
+
+/// Connect to the Wi-Fi network, `hidden` is for networks not broadcasting their SSIDs
+pub fn connect_wifi(
+    backend: WifiBackend,
+    interface: &str,
+    ssid: &str,
+    passphrase: Option<&str>,
+    hidden: bool,
+) -> Result<()> {
+    crate::assert_not_ui_thread("Connecting to a Wi-Fi network");
+    info!("Connecting to Wi-Fi network {} on {}", ssid, interface);
+    match backend {
+        WifiBackend::NetworkManager => {
+            let mut args = vec!["device", "wifi", "connect", ssid, "ifname", interface];
+            if let Some(passphrase) = passphrase {
+                args.extend(["password", passphrase]);
+            }
+            if hidden {
+                args.extend(["hidden", "yes"]);
+            }
+            run("nmcli", &args)?;
+        }
+        WifiBackend::Iwd => {
+            let mut args = vec![];
+            if let Some(passphrase) = passphrase {
+                args.extend(["--passphrase", passphrase]);
+            }
+            let command = if hidden { "connect-hidden" } else { "connect" };
+            args.extend(["station", interface, command, ssid]);
+            run("iwctl", &args)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn nmcli_security(security: &str) -> Security {
+    match security.trim() {
+        "" | "--" => Security::Open,
+        x if x.contains("802.1X") => Security::Enterprise,
+        _ => Security::Personal,
+    }
+}
+
+/// Split a line of `nmcli --terse` output, where colons in the values are escaped
+fn split_terse(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().unwrap().extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+
+    fields
+}
+
+fn parse_nmcli_networks(output: &str) -> Vec<WifiNetwork> {
+    output
+        .lines()
+        .filter_map(|line| match split_terse(line).as_slice() {
+            // hidden networks have no SSID to be listed with
+            [ssid, security, signal] if !ssid.is_empty() => Some(WifiNetwork {
+                ssid: ssid.to_string(),
+                security: nmcli_security(security),
+                signal: signal.trim().parse().unwrap_or(0),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn strip_ansi(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip to the end of the escape sequence
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        result.push(c);
+    }
+
+    result
+}
+
+/// Parse the table of `iwctl station <interface> get-networks`, the signal is shown
+/// in four stars, of which the missing strength is in gray
+fn parse_iwctl_networks(output: &str) -> Vec<WifiNetwork> {
+    let output = (1..=3).fold(output.to_string(), |output, n| {
+        output.replace(
+            &format!("{IWCTL_GRAY}{}{IWCTL_COLOR_OFF}", "*".repeat(n)),
+            "",
+        )
+    });
+    let output = strip_ansi(&output);
+    output
+        .lines()
+        .skip_while(|x| !x.contains("Network name"))
+        .skip(2)
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches('>').trim();
+            let (rest, signal) = line.rsplit_once(char::is_whitespace)?;
+            let (ssid, security) = rest.trim_end().rsplit_once(char::is_whitespace)?;
+            let ssid = ssid.trim();
+            if ssid.is_empty() || !signal.chars().all(|x| x == '*') {
+                return None;
+            }
+
+            Some(WifiNetwork {
+                ssid: ssid.to_string(),
+                security: match security {
+                    "open" => Security::Open,
+                    "8021x" => Security::Enterprise,
+                    _ => Security::Personal,
+                },
+                signal: (signal.len() * 25).min(100) as u8,
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_wifi_networks() {
+    let networks =
+        parse_nmcli_networks("Home\\:5G:WPA2:82\n:WPA2:70\nCafe::40\nCorp:WPA2 802.1X:65\n");
+    assert_eq!(
+        networks,
+        vec![
+            WifiNetwork {
+                ssid: "Home:5G".to_string(),
+                security: Security::Personal,
+                signal: 82
+            },
+            WifiNetwork {
+                ssid: "Cafe".to_string(),
+                security: Security::Open,
+                signal: 40
+            },
+            WifiNetwork {
+                ssid: "Corp".to_string(),
+                security: Security::Enterprise,
+                signal: 65
+            },
+        ]
+    );
+
+    let networks = parse_iwctl_networks(
+        "                               Available networks\n\
+         --------------------------------------------------------------------------------\n\
+         \x1b[1;90m      Network name                      Security            Signal\x1b[0m\n\
+         --------------------------------------------------------------------------------\n\
+         \x1b[0m  > \x1b[0m  My Home                           psk                 ****\n\
+         \x20     Cafe                              open                **\x1b[1;90m**\x1b[0m\n\
+         \x20     Corp                              8021x               ***\x1b[1;90m*\x1b[0m\n",
+    );
+    assert_eq!(
+        networks
+            .iter()
+            .map(|x| (x.ssid.as_str(), x.security, x.signal))
+            .collect::<Vec<_>>(),
+        vec![
+            ("My Home", Security::Personal, 100),
+            ("Cafe", Security::Open, 50),
+            ("Corp", Security::Enterprise, 75),
+        ]
+    );
+}